@@ -51,12 +51,15 @@ fi
 ./test_time.sh
 ./test_signature_verification.sh
 ./test_supp_plugin.sh
+./test_ree_kv.sh
 ./test_error_handling.sh
 ./test_tcp_client.sh
 ./test_udp_socket.sh
 ./test_client_pool.sh
 ./test_inter_ta.sh
 ./test_property.sh
+./test_crypto_bench.sh
+./test_fido2.sh
 
 
 popd
@@ -0,0 +1,43 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Invoking the benchmarks from the CA.
+
+use crate::Report;
+use optee_teec::{ErrorKind, Operation, OutputReader, ParamNone, ParamTmpRef, Result, Session};
+
+/// Size of the first buffer of the report, grown if the TA needs more.
+const INITIAL_CAPACITY: usize = 1024;
+
+/// Invokes the command `cmd_id` of `session`, which runs the benchmarks with
+/// `optee_bench::ta::Bench`, and returns their report.
+///
+/// The command fails with `BadFormat` if the TA does not answer with a
+/// report.
+pub fn run(session: &mut Session, cmd_id: u32) -> Result<Report> {
+    let report = OutputReader::new(INITIAL_CAPACITY).read(|buffer| {
+        let mut operation = Operation::new(
+            0,
+            ParamTmpRef::new_output(buffer),
+            ParamNone,
+            ParamNone,
+            ParamNone,
+        );
+        let result = session.invoke_command(cmd_id, &mut operation);
+        (result, operation.parameters().0.updated_size())
+    })?;
+    serde_json::from_slice(&report).map_err(|_| ErrorKind::BadFormat.into())
+}
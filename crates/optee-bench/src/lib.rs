@@ -0,0 +1,240 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Latency benchmarks of TA code paths, e.g. crypto operations or secure
+//! storage accesses, measured in the TEE and reported to the CA.
+//!
+//! The TA runs the benchmarks in a command with [`ta::Bench`], which times
+//! repeated invocations of each function with the system time of the TEE,
+//! and writes the [`Report`] into an output memref:
+//!
+//! ``` rust,ignore
+//! #[ta_invoke_command]
+//! fn invoke_command(cmd_id: u32, params: &mut ParametersAny) -> Result<()> {
+//!     match Command::from(cmd_id) {
+//!         Command::Bench => {
+//!             let mut bench = Bench::new();
+//!             bench.function("sha256 4 KiB", |b| {
+//!                 let digest = Digest::allocate(AlgorithmId::Sha256).unwrap();
+//!                 b.iter(|| digest.do_final(&[0u8; 4096], &mut [0u8; 32]))
+//!             });
+//!             bench.finish(&mut params.0)
+//!         }
+//!         ...
+//!     }
+//! }
+//! ```
+//!
+//! The CA invokes the command with [`ca::run`](fn@ca::run), with the `ca`
+//! feature, and prints the report:
+//!
+//! ``` rust,ignore
+//! let report = optee_bench::ca::run(&mut session, Command::Bench as u32)?;
+//! println!("{report}");
+//! ```
+//!
+//! ``` text
+//! benchmark     iterations       min    median      mean       max
+//! sha256 4 KiB   10 x 1250  15.20 µs  15.60 µs  15.63 µs  16.00 µs
+//! ```
+//!
+//! # Wire format
+//!
+//! The report is encoded as JSON in the output memref in parameter 0, which
+//! the CA grows following the short-buffer convention. As the report
+//! implements `Serialize` and `Deserialize`, the CA may also save it to
+//! compare the results of later builds.
+#![cfg_attr(doc, doc = concat!(
+    "## Feature flags\n",
+    document_features::document_features!(),
+))]
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "ca")]
+extern crate std;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ca")]
+pub mod ca;
+#[cfg(feature = "ta")]
+pub mod ta;
+
+/// The latencies of one benchmark, in nanoseconds per iteration.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Summary {
+    /// Name of the benchmark.
+    pub name: String,
+    /// Number of samples the latencies are computed from.
+    pub samples: u32,
+    /// Iterations of the function in every sample.
+    pub iterations: u64,
+    pub min: u64,
+    pub median: u64,
+    pub mean: u64,
+    pub max: u64,
+}
+
+impl Summary {
+    /// Summarizes the `samples` of the benchmark `name`, each the mean time
+    /// of `iterations` iterations in nanoseconds.
+    ///
+    /// # Panics
+    ///
+    /// If `samples` is empty.
+    pub fn new(name: impl Into<String>, iterations: u64, samples: &[u64]) -> Self {
+        assert!(!samples.is_empty(), "a benchmark needs at least one sample");
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        let middle = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            sorted[middle - 1].midpoint(sorted[middle])
+        } else {
+            sorted[middle]
+        };
+        let total: u128 = sorted.iter().map(|&sample| u128::from(sample)).sum();
+        Self {
+            name: name.into(),
+            samples: sorted.len() as u32,
+            iterations,
+            min: sorted[0],
+            median,
+            mean: (total / sorted.len() as u128) as u64,
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// The results of the benchmarks of a command, printed as a table.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub benchmarks: Vec<Summary>,
+}
+
+impl Report {
+    /// The summary of the benchmark `name`, if it ran.
+    pub fn get(&self, name: &str) -> Option<&Summary> {
+        self.benchmarks.iter().find(|summary| summary.name == name)
+    }
+}
+
+/// A latency in nanoseconds, printed in the largest unit it has at least one
+/// of.
+struct Latency(u64);
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (value, unit) = match self.0 {
+            0..1_000 => return f.pad(&alloc::format!("{} ns", self.0)),
+            1_000..1_000_000 => (self.0 as f64 / 1e3, "µs"),
+            1_000_000..1_000_000_000 => (self.0 as f64 / 1e6, "ms"),
+            _ => (self.0 as f64 / 1e9, "s"),
+        };
+        f.pad(&alloc::format!("{:.2} {}", value, unit))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = ["benchmark", "iterations", "min", "median", "mean", "max"];
+        let rows: Vec<[String; 6]> = self
+            .benchmarks
+            .iter()
+            .map(|summary| {
+                [
+                    summary.name.clone(),
+                    alloc::format!("{} x {}", summary.samples, summary.iterations),
+                    alloc::format!("{}", Latency(summary.min)),
+                    alloc::format!("{}", Latency(summary.median)),
+                    alloc::format!("{}", Latency(summary.mean)),
+                    alloc::format!("{}", Latency(summary.max)),
+                ]
+            })
+            .collect();
+        let mut widths = header.map(|title| title.chars().count());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        write!(f, "{:<width$}", header[0], width = widths[0])?;
+        for (title, width) in header.iter().zip(&widths).skip(1) {
+            write!(f, "  {:>width$}", title, width = width)?;
+        }
+        for row in &rows {
+            writeln!(f)?;
+            write!(f, "{:<width$}", row[0], width = widths[0])?;
+            for (cell, width) in row.iter().zip(&widths).skip(1) {
+                write!(f, "  {:>width$}", cell, width = width)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary::new("odd", 8, &[30, 10, 20]);
+        assert_eq!(
+            (
+                summary.samples,
+                summary.iterations,
+                summary.min,
+                summary.median
+            ),
+            (3, 8, 10, 20)
+        );
+        assert_eq!((summary.mean, summary.max), (20, 30));
+
+        let summary = Summary::new("even", 1, &[40, 10, 20, 90]);
+        assert_eq!((summary.median, summary.mean), (30, 40));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_summary_without_samples() {
+        Summary::new("empty", 1, &[]);
+    }
+
+    #[test]
+    fn test_report_display() {
+        let report = Report {
+            benchmarks: vec![
+                Summary::new("sha256 4 KiB", 1250, &[15_200, 15_600, 16_000]),
+                Summary::new("noop", 1_000_000, &[3]),
+            ],
+        };
+        assert_eq!(report.get("noop").map(|summary| summary.min), Some(3));
+        assert!(report.get("aes").is_none());
+        assert_eq!(
+            report.to_string(),
+            "benchmark      iterations       min    median      mean       max\n\
+             sha256 4 KiB     3 x 1250  15.20 µs  15.60 µs  15.60 µs  16.00 µs\n\
+             noop          1 x 1000000      3 ns      3 ns      3 ns      3 ns"
+        );
+    }
+}
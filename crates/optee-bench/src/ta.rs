@@ -0,0 +1,164 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Running the benchmarks in the TA.
+
+use crate::{Report, Summary};
+use alloc::vec::Vec;
+use core::hint::black_box;
+use core::time::Duration;
+use optee_utee::{ErrorKind, ParameterAny, Result, SystemTime, dispatch};
+
+/// Runs benchmarks and collects their results into a [`Report`].
+///
+/// Every benchmark is first warmed up, calling the function with a doubling
+/// number of iterations for the warm-up time, which also estimates the time
+/// of an iteration. It then takes its samples, each timing as many iterations
+/// as fit in the sample time.
+///
+/// The system time of the TEE has a resolution of a millisecond on OP-TEE, so
+/// the sample time must stay well above it for the latencies to be accurate.
+pub struct Bench {
+    samples: u32,
+    sample_time: Duration,
+    warm_up_time: Duration,
+    report: Report,
+}
+
+impl Bench {
+    /// A harness taking 10 samples of 20 ms per benchmark, after 100 ms of
+    /// warm-up.
+    pub fn new() -> Self {
+        Self {
+            samples: 10,
+            sample_time: Duration::from_millis(20),
+            warm_up_time: Duration::from_millis(100),
+            report: Report::default(),
+        }
+    }
+
+    /// Sets the number of samples of every benchmark.
+    ///
+    /// # Panics
+    ///
+    /// If `samples` is 0.
+    pub fn samples(mut self, samples: u32) -> Self {
+        assert!(samples > 0, "a benchmark needs at least one sample");
+        self.samples = samples;
+        self
+    }
+
+    /// Sets the time of a sample, from which the number of iterations in a
+    /// sample is computed.
+    pub fn sample_time(mut self, sample_time: Duration) -> Self {
+        self.sample_time = sample_time;
+        self
+    }
+
+    /// Sets the time the function is called before the samples are taken.
+    pub fn warm_up_time(mut self, warm_up_time: Duration) -> Self {
+        self.warm_up_time = warm_up_time;
+        self
+    }
+
+    /// Benchmarks the function which `f` passes to [`Bencher::iter`], under
+    /// `name` in the report.
+    ///
+    /// # Panics
+    ///
+    /// If `f` does not call [`Bencher::iter`].
+    pub fn function<F>(&mut self, name: &str, mut f: F) -> &mut Self
+    where
+        F: FnMut(&mut Bencher),
+    {
+        let mut bencher = Bencher {
+            iterations: 1,
+            elapsed: None,
+        };
+        let mut run = |iterations| {
+            bencher.iterations = iterations;
+            f(&mut bencher);
+            bencher
+                .elapsed
+                .take()
+                .expect("the benchmark must call Bencher::iter")
+        };
+
+        let start = SystemTime::now();
+        let (mut iterations, mut warm_up_iterations, mut warm_up_elapsed) = (1u64, 0u64, 0u128);
+        loop {
+            warm_up_elapsed += run(iterations).as_nanos();
+            warm_up_iterations += iterations;
+            if start.elapsed() >= self.warm_up_time {
+                break;
+            }
+            iterations = iterations.saturating_mul(2);
+        }
+        let iteration_time = (warm_up_elapsed / u128::from(warm_up_iterations)).max(1);
+        let iterations = (self.sample_time.as_nanos() / iteration_time).clamp(1, u64::MAX.into());
+        let iterations = iterations as u64;
+
+        let samples: Vec<u64> = (0..self.samples)
+            .map(|_| {
+                let per_iteration = run(iterations).as_nanos() / u128::from(iterations);
+                per_iteration.min(u64::MAX.into()) as u64
+            })
+            .collect();
+        self.report
+            .benchmarks
+            .push(Summary::new(name, iterations, &samples));
+        self
+    }
+
+    /// The results of the benchmarks run so far.
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
+    /// Writes the report as JSON into `param`, an output memref, following
+    /// the short-buffer convention.
+    pub fn finish(self, param: &mut ParameterAny<'_>) -> Result<()> {
+        let report = serde_json::to_vec(&self.report).map_err(|_| ErrorKind::BadFormat)?;
+        dispatch::output(param, &report)
+    }
+}
+
+impl Default for Bench {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Times the iterations of the function of a benchmark.
+pub struct Bencher {
+    iterations: u64,
+    elapsed: Option<Duration>,
+}
+
+impl Bencher {
+    /// Times the iterations of `f`, whose return value is passed to
+    /// [`black_box`] so the computation is not optimized away.
+    pub fn iter<R, F>(&mut self, mut f: F)
+    where
+        F: FnMut() -> R,
+    {
+        let start = SystemTime::now();
+        for _ in 0..self.iterations {
+            black_box(f());
+        }
+        self.elapsed = Some(start.elapsed());
+    }
+}
@@ -0,0 +1,220 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Procedural macros of optee-proto, see the documentation there.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse_macro_input;
+use syn::spanned::Spanned;
+
+/// Derive the `Protocol` of an enum of commands, see `optee_proto`.
+#[proc_macro_derive(OpteeProtocol, attributes(protocol))]
+pub fn derive_optee_protocol(input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as syn::DeriveInput);
+    match expand_protocol(&item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct Command<'a> {
+    variant: &'a syn::Ident,
+    id: &'a syn::Expr,
+    request: Option<syn::Type>,
+    response: Option<syn::Type>,
+    params: Option<Vec<syn::Ident>>,
+}
+
+/// The names of `#[protocol(params = "...")]` and their `ParamKind` variants.
+const PARAM_KINDS: &[(&str, &str)] = &[
+    ("none", "None"),
+    ("value_in", "ValueIn"),
+    ("value_out", "ValueOut"),
+    ("value_inout", "ValueInout"),
+    ("memref_in", "MemrefIn"),
+    ("memref_out", "MemrefOut"),
+    ("memref_inout", "MemrefInout"),
+];
+
+/// Parse the comma separated parameter types of `params`, padded with `None`
+/// to the four parameters.
+fn parse_params(params: &syn::LitStr) -> syn::Result<Vec<syn::Ident>> {
+    let value = params.value();
+    let mut kinds = Vec::with_capacity(4);
+    for name in value.split(',').map(str::trim) {
+        let Some((_, variant)) = PARAM_KINDS.iter().find(|(kind, _)| *kind == name) else {
+            let names: Vec<_> = PARAM_KINDS.iter().map(|(kind, _)| *kind).collect();
+            return Err(syn::Error::new(
+                params.span(),
+                format!(
+                    "unknown parameter type `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                ),
+            ));
+        };
+        kinds.push(syn::Ident::new(variant, params.span()));
+    }
+    if kinds.len() > 4 {
+        return Err(syn::Error::new(
+            params.span(),
+            "a command has at most four parameters",
+        ));
+    }
+    kinds.resize_with(4, || syn::Ident::new("None", params.span()));
+    Ok(kinds)
+}
+
+fn expand_protocol(item: &syn::DeriveInput) -> syn::Result<TokenStream2> {
+    let syn::Data::Enum(data) = &item.data else {
+        return Err(syn::Error::new(
+            item.span(),
+            "`#[derive(OpteeProtocol)]` is only supported on enums",
+        ));
+    };
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            item.generics.span(),
+            "`#[derive(OpteeProtocol)]` does not support generic enums",
+        ));
+    }
+
+    let mut commands = Vec::with_capacity(data.variants.len());
+    for variant in &data.variants {
+        if !matches!(variant.fields, syn::Fields::Unit) {
+            return Err(syn::Error::new(
+                variant.fields.span(),
+                "a command must be a unit variant",
+            ));
+        }
+        let Some((_, id)) = &variant.discriminant else {
+            return Err(syn::Error::new(
+                variant.ident.span(),
+                "a command must have an explicit ID, e.g. `Command = 1`, so the IDs do not \
+                 change when commands are added or reordered",
+            ));
+        };
+        let mut request = None;
+        let mut response: Option<syn::Type> = None;
+        let mut params = None;
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("protocol"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("request") {
+                    request = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("response") {
+                    response = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("params") {
+                    params = Some(parse_params(&meta.value()?.parse()?)?);
+                } else {
+                    return Err(meta.error("expected `request`, `response` or `params`"));
+                }
+                Ok(())
+            })?;
+        }
+        if request.is_none()
+            && let Some(response) = &response
+        {
+            return Err(syn::Error::new(
+                response.span(),
+                "a command with a response must have a request type",
+            ));
+        }
+        commands.push(Command {
+            variant: &variant.ident,
+            id,
+            request,
+            response,
+            params,
+        });
+    }
+
+    let ident = &item.ident;
+    let variants: Vec<_> = commands.iter().map(|c| c.variant).collect();
+    let ids: Vec<_> = commands.iter().map(|c| c.id).collect();
+    let params = commands.iter().map(|c| match &c.params {
+        Some(kinds) => quote! {
+            ::core::option::Option::Some(::optee_proto::ParamSignature([
+                #(::optee_proto::ParamKind::#kinds),*
+            ]))
+        },
+        None => quote!(::core::option::Option::None),
+    });
+    let requests = commands.iter().filter_map(|c| {
+        let request = c.request.as_ref()?;
+        let variant = c.variant;
+        let response = match &c.response {
+            Some(response) => quote!(#response),
+            None => quote!(()),
+        };
+        Some(quote! {
+            impl ::optee_proto::Request for #request {
+                type Protocol = #ident;
+                type Response = #response;
+
+                const COMMAND: #ident = #ident::#variant;
+            }
+        })
+    });
+
+    Ok(quote! {
+        impl ::core::convert::From<#ident> for u32 {
+            fn from(command: #ident) -> u32 {
+                match command {
+                    #(#ident::#variants => (#ids) as u32,)*
+                }
+            }
+        }
+
+        impl ::core::convert::TryFrom<u32> for #ident {
+            type Error = ::optee_proto::UnknownCommand;
+
+            fn try_from(id: u32) -> ::core::result::Result<Self, Self::Error> {
+                #(
+                    if id == (#ids) as u32 {
+                        return ::core::result::Result::Ok(#ident::#variants);
+                    }
+                )*
+                ::core::result::Result::Err(::optee_proto::UnknownCommand(id))
+            }
+        }
+
+        impl ::optee_proto::Protocol for #ident {
+            const COMMANDS: &'static [Self] = &[#(#ident::#variants),*];
+
+            fn name(self) -> &'static str {
+                match self {
+                    #(#ident::#variants => ::core::stringify!(#variants),)*
+                }
+            }
+
+            fn params(self) -> ::core::option::Option<::optee_proto::ParamSignature> {
+                match self {
+                    #(#ident::#variants => #params,)*
+                }
+            }
+        }
+
+        #(#requests)*
+    })
+}
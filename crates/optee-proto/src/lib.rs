@@ -0,0 +1,343 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Command IDs and messages of the protocol between a CA and a TA, shared by
+//! both sides through a `proto` crate.
+//!
+//! `#[derive(OpteeProtocol)]` turns an enum of commands into the protocol:
+//!
+//! ```
+//! use optee_proto::OpteeProtocol;
+//!
+//! pub struct SignRequest {
+//!     pub message: [u8; 32],
+//! }
+//!
+//! pub struct Signature(pub [u8; 64]);
+//!
+//! #[derive(OpteeProtocol, Clone, Copy, Debug, PartialEq, Eq)]
+//! #[repr(u32)]
+//! pub enum Command {
+//!     #[protocol(request = SignRequest, response = Signature)]
+//!     Sign = 1,
+//!     Reset = 2,
+//! }
+//!
+//! // The CA side
+//! let cmd_id: u32 = Command::Sign.into();
+//! // The TA side
+//! assert_eq!(Command::try_from(cmd_id), Ok(Command::Sign));
+//! assert!(Command::try_from(3).is_err());
+//! ```
+//!
+//! Every command has an explicit ID, so reordering or inserting commands
+//! does not change the IDs an already deployed CA or TA uses. Unknown IDs are
+//! rejected with [`UnknownCommand`] instead of mapping to a catch-all
+//! variant, which the TA would have to remember to handle.
+//!
+//! `#[protocol(request = ..., response = ...)]` associates the command with
+//! the type of its request and response through [`Request`], so generic code
+//! on either side can serialize the request and deserialize the response
+//! without a hand-written table. The response defaults to `()`. The crate
+//! leaves the encoding to the application, `serde_json` or `bincode` being
+//! common choices.
+//!
+//! `#[protocol(params = "memref_in, memref_out, value_inout, none")]`
+//! declares the types of the four parameters of the command, trailing `none`
+//! parameters may be left out. Both sides can then check the parameter types
+//! of an operation with [`Protocol::check_params`], so a CA and a TA built
+//! from diverging versions of the protocol fail with a [`ParamMismatch`]
+//! naming the command and the parameter instead of misreading a parameter:
+//!
+//! ```
+//! # use optee_proto::{OpteeProtocol, ParamKind, Protocol};
+//! #[derive(OpteeProtocol, Clone, Copy, Debug, PartialEq, Eq)]
+//! #[repr(u32)]
+//! pub enum Command {
+//!     #[protocol(params = "memref_in, memref_out")]
+//!     Sign = 1,
+//! }
+//!
+//! // A memref input followed by a value output, e.g. the `paramTypes` of a
+//! // `TEEC_Operation` or the `param_types` of `TA_InvokeCommandEntryPoint`
+//! let raw_param_types = 0x5 | 0x2 << 4;
+//! let err = Command::Sign.check_params(raw_param_types).unwrap_err();
+//! assert_eq!(err.to_string(), "parameter 1 of command Sign is value_out, expected memref_out");
+//! assert!(Command::Sign.check_params(0x5 | 0x6 << 4).is_ok());
+//! ```
+#![no_std]
+
+use core::fmt;
+
+// The derive refers to the crate as `::optee_proto`
+#[cfg(test)]
+extern crate self as optee_proto;
+
+/// Derive the [`Protocol`] of an enum of commands.
+///
+/// The enum must only have unit variants, each with an explicit
+/// discriminant, which is its command ID. With `#[repr(u32)]` the IDs can
+/// use the whole `u32` range on any target. The derive implements
+/// `From<Command> for u32`, `TryFrom<u32> for Command` and [`Protocol`],
+/// and [`Request`] for the request type of every variant annotated with
+/// `#[protocol(request = Type, response = Type)]`. The parameter types of
+/// `#[protocol(params = "...")]` are returned by [`Protocol::params`].
+pub use optee_proto_macros::OpteeProtocol;
+
+/// An enum of the commands of a TA, see [`OpteeProtocol`].
+pub trait Protocol: Copy + Into<u32> + TryFrom<u32, Error = UnknownCommand> + 'static {
+    /// All the commands, in declaration order.
+    const COMMANDS: &'static [Self];
+
+    /// Returns the command ID of `self`.
+    fn id(self) -> u32 {
+        self.into()
+    }
+
+    /// Returns the name of the variant of `self`, e.g. to generate the
+    /// constants of a C client application.
+    fn name(self) -> &'static str;
+
+    /// Returns the parameter types declared with
+    /// `#[protocol(params = "...")]`, `None` if the command declares none.
+    fn params(self) -> Option<ParamSignature>;
+
+    /// Checks the raw parameter types of an operation sending the command,
+    /// which always succeeds if the command declares no parameter types.
+    fn check_params(self, raw_param_types: u32) -> Result<(), ParamMismatch> {
+        match self.params() {
+            Some(params) => params.check(self.name(), raw_param_types),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A request of the protocol, sent with the command `COMMAND`.
+pub trait Request {
+    /// The protocol the command belongs to.
+    type Protocol: Protocol;
+    /// What the TA returns for the request.
+    type Response;
+
+    /// The command the request is sent with.
+    const COMMAND: Self::Protocol;
+}
+
+/// Error of converting an ID which is not the ID of any command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownCommand(pub u32);
+
+impl fmt::Display for UnknownCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown command ID {}", self.0)
+    }
+}
+
+impl core::error::Error for UnknownCommand {}
+
+/// The type of a parameter of a command, as declared with
+/// `#[protocol(params = "...")]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamKind {
+    None,
+    ValueIn,
+    ValueOut,
+    ValueInout,
+    MemrefIn,
+    MemrefOut,
+    MemrefInout,
+}
+
+impl ParamKind {
+    /// Returns the name used in `#[protocol(params = "...")]`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::ValueIn => "value_in",
+            Self::ValueOut => "value_out",
+            Self::ValueInout => "value_inout",
+            Self::MemrefIn => "memref_in",
+            Self::MemrefOut => "memref_out",
+            Self::MemrefInout => "memref_inout",
+        }
+    }
+
+    /// Returns the kind of the raw parameter type `raw`, of the TEE client
+    /// API or of the TEE internal core API. Memory references to registered
+    /// shared memory map to the memref kinds the TA receives them as, except
+    /// whole references, whose direction depends on the flags of the shared
+    /// memory.
+    pub fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0x0 => Some(Self::None),
+            0x1 => Some(Self::ValueIn),
+            0x2 => Some(Self::ValueOut),
+            0x3 => Some(Self::ValueInout),
+            0x5 | 0xd => Some(Self::MemrefIn),
+            0x6 | 0xe => Some(Self::MemrefOut),
+            0x7 | 0xf => Some(Self::MemrefInout),
+            _ => None,
+        }
+    }
+
+    /// Whether the raw parameter type `raw` is of this kind, a whole
+    /// reference to registered shared memory being of any memref kind.
+    pub fn matches(self, raw: u32) -> bool {
+        const MEMREF_WHOLE: u32 = 0xc;
+        match Self::from_raw(raw) {
+            Some(kind) => kind == self,
+            None => {
+                raw == MEMREF_WHOLE
+                    && matches!(self, Self::MemrefIn | Self::MemrefOut | Self::MemrefInout)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParamKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The types of the four parameters of a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParamSignature(pub [ParamKind; 4]);
+
+impl ParamSignature {
+    /// Checks the raw parameter types of an operation sending `command`,
+    /// four types of 4 bits from the least significant bits, as built by
+    /// `TEEC_PARAM_TYPES` and `TEE_PARAM_TYPES`.
+    pub fn check(&self, command: &'static str, raw_param_types: u32) -> Result<(), ParamMismatch> {
+        for (index, expected) in self.0.iter().enumerate() {
+            let found = (raw_param_types >> (4 * index)) & 0xf;
+            if !expected.matches(found) {
+                return Err(ParamMismatch {
+                    command,
+                    index,
+                    expected: *expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error of an operation whose parameter types differ from the ones its
+/// command declares, see [`Protocol::check_params`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParamMismatch {
+    /// The name of the command.
+    pub command: &'static str,
+    /// The index of the first mismatching parameter, from 0 to 3.
+    pub index: usize,
+    pub expected: ParamKind,
+    /// The raw type of the parameter.
+    pub found: u32,
+}
+
+impl fmt::Display for ParamMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "parameter {} of command {} is ",
+            self.index, self.command
+        )?;
+        match ParamKind::from_raw(self.found) {
+            Some(kind) => write!(f, "{}", kind)?,
+            None => write!(f, "of type {:#x}", self.found)?,
+        }
+        write!(f, ", expected {}", self.expected)
+    }
+}
+
+impl core::error::Error for ParamMismatch {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    struct GetRequest;
+    struct Value(#[allow(dead_code)] u32);
+
+    #[derive(OpteeProtocol, Clone, Copy, Debug, PartialEq, Eq)]
+    enum Command {
+        #[protocol(request = GetRequest, response = Value)]
+        #[protocol(params = "memref_in, value_out")]
+        Get = 0x10,
+        Reset = 0x20,
+    }
+
+    fn command_of<R: Request>(_request: &R) -> R::Protocol {
+        R::COMMAND
+    }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(u32::from(Command::Get), 0x10);
+        assert_eq!(Command::Reset.id(), 0x20);
+        assert_eq!(Command::try_from(0x20), Ok(Command::Reset));
+        assert_eq!(Command::try_from(0x11), Err(UnknownCommand(0x11)));
+        assert_eq!(Command::COMMANDS, &[Command::Get, Command::Reset]);
+        assert_eq!(Command::Reset.name(), "Reset");
+    }
+
+    #[test]
+    fn test_request() {
+        assert_eq!(command_of(&GetRequest), Command::Get);
+        let _response: <GetRequest as Request>::Response = Value(1);
+    }
+
+    #[test]
+    fn test_params() {
+        use ParamKind::*;
+
+        assert_eq!(
+            Command::Get.params(),
+            Some(ParamSignature([MemrefIn, ValueOut, None, None]))
+        );
+        assert_eq!(Command::Reset.params(), Option::None);
+        // A temporary and a partial registered memref input
+        assert!(Command::Get.check_params(0x25).is_ok());
+        assert!(Command::Get.check_params(0x2d).is_ok());
+        // A whole registered memref, of any direction
+        assert!(Command::Get.check_params(0x2c).is_ok());
+        assert!(Command::Reset.check_params(0x7777).is_ok());
+
+        let err = Command::Get.check_params(0x1025).unwrap_err();
+        assert_eq!(
+            err,
+            ParamMismatch {
+                command: "Get",
+                index: 3,
+                expected: None,
+                found: 1,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "parameter 3 of command Get is value_in, expected none"
+        );
+        assert_eq!(
+            Command::Get.check_params(0x24).unwrap_err().to_string(),
+            "parameter 0 of command Get is of type 0x4, expected memref_in"
+        );
+    }
+}
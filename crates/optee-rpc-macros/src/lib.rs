@@ -0,0 +1,312 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Procedural macros of optee-rpc, see the documentation there.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::parse_macro_input;
+use syn::spanned::Spanned;
+
+/// Define a service between a CA and a TA, see `optee_rpc`.
+#[proc_macro_attribute]
+pub fn service(args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as syn::ItemTrait);
+    match expand_service(args.into(), item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct Method {
+    attrs: Vec<syn::Attribute>,
+    name: syn::Ident,
+    id: u32,
+    is_async: bool,
+    args: Vec<syn::Ident>,
+    types: Vec<syn::Type>,
+    output: syn::Type,
+}
+
+fn expand_service(args: TokenStream2, mut item: syn::ItemTrait) -> syn::Result<TokenStream2> {
+    let mut version: u32 = 1;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("version") {
+            version = meta.value()?.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(())
+        } else {
+            Err(meta.error("expected `version`"))
+        }
+    });
+    syn::parse::Parser::parse2(parser, args)?;
+
+    if !item.generics.params.is_empty() || item.generics.where_clause.is_some() {
+        return Err(syn::Error::new(
+            item.generics.span(),
+            "a service must not be generic",
+        ));
+    }
+
+    let mut methods = Vec::new();
+    let mut next_id: u32 = 0;
+    for trait_item in &mut item.items {
+        let syn::TraitItem::Fn(method) = trait_item else {
+            return Err(syn::Error::new(
+                trait_item.span(),
+                "a service may only have methods",
+            ));
+        };
+        let id = take_id(&mut method.attrs)?.unwrap_or(next_id);
+        if let Some(other) = methods.iter().find(|m: &&Method| m.id == id) {
+            return Err(syn::Error::new(
+                method.sig.ident.span(),
+                format!("method ID {} is already used by `{}`", id, other.name),
+            ));
+        }
+        next_id = id.wrapping_add(1);
+        methods.push(parse_method(id, method)?);
+    }
+
+    let vis = &item.vis;
+    let service = &item.ident;
+    let server = format_ident!("{}Server", service);
+    let client = format_ident!("{}Client", service);
+    let async_client = format_ident!("{}AsyncClient", service);
+
+    let arms = methods.iter().map(|method| {
+        let Method {
+            name,
+            id,
+            args,
+            types,
+            ..
+        } = method;
+        let call = if method.is_async {
+            quote!(::optee_rpc::__private::block_on(self.0.#name(#(#args),*)))
+        } else {
+            quote!(self.0.#name(#(#args),*))
+        };
+        quote! {
+            #id => {
+                let (#(#args,)*): (#(#types,)*) = ::optee_rpc::decode(request)?;
+                ::optee_rpc::__private::encode_ok(&#call)
+            }
+        }
+    });
+    let client_methods = methods.iter().map(|method| {
+        let Method {
+            attrs,
+            name,
+            id,
+            args,
+            types,
+            output,
+            ..
+        } = method;
+        quote! {
+            #(#attrs)*
+            pub fn #name(&mut self, #(#args: #types),*)
+                -> ::core::result::Result<#output, ::optee_rpc::Error>
+            {
+                let request = ::optee_rpc::encode(&(#(&#args,)*))?;
+                let response = ::optee_rpc::Transport::call(
+                    &mut self.transport, #id, Self::VERSION, &request,
+                )?;
+                ::optee_rpc::__private::decode_response(&response)
+            }
+        }
+    });
+    let async_client_methods = methods.iter().map(|method| {
+        let Method {
+            attrs,
+            name,
+            id,
+            args,
+            types,
+            output,
+            ..
+        } = method;
+        quote! {
+            #(#attrs)*
+            pub async fn #name(&self, #(#args: #types),*)
+                -> ::core::result::Result<#output, ::optee_rpc::Error>
+            {
+                let request = ::optee_rpc::encode(&(#(&#args,)*))?;
+                let response = ::optee_rpc::AsyncTransport::call(
+                    &self.transport, #id, Self::VERSION, request,
+                ).await?;
+                ::optee_rpc::__private::decode_response(&response)
+            }
+        }
+    });
+    let server_doc = format!("Serves a [`{}`] implementation in the TA.", service);
+    let client_doc = format!("Calls the [`{}`] service of a TA.", service);
+    let async_client_doc = format!(
+        "Calls the [`{}`] service of a TA with `async` methods.",
+        service
+    );
+
+    Ok(quote! {
+        #[allow(async_fn_in_trait)]
+        #item
+
+        #[doc = #server_doc]
+        #[derive(Default)]
+        #vis struct #server<T>(pub T);
+
+        impl<T: #service> ::optee_rpc::Server for #server<T> {
+            const VERSION: u32 = #version;
+
+            fn handle(
+                &mut self,
+                method: u32,
+                request: &[u8],
+            ) -> ::core::result::Result<::optee_rpc::__private::Vec<u8>, ::optee_rpc::Error> {
+                match method {
+                    #(#arms)*
+                    _ => Err(::optee_rpc::Error::UnknownMethod(method)),
+                }
+            }
+        }
+
+        #[doc = #client_doc]
+        #vis struct #client<T> {
+            transport: T,
+        }
+
+        impl<T> #client<T> {
+            /// The version of the service the client calls.
+            pub const VERSION: u32 = #version;
+
+            /// Creates a client calling the service over `transport`.
+            pub fn new(transport: T) -> Self {
+                Self { transport }
+            }
+
+            /// Returns the transport of the client.
+            pub fn into_inner(self) -> T {
+                self.transport
+            }
+        }
+
+        impl<T: ::optee_rpc::Transport> #client<T> {
+            #(#client_methods)*
+        }
+
+        #[doc = #async_client_doc]
+        #vis struct #async_client<T> {
+            transport: T,
+        }
+
+        impl<T> #async_client<T> {
+            /// The version of the service the client calls.
+            pub const VERSION: u32 = #version;
+
+            /// Creates a client calling the service over `transport`.
+            pub fn new(transport: T) -> Self {
+                Self { transport }
+            }
+
+            /// Returns the transport of the client.
+            pub fn into_inner(self) -> T {
+                self.transport
+            }
+        }
+
+        impl<T: ::optee_rpc::AsyncTransport> #async_client<T> {
+            #(#async_client_methods)*
+        }
+    })
+}
+
+/// Removes the `#[rpc(id = ...)]` attribute of a method, which is not
+/// allowed on trait items, and returns its ID.
+fn take_id(attrs: &mut Vec<syn::Attribute>) -> syn::Result<Option<u32>> {
+    let mut id = None;
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("rpc") {
+            return true;
+        }
+        if result.is_ok() {
+            result = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("id") {
+                    id = Some(meta.value()?.parse::<syn::LitInt>()?.base10_parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `id`"))
+                }
+            });
+        }
+        false
+    });
+    result.map(|_| id)
+}
+
+fn parse_method(id: u32, method: &syn::TraitItemFn) -> syn::Result<Method> {
+    let sig = &method.sig;
+    if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "a method of a service must not be generic",
+        ));
+    }
+    match sig.receiver() {
+        Some(receiver) if receiver.reference.is_some() => {}
+        _ => {
+            return Err(syn::Error::new(
+                sig.span(),
+                "a method of a service must take `&self` or `&mut self`",
+            ));
+        }
+    }
+    let mut args = Vec::new();
+    let mut types = Vec::new();
+    for input in sig.inputs.iter().skip(1) {
+        let syn::FnArg::Typed(arg) = input else {
+            unreachable!("only the first input is a receiver");
+        };
+        let syn::Pat::Ident(pat) = &*arg.pat else {
+            return Err(syn::Error::new(
+                arg.pat.span(),
+                "the arguments of a method of a service must be identifiers",
+            ));
+        };
+        args.push(pat.ident.clone());
+        types.push((*arg.ty).clone());
+    }
+    let output = match &sig.output {
+        syn::ReturnType::Default => syn::parse_quote!(()),
+        syn::ReturnType::Type(_, ty) => (**ty).clone(),
+    };
+    Ok(Method {
+        attrs: method
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("doc"))
+            .cloned()
+            .collect(),
+        name: sig.ident.clone(),
+        id,
+        is_async: sig.asyncness.is_some(),
+        args,
+        types,
+        output,
+    })
+}
@@ -0,0 +1,75 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Transports of the CA over `optee_teec` sessions.
+
+use crate::{Error, Transport};
+use alloc::vec::Vec;
+use optee_teec::{Operation, OutputReader, ParamNone, ParamTmpRef, ParamType, ParamValue};
+
+/// Size of the first buffer of the response, grown if the TA needs more.
+const INITIAL_CAPACITY: usize = 256;
+
+impl From<optee_teec::Error> for Error {
+    fn from(e: optee_teec::Error) -> Self {
+        Self::Tee(e.raw_code())
+    }
+}
+
+type CallOperation<'a, 'b> = Operation<ParamTmpRef<'a>, ParamTmpRef<'b>, ParamValue, ParamNone>;
+
+/// Invokes a call with `invoke` until the response fits the output buffer.
+fn call_with<F>(mut invoke: F, version: u32, request: &[u8]) -> optee_teec::Result<Vec<u8>>
+where
+    F: FnMut(&mut CallOperation<'_, '_>) -> optee_teec::Result<()>,
+{
+    OutputReader::new(INITIAL_CAPACITY).read(|buffer| {
+        let mut operation = Operation::new(
+            0,
+            ParamTmpRef::new_input(request),
+            ParamTmpRef::new_output(buffer),
+            ParamValue::new(version, 0, ParamType::ValueInput),
+            ParamNone,
+        );
+        let result = invoke(&mut operation);
+        (result, operation.parameters().1.updated_size())
+    })
+}
+
+impl Transport for optee_teec::Session {
+    fn call(&mut self, method: u32, version: u32, request: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(call_with(
+            |operation| self.invoke_command(method, operation),
+            version,
+            request,
+        )?)
+    }
+}
+
+#[cfg(feature = "async")]
+impl crate::AsyncTransport for optee_teec::asynch::Session {
+    fn call(
+        &self,
+        method: u32,
+        version: u32,
+        request: Vec<u8>,
+    ) -> impl Future<Output = Result<Vec<u8>, Error>> + Send {
+        let invocation = self.invoke_command(method, move |invoker| {
+            call_with(|operation| invoker.invoke(operation), version, &request)
+        });
+        async move { Ok(invocation.await?) }
+    }
+}
@@ -0,0 +1,371 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Services between a CA and a TA defined as Rust traits.
+//!
+//! A service is a trait in the `proto` crate shared by the CA and the TA,
+//! annotated with `#[service]`:
+//!
+//! ```
+//! #[optee_rpc::service(version = 2)]
+//! pub trait KeyStore {
+//!     /// Stores `key` under `label`, returns whether it replaced a key.
+//!     fn store(&mut self, label: String, key: Vec<u8>) -> bool;
+//!     async fn sign(&self, label: String, message: Vec<u8>) -> Result<Vec<u8>, String>;
+//!     #[rpc(id = 10)]
+//!     fn count(&self) -> u32;
+//! }
+//! ```
+//!
+//! The TA implements the trait and serves it with [`ta::serve`], through the
+//! `KeyStoreServer` wrapper generated by the attribute:
+//!
+//! ``` rust,ignore
+//! #[ta_invoke_command]
+//! fn invoke_command(
+//!     server: &mut KeyStoreServer<Store>,
+//!     cmd_id: u32,
+//!     params: &mut ParametersAny,
+//! ) -> Result<()> {
+//!     optee_rpc::ta::serve(server, cmd_id, params)
+//! }
+//! ```
+//!
+//! The CA calls it through the generated `KeyStoreClient`, over an
+//! `optee_teec::Session` with the `ca` feature:
+//!
+//! ``` rust,ignore
+//! let mut client = KeyStoreClient::new(ctx.open_session(uuid)?);
+//! let replaced = client.store("device".into(), key)?;
+//! let signature = client.sign("device".into(), message)??;
+//! ```
+//!
+//! or through `KeyStoreAsyncClient`, whose methods are `async`, over an
+//! `optee_teec::asynch::Session` with the `async` feature.
+//!
+//! # Methods
+//!
+//! The methods take `&self` or `&mut self` and arguments which implement
+//! `Serialize` and `DeserializeOwned`, as does their return type. They may
+//! be `async fn`: the TA has no executor, so [`ta::serve`] polls the future
+//! until it completes, and it must not wait for anything else than its own
+//! computations.
+//!
+//! The client methods return `Result<R, Error>` for a method returning `R`,
+//! an [`Error`] meaning that the call did not reach the implementation or
+//! its return value did not come back. Errors of the service itself are part
+//! of `R`, e.g. `Result<Vec<u8>, String>` above, so the client gets
+//! `Result<Result<Vec<u8>, String>, Error>`.
+//!
+//! # Wire format
+//!
+//! Every method is a command of the TA, with the ID of its position in the
+//! trait, from 0, unless set with `#[rpc(id = ...)]`, the following methods
+//! being numbered from there. The arguments are encoded as a JSON array in an
+//! input memref in parameter 0, the `Result<R, Error>` is returned as JSON in
+//! an output memref in parameter 1, which the client grows following the
+//! short-buffer convention, and parameter 2 is a value input carrying the
+//! version of the client in `a`.
+//!
+//! # Versioning
+//!
+//! The `version` of the service, 1 by default, must be incremented when
+//! methods are added, at the end of the trait so the IDs of the others do not
+//! change. A TA answers the clients of its version or an older one, and
+//! rejects newer clients with [`Error::Version`], as they may call methods
+//! it does not have.
+#![cfg_attr(doc, doc = concat!(
+    "## Feature flags\n",
+    document_features::document_features!(),
+))]
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "ca")]
+extern crate std;
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::future::Future;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+// The attribute refers to the crate as `::optee_rpc`
+#[cfg(test)]
+extern crate self as optee_rpc;
+
+/// Defines a service, see the [crate documentation](crate).
+///
+/// For a trait `Service`, the attribute generates `ServiceServer<T>`,
+/// serving a `T: Service` in the TA, and `ServiceClient<T>` and
+/// `ServiceAsyncClient<T>`, calling the service over a [`Transport`] and
+/// an [`AsyncTransport`] respectively.
+pub use optee_rpc_macros::service;
+
+#[cfg(feature = "ca")]
+mod ca;
+#[cfg(feature = "ta")]
+pub mod ta;
+
+/// Error of a call, which did not reach the implementation of the method or
+/// whose return value did not come back.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Error {
+    /// The TA implements an older version of the service than the client.
+    Version { client: u32, server: u32 },
+    /// The TA has no method with this ID.
+    UnknownMethod(u32),
+    /// The arguments or the return value could not be encoded or decoded.
+    Codec,
+    /// The command failed with this TEE error code, e.g. the TA panicked.
+    Tee(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Version { client, server } => write!(
+                f,
+                "client of version {} is newer than the service of version {}",
+                client, server
+            ),
+            Self::UnknownMethod(id) => write!(f, "unknown method ID {}", id),
+            Self::Codec => f.write_str("failed to encode or decode the call"),
+            Self::Tee(code) => write!(f, "TEE error {:#010x}", code),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// The TA side of a service, implemented by `#[service]` for the generated
+/// `ServiceServer<T>`.
+pub trait Server {
+    /// The version of the service.
+    const VERSION: u32;
+
+    /// Calls the method `method` with the encoded arguments `request`,
+    /// returns the encoded `Ok` response.
+    fn handle(&mut self, method: u32, request: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Answers a call of a client of version `client_version`, returns the
+    /// encoded response, successful or not.
+    fn respond(
+        &mut self,
+        method: u32,
+        client_version: u32,
+        request: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let result = if client_version > Self::VERSION {
+            Err(Error::Version {
+                client: client_version,
+                server: Self::VERSION,
+            })
+        } else {
+            self.handle(method, request)
+        };
+        match result {
+            Ok(response) => Ok(response),
+            Err(e) => encode(&Err::<(), _>(e)),
+        }
+    }
+}
+
+/// Sends the calls of a client to the TA, implemented for
+/// `optee_teec::Session` with the `ca` feature.
+pub trait Transport {
+    /// Invokes the command `method` with the encoded arguments `request`,
+    /// returns the encoded response.
+    fn call(&mut self, method: u32, version: u32, request: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn call(&mut self, method: u32, version: u32, request: &[u8]) -> Result<Vec<u8>, Error> {
+        (**self).call(method, version, request)
+    }
+}
+
+/// Sends the calls of an async client to the TA, implemented for
+/// `optee_teec::asynch::Session` with the `async` feature.
+pub trait AsyncTransport {
+    /// Invokes the command `method` with the encoded arguments `request`,
+    /// resolves to the encoded response.
+    fn call(
+        &self,
+        method: u32,
+        version: u32,
+        request: Vec<u8>,
+    ) -> impl Future<Output = Result<Vec<u8>, Error>> + Send;
+}
+
+/// Encodes the arguments or the response of a call.
+pub fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    serde_json::to_vec(value).map_err(|_| Error::Codec)
+}
+
+/// Decodes the arguments or the response of a call.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    serde_json::from_slice(bytes).map_err(|_| Error::Codec)
+}
+
+#[doc(hidden)]
+pub mod __private {
+    use super::*;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+
+    pub use alloc::vec::Vec;
+
+    /// Encodes the successful response of a method returning `value`.
+    pub fn encode_ok<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        encode(&Ok::<&T, Error>(value))
+    }
+
+    /// Decodes a response, successful or not.
+    pub fn decode_response<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+        decode::<Result<T, Error>>(bytes)?
+    }
+
+    /// Polls `future` until it completes, for the `async fn` methods of a
+    /// service in the TA, which has no executor.
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+
+    #[service(version = 2)]
+    trait Counter {
+        /// Adds `amount`, returns the new count.
+        fn add(&mut self, amount: u32) -> u32;
+        async fn get(&self) -> u32;
+        #[rpc(id = 10)]
+        fn label(&mut self, prefix: String, suffix: String) -> Result<String, String>;
+        fn reset(&mut self);
+    }
+
+    #[derive(Default)]
+    struct Count(u32);
+
+    impl Counter for Count {
+        fn add(&mut self, amount: u32) -> u32 {
+            self.0 += amount;
+            self.0
+        }
+
+        async fn get(&self) -> u32 {
+            self.0
+        }
+
+        fn label(&mut self, prefix: String, suffix: String) -> Result<String, String> {
+            match self.0 {
+                0 => Err("empty".to_string()),
+                count => Ok(alloc::format!("{}{}{}", prefix, count, suffix)),
+            }
+        }
+
+        fn reset(&mut self) {
+            self.0 = 0;
+        }
+    }
+
+    /// Calls the server directly, as a client of version `version`.
+    struct Loopback<'a> {
+        server: &'a mut CounterServer<Count>,
+        version: u32,
+        methods: Vec<u32>,
+    }
+
+    impl Transport for Loopback<'_> {
+        fn call(&mut self, method: u32, _version: u32, request: &[u8]) -> Result<Vec<u8>, Error> {
+            self.methods.push(method);
+            self.server.respond(method, self.version, request)
+        }
+    }
+
+    #[test]
+    fn test_calls() {
+        let mut server = CounterServer::<Count>::default();
+        let mut client = CounterClient::new(Loopback {
+            server: &mut server,
+            version: CounterClient::<()>::VERSION,
+            methods: Vec::new(),
+        });
+        assert_eq!(
+            client.label("<".into(), ">".into()),
+            Ok(Err("empty".into()))
+        );
+        assert_eq!(client.add(2), Ok(2));
+        assert_eq!(client.add(3), Ok(5));
+        assert_eq!(
+            client.label("<".into(), ">".into()),
+            Ok(Ok("<5>".to_string()))
+        );
+        assert_eq!(client.reset(), Ok(()));
+        assert_eq!(client.into_inner().methods, [10, 0, 0, 10, 11]);
+        assert_eq!(__private::block_on(server.0.get()), 0);
+    }
+
+    #[test]
+    fn test_async_method() {
+        let mut server = CounterServer(Count(7));
+        let response = server.respond(1, 2, &encode(&()).unwrap()).unwrap();
+        assert_eq!(__private::decode_response::<u32>(&response), Ok(7));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut server = CounterServer(Count(0));
+        let mut client = CounterClient::new(Loopback {
+            server: &mut server,
+            version: 3,
+            methods: Vec::new(),
+        });
+        assert_eq!(
+            client.add(1),
+            Err(Error::Version {
+                client: 3,
+                server: 2
+            })
+        );
+        // Older clients are served
+        client.transport.version = 1;
+        assert_eq!(client.add(1), Ok(1));
+
+        let response = server.respond(2, 2, b"null").unwrap();
+        assert_eq!(
+            __private::decode_response::<()>(&response),
+            Err(Error::UnknownMethod(2))
+        );
+        let response = server.respond(0, 2, b"[\"1\"]").unwrap();
+        assert_eq!(
+            __private::decode_response::<u32>(&response),
+            Err(Error::Codec)
+        );
+        assert_eq!(__private::decode_response::<u32>(b"x"), Err(Error::Codec));
+    }
+}
@@ -0,0 +1,48 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Serving a service from the TA.
+
+use crate::Server;
+use optee_utee::{ErrorKind, ParameterValueRead, ParametersAny, Result, dispatch};
+
+/// Answers the call of the command `cmd_id` with `server`, to be called from
+/// `#[ta_invoke_command]` with the session context wrapped in the server
+/// generated by `#[service]`:
+///
+/// ``` rust,ignore
+/// #[ta_invoke_command]
+/// fn invoke_command(
+///     server: &mut KeyStoreServer<Store>,
+///     cmd_id: u32,
+///     params: &mut ParametersAny,
+/// ) -> Result<()> {
+///     optee_rpc::ta::serve(server, cmd_id, params)
+/// }
+/// ```
+///
+/// Errors of the call, e.g. an unknown method, are returned to the client
+/// in the response, the command only fails if the parameters do not follow
+/// the wire format, with `BadParameters`, or if the response does not fit
+/// the output memref, with `ShortBuffer`.
+pub fn serve<S: Server>(server: &mut S, cmd_id: u32, params: &mut ParametersAny) -> Result<()> {
+    let version = params.2.as_value_input()?.get_a();
+    let request = dispatch::input(&params.0)?;
+    let response = server
+        .respond(cmd_id, version, request)
+        .map_err(|_| ErrorKind::BadFormat)?;
+    dispatch::output(&mut params.1, &response)
+}
@@ -0,0 +1,185 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use std::marker::PhantomData;
+
+use optee_teec::{
+    Context, ErrorKind, Operation, OutputReader, ParamNone, ParamTmpRef, Session, Uuid,
+};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::{Codec, Error, Json, Result};
+
+/// Session with a TA, calling its commands with requests and responses
+/// encoded with `C`.
+///
+/// A command gets the request in an input memref in parameter 0 and writes
+/// the response into an output memref in parameter 1, the layout of
+/// `#[ta_dispatch]` of `optee_utee`. The output buffer starts at
+/// [`DEFAULT_INITIAL_CAPACITY`](Self::DEFAULT_INITIAL_CAPACITY) bytes and
+/// grows to the size the TA reports with `ShortBuffer`, up to
+/// [`max_capacity`](Self::max_capacity).
+pub struct TaClient<C: Codec = Json> {
+    // Closed before the context it was opened in
+    session: Session,
+    _context: Context,
+    initial_capacity: usize,
+    max_capacity: usize,
+    codec: PhantomData<C>,
+}
+
+impl TaClient {
+    /// Opens a session with the TA `uuid`, calling it with JSON.
+    pub fn open(uuid: &str) -> Result<Self> {
+        Self::open_with_codec(uuid)
+    }
+}
+
+impl<C: Codec> TaClient<C> {
+    /// The size of the first output buffer of a call, 1 KiB.
+    pub const DEFAULT_INITIAL_CAPACITY: usize = 1024;
+
+    /// Opens a session with the TA `uuid`, calling it with the codec `C`.
+    pub fn open_with_codec(uuid: &str) -> Result<Self> {
+        let uuid = Uuid::parse_str(uuid.trim())?;
+        let mut context = Context::new()?;
+        let session = context.open_session(uuid)?;
+        Ok(Self {
+            session,
+            _context: context,
+            initial_capacity: Self::DEFAULT_INITIAL_CAPACITY,
+            max_capacity: OutputReader::DEFAULT_MAX_CAPACITY,
+            codec: PhantomData,
+        })
+    }
+
+    /// Starts the output buffer of the calls at `initial_capacity` bytes,
+    /// e.g. the usual size of the responses.
+    pub fn initial_capacity(mut self, initial_capacity: usize) -> Self {
+        self.initial_capacity = initial_capacity;
+        self
+    }
+
+    /// Fails the calls whose response needs more than `max_capacity` bytes
+    /// with `ExcessData`, 1 MiB by default.
+    pub fn max_capacity(mut self, max_capacity: usize) -> Self {
+        self.max_capacity = max_capacity;
+        self
+    }
+
+    /// The session, for the commands with another layout.
+    pub fn session(&mut self) -> &mut Session {
+        &mut self.session
+    }
+
+    /// Calls the command `cmd` with `input` and returns its decoded output.
+    ///
+    /// A command without output returns `()`.
+    pub fn call<I, O>(&mut self, cmd: impl Into<u32>, input: &I) -> Result<O>
+    where
+        I: Serialize + ?Sized,
+        O: DeserializeOwned,
+    {
+        let input = C::encode(input).map_err(Error::Encode)?;
+        let output = self.call_raw(cmd, &input)?;
+        C::decode(&output).map_err(Error::Decode)
+    }
+
+    /// Calls the command `cmd` with the encoded `input` and returns its
+    /// encoded output.
+    pub fn call_raw(&mut self, cmd: impl Into<u32>, input: &[u8]) -> Result<Vec<u8>> {
+        let cmd = cmd.into();
+        let session = &mut self.session;
+        let reader = OutputReader::new(self.initial_capacity).max_capacity(self.max_capacity);
+        read_output(&reader, |output| {
+            let p0 = ParamTmpRef::new_input(input);
+            let p1 = ParamTmpRef::new_output(output);
+            let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+            let result = session.invoke_command(cmd, &mut operation);
+            (result, operation.parameters().1.updated_size())
+        })
+    }
+}
+
+/// Reads the output of a command with `reader`, keeping the message the TA
+/// wrote into the output buffer when the command fails.
+fn read_output<F>(reader: &OutputReader, mut invoke: F) -> Result<Vec<u8>>
+where
+    F: FnMut(&mut [u8]) -> (optee_teec::Result<()>, usize),
+{
+    let mut message = None;
+    reader
+        .read(|output| {
+            let (result, updated_size) = invoke(output);
+            if let Err(e) = &result
+                && e.kind() != ErrorKind::ShortBuffer
+                && updated_size > 0
+                && updated_size <= output.len()
+            {
+                let text = String::from_utf8_lossy(&output[..updated_size]);
+                message = Some(text.trim_end_matches('\0').trim().to_owned());
+            }
+            (result, updated_size)
+        })
+        .map_err(|error| Error::Tee {
+            error,
+            message: message.filter(|message| !message.is_empty()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_output() {
+        // Grown to the size reported by the TA
+        let mut capacities = Vec::new();
+        let output = read_output(&OutputReader::new(4), |output| {
+            capacities.push(output.len());
+            if output.len() < 10 {
+                return (Err(ErrorKind::ShortBuffer.into()), 10);
+            }
+            output[..10].copy_from_slice(b"0123456789");
+            (Ok(()), 10)
+        })
+        .unwrap();
+        assert_eq!(output, b"0123456789");
+        assert_eq!(capacities, [4, 10]);
+
+        let err = read_output(&OutputReader::new(64), |output| {
+            output[..14].copy_from_slice(b"unknown wallet");
+            (Err(ErrorKind::BadParameters.into()), 14)
+        })
+        .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::Tee { error, message: Some(message) }
+                if error.kind() == ErrorKind::BadParameters && message == "unknown wallet"
+        ));
+        assert!(err.to_string().ends_with(": unknown wallet"));
+
+        // No message, or a size which does not fit the buffer
+        for updated_size in [0, 100] {
+            let err = read_output(&OutputReader::new(64), |_| {
+                (Err(ErrorKind::Generic.into()), updated_size)
+            })
+            .unwrap_err();
+            assert!(matches!(err, Error::Tee { message: None, .. }));
+        }
+    }
+}
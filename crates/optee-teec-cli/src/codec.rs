@@ -0,0 +1,76 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Encoding of the requests and responses of a TA.
+///
+/// A TA encoding them with `bincode`, for example, is called with:
+///
+/// ```ignore
+/// struct Bincode;
+///
+/// impl Codec for Bincode {
+///     fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, String> {
+///         bincode::serialize(value).map_err(|e| e.to_string())
+///     }
+///
+///     fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+///         bincode::deserialize(bytes).map_err(|e| e.to_string())
+///     }
+/// }
+///
+/// let mut client = TaClient::<Bincode>::open_with_codec(proto::UUID)?;
+/// ```
+pub trait Codec {
+    /// Encodes a request.
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, String>;
+
+    /// Decodes a response.
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String>;
+}
+
+/// JSON, the encoding of the `Json` codec of `optee_utee::dispatch`.
+///
+/// An empty response, that of a command without output, decodes as `null`,
+/// i.e. into `()` or `None`.
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, String> {
+        serde_json::to_vec(value).map_err(|e| e.to_string())
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+        let bytes = if bytes.is_empty() { b"null" } else { bytes };
+        serde_json::from_slice(bytes).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        assert_eq!(Json::encode(&[1, 2]).unwrap(), b"[1,2]");
+        assert_eq!(Json::decode::<Vec<u8>>(b"[1,2]").unwrap(), [1, 2]);
+        Json::decode::<()>(b"").unwrap();
+        assert_eq!(Json::decode::<Option<u8>>(b"").unwrap(), None);
+        assert!(Json::decode::<u8>(b"").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Input and output files of a CLI, `-` standing for the standard input or
+//! output.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::{Error, Result};
+
+/// The path standing for the standard input or output.
+pub const STDIO: &str = "-";
+
+/// Reads the file at `path`, or the standard input for `-`.
+pub fn read_input(path: &Path) -> Result<Vec<u8>> {
+    let result = if path == Path::new(STDIO) {
+        let mut input = Vec::new();
+        io::stdin().read_to_end(&mut input).map(|_| input)
+    } else {
+        fs::read(path)
+    };
+    result.map_err(|error| Error::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Writes `data` to the file at `path`, replacing it, or to the standard
+/// output for `-`.
+pub fn write_output(path: &Path, data: &[u8]) -> Result<()> {
+    let result = if path == Path::new(STDIO) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data).and_then(|_| stdout.flush())
+    } else {
+        fs::write(path, data)
+    };
+    result.map_err(|error| Error::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files() {
+        let path = std::env::temp_dir().join(format!("optee-teec-cli-io-{}", std::process::id()));
+        write_output(&path, b"data").unwrap();
+        assert_eq!(read_input(&path).unwrap(), b"data");
+        fs::remove_file(&path).unwrap();
+
+        let err = read_input(&path).unwrap_err();
+        assert!(matches!(&err, Error::Io { error, .. } if error.kind() == io::ErrorKind::NotFound));
+        assert!(err.to_string().starts_with(&path.display().to_string()));
+    }
+}
@@ -0,0 +1,124 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Typed calls of a TA for the CLIs and other front-ends of the normal
+//! world.
+//!
+//! Most hosts open a session, encode a request into an input memref, give an
+//! output memref of a fixed size, and decode the response. [`TaClient`] does
+//! it for the TAs dispatching their commands with `#[ta_dispatch]` of
+//! `optee_utee`, growing the output buffer when the TA reports a short
+//! buffer:
+//!
+//! ```no_run
+//! use optee_teec_cli::{TaClient, io};
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Serialize)]
+//! # struct SignInput { message: Vec<u8> }
+//! # #[derive(Deserialize)]
+//! # struct SignOutput { signature: Vec<u8> }
+//! # const CMD_SIGN: u32 = 0;
+//! # fn main() -> optee_teec_cli::Result<()> {
+//! let mut client = TaClient::open("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+//! let input = SignInput {
+//!     message: io::read_input("message.bin".as_ref())?,
+//! };
+//! let output: SignOutput = client.call(CMD_SIGN, &input)?;
+//! io::write_output("-".as_ref(), &output.signature)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The requests and responses are JSON by default, as with the `Json` codec
+//! of `optee_utee::dispatch`. A TA using another encoding is called with a
+//! client of another [`Codec`].
+
+mod client;
+mod codec;
+pub mod io;
+
+use std::fmt;
+use std::path::PathBuf;
+
+pub use client::TaClient;
+pub use codec::{Codec, Json};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Error of a call of the TA, or of the input and output of the CLI.
+#[derive(Debug)]
+pub enum Error {
+    /// The session could not be opened or the command failed, with the
+    /// message the TA wrote into the output buffer if any.
+    Tee {
+        error: optee_teec::Error,
+        message: Option<String>,
+    },
+    /// The request could not be encoded.
+    Encode(String),
+    /// The response could not be decoded.
+    Decode(String),
+    /// A file of the CLI could not be read or written, `-` for the standard
+    /// input or output.
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+}
+
+impl Error {
+    /// The TEE error of the call, if it failed in the TEE.
+    pub fn tee_error(&self) -> Option<&optee_teec::Error> {
+        match self {
+            Self::Tee { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<optee_teec::Error> for Error {
+    fn from(error: optee_teec::Error) -> Self {
+        Self::Tee {
+            error,
+            message: None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Tee {
+                error,
+                message: Some(message),
+            } => write!(f, "{}: {}", error, message),
+            Self::Tee { error, .. } => write!(f, "{}", error),
+            Self::Encode(e) => write!(f, "failed to encode the request: {}", e),
+            Self::Decode(e) => write!(f, "failed to decode the response: {}", e),
+            Self::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Tee { error, .. } => Some(error),
+            Self::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
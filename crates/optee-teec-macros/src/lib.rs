@@ -78,6 +78,67 @@ pub fn plugin_init(_args: TokenStream, input: TokenStream) -> TokenStream {
     .into()
 }
 
+/// Attribute to run an existing function when the plugin is unloaded, i.e.
+/// when tee-supplicant exits, to release the state of the plugin
+/// ``` ignore
+/// # /// NOTE: This example uses `optee_teec`, but including it as a
+/// # /// dev-dependency would introduce a cyclic dependency when publishing the
+/// # /// crate. Therefore, the example is intentionally marked as `ignore`.
+/// use optee_teec_macros::plugin_shutdown;
+///
+/// #[plugin_shutdown]
+/// fn plugin_shutdown() {
+///     STATE.shutdown();
+/// }
+/// ```
+///
+/// The function is registered as a destructor of the shared object, which is
+/// not run if tee-supplicant is killed by a signal it does not handle.
+#[proc_macro_attribute]
+pub fn plugin_shutdown(_args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = parse_macro_input!(input as syn::ItemFn);
+    let f_vis = &f.vis;
+    let f_block = &f.block;
+    let f_sig = &f.sig;
+
+    // check the function signature
+    let valid_signature = f_sig.constness.is_none()
+        && matches!(f_vis, syn::Visibility::Inherited)
+        && f_sig.asyncness.is_none()
+        && f_sig.abi.is_none()
+        && f_sig.inputs.is_empty()
+        && f_sig.generics.params.is_empty()
+        && f_sig.variadic.is_none()
+        && matches!(f_sig.output, syn::ReturnType::Default);
+
+    if !valid_signature {
+        return syn::parse::Error::new(
+            f.span(),
+            "`#[plugin_shutdown]` function must have signature `fn()`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let origin_fn_name = &f_sig.ident;
+    quote!(
+        #f_vis #f_sig {
+            #f_block
+        }
+        const _: fn() = #origin_fn_name;
+
+        #[used]
+        #[unsafe(link_section = ".fini_array")]
+        static __PLUGIN_BINDGEN_SHUTDOWN: unsafe extern "C" fn() = {
+            unsafe extern "C" fn shutdown() {
+                #origin_fn_name()
+            }
+            shutdown
+        };
+    )
+    .into()
+}
+
 // check if return_type of the function is `optee_teec::Result<()>`
 fn check_return_type(item_fn: &syn::ItemFn) -> bool {
     const EXPECTED: [&str; 2] = ["optee_teec", "Result"];
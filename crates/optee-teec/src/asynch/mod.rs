@@ -0,0 +1,465 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Async wrappers of [`Context`](crate::Context) and
+//! [`Session`](crate::Session), enabled by the `async` feature.
+//!
+//! The client API blocks the calling thread until the TA returns, which
+//! stalls the executor when called from an async task. The wrappers run the
+//! calls on a dedicated thread pool instead and return futures, which work
+//! with any executor such as tokio:
+//!
+//! ``` no_run
+//! use optee_teec::asynch::Context;
+//! use optee_teec::{Operation, ParamNone, ParamType, ParamValue, Uuid};
+//!
+//! # async fn example() -> optee_teec::Result<()> {
+//! let ctx = Context::new()?;
+//! let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+//! let session = ctx.open_session(uuid).await?;
+//! let value = session
+//!     .invoke_command(1, |invoker| {
+//!         let p0 = ParamValue::new(29, 0, ParamType::ValueInout);
+//!         let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
+//!         invoker.invoke(&mut operation)?;
+//!         Ok(operation.parameters().0.a())
+//!     })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The closure passed to [`Session::invoke_command`] runs on a pool thread,
+//! so the operation and its buffers are created there and only the result is
+//! sent back to the task.
+//!
+//! # Ordering
+//!
+//! The commands of a session, including its clones, run one at a time in the
+//! order `invoke_command` was called. Commands of different sessions run
+//! concurrently, up to the number of pool threads.
+//!
+//! # Cancellation
+//!
+//! Dropping an [`Invocation`], e.g. when it loses a `select!` or times out,
+//! or calling [`CancelHandle::cancel`] cancels it: a command that has not
+//! started yet is skipped and resolves with [`ErrorKind::Cancel`], a running
+//! command is asked to stop with `TEEC_RequestCancellation`. A TA only stops
+//! early if it checks for cancellation, otherwise the command completes and
+//! the later commands of the session wait for it.
+
+mod pool;
+
+use self::pool::{SerialQueue, ThreadPool, lock};
+use crate::{ConnectionMethods, Error, ErrorKind, Operation, Param, ParamNone, Result, Uuid, raw};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context as TaskContext, Poll, Waker};
+use std::thread;
+
+/// Async wrapper of [`Context`](crate::Context), running the calls of its
+/// sessions on a dedicated thread pool.
+#[derive(Clone)]
+pub struct Context {
+    // Sessions share the context through a non-atomic `Rc`, so opening and
+    // closing them is serialized by this lock.
+    ctx: Arc<Mutex<crate::Context>>,
+    pool: Arc<ThreadPool>,
+}
+
+impl Context {
+    /// Creates a TEE client context with a thread pool of one thread per CPU.
+    pub fn new() -> Result<Self> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_threads(threads)
+    }
+
+    /// Creates a TEE client context with a thread pool of `threads` threads,
+    /// the maximum number of commands running concurrently.
+    pub fn with_threads(threads: usize) -> Result<Self> {
+        let ctx = crate::Context::new()?;
+        let pool = ThreadPool::new(threads).map_err(|_| Error::new(ErrorKind::OutOfMemory))?;
+        Ok(Self {
+            ctx: Arc::new(Mutex::new(ctx)),
+            pool: Arc::new(pool),
+        })
+    }
+
+    /// Opens a new session with the specified trusted application.
+    pub fn open_session(&self, uuid: Uuid) -> Invocation<Session> {
+        self.open_session_with_login(uuid, ConnectionMethods::LoginPublic)
+    }
+
+    /// Opens a new session with the specified trusted application and login
+    /// method.
+    pub fn open_session_with_login(
+        &self,
+        uuid: Uuid,
+        login: ConnectionMethods,
+    ) -> Invocation<Session> {
+        let ctx = self.ctx.clone();
+        let pool = self.pool.clone();
+        let queue = Arc::new(SerialQueue::default());
+        let session_queue = queue.clone();
+        spawn(&self.pool, &queue, move |_| {
+            let session = crate::Session::new(
+                &mut lock(&ctx),
+                uuid,
+                login,
+                None::<&mut Operation<ParamNone, ParamNone, ParamNone, ParamNone>>,
+            )?;
+            Ok(Session {
+                shared: Arc::new(SessionShared {
+                    session: Mutex::new(Some(session)),
+                    ctx,
+                    pool,
+                    queue: session_queue,
+                }),
+            })
+        })
+    }
+}
+
+struct SessionShared {
+    session: Mutex<Option<crate::Session>>,
+    ctx: Arc<Mutex<crate::Context>>,
+    pool: Arc<ThreadPool>,
+    queue: Arc<SerialQueue>,
+}
+
+impl Drop for SessionShared {
+    fn drop(&mut self) {
+        // Closing the session releases its reference to the context
+        let _ctx = lock(&self.ctx);
+        drop(
+            self.session
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(),
+        );
+    }
+}
+
+/// Async wrapper of [`Session`](crate::Session).
+///
+/// Clones refer to the same session, which is closed once the last clone is
+/// dropped and its commands are done.
+#[derive(Clone)]
+pub struct Session {
+    shared: Arc<SessionShared>,
+}
+
+impl Session {
+    /// Invokes the command `command_id` with the operation `invoke` sends
+    /// through its [`Invoker`], returning what `invoke` returns.
+    ///
+    /// `invoke` runs on a pool thread after the commands invoked on this
+    /// session before.
+    pub fn invoke_command<F, R>(&self, command_id: u32, invoke: F) -> Invocation<R>
+    where
+        F: FnOnce(&mut Invoker<'_>) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let shared = self.shared.clone();
+        spawn(&self.shared.pool, &self.shared.queue, move |cancellation| {
+            let mut session = lock(&shared.session);
+            let session = session
+                .as_mut()
+                .ok_or_else(|| Error::new(ErrorKind::BadState))?;
+            invoke(&mut Invoker {
+                session,
+                command_id,
+                cancellation,
+            })
+        })
+    }
+}
+
+/// Sends the operations of a command to the TA, see
+/// [`Session::invoke_command`].
+pub struct Invoker<'a> {
+    session: &'a mut crate::Session,
+    command_id: u32,
+    cancellation: &'a Cancellation,
+}
+
+impl Invoker<'_> {
+    /// The ID of the invoked command.
+    pub fn command_id(&self) -> u32 {
+        self.command_id
+    }
+
+    /// Invokes the command with `operation`, which may be done several
+    /// times, e.g. to retry with a larger output buffer.
+    ///
+    /// Fails with [`ErrorKind::Cancel`] without invoking the command if the
+    /// invocation has been cancelled. The operation must have been created
+    /// with `started` set to 0 for a cancellation to reach the TA.
+    pub fn invoke<A: Param, B: Param, C: Param, D: Param>(
+        &mut self,
+        operation: &mut Operation<A, B, C, D>,
+    ) -> Result<()> {
+        {
+            let mut state = lock(&self.cancellation.state);
+            if state.cancelled {
+                return Err(Error::new(ErrorKind::Cancel));
+            }
+            state.operation = Some(RunningOperation(operation.as_mut_raw_ptr()));
+        }
+        let result = self.session.invoke_command(self.command_id, operation);
+        lock(&self.cancellation.state).operation = None;
+        result
+    }
+}
+
+/// Pointer to the operation of a running command, only used while the
+/// command runs, which the cancellation lock guarantees.
+struct RunningOperation(*mut raw::TEEC_Operation);
+
+unsafe impl Send for RunningOperation {}
+
+impl RunningOperation {
+    /// # Safety
+    ///
+    /// The operation must still be alive.
+    unsafe fn request_cancellation(&self) {
+        // libteec is not linked into the unit tests, which never run
+        // operations
+        #[cfg(not(test))]
+        unsafe {
+            raw::TEEC_RequestCancellation(self.0)
+        };
+        #[cfg(test)]
+        unreachable!("no operation runs in unit tests: {:p}", self.0);
+    }
+}
+
+#[derive(Default)]
+struct CancellationState {
+    cancelled: bool,
+    operation: Option<RunningOperation>,
+}
+
+#[derive(Default)]
+struct Cancellation {
+    state: Mutex<CancellationState>,
+}
+
+impl Cancellation {
+    fn cancel(&self) {
+        let mut state = lock(&self.state);
+        state.cancelled = true;
+        if let Some(operation) = &state.operation {
+            // SAFETY: the operation stays alive while it is registered, as
+            // `Invoker::invoke` needs this lock to unregister it.
+            unsafe { operation.request_cancellation() };
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        lock(&self.state).cancelled
+    }
+}
+
+/// Cancels an [`Invocation`] from elsewhere, e.g. another task.
+#[derive(Clone)]
+pub struct CancelHandle {
+    cancellation: Arc<Cancellation>,
+}
+
+impl CancelHandle {
+    /// Cancels the invocation, see the [module docs](self#cancellation).
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+}
+
+struct CallState<R> {
+    result: Option<thread::Result<Result<R>>>,
+    waker: Option<Waker>,
+}
+
+struct Call<R> {
+    state: Mutex<CallState<R>>,
+    cancellation: Arc<Cancellation>,
+}
+
+/// Future of a call running on the thread pool, resolving to its result.
+///
+/// Dropping it before it resolves cancels the call. A panic of the call is
+/// resumed when the future is polled.
+#[must_use = "an invocation is cancelled when dropped"]
+pub struct Invocation<R> {
+    call: Arc<Call<R>>,
+    done: bool,
+}
+
+impl<R> Invocation<R> {
+    /// Returns a handle cancelling this invocation.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle {
+            cancellation: self.call.cancellation.clone(),
+        }
+    }
+}
+
+impl<R> Future for Invocation<R> {
+    type Output = Result<R>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let result = {
+            let mut state = lock(&self.call.state);
+            match state.result.take() {
+                Some(result) => result,
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        };
+        self.done = true;
+        match result {
+            Ok(result) => Poll::Ready(result),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl<R> Drop for Invocation<R> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.call.cancellation.cancel();
+        }
+    }
+}
+
+/// Run `f` on `pool` after the jobs of `queue` and return its future. `f` is
+/// skipped if the invocation is cancelled before it starts.
+fn spawn<F, R>(pool: &ThreadPool, queue: &Arc<SerialQueue>, f: F) -> Invocation<R>
+where
+    F: FnOnce(&Cancellation) -> Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    let call = Arc::new(Call {
+        state: Mutex::new(CallState {
+            result: None,
+            waker: None,
+        }),
+        cancellation: Arc::new(Cancellation::default()),
+    });
+    let job_call = call.clone();
+    pool.submit(
+        queue,
+        Box::new(move || {
+            let call = job_call;
+            let result = if call.cancellation.is_cancelled() {
+                Ok(Err(Error::new(ErrorKind::Cancel)))
+            } else {
+                panic::catch_unwind(AssertUnwindSafe(|| f(&call.cancellation)))
+            };
+            let waker = {
+                let mut state = lock(&call.state);
+                state.result = Some(result);
+                state.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }),
+    );
+    Invocation { call, done: false }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::task::Wake;
+    use std::time::Duration;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = TaskContext::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn test_invocation_result() {
+        let pool = ThreadPool::new(2).unwrap();
+        let queue = Arc::new(SerialQueue::default());
+        let first = spawn(&pool, &queue, |_| Ok(1));
+        let second = spawn(&pool, &queue, |_| Err::<(), _>(Error::new(ErrorKind::Busy)));
+        assert_eq!(block_on(first).unwrap(), 1);
+        assert_eq!(block_on(second).unwrap_err().kind(), ErrorKind::Busy);
+    }
+
+    #[test]
+    fn test_cancel_before_start() {
+        let pool = ThreadPool::new(1).unwrap();
+        let queue = Arc::new(SerialQueue::default());
+        let (release, wait) = mpsc::channel::<()>();
+        let blocking = spawn(&pool, &queue, move |_| {
+            wait.recv().unwrap();
+            Ok(())
+        });
+        let (sender, receiver) = mpsc::channel();
+        let skipped = spawn(&pool, &queue, move |_| {
+            sender.send(()).unwrap();
+            Ok(())
+        });
+        let cancelled = spawn(
+            &pool,
+            &queue,
+            |cancellation| Ok(cancellation.is_cancelled()),
+        );
+        let handle = cancelled.cancel_handle();
+
+        // Dropping cancels, the handle cancels without dropping
+        drop(skipped);
+        handle.cancel();
+        release.send(()).unwrap();
+        block_on(blocking).unwrap();
+        assert_eq!(block_on(cancelled).unwrap_err().kind(), ErrorKind::Cancel);
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "call panicked")]
+    fn test_panic_resumed() {
+        let pool = ThreadPool::new(1).unwrap();
+        let queue = Arc::new(SerialQueue::default());
+        let _ = block_on(spawn(&pool, &queue, |_| -> Result<()> {
+            panic!("call panicked")
+        }));
+    }
+}
@@ -0,0 +1,217 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Thread pool running the blocking client API calls of the async wrappers.
+//!
+//! Jobs are submitted to a [`SerialQueue`], one per session. The pool runs
+//! the jobs of a queue one at a time in submission order, while the queues of
+//! different sessions are served concurrently by the worker threads.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Lock `mutex`, ignoring poisoning: jobs run outside of the pool's locks and
+/// panics in them are caught, so the protected state is always consistent.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[derive(Default)]
+struct PoolState {
+    /// Queues with pending jobs, each present at most once.
+    ready: VecDeque<Arc<SerialQueue>>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct PoolShared {
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+/// Handle to the worker threads, which exit once the last handle is dropped
+/// and the pending jobs are done.
+pub(crate) struct ThreadPool {
+    shared: Arc<PoolShared>,
+}
+
+impl ThreadPool {
+    pub(crate) fn new(threads: usize) -> std::io::Result<Self> {
+        let shared = Arc::new(PoolShared::default());
+        for index in 0..threads.max(1) {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name(format!("optee-teec-async-{}", index))
+                .spawn(move || worker(&shared))?;
+        }
+        Ok(Self { shared })
+    }
+
+    /// Run `job` after all jobs submitted to `queue` before.
+    pub(crate) fn submit(&self, queue: &Arc<SerialQueue>, job: Job) {
+        let mut jobs = lock(&queue.state);
+        jobs.pending.push_back(job);
+        if jobs.scheduled {
+            return;
+        }
+        jobs.scheduled = true;
+        drop(jobs);
+        self.shared.schedule(queue.clone());
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        lock(&self.shared.state).shutdown = true;
+        self.shared.available.notify_all();
+    }
+}
+
+impl PoolShared {
+    fn schedule(&self, queue: Arc<SerialQueue>) {
+        lock(&self.state).ready.push_back(queue);
+        self.available.notify_one();
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<Job>,
+    /// Whether the queue is ready in the pool or being run by a worker.
+    scheduled: bool,
+}
+
+/// Jobs that run one at a time, in the order they were submitted.
+#[derive(Default)]
+pub(crate) struct SerialQueue {
+    state: Mutex<QueueState>,
+}
+
+impl SerialQueue {
+    /// Run the next job, returning whether more are pending. Only called by
+    /// the worker that took the scheduled queue from the pool.
+    fn run_next(&self) -> bool {
+        let job = lock(&self.state).pending.pop_front();
+        if let Some(job) = job {
+            // A panicking job must not take the worker down, the wrappers
+            // report it to the waiting future
+            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+        }
+        let mut state = lock(&self.state);
+        state.scheduled = !state.pending.is_empty();
+        state.scheduled
+    }
+}
+
+fn worker(shared: &PoolShared) {
+    loop {
+        let queue = {
+            let mut state = lock(&shared.state);
+            loop {
+                if let Some(queue) = state.ready.pop_front() {
+                    break queue;
+                }
+                if state.shutdown {
+                    return;
+                }
+                state = shared
+                    .available
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        };
+        // One job per turn, so a busy session cannot starve the others
+        if queue.run_next() {
+            shared.schedule(queue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_queue_order() {
+        let pool = ThreadPool::new(4).unwrap();
+        let queue = Arc::new(SerialQueue::default());
+        let (sender, receiver) = mpsc::channel();
+        for i in 0..100 {
+            let sender = sender.clone();
+            pool.submit(
+                &queue,
+                Box::new(move || {
+                    if i % 10 == 0 {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    sender.send(i).unwrap();
+                }),
+            );
+        }
+        let order: Vec<i32> = receiver.iter().take(100).collect();
+        assert_eq!(order, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_queues_run_concurrently() {
+        let pool = ThreadPool::new(2).unwrap();
+        let blocked = Arc::new(SerialQueue::default());
+        let other = Arc::new(SerialQueue::default());
+        let (release, wait) = mpsc::channel::<()>();
+        let (sender, receiver) = mpsc::channel();
+        pool.submit(
+            &blocked,
+            Box::new(move || {
+                wait.recv().unwrap();
+            }),
+        );
+        pool.submit(&other, Box::new(move || sender.send(()).unwrap()));
+        // Runs although the first queue is still blocked
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_panicking_job() {
+        let pool = ThreadPool::new(1).unwrap();
+        let queue = Arc::new(SerialQueue::default());
+        let (sender, receiver) = mpsc::channel();
+        pool.submit(&queue, Box::new(|| panic!("job panicked")));
+        pool.submit(&queue, Box::new(move || sender.send(()).unwrap()));
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn test_pending_jobs_run_after_drop() {
+        let pool = ThreadPool::new(1).unwrap();
+        let queue = Arc::new(SerialQueue::default());
+        let (sender, receiver) = mpsc::channel();
+        for i in 0..10 {
+            let sender = sender.clone();
+            pool.submit(&queue, Box::new(move || sender.send(i).unwrap()));
+        }
+        drop(pool);
+        let done: Vec<i32> = receiver.iter().take(10).collect();
+        assert_eq!(done, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,278 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use std::io::{self, Read, Write};
+
+use crate::{Error, ErrorKind, Operation, ParamTmpRef, ParamType, ParamValue, Result, Session};
+
+// Flags of a chunk, as defined by `optee_utee::chunked`
+const FLAG_FIRST: u32 = 1 << 0;
+const FLAG_LAST: u32 = 1 << 1;
+const FLAG_FETCH: u32 = 1 << 2;
+
+/// Sends requests and receives responses larger than the shared memory
+/// limit in chunks, over several invocations of a command.
+///
+/// The TA handles the command with `optee_utee::chunked::ChunkedReceiver`,
+/// whose documentation describes the protocol. The request is read from a
+/// [`Read`] and the response written to a [`Write`], so neither has to be
+/// held in memory on the client side.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use optee_teec::{ChunkedSession, Context, Uuid};
+/// # const CMD_RESTORE_BACKUP: u32 = 0;
+/// # fn main() -> optee_teec::Result<()> {
+/// # let mut ctx = Context::new()?;
+/// # let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+/// # let mut session = ctx.open_session(uuid)?;
+/// let backup = std::fs::File::open("wallet.backup").expect("the backup exists");
+/// let mut report = Vec::new();
+/// ChunkedSession::new(&mut session)
+///     .chunk_size(64 * 1024)
+///     .invoke_large(CMD_RESTORE_BACKUP, backup, &mut report)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChunkedSession<'s> {
+    session: &'s mut Session,
+    chunk_size: usize,
+}
+
+impl<'s> ChunkedSession<'s> {
+    /// The default size of the chunks, 256 KiB, well below the default
+    /// shared memory pool of OP-TEE.
+    pub const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+
+    /// Creates a chunked session invoking the commands with `session`.
+    pub fn new(session: &'s mut Session) -> Self {
+        Self {
+            session,
+            chunk_size: Self::DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets the largest chunk passed in a single invocation, in each
+    /// direction. At least 1 byte.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Invokes the command `command_id` with the request read from `request`
+    /// until its end, and writes the response to `response`.
+    ///
+    /// Reading the request or writing the response failing fails the
+    /// transfer with `ErrorKind::Generic`.
+    pub fn invoke_large<R: Read, W: Write>(
+        &mut self,
+        command_id: u32,
+        request: R,
+        response: W,
+    ) -> Result<()> {
+        let session = &mut *self.session;
+        transfer(
+            self.chunk_size,
+            request,
+            response,
+            |sequence, flags, chunk, output| {
+                let p0 = ParamValue::new(sequence, flags, ParamType::ValueInput);
+                let p1 = ParamTmpRef::new_input(chunk);
+                let p2 = ParamTmpRef::new_output(output);
+                let p3 = ParamValue::new(0, 0, ParamType::ValueOutput);
+                let mut operation = Operation::new(0, p0, p1, p2, p3);
+                session.invoke_command(command_id, &mut operation)?;
+                let (_, _, p2, p3) = operation.parameters();
+                Ok((p2.updated_size(), p3.a()))
+            },
+        )
+    }
+}
+
+/// Runs a chunked transfer of `chunk_size` bytes chunks, where `invoke`
+/// performs one invocation with its sequence number, flags, request chunk
+/// and output buffer, and returns the size of the response chunk and the
+/// number of bytes of the response left.
+fn transfer<R, W, F>(
+    chunk_size: usize,
+    mut request: R,
+    mut response: W,
+    mut invoke: F,
+) -> Result<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(u32, u32, &[u8], &mut [u8]) -> Result<(usize, u32)>,
+{
+    let mut chunk = Vec::with_capacity(chunk_size);
+    let mut next = Vec::with_capacity(chunk_size);
+    let mut output = vec![0u8; chunk_size];
+    let mut sequence = 0;
+    let mut flags = FLAG_FIRST;
+
+    fill(&mut request, &mut chunk, chunk_size)?;
+    let mut remaining = loop {
+        // Read ahead to know whether the chunk is the last one
+        next.clear();
+        if chunk.len() == chunk_size {
+            fill(&mut request, &mut next, chunk_size)?;
+        }
+        if next.is_empty() {
+            flags |= FLAG_LAST;
+        }
+        let (written, remaining) = invoke(sequence, flags, &chunk, &mut output)?;
+        sequence = sequence.wrapping_add(1);
+        if flags & FLAG_LAST != 0 {
+            write(&mut response, &output, written)?;
+            break remaining;
+        }
+        flags = 0;
+        std::mem::swap(&mut chunk, &mut next);
+    };
+
+    while remaining > 0 {
+        let (written, left) = invoke(sequence, FLAG_FETCH, &[], &mut output)?;
+        sequence = sequence.wrapping_add(1);
+        // A TA returning nothing while data is left would loop forever
+        if written == 0 {
+            return Err(ErrorKind::Communication.into());
+        }
+        write(&mut response, &output, written)?;
+        remaining = left;
+    }
+    response.flush().map_err(io_error)
+}
+
+/// Reads from `reader` into `chunk` until it holds `chunk_size` bytes or the
+/// end of the input.
+fn fill<R: Read>(reader: &mut R, chunk: &mut Vec<u8>, chunk_size: usize) -> Result<()> {
+    reader
+        .take(chunk_size as u64)
+        .read_to_end(chunk)
+        .map_err(io_error)?;
+    Ok(())
+}
+
+fn write<W: Write>(writer: &mut W, output: &[u8], written: usize) -> Result<()> {
+    // The TA cannot report more than the size of the buffer
+    let data = output
+        .get(..written)
+        .ok_or(Error::from(ErrorKind::Communication))?;
+    writer.write_all(data).map_err(io_error)
+}
+
+fn io_error(err: io::Error) -> Error {
+    log::debug!("Chunked transfer I/O error: {}", err);
+    ErrorKind::Generic.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mimics a TA running `optee_utee::chunked::ChunkedReceiver` with a
+    /// handler reversing the request, and records the invocations.
+    struct Ta {
+        request: Vec<u8>,
+        response: Vec<u8>,
+        invocations: Vec<(u32, u32, usize)>,
+    }
+
+    impl Ta {
+        fn new() -> Self {
+            Self {
+                request: Vec::new(),
+                response: Vec::new(),
+                invocations: Vec::new(),
+            }
+        }
+
+        fn invoke(
+            &mut self,
+            sequence: u32,
+            flags: u32,
+            chunk: &[u8],
+            output: &mut [u8],
+        ) -> Result<(usize, u32)> {
+            self.invocations.push((sequence, flags, chunk.len()));
+            if flags & FLAG_FETCH == 0 {
+                self.request.extend_from_slice(chunk);
+                if flags & FLAG_LAST == 0 {
+                    return Ok((0, 0));
+                }
+                self.response = self.request.iter().rev().copied().collect();
+            }
+            let written = output.len().min(self.response.len());
+            output[..written].copy_from_slice(&self.response[..written]);
+            self.response.drain(..written);
+            Ok((written, self.response.len() as u32))
+        }
+    }
+
+    fn run(chunk_size: usize, request: &[u8]) -> (Ta, Vec<u8>) {
+        let mut ta = Ta::new();
+        let mut response = Vec::new();
+        transfer(chunk_size, request, &mut response, |s, f, c, o| {
+            ta.invoke(s, f, c, o)
+        })
+        .unwrap();
+        (ta, response)
+    }
+
+    #[test]
+    fn test_chunks() {
+        let (ta, response) = run(4, b"0123456789");
+        assert_eq!(response, b"9876543210");
+        assert_eq!(
+            ta.invocations,
+            [
+                (0, FLAG_FIRST, 4),
+                (1, 0, 4),
+                (2, FLAG_LAST, 2),
+                (3, FLAG_FETCH, 0),
+                (4, FLAG_FETCH, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exact_multiple_and_empty() {
+        let (ta, response) = run(4, b"01234567");
+        assert_eq!(response, b"76543210");
+        assert_eq!(ta.invocations[1], (1, FLAG_LAST, 4));
+
+        let (ta, response) = run(4, b"");
+        assert!(response.is_empty());
+        assert_eq!(ta.invocations, [(0, FLAG_FIRST | FLAG_LAST, 0)]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut response = Vec::new();
+        let err = transfer(4, &b"0123456789"[..], &mut response, |_, flags, _, _| {
+            if flags & FLAG_LAST != 0 {
+                return Err(ErrorKind::OutOfMemory.into());
+            }
+            Ok((0, 0))
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+
+        let err = transfer(4, &b"0"[..], &mut response, |_, _, _, _| Ok((0, 10))).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Communication);
+    }
+}
@@ -15,6 +15,7 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use crate::trace::Span;
 use crate::{ConnectionMethods, Error, Operation, Param, ParamNone, Result, Session, Uuid, raw};
 use std::{cell::RefCell, ptr, rc::Rc};
 
@@ -35,6 +36,7 @@ pub struct Context {
     // Use RefCell to allow conversion into a raw mutable pointer.
     // As RefCell is not Send + Sync, there is no need to use Arc.
     raw: Rc<RefCell<InnerContext>>,
+    span: Span,
 }
 
 // Since RefCell is used for Context, Rust does not automatically implement
@@ -62,6 +64,7 @@ impl Context {
         match unsafe { raw::TEEC_InitializeContext(ptr::null_mut(), &mut raw_ctx) } {
             raw::TEEC_SUCCESS => Ok(Self {
                 raw: Rc::new(RefCell::new(InnerContext(raw_ctx))),
+                span: Span::context(),
             }),
             code => Err(Error::from_raw_error(code)),
         }
@@ -137,6 +140,26 @@ impl Context {
     ) -> Result<Session> {
         Session::new(self, uuid, ConnectionMethods::LoginPublic, Some(operation))
     }
+
+    /// Replaces the span of this context, parent of the spans of the sessions
+    /// opened afterwards, e.g. to tell apart the contexts of a host with a
+    /// `tracing::info_span!("tls", client = %addr)`.
+    ///
+    /// The context starts with a `teec_context` span at the `DEBUG` level,
+    /// child of the current span when it was created. The spans are emitted
+    /// to the subscriber installed by the application, e.g. with
+    /// `tracing_subscriber::fmt().with_span_events(FmtSpan::CLOSE).init()`
+    /// printing each command with its duration.
+    #[cfg(feature = "tracing")]
+    pub fn set_span(&mut self, span: tracing::Span) {
+        self.span = span.into();
+    }
+
+    /// The span of this context, see [`set_span`](Self::set_span).
+    #[cfg(feature = "tracing")]
+    pub fn span(&self) -> &tracing::Span {
+        self.span.as_tracing()
+    }
 }
 
 // Internal usage only
@@ -145,4 +168,8 @@ impl Context {
     pub(crate) fn inner_context(&mut self) -> Rc<RefCell<InnerContext>> {
         self.raw.clone()
     }
+
+    pub(crate) fn inner_span(&self) -> &Span {
+        &self.span
+    }
 }
@@ -36,6 +36,27 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 /// The error type for TEE operations of [`Context`] and [`Session`].
 ///
+/// The error keeps the code returned by the TEE Client API, with its
+/// [`ErrorOrigin`] for the errors of a session, which tells a failure of the
+/// TA apart from one on the way to it:
+///
+/// ``` no_run
+/// use optee_teec::{ErrorKind, ErrorOrigin, Operation, ParamNone, Session};
+///
+/// fn invoke(session: &mut Session) -> optee_teec::Result<()> {
+///     let mut operation = Operation::new(0, ParamNone, ParamNone, ParamNone, ParamNone);
+///     loop {
+///         match session.invoke_command(1, &mut operation) {
+///             // The TA is busy, invoking the command again may succeed
+///             Err(e) if e.is_from_ta() && e.kind() == ErrorKind::Busy => continue,
+///             // The TEE or the driver failed, retrying will not help
+///             Err(e) if e.origin() == Some(ErrorOrigin::COMMS) => return Err(e),
+///             result => return result,
+///         }
+///     }
+/// }
+/// ```
+///
 /// [`Context`]: struct.Context.html
 /// [`Session`]: struct.Session.html
 #[derive(Clone)]
@@ -46,9 +67,7 @@ pub struct Error {
 
 /// A list specifying general categories of TEE client error and its
 /// corresponding code in OP-TEE client library.
-#[derive(
-    Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive,
-)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[repr(u32)]
 pub enum ErrorKind {
     /// Non-specific cause.
@@ -89,9 +108,9 @@ pub enum ErrorKind {
     ExternalCancel = raw::TEEC_ERROR_EXTERNAL_CANCEL,
     /// Implementation defined error code: trusted Application has panicked during the operation.
     TargetDead = raw::TEEC_ERROR_TARGET_DEAD,
-    /// Unknown error.
-    #[default]
-    Unknown,
+    /// A code the TEE Client API does not define, usually an error of the TA
+    /// outside of the ones above.
+    Other(u32),
 }
 
 impl ErrorKind {
@@ -120,7 +139,61 @@ impl ErrorKind {
             ErrorKind::ShortBuffer => "The supplied buffer is too short for the generated output.",
             ErrorKind::ExternalCancel => "Undocumented.",
             ErrorKind::TargetDead => "Trusted Application has panicked during the operation.",
-            ErrorKind::Unknown => "Unknown error.",
+            ErrorKind::Other(_) => "Error code not defined by the TEE Client API.",
+        }
+    }
+}
+
+impl From<u32> for ErrorKind {
+    fn from(code: u32) -> Self {
+        match code {
+            raw::TEEC_ERROR_GENERIC => ErrorKind::Generic,
+            raw::TEEC_ERROR_ACCESS_DENIED => ErrorKind::AccessDenied,
+            raw::TEEC_ERROR_CANCEL => ErrorKind::Cancel,
+            raw::TEEC_ERROR_ACCESS_CONFLICT => ErrorKind::AccessConflict,
+            raw::TEEC_ERROR_EXCESS_DATA => ErrorKind::ExcessData,
+            raw::TEEC_ERROR_BAD_FORMAT => ErrorKind::BadFormat,
+            raw::TEEC_ERROR_BAD_PARAMETERS => ErrorKind::BadParameters,
+            raw::TEEC_ERROR_BAD_STATE => ErrorKind::BadState,
+            raw::TEEC_ERROR_ITEM_NOT_FOUND => ErrorKind::ItemNotFound,
+            raw::TEEC_ERROR_NOT_IMPLEMENTED => ErrorKind::NotImplemented,
+            raw::TEEC_ERROR_NOT_SUPPORTED => ErrorKind::NotSupported,
+            raw::TEEC_ERROR_NO_DATA => ErrorKind::NoData,
+            raw::TEEC_ERROR_OUT_OF_MEMORY => ErrorKind::OutOfMemory,
+            raw::TEEC_ERROR_BUSY => ErrorKind::Busy,
+            raw::TEEC_ERROR_COMMUNICATION => ErrorKind::Communication,
+            raw::TEEC_ERROR_SECURITY => ErrorKind::Security,
+            raw::TEEC_ERROR_SHORT_BUFFER => ErrorKind::ShortBuffer,
+            raw::TEEC_ERROR_EXTERNAL_CANCEL => ErrorKind::ExternalCancel,
+            raw::TEEC_ERROR_TARGET_DEAD => ErrorKind::TargetDead,
+            code => ErrorKind::Other(code),
+        }
+    }
+}
+
+impl From<ErrorKind> for u32 {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::Generic => raw::TEEC_ERROR_GENERIC,
+            ErrorKind::AccessDenied => raw::TEEC_ERROR_ACCESS_DENIED,
+            ErrorKind::Cancel => raw::TEEC_ERROR_CANCEL,
+            ErrorKind::AccessConflict => raw::TEEC_ERROR_ACCESS_CONFLICT,
+            ErrorKind::ExcessData => raw::TEEC_ERROR_EXCESS_DATA,
+            ErrorKind::BadFormat => raw::TEEC_ERROR_BAD_FORMAT,
+            ErrorKind::BadParameters => raw::TEEC_ERROR_BAD_PARAMETERS,
+            ErrorKind::BadState => raw::TEEC_ERROR_BAD_STATE,
+            ErrorKind::ItemNotFound => raw::TEEC_ERROR_ITEM_NOT_FOUND,
+            ErrorKind::NotImplemented => raw::TEEC_ERROR_NOT_IMPLEMENTED,
+            ErrorKind::NotSupported => raw::TEEC_ERROR_NOT_SUPPORTED,
+            ErrorKind::NoData => raw::TEEC_ERROR_NO_DATA,
+            ErrorKind::OutOfMemory => raw::TEEC_ERROR_OUT_OF_MEMORY,
+            ErrorKind::Busy => raw::TEEC_ERROR_BUSY,
+            ErrorKind::Communication => raw::TEEC_ERROR_COMMUNICATION,
+            ErrorKind::Security => raw::TEEC_ERROR_SECURITY,
+            ErrorKind::ShortBuffer => raw::TEEC_ERROR_SHORT_BUFFER,
+            ErrorKind::ExternalCancel => raw::TEEC_ERROR_EXTERNAL_CANCEL,
+            ErrorKind::TargetDead => raw::TEEC_ERROR_TARGET_DEAD,
+            ErrorKind::Other(code) => code,
         }
     }
 }
@@ -146,6 +219,26 @@ impl Error {
         }
     }
 
+    /// Creates an `Error` from the code and the origin returned by the TEE
+    /// Client API.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use optee_teec::{Error, ErrorKind, ErrorOrigin};
+    ///
+    /// let error = Error::from_raw(0x80000001, 4);
+    /// assert_eq!(error.kind(), ErrorKind::Other(0x80000001));
+    /// assert_eq!(error.origin(), Some(ErrorOrigin::TA));
+    /// ```
+    pub fn from_raw(code: u32, origin: u32) -> Error {
+        Error {
+            kind: ErrorKind::from(code),
+            origin: Some(ErrorOrigin::from(origin)),
+        }
+    }
+
+    /// Sets the origin of this error.
     pub fn with_origin(mut self, origin: ErrorOrigin) -> Self {
         self.origin = Some(origin);
         self
@@ -166,7 +259,19 @@ impl Error {
 
     /// Returns the origin of this error.
     pub fn origin(&self) -> Option<ErrorOrigin> {
-        self.origin.clone()
+        self.origin
+    }
+
+    /// Returns whether the error was returned by the TA itself, rather than
+    /// by the client library, the driver or the TEE on its way to the TA.
+    pub fn is_from_ta(&self) -> bool {
+        self.origin == Some(ErrorOrigin::TA)
+    }
+
+    /// Returns whether the TA has died, e.g. it panicked, and the session
+    /// must be reopened. A TA returning `TargetDead` itself has not.
+    pub fn is_target_dead(&self) -> bool {
+        self.kind == ErrorKind::TargetDead && !self.is_from_ta()
     }
 
     /// Returns raw code of this error.
@@ -194,15 +299,15 @@ impl fmt::Debug for Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        write!(f, "{} (error code 0x{:x}", self.message(), self.raw_code())?;
+        match self.origin {
+            Some(origin) => write!(f, ", returned by {})", origin),
+            None => f.write_str(")"),
+        }
     }
 }
 
-impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        self.message()
-    }
-}
+impl std::error::Error for Error {}
 
 impl From<ErrorKind> for Error {
     #[inline]
@@ -211,13 +316,75 @@ impl From<ErrorKind> for Error {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, FromPrimitive, IntoPrimitive)]
+impl From<Error> for u32 {
+    fn from(error: Error) -> Self {
+        error.raw_code()
+    }
+}
+
+/// Where an error of a session was raised, from the client to the TA.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, FromPrimitive, IntoPrimitive)]
 #[repr(u32)]
 pub enum ErrorOrigin {
+    /// The TEE Client API of the client library.
     API = raw::TEEC_ORIGIN_API,
+    /// The communication between the client and the TEE, i.e. the driver.
     COMMS = raw::TEEC_ORIGIN_COMMS,
+    /// The TEE, outside of the TA, e.g. when loading it or after it panicked.
     TEE = raw::TEEC_ORIGIN_TEE,
+    /// The TA itself.
     TA = raw::TEEC_ORIGIN_TRUSTED_APP,
+    /// An origin the TEE Client API does not define.
     #[default]
     UNKNOWN,
 }
+
+impl fmt::Display for ErrorOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ErrorOrigin::API => "the client library",
+            ErrorOrigin::COMMS => "the communication with the TEE",
+            ErrorOrigin::TEE => "the TEE",
+            ErrorOrigin::TA => "the TA",
+            ErrorOrigin::UNKNOWN => "an unknown origin",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_code_round_trip() {
+        for code in [
+            raw::TEEC_ERROR_BUSY,
+            raw::TEEC_ERROR_TARGET_DEAD,
+            0x80000001,
+        ] {
+            let error = Error::from_raw_error(code);
+            assert_eq!(error.raw_code(), code);
+            assert_eq!(u32::from(error.kind()), code);
+            assert_eq!(u32::from(error), code);
+        }
+        assert_eq!(ErrorKind::from(0x80000001), ErrorKind::Other(0x80000001));
+    }
+
+    #[test]
+    fn test_origin() {
+        let error = Error::from_raw(raw::TEEC_ERROR_TARGET_DEAD, raw::TEEC_ORIGIN_TEE);
+        assert_eq!(error.origin(), Some(ErrorOrigin::TEE));
+        assert!(!error.is_from_ta());
+        assert!(error.is_target_dead());
+        assert_eq!(
+            error.to_string(),
+            "Trusted Application has panicked during the operation. \
+            (error code 0xffff3024, returned by the TEE)"
+        );
+
+        let error = Error::from_raw(raw::TEEC_ERROR_TARGET_DEAD, raw::TEEC_ORIGIN_TRUSTED_APP);
+        assert!(error.is_from_ta());
+        assert!(!error.is_target_dead());
+        assert_eq!(Error::from_raw(0, 42).origin(), Some(ErrorOrigin::UNKNOWN));
+    }
+}
@@ -22,12 +22,21 @@
     document_features::document_features!(),
 ))]
 
+pub use self::chunked::ChunkedSession;
 pub use self::context::Context;
 pub use self::error::{Error, ErrorKind, ErrorOrigin, Result};
 pub use self::extension::*;
-pub use self::operation::Operation;
-pub use self::parameter::{Param, ParamNone, ParamTmpRef, ParamType, ParamTypes, ParamValue};
+pub use self::operation::{Operation, OperationBuilder};
+pub use self::output::OutputReader;
+pub use self::parameter::{
+    Param, ParamNone, ParamSharedMemRef, ParamTmpRef, ParamType, ParamTypes, ParamValue,
+};
+pub use self::plugin::{PluginInfo, PluginRouter, PluginState};
+pub use self::ring::{RingReceiver, RingSender};
+pub use self::self_test::{SELF_TEST_COMMAND_ID, SelfTestCase, SelfTestReport};
 pub use self::session::{ConnectionMethods, Session};
+pub use self::session_pool::{PooledSession, SessionPool};
+pub use self::shared_memory::{SharedMemory, SharedMemoryFlags};
 pub use self::uuid::Uuid;
 // Re-export optee_teec_sys so developers don't have to add it to their cargo
 // dependencies.
@@ -40,10 +49,26 @@ pub use optee_teec_sys as raw;
 #[cfg(feature = "macros")]
 pub use optee_teec_macros as macros;
 
+#[cfg(feature = "async")]
+pub mod asynch;
+
+mod chunked;
 mod context;
 mod error;
 mod extension;
 mod operation;
+mod output;
 mod parameter;
+mod plugin;
+#[cfg(feature = "proxy")]
+pub mod proxy;
+pub mod ree_kv;
+pub mod resolver;
+mod ring;
+mod self_test;
 mod session;
+mod session_pool;
+mod shared_memory;
+mod trace;
+pub mod user_auth;
 mod uuid;
@@ -15,12 +15,35 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use crate::{Param, ParamTypes, raw};
+use crate::{Param, ParamNone, ParamTmpRef, ParamType, ParamTypes, ParamValue, raw};
 use std::{marker::PhantomData, mem};
 
 /// This type defines the payload of either an open session operation or an
 /// invoke command operation. It is also used for cancellation of operations,
 /// which may be desirable even if no payload is passed.
+///
+/// An operation is easiest created with [`Operation::builder`], which
+/// appends the parameters in order and leaves the remaining ones `None`:
+///
+/// ``` no_run
+/// use optee_teec::{Context, Operation, Uuid};
+///
+/// fn main() -> optee_teec::Result<()> {
+///     let mut ctx = Context::new()?;
+///     let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+///     let mut session = ctx.open_session(uuid)?;
+///     let mut digest = [0u8; 32];
+///     let mut operation = Operation::builder()
+///         .memref_in(b"message")
+///         .memref_out(&mut digest)
+///         .value_out()
+///         .build();
+///     session.invoke_command(0, &mut operation)?;
+///     let (_, digest_ref, value, _) = operation.parameters();
+///     println!("{} bytes, flags {}", digest_ref.updated_size(), value.a());
+///     Ok(())
+/// }
+/// ```
 pub struct Operation<A, B, C, D> {
     raw: raw::TEEC_Operation,
     phantom0: PhantomData<A>,
@@ -54,6 +77,13 @@ impl<A: Param, B: Param, C: Param, D: Param> Operation<A, B, C, D> {
         &mut self.raw
     }
 
+    /// The types of the parameters, e.g. to check them against the parameter
+    /// types a command declares in its `optee_proto` protocol with
+    /// `u32::from(operation.param_types())`.
+    pub fn param_types(&self) -> ParamTypes {
+        ParamTypes::from(self.raw.paramTypes)
+    }
+
     pub fn parameters(&self) -> (A, B, C, D) {
         let (f0, f1, f2, f3) = ParamTypes::from(self.raw.paramTypes).into_flags();
         (
@@ -64,3 +94,286 @@ impl<A: Param, B: Param, C: Param, D: Param> Operation<A, B, C, D> {
         )
     }
 }
+
+impl Operation<ParamNone, ParamNone, ParamNone, ParamNone> {
+    /// Returns a builder of an operation, see [`OperationBuilder`].
+    pub fn builder() -> OperationBuilder<()> {
+        OperationBuilder {
+            started: 0,
+            params: (),
+        }
+    }
+}
+
+/// Builds an [`Operation`] from up to four parameters, given in order.
+///
+/// The parameter types are derived from the parameters and the unused ones
+/// are `None`, appending a fifth parameter does not compile. Output memory
+/// references borrow a mutable slice, which cannot be used again until the
+/// operation is dropped:
+///
+/// ``` compile_fail
+/// use optee_teec::Operation;
+///
+/// let mut output = [0u8; 16];
+/// let operation = Operation::builder().memref_out(&mut output).build();
+/// output[0] = 1;
+/// drop(operation);
+/// ```
+pub struct OperationBuilder<P> {
+    started: u32,
+    params: P,
+}
+
+impl<P> OperationBuilder<P> {
+    /// Sets the `started` field of the operation, 0 by default.
+    pub fn started(mut self, started: u32) -> Self {
+        self.started = started;
+        self
+    }
+
+    /// Appends `param`.
+    pub fn param<T: Param>(self, param: T) -> OperationBuilder<P::Output>
+    where
+        P: PushParam<T>,
+    {
+        OperationBuilder {
+            started: self.started,
+            params: self.params.push(param),
+        }
+    }
+
+    /// Appends a `None` parameter.
+    pub fn none(self) -> OperationBuilder<P::Output>
+    where
+        P: PushParam<ParamNone>,
+    {
+        self.param(ParamNone)
+    }
+
+    /// Appends a value parameter passing `a` and `b` to the TA.
+    pub fn value_in(self, a: u32, b: u32) -> OperationBuilder<P::Output>
+    where
+        P: PushParam<ParamValue>,
+    {
+        self.param(ParamValue::new(a, b, ParamType::ValueInput))
+    }
+
+    /// Appends a value parameter set by the TA.
+    pub fn value_out(self) -> OperationBuilder<P::Output>
+    where
+        P: PushParam<ParamValue>,
+    {
+        self.param(ParamValue::new(0, 0, ParamType::ValueOutput))
+    }
+
+    /// Appends a value parameter passing `a` and `b` to the TA, which may
+    /// update them.
+    pub fn value_inout(self, a: u32, b: u32) -> OperationBuilder<P::Output>
+    where
+        P: PushParam<ParamValue>,
+    {
+        self.param(ParamValue::new(a, b, ParamType::ValueInout))
+    }
+
+    /// Appends a temporary memory reference to `buffer`, read by the TA.
+    pub fn memref_in<'a>(self, buffer: &'a [u8]) -> OperationBuilder<P::Output>
+    where
+        P: PushParam<ParamTmpRef<'a>>,
+    {
+        self.param(ParamTmpRef::new_input(buffer))
+    }
+
+    /// Appends a temporary memory reference to `buffer`, written by the TA.
+    pub fn memref_out<'a>(self, buffer: &'a mut [u8]) -> OperationBuilder<P::Output>
+    where
+        P: PushParam<ParamTmpRef<'a>>,
+    {
+        self.param(ParamTmpRef::new_output(buffer))
+    }
+
+    /// Appends a temporary memory reference to `buffer`, read and written by
+    /// the TA.
+    pub fn memref_inout<'a>(self, buffer: &'a mut [u8]) -> OperationBuilder<P::Output>
+    where
+        P: PushParam<ParamTmpRef<'a>>,
+    {
+        self.param(ParamTmpRef::new_inout(buffer))
+    }
+
+    /// Creates the operation.
+    pub fn build(self) -> Operation<P::P0, P::P1, P::P2, P::P3>
+    where
+        P: IntoParams,
+    {
+        let (p0, p1, p2, p3) = self.params.into_params();
+        Operation::new(self.started, p0, p1, p2, p3)
+    }
+}
+
+/// The parameters of an [`OperationBuilder`] accepting one more parameter.
+pub trait PushParam<T> {
+    type Output;
+
+    fn push(self, param: T) -> Self::Output;
+}
+
+impl<T> PushParam<T> for () {
+    type Output = (T,);
+
+    fn push(self, param: T) -> Self::Output {
+        (param,)
+    }
+}
+
+impl<A, T> PushParam<T> for (A,) {
+    type Output = (A, T);
+
+    fn push(self, param: T) -> Self::Output {
+        (self.0, param)
+    }
+}
+
+impl<A, B, T> PushParam<T> for (A, B) {
+    type Output = (A, B, T);
+
+    fn push(self, param: T) -> Self::Output {
+        (self.0, self.1, param)
+    }
+}
+
+impl<A, B, C, T> PushParam<T> for (A, B, C) {
+    type Output = (A, B, C, T);
+
+    fn push(self, param: T) -> Self::Output {
+        (self.0, self.1, self.2, param)
+    }
+}
+
+/// The parameters of an [`OperationBuilder`], padded with `None` to four.
+pub trait IntoParams {
+    type P0: Param;
+    type P1: Param;
+    type P2: Param;
+    type P3: Param;
+
+    fn into_params(self) -> (Self::P0, Self::P1, Self::P2, Self::P3);
+}
+
+impl IntoParams for () {
+    type P0 = ParamNone;
+    type P1 = ParamNone;
+    type P2 = ParamNone;
+    type P3 = ParamNone;
+
+    fn into_params(self) -> (ParamNone, ParamNone, ParamNone, ParamNone) {
+        (ParamNone, ParamNone, ParamNone, ParamNone)
+    }
+}
+
+impl<A: Param> IntoParams for (A,) {
+    type P0 = A;
+    type P1 = ParamNone;
+    type P2 = ParamNone;
+    type P3 = ParamNone;
+
+    fn into_params(self) -> (A, ParamNone, ParamNone, ParamNone) {
+        (self.0, ParamNone, ParamNone, ParamNone)
+    }
+}
+
+impl<A: Param, B: Param> IntoParams for (A, B) {
+    type P0 = A;
+    type P1 = B;
+    type P2 = ParamNone;
+    type P3 = ParamNone;
+
+    fn into_params(self) -> (A, B, ParamNone, ParamNone) {
+        (self.0, self.1, ParamNone, ParamNone)
+    }
+}
+
+impl<A: Param, B: Param, C: Param> IntoParams for (A, B, C) {
+    type P0 = A;
+    type P1 = B;
+    type P2 = C;
+    type P3 = ParamNone;
+
+    fn into_params(self) -> (A, B, C, ParamNone) {
+        (self.0, self.1, self.2, ParamNone)
+    }
+}
+
+impl<A: Param, B: Param, C: Param, D: Param> IntoParams for (A, B, C, D) {
+    type P0 = A;
+    type P1 = B;
+    type P2 = C;
+    type P3 = D;
+
+    fn into_params(self) -> (A, B, C, D) {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param_types<A, B, C, D>(operation: &Operation<A, B, C, D>) -> [u32; 4] {
+        let (p0, p1, p2, p3) = ParamTypes::from(operation.raw.paramTypes).into_flags();
+        [p0 as u32, p1 as u32, p2 as u32, p3 as u32]
+    }
+
+    #[test]
+    fn test_builder() {
+        let operation = Operation::builder().build();
+        assert_eq!(param_types(&operation), [0, 0, 0, 0]);
+
+        let mut output = [0u8; 4];
+        let mut operation = Operation::builder()
+            .value_in(1, 2)
+            .memref_in(b"abc")
+            .none()
+            .memref_out(&mut output)
+            .started(1)
+            .build();
+        assert_eq!(operation.raw.started, 1);
+        assert_eq!(
+            param_types(&operation),
+            [
+                ParamType::ValueInput as u32,
+                ParamType::MemrefTempInput as u32,
+                ParamType::None as u32,
+                ParamType::MemrefTempOutput as u32,
+            ]
+        );
+
+        // Mimics the TA writing 2 bytes of output
+        unsafe {
+            let raw = &mut operation.raw.params[3].tmpref;
+            *(raw.buffer as *mut u8) = 7;
+            raw.size = 2;
+        }
+        let (value, input, _, output_ref) = operation.parameters();
+        assert_eq!((value.a(), value.b()), (1, 2));
+        assert_eq!(input.updated_size(), 3);
+        assert_eq!(output_ref.updated_size(), 2);
+        assert_eq!(output[0], 7);
+    }
+
+    #[test]
+    fn test_builder_values() {
+        let operation = Operation::builder().value_out().value_inout(3, 4).build();
+        assert_eq!(
+            param_types(&operation),
+            [
+                ParamType::ValueOutput as u32,
+                ParamType::ValueInout as u32,
+                0,
+                0
+            ]
+        );
+        let (_, inout, _, _) = operation.parameters();
+        assert_eq!((inout.a(), inout.b()), (3, 4));
+    }
+}
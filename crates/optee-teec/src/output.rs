@@ -0,0 +1,164 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use crate::{ErrorKind, Result};
+
+/// Reads variable-sized output of a command following the GlobalPlatform
+/// short-buffer convention.
+///
+/// When the output buffer is too small, the Trusted Application (TA) returns
+/// `ErrorKind::ShortBuffer` and reports the required size as the updated
+/// size of the memref (see `optee_utee::OutputWriter`). `OutputReader` then
+/// grows the buffer to the required size and invokes the command again.
+/// [`Session::invoke_with_growable_output`](crate::Session::invoke_with_growable_output)
+/// does so for the common layout of an input memref followed by an output
+/// memref.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use optee_teec::{Context, Operation, OutputReader, ParamNone, ParamTmpRef, Uuid};
+/// # fn main() -> optee_teec::Result<()> {
+/// # let mut ctx = Context::new()?;
+/// # let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+/// # let mut session = ctx.open_session(uuid)?;
+/// let output = OutputReader::new(64).read(|buffer| {
+///     let p0 = ParamTmpRef::new_output(buffer);
+///     let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
+///     let result = session.invoke_command(0, &mut operation);
+///     (result, operation.parameters().0.updated_size())
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct OutputReader {
+    initial_capacity: usize,
+    max_capacity: usize,
+}
+
+impl OutputReader {
+    /// The default upper bound of the output buffer, 1 MiB.
+    pub const DEFAULT_MAX_CAPACITY: usize = 1024 * 1024;
+
+    /// Creates a reader starting with an `initial_capacity` bytes buffer.
+    pub fn new(initial_capacity: usize) -> Self {
+        Self {
+            initial_capacity,
+            max_capacity: Self::DEFAULT_MAX_CAPACITY,
+        }
+    }
+
+    /// Sets the largest buffer the reader is allowed to allocate. A TA
+    /// requiring more fails the read with `ErrorKind::ExcessData`.
+    pub fn max_capacity(mut self, max_capacity: usize) -> Self {
+        self.max_capacity = max_capacity;
+        self
+    }
+
+    /// Invokes `invoke` with an output buffer until the output fits, and
+    /// returns the output truncated to its updated size.
+    ///
+    /// `invoke` must pass the buffer as an output memref and return the
+    /// result of the command together with the updated size of that memref.
+    pub fn read<F>(&self, mut invoke: F) -> Result<Vec<u8>>
+    where
+        F: FnMut(&mut [u8]) -> (Result<()>, usize),
+    {
+        let mut buffer = vec![0u8; self.initial_capacity.min(self.max_capacity)];
+        loop {
+            let (result, updated_size) = invoke(&mut buffer);
+            match result {
+                Ok(()) => {
+                    buffer.truncate(updated_size.min(buffer.len()));
+                    return Ok(buffer);
+                }
+                // A TA reporting a size that fits the buffer is not following
+                // the convention, retrying would loop forever.
+                Err(e) if e.kind() == ErrorKind::ShortBuffer && updated_size > buffer.len() => {
+                    if updated_size > self.max_capacity {
+                        return Err(ErrorKind::ExcessData.into());
+                    }
+                    buffer.resize(updated_size, 0);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mimics a TA producing `data` the way `optee_utee::OutputWriter` does.
+    fn ta_output(data: &[u8], buffer: &mut [u8]) -> (Result<()>, usize) {
+        if data.len() > buffer.len() {
+            return (Err(ErrorKind::ShortBuffer.into()), data.len());
+        }
+        buffer[..data.len()].copy_from_slice(data);
+        (Ok(()), data.len())
+    }
+
+    #[test]
+    fn test_fits() {
+        let mut calls = 0;
+        let output = OutputReader::new(16)
+            .read(|buffer| {
+                calls += 1;
+                ta_output(b"hello", buffer)
+            })
+            .unwrap();
+        assert_eq!(output, b"hello");
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_grows_to_required_size() {
+        let data = [0x5a; 100];
+        let mut capacities = Vec::new();
+        let output = OutputReader::new(8)
+            .read(|buffer| {
+                capacities.push(buffer.len());
+                ta_output(&data, buffer)
+            })
+            .unwrap();
+        assert_eq!(output, data);
+        assert_eq!(capacities, [8, 100]);
+    }
+
+    #[test]
+    fn test_max_capacity() {
+        let data = [0x5a; 100];
+        let err = OutputReader::new(8)
+            .max_capacity(64)
+            .read(|buffer| ta_output(&data, buffer))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ExcessData);
+    }
+
+    #[test]
+    fn test_other_errors_and_bogus_sizes() {
+        let err = OutputReader::new(8)
+            .read(|_| (Err(ErrorKind::BadParameters.into()), 100))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadParameters);
+
+        let err = OutputReader::new(8)
+            .read(|_| (Err(ErrorKind::ShortBuffer.into()), 4))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ShortBuffer);
+    }
+}
@@ -15,7 +15,7 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use crate::raw;
+use crate::{ErrorKind, Result, SharedMemory, raw};
 use std::{marker, mem};
 
 pub trait Param {
@@ -170,9 +170,127 @@ impl<'a> Param for ParamTmpRef<'a> {
     }
 }
 
+/// This type defines a registered memory reference to a [`SharedMemory`]
+/// block. It is used as a `Operation` parameter when the corresponding
+/// parameter type is `MemrefWhole` or one of `MemrefPartialInput`,
+/// `MemrefPartialOutput`, or `MemrefPartialInout`.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use optee_teec::{Context, Operation, ParamNone, ParamSharedMemRef};
+/// use optee_teec::{SharedMemory, SharedMemoryFlags, Uuid};
+///
+/// fn main() -> optee_teec::Result<()> {
+///     let mut ctx = Context::new()?;
+///     let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+///     let mut session = ctx.open_session(uuid)?;
+///     let mut shm = SharedMemory::allocate(&mut ctx, 1024 * 1024, SharedMemoryFlags::OUTPUT)?;
+///     let p0 = ParamSharedMemRef::new_whole(&mut shm);
+///     let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
+///     session.invoke_command(0, &mut operation)?;
+///     let written = operation.parameters().0.updated_size();
+///     println!("{:?}", &shm.buffer()[..written]);
+///     Ok(())
+/// }
+/// ```
+pub struct ParamSharedMemRef<'a> {
+    raw: raw::TEEC_RegisteredMemoryReference,
+    param_type: ParamType,
+    _marker: marker::PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> ParamSharedMemRef<'a> {
+    /// Creates a reference to the whole `shm` block, which the Trusted
+    /// Application (TA) reads and writes as the block's flags allow.
+    pub fn new_whole(shm: &'a mut SharedMemory<'_>) -> Self {
+        Self::new(shm, 0, shm.size(), ParamType::MemrefWhole)
+    }
+
+    /// Creates an input only reference to `size` bytes of `shm` starting at
+    /// `offset`, failing with `BadParameters` if they are out of bounds.
+    pub fn new_partial_input(
+        shm: &'a SharedMemory<'_>,
+        offset: usize,
+        size: usize,
+    ) -> Result<Self> {
+        Self::new_partial(shm, offset, size, ParamType::MemrefPartialInput)
+    }
+
+    /// Creates an output reference to `size` bytes of `shm` starting at
+    /// `offset`, failing with `BadParameters` if they are out of bounds.
+    pub fn new_partial_output(
+        shm: &'a mut SharedMemory<'_>,
+        offset: usize,
+        size: usize,
+    ) -> Result<Self> {
+        Self::new_partial(shm, offset, size, ParamType::MemrefPartialOutput)
+    }
+
+    /// Creates an input/output reference to `size` bytes of `shm` starting
+    /// at `offset`, failing with `BadParameters` if they are out of bounds.
+    pub fn new_partial_inout(
+        shm: &'a mut SharedMemory<'_>,
+        offset: usize,
+        size: usize,
+    ) -> Result<Self> {
+        Self::new_partial(shm, offset, size, ParamType::MemrefPartialInout)
+    }
+
+    fn new_partial(
+        shm: &SharedMemory<'_>,
+        offset: usize,
+        size: usize,
+        param_type: ParamType,
+    ) -> Result<Self> {
+        match offset.checked_add(size) {
+            Some(end) if end <= shm.size() => Ok(Self::new(shm, offset, size, param_type)),
+            _ => Err(ErrorKind::BadParameters.into()),
+        }
+    }
+
+    fn new(shm: &SharedMemory<'_>, offset: usize, size: usize, param_type: ParamType) -> Self {
+        let raw = raw::TEEC_RegisteredMemoryReference {
+            parent: shm.as_mut_raw_ptr(),
+            size,
+            offset,
+        };
+        Self {
+            raw,
+            param_type,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns the size of the referenced region, updated by the TA for
+    /// output references, e.g. to the number of bytes written or, with a
+    /// `ShortBuffer` error, the size it requires.
+    pub fn updated_size(&self) -> usize {
+        self.raw.size
+    }
+}
+
+impl<'a> Param for ParamSharedMemRef<'a> {
+    fn to_raw(&mut self) -> raw::TEEC_Parameter {
+        raw::TEEC_Parameter { memref: self.raw }
+    }
+
+    fn param_type(&self) -> ParamType {
+        self.param_type
+    }
+
+    fn from_raw(raw: raw::TEEC_Parameter, param_type: ParamType) -> Self {
+        Self {
+            raw: unsafe { raw.memref },
+            param_type,
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
 /// These are used to indicate the type of Parameter encoded inside the
 /// operation structure.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum ParamType {
     /// The Parameter is not used.
     None = 0,
@@ -0,0 +1,437 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+use crate::{ErrorKind, PluginParameters, Result, Uuid};
+
+type Handler = Box<dyn Fn(&mut PluginParameters) -> Result<()> + Send + Sync>;
+
+/// Dispatches the invocations of a supplicant plugin to a handler per
+/// command, or per command and sub-command, instead of matching `params.cmd`
+/// by hand.
+///
+/// The router is built once and shared by all invocations:
+///
+/// ```no_run
+/// # use optee_teec::{PluginParameters, PluginRouter, Result};
+/// use std::sync::LazyLock;
+///
+/// const CMD_PING: u32 = 0;
+/// const CMD_ECHO: u32 = 1;
+///
+/// static ROUTER: LazyLock<PluginRouter> = LazyLock::new(|| {
+///     PluginRouter::new()
+///         .route(CMD_PING, |_params| Ok(()))
+///         .route(CMD_ECHO, |params| {
+///             let input = params.get_buffer().to_vec();
+///             params.set_buf_from_slice(&input)
+///         })
+/// });
+///
+/// // Annotated with `#[plugin_invoke]` in the plugin
+/// fn invoke(params: &mut PluginParameters) -> Result<()> {
+///     ROUTER.dispatch(params)
+/// }
+/// ```
+#[derive(Default)]
+pub struct PluginRouter {
+    // (cmd, sub_cmd), `None` for any sub-command
+    routes: BTreeMap<(u32, Option<u32>), Handler>,
+}
+
+impl PluginRouter {
+    /// Creates a router without routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles the command `cmd` with `handler`, whatever its sub-command.
+    ///
+    /// A later route for the same command replaces the earlier one.
+    pub fn route<F>(mut self, cmd: u32, handler: F) -> Self
+    where
+        F: Fn(&mut PluginParameters) -> Result<()> + Send + Sync + 'static,
+    {
+        self.routes.insert((cmd, None), Box::new(handler));
+        self
+    }
+
+    /// Handles the command `cmd` with the sub-command `sub_cmd` with
+    /// `handler`, rather than the handler routed for the whole command.
+    ///
+    /// A later route for the same command and sub-command replaces the
+    /// earlier one.
+    pub fn route_sub<F>(mut self, cmd: u32, sub_cmd: u32, handler: F) -> Self
+    where
+        F: Fn(&mut PluginParameters) -> Result<()> + Send + Sync + 'static,
+    {
+        self.routes.insert((cmd, Some(sub_cmd)), Box::new(handler));
+        self
+    }
+
+    /// Handles the command `cmd` with `handler`, which takes the sub-command
+    /// converted to `S` and the request decoded from the JSON in the buffer,
+    /// and returns the response written to the buffer as JSON.
+    ///
+    /// The invocation fails with `BadParameters` if the sub-command cannot be
+    /// converted, with `BadFormat` if the request cannot be decoded, and with
+    /// `ShortBuffer` if the response does not fit into the buffer. Bytes
+    /// after the request, such as the zero padding of the buffer of
+    /// `optee_utee::LoadablePlugin`, are ignored.
+    ///
+    /// ```no_run
+    /// # use optee_teec::PluginRouter;
+    /// # use serde::{Deserialize, Serialize};
+    /// # const CMD_SYSLOG: u32 = 0;
+    /// #[derive(Deserialize)]
+    /// struct Message {
+    ///     text: String,
+    /// }
+    ///
+    /// #[derive(Serialize)]
+    /// struct Written {
+    ///     bytes: usize,
+    /// }
+    ///
+    /// // The priority is passed as the sub-command
+    /// let router = PluginRouter::new().route_json(CMD_SYSLOG, |priority: u32, msg: Message| {
+    ///     println!("<{}> {}", priority, msg.text);
+    ///     Ok(Written {
+    ///         bytes: msg.text.len(),
+    ///     })
+    /// });
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn route_json<S, Req, Resp, F>(self, cmd: u32, handler: F) -> Self
+    where
+        S: TryFrom<u32>,
+        Req: serde::de::DeserializeOwned,
+        Resp: serde::Serialize,
+        F: Fn(S, Req) -> Result<Resp> + Send + Sync + 'static,
+    {
+        self.route(cmd, move |params| {
+            let sub_cmd = S::try_from(params.sub_cmd).map_err(|_| {
+                log::debug!("Unknown sub-command {} of command {}", params.sub_cmd, cmd);
+                ErrorKind::BadParameters
+            })?;
+            let request = serde_json::Deserializer::from_slice(params.get_buffer())
+                .into_iter::<Req>()
+                .next()
+                .ok_or(ErrorKind::BadFormat)?
+                .map_err(|e| {
+                    log::debug!("Failed to decode the request of command {}: {}", cmd, e);
+                    ErrorKind::BadFormat
+                })?;
+            let response = handler(sub_cmd, request)?;
+            let response = serde_json::to_vec(&response).map_err(|e| {
+                log::debug!("Failed to encode the response of command {}: {}", cmd, e);
+                ErrorKind::BadFormat
+            })?;
+            params.set_buf_from_slice(&response)
+        })
+    }
+
+    /// Runs the handler of the command and sub-command of `params`, or of
+    /// the command if none is routed for the sub-command.
+    ///
+    /// Returns `NotSupported` if no handler is routed for the command.
+    pub fn dispatch(&self, params: &mut PluginParameters) -> Result<()> {
+        let handler = self
+            .routes
+            .get(&(params.cmd, Some(params.sub_cmd)))
+            .or_else(|| self.routes.get(&(params.cmd, None)));
+        match handler {
+            Some(handler) => handler(params),
+            None => {
+                log::debug!(
+                    "No route for plugin command {} sub-command {}",
+                    params.cmd,
+                    params.sub_cmd
+                );
+                Err(ErrorKind::NotSupported.into())
+            }
+        }
+    }
+}
+
+/// State of a supplicant plugin kept across its invocations, in a `static`
+/// instead of a `static mut`.
+///
+/// The state is set by `#[plugin_init]`, used by the invocations, which
+/// tee-supplicant may run concurrently on several threads, and dropped by
+/// `#[plugin_shutdown]`:
+///
+/// ```no_run
+/// # use optee_teec::{ErrorKind, PluginParameters, PluginState, Result};
+/// use std::fs::File;
+/// use std::io::Write;
+///
+/// static LOG: PluginState<File> = PluginState::new();
+///
+/// // #[plugin_init]
+/// fn init() -> Result<()> {
+///     let file = File::create("/var/log/ta.log").map_err(|_| ErrorKind::Generic)?;
+///     LOG.init(file)
+/// }
+///
+/// // #[plugin_invoke]
+/// fn invoke(params: &mut PluginParameters) -> Result<()> {
+///     let line = params.get_buffer().to_vec();
+///     LOG.with(|file| file.write_all(&line))?
+///         .map_err(|_| ErrorKind::Generic.into())
+/// }
+///
+/// // #[plugin_shutdown]
+/// fn shutdown() {
+///     if let Some(mut file) = LOG.shutdown() {
+///         let _ = file.flush();
+///     }
+/// }
+/// ```
+pub struct PluginState<T> {
+    state: Mutex<Option<T>>,
+}
+
+impl<T> PluginState<T> {
+    /// Creates an uninitialized state.
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(None),
+        }
+    }
+
+    /// Sets the state, usually in `#[plugin_init]`.
+    ///
+    /// Fails with `BadState` if the state is already set.
+    pub fn init(&self, state: T) -> Result<()> {
+        let mut guard = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if guard.is_some() {
+            return Err(ErrorKind::BadState.into());
+        }
+        *guard = Some(state);
+        Ok(())
+    }
+
+    /// Runs `f` with the state, which is locked until `f` returns.
+    ///
+    /// Fails with `BadState` if the state is not set, i.e. before
+    /// [`init`](Self::init) or after [`shutdown`](Self::shutdown).
+    pub fn with<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut guard = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match guard.as_mut() {
+            Some(state) => Ok(f(state)),
+            None => {
+                log::debug!("Plugin state used before init or after shutdown");
+                Err(ErrorKind::BadState.into())
+            }
+        }
+    }
+
+    /// Takes the state out, usually in `#[plugin_shutdown]`. The invocations
+    /// after it fail with `BadState`.
+    pub fn shutdown(&self) -> Option<T> {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    /// Returns whether the state is set.
+    pub fn is_initialized(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+}
+
+impl<T> Default for PluginState<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Name, UUID and version of a plugin, declared with [`plugin_info!`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PluginInfo {
+    /// Name of the plugin.
+    pub name: &'static str,
+    /// UUID the TAs load the plugin with, as a string.
+    pub uuid: &'static str,
+    /// Version of the plugin crate.
+    pub version: &'static str,
+}
+
+impl PluginInfo {
+    /// Parses the UUID of the plugin.
+    pub fn uuid(&self) -> Result<Uuid> {
+        Uuid::parse_str(self.uuid.trim())
+    }
+}
+
+/// Declares the [`PluginInfo`] of the calling crate, with its name, its UUID,
+/// usually shared with the TAs in a proto crate, and the version of the
+/// crate.
+///
+/// ```
+/// use optee_teec::{PluginInfo, plugin_info};
+///
+/// const PLUGIN: PluginInfo = plugin_info!("syslog", "ef620757-fa2b-4f19-a1c4-6e51cfe4c0f9");
+/// assert_eq!(PLUGIN.version, env!("CARGO_PKG_VERSION"));
+/// ```
+#[macro_export]
+macro_rules! plugin_info {
+    ($name:expr, $uuid:expr $(,)?) => {
+        $crate::PluginInfo {
+            name: $name,
+            uuid: $uuid,
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::size_t;
+
+    // Runs `router` for `cmd` and `sub_cmd` on a buffer holding `input`,
+    // returning the output.
+    fn invoke(
+        router: &PluginRouter,
+        cmd: u32,
+        sub_cmd: u32,
+        input: &[u8],
+        capacity: usize,
+    ) -> Result<Vec<u8>> {
+        let mut buf = input.to_vec();
+        buf.resize(capacity, 0);
+        let mut out_len: size_t = 0;
+        let mut params = unsafe {
+            PluginParameters::from_raw(cmd, sub_cmd, buf.as_mut_ptr() as _, buf.len(), &mut out_len)
+        }?;
+        router.dispatch(&mut params)?;
+        Ok(buf[..out_len].to_vec())
+    }
+
+    #[test]
+    fn test_route() {
+        let router = PluginRouter::new()
+            .route(1, |params| params.set_buf_from_slice(b"one"))
+            .route(2, |params| params.set_buf_from_slice(b"two"))
+            .route(2, |params| params.set_buf_from_slice(b"TWO"));
+        assert_eq!(invoke(&router, 1, 0, b"", 8).unwrap(), b"one");
+        assert_eq!(invoke(&router, 2, 7, b"", 8).unwrap(), b"TWO");
+        assert_eq!(
+            invoke(&router, 3, 0, b"", 8).unwrap_err().kind(),
+            ErrorKind::NotSupported
+        );
+    }
+
+    #[test]
+    fn test_route_sub() {
+        let router = PluginRouter::new()
+            .route(1, |params| params.set_buf_from_slice(b"any"))
+            .route_sub(1, 2, |params| params.set_buf_from_slice(b"two"))
+            .route_sub(3, 0, |params| params.set_buf_from_slice(b"zero"));
+        assert_eq!(invoke(&router, 1, 2, b"", 8).unwrap(), b"two");
+        assert_eq!(invoke(&router, 1, 5, b"", 8).unwrap(), b"any");
+        assert_eq!(invoke(&router, 3, 0, b"", 8).unwrap(), b"zero");
+        assert_eq!(
+            invoke(&router, 3, 1, b"", 8).unwrap_err().kind(),
+            ErrorKind::NotSupported
+        );
+    }
+
+    #[test]
+    fn test_plugin_state() {
+        static STATE: PluginState<Vec<u32>> = PluginState::new();
+
+        assert_eq!(STATE.with(|_| ()).unwrap_err().kind(), ErrorKind::BadState);
+        STATE.init(vec![1]).unwrap();
+        assert!(STATE.is_initialized());
+        assert_eq!(STATE.init(vec![]).unwrap_err().kind(), ErrorKind::BadState);
+
+        STATE.with(|state| state.push(2)).unwrap();
+        assert_eq!(STATE.shutdown(), Some(vec![1, 2]));
+        assert_eq!(STATE.shutdown(), None);
+        assert_eq!(STATE.with(|_| ()).unwrap_err().kind(), ErrorKind::BadState);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_route_json() {
+        #[derive(serde::Deserialize)]
+        struct Request {
+            a: u32,
+            b: u32,
+        }
+
+        #[derive(Debug)]
+        enum Op {
+            Add,
+            Mul,
+        }
+
+        impl TryFrom<u32> for Op {
+            type Error = ();
+
+            fn try_from(value: u32) -> std::result::Result<Self, ()> {
+                match value {
+                    0 => Ok(Op::Add),
+                    1 => Ok(Op::Mul),
+                    _ => Err(()),
+                }
+            }
+        }
+
+        let router = PluginRouter::new().route_json(5, |op: Op, req: Request| {
+            Ok(match op {
+                Op::Add => req.a + req.b,
+                Op::Mul => req.a * req.b,
+            })
+        });
+        let request = br#"{"a":6,"b":7}"#;
+        // The buffer is zero padded
+        assert_eq!(invoke(&router, 5, 0, request, 32).unwrap(), b"13");
+        assert_eq!(invoke(&router, 5, 1, request, 32).unwrap(), b"42");
+        assert_eq!(
+            invoke(&router, 5, 2, request, 32).unwrap_err().kind(),
+            ErrorKind::BadParameters
+        );
+        assert_eq!(
+            invoke(&router, 5, 0, b"{\"a\":6}", 32).unwrap_err().kind(),
+            ErrorKind::BadFormat
+        );
+        assert_eq!(
+            invoke(&router, 5, 0, b"", 32).unwrap_err().kind(),
+            ErrorKind::BadFormat
+        );
+    }
+
+    #[test]
+    fn test_plugin_info() {
+        let info = plugin_info!("test", "ef620757-fa2b-4f19-a1c4-6e51cfe4c0f9\n");
+        assert_eq!(info.name, "test");
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(info.uuid().is_ok());
+    }
+}
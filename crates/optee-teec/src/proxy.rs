@@ -0,0 +1,789 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Forwarding of socket connections to a TA, enabled by the `proxy` feature.
+//!
+//! A TA cannot accept connections, so a client application terminating
+//! them inside the TA, e.g. TLS with `optee-tls`, accepts them and relays
+//! their traffic. [`ProxyService`] does so on tokio for TCP and Unix sockets:
+//! it reads the traffic of each connection in frames of at most
+//! [`ProxyConfig::max_frame_size`] bytes, hands them to a [`Forwarder`]
+//! invoking the TA, and writes what the TA returns back to the connection.
+//!
+//! ``` no_run
+//! use optee_teec::asynch::Context;
+//! use optee_teec::proxy::{Listener, ProxyService, TlsCommands, TlsRelay};
+//! use optee_teec::Uuid;
+//!
+//! # async fn example() -> optee_teec::Result<()> {
+//! let ctx = Context::new()?;
+//! let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+//! let session = ctx.open_session(uuid).await?;
+//! let commands = TlsCommands {
+//!     new_session: 0,
+//!     close_session: 1,
+//!     read: 2,
+//!     write: 3,
+//! };
+//! let proxy = ProxyService::new(TlsRelay::new(session, commands));
+//! let listener = Listener::bind_tcp("0.0.0.0:4433").await.expect("bind");
+//! proxy
+//!     .serve(listener, async {
+//!         let _ = tokio::signal::ctrl_c().await;
+//!     })
+//!     .await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Backpressure
+//!
+//! The frames of a connection wait for the TA in a queue of
+//! [`ProxyConfig::queue_depth`] frames. A connection is only read when its
+//! queue has room, and the next frame is only forwarded once the response to
+//! the previous one is written, so a peer sending faster than the TA or a
+//! peer reading slower than it sends is slowed down by TCP flow control
+//! instead of growing buffers in the client application. At most
+//! [`ProxyConfig::max_connections`] connections are served at once, the
+//! others wait in the backlog of the listener.
+//!
+//! # Shutdown
+//!
+//! When the future given to [`ProxyService::serve`] completes, the service
+//! stops accepting connections and reading from them. The frames already
+//! queued are forwarded and their responses written, then the TA-side state
+//! of every connection is closed. Connections still busy after
+//! [`ProxyConfig::shutdown_timeout`] are dropped.
+
+use crate::asynch::{Invoker, Session};
+use crate::{Operation, ParamNone, ParamTmpRef, ParamType, ParamValue, Result};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::{Semaphore, mpsc, watch};
+use tokio::task::JoinSet;
+
+/// Forwards the traffic of the connections of a [`ProxyService`] to a TA.
+///
+/// Connections are identified by IDs starting at 1, unique among the open
+/// connections of a service.
+pub trait Forwarder: Send + Sync + 'static {
+    /// Sets up the TA-side state of the connection `id`.
+    fn open(&self, id: u32) -> impl Future<Output = Result<()>> + Send;
+
+    /// Forwards a `frame` received from the connection `id`, returning the
+    /// bytes to send back, if any.
+    fn forward(&self, id: u32, frame: Vec<u8>) -> impl Future<Output = Result<Vec<u8>>> + Send;
+
+    /// Frees the TA-side state of the connection `id`, once it is closed.
+    fn close(&self, id: u32) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Settings of a [`ProxyService`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProxyConfig {
+    /// Largest frame read from a connection and forwarded at once, in bytes.
+    pub max_frame_size: usize,
+    /// Frames of a connection waiting for the TA, see
+    /// [Backpressure](self#backpressure).
+    pub queue_depth: usize,
+    /// Connections served at once.
+    pub max_connections: usize,
+    /// How long [`ProxyService::serve`] waits for the connections to finish
+    /// once shutting down.
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for ProxyConfig {
+    /// Frames of the size of the largest TLS record, 4 frames per connection,
+    /// 64 connections and 5 seconds to shut down.
+    fn default() -> Self {
+        Self {
+            max_frame_size: TLS_MAX_RECORD_SIZE,
+            queue_depth: 4,
+            max_connections: 64,
+            shutdown_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Size of the largest TLS record: a 5 bytes header and a 16 KiB payload with
+/// up to 2 KiB of expansion.
+const TLS_MAX_RECORD_SIZE: usize = 5 + 16 * 1024 + 2048;
+
+/// Counters of a [`ProxyService`] since it was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProxyStats {
+    /// Connections accepted.
+    pub accepted: u64,
+    /// Connections being served.
+    pub active: u64,
+    /// Connections closed after the forwarder failed.
+    pub failed: u64,
+    /// Bytes received from the connections.
+    pub bytes_in: u64,
+    /// Bytes sent back to the connections.
+    pub bytes_out: u64,
+}
+
+/// Counters of a connection being served by a [`ProxyService`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// ID of the connection given to the [`Forwarder`].
+    pub id: u32,
+    /// Address of the peer, empty for Unix sockets whose peer is unnamed.
+    pub peer: String,
+    /// Bytes received from the peer.
+    pub bytes_in: u64,
+    /// Bytes sent to the peer.
+    pub bytes_out: u64,
+    /// Frames forwarded to the TA.
+    pub frames: u64,
+    /// Frames waiting for the TA.
+    pub queued: usize,
+    /// Times reading from the peer waited for the queue to have room.
+    pub stalls: u64,
+    /// Time since the connection was accepted.
+    pub age: Duration,
+}
+
+/// A listening socket of a [`ProxyService`].
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+impl Listener {
+    /// Listens for TCP connections on `addr`.
+    pub async fn bind_tcp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        TcpListener::bind(addr).await.map(Self::Tcp)
+    }
+
+    /// Listens for connections on the Unix socket `path`.
+    #[cfg(unix)]
+    pub fn bind_unix(path: impl AsRef<Path>) -> io::Result<Self> {
+        tokio::net::UnixListener::bind(path).map(Self::Unix)
+    }
+
+    /// The address of a TCP listener, e.g. to find the port picked by the
+    /// system when bound to port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Self::Unix(_) => None,
+        }
+    }
+
+    async fn accept(&self) -> io::Result<(Box<dyn Stream>, String)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                Ok((Box::new(stream), peer.to_string()))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                let peer = peer
+                    .as_pathname()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default();
+                Ok((Box::new(stream), peer))
+            }
+        }
+    }
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Self::Tcp(listener)
+    }
+}
+
+#[cfg(unix)]
+impl From<tokio::net::UnixListener> for Listener {
+    fn from(listener: tokio::net::UnixListener) -> Self {
+        Self::Unix(listener)
+    }
+}
+
+/// A connected socket.
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+/// Accepts connections and forwards their traffic to a TA with a
+/// [`Forwarder`], see the [module documentation](self).
+///
+/// Clones refer to the same service, e.g. to read its counters while it
+/// serves.
+pub struct ProxyService<F> {
+    inner: Arc<Inner<F>>,
+}
+
+struct Inner<F> {
+    forwarder: F,
+    config: ProxyConfig,
+    accepted: AtomicU64,
+    failed: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    connections: Mutex<BTreeMap<u32, Arc<Metrics>>>,
+    next_id: Mutex<u32>,
+}
+
+/// The counters of a connection.
+struct Metrics {
+    peer: String,
+    started: Instant,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    frames: AtomicU64,
+    queued: AtomicUsize,
+    stalls: AtomicU64,
+}
+
+impl<F> Clone for ProxyService<F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<F: Forwarder> ProxyService<F> {
+    /// Creates a service forwarding with `forwarder`, with the default
+    /// [`ProxyConfig`].
+    pub fn new(forwarder: F) -> Self {
+        Self::with_config(forwarder, ProxyConfig::default())
+    }
+
+    /// Creates a service forwarding with `forwarder` and the settings
+    /// `config`.
+    ///
+    /// # Panics
+    ///
+    /// If the frame size, the queue depth or the connection limit is 0.
+    pub fn with_config(forwarder: F, config: ProxyConfig) -> Self {
+        assert!(config.max_frame_size > 0, "max_frame_size must not be 0");
+        assert!(config.queue_depth > 0, "queue_depth must not be 0");
+        assert!(config.max_connections > 0, "max_connections must not be 0");
+        Self {
+            inner: Arc::new(Inner {
+                forwarder,
+                config,
+                accepted: AtomicU64::new(0),
+                failed: AtomicU64::new(0),
+                bytes_in: AtomicU64::new(0),
+                bytes_out: AtomicU64::new(0),
+                connections: Mutex::new(BTreeMap::new()),
+                next_id: Mutex::new(0),
+            }),
+        }
+    }
+
+    /// The forwarder of the service.
+    pub fn forwarder(&self) -> &F {
+        &self.inner.forwarder
+    }
+
+    /// The counters of the service.
+    pub fn stats(&self) -> ProxyStats {
+        let inner = &self.inner;
+        ProxyStats {
+            accepted: inner.accepted.load(Ordering::Relaxed),
+            active: lock(&inner.connections).len() as u64,
+            failed: inner.failed.load(Ordering::Relaxed),
+            bytes_in: inner.bytes_in.load(Ordering::Relaxed),
+            bytes_out: inner.bytes_out.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The counters of the connections being served, by ID.
+    pub fn connections(&self) -> Vec<ConnectionStats> {
+        lock(&self.inner.connections)
+            .iter()
+            .map(|(id, metrics)| ConnectionStats {
+                id: *id,
+                peer: metrics.peer.clone(),
+                bytes_in: metrics.bytes_in.load(Ordering::Relaxed),
+                bytes_out: metrics.bytes_out.load(Ordering::Relaxed),
+                frames: metrics.frames.load(Ordering::Relaxed),
+                queued: metrics.queued.load(Ordering::Relaxed),
+                stalls: metrics.stalls.load(Ordering::Relaxed),
+                age: metrics.started.elapsed(),
+            })
+            .collect()
+    }
+
+    /// Serves the connections of `listener` until `shutdown` completes, then
+    /// shuts down, see [Shutdown](self#shutdown).
+    ///
+    /// Failing to accept a connection, e.g. when the process is out of file
+    /// descriptors, is logged and retried after a short delay.
+    pub async fn serve(&self, listener: Listener, shutdown: impl Future<Output = ()>) {
+        let config = self.inner.config;
+        let permits = Arc::new(Semaphore::new(config.max_connections));
+        let (stop, stopped) = watch::channel(false);
+        let mut tasks = JoinSet::new();
+        let mut shutdown = std::pin::pin!(shutdown);
+        loop {
+            let accepted = tokio::select! {
+                _ = &mut shutdown => break,
+                accepted = async {
+                    let permit = permits.clone().acquire_owned().await;
+                    (permit, listener.accept().await)
+                } => accepted,
+            };
+            // Reap the finished connections
+            while tasks.try_join_next().is_some() {}
+            let (permit, stream, peer) = match accepted {
+                (Ok(permit), Ok((stream, peer))) => (permit, stream, peer),
+                (_, Err(e)) => {
+                    log::warn!("proxy: failed to accept a connection: {}", e);
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+                // The semaphore is never closed
+                (Err(_), _) => break,
+            };
+            let (id, metrics) = self.register(peer);
+            let service = self.clone();
+            let stopped = stopped.clone();
+            tasks.spawn(async move {
+                service.connection(id, &metrics, stream, stopped).await;
+                lock(&service.inner.connections).remove(&id);
+                drop(permit);
+            });
+        }
+
+        let _ = stop.send(true);
+        let drained = tokio::time::timeout(config.shutdown_timeout, async {
+            while tasks.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            log::warn!(
+                "proxy: dropping {} connections still busy after the shutdown timeout",
+                tasks.len()
+            );
+            tasks.shutdown().await;
+            lock(&self.inner.connections).clear();
+        }
+    }
+
+    /// Assigns an ID to a new connection from `peer`.
+    fn register(&self, peer: String) -> (u32, Arc<Metrics>) {
+        let inner = &self.inner;
+        inner.accepted.fetch_add(1, Ordering::Relaxed);
+        let metrics = Arc::new(Metrics {
+            peer,
+            started: Instant::now(),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            frames: AtomicU64::new(0),
+            queued: AtomicUsize::new(0),
+            stalls: AtomicU64::new(0),
+        });
+        let mut connections = lock(&inner.connections);
+        let mut next_id = lock(&inner.next_id);
+        let id = loop {
+            *next_id = next_id.checked_add(1).unwrap_or(1);
+            if !connections.contains_key(&next_id) {
+                break *next_id;
+            }
+        };
+        connections.insert(id, metrics.clone());
+        (id, metrics)
+    }
+
+    /// Serves the connection `id` until it is closed, the forwarder fails or
+    /// the service stops.
+    async fn connection(
+        &self,
+        id: u32,
+        metrics: &Metrics,
+        stream: Box<dyn Stream>,
+        mut stopped: watch::Receiver<bool>,
+    ) {
+        let inner = &self.inner;
+        if let Err(e) = inner.forwarder.open(id).await {
+            log::warn!("proxy: failed to open connection {}: {}", id, e);
+            inner.failed.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let (frames, mut queue) = mpsc::channel::<Vec<u8>>(inner.config.queue_depth);
+
+        let read = async {
+            let mut buffer = vec![0u8; inner.config.max_frame_size];
+            while !*stopped.borrow() {
+                if frames.capacity() == 0 {
+                    metrics.stalls.fetch_add(1, Ordering::Relaxed);
+                }
+                // Only read when the frame can be queued
+                let permit = tokio::select! {
+                    permit = frames.reserve() => match permit {
+                        Ok(permit) => permit,
+                        Err(_) => break,
+                    },
+                    _ = stopped.changed() => break,
+                };
+                let len = tokio::select! {
+                    read = reader.read(&mut buffer) => match read {
+                        Ok(0) | Err(_) => break,
+                        Ok(len) => len,
+                    },
+                    _ = stopped.changed() => break,
+                    // The forwarding failed
+                    _ = frames.closed() => break,
+                };
+                metrics.bytes_in.fetch_add(len as u64, Ordering::Relaxed);
+                inner.bytes_in.fetch_add(len as u64, Ordering::Relaxed);
+                metrics.queued.fetch_add(1, Ordering::Relaxed);
+                permit.send(buffer[..len].to_vec());
+            }
+            // Lets the forwarding drain the queue and finish
+            drop(frames);
+        };
+
+        let forward = async {
+            while let Some(frame) = queue.recv().await {
+                metrics.queued.fetch_sub(1, Ordering::Relaxed);
+                metrics.frames.fetch_add(1, Ordering::Relaxed);
+                let response = match inner.forwarder.forward(id, frame).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        log::warn!("proxy: failed to forward on connection {}: {}", id, e);
+                        inner.failed.fetch_add(1, Ordering::Relaxed);
+                        break;
+                    }
+                };
+                if response.is_empty() {
+                    continue;
+                }
+                if writer.write_all(&response).await.is_err() {
+                    break;
+                }
+                metrics
+                    .bytes_out
+                    .fetch_add(response.len() as u64, Ordering::Relaxed);
+                inner
+                    .bytes_out
+                    .fetch_add(response.len() as u64, Ordering::Relaxed);
+            }
+            // Stops the reading if the forwarding failed
+            drop(queue);
+            let _ = writer.shutdown().await;
+        };
+
+        tokio::join!(read, forward);
+        if let Err(e) = inner.forwarder.close(id).await {
+            log::debug!("proxy: failed to close connection {}: {}", id, e);
+        }
+    }
+}
+
+/// Delay before accepting again after failing to.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The command IDs of a TA terminating TLS with `optee_tls::TlsSessionManager`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TlsCommands {
+    /// `NewTlsSession`.
+    pub new_session: u32,
+    /// `CloseTlsSession`.
+    pub close_session: u32,
+    /// `DoTlsRead`.
+    pub read: u32,
+    /// `DoTlsWrite`.
+    pub write: u32,
+}
+
+/// A [`Forwarder`] relaying TLS records to a TA terminating TLS with
+/// `optee_tls::TlsSessionManager`, the connection ID being the TLS session
+/// ID.
+///
+/// Each frame is passed with `DoTlsRead`, then `DoTlsWrite` is invoked until
+/// the TA has no more records to send.
+pub struct TlsRelay {
+    session: Session,
+    commands: TlsCommands,
+}
+
+impl TlsRelay {
+    /// Creates a relay invoking `commands` on `session`.
+    pub fn new(session: Session, commands: TlsCommands) -> Self {
+        Self { session, commands }
+    }
+
+    fn session_command(&self, command_id: u32, id: u32) -> impl Future<Output = Result<()>> {
+        self.session.invoke_command(command_id, move |invoker| {
+            invoke_with(invoker, id, ParamNone)
+        })
+    }
+}
+
+impl Forwarder for TlsRelay {
+    fn open(&self, id: u32) -> impl Future<Output = Result<()>> + Send {
+        self.session_command(self.commands.new_session, id)
+    }
+
+    fn forward(&self, id: u32, frame: Vec<u8>) -> impl Future<Output = Result<Vec<u8>>> + Send {
+        let session = self.session.clone();
+        let commands = self.commands;
+        async move {
+            session
+                .invoke_command(commands.read, move |invoker| {
+                    invoke_with(invoker, id, ParamTmpRef::new_input(&frame))
+                })
+                .await?;
+            session
+                .invoke_command(commands.write, move |invoker| {
+                    let mut records = Vec::new();
+                    let mut buffer = vec![0u8; TLS_MAX_RECORD_SIZE];
+                    loop {
+                        let p0 = ParamValue::new(id, 0, ParamType::ValueInput);
+                        let p1 = ParamTmpRef::new_output(&mut buffer);
+                        let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+                        invoker.invoke(&mut operation)?;
+                        let len = operation.parameters().1.updated_size();
+                        if len == 0 {
+                            return Ok(records);
+                        }
+                        records.extend_from_slice(&buffer[..len]);
+                    }
+                })
+                .await
+        }
+    }
+
+    fn close(&self, id: u32) -> impl Future<Output = Result<()>> + Send {
+        self.session_command(self.commands.close_session, id)
+    }
+}
+
+/// Invokes a command of a TLS session, whose ID is in parameter 0.
+fn invoke_with<P: crate::Param>(invoker: &mut Invoker<'_>, id: u32, p1: P) -> Result<()> {
+    let p0 = ParamValue::new(id, 0, ParamType::ValueInput);
+    let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+    invoker.invoke(&mut operation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, ErrorKind};
+    use tokio::net::TcpStream;
+
+    /// Answers every frame with it in upper case, or fails on "fail".
+    #[derive(Default)]
+    struct Upper {
+        opened: Mutex<Vec<u32>>,
+        closed: Mutex<Vec<u32>>,
+        // Holds each frame until a permit is added, when set
+        gate: Option<Arc<Semaphore>>,
+    }
+
+    impl Forwarder for Upper {
+        async fn open(&self, id: u32) -> Result<()> {
+            lock(&self.opened).push(id);
+            Ok(())
+        }
+
+        async fn forward(&self, _id: u32, frame: Vec<u8>) -> Result<Vec<u8>> {
+            if let Some(gate) = &self.gate {
+                gate.acquire().await.unwrap().forget();
+            }
+            if frame == b"fail" {
+                return Err(Error::new(ErrorKind::Security));
+            }
+            Ok(frame.to_ascii_uppercase())
+        }
+
+        async fn close(&self, id: u32) -> Result<()> {
+            lock(&self.closed).push(id);
+            Ok(())
+        }
+    }
+
+    async fn start(
+        forwarder: Upper,
+        config: ProxyConfig,
+    ) -> (
+        ProxyService<Upper>,
+        SocketAddr,
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<()>,
+    ) {
+        let proxy = ProxyService::with_config(forwarder, config);
+        let listener = Listener::bind_tcp("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let service = proxy.clone();
+        let serving = tokio::spawn(async move {
+            service
+                .serve(listener, async {
+                    let _ = stopped.await;
+                })
+                .await
+        });
+        (proxy, addr, stop, serving)
+    }
+
+    async fn exchange(stream: &mut TcpStream, request: &[u8]) -> Vec<u8> {
+        stream.write_all(request).await.unwrap();
+        let mut response = vec![0u8; request.len()];
+        stream.read_exact(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_forward_and_shutdown() {
+        let (proxy, addr, stop, serving) = start(Upper::default(), ProxyConfig::default()).await;
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(exchange(&mut first, b"hello").await, b"HELLO");
+        assert_eq!(exchange(&mut second, b"world").await, b"WORLD");
+
+        let connections = proxy.connections();
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections[0].id, 1);
+        assert_eq!(connections[0].bytes_in, 5);
+        assert_eq!(connections[0].bytes_out, 5);
+        assert_eq!(connections[0].frames, 1);
+
+        // Closed by the peer
+        drop(first);
+        while proxy.stats().active > 1 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(*lock(&proxy.forwarder().closed), [1]);
+
+        // The open connection is closed by the shutdown
+        stop.send(()).unwrap();
+        serving.await.unwrap();
+        let mut rest = Vec::new();
+        assert_eq!(second.read_to_end(&mut rest).await.unwrap(), 0);
+        assert_eq!(*lock(&proxy.forwarder().opened), [1, 2]);
+        assert_eq!(*lock(&proxy.forwarder().closed), [1, 2]);
+        assert_eq!(
+            proxy.stats(),
+            ProxyStats {
+                accepted: 2,
+                active: 0,
+                failed: 0,
+                bytes_in: 10,
+                bytes_out: 10,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forward_failure() {
+        let (proxy, addr, stop, serving) = start(Upper::default(), ProxyConfig::default()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"fail").await.unwrap();
+        let mut rest = Vec::new();
+        assert_eq!(stream.read_to_end(&mut rest).await.unwrap(), 0);
+        while proxy.stats().active > 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(proxy.stats().failed, 1);
+        assert_eq!(*lock(&proxy.forwarder().closed), [1]);
+        stop.send(()).unwrap();
+        serving.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let gate = Arc::new(Semaphore::new(0));
+        let forwarder = Upper {
+            gate: Some(gate.clone()),
+            ..Upper::default()
+        };
+        let config = ProxyConfig {
+            max_frame_size: 4,
+            queue_depth: 2,
+            ..ProxyConfig::default()
+        };
+        let (proxy, addr, stop, serving) = start(forwarder, config).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"aaaabbbbccccdddd").await.unwrap();
+
+        // One frame held by the forwarder, two queued, the rest not read
+        let queued = |proxy: &ProxyService<Upper>| {
+            let connections = proxy.connections();
+            connections
+                .first()
+                .is_some_and(|c| c.queued == 2 && c.bytes_in == 12)
+        };
+        while !queued(&proxy) {
+            tokio::task::yield_now().await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(queued(&proxy));
+        assert!(proxy.connections()[0].stalls > 0);
+
+        gate.add_permits(4);
+        let mut response = [0u8; 16];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response, b"AAAABBBBCCCCDDDD");
+        stop.send(()).unwrap();
+        serving.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket() {
+        let path = std::env::temp_dir().join(format!("optee-teec-proxy-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let proxy = ProxyService::new(Upper::default());
+        let listener = Listener::bind_unix(&path).unwrap();
+        assert_eq!(listener.local_addr(), None);
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let service = proxy.clone();
+        let serving = tokio::spawn(async move {
+            service
+                .serve(listener, async {
+                    let _ = stopped.await;
+                })
+                .await
+        });
+        let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        stream.write_all(b"unix").await.unwrap();
+        let mut response = [0u8; 4];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response, b"UNIX");
+        stop.send(()).unwrap();
+        serving.await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
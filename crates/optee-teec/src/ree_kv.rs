@@ -0,0 +1,399 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Key-value store in the file system of the REE for the TAs, served by a
+//! supplicant plugin and called with `optee_utee::storage::ReeKvStore`.
+//!
+//! The values are plain files the REE can read, replace and delete, so the
+//! store is meant for low-sensitivity bulk data which does not fit the budget
+//! of the Trusted Storage; a TA encrypts and authenticates what it keeps
+//! there, e.g. with `optee_utee::crypto::Sealed`.
+//!
+//! A plugin installed as `/usr/lib/tee-supplicant/plugins/<PLUGIN_UUID>.plugin`
+//! serves the TAs with the router of a [`ReeKv`]:
+//!
+//! ```no_run
+//! # use optee_teec::{PluginParameters, PluginRouter, Result};
+//! use optee_teec::ree_kv::{DEFAULT_ROOT, ReeKv};
+//! use std::sync::LazyLock;
+//!
+//! static ROUTER: LazyLock<PluginRouter> = LazyLock::new(|| ReeKv::new(DEFAULT_ROOT).router());
+//!
+//! // Annotated with `#[plugin_invoke]` in the plugin
+//! fn invoke(params: &mut PluginParameters) -> Result<()> {
+//!     ROUTER.dispatch(params)
+//! }
+//! ```
+//!
+//! Every request starts with the length of the key as 2 bytes little-endian
+//! and the key, followed by:
+//!
+//! - for [`CMD_GET`], the offset to read the value from, 8 bytes
+//!   little-endian. The plugin answers with the bytes of the value from the
+//!   offset which fit into the buffer, fewer than the buffer holds at the end
+//!   of the value.
+//! - for [`CMD_PUT`], a chunk of the value. The chunks are written to a
+//!   temporary file, truncated by the chunk flagged [`PUT_FIRST`] and renamed
+//!   over the value by the chunk flagged [`PUT_LAST`] in the sub-command, so
+//!   a value is replaced atomically.
+//! - for [`CMD_DELETE`], nothing.
+//! - for [`CMD_LIST`], nothing, the key being a prefix. The plugin answers
+//!   with the keys starting with it in ascending order, from the index in the
+//!   sub-command, each prefixed with its length as 2 bytes little-endian, as
+//!   many as fit into the buffer.
+//!
+//! Each value is a file in the root directory named after the hexadecimal
+//! encoding of its key, so no key escapes the root directory.
+
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{ErrorKind, PluginParameters, PluginRouter, Result};
+
+/// UUID of the plugin `optee_utee::storage::ReeKvStore` calls.
+pub const PLUGIN_UUID: &str = "69614b85-e591-4db1-ae0c-6c697a39f4c5";
+/// Command reading a value from an offset.
+pub const CMD_GET: u32 = 0;
+/// Command writing a chunk of a value.
+pub const CMD_PUT: u32 = 1;
+/// Command deleting a value.
+pub const CMD_DELETE: u32 = 2;
+/// Command listing the keys starting with a prefix.
+pub const CMD_LIST: u32 = 3;
+
+/// Flag of the sub-command of [`CMD_PUT`] for the first chunk of a value.
+pub const PUT_FIRST: u32 = 1;
+/// Flag of the sub-command of [`CMD_PUT`] for the last chunk of a value.
+pub const PUT_LAST: u32 = 2;
+
+/// Maximum length of a key in bytes, whose file name is twice as long.
+pub const MAX_KEY_LEN: usize = 127;
+
+/// Root directory of the store of the plugin shipped with the SDK, next to
+/// the files of the REE FS Trusted Storage.
+pub const DEFAULT_ROOT: &str = "/data/tee/ree_kv";
+
+/// Extension of the temporary file a value is written to.
+const TMP_EXTENSION: &str = "tmp";
+
+/// Key-value store of the TAs in the directory `root`.
+///
+/// The store does not lock the values: two TAs writing the same key at once
+/// interleave their chunks, and a reader may see a value replaced between
+/// two chunks it reads.
+#[derive(Clone, Debug)]
+pub struct ReeKv {
+    root: PathBuf,
+}
+
+impl ReeKv {
+    /// A store in `root`, created with mode `0700` at the first write.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The root directory of the store.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// A router serving the commands of the store.
+    pub fn router(self) -> PluginRouter {
+        let store = Arc::new(self);
+        let (get, put, delete, list) = (store.clone(), store.clone(), store.clone(), store);
+        PluginRouter::new()
+            .route(CMD_GET, move |params| get.get(params))
+            .route(CMD_PUT, move |params| put.put(params))
+            .route(CMD_DELETE, move |params| delete.delete(params))
+            .route(CMD_LIST, move |params| list.list(params))
+    }
+
+    /// Answers [`CMD_GET`] with the bytes of the value from the offset which
+    /// fit into the buffer.
+    ///
+    /// Fails with `ItemNotFound` if the key has no value.
+    pub fn get(&self, params: &mut PluginParameters) -> Result<()> {
+        let (key, rest) = split_key(params.get_buffer())?;
+        let offset = rest
+            .get(..8)
+            .and_then(|offset| offset.try_into().ok())
+            .map(u64::from_le_bytes)
+            .ok_or(ErrorKind::BadParameters)?;
+        let path = self.path(key);
+        let mut value = Vec::with_capacity(params.get_buffer().len());
+        File::open(&path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(offset))?;
+                file.take(params.get_buffer().len() as u64)
+                    .read_to_end(&mut value)
+            })
+            .map_err(|e| io_error(&path, e))?;
+        params.set_buf_from_slice(&value)
+    }
+
+    /// Handles [`CMD_PUT`], writing the chunk of the value in the buffer.
+    ///
+    /// Fails with `BadState` if no chunk flagged [`PUT_FIRST`] came before.
+    pub fn put(&self, params: &mut PluginParameters) -> Result<()> {
+        let (key, chunk) = split_key(params.get_buffer())?;
+        let path = self.path(key);
+        let tmp = path.with_extension(TMP_EXTENSION);
+        let mut file = if params.sub_cmd & PUT_FIRST != 0 {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(&self.root)
+                .map_err(|e| io_error(&self.root, e))?;
+            File::create(&tmp)
+        } else {
+            OpenOptions::new().append(true).open(&tmp)
+        }
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ErrorKind::BadState.into(),
+            _ => io_error(&tmp, e),
+        })?;
+        file.write_all(chunk).map_err(|e| io_error(&tmp, e))?;
+        if params.sub_cmd & PUT_LAST != 0 {
+            file.sync_all().map_err(|e| io_error(&tmp, e))?;
+            fs::rename(&tmp, &path).map_err(|e| io_error(&path, e))?;
+        }
+        params.set_out_len(0)
+    }
+
+    /// Handles [`CMD_DELETE`].
+    ///
+    /// Fails with `ItemNotFound` if the key has no value.
+    pub fn delete(&self, params: &mut PluginParameters) -> Result<()> {
+        let (key, _) = split_key(params.get_buffer())?;
+        let path = self.path(key);
+        fs::remove_file(&path).map_err(|e| io_error(&path, e))?;
+        params.set_out_len(0)
+    }
+
+    /// Answers [`CMD_LIST`] with the keys starting with the prefix which fit
+    /// into the buffer, from the index in the sub-command.
+    pub fn list(&self, params: &mut PluginParameters) -> Result<()> {
+        let (prefix, _) = split_key(params.get_buffer())?;
+        let mut keys = match fs::read_dir(&self.root) {
+            Ok(entries) => entries
+                .filter_map(|entry| {
+                    let name = entry.ok()?.file_name();
+                    hex::decode(name.to_str()?).ok()
+                })
+                .filter(|key| key.starts_with(prefix))
+                .collect(),
+            // Nothing was written yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(io_error(&self.root, e)),
+        };
+        keys.sort();
+        let capacity = params.get_buffer().len();
+        let mut response = Vec::new();
+        for key in keys.iter().skip(params.sub_cmd as usize) {
+            if response.len() + 2 + key.len() > capacity {
+                break;
+            }
+            response.extend_from_slice(&(key.len() as u16).to_le_bytes());
+            response.extend_from_slice(key);
+        }
+        params.set_buf_from_slice(&response)
+    }
+
+    fn path(&self, key: &[u8]) -> PathBuf {
+        self.root.join(hex::encode(key))
+    }
+}
+
+/// Splits the buffer of a request into the key and the rest.
+fn split_key(buffer: &[u8]) -> Result<(&[u8], &[u8])> {
+    let (len, rest) = buffer.split_at_checked(2).ok_or(ErrorKind::BadParameters)?;
+    let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+    if len > MAX_KEY_LEN {
+        return Err(ErrorKind::BadParameters.into());
+    }
+    rest.split_at_checked(len)
+        .ok_or(ErrorKind::BadParameters.into())
+}
+
+fn io_error(path: &Path, e: io::Error) -> crate::Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        return ErrorKind::ItemNotFound.into();
+    }
+    log::debug!("Failed to access {}: {}", path.display(), e);
+    ErrorKind::Generic.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::size_t;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn store() -> ReeKv {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "optee-teec-ree-kv-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        ReeKv::new(root)
+    }
+
+    fn request(key: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut request = (key.len() as u16).to_le_bytes().to_vec();
+        request.extend_from_slice(key);
+        request.extend_from_slice(payload);
+        request
+    }
+
+    fn invoke(
+        router: &PluginRouter,
+        cmd: u32,
+        sub_cmd: u32,
+        input: &[u8],
+        capacity: usize,
+    ) -> Result<Vec<u8>> {
+        let mut buf = input.to_vec();
+        buf.resize(capacity.max(input.len()), 0);
+        let mut out_len: size_t = 0;
+        let mut params = unsafe {
+            PluginParameters::from_raw(cmd, sub_cmd, buf.as_mut_ptr() as _, buf.len(), &mut out_len)
+        }?;
+        router.dispatch(&mut params)?;
+        Ok(buf[..out_len].to_vec())
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let store = store();
+        let root = store.root().to_path_buf();
+        let router = store.router();
+        let get = |offset: u64, capacity| {
+            invoke(
+                &router,
+                CMD_GET,
+                0,
+                &request(b"a/b", &offset.to_le_bytes()),
+                capacity,
+            )
+        };
+        assert_eq!(get(0, 16).unwrap_err().kind(), ErrorKind::ItemNotFound);
+
+        invoke(&router, CMD_PUT, PUT_FIRST, &request(b"a/b", b"hello "), 0).unwrap();
+        // Not visible before the last chunk
+        assert_eq!(get(0, 16).unwrap_err().kind(), ErrorKind::ItemNotFound);
+        invoke(
+            &router,
+            CMD_PUT,
+            PUT_LAST,
+            &request(b"a/b", b"world, again"),
+            0,
+        )
+        .unwrap();
+        // As much as fits into the buffer
+        assert_eq!(get(0, 16).unwrap(), b"hello world, aga");
+        assert_eq!(get(16, 16).unwrap(), b"in");
+        assert_eq!(get(6, 32).unwrap(), b"world, again");
+        assert!(get(18, 16).unwrap().is_empty());
+        assert!(root.join(hex::encode("a/b")).is_file());
+
+        // Replaced in a single chunk
+        invoke(
+            &router,
+            CMD_PUT,
+            PUT_FIRST | PUT_LAST,
+            &request(b"a/b", b"x"),
+            0,
+        )
+        .unwrap();
+        assert_eq!(get(0, 16).unwrap(), b"x");
+
+        invoke(&router, CMD_DELETE, 0, &request(b"a/b", b""), 0).unwrap();
+        assert_eq!(get(0, 16).unwrap_err().kind(), ErrorKind::ItemNotFound);
+        assert_eq!(
+            invoke(&router, CMD_DELETE, 0, &request(b"a/b", b""), 0)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::ItemNotFound
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_bad_requests() {
+        let router = store().router();
+        // A chunk without the first one
+        assert_eq!(
+            invoke(&router, CMD_PUT, PUT_LAST, &request(b"k", b"v"), 0)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::BadState
+        );
+        for input in [
+            &b"\x01"[..],
+            &b"\x05\x00abc"[..],
+            &request(&[b'k'; MAX_KEY_LEN + 1], b""),
+        ] {
+            assert_eq!(
+                invoke(&router, CMD_DELETE, 0, input, 0).unwrap_err().kind(),
+                ErrorKind::BadParameters
+            );
+        }
+        // No offset
+        assert_eq!(
+            invoke(&router, CMD_GET, 0, &request(b"k", b""), 0)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::BadParameters
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        let store = store();
+        let root = store.root().to_path_buf();
+        let router = store.router();
+        let list = |prefix: &[u8], start, capacity| {
+            invoke(&router, CMD_LIST, start, &request(prefix, b""), capacity).unwrap()
+        };
+        assert!(list(b"", 0, 64).is_empty());
+        for key in [&b"logs/2"[..], b"logs/1", b"cache"] {
+            invoke(
+                &router,
+                CMD_PUT,
+                PUT_FIRST | PUT_LAST,
+                &request(key, b"v"),
+                0,
+            )
+            .unwrap();
+        }
+        // An unfinished value is not listed
+        invoke(&router, CMD_PUT, PUT_FIRST, &request(b"logs/3", b"v"), 0).unwrap();
+
+        assert_eq!(list(b"logs/", 0, 64), b"\x06\x00logs/1\x06\x00logs/2");
+        assert_eq!(list(b"logs/", 1, 64), b"\x06\x00logs/2");
+        assert_eq!(list(b"logs/", 0, 10), b"\x06\x00logs/1");
+        assert_eq!(
+            list(b"", 0, 64),
+            b"\x05\x00cache\x06\x00logs/1\x06\x00logs/2"
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
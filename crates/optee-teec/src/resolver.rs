@@ -0,0 +1,139 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Resolution of host names for the TAs, served by a supplicant plugin and
+//! called with `optee_utee::net::lookup_host`.
+//!
+//! A plugin installed as `/usr/lib/tee-supplicant/plugins/<PLUGIN_UUID>.plugin`
+//! serves the TAs by routing [`CMD_LOOKUP_HOST`] to [`lookup_host`]:
+//!
+//! ```no_run
+//! # use optee_teec::{PluginParameters, PluginRouter, Result};
+//! use optee_teec::resolver;
+//! use std::sync::LazyLock;
+//!
+//! static ROUTER: LazyLock<PluginRouter> = LazyLock::new(|| {
+//!     PluginRouter::new().route(resolver::CMD_LOOKUP_HOST, resolver::lookup_host)
+//! });
+//!
+//! // Annotated with `#[plugin_invoke]` in the plugin
+//! fn invoke(params: &mut PluginParameters) -> Result<()> {
+//!     ROUTER.dispatch(params)
+//! }
+//! ```
+//!
+//! The TA writes the host name into the buffer of the plugin, followed by
+//! zero padding, and the plugin answers with the addresses of the host which
+//! fit into the buffer, each a tag, 4 or 6, followed by the 4 bytes of an
+//! IPv4 address or the 16 bytes of an IPv6 address.
+
+use std::net::{IpAddr, ToSocketAddrs};
+
+use crate::{ErrorKind, PluginParameters, Result};
+
+/// UUID of the plugin `optee_utee::net::lookup_host` calls.
+pub const PLUGIN_UUID: &str = "c4ed089a-0633-41de-bab4-6d4cfa7e8d9f";
+/// Command resolving the host name in the buffer.
+pub const CMD_LOOKUP_HOST: u32 = 0;
+
+/// Tag of an IPv4 address in the response, followed by its 4 bytes.
+const TAG_V4: u8 = 4;
+/// Tag of an IPv6 address in the response, followed by its 16 bytes.
+const TAG_V6: u8 = 6;
+
+/// Resolves the host name in the buffer of `params` with the resolver of the
+/// system, and answers with its addresses, none if it cannot be resolved.
+///
+/// Fails with `BadParameters` if the buffer does not hold a host name.
+pub fn lookup_host(params: &mut PluginParameters) -> Result<()> {
+    let buffer = params.get_buffer();
+    let name = &buffer[..buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len())];
+    let host = std::str::from_utf8(name).map_err(|_| ErrorKind::BadParameters)?;
+    if host.is_empty() {
+        return Err(ErrorKind::BadParameters.into());
+    }
+    let addrs: Vec<IpAddr> = match (host, 0).to_socket_addrs() {
+        Ok(addrs) => addrs.map(|addr| addr.ip()).collect(),
+        Err(e) => {
+            log::debug!("Failed to resolve {}: {}", host, e);
+            Vec::new()
+        }
+    };
+    let response = encode(&addrs, params.get_buffer().len());
+    params.set_buf_from_slice(&response)
+}
+
+/// Encodes the distinct `addrs` which fit into `capacity` bytes.
+fn encode(addrs: &[IpAddr], capacity: usize) -> Vec<u8> {
+    let mut response = Vec::new();
+    let mut encoded = Vec::new();
+    for addr in addrs {
+        if encoded.contains(addr) {
+            continue;
+        }
+        let (tag, octets) = match addr {
+            IpAddr::V4(addr) => (TAG_V4, addr.octets().to_vec()),
+            IpAddr::V6(addr) => (TAG_V6, addr.octets().to_vec()),
+        };
+        if response.len() + 1 + octets.len() > capacity {
+            break;
+        }
+        response.push(tag);
+        response.extend_from_slice(&octets);
+        encoded.push(*addr);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::size_t;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn invoke(input: &[u8], capacity: usize) -> Result<Vec<u8>> {
+        let mut buf = input.to_vec();
+        buf.resize(capacity, 0);
+        let mut out_len: size_t = 0;
+        let mut params = unsafe {
+            PluginParameters::from_raw(0, 0, buf.as_mut_ptr() as _, buf.len(), &mut out_len)
+        }?;
+        lookup_host(&mut params)?;
+        Ok(buf[..out_len].to_vec())
+    }
+
+    #[test]
+    fn test_encode() {
+        let v4 = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let mut expected = vec![TAG_V4, 10, 0, 0, 1, TAG_V6];
+        expected.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        assert_eq!(encode(&[v4, v6, v4], 64), expected);
+        // Only the addresses which fit
+        assert_eq!(encode(&[v4, v6], 16), [TAG_V4, 10, 0, 0, 1]);
+        assert!(encode(&[v6], 16).is_empty());
+    }
+
+    #[test]
+    fn test_lookup_host() {
+        assert_eq!(invoke(b"127.0.0.1", 64).unwrap(), [TAG_V4, 127, 0, 0, 1]);
+        assert_eq!(
+            invoke(b"", 64).unwrap_err().kind(),
+            ErrorKind::BadParameters
+        );
+        assert!(invoke(b"host.invalid", 64).unwrap().is_empty());
+    }
+}
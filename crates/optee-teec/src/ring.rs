@@ -0,0 +1,385 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use crate::{
+    Context, Error, ErrorKind, Operation, ParamNone, ParamSharedMemRef, ParamType, ParamValue,
+    Result, Session, SharedMemory, SharedMemoryFlags,
+};
+
+// Layout of a ring, as defined by `optee_utee::ring`
+const MAGIC: u32 = u32::from_le_bytes(*b"ORNG");
+const VERSION: u32 = 1;
+const CAPACITY_OFFSET: usize = 8;
+const HEAD_OFFSET: usize = 64;
+const PUSHED_OFFSET: usize = 72;
+const TAIL_OFFSET: usize = 128;
+const POPPED_OFFSET: usize = 136;
+const DATA_OFFSET: usize = 192;
+const RECORD_HEADER: usize = 8;
+
+/// The space a message of `len` bytes takes in the ring.
+fn record_size(len: usize) -> usize {
+    RECORD_HEADER + len.next_multiple_of(8)
+}
+
+fn corrupted() -> Error {
+    log::debug!("Corrupted ring buffer");
+    ErrorKind::BadFormat.into()
+}
+
+/// A ring in shared memory, the client side of `optee_utee::ring::Ring`.
+struct Ring<'a> {
+    base: *mut u8,
+    capacity: usize,
+    _marker: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> Ring<'a> {
+    /// Formats `memory` as an empty ring of the largest capacity it fits.
+    fn init(memory: &'a mut [u8]) -> Result<Self> {
+        let capacity = memory.len().saturating_sub(DATA_OFFSET) / 8 * 8;
+        if memory.as_ptr().align_offset(8) != 0 || capacity == 0 || capacity > u32::MAX as usize {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        memory[..DATA_OFFSET].fill(0);
+        memory[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        memory[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        memory[CAPACITY_OFFSET..CAPACITY_OFFSET + 4]
+            .copy_from_slice(&(capacity as u32).to_le_bytes());
+        Ok(Self::attach(memory))
+    }
+
+    /// Uses the ring formatted by [`init`](Self::init) in `memory`, whose
+    /// capacity the TA cannot change as it is only read here.
+    fn attach(memory: &'a mut [u8]) -> Self {
+        let capacity = memory.len().saturating_sub(DATA_OFFSET) / 8 * 8;
+        Self {
+            base: memory.as_mut_ptr(),
+            capacity,
+            _marker: PhantomData,
+        }
+    }
+
+    fn u64_at(&self, offset: usize) -> &AtomicU64 {
+        // SAFETY: the offsets are within the header, aligned to 8 as the
+        // base, and the memory outlives self.
+        unsafe { AtomicU64::from_ptr(self.base.add(offset) as *mut u64) }
+    }
+
+    fn u32_at(&self, offset: usize) -> &AtomicU32 {
+        // SAFETY: see u64_at().
+        unsafe { AtomicU32::from_ptr(self.base.add(offset) as *mut u32) }
+    }
+
+    /// The head and the tail, checked to be consistent.
+    fn positions(&self, head: Ordering, tail: Ordering) -> Result<(u64, u64)> {
+        let head = u64::from_le(self.u64_at(HEAD_OFFSET).load(head));
+        let tail = u64::from_le(self.u64_at(TAIL_OFFSET).load(tail));
+        match head.checked_sub(tail) {
+            Some(used) if used <= self.capacity as u64 && tail.is_multiple_of(8) => {
+                Ok((head, tail))
+            }
+            _ => Err(corrupted()),
+        }
+    }
+
+    /// Copies `data` into the data of the ring at `position`, wrapping
+    /// around the end.
+    fn write_at(&mut self, position: u64, data: &[u8]) {
+        let offset = (position % self.capacity as u64) as usize;
+        let first = data.len().min(self.capacity - offset);
+        // SAFETY: both parts are within the data of the ring.
+        unsafe {
+            let start = self.base.add(DATA_OFFSET);
+            ptr::copy_nonoverlapping(data.as_ptr(), start.add(offset), first);
+            ptr::copy_nonoverlapping(data[first..].as_ptr(), start, data.len() - first);
+        }
+    }
+
+    /// Copies the data of the ring at `position` into `data`, wrapping around
+    /// the end.
+    fn read_at(&self, position: u64, data: &mut [u8]) {
+        let offset = (position % self.capacity as u64) as usize;
+        let first = data.len().min(self.capacity - offset);
+        // SAFETY: both parts are within the data of the ring.
+        unsafe {
+            let start = self.base.add(DATA_OFFSET);
+            ptr::copy_nonoverlapping(start.add(offset), data.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(start, data[first..].as_mut_ptr(), data.len() - first);
+        }
+    }
+
+    /// Pushes `message`, returning false if the ring is too full to hold it.
+    fn push(&mut self, message: &[u8]) -> Result<bool> {
+        let size = record_size(message.len());
+        if size > self.capacity {
+            log::debug!("Message of {} bytes larger than the ring", message.len());
+            return Err(ErrorKind::ExcessData.into());
+        }
+        let (head, tail) = self.positions(Ordering::Relaxed, Ordering::Acquire)?;
+        if size as u64 > self.capacity as u64 - (head - tail) {
+            return Ok(false);
+        }
+        let sequence = u32::from_le(self.u32_at(PUSHED_OFFSET).load(Ordering::Relaxed));
+        let mut header = [0u8; RECORD_HEADER];
+        header[..4].copy_from_slice(&(message.len() as u32).to_le_bytes());
+        header[4..].copy_from_slice(&sequence.to_le_bytes());
+        self.write_at(head, &header);
+        self.write_at(head + RECORD_HEADER as u64, message);
+        self.u32_at(PUSHED_OFFSET)
+            .store(sequence.wrapping_add(1).to_le(), Ordering::Relaxed);
+        self.u64_at(HEAD_OFFSET)
+            .store((head + size as u64).to_le(), Ordering::Release);
+        Ok(true)
+    }
+
+    /// Pops the next message, `None` if the ring is empty.
+    fn pop(&mut self) -> Result<Option<Vec<u8>>> {
+        let (head, tail) = self.positions(Ordering::Acquire, Ordering::Relaxed)?;
+        if head == tail {
+            return Ok(None);
+        }
+        let mut header = [0u8; RECORD_HEADER];
+        self.read_at(tail, &mut header);
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let sequence = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let expected = u32::from_le(self.u32_at(POPPED_OFFSET).load(Ordering::Relaxed));
+        let size = record_size(len);
+        if sequence != expected || size as u64 > head - tail {
+            return Err(corrupted());
+        }
+        let mut message = vec![0u8; len];
+        self.read_at(tail + RECORD_HEADER as u64, &mut message);
+        self.u32_at(POPPED_OFFSET)
+            .store(expected.wrapping_add(1).to_le(), Ordering::Relaxed);
+        self.u64_at(TAIL_OFFSET)
+            .store((tail + size as u64).to_le(), Ordering::Release);
+        Ok(Some(message))
+    }
+}
+
+/// The shared memory of a ring and the doorbell of the TA.
+struct Channel<'s> {
+    session: &'s mut Session,
+    shm: SharedMemory<'static>,
+    command_id: u32,
+}
+
+impl<'s> Channel<'s> {
+    fn new(
+        context: &mut Context,
+        session: &'s mut Session,
+        command_id: u32,
+        capacity: usize,
+    ) -> Result<Self> {
+        let size = DATA_OFFSET + capacity.next_multiple_of(8);
+        let mut shm = SharedMemory::allocate(context, size, SharedMemoryFlags::INOUT)?;
+        Ring::init(shm.buffer_mut())?;
+        Ok(Self {
+            session,
+            shm,
+            command_id,
+        })
+    }
+
+    fn ring(&mut self) -> Ring<'_> {
+        Ring::attach(self.shm.buffer_mut())
+    }
+
+    /// Invokes the command of the ring, returning the number of messages
+    /// the TA received or sent.
+    fn doorbell(&mut self) -> Result<u32> {
+        let p0 = ParamSharedMemRef::new_whole(&mut self.shm);
+        let p1 = ParamValue::new(0, 0, ParamType::ValueOutput);
+        let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+        self.session
+            .invoke_command(self.command_id, &mut operation)?;
+        Ok(operation.parameters().1.a())
+    }
+}
+
+/// Streams messages to the TA through a ring buffer in shared memory,
+/// ringing the doorbell of the TA only when the ring is full or flushed.
+///
+/// The TA answers the doorbell with `optee_utee::ring::receive`, whose
+/// documentation describes the protocol.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use optee_teec::{Context, RingSender, Uuid};
+/// # const CMD_FRAMES: u32 = 0;
+/// # fn main() -> optee_teec::Result<()> {
+/// # let mut ctx = Context::new()?;
+/// # let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+/// # let mut session = ctx.open_session(uuid)?;
+/// # let frames: Vec<Vec<u8>> = Vec::new();
+/// let mut sender = RingSender::new(&mut ctx, &mut session, CMD_FRAMES, 1024 * 1024)?;
+/// for frame in &frames {
+///     sender.send(frame)?;
+/// }
+/// sender.flush()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RingSender<'s> {
+    channel: Channel<'s>,
+}
+
+impl<'s> RingSender<'s> {
+    /// Allocates a ring of `capacity` bytes in the shared memory of
+    /// `context`, whose doorbell is the command `command_id` of `session`.
+    pub fn new(
+        context: &mut Context,
+        session: &'s mut Session,
+        command_id: u32,
+        capacity: usize,
+    ) -> Result<Self> {
+        Channel::new(context, session, command_id, capacity).map(|channel| Self { channel })
+    }
+
+    /// Pushes `message` into the ring without ringing the doorbell, returning
+    /// false if the ring is too full to hold it.
+    ///
+    /// Fails with `ExcessData` if the message does not fit in the ring.
+    pub fn try_send(&mut self, message: &[u8]) -> Result<bool> {
+        self.channel.ring().push(message)
+    }
+
+    /// Pushes `message` into the ring, ringing the doorbell first if the ring
+    /// is too full to hold it.
+    ///
+    /// Fails with `ExcessData` if the message does not fit in the ring, and
+    /// with `Busy` if the TA left it full.
+    pub fn send(&mut self, message: &[u8]) -> Result<()> {
+        if self.try_send(message)? {
+            return Ok(());
+        }
+        self.flush()?;
+        match self.try_send(message)? {
+            true => Ok(()),
+            false => Err(ErrorKind::Busy.into()),
+        }
+    }
+
+    /// Rings the doorbell for the TA to receive the messages in the ring,
+    /// returning how many it received.
+    pub fn flush(&mut self) -> Result<u32> {
+        self.channel.doorbell()
+    }
+}
+
+/// Streams messages from the TA through a ring buffer in shared memory,
+/// ringing the doorbell of the TA only when the ring is empty.
+///
+/// The TA answers the doorbell with `optee_utee::ring::send`, whose
+/// documentation describes the protocol.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use optee_teec::{Context, RingReceiver, Uuid};
+/// # const CMD_LOGS: u32 = 1;
+/// # fn main() -> optee_teec::Result<()> {
+/// # let mut ctx = Context::new()?;
+/// # let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+/// # let mut session = ctx.open_session(uuid)?;
+/// let mut receiver = RingReceiver::new(&mut ctx, &mut session, CMD_LOGS, 64 * 1024)?;
+/// while let Some(line) = receiver.recv()? {
+///     println!("{}", String::from_utf8_lossy(&line));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct RingReceiver<'s> {
+    channel: Channel<'s>,
+}
+
+impl<'s> RingReceiver<'s> {
+    /// Allocates a ring of `capacity` bytes in the shared memory of
+    /// `context`, whose doorbell is the command `command_id` of `session`.
+    pub fn new(
+        context: &mut Context,
+        session: &'s mut Session,
+        command_id: u32,
+        capacity: usize,
+    ) -> Result<Self> {
+        Channel::new(context, session, command_id, capacity).map(|channel| Self { channel })
+    }
+
+    /// Pops the next message from the ring without ringing the doorbell,
+    /// `None` if the ring is empty.
+    pub fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        self.channel.ring().pop()
+    }
+
+    /// Pops the next message from the ring, ringing the doorbell first if the
+    /// ring is empty, `None` if the TA has no message to send.
+    pub fn recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(message) = self.try_recv()? {
+            return Ok(Some(message));
+        }
+        self.poll()?;
+        self.try_recv()
+    }
+
+    /// Rings the doorbell for the TA to send messages into the ring,
+    /// returning how many it sent.
+    pub fn poll(&mut self) -> Result<u32> {
+        self.channel.doorbell()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring() {
+        // Aligned to 8 bytes
+        let mut memory = vec![0u64; (DATA_OFFSET + 64) / 8];
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u8, memory.len() * 8)
+        };
+        let mut ring = Ring::init(bytes).unwrap();
+        assert_eq!(ring.pop().unwrap(), None);
+        assert_eq!(
+            ring.push(&[0u8; 57]).unwrap_err().kind(),
+            ErrorKind::ExcessData
+        );
+        for round in 0..20u8 {
+            let message = vec![round; (round % 30) as usize];
+            assert!(ring.push(&message).unwrap());
+            assert!(ring.push(b"second").unwrap());
+            assert_eq!(ring.pop().unwrap(), Some(message));
+            assert_eq!(ring.pop().unwrap().as_deref(), Some(&b"second"[..]));
+        }
+        assert!(ring.push(&[1u8; 40]).unwrap());
+        assert!(!ring.push(&[2u8; 9]).unwrap());
+
+        // The sequence numbers are checked
+        let tail = ring
+            .positions(Ordering::Relaxed, Ordering::Relaxed)
+            .unwrap()
+            .1;
+        ring.write_at(tail + 4, &7u32.to_le_bytes());
+        assert_eq!(ring.pop().unwrap_err().kind(), ErrorKind::BadFormat);
+    }
+}
@@ -0,0 +1,223 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::{
+    ErrorKind, Operation, OutputReader, ParamNone, ParamTmpRef, ParamType, ParamValue, Result,
+    Session,
+};
+
+/// The command running the self-tests of a TA, as defined by
+/// `optee_utee::self_test::COMMAND_ID`
+pub const SELF_TEST_COMMAND_ID: u32 = 0xFFFF_5E1F;
+
+/// The result of one self-test of a TA
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestCase {
+    /// Path of the test function in the TA, e.g. `ta::self_tests::seal`
+    pub name: String,
+    pub passed: bool,
+    pub duration: Duration,
+    /// Why the test failed, empty if it passed
+    pub message: String,
+}
+
+/// The results of the self-tests of a TA, declared with `#[ta_self_test]`
+/// and built with the `self_test` feature of `optee-utee`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use optee_teec::{Context, SelfTestReport, Uuid};
+/// # fn main() -> optee_teec::Result<()> {
+/// # let mut ctx = Context::new()?;
+/// # let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+/// # let mut session = ctx.open_session(uuid)?;
+/// let report = SelfTestReport::run(&mut session, "")?;
+/// std::fs::write("ta-self-tests.xml", report.to_junit("ta")).expect("the report is written");
+/// assert!(report.all_passed(), "{} self-tests failed", report.failed().count());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub cases: Vec<SelfTestCase>,
+}
+
+impl SelfTestReport {
+    /// Runs the self-tests of the TA of `session` whose name contains
+    /// `filter`, all of them with an empty filter.
+    ///
+    /// A TA built without the `self_test` feature fails the command, usually
+    /// with `ErrorKind::BadParameters` or `ErrorKind::NotSupported`.
+    pub fn run(session: &mut Session, filter: &str) -> Result<Self> {
+        let report = OutputReader::new(16 * 1024).read(|buffer| {
+            let p0 = ParamTmpRef::new_input(filter.as_bytes());
+            let p1 = ParamTmpRef::new_output(buffer);
+            let p2 = ParamValue::new(0, 0, ParamType::ValueOutput);
+            let mut operation = Operation::new(0, p0, p1, p2, ParamNone);
+            let result = session.invoke_command(SELF_TEST_COMMAND_ID, &mut operation);
+            (result, operation.parameters().1.updated_size())
+        })?;
+        Self::parse(&report)
+    }
+
+    /// Parses the report written by `optee_utee::self_test`.
+    fn parse(report: &[u8]) -> Result<Self> {
+        let report = std::str::from_utf8(report).map_err(|_| ErrorKind::BadFormat)?;
+        let cases = report
+            .lines()
+            .map(|line| -> Result<SelfTestCase> {
+                let mut fields = line.splitn(4, '\t');
+                let mut field = || fields.next().ok_or(ErrorKind::BadFormat);
+                let name = field()?.to_string();
+                let passed = match field()? {
+                    "ok" => true,
+                    "FAILED" => false,
+                    _ => return Err(ErrorKind::BadFormat.into()),
+                };
+                let micros = field()?.parse().map_err(|_| ErrorKind::BadFormat)?;
+                Ok(SelfTestCase {
+                    name,
+                    passed,
+                    duration: Duration::from_micros(micros),
+                    message: field()?.to_string(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { cases })
+    }
+
+    /// Whether every test that ran passed.
+    pub fn all_passed(&self) -> bool {
+        self.cases.iter().all(|case| case.passed)
+    }
+
+    /// The tests that failed.
+    pub fn failed(&self) -> impl Iterator<Item = &SelfTestCase> {
+        self.cases.iter().filter(|case| !case.passed)
+    }
+
+    /// Renders the report as a JUnit XML test suite named `suite`, for the
+    /// test report viewers of CI systems.
+    pub fn to_junit(&self, suite: &str) -> String {
+        let total: Duration = self.cases.iter().map(|case| case.duration).sum();
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.6}\">",
+            escape(suite),
+            self.cases.len(),
+            self.failed().count(),
+            total.as_secs_f64()
+        );
+        for case in &self.cases {
+            let _ = write!(
+                xml,
+                "  <testcase name=\"{}\" classname=\"{}\" time=\"{:.6}\"",
+                escape(&case.name),
+                escape(suite),
+                case.duration.as_secs_f64()
+            );
+            if case.passed {
+                xml.push_str("/>\n");
+            } else {
+                let _ = writeln!(
+                    xml,
+                    ">\n    <failure message=\"{}\"/>\n  </testcase>",
+                    escape(&case.message)
+                );
+            }
+        }
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+}
+
+/// Escapes `text` for XML attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &[u8] = b"ta::self_tests::seal\tok\t1500\t\n\
+        ta::self_tests::rng\tFAILED\t20\tassertion failed: a < b at src/main.rs:10\n";
+
+    #[test]
+    fn test_parse() {
+        let report = SelfTestReport::parse(REPORT).unwrap();
+        assert_eq!(report.cases.len(), 2);
+        assert_eq!(
+            report.cases[0],
+            SelfTestCase {
+                name: "ta::self_tests::seal".to_string(),
+                passed: true,
+                duration: Duration::from_micros(1500),
+                message: String::new(),
+            }
+        );
+        assert!(!report.all_passed());
+        let failed: Vec<_> = report.failed().map(|case| case.name.as_str()).collect();
+        assert_eq!(failed, ["ta::self_tests::rng"]);
+        assert!(SelfTestReport::parse(b"").unwrap().all_passed());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for report in [
+            &b"name\tok\n"[..],
+            b"name\tskipped\t1\t\n",
+            b"name\tok\tx\t\n",
+        ] {
+            assert_eq!(
+                SelfTestReport::parse(report).unwrap_err().kind(),
+                ErrorKind::BadFormat
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_junit() {
+        let report = SelfTestReport::parse(REPORT).unwrap();
+        assert_eq!(
+            report.to_junit("my-ta"),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <testsuite name=\"my-ta\" tests=\"2\" failures=\"1\" time=\"0.001520\">\n  \
+             <testcase name=\"ta::self_tests::seal\" classname=\"my-ta\" time=\"0.001500\"/>\n  \
+             <testcase name=\"ta::self_tests::rng\" classname=\"my-ta\" time=\"0.000020\">\n    \
+             <failure message=\"assertion failed: a &lt; b at src/main.rs:10\"/>\n  \
+             </testcase>\n\
+             </testsuite>\n"
+        );
+    }
+}
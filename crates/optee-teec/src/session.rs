@@ -16,11 +16,17 @@
 // under the License.
 
 use super::context::InnerContext;
-use crate::{Context, Error, Operation, Param, Result, Uuid, raw};
-use std::{cell::RefCell, ptr, rc::Rc};
+use crate::trace::Span;
+use crate::{
+    Context, Error, Operation, OutputReader, Param, ParamNone, ParamTmpRef, ParamTypes, Result,
+    Uuid, raw,
+};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::Duration;
+use std::{cell::RefCell, ptr, rc::Rc, thread};
 
 /// Session login methods.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum ConnectionMethods {
     /// No login data is provided.
     LoginPublic,
@@ -43,6 +49,7 @@ pub struct Session {
     // Just a holder to ensure InnerContext is not dropped and to eliminate the
     // lifetime constraint, never use it.
     _ctx: Rc<RefCell<InnerContext>>,
+    span: Span,
 }
 
 // Since raw::TEEC_Session contains a raw pointer, Rust does not automatically
@@ -66,31 +73,36 @@ impl Session {
         // block to maximize Rust's safety checks and leverage the compiler's
         // validation.
         let mut err_origin: u32 = 0;
-        let raw_operation = match operation {
-            Some(o) => o.as_mut_raw_ptr(),
-            None => ptr::null_mut(),
+        let (raw_operation, param_types) = match operation {
+            Some(o) => (o.as_mut_raw_ptr(), o.param_types()),
+            None => (ptr::null_mut(), ParamTypes::from(0)),
         };
         let inner_ctx = context.inner_context();
         let raw_ctx = &mut inner_ctx.borrow_mut().0;
         let raw_uuid = uuid.as_raw_ptr();
+        let span = context.inner_span().session(&uuid, login);
 
-        match unsafe {
-            raw::TEEC_OpenSession(
-                raw_ctx,
-                &mut raw_session,
-                raw_uuid,
-                login as u32,
-                ptr::null(),
-                raw_operation,
-                &mut err_origin,
-            )
-        } {
-            raw::TEEC_SUCCESS => Ok(Self {
-                raw: raw_session,
-                _ctx: context.inner_context(),
-            }),
-            code => Err(Error::from_raw_error(code).with_origin(err_origin.into())),
-        }
+        span.open_session(param_types, || {
+            match unsafe {
+                raw::TEEC_OpenSession(
+                    raw_ctx,
+                    &mut raw_session,
+                    raw_uuid,
+                    login as u32,
+                    ptr::null(),
+                    raw_operation,
+                    &mut err_origin,
+                )
+            } {
+                raw::TEEC_SUCCESS => Ok(()),
+                code => Err(Error::from_raw(code, err_origin)),
+            }
+        })?;
+        Ok(Self {
+            raw: raw_session,
+            _ctx: context.inner_context(),
+            span,
+        })
     }
 
     /// Invokes a command with an operation with this session.
@@ -100,17 +112,117 @@ impl Session {
         operation: &mut Operation<A, B, C, D>,
     ) -> Result<()> {
         let mut err_origin: u32 = 0;
-        match unsafe {
-            raw::TEEC_InvokeCommand(
-                &mut self.raw,
-                command_id,
-                operation.as_mut_raw_ptr(),
-                &mut err_origin,
-            )
-        } {
-            raw::TEEC_SUCCESS => Ok(()),
-            code => Err(Error::from_raw_error(code).with_origin(err_origin.into())),
-        }
+        let raw_session = &mut self.raw;
+        let param_types = operation.param_types();
+        self.span.invoke_command(command_id, param_types, || {
+            match unsafe {
+                raw::TEEC_InvokeCommand(
+                    raw_session,
+                    command_id,
+                    operation.as_mut_raw_ptr(),
+                    &mut err_origin,
+                )
+            } {
+                raw::TEEC_SUCCESS => Ok(()),
+                code => Err(Error::from_raw(code, err_origin)),
+            }
+        })
+    }
+
+    /// Invokes a command taking `input` as an input memref in the first
+    /// parameter and returning the content of an output memref in the second
+    /// one, retrying with a larger buffer while the TA reports a short buffer.
+    ///
+    /// The output buffer starts with `initial_capacity` bytes and grows to the
+    /// size required by the TA, see [`OutputReader`], up to
+    /// [`OutputReader::DEFAULT_MAX_CAPACITY`]. The command may therefore be
+    /// invoked more than once and must not have side effects when it fails
+    /// with `ErrorKind::ShortBuffer`.
+    ///
+    /// ```no_run
+    /// # use optee_teec::{Context, Uuid};
+    /// # fn main() -> optee_teec::Result<()> {
+    /// # let mut ctx = Context::new()?;
+    /// # let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+    /// let mut session = ctx.open_session(uuid)?;
+    /// let output = session.invoke_with_growable_output(0, b"input", 256)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn invoke_with_growable_output(
+        &mut self,
+        command_id: u32,
+        input: &[u8],
+        initial_capacity: usize,
+    ) -> Result<Vec<u8>> {
+        OutputReader::new(initial_capacity).read(|buffer| {
+            let p0 = ParamTmpRef::new_input(input);
+            let p1 = ParamTmpRef::new_output(buffer);
+            let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+            let result = self.invoke_command(command_id, &mut operation);
+            (result, operation.parameters().1.updated_size())
+        })
+    }
+
+    /// The `teec_session` span of this session, parent of the spans of its
+    /// commands, see the `tracing` feature.
+    #[cfg(feature = "tracing")]
+    pub fn span(&self) -> &tracing::Span {
+        self.span.as_tracing()
+    }
+
+    /// Invokes a command like [`invoke_command`](Self::invoke_command), and
+    /// requests its cancellation if the TA has not returned after `timeout`.
+    ///
+    /// The TA is not interrupted: it stops early, usually failing with
+    /// [`ErrorKind::Cancel`](crate::ErrorKind::Cancel), only if it checks for
+    /// cancellation, e.g. with `optee_utee::cancellation`. Otherwise the
+    /// command completes as if no timeout was set. The operation must have
+    /// been created with `started` set to 0 for the request to reach the TA.
+    pub fn invoke_command_with_timeout<A: Param, B: Param, C: Param, D: Param>(
+        &mut self,
+        command_id: u32,
+        operation: &mut Operation<A, B, C, D>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let raw_operation = RawOperation(operation.as_mut_raw_ptr());
+        let done = (Mutex::new(false), Condvar::new());
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let (done, returned) = &done;
+                let done = done.lock().unwrap_or_else(PoisonError::into_inner);
+                let (done, _) = returned
+                    .wait_timeout_while(done, timeout, |done| !*done)
+                    .unwrap_or_else(PoisonError::into_inner);
+                if !*done {
+                    // SAFETY: the command has not returned, as it sets
+                    // `done` under the lock held here, so the operation is
+                    // still alive.
+                    unsafe { raw::TEEC_RequestCancellation(raw_operation.get()) };
+                }
+            });
+            let result = self.invoke_command(command_id, operation);
+            *done.0.lock().unwrap_or_else(PoisonError::into_inner) = true;
+            done.1.notify_one();
+            result
+        })
+    }
+}
+
+/// Pointer to the operation of a running command, sent to the thread
+/// requesting its cancellation.
+struct RawOperation(*mut raw::TEEC_Operation);
+
+// SAFETY: the pointer is only used while the operation is alive, see
+// `Session::invoke_command_with_timeout`.
+unsafe impl Send for RawOperation {}
+unsafe impl Sync for RawOperation {}
+
+impl RawOperation {
+    // A method, so closures capture the whole `Send` wrapper and not the
+    // raw pointer field
+    fn get(&self) -> *mut raw::TEEC_Operation {
+        self.0
     }
 }
 
@@ -0,0 +1,404 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+use crate::{ConnectionMethods, Context, Operation, Param, ParamNone, Result, Session, Uuid};
+
+/// A fixed number of sessions to a TA, shared by several threads.
+///
+/// A thread takes a session with [`get`](Self::get), waiting for one if all
+/// of them are in use, and returns it to the pool when dropping the
+/// [`PooledSession`]. Commands invoked through a pooled session run
+/// concurrently with the commands of the other sessions, as far as the TA
+/// allows it: a single instance TA without `TA_FLAG_MULTI_SESSION` accepts
+/// one session only.
+///
+/// # Reconnection
+///
+/// A session whose TA panicked is unusable and its commands fail with
+/// [`ErrorKind::TargetDead`] from outside of the TA, see
+/// [`Error::is_target_dead`]. [`PooledSession::invoke_command`] then opens a
+/// new session in its place and invokes the command once more, so commands
+/// sent through a pool should be safe to repeat. Sessions of a single
+/// instance TA all die with it, they are reopened one by one on their next
+/// command.
+///
+/// [`ErrorKind::TargetDead`]: crate::ErrorKind::TargetDead
+/// [`Error::is_target_dead`]: crate::Error::is_target_dead
+///
+/// # Examples
+///
+/// ```no_run
+/// use optee_teec::{Context, Operation, ParamNone, ParamType, ParamValue, SessionPool, Uuid};
+/// use std::thread;
+///
+/// # fn main() -> optee_teec::Result<()> {
+/// let uuid = Uuid::parse_str("8abcf200-2450-11e4-abe2-0002a5d5c51b")?;
+/// let pool = SessionPool::new(Context::new()?, uuid, 4)?;
+/// thread::scope(|s| {
+///     for client in 0..16 {
+///         let pool = &pool;
+///         s.spawn(move || -> optee_teec::Result<u32> {
+///             let p0 = ParamValue::new(client, 0, ParamType::ValueInout);
+///             let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
+///             pool.invoke_command(1, &mut operation)?;
+///             Ok(operation.parameters().0.a())
+///         });
+///     }
+/// });
+/// # Ok(())
+/// # }
+/// ```
+pub struct SessionPool {
+    // Sessions share the context through a non-atomic `Rc`, so opening and
+    // closing them is serialized by this lock.
+    ctx: Mutex<Context>,
+    uuid: Uuid,
+    login: ConnectionMethods,
+    slots: Slots<Session>,
+}
+
+impl SessionPool {
+    /// Opens `size` sessions with the specified trusted application, at
+    /// least one.
+    pub fn new(context: Context, uuid: Uuid, size: usize) -> Result<Self> {
+        Self::with_login(context, uuid, ConnectionMethods::LoginPublic, size)
+    }
+
+    /// Opens `size` sessions with the specified trusted application and
+    /// login method, at least one.
+    pub fn with_login(
+        mut context: Context,
+        uuid: Uuid,
+        login: ConnectionMethods,
+        size: usize,
+    ) -> Result<Self> {
+        let size = size.max(1);
+        let mut sessions = Vec::with_capacity(size);
+        for _ in 0..size {
+            sessions.push(open(&mut context, &uuid, login)?);
+        }
+        Ok(Self {
+            ctx: Mutex::new(context),
+            uuid,
+            login,
+            slots: Slots::new(sessions),
+        })
+    }
+
+    /// The number of sessions of the pool.
+    pub fn size(&self) -> usize {
+        self.slots.size
+    }
+
+    /// Takes a session from the pool, waiting until one is returned if all of
+    /// them are in use.
+    pub fn get(&self) -> PooledSession<'_> {
+        PooledSession {
+            pool: self,
+            session: Some(self.slots.take()),
+        }
+    }
+
+    /// Takes a session from the pool if one is not in use.
+    pub fn try_get(&self) -> Option<PooledSession<'_>> {
+        Some(PooledSession {
+            pool: self,
+            session: Some(self.slots.try_take()?),
+        })
+    }
+
+    /// Invokes a command with a session of the pool, see
+    /// [`PooledSession::invoke_command`].
+    pub fn invoke_command<A: Param, B: Param, C: Param, D: Param>(
+        &self,
+        command_id: u32,
+        operation: &mut Operation<A, B, C, D>,
+    ) -> Result<()> {
+        self.get().invoke_command(command_id, operation)
+    }
+}
+
+fn open(context: &mut Context, uuid: &Uuid, login: ConnectionMethods) -> Result<Session> {
+    Session::new(
+        context,
+        uuid.clone(),
+        login,
+        None::<&mut Operation<ParamNone, ParamNone, ParamNone, ParamNone>>,
+    )
+}
+
+/// A session taken from a [`SessionPool`], returned to it when dropped.
+pub struct PooledSession<'p> {
+    pool: &'p SessionPool,
+    // Only taken when dropped
+    session: Option<Session>,
+}
+
+impl PooledSession<'_> {
+    /// Invokes a command with an operation with this session.
+    ///
+    /// If the TA has died, the session is reopened and the command invoked
+    /// once more with the same operation. The command still fails with
+    /// [`ErrorKind::TargetDead`](crate::ErrorKind::TargetDead) if reopening
+    /// fails or the TA dies again.
+    pub fn invoke_command<A: Param, B: Param, C: Param, D: Param>(
+        &mut self,
+        command_id: u32,
+        operation: &mut Operation<A, B, C, D>,
+    ) -> Result<()> {
+        retry_dead(
+            self,
+            |session| session.deref_mut().invoke_command(command_id, operation),
+            |session| session.reopen(),
+        )
+    }
+
+    /// Closes the session and opens a new one in its place, keeping the
+    /// current session if opening fails.
+    pub fn reopen(&mut self) -> Result<()> {
+        let mut ctx = lock(&self.pool.ctx);
+        let session = open(&mut ctx, &self.pool.uuid, self.pool.login)?;
+        // Closed with the context locked
+        drop(self.session.replace(session));
+        Ok(())
+    }
+}
+
+impl Deref for PooledSession<'_> {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        self.session
+            .as_ref()
+            .expect("the session is only taken on drop")
+    }
+}
+
+impl DerefMut for PooledSession<'_> {
+    fn deref_mut(&mut self) -> &mut Session {
+        self.session
+            .as_mut()
+            .expect("the session is only taken on drop")
+    }
+}
+
+impl Drop for PooledSession<'_> {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.pool.slots.put(session);
+        }
+    }
+}
+
+/// Runs `invoke` with `session`, and once more after `reopen` if the TA
+/// died.
+fn retry_dead<S, R>(
+    session: &mut S,
+    mut invoke: impl FnMut(&mut S) -> Result<R>,
+    reopen: impl FnOnce(&mut S) -> Result<()>,
+) -> Result<R> {
+    match invoke(session) {
+        Err(err) if err.is_target_dead() => {
+            if let Err(reopen_err) = reopen(session) {
+                log::debug!("Reopening a session of a dead TA failed: {}", reopen_err);
+                return Err(err);
+            }
+            invoke(session)
+        }
+        result => result,
+    }
+}
+
+/// Lock `mutex`, ignoring poisoning: the sessions and the context stay
+/// usable after a panic of another thread.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The idle sessions of a pool.
+struct Slots<S> {
+    idle: Mutex<Vec<S>>,
+    returned: Condvar,
+    size: usize,
+}
+
+impl<S> Slots<S> {
+    fn new(idle: Vec<S>) -> Self {
+        Self {
+            size: idle.len(),
+            idle: Mutex::new(idle),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Takes an idle session, waiting for one to be returned if there is
+    /// none.
+    fn take(&self) -> S {
+        let mut idle = lock(&self.idle);
+        loop {
+            if let Some(session) = idle.pop() {
+                return session;
+            }
+            idle = self
+                .returned
+                .wait(idle)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn try_take(&self) -> Option<S> {
+        lock(&self.idle).pop()
+    }
+
+    fn put(&self, session: S) {
+        lock(&self.idle).push(session);
+        self.returned.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, ErrorKind, raw};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    /// Mimics a session, whose TA dies after `lives` commands.
+    struct FakeSession {
+        generation: u32,
+        lives: u32,
+    }
+
+    impl FakeSession {
+        fn invoke(&mut self) -> Result<u32> {
+            if self.lives == 0 {
+                return Err(Error::new(ErrorKind::TargetDead));
+            }
+            self.lives -= 1;
+            Ok(self.generation)
+        }
+    }
+
+    #[test]
+    fn test_retry_dead() {
+        let reopen = |session: &mut FakeSession| -> Result<()> {
+            session.generation += 1;
+            session.lives = 1;
+            Ok(())
+        };
+        let mut session = FakeSession {
+            generation: 0,
+            lives: 1,
+        };
+        assert_eq!(
+            retry_dead(&mut session, FakeSession::invoke, reopen).unwrap(),
+            0
+        );
+        assert_eq!(
+            retry_dead(&mut session, FakeSession::invoke, reopen).unwrap(),
+            1
+        );
+
+        // Dies again after reopening
+        let err = retry_dead(&mut session, FakeSession::invoke, |_| Ok(())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TargetDead);
+
+        // Reopening fails, the original error is returned
+        let err = retry_dead(&mut session, FakeSession::invoke, |_| {
+            Err(ErrorKind::ItemNotFound.into())
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TargetDead);
+
+        // Other errors are not retried
+        let mut reopened = false;
+        let err = retry_dead(
+            &mut session,
+            |_| -> Result<()> { Err(ErrorKind::BadParameters.into()) },
+            |_| {
+                reopened = true;
+                Ok(())
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadParameters);
+        assert!(!reopened);
+
+        // Nor is a TA returning `TargetDead` itself
+        let err = retry_dead(
+            &mut session,
+            |_| -> Result<()> {
+                Err(Error::from_raw(
+                    raw::TEEC_ERROR_TARGET_DEAD,
+                    raw::TEEC_ORIGIN_TRUSTED_APP,
+                ))
+            },
+            |_| {
+                reopened = true;
+                Ok(())
+            },
+        )
+        .unwrap_err();
+        assert!(err.is_from_ta());
+        assert!(!reopened);
+    }
+
+    #[test]
+    fn test_slots() {
+        let slots = Arc::new(Slots::new(vec![0, 1]));
+        assert_eq!(slots.size, 2);
+        let a = slots.take();
+        let b = slots.try_take().unwrap();
+        assert_eq!(slots.try_take(), None);
+
+        let in_use = Arc::new(AtomicUsize::new(2));
+        let max_in_use = Arc::new(AtomicUsize::new(2));
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let slots = slots.clone();
+                let in_use = in_use.clone();
+                let max_in_use = max_in_use.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        let session = slots.take();
+                        let now = in_use.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_in_use.fetch_max(now, Ordering::SeqCst);
+                        thread::yield_now();
+                        in_use.fetch_sub(1, Ordering::SeqCst);
+                        slots.put(session);
+                    }
+                })
+            })
+            .collect();
+        // The workers wait for the sessions held here
+        in_use.fetch_sub(2, Ordering::SeqCst);
+        slots.put(a);
+        slots.put(b);
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(max_in_use.load(Ordering::SeqCst), 2);
+
+        let mut idle = lock(&slots.idle).clone();
+        idle.sort();
+        assert_eq!(idle, [0, 1]);
+    }
+}
@@ -0,0 +1,198 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::context::InnerContext;
+use crate::{Context, Error, Result, raw};
+use std::{cell::RefCell, marker, mem, ops, rc::Rc, slice};
+
+/// The directions a [`SharedMemory`] block is used in, combined with `|`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SharedMemoryFlags(u32);
+
+impl SharedMemoryFlags {
+    /// The Trusted Application (TA) reads from the block.
+    pub const INPUT: Self = Self(raw::TEEC_MEM_INPUT);
+    /// The Trusted Application (TA) writes to the block.
+    pub const OUTPUT: Self = Self(raw::TEEC_MEM_OUTPUT);
+    /// The Trusted Application (TA) both reads from and writes to the block.
+    pub const INOUT: Self = Self(raw::TEEC_MEM_INPUT | raw::TEEC_MEM_OUTPUT);
+
+    /// Returns whether all directions of `other` are set in `self`.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl ops::BitOr for SharedMemoryFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl From<SharedMemoryFlags> for u32 {
+    fn from(flags: SharedMemoryFlags) -> u32 {
+        flags.0
+    }
+}
+
+/// A block of memory shared with the Trusted Application (TA), passed to it
+/// with [`ParamSharedMemRef`](crate::ParamSharedMemRef).
+///
+/// Unlike a [`ParamTmpRef`](crate::ParamTmpRef), which is shared for the
+/// duration of a single operation and may be copied by the client library,
+/// a shared memory block is registered once and can be used by any number
+/// of operations without copying, which suits large or reused buffers.
+///
+/// The block is released when dropped.
+pub struct SharedMemory<'a> {
+    // Boxed, as operations refer to it by address.
+    raw: Box<raw::TEEC_SharedMemory>,
+    // Just a holder to ensure InnerContext is not dropped, never use it.
+    _ctx: Rc<RefCell<InnerContext>>,
+    _marker: marker::PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> SharedMemory<'a> {
+    /// Registers `buffer` as shared memory, so the TA accesses it directly
+    /// for as long as the returned block lives.
+    ///
+    /// # Examples
+    ///
+    /// ``` no_run
+    /// use optee_teec::{Context, SharedMemory, SharedMemoryFlags};
+    ///
+    /// fn main() -> optee_teec::Result<()> {
+    ///     let mut ctx = Context::new()?;
+    ///     let mut buffer = vec![0u8; 1024 * 1024];
+    ///     let shm = SharedMemory::register(&mut ctx, &mut buffer, SharedMemoryFlags::INOUT)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn register(
+        context: &mut Context,
+        buffer: &'a mut [u8],
+        flags: SharedMemoryFlags,
+    ) -> Result<Self> {
+        // SAFETY:
+        // raw_shm is a C struct(TEEC_SharedMemory), which zero value is valid.
+        let mut raw_shm: Box<raw::TEEC_SharedMemory> = Box::new(unsafe { mem::zeroed() });
+        raw_shm.buffer = buffer.as_mut_ptr() as _;
+        raw_shm.size = buffer.len();
+        raw_shm.flags = flags.into();
+        let inner_ctx = context.inner_context();
+        let raw_ctx = &mut inner_ctx.borrow_mut().0;
+
+        match unsafe { raw::TEEC_RegisterSharedMemory(raw_ctx, raw_shm.as_mut()) } {
+            raw::TEEC_SUCCESS => Ok(Self {
+                raw: raw_shm,
+                _ctx: context.inner_context(),
+                _marker: marker::PhantomData,
+            }),
+            code => Err(Error::from_raw_error(code)),
+        }
+    }
+}
+
+impl SharedMemory<'static> {
+    /// Allocates `size` bytes of zeroed shared memory, owned by the returned
+    /// block.
+    ///
+    /// # Examples
+    ///
+    /// ``` no_run
+    /// use optee_teec::{Context, SharedMemory, SharedMemoryFlags};
+    ///
+    /// fn main() -> optee_teec::Result<()> {
+    ///     let mut ctx = Context::new()?;
+    ///     let mut shm = SharedMemory::allocate(&mut ctx, 4096, SharedMemoryFlags::INPUT)?;
+    ///     shm.buffer_mut()[..5].copy_from_slice(b"hello");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn allocate(context: &mut Context, size: usize, flags: SharedMemoryFlags) -> Result<Self> {
+        // SAFETY:
+        // raw_shm is a C struct(TEEC_SharedMemory), which zero value is valid.
+        let mut raw_shm: Box<raw::TEEC_SharedMemory> = Box::new(unsafe { mem::zeroed() });
+        raw_shm.size = size;
+        raw_shm.flags = flags.into();
+        let inner_ctx = context.inner_context();
+        let raw_ctx = &mut inner_ctx.borrow_mut().0;
+
+        match unsafe { raw::TEEC_AllocateSharedMemory(raw_ctx, raw_shm.as_mut()) } {
+            raw::TEEC_SUCCESS => {
+                let mut shm = Self {
+                    raw: raw_shm,
+                    _ctx: context.inner_context(),
+                    _marker: marker::PhantomData,
+                };
+                // The memory comes from the TEE driver, do not leak whatever
+                // it held before
+                shm.buffer_mut().fill(0);
+                Ok(shm)
+            }
+            code => Err(Error::from_raw_error(code)),
+        }
+    }
+}
+
+impl SharedMemory<'_> {
+    /// Returns the contents of the block.
+    pub fn buffer(&self) -> &[u8] {
+        if self.raw.size == 0 || self.raw.buffer.is_null() {
+            return &[];
+        }
+        // SAFETY: the buffer is either borrowed for the lifetime of self or
+        // owned by the block, and only accessed by the TA during operations,
+        // which borrow self.
+        unsafe { slice::from_raw_parts(self.raw.buffer as *const u8, self.raw.size) }
+    }
+
+    /// Returns the contents of the block for writing.
+    pub fn buffer_mut(&mut self) -> &mut [u8] {
+        if self.raw.size == 0 || self.raw.buffer.is_null() {
+            return &mut [];
+        }
+        // SAFETY: see buffer().
+        unsafe { slice::from_raw_parts_mut(self.raw.buffer as *mut u8, self.raw.size) }
+    }
+
+    /// Returns the size of the block in bytes.
+    pub fn size(&self) -> usize {
+        self.raw.size
+    }
+
+    /// Returns the directions the block was shared for.
+    pub fn flags(&self) -> SharedMemoryFlags {
+        SharedMemoryFlags(self.raw.flags)
+    }
+
+    // The client library only reads the descriptor, the TA writes to the
+    // buffer, which output references borrow mutably.
+    pub(crate) fn as_mut_raw_ptr(&self) -> *mut raw::TEEC_SharedMemory {
+        &*self.raw as *const raw::TEEC_SharedMemory as *mut raw::TEEC_SharedMemory
+    }
+}
+
+impl Drop for SharedMemory<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            raw::TEEC_ReleaseSharedMemory(self.raw.as_mut());
+        }
+    }
+}
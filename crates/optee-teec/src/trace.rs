@@ -0,0 +1,127 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Spans of the `tracing` crate around the calls to the TEE, compiled to
+//! nothing without the `tracing` feature.
+//!
+//! Every context has a `teec_context` span, parent of the `teec_session` span
+//! of each of its sessions, which lives as long as the session and carries
+//! the UUID of the TA and the login method. The calls to the TEE are
+//! recorded in `open_session` and `invoke_command` spans, children of the
+//! session span, with the command ID, the parameter types, the duration of
+//! the call in microseconds and the error it failed with.
+
+use crate::{ConnectionMethods, ParamTypes, Result, Uuid};
+
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    inner: tracing::Span,
+}
+
+#[cfg(feature = "tracing")]
+impl Span {
+    pub(crate) fn context() -> Self {
+        tracing::debug_span!("teec_context").into()
+    }
+
+    pub(crate) fn session(&self, uuid: &Uuid, login: ConnectionMethods) -> Self {
+        tracing::debug_span!(parent: &self.inner, "teec_session", %uuid, ?login).into()
+    }
+
+    pub(crate) fn open_session<R>(
+        &self,
+        param_types: ParamTypes,
+        call: impl FnOnce() -> Result<R>,
+    ) -> Result<R> {
+        let span = tracing::debug_span!(
+            parent: &self.inner,
+            "open_session",
+            param_types = ?param_types.into_flags(),
+            duration_us = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        record(span, call)
+    }
+
+    pub(crate) fn invoke_command<R>(
+        &self,
+        command_id: u32,
+        param_types: ParamTypes,
+        call: impl FnOnce() -> Result<R>,
+    ) -> Result<R> {
+        let span = tracing::debug_span!(
+            parent: &self.inner,
+            "invoke_command",
+            command_id,
+            param_types = ?param_types.into_flags(),
+            duration_us = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        record(span, call)
+    }
+
+    pub(crate) fn as_tracing(&self) -> &tracing::Span {
+        &self.inner
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<tracing::Span> for Span {
+    fn from(inner: tracing::Span) -> Self {
+        Self { inner }
+    }
+}
+
+/// Runs `call` in `span`, recording its duration and error.
+#[cfg(feature = "tracing")]
+fn record<R>(span: tracing::Span, call: impl FnOnce() -> Result<R>) -> Result<R> {
+    let start = std::time::Instant::now();
+    let result = span.in_scope(call);
+    span.record("duration_us", start.elapsed().as_micros() as u64);
+    if let Err(e) = &result {
+        span.record("error", tracing::field::display(e));
+    }
+    result
+}
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn context() -> Self {
+        Self {}
+    }
+
+    pub(crate) fn session(&self, _uuid: &Uuid, _login: ConnectionMethods) -> Self {
+        Self {}
+    }
+
+    pub(crate) fn open_session<R>(
+        &self,
+        _param_types: ParamTypes,
+        call: impl FnOnce() -> Result<R>,
+    ) -> Result<R> {
+        call()
+    }
+
+    pub(crate) fn invoke_command<R>(
+        &self,
+        _command_id: u32,
+        _param_types: ParamTypes,
+        call: impl FnOnce() -> Result<R>,
+    ) -> Result<R> {
+        call()
+    }
+}
@@ -0,0 +1,311 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Verification of the user in the REE, e.g. by Android Gatekeeper, a
+//! fingerprint sensor or PAM, for the TAs gating a command on it, served by
+//! a supplicant plugin and called with `optee_utee::user_auth::UserAuth`.
+//!
+//! The TA sends a random challenge, the authenticator types it accepts and
+//! the reason shown to the user. A [`Verifier`] checks the user and answers
+//! with an [`AuthToken`] for the challenge, authenticated with an HMAC-SHA256
+//! key shared with the TA, in the layout of the `hw_auth_token_t` of Android,
+//! so the tokens of Gatekeeper and of the fingerprint HAL pass through as
+//! they are. The TA accepts the verdict only if the tag and the challenge
+//! match: the REE cannot forge a verdict without the key, which must be held
+//! by the authenticator rather than by the plugin for the check to mean more
+//! than "the REE says so".
+//!
+//! A plugin installed as `/usr/lib/tee-supplicant/plugins/<PLUGIN_UUID>.plugin`
+//! serves the TAs with the router of its verifier:
+//!
+//! ```no_run
+//! # use optee_teec::{ErrorKind, PluginParameters, PluginRouter, Result};
+//! use optee_teec::user_auth::{self, AuthToken, VerifyRequest};
+//! use std::sync::LazyLock;
+//!
+//! # fn prompt_pam(_reason: &str) -> bool { false }
+//! # fn hmac_sha256(_message: &[u8]) -> [u8; 32] { [0; 32] }
+//! fn verify(request: &VerifyRequest) -> Result<Vec<u8>> {
+//!     if !prompt_pam(&request.reason) {
+//!         return Err(ErrorKind::AccessDenied.into());
+//!     }
+//!     let token = AuthToken {
+//!         challenge: request.challenge,
+//!         user_id: 1000,
+//!         authenticator_id: 0,
+//!         authenticator_type: user_auth::AUTHENTICATOR_PASSWORD,
+//!         timestamp_ms: 0,
+//!     };
+//!     Ok(token.sign(hmac_sha256).to_vec())
+//! }
+//!
+//! static ROUTER: LazyLock<PluginRouter> = LazyLock::new(|| user_auth::router(verify));
+//!
+//! // Annotated with `#[plugin_invoke]` in the plugin
+//! fn invoke(params: &mut PluginParameters) -> Result<()> {
+//!     ROUTER.dispatch(params)
+//! }
+//! ```
+//!
+//! The request is the challenge, 8 bytes little-endian, the authenticator
+//! types, 4 bytes little-endian, and the reason in UTF-8, followed by zero
+//! padding. The response is the token, [`TOKEN_SIZE`] bytes.
+
+use crate::{ErrorKind, PluginParameters, PluginRouter, Result};
+
+/// UUID of the plugin `optee_utee::user_auth::UserAuth` calls.
+pub const PLUGIN_UUID: &str = "3e07acba-e1d1-4ed8-8823-403941be6a2d";
+/// Command verifying the user.
+pub const CMD_VERIFY_USER: u32 = 0;
+
+/// Authenticator type of a password, PIN or pattern, as in Android.
+pub const AUTHENTICATOR_PASSWORD: u32 = 1 << 0;
+/// Authenticator type of a biometric, as in Android.
+pub const AUTHENTICATOR_FINGERPRINT: u32 = 1 << 1;
+
+/// Size of an encoded token in bytes.
+pub const TOKEN_SIZE: usize = 69;
+/// Size of the part of an encoded token the tag authenticates in bytes.
+pub const TOKEN_MESSAGE_SIZE: usize = TOKEN_SIZE - 32;
+/// Version of the tokens.
+const TOKEN_VERSION: u8 = 0;
+
+/// Request of a TA to verify the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyRequest {
+    /// Random value of the TA the token must carry.
+    pub challenge: u64,
+    /// The authenticator types the TA accepts.
+    pub authenticator_types: u32,
+    /// Reason of the verification shown to the user, e.g. "Sign a payment".
+    pub reason: String,
+}
+
+impl VerifyRequest {
+    /// Decodes the request in a buffer of the plugin.
+    pub fn decode(buffer: &[u8]) -> Result<Self> {
+        if buffer.len() < 12 {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        let (challenge, rest) = buffer.split_at(8);
+        let (types, reason) = rest.split_at(4);
+        let reason = &reason[..reason.iter().position(|&b| b == 0).unwrap_or(reason.len())];
+        Ok(Self {
+            challenge: u64::from_le_bytes(challenge.try_into().unwrap()),
+            authenticator_types: u32::from_le_bytes(types.try_into().unwrap()),
+            reason: std::str::from_utf8(reason)
+                .map_err(|_| ErrorKind::BadParameters)?
+                .to_owned(),
+        })
+    }
+}
+
+/// Verdict of a successful verification, in the layout of the
+/// `hw_auth_token_t` of Android.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuthToken {
+    /// The challenge of the request.
+    pub challenge: u64,
+    /// Secure user ID of the user verified.
+    pub user_id: u64,
+    /// ID of the enrolment of the authenticator, e.g. of the fingerprints.
+    pub authenticator_id: u64,
+    /// The authenticator type which verified the user.
+    pub authenticator_type: u32,
+    /// Time of the verification in milliseconds on the clock of the
+    /// authenticator.
+    pub timestamp_ms: u64,
+}
+
+impl AuthToken {
+    /// The part of the encoded token the tag authenticates.
+    pub fn message(&self) -> [u8; TOKEN_MESSAGE_SIZE] {
+        let mut message = [0u8; TOKEN_MESSAGE_SIZE];
+        message[0] = TOKEN_VERSION;
+        message[1..9].copy_from_slice(&self.challenge.to_le_bytes());
+        message[9..17].copy_from_slice(&self.user_id.to_le_bytes());
+        message[17..25].copy_from_slice(&self.authenticator_id.to_le_bytes());
+        // Big-endian, as in Android
+        message[25..29].copy_from_slice(&self.authenticator_type.to_be_bytes());
+        message[29..37].copy_from_slice(&self.timestamp_ms.to_be_bytes());
+        message
+    }
+
+    /// Encodes the token with the tag `mac` computes of its message with the
+    /// HMAC-SHA256 key shared with the TA.
+    pub fn sign<F>(&self, mac: F) -> [u8; TOKEN_SIZE]
+    where
+        F: FnOnce(&[u8]) -> [u8; 32],
+    {
+        let message = self.message();
+        let mut token = [0u8; TOKEN_SIZE];
+        token[..TOKEN_MESSAGE_SIZE].copy_from_slice(&message);
+        token[TOKEN_MESSAGE_SIZE..].copy_from_slice(&mac(&message));
+        token
+    }
+}
+
+/// Verifies the user for the requests of the TAs.
+pub trait Verifier: Send + Sync + 'static {
+    /// Verifies the user for `request` and returns the encoded token, e.g.
+    /// one signed with [`AuthToken::sign`] or returned by Gatekeeper.
+    ///
+    /// Fails with `AccessDenied` if the user was not verified, and with
+    /// `Cancel` if the user dismissed the prompt.
+    fn verify(&self, request: &VerifyRequest) -> Result<Vec<u8>>;
+}
+
+impl<F> Verifier for F
+where
+    F: Fn(&VerifyRequest) -> Result<Vec<u8>> + Send + Sync + 'static,
+{
+    fn verify(&self, request: &VerifyRequest) -> Result<Vec<u8>> {
+        self(request)
+    }
+}
+
+/// A router serving [`CMD_VERIFY_USER`] with `verifier`.
+pub fn router(verifier: impl Verifier) -> PluginRouter {
+    PluginRouter::new().route(CMD_VERIFY_USER, move |params| {
+        verify_user(&verifier, params)
+    })
+}
+
+/// Verifies the user for the request in the buffer of `params` with
+/// `verifier`, and answers with the token.
+///
+/// Fails with `BadParameters` if the buffer does not hold a request, and
+/// with `BadFormat` if the verifier returns a token of another size.
+pub fn verify_user(verifier: &dyn Verifier, params: &mut PluginParameters) -> Result<()> {
+    let request = VerifyRequest::decode(params.get_buffer())?;
+    log::debug!(
+        "Verifying the user for \"{}\", authenticators {:#x}",
+        request.reason,
+        request.authenticator_types
+    );
+    let token = verifier.verify(&request)?;
+    if token.len() != TOKEN_SIZE {
+        return Err(ErrorKind::BadFormat.into());
+    }
+    params.set_buf_from_slice(&token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::size_t;
+
+    fn request(challenge: u64, types: u32, reason: &[u8]) -> Vec<u8> {
+        let mut request = challenge.to_le_bytes().to_vec();
+        request.extend_from_slice(&types.to_le_bytes());
+        request.extend_from_slice(reason);
+        request
+    }
+
+    fn invoke(router: &PluginRouter, input: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = input.to_vec();
+        buf.resize(128, 0);
+        let mut out_len: size_t = 0;
+        let mut params = unsafe {
+            PluginParameters::from_raw(
+                CMD_VERIFY_USER,
+                0,
+                buf.as_mut_ptr() as _,
+                buf.len(),
+                &mut out_len,
+            )
+        }?;
+        router.dispatch(&mut params)?;
+        Ok(buf[..out_len].to_vec())
+    }
+
+    #[test]
+    fn test_decode_request() {
+        let mut buffer = request(7, AUTHENTICATOR_FINGERPRINT, b"Sign");
+        buffer.resize(64, 0);
+        assert_eq!(
+            VerifyRequest::decode(&buffer).unwrap(),
+            VerifyRequest {
+                challenge: 7,
+                authenticator_types: AUTHENTICATOR_FINGERPRINT,
+                reason: "Sign".to_owned(),
+            }
+        );
+        assert_eq!(
+            VerifyRequest::decode(&buffer[..11]).unwrap_err().kind(),
+            ErrorKind::BadParameters
+        );
+        assert_eq!(
+            VerifyRequest::decode(&request(7, 1, b"\xff"))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::BadParameters
+        );
+    }
+
+    #[test]
+    fn test_sign() {
+        let token = AuthToken {
+            challenge: 0x0102,
+            user_id: 3,
+            authenticator_id: 4,
+            authenticator_type: AUTHENTICATOR_FINGERPRINT,
+            timestamp_ms: 5,
+        };
+        let signed = token.sign(|message| {
+            assert_eq!(message.len(), TOKEN_MESSAGE_SIZE);
+            [0xaa; 32]
+        });
+        assert_eq!(signed[..3], [TOKEN_VERSION, 0x02, 0x01]);
+        assert_eq!(signed[9], 3);
+        assert_eq!(signed[17], 4);
+        assert_eq!(signed[25..29], [0, 0, 0, 2]);
+        assert_eq!(signed[36], 5);
+        assert_eq!(signed[TOKEN_MESSAGE_SIZE..], [0xaa; 32]);
+    }
+
+    #[test]
+    fn test_router() {
+        let router = router(|request: &VerifyRequest| {
+            if request.reason != "Sign" {
+                return Err(ErrorKind::AccessDenied.into());
+            }
+            let token = AuthToken {
+                challenge: request.challenge,
+                user_id: 0,
+                authenticator_id: 0,
+                authenticator_type: AUTHENTICATOR_PASSWORD,
+                timestamp_ms: 0,
+            };
+            Ok(token.sign(|_| [0; 32]).to_vec())
+        });
+        let token = invoke(&router, &request(9, AUTHENTICATOR_PASSWORD, b"Sign")).unwrap();
+        assert_eq!(token.len(), TOKEN_SIZE);
+        assert_eq!(token[1], 9);
+        assert_eq!(
+            invoke(&router, &request(9, AUTHENTICATOR_PASSWORD, b"Wipe"))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::AccessDenied
+        );
+
+        let router = super::router(|_: &VerifyRequest| Ok(vec![0; 3]));
+        assert_eq!(
+            invoke(&router, &request(9, 1, b"")).unwrap_err().kind(),
+            ErrorKind::BadFormat
+        );
+    }
+}
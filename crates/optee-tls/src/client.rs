@@ -0,0 +1,240 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! TLS client connections of the TA.
+
+use optee_utee::net::TcpStream;
+use optee_utee::{ErrorKind, ReeTime, Result};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{ServerName, UnixTime};
+use rustls::time_provider::TimeProvider;
+use rustls::{ClientConfig, ClientConnection, ConfigBuilder, RootCertStore, StreamOwned};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use crate::Error;
+
+/// A TLS connection of the TA over `S`, read and written as plaintext with
+/// `std::io::Read` and `std::io::Write`.
+pub type TlsStream<S> = StreamOwned<ClientConnection, S>;
+
+/// Opens TLS connections from the TA, e.g. to fetch data from HTTPS
+/// endpoints, with the rustls [`ClientConfig`] given by the TA.
+///
+/// ```rust,ignore
+/// use optee_tls::TlsConnector;
+/// use optee_utee::net::http::Request;
+///
+/// let mut roots = RootCertStore::empty();
+/// roots.add(CertificateDer::from(ROOT_CA_DER))?;
+/// let connector = TlsConnector::with_root_store(provider, roots)?;
+///
+/// let mut stream = connector.connect_tcp("api.example.com", 443)?;
+/// let response = Request::get("api.example.com", "/price").send(&mut stream)?;
+/// ```
+///
+/// The TA connects to the host directly with the GP TEE Sockets API, the REE
+/// only relays the encrypted records, and it authenticates the server with
+/// the roots it trusts.
+pub struct TlsConnector {
+    config: Arc<ClientConfig>,
+}
+
+impl TlsConnector {
+    /// Creates a connector whose connections use `config`.
+    pub fn new(config: Arc<ClientConfig>) -> Self {
+        Self { config }
+    }
+
+    /// Creates a connector authenticating the servers with `roots`, without
+    /// client authentication, and checking the validity of their
+    /// certificates with the [`ReeTimeProvider`].
+    pub fn with_root_store(provider: Arc<CryptoProvider>, roots: RootCertStore) -> Result<Self> {
+        let config = ree_time_builder(provider)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Self::new(Arc::new(config)))
+    }
+
+    /// The configuration of the connections.
+    pub fn config(&self) -> &Arc<ClientConfig> {
+        &self.config
+    }
+
+    /// Opens a TLS connection to `server_name` over `stream` and completes
+    /// the handshake.
+    ///
+    /// Fails with `BadParameters` if `server_name` is neither a DNS name nor
+    /// an IP address, and with `Security` if the server is not
+    /// authenticated.
+    pub fn connect<S: Read + Write>(
+        &self,
+        server_name: &str,
+        mut stream: S,
+    ) -> Result<TlsStream<S>> {
+        let name = ServerName::try_from(server_name.to_owned()).map_err(|_| {
+            Error::with_message(ErrorKind::BadParameters, "Invalid TLS server name")
+        })?;
+        let mut conn = ClientConnection::new(self.config.clone(), name).map_err(|_| {
+            Error::with_message(ErrorKind::Generic, "Failed to create the TLS connection")
+        })?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream).map_err(|e| match e.kind() {
+                std::io::ErrorKind::InvalidData => {
+                    Error::with_message(ErrorKind::Security, "TLS handshake failed")
+                }
+                _ => Error::with_message(
+                    ErrorKind::Communication,
+                    "TLS connection closed during the handshake",
+                ),
+            })?;
+        }
+        Ok(StreamOwned::new(conn, stream))
+    }
+
+    /// Connects to `host` on `port` with TCP and opens a TLS connection to
+    /// it, see [`connect`](Self::connect).
+    pub fn connect_tcp(&self, host: &str, port: u16) -> Result<TlsStream<TcpStream>> {
+        let stream = TcpStream::connect(host, port).map_err(|_| {
+            Error::with_message(
+                ErrorKind::Communication,
+                "Failed to connect to the TLS server",
+            )
+        })?;
+        self.connect(host, stream)
+    }
+}
+
+/// Provides rustls with the REE time, to check the validity period of the
+/// certificates of the servers.
+///
+/// The REE time is as trusted as the REE, which may set the clock back to
+/// have an expired certificate accepted.
+#[derive(Debug, Default)]
+pub struct ReeTimeProvider;
+
+impl TimeProvider for ReeTimeProvider {
+    fn current_time(&self) -> Option<UnixTime> {
+        Some(UnixTime::since_unix_epoch(
+            ReeTime::now().since_unix_epoch(),
+        ))
+    }
+}
+
+/// A client configuration builder of `provider` with the safe default
+/// protocol versions and the [`ReeTimeProvider`].
+fn ree_time_builder(
+    provider: Arc<CryptoProvider>,
+) -> Result<ConfigBuilder<ClientConfig, rustls::WantsVerifier>> {
+    ClientConfig::builder_with_details(provider, Arc::new(ReeTimeProvider))
+        .with_safe_default_protocol_versions()
+        .map_err(|_| {
+            Error::with_message(
+                ErrorKind::NotSupported,
+                "The crypto provider supports no safe TLS version",
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::CertificateDer;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::{ServerConfig, ServerConnection};
+
+    const CA_CERT: &[u8] = include_bytes!("../test_files/ca.cert");
+
+    /// A stream to an echo server, running in the same thread.
+    struct Loopback {
+        server: ServerConnection,
+    }
+
+    impl Read for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.server.write_tls(&mut &mut buf[..])
+        }
+    }
+
+    impl Write for Loopback {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let n = self.server.read_tls(&mut &buf[..])?;
+            self.server
+                .process_new_packets()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            let mut plaintext = Vec::new();
+            let _ = self.server.reader().read_to_end(&mut plaintext);
+            self.server.writer().write_all(&plaintext)?;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn loopback(config: Arc<ServerConfig>) -> Loopback {
+        Loopback {
+            server: ServerConnection::new(config).unwrap(),
+        }
+    }
+
+    fn connector() -> TlsConnector {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_slice(CA_CERT).unwrap())
+            .unwrap();
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        TlsConnector::new(Arc::new(config))
+    }
+
+    #[test]
+    fn test_connect() {
+        let connector = connector();
+        let server = crate::tests::server_config();
+        let mut stream = connector.connect("localhost", loopback(server)).unwrap();
+        assert!(!stream.conn.is_handshaking());
+
+        stream.write_all(b"hello").unwrap();
+        stream.flush().unwrap();
+        let mut echoed = [0u8; 5];
+        stream.read_exact(&mut echoed).unwrap();
+        assert_eq!(&echoed, b"hello");
+    }
+
+    #[test]
+    fn test_connect_errors() {
+        let connector = connector();
+        let server = crate::tests::server_config();
+        let err = connector
+            .connect("", loopback(server.clone()))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::BadParameters);
+
+        // The certificate of the server is not valid for this name
+        let err = connector
+            .connect("example.com", loopback(server))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::Security);
+    }
+}
@@ -0,0 +1,578 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! TLS session management for TAs terminating TLS connections, and TLS
+//! client connections of TAs with [`TlsConnector`].
+//!
+//! A TA cannot accept connections itself, so its client application accepts
+//! them and relays the TLS records between the peer and the TA, which holds
+//! the keys and sees the plaintext. For every connection, the client
+//! application:
+//!
+//! 1. picks a session ID and invokes `NewTlsSession`,
+//! 2. passes the records received from the peer with `DoTlsRead`,
+//! 3. sends the records returned by `DoTlsWrite` to the peer, until none are
+//!    left,
+//! 4. invokes `CloseTlsSession` once the peer is gone.
+//!
+//! [`TlsSessionManager`] keeps the rustls connections of these sessions,
+//! keyed by session ID, and implements the commands on top of the rustls
+//! [`ServerConfig`] given by the TA. The data buffered inside the TA is
+//! bounded by [`BufferLimits`], so that a misbehaving client application
+//! cannot exhaust the TA heap.
+//!
+//! # Parameters
+//!
+//! Parameter 0 of every command is a value input whose `a` is the session ID.
+//! Parameter 1 of `DoTlsRead` is a memref input with the received records,
+//! parameter 1 of `DoTlsWrite` a memref output receiving the records to send.
+//!
+//! # Examples
+//!
+//! An echo server, with the commands of its proto crate mapped to
+//! [`TlsCommand`]:
+//!
+//! ```rust,ignore
+//! use optee_tls::{TlsCommand, TlsSessionManager};
+//! use optee_utee::prelude::*;
+//! use std::sync::LazyLock;
+//!
+//! static TLS: LazyLock<TlsSessionManager> =
+//!     LazyLock::new(|| TlsSessionManager::new(make_config()));
+//!
+//! #[ta_invoke_command]
+//! fn invoke_command(cmd_id: u32, params: &mut ParametersAny<'_>) -> Result<()> {
+//!     let command = match Command::from(cmd_id) {
+//!         Command::NewTlsSession => TlsCommand::NewSession,
+//!         Command::CloseTlsSession => TlsCommand::CloseSession,
+//!         Command::DoTlsRead => TlsCommand::Read,
+//!         Command::DoTlsWrite => TlsCommand::Write,
+//!         _ => return Err(ErrorKind::BadParameters.into()),
+//!     };
+//!     TLS.invoke_command(command, params, |session| {
+//!         let request = session.read_plaintext()?;
+//!         session.write_plaintext(&request)
+//!     })
+//! }
+//! ```
+//!
+//! # Client connections
+//!
+//! A TA can also open TLS connections itself, over the GP TEE Sockets API,
+//! with a [`TlsConnector`] and the roots of trust it is given, e.g. to fetch
+//! data from an HTTPS endpoint:
+//!
+//! ```rust,ignore
+//! let connector = TlsConnector::with_root_store(provider, roots)?;
+//! let mut stream = connector.connect_tcp("api.example.com", 443)?;
+//! ```
+
+mod client;
+
+pub use client::{ReeTimeProvider, TlsConnector, TlsStream};
+
+use optee_utee::{
+    ErrorKind, ParameterMemrefRead, ParameterMemrefWrite, ParameterValueRead, ParametersAny, Result,
+};
+use rustls::{ServerConfig, ServerConnection};
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+type Error = optee_utee::Error;
+
+/// The commands of a TLS terminating TA, see the [crate documentation](crate).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlsCommand {
+    /// `NewTlsSession`: opens a session.
+    NewSession,
+    /// `CloseTlsSession`: closes a session.
+    CloseSession,
+    /// `DoTlsRead`: processes the records received from the peer.
+    Read,
+    /// `DoTlsWrite`: returns the records to send to the peer.
+    Write,
+}
+
+/// Bounds of the TLS data buffered inside the TA, in bytes.
+///
+/// The records passed with `DoTlsRead` are accounted to their session until
+/// `DoTlsWrite` has drained everything the session wants to send back. A
+/// session exceeding either bound is closed and the command fails with
+/// `OutOfMemory`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferLimits {
+    /// Limit of a single session, also applied to the buffers of rustls.
+    pub per_session: usize,
+    /// Limit of all the sessions together.
+    pub total: usize,
+}
+
+impl Default for BufferLimits {
+    /// 64 KiB per session and 1 MiB in total, to fit in the `ta_data_size`
+    /// of a typical TA.
+    fn default() -> Self {
+        Self {
+            per_session: 64 * 1024,
+            total: 1024 * 1024,
+        }
+    }
+}
+
+/// The TLS sessions of a TA, keyed by the session IDs chosen by the client
+/// application.
+///
+/// Sessions are independent: commands of different sessions run
+/// concurrently in a multi-session TA.
+pub struct TlsSessionManager {
+    config: Arc<ServerConfig>,
+    limits: BufferLimits,
+    sessions: RwLock<HashMap<u32, Mutex<TlsContext>>>,
+    total_buffered: Arc<AtomicUsize>,
+}
+
+impl TlsSessionManager {
+    /// Creates a manager whose sessions use `config`, with the default
+    /// [`BufferLimits`].
+    pub fn new(config: Arc<ServerConfig>) -> Self {
+        Self::with_limits(config, BufferLimits::default())
+    }
+
+    /// Creates a manager whose sessions use `config` and buffer at most
+    /// `limits`.
+    pub fn with_limits(config: Arc<ServerConfig>, limits: BufferLimits) -> Self {
+        Self {
+            config,
+            limits,
+            sessions: RwLock::new(HashMap::new()),
+            total_buffered: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The number of open sessions.
+    pub fn session_count(&self) -> usize {
+        self.sessions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// The number of bytes buffered by all the sessions.
+    pub fn buffered(&self) -> usize {
+        self.total_buffered.load(Ordering::SeqCst)
+    }
+
+    /// Runs `command` with `params`, see the [crate documentation](crate).
+    ///
+    /// `on_plaintext` is called by `DoTlsRead`, see
+    /// [`read_tls`](Self::read_tls).
+    pub fn invoke_command(
+        &self,
+        command: TlsCommand,
+        params: &mut ParametersAny<'_>,
+        on_plaintext: impl FnOnce(&mut TlsContext) -> Result<()>,
+    ) -> Result<()> {
+        let session_id = params.0.as_value_input()?.get_a();
+        match command {
+            TlsCommand::NewSession => self.open(session_id),
+            TlsCommand::CloseSession => self.close(session_id),
+            TlsCommand::Read => {
+                let records = params.1.as_memref_input()?.get_buffer();
+                self.read_tls(session_id, records, on_plaintext)
+            }
+            TlsCommand::Write => {
+                let output = params.1.as_memref_output()?;
+                let len = self.write_tls(session_id, output.get_buffer_mut())?;
+                output.set_updated_size(len)
+            }
+        }
+    }
+
+    /// Opens the session `session_id`, failing with `AccessConflict` if it is
+    /// already open.
+    pub fn open(&self, session_id: u32) -> Result<()> {
+        let mut conn = ServerConnection::new(self.config.clone()).map_err(|_| {
+            Error::with_message(ErrorKind::Generic, "Failed to create the TLS connection")
+        })?;
+        // Also bound the buffers kept by rustls itself
+        conn.set_buffer_limit(Some(self.limits.per_session));
+
+        let mut sessions = self
+            .sessions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if sessions.contains_key(&session_id) {
+            return Err(Error::with_message(
+                ErrorKind::AccessConflict,
+                "TLS session already open",
+            ));
+        }
+        let context = TlsContext {
+            session_id,
+            conn,
+            buffered: 0,
+            per_session: self.limits.per_session,
+            total: self.limits.total,
+            total_buffered: self.total_buffered.clone(),
+        };
+        sessions.insert(session_id, Mutex::new(context));
+        Ok(())
+    }
+
+    /// Closes the session `session_id`, freeing its buffers.
+    pub fn close(&self, session_id: u32) -> Result<()> {
+        self.sessions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&session_id)
+            .map(|_| ())
+            .ok_or_else(not_found)
+    }
+
+    /// Runs `f` with the session `session_id`, failing with `ItemNotFound` if
+    /// it is not open.
+    pub fn with_session<T>(
+        &self,
+        session_id: u32,
+        f: impl FnOnce(&mut TlsContext) -> Result<T>,
+    ) -> Result<T> {
+        let sessions = self.sessions.read().unwrap_or_else(PoisonError::into_inner);
+        let session = sessions.get(&session_id).ok_or_else(not_found)?;
+        f(&mut lock(session))
+    }
+
+    /// Processes the `records` received from the peer, then calls
+    /// `on_plaintext` to consume the plaintext they carried and queue a
+    /// response.
+    ///
+    /// The session is closed if it exceeds its [`BufferLimits`]. After any
+    /// other failure it stays open, so the alert queued by rustls can be sent
+    /// with [`write_tls`](Self::write_tls).
+    pub fn read_tls(
+        &self,
+        session_id: u32,
+        records: &[u8],
+        on_plaintext: impl FnOnce(&mut TlsContext) -> Result<()>,
+    ) -> Result<()> {
+        let result = self.with_session(session_id, |session| {
+            session.reserve(records.len())?;
+            // Reading from memory only fails when the buffers of rustls are
+            // full, the same backpressure as the limits
+            session
+                .conn
+                .read_tls(&mut Cursor::new(records))
+                .map_err(|_| overloaded("TLS session exceeded the rustls buffer limit"))?;
+            session
+                .conn
+                .process_new_packets()
+                .map_err(|_| Error::with_message(ErrorKind::Security, "Invalid TLS records"))?;
+            on_plaintext(session)
+        });
+        if result
+            .as_ref()
+            .is_err_and(|e| e.kind() == ErrorKind::OutOfMemory)
+        {
+            let _ = self.close(session_id);
+        }
+        result
+    }
+
+    /// Writes the records to send to the peer into `buf`, returning their
+    /// length. What does not fit is returned by the next call.
+    pub fn write_tls(&self, session_id: u32, buf: &mut [u8]) -> Result<usize> {
+        self.with_session(session_id, |session| {
+            let mut output = Cursor::new(buf);
+            let mut len = 0;
+            while session.conn.wants_write() {
+                let n = session.conn.write_tls(&mut output).map_err(|_| {
+                    Error::with_message(ErrorKind::Generic, "Failed to write the TLS records")
+                })?;
+                if n == 0 {
+                    // The output buffer is full
+                    break;
+                }
+                len += n;
+            }
+            if !session.conn.wants_write() {
+                session.release();
+            }
+            Ok(len)
+        })
+    }
+}
+
+/// A TLS session of a [`TlsSessionManager`].
+pub struct TlsContext {
+    session_id: u32,
+    conn: ServerConnection,
+    buffered: usize,
+    per_session: usize,
+    total: usize,
+    total_buffered: Arc<AtomicUsize>,
+}
+
+impl TlsContext {
+    /// The ID of the session, chosen by the client application.
+    pub fn session_id(&self) -> u32 {
+        self.session_id
+    }
+
+    /// The rustls connection of the session, e.g. to get the SNI or the
+    /// negotiated protocol.
+    pub fn connection(&self) -> &ServerConnection {
+        &self.conn
+    }
+
+    /// The rustls connection of the session.
+    pub fn connection_mut(&mut self) -> &mut ServerConnection {
+        &mut self.conn
+    }
+
+    /// Whether the handshake is still in progress, in which case there is no
+    /// plaintext to read yet.
+    pub fn is_handshaking(&self) -> bool {
+        self.conn.is_handshaking()
+    }
+
+    /// Returns the plaintext received from the peer and not read yet, empty
+    /// if there is none.
+    pub fn read_plaintext(&mut self) -> Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        match self.conn.reader().read_to_end(&mut plaintext) {
+            // The peer sent close_notify
+            Ok(_) => Ok(plaintext),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(plaintext),
+            Err(_) => Err(Error::with_message(
+                ErrorKind::Communication,
+                "TLS peer closed the connection without close_notify",
+            )),
+        }
+    }
+
+    /// Queues `plaintext` to be sent to the peer with `DoTlsWrite`.
+    pub fn write_plaintext(&mut self, plaintext: &[u8]) -> Result<()> {
+        self.conn
+            .writer()
+            .write_all(plaintext)
+            .map_err(|_| overloaded("TLS session exceeded its buffer limit"))
+    }
+
+    /// Accounts `len` more bytes to this session, failing if either limit
+    /// would be exceeded.
+    fn reserve(&mut self, len: usize) -> Result<()> {
+        if self.buffered + len > self.per_session {
+            return Err(overloaded("TLS session exceeded its buffer limit"));
+        }
+        let total = self.total;
+        self.total_buffered
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |buffered| {
+                buffered
+                    .checked_add(len)
+                    .filter(|buffered| *buffered <= total)
+            })
+            .map_err(|_| overloaded("TLS sessions exceeded the total buffer limit"))?;
+        self.buffered += len;
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        self.total_buffered
+            .fetch_sub(self.buffered, Ordering::SeqCst);
+        self.buffered = 0;
+    }
+}
+
+impl Drop for TlsContext {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+fn not_found() -> Error {
+    Error::with_message(ErrorKind::ItemNotFound, "TLS session not found")
+}
+
+fn overloaded(message: &'static str) -> Error {
+    Error::with_message(ErrorKind::OutOfMemory, message)
+}
+
+/// Lock `mutex`, ignoring poisoning: TAs abort on panic, a poisoned lock
+/// is only seen by host tests.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use rustls::{ClientConfig, ClientConnection, RootCertStore};
+
+    const CA_CERT: &[u8] = include_bytes!("../test_files/ca.cert");
+    const SERVER_CERTS: &[u8] = include_bytes!("../test_files/end.fullchain");
+    const SERVER_KEY: &[u8] = include_bytes!("../test_files/end.key");
+
+    pub(crate) fn server_config() -> Arc<ServerConfig> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let certs = CertificateDer::pem_slice_iter(SERVER_CERTS)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_slice(SERVER_KEY).unwrap();
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        Arc::new(config)
+    }
+
+    fn client() -> ClientConnection {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_slice(CA_CERT).unwrap())
+            .unwrap();
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap()).unwrap()
+    }
+
+    fn echo(session: &mut TlsContext) -> Result<()> {
+        let plaintext = session.read_plaintext()?;
+        session.write_plaintext(&plaintext)
+    }
+
+    /// Relays the records between `client` and the session, as the client
+    /// application does, until neither side has anything to send.
+    fn relay(manager: &TlsSessionManager, session_id: u32, client: &mut ClientConnection) {
+        // Smaller than a handshake flight, to also cover partial writes
+        let mut buf = [0u8; 1024];
+        loop {
+            let mut records = Vec::new();
+            while client.wants_write() {
+                client.write_tls(&mut records).unwrap();
+            }
+            if !records.is_empty() {
+                manager.read_tls(session_id, &records, echo).unwrap();
+            }
+            let len = manager.write_tls(session_id, &mut buf).unwrap();
+            if len > 0 {
+                client.read_tls(&mut &buf[..len]).unwrap();
+                client.process_new_packets().unwrap();
+            }
+            if records.is_empty() && len == 0 {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_echo() {
+        let manager = TlsSessionManager::new(server_config());
+        manager.open(1).unwrap();
+        manager.open(2).unwrap();
+        assert_eq!(manager.session_count(), 2);
+
+        let mut client = client();
+        relay(&manager, 1, &mut client);
+        assert!(!client.is_handshaking());
+        manager
+            .with_session(1, |session| {
+                assert_eq!(session.session_id(), 1);
+                assert!(!session.is_handshaking());
+                Ok(())
+            })
+            .unwrap();
+
+        client.writer().write_all(b"hello").unwrap();
+        relay(&manager, 1, &mut client);
+        let mut echoed = [0u8; 5];
+        client.reader().read_exact(&mut echoed).unwrap();
+        assert_eq!(&echoed, b"hello");
+        assert_eq!(manager.buffered(), 0);
+
+        manager.close(1).unwrap();
+        assert_eq!(manager.session_count(), 1);
+    }
+
+    #[test]
+    fn test_sessions() {
+        let manager = TlsSessionManager::new(server_config());
+        manager.open(1).unwrap();
+        assert_eq!(
+            manager.open(1).unwrap_err().kind(),
+            ErrorKind::AccessConflict
+        );
+        assert_eq!(
+            manager.close(2).unwrap_err().kind(),
+            ErrorKind::ItemNotFound
+        );
+        assert_eq!(
+            manager.write_tls(2, &mut [0u8; 16]).unwrap_err().kind(),
+            ErrorKind::ItemNotFound
+        );
+
+        // Garbage is rejected, and the session kept to send the alert
+        let err = manager.read_tls(1, &[0xff; 16], echo).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Security);
+        assert!(manager.write_tls(1, &mut [0u8; 64]).unwrap() > 0);
+        assert_eq!(manager.session_count(), 1);
+    }
+
+    #[test]
+    fn test_buffer_limits() {
+        let limits = BufferLimits {
+            per_session: 4096,
+            total: 4096 + 64,
+        };
+        let manager = TlsSessionManager::with_limits(server_config(), limits);
+        for session_id in 0..3 {
+            manager.open(session_id).unwrap();
+        }
+        let mut client = client();
+        let mut hello = Vec::new();
+        client.write_tls(&mut hello).unwrap();
+
+        // Session 0 keeps its records buffered as its response is not drained
+        manager.read_tls(0, &hello, echo).unwrap();
+        assert_eq!(manager.buffered(), hello.len());
+
+        // Over the session limit
+        let err = manager.read_tls(1, &[0u8; 4097], echo).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+        assert_eq!(
+            manager.close(1).unwrap_err().kind(),
+            ErrorKind::ItemNotFound
+        );
+
+        // Over the total limit
+        let err = manager.read_tls(2, &[0u8; 4096], echo).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+        assert_eq!(manager.session_count(), 1);
+        assert_eq!(manager.buffered(), hello.len());
+
+        // Released once drained
+        manager.write_tls(0, &mut [0u8; 8192]).unwrap();
+        assert_eq!(manager.buffered(), 0);
+    }
+}
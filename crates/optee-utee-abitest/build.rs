@@ -0,0 +1,268 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+/// A type whose layout is compared between optee-utee-sys and the headers.
+struct Layout {
+    /// Name of the type in optee-utee-sys.
+    rust: &'static str,
+    /// Name of the type in the TA dev kit headers.
+    c: &'static str,
+    /// Whether size and alignment are compared. Types ending with a flexible
+    /// array member in C are represented with a pointer in Rust, so only
+    /// their field offsets can match.
+    sized: bool,
+    /// `(Rust field, C field)` pairs whose offsets are compared, nested
+    /// fields are separated by `.`. Private Rust fields cannot be checked.
+    fields: &'static [(&'static str, &'static str)],
+}
+
+/// Headers declaring the types in [`LAYOUTS`].
+const HEADERS: [&str; 3] = ["tee_api_types.h", "utee_types.h", "user_ta_header.h"];
+
+const LAYOUTS: &[Layout] = &[
+    Layout {
+        rust: "TEE_UUID",
+        c: "TEE_UUID",
+        sized: true,
+        fields: &[
+            ("timeLow", "timeLow"),
+            ("timeMid", "timeMid"),
+            ("timeHiAndVersion", "timeHiAndVersion"),
+            ("clockSeqAndNode", "clockSeqAndNode"),
+        ],
+    },
+    Layout {
+        rust: "TEE_Identity",
+        c: "TEE_Identity",
+        sized: true,
+        fields: &[("login", "login"), ("uuid", "uuid")],
+    },
+    Layout {
+        rust: "TEE_Param",
+        c: "TEE_Param",
+        sized: true,
+        fields: &[
+            ("memref.buffer", "memref.buffer"),
+            ("memref.size", "memref.size"),
+            ("value.a", "value.a"),
+            ("value.b", "value.b"),
+        ],
+    },
+    Layout {
+        rust: "TEE_ObjectInfo",
+        c: "TEE_ObjectInfo",
+        sized: true,
+        fields: &[
+            ("objectType", "objectType"),
+            ("objectSize", "objectSize"),
+            ("maxObjectSize", "maxObjectSize"),
+            ("objectUsage", "objectUsage"),
+            ("dataSize", "dataSize"),
+            ("dataPosition", "dataPosition"),
+            ("handleFlags", "handleFlags"),
+        ],
+    },
+    Layout {
+        rust: "TEE_Attribute",
+        c: "TEE_Attribute",
+        sized: true,
+        fields: &[
+            ("attributeID", "attributeID"),
+            ("content.memref.buffer", "content.ref.buffer"),
+            ("content.memref.size", "content.ref.length"),
+            ("content.value.a", "content.value.a"),
+            ("content.value.b", "content.value.b"),
+        ],
+    },
+    Layout {
+        rust: "TEE_OperationInfo",
+        c: "TEE_OperationInfo",
+        sized: true,
+        fields: &[
+            ("algorithm", "algorithm"),
+            ("operationClass", "operationClass"),
+            ("mode", "mode"),
+            ("digestLength", "digestLength"),
+            ("maxKeySize", "maxKeySize"),
+            ("keySize", "keySize"),
+            ("requiredKeyUsage", "requiredKeyUsage"),
+            ("handleState", "handleState"),
+        ],
+    },
+    Layout {
+        rust: "TEE_OperationInfoKey",
+        c: "TEE_OperationInfoKey",
+        sized: true,
+        fields: &[
+            ("keySize", "keySize"),
+            ("requiredKeyUsage", "requiredKeyUsage"),
+        ],
+    },
+    Layout {
+        rust: "TEE_OperationInfoMultiple",
+        c: "TEE_OperationInfoMultiple",
+        sized: false,
+        fields: &[
+            ("algorithm", "algorithm"),
+            ("operationClass", "operationClass"),
+            ("mode", "mode"),
+            ("digestLength", "digestLength"),
+            ("maxKeySize", "maxKeySize"),
+            ("handleState", "handleState"),
+            ("operationState", "operationState"),
+            ("numberOfKeys", "numberOfKeys"),
+            ("keyInformation", "keyInformation"),
+        ],
+    },
+    Layout {
+        rust: "TEE_Time",
+        c: "TEE_Time",
+        sized: true,
+        fields: &[("seconds", "seconds"), ("millis", "millis")],
+    },
+    Layout {
+        rust: "utee_params",
+        c: "struct utee_params",
+        sized: true,
+        fields: &[],
+    },
+    Layout {
+        rust: "utee_attribute",
+        c: "struct utee_attribute",
+        sized: true,
+        fields: &[],
+    },
+    Layout {
+        rust: "utee_object_info",
+        c: "struct utee_object_info",
+        sized: true,
+        fields: &[],
+    },
+    Layout {
+        rust: "ta_head",
+        c: "struct ta_head",
+        sized: true,
+        fields: &[
+            ("uuid", "uuid"),
+            ("stack_size", "stack_size"),
+            ("flags", "flags"),
+            ("depr_entry", "depr_entry"),
+        ],
+    },
+    Layout {
+        rust: "user_ta_property",
+        c: "struct user_ta_property",
+        sized: true,
+        fields: &[("name", "name"), ("prop_type", "type"), ("value", "value")],
+    },
+];
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(dev_kit)");
+    println!("cargo:rerun-if-env-changed=TA_DEV_KIT_DIR");
+
+    // The tests are optional, so that the workspace still builds and tests
+    // without a dev kit.
+    let Ok(ta_dev_kit_dir) = env::var("TA_DEV_KIT_DIR") else {
+        println!("cargo:warning=TA_DEV_KIT_DIR not set, skipping ABI conformance tests");
+        return;
+    };
+    let include_path = PathBuf::from(ta_dev_kit_dir).join("include");
+    if !include_path.exists() {
+        panic!(
+            "TA_DEV_KIT_DIR include path {} does not exist",
+            include_path.display()
+        );
+    }
+
+    for header in HEADERS {
+        println!(
+            "cargo:rerun-if-changed={}",
+            include_path.join(header).display()
+        );
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let (c_source, rust_source) = generate();
+    let shim_path = out_dir.join("layouts.c");
+    fs::write(&shim_path, c_source).unwrap();
+    fs::write(out_dir.join("layouts.rs"), rust_source).unwrap();
+
+    cc::Build::new()
+        .include(&include_path)
+        .file(&shim_path)
+        .compile("layouts");
+
+    println!("cargo:rustc-cfg=dev_kit");
+}
+
+/// Generate the C shim capturing `sizeof`/`offsetof` from the headers, and the
+/// Rust table computing the same values from optee-utee-sys, in the same order.
+fn generate() -> (String, String) {
+    let mut c_values = String::new();
+    let mut rust_values = String::new();
+    let mut count = 0;
+    let mut push = |name: String, c: String, rust: String| {
+        writeln!(c_values, "    {},", c).unwrap();
+        writeln!(rust_values, "    ({:?}, {}),", name, rust).unwrap();
+        count += 1;
+    };
+
+    for layout in LAYOUTS {
+        if layout.sized {
+            push(
+                format!("sizeof({})", layout.c),
+                format!("sizeof({})", layout.c),
+                format!("core::mem::size_of::<{}>()", layout.rust),
+            );
+            push(
+                format!("alignof({})", layout.c),
+                format!("_Alignof({})", layout.c),
+                format!("core::mem::align_of::<{}>()", layout.rust),
+            );
+        }
+        for (rust_field, c_field) in layout.fields {
+            push(
+                format!("offsetof({}, {})", layout.c, c_field),
+                format!("offsetof({}, {})", layout.c, c_field),
+                format!("core::mem::offset_of!({}, {})", layout.rust, rust_field),
+            );
+        }
+    }
+
+    let includes: String = HEADERS
+        .iter()
+        .map(|header| format!("#include <{}>\n", header))
+        .collect();
+    let c_source = format!(
+        "#include <stddef.h>\n\
+         {includes}\n\
+         const size_t abitest_layouts[{count}] = {{\n{c_values}}};\n"
+    );
+    let rust_source = format!(
+        "unsafe extern \"C\" {{\n    \
+             static abitest_layouts: [usize; {count}];\n\
+         }}\n\
+         \n\
+         const RUST_LAYOUTS: [(&str, usize); {count}] = [\n{rust_values}];\n"
+    );
+    (c_source, rust_source)
+}
@@ -0,0 +1,50 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Compares the size, alignment and field offsets of the optee-utee-sys
+//! types with the C definitions in the TA dev kit headers, to catch struct
+//! drift between OP-TEE releases.
+//!
+//! The tests only run when `TA_DEV_KIT_DIR` is set at build time. The C shim
+//! is compiled for the same target as the tests, so run them natively or on
+//! the target:
+//!
+//! ```sh
+//! TA_DEV_KIT_DIR=<path> cargo test -p optee-utee-abitest
+//! ```
+
+#[cfg(all(test, dev_kit))]
+mod tests {
+    use optee_utee_sys::*;
+
+    include!(concat!(env!("OUT_DIR"), "/layouts.rs"));
+
+    #[test]
+    fn test_layouts_match_dev_kit() {
+        let c_layouts = unsafe { abitest_layouts };
+        let mismatches: Vec<String> = RUST_LAYOUTS
+            .iter()
+            .zip(c_layouts)
+            .filter(|((_, rust), c)| rust != c)
+            .map(|((name, rust), c)| format!("{}: Rust {} != C {}", name, rust, c))
+            .collect();
+        assert!(
+            mismatches.is_empty(),
+            "optee-utee-sys does not match the dev kit headers:\n{}",
+            mismatches.join("\n")
+        );
+    }
+}
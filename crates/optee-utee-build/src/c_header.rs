@@ -0,0 +1,187 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use crate::Error;
+use optee_proto::Protocol;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Generator of a C header with the UUID and the command IDs of a TA, for
+/// client applications written in C, e.g. Android HALs.
+///
+/// The header follows the layout of the TA headers of the OP-TEE C examples:
+/// a TA named `hello_world` gets `TA_HELLO_WORLD_UUID`, usable as a
+/// `TEEC_UUID` initializer, and a `TA_HELLO_WORLD_CMD_*` macro per command.
+///
+/// It is typically generated by the build script of the TA, from the `proto`
+/// crate shared with the Rust client application:
+///
+/// ```rust,no_run
+/// use optee_utee_build::{CHeaderGenerator, Error};
+/// # mod proto {
+/// #     #[derive(optee_proto::OpteeProtocol, Clone, Copy)]
+/// #     #[repr(u32)]
+/// #     pub enum Command { IncValue = 0, DecValue = 1 }
+/// #     pub const UUID: &str = "26509cec-4a2b-4935-87ab-762d89fbf0b0";
+/// # }
+/// # fn main() -> Result<(), Error> {
+/// CHeaderGenerator::new("hello_world", proto::UUID)?
+///     .protocol::<proto::Command>()
+///     .write("../include/hello_world_ta.h")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Commands of enums not deriving `OpteeProtocol` are added one by one with
+/// [`command`](Self::command).
+pub struct CHeaderGenerator {
+    name: String,
+    uuid: uuid::Uuid,
+    commands: Vec<(String, u32)>,
+}
+
+impl CHeaderGenerator {
+    /// Creates a generator for the TA called `name` with `uuid`.
+    pub fn new(name: &str, uuid: &str) -> Result<Self, Error> {
+        Ok(Self {
+            name: name.to_string(),
+            uuid: uuid.try_into()?,
+            commands: Vec::new(),
+        })
+    }
+
+    /// Adds the command `name` with ID `id`, the name is converted to upper
+    /// snake case.
+    pub fn command(mut self, name: &str, id: u32) -> Self {
+        self.commands.push((name.to_string(), id));
+        self
+    }
+
+    /// Adds all the commands of the protocol `P`.
+    pub fn protocol<P: Protocol>(mut self) -> Self {
+        for command in P::COMMANDS {
+            self = self.command(command.name(), command.id());
+        }
+        self
+    }
+
+    /// Returns the content of the header.
+    pub fn generate(&self) -> String {
+        let prefix = format!("TA_{}", upper_snake_case(&self.name));
+        let (time_low, time_mid, time_hi_and_version, clock_seq_and_node) = self.uuid.as_fields();
+        let clock_seq_and_node = clock_seq_and_node
+            .iter()
+            .map(|b| format!("0x{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut header = String::new();
+        header.push_str("/* Generated by optee-utee-build, do not edit. */\n\n");
+        let _ = writeln!(header, "#ifndef {}_H", prefix);
+        let _ = writeln!(header, "#define {}_H\n", prefix);
+        let _ = writeln!(
+            header,
+            "#define {}_UUID \\\n\t{{ 0x{:08x}, 0x{:04x}, 0x{:04x}, \\\n\t\t{{ {} }} }}\n",
+            prefix, time_low, time_mid, time_hi_and_version, clock_seq_and_node
+        );
+        for (name, id) in &self.commands {
+            let _ = writeln!(
+                header,
+                "#define {}_CMD_{} {}",
+                prefix,
+                upper_snake_case(name),
+                id
+            );
+        }
+        if !self.commands.is_empty() {
+            header.push('\n');
+        }
+        let _ = writeln!(header, "#endif /* {}_H */", prefix);
+        header
+    }
+
+    /// Writes the header to `path`, leaving the file untouched if it is up to
+    /// date so the client application is not rebuilt needlessly.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let header = self.generate();
+        if std::fs::read(path).is_ok_and(|current| current == header.as_bytes()) {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, header)?;
+        Ok(())
+    }
+}
+
+/// Converts `IncValue`, `incValue` or `inc_value` to `INC_VALUE`.
+fn upper_snake_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !result.is_empty() && !result.ends_with('_') {
+                result.push('_');
+            }
+        } else {
+            if c.is_ascii_uppercase()
+                && previous.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+            {
+                result.push('_');
+            }
+            result.push(c.to_ascii_uppercase());
+        }
+        previous = Some(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use optee_proto::OpteeProtocol;
+
+    #[derive(OpteeProtocol, Clone, Copy)]
+    #[repr(u32)]
+    enum Command {
+        IncValue = 0,
+        DecValue = 1,
+        GetRandom2 = 0x10,
+    }
+
+    #[test]
+    fn test_upper_snake_case() {
+        assert_eq!(upper_snake_case("IncValue"), "INC_VALUE");
+        assert_eq!(upper_snake_case("hello_world"), "HELLO_WORLD");
+        assert_eq!(upper_snake_case("hello-world"), "HELLO_WORLD");
+        assert_eq!(upper_snake_case("AES"), "AES");
+        assert_eq!(upper_snake_case("Sha256Update"), "SHA256_UPDATE");
+    }
+
+    #[test]
+    fn test_c_header_generation() {
+        let header = CHeaderGenerator::new("hello_world", "8aaaf200-2450-11e4-abe2-0002a5d5c51b")
+            .unwrap()
+            .protocol::<Command>()
+            .command("Reset", 0x20)
+            .generate();
+        let exp_result = include_str!("../test_files/test_result.h");
+        assert_eq!(header, exp_result);
+    }
+}
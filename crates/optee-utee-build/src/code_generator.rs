@@ -45,22 +45,30 @@ impl HeaderFileGenerator {
     pub fn new() -> Self {
         Default::default()
     }
-    pub fn generate(mut self, conf: &TaConfig) -> Result<String, Error> {
-        self.write_includes();
-        self.write_configurations(conf);
-        self.write_trace(conf);
-        self.write_properties(conf)?;
-        self.write_ta_head(conf)?;
-        self.write_ta_heap();
+    pub fn generate(self, conf: &TaConfig) -> Result<String, Error> {
+        let code = self.generate_tokens(conf)?;
 
         const LICENSE_STR: &str = include_str!("./license_str.txt");
-        let f = syn::parse2(self.code).unwrap();
+        let f = syn::parse2(code).unwrap();
         // prettyplease will remove all of the comments in it, and the
         // maintainer will not support keeping comments,
         // so we just add the comments to codes after formatting
         let code_string = format!("{}\n{}", LICENSE_STR, prettyplease::unparse(&f));
         Ok(code_string)
     }
+
+    /// Generates the header as tokens, for procedural macros like
+    /// `optee_utee::ta_config` which expand to it instead of writing a file.
+    pub fn generate_tokens(mut self, conf: &TaConfig) -> Result<proc_macro2::TokenStream, Error> {
+        conf.validate()?;
+        self.write_includes();
+        self.write_configurations(conf);
+        self.write_trace(conf);
+        self.write_properties(conf)?;
+        self.write_ta_head(conf)?;
+        self.write_ta_heap();
+        Ok(self.code)
+    }
 }
 
 impl HeaderFileGenerator {
@@ -323,8 +331,10 @@ fn property_value_utee_type_codes(value: &PropertyValue) -> proc_macro2::TokenSt
 }
 
 fn string_to_binary_codes(s: &str) -> proc_macro2::TokenStream {
-    let wrapped = format!("b\"{}\\0\"", s);
-    proc_macro2::TokenStream::from_str(&wrapped).unwrap()
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.push(0);
+    let literal = proc_macro2::Literal::byte_string(&bytes);
+    quote! { #literal }
 }
 
 fn no_mangle_attribute_codes() -> proc_macro2::TokenStream {
@@ -337,6 +347,7 @@ fn link_section_attribute_codes(session_name: &str) -> proc_macro2::TokenStream
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::TA_FLAG_MULTI_SESSION;
 
     #[test]
     fn test_header_generation() {
@@ -347,4 +358,69 @@ mod tests {
         let exp_result = include_str!("../test_files/test_result.rs");
         assert_eq!(codes, exp_result);
     }
+
+    #[test]
+    fn test_string_escaping() {
+        let uuid = "26509cec-4a2b-4935-87ab-762d89fbf0b0";
+        let conf = TaConfig::new_default(uuid, "0.1.0", "a \"quoted\" \\ description").unwrap();
+        let codes = HeaderFileGenerator::new().generate(&conf).unwrap();
+        assert!(codes.contains(r#"b"a \"quoted\" \\ description\0""#));
+    }
+
+    #[test]
+    fn test_session_flags() {
+        let uuid = "26509cec-4a2b-4935-87ab-762d89fbf0b0";
+        let conf = TaConfig::new_default(uuid, "0.1.0", "test").unwrap();
+        assert_eq!(conf.clone().single_instance().ta_flags, 1 << 2);
+        assert_eq!(conf.clone().multi_session().ta_flags, (1 << 2) | (1 << 3));
+        assert_eq!(conf.instance_keep_alive().ta_flags, (1 << 2) | (1 << 4));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let uuid = "26509cec-4a2b-4935-87ab-762d89fbf0b0";
+        let conf = TaConfig::new_default(uuid, "0.1.0", "test").unwrap();
+        for invalid in [
+            conf.clone().ta_data_size(0),
+            conf.clone().ta_stack_size(0),
+            conf.clone().ta_framework_stack_size(1024),
+            conf.clone().ta_stack_size(u32::MAX),
+            conf.clone().trace_ext_prefix("T\0A"),
+            conf.clone().ta_flags(TA_FLAG_MULTI_SESSION),
+            conf.clone().add_ext_property("", 1u32),
+            conf.clone().add_ext_property("gpd.ta.version", "1.0"),
+            conf.clone()
+                .add_ext_property("org.example.a", 1u32)
+                .add_ext_property("org.example.a", true),
+            conf.clone().add_ext_property(
+                "org.example.b",
+                PropertyValue::BinaryBlock("abc".to_string()),
+            ),
+        ] {
+            assert!(matches!(
+                HeaderFileGenerator::new().generate_tokens(&invalid),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_ext_properties() {
+        let uuid = "26509cec-4a2b-4935-87ab-762d89fbf0b0";
+        let conf = TaConfig::new_default(uuid, "0.1.0", "test")
+            .unwrap()
+            .add_ext_property("org.example.max_keys", 16u32)
+            .add_ext_property("org.example.vendor", "example")
+            .add_ext_property(
+                "org.example.blob",
+                PropertyValue::BinaryBlock("aGk=".to_string()),
+            );
+        let tokens = HeaderFileGenerator::new()
+            .generate_tokens(&conf)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("ta_num_props : usize = 13usize"));
+        assert!(tokens.contains("const EXT_PROP_VALUE_1 : u32 = 16u32"));
+        assert!(tokens.contains("USER_TA_PROP_TYPE_BINARY_BLOCK"));
+    }
 }
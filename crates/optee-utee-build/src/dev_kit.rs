@@ -0,0 +1,167 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::Error;
+
+/// The oldest release of OP-TEE OS whose TA dev kit the SDK supports, equal
+/// to `optee_utee_sys::OPTEE_OS_VERSION_MIN`.
+pub const OPTEE_OS_VERSION_MIN: DevKitVersion = DevKitVersion::new(4, 0);
+/// The newest release of OP-TEE OS whose TA dev kit the SDK supports, equal
+/// to `optee_utee_sys::OPTEE_OS_VERSION_MAX`.
+pub const OPTEE_OS_VERSION_MAX: DevKitVersion = DevKitVersion::new(4, 10);
+
+/// Set to skip the check of the version of the TA dev kit, e.g. to try a
+/// release of OP-TEE newer than the SDK.
+const ENV_SKIP_VERSION_CHECK: &str = "TA_DEV_KIT_SKIP_VERSION_CHECK";
+
+/// The release of OP-TEE OS a TA dev kit was exported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DevKitVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DevKitVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    /// Read the version of the TA dev kit in `ta_dev_kit_dir` from the
+    /// `CFG_OPTEE_REVISION_*` of `host_include/conf.h`, or of `mk/conf.mk`
+    /// for dev kits without it. Returns `None` if neither defines them.
+    pub fn read<P: AsRef<Path>>(ta_dev_kit_dir: P) -> Result<Option<Self>, Error> {
+        let ta_dev_kit_dir = ta_dev_kit_dir.as_ref();
+        for file in ["host_include/conf.h", "mk/conf.mk"] {
+            let path = ta_dev_kit_dir.join(file);
+            if !path.exists() {
+                continue;
+            }
+            println!("cargo:rerun-if-changed={}", path.display());
+            if let Some(version) = Self::parse(&std::fs::read_to_string(path)?) {
+                return Ok(Some(version));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Parse the version from the `#define`s of a `conf.h` or the
+    /// assignments of a `conf.mk`.
+    fn parse(conf: &str) -> Option<Self> {
+        let value = |name: &str| {
+            conf.lines().find_map(|line| {
+                let mut words = line
+                    .split(|c: char| c.is_whitespace() || ":?=".contains(c))
+                    .filter(|word| !word.is_empty() && *word != "#define");
+                match words.next() {
+                    Some(word) if word == name => words.next()?.parse::<u32>().ok(),
+                    _ => None,
+                }
+            })
+        };
+        Some(Self::new(
+            value("CFG_OPTEE_REVISION_MAJOR")?,
+            value("CFG_OPTEE_REVISION_MINOR")?,
+        ))
+    }
+
+    /// Fails with `Error::UnsupportedDevKit` if the SDK does not support
+    /// this release.
+    pub fn check(self) -> Result<(), Error> {
+        if (OPTEE_OS_VERSION_MIN..=OPTEE_OS_VERSION_MAX).contains(&self) {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedDevKit(self))
+        }
+    }
+}
+
+impl fmt::Display for DevKitVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Check that the SDK supports the release of OP-TEE the TA dev kit in
+/// `ta_dev_kit_dir` comes from, so that a mismatch fails the build here
+/// rather than with errors of the linker or of the ABI later.
+///
+/// The check is skipped if `TA_DEV_KIT_SKIP_VERSION_CHECK` is set, and only
+/// warns if the version cannot be read from the dev kit.
+pub fn check_dev_kit_version<P: AsRef<Path>>(ta_dev_kit_dir: P) -> Result<(), Error> {
+    println!("cargo:rerun-if-env-changed={}", ENV_SKIP_VERSION_CHECK);
+    if std::env::var_os(ENV_SKIP_VERSION_CHECK).is_some() {
+        return Ok(());
+    }
+    match DevKitVersion::read(ta_dev_kit_dir.as_ref())? {
+        Some(version) => version.check(),
+        None => {
+            println!(
+                "cargo:warning=could not read the OP-TEE version of the TA dev kit in {}, \
+                 supported versions are {} to {}",
+                ta_dev_kit_dir.as_ref().display(),
+                OPTEE_OS_VERSION_MIN,
+                OPTEE_OS_VERSION_MAX
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let conf_h = "#define CFG_OPTEE_REVISION_MAJOR 4\n\
+                      #define CFG_OPTEE_REVISION_MINOR 10\n\
+                      #define CFG_OPTEE_REVISION_EXTRA \"\"\n";
+        assert_eq!(
+            DevKitVersion::parse(conf_h),
+            Some(DevKitVersion::new(4, 10))
+        );
+        let conf_mk = "CFG_OPTEE_REVISION_MAJOR := 3\nCFG_OPTEE_REVISION_MINOR ?= 22\n";
+        assert_eq!(
+            DevKitVersion::parse(conf_mk),
+            Some(DevKitVersion::new(3, 22))
+        );
+        assert_eq!(DevKitVersion::parse("CFG_TA_FLOAT_SUPPORT := y\n"), None);
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(DevKitVersion::new(4, 0).check().is_ok());
+        assert!(DevKitVersion::new(4, 10).check().is_ok());
+        for version in [DevKitVersion::new(3, 22), DevKitVersion::new(4, 11)] {
+            assert!(matches!(
+                version.check(),
+                Err(Error::UnsupportedDevKit(v)) if v == version
+            ));
+        }
+    }
+
+    #[test]
+    fn test_same_range_as_sys() {
+        let (major, minor) = optee_utee_sys::OPTEE_OS_VERSION_MIN;
+        assert_eq!(OPTEE_OS_VERSION_MIN, DevKitVersion::new(major, minor));
+        let (major, minor) = optee_utee_sys::OPTEE_OS_VERSION_MAX;
+        assert_eq!(OPTEE_OS_VERSION_MAX, DevKitVersion::new(major, minor));
+    }
+}
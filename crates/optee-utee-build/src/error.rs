@@ -22,9 +22,38 @@ pub enum Error {
     Uuid(uuid::Error),
     PropertyNotFound(String),
     InvalidVersion(String),
+    InvalidConfig(String),
+    UnsupportedDevKit(crate::DevKitVersion),
     Utf(std::string::FromUtf8Error),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Env(e) => write!(f, "environment variable: {}", e),
+            Self::Io(e) => write!(f, "io: {}", e),
+            Self::Uuid(e) => write!(f, "invalid uuid: {}", e),
+            Self::PropertyNotFound(name) => write!(f, "property not found: {}", name),
+            Self::InvalidVersion(version) => write!(f, "invalid version: {}", version),
+            Self::InvalidConfig(reason) => write!(f, "invalid TA configuration: {}", reason),
+            Self::UnsupportedDevKit(version) => write!(
+                f,
+                "the TA dev kit in TA_DEV_KIT_DIR comes from OP-TEE {}, but this SDK supports \
+                 OP-TEE {} to {}: point TA_DEV_KIT_DIR to the dev kit of a supported release \
+                 (the one in optee-version.txt is tested), or use a release of the SDK \
+                 supporting OP-TEE {}; set TA_DEV_KIT_SKIP_VERSION_CHECK to build anyway",
+                version,
+                crate::OPTEE_OS_VERSION_MIN,
+                crate::OPTEE_OS_VERSION_MAX,
+                version
+            ),
+            Self::Utf(e) => write!(f, "invalid utf-8: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
         Self::Io(value)
@@ -16,13 +16,18 @@
 // under the License.
 
 mod builder;
+mod c_header;
 mod code_generator;
+mod dev_kit;
 mod error;
 mod linker;
+mod metadata;
 mod ta_config;
 
 pub use builder::*;
+pub use c_header::CHeaderGenerator;
 pub use code_generator::*;
+pub use dev_kit::*;
 pub use error::Error;
 pub use linker::*;
 pub use ta_config::*;
@@ -45,3 +50,33 @@ pub use uuid::Uuid;
 pub fn build(config: TaConfig) -> Result<(), Error> {
     Builder::new(config).build()
 }
+
+/// a link method, use it for TAs declaring their configuration with the
+/// `#[ta_config]` attribute of optee-utee instead of a `TaConfig`, so only
+/// the linking is left to the build script.
+/// Usage:
+/// ```no_run
+/// # use optee_utee_build::Error;
+/// # fn main() -> Result<(), Error> {
+/// optee_utee_build::link()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn link() -> Result<(), Error> {
+    Linker::auto().link_all(std::env::var("OUT_DIR")?)
+}
+
+/// a build method, use it for TAs declaring their configuration in the
+/// `[package.metadata.optee.ta-header]` section of their `Cargo.toml`, see
+/// [`TaConfig::from_cargo_metadata`].
+/// Usage:
+/// ```no_run
+/// # use optee_utee_build::Error;
+/// # fn main() -> Result<(), Error> {
+/// optee_utee_build::build_from_metadata()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn build_from_metadata() -> Result<(), Error> {
+    build(TaConfig::from_cargo_metadata()?)
+}
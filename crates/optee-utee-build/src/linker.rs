@@ -18,10 +18,10 @@
 use std::env;
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use crate::Error;
+use crate::{Error, check_dev_kit_version};
 
 /// The type of the linker, there are difference when using gcc/cc or ld/lld as  
 /// linker, For example, `--sort-section=alignment` parameter changes to  
@@ -84,7 +84,12 @@ impl Linker {
     pub fn auto() -> Self {
         Self::new(Self::auto_detect_linker_type())
     }
-    /// Set the ftrace buffer size
+    /// Set the ftrace buffer size, enabling the function tracer of OP-TEE
+    /// for a TA compiled with `-Z instrument-mcount`.
+    ///
+    /// Without it, the size is read from the `TA_FTRACE_BUF_SIZE`
+    /// environment variable, which `cargo optee build ta --profile ftrace`
+    /// sets.
     pub fn with_ftrace_buf_size(mut self, ftrace_buf_size: usize) -> Self {
         self.ftrace_buf_size = Some(ftrace_buf_size);
         self
@@ -93,12 +98,24 @@ impl Linker {
     ///
     /// param out_dir is used for putting some generated files that linker would
     ///  use.
-    pub fn link_all<P: Into<PathBuf>>(self, out_dir: P) -> Result<(), Error> {
+    ///
+    /// Fails first if the SDK does not support the release of OP-TEE the TA
+    /// dev kit comes from, see [`check_dev_kit_version`].
+    pub fn link_all<P: Into<PathBuf>>(mut self, out_dir: P) -> Result<(), Error> {
         const ENV_TA_DEV_KIT_DIR: &str = "TA_DEV_KIT_DIR";
         println!("cargo:rerun-if-env-changed={}", ENV_TA_DEV_KIT_DIR);
         let ta_dev_kit_dir = PathBuf::from(std::env::var(ENV_TA_DEV_KIT_DIR)?);
         let out_dir: PathBuf = out_dir.into();
 
+        check_dev_kit_version(&ta_dev_kit_dir)?;
+
+        if self.ftrace_buf_size.is_none() {
+            self.ftrace_buf_size = Self::ftrace_buf_size_from_env()?;
+        }
+        if self.ftrace_buf_size.is_some() {
+            Self::check_ftrace_support(&ta_dev_kit_dir)?;
+        }
+
         self.write_and_set_linker_script(out_dir.clone(), ta_dev_kit_dir.clone())?;
 
         let search_path = ta_dev_kit_dir.join("lib");
@@ -112,10 +129,17 @@ impl Linker {
             LinkerType::Cc => println!("cargo:rustc-link-arg=-Wl,--sort-section=alignment"),
             LinkerType::Ld => println!("cargo:rustc-link-arg=--sort-section=alignment"),
         };
+        // libutee finds the ftrace buffer through `__ftrace_info`
+        let ftrace_info = if self.ftrace_buf_size.is_some() {
+            " __ftrace_info;"
+        } else {
+            ""
+        };
         let mut dyn_list = File::create(out_dir.join("dyn_list"))?;
         writeln!(
             dyn_list,
-            "{{ __elf_phdr_info; trace_ext_prefix; trace_level; ta_head; }};"
+            "{{ __elf_phdr_info;{} trace_ext_prefix; trace_level; ta_head; }};",
+            ftrace_info
         )?;
         match self.linker_type {
             LinkerType::Cc => println!("cargo:rustc-link-arg=-Wl,--dynamic-list=dyn_list"),
@@ -133,18 +157,14 @@ impl Linker {
         out_dir: PathBuf,
         ta_dev_kit_dir: PathBuf,
     ) -> Result<(), Error> {
-        // cargo passes TARGET as env to the build scripts
-        const ENV_TARGET: &str = "TARGET";
-        println!("cargo:rerun-if-env-changed={}", ENV_TARGET);
-        match env::var(ENV_TARGET) {
-            Ok(ref v) if v == "arm-unknown-linux-gnueabihf" || v == "arm-unknown-optee" => {
-                match self.linker_type {
-                    LinkerType::Cc => println!("cargo:rustc-link-arg=-Wl,--no-warn-mismatch"),
-                    LinkerType::Ld => println!("cargo:rustc-link-arg=--no-warn-mismatch"),
-                };
-            }
-            _ => {}
-        };
+        // The arch rather than the target name, so that the Linux and the
+        // OP-TEE targets of an arch are handled the same
+        if Self::target_arch()? == "arm" {
+            match self.linker_type {
+                LinkerType::Cc => println!("cargo:rustc-link-arg=-Wl,--no-warn-mismatch"),
+                LinkerType::Ld => println!("cargo:rustc-link-arg=--no-warn-mismatch"),
+            };
+        }
 
         let link_script_dest = out_dir.join("ta.lds");
         let link_script = self.generate_new_link_script(ta_dev_kit_dir)?;
@@ -234,9 +254,7 @@ impl Linker {
                 "c",
                 link_script_template_path.to_str().expect("infallible"),
             ]);
-            const ENV_TARGET_ARCH: &str = "CARGO_CFG_TARGET_ARCH";
-            println!("cargo:rerun-if-env-changed={}", ENV_TARGET_ARCH);
-            match env::var(ENV_TARGET_ARCH)?.as_str() {
+            match Self::target_arch()?.as_str() {
                 "riscv32" => {
                     tmp.arg("-DRV32=1");
                 }
@@ -252,6 +270,7 @@ impl Linker {
                 _ => {}
             };
             if let Some(ftrace_buf_size) = self.ftrace_buf_size {
+                tmp.arg("-DCFG_FTRACE_SUPPORT=1");
                 tmp.arg(format!("-DCFG_FTRACE_BUF_SIZE={}", ftrace_buf_size));
             }
             tmp
@@ -262,6 +281,53 @@ impl Linker {
         Ok(link_script_text)
     }
 
+    // cargo passes the arch of the target as env to the build scripts, e.g.
+    // "riscv64" for both riscv64gc-unknown-linux-gnu and
+    // riscv64gc-unknown-optee
+    fn target_arch() -> Result<String, Error> {
+        const ENV_TARGET_ARCH: &str = "CARGO_CFG_TARGET_ARCH";
+        println!("cargo:rerun-if-env-changed={}", ENV_TARGET_ARCH);
+        Ok(env::var(ENV_TARGET_ARCH)?)
+    }
+
+    fn ftrace_buf_size_from_env() -> Result<Option<usize>, Error> {
+        const ENV_FTRACE_BUF_SIZE: &str = "TA_FTRACE_BUF_SIZE";
+        println!("cargo:rerun-if-env-changed={}", ENV_FTRACE_BUF_SIZE);
+        match env::var(ENV_FTRACE_BUF_SIZE) {
+            Ok(size) => size.trim().parse().map(Some).map_err(|_| {
+                Error::InvalidConfig(format!(
+                    "{} must be a size in bytes, got {:?}",
+                    ENV_FTRACE_BUF_SIZE, size
+                ))
+            }),
+            Err(_) => Ok(None),
+        }
+    }
+
+    // The tracer is part of libutee only if OP-TEE was built with
+    // CFG_FTRACE_SUPPORT=y, without it the `_mcount` calls of the TA do not
+    // link
+    fn check_ftrace_support(ta_dev_kit_dir: &Path) -> Result<(), Error> {
+        let conf_mk = ta_dev_kit_dir.join("mk/conf.mk");
+        println!("cargo:rerun-if-changed={}", conf_mk.display());
+        let conf = std::fs::read_to_string(&conf_mk)?;
+        let enabled = conf.lines().any(|line| {
+            let mut words = line
+                .split(|c: char| c.is_whitespace() || ":?=".contains(c))
+                .filter(|word| !word.is_empty());
+            words.next() == Some("CFG_FTRACE_SUPPORT") && words.next() == Some("y")
+        });
+        if enabled {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfig(format!(
+                "ftrace requires a TA dev kit of OP-TEE built with CFG_FTRACE_SUPPORT=y, \
+                 which {} is not",
+                ta_dev_kit_dir.display()
+            )))
+        }
+    }
+
     fn auto_detect_linker_type() -> LinkerType {
         const ENV_RUSTC_LINKER: &str = "RUSTC_LINKER";
         println!("cargo:rerun-if-env-changed={}", ENV_RUSTC_LINKER);
@@ -0,0 +1,374 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The TA configuration declared in `[package.metadata.optee.ta-header]` of
+//! the `Cargo.toml` of the TA.
+
+use std::path::Path;
+
+use toml::{Table, Value};
+
+use crate::{Error, PropertyValue, TaConfig, parse_size, ta_flag};
+
+/// Default of `uuid-path`, the same as the one of cargo-optee.
+const DEFAULT_UUID_PATH: &str = "../uuid.txt";
+
+impl TaConfig {
+    /// Read the config from `[package.metadata.optee.ta-header]` of the
+    /// `Cargo.toml` of the crate being built, with the version and the
+    /// description of the crate as defaults.
+    ///
+    /// ``` toml
+    /// [package.metadata.optee.ta-header]
+    /// # or `uuid = "..."`, default: "../uuid.txt"
+    /// uuid-path = "../uuid.txt"
+    /// flags = ["TA_FLAG_SINGLE_INSTANCE", "TA_FLAG_MULTI_SESSION"]
+    /// data-size = "4M"
+    /// stack-size = "512K"
+    /// framework-stack-size = 2048
+    /// version = "0.2"
+    /// description = "Key storage TA"
+    /// trace-level = 4
+    /// trace-ext-prefix = "TA"
+    ///
+    /// [package.metadata.optee.ta-header.properties]
+    /// "org.example.name" = "key storage"
+    /// "org.example.max_keys" = 16
+    /// "org.example.debug" = false
+    /// "org.example.serial" = { u64 = 1234567890123 }
+    /// "org.example.blob" = { binary = "aGVsbG8=" }
+    /// "org.example.peer" = { uuid = "26509cec-4a2b-4935-87ab-762d89fbf0b0" }
+    /// "org.example.client" = { login = 0, uuid = "26509cec-4a2b-4935-87ab-762d89fbf0b0" }
+    /// ```
+    ///
+    /// Sizes are integers or strings with a `K` or `M` suffix, and flags
+    /// `TA_FLAG_*` names or an integer. Integer properties are `u32`, and
+    /// other types are tables of a single entry named after the type, or of
+    /// `login` and `uuid` for identities. Every entry is optional, the section
+    /// itself too.
+    pub fn from_cargo_metadata() -> Result<Self, Error> {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")?;
+        let manifest_path = Path::new(&manifest_dir).join("Cargo.toml");
+        println!("cargo:rerun-if-changed={}", manifest_path.display());
+        let manifest = std::fs::read_to_string(&manifest_path)?;
+        Self::from_manifest(
+            &manifest,
+            Path::new(&manifest_dir),
+            &std::env::var("CARGO_PKG_VERSION")?,
+            &std::env::var("CARGO_PKG_DESCRIPTION")?,
+        )
+    }
+
+    /// Read the config from `[package.metadata.optee.ta-header]` of
+    /// `manifest`, the content of the `Cargo.toml` in `manifest_dir`, see
+    /// [`from_cargo_metadata`](Self::from_cargo_metadata).
+    pub fn from_manifest(
+        manifest: &str,
+        manifest_dir: &Path,
+        version: &str,
+        description: &str,
+    ) -> Result<Self, Error> {
+        let manifest: Table = manifest
+            .parse()
+            .map_err(|e| invalid(format!("Cargo.toml: {}", e)))?;
+        let empty = Table::new();
+        let mut section = &manifest;
+        for key in ["package", "metadata", "optee", "ta-header"] {
+            section = match section.get(key) {
+                Some(Value::Table(table)) => table,
+                Some(_) => return Err(invalid(format!("`{}` of Cargo.toml is not a table", key))),
+                None => &empty,
+            };
+        }
+        Self::from_section(section, manifest_dir, version, description)
+    }
+
+    fn from_section(
+        section: &Table,
+        manifest_dir: &Path,
+        version: &str,
+        description: &str,
+    ) -> Result<Self, Error> {
+        for key in section.keys() {
+            if !KEYS.contains(&key.as_str()) {
+                return Err(invalid(format!(
+                    "unknown key `{}` in [package.metadata.optee.ta-header], expected one of {}",
+                    key,
+                    KEYS.join(", ")
+                )));
+            }
+        }
+        if section.contains_key("uuid") && section.contains_key("uuid-path") {
+            return Err(invalid("`uuid` and `uuid-path` are mutually exclusive"));
+        }
+
+        let uuid = match str_entry(section, "uuid")? {
+            Some(uuid) => uuid.to_string(),
+            None => {
+                let path = manifest_dir
+                    .join(str_entry(section, "uuid-path")?.unwrap_or(DEFAULT_UUID_PATH));
+                println!("cargo:rerun-if-changed={}", path.display());
+                std::fs::read_to_string(&path)?.trim().to_string()
+            }
+        };
+        let mut config = Self::new_default(
+            &uuid,
+            str_entry(section, "version")?.unwrap_or(version),
+            str_entry(section, "description")?.unwrap_or(description),
+        )?;
+        if let Some(flags) = section.get("flags") {
+            config = config.ta_flags(flags_value(flags)?);
+        }
+        if let Some(size) = size_entry(section, "data-size")? {
+            config = config.ta_data_size(size);
+        }
+        if let Some(size) = size_entry(section, "stack-size")? {
+            config = config.ta_stack_size(size);
+        }
+        if let Some(size) = size_entry(section, "framework-stack-size")? {
+            config = config.ta_framework_stack_size(size);
+        }
+        if let Some(level) = section.get("trace-level") {
+            let level = level
+                .as_integer()
+                .and_then(|level| i32::try_from(level).ok())
+                .ok_or_else(|| invalid("`trace-level` must be an integer"))?;
+            config = config.trace_level(level);
+        }
+        if let Some(prefix) = str_entry(section, "trace-ext-prefix")? {
+            config = config.trace_ext_prefix(prefix);
+        }
+        match section.get("properties") {
+            None => {}
+            Some(Value::Table(properties)) => {
+                for (name, value) in properties {
+                    config = config.add_ext_property(name, property_value(name, value)?);
+                }
+            }
+            Some(_) => return Err(invalid("`properties` must be a table")),
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+const KEYS: &[&str] = &[
+    "uuid",
+    "uuid-path",
+    "flags",
+    "data-size",
+    "stack-size",
+    "framework-stack-size",
+    "version",
+    "description",
+    "trace-level",
+    "trace-ext-prefix",
+    "properties",
+];
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidConfig(reason.into())
+}
+
+fn str_entry<'a>(section: &'a Table, key: &str) -> Result<Option<&'a str>, Error> {
+    match section.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(invalid(format!("`{}` must be a string", key))),
+    }
+}
+
+fn size_entry(section: &Table, key: &str) -> Result<Option<u32>, Error> {
+    let size = match section.get(key) {
+        None => return Ok(None),
+        Some(Value::Integer(size)) => u32::try_from(*size).ok(),
+        Some(Value::String(size)) => parse_size(size),
+        Some(_) => None,
+    };
+    size.map(Some).ok_or_else(|| {
+        invalid(format!(
+            "`{}` must be a size in bytes, like 4096, \"512K\" or \"4M\"",
+            key
+        ))
+    })
+}
+
+fn flags_value(flags: &Value) -> Result<u32, Error> {
+    match flags {
+        Value::Integer(flags) => {
+            u32::try_from(*flags).map_err(|_| invalid("`flags` does not fit in a u32"))
+        }
+        Value::Array(names) => names.iter().try_fold(0, |flags, name| {
+            name.as_str()
+                .and_then(ta_flag)
+                .map(|flag| flags | flag)
+                .ok_or_else(|| invalid(format!("unknown TA flag {}", name)))
+        }),
+        _ => Err(invalid(
+            "`flags` must be an array of TA_FLAG_* names or an integer",
+        )),
+    }
+}
+
+fn property_value(name: &str, value: &Value) -> Result<PropertyValue, Error> {
+    let invalid_value = || {
+        invalid(format!(
+            "invalid value of the property `{}`: {}, expected a string, a boolean, a u32 or \
+             a table of `u32`, `u64`, `bool`, `string`, `binary`, `uuid`, or `login` and `uuid`",
+            name, value
+        ))
+    };
+    let uuid = |value: &Value| -> Result<uuid::Uuid, Error> {
+        Ok(value.as_str().ok_or_else(invalid_value)?.try_into()?)
+    };
+    Ok(match value {
+        Value::String(s) => PropertyValue::Str(s.clone()),
+        Value::Boolean(b) => PropertyValue::Bool(*b),
+        Value::Integer(n) => PropertyValue::U32(u32::try_from(*n).map_err(|_| invalid_value())?),
+        Value::Table(table) if table.len() == 2 => {
+            let login = table
+                .get("login")
+                .and_then(Value::as_integer)
+                .and_then(|login| u32::try_from(login).ok())
+                .ok_or_else(invalid_value)?;
+            PropertyValue::Identity(login, uuid(table.get("uuid").ok_or_else(invalid_value)?)?)
+        }
+        Value::Table(table) if table.len() == 1 => match table.iter().next() {
+            Some((kind, Value::Integer(n))) if kind == "u32" => {
+                PropertyValue::U32(u32::try_from(*n).map_err(|_| invalid_value())?)
+            }
+            Some((kind, Value::Integer(n))) if kind == "u64" => {
+                PropertyValue::U64(u64::try_from(*n).map_err(|_| invalid_value())?)
+            }
+            Some((kind, Value::Boolean(b))) if kind == "bool" => PropertyValue::Bool(*b),
+            Some((kind, Value::String(s))) if kind == "string" => PropertyValue::Str(s.clone()),
+            Some((kind, Value::String(s))) if kind == "binary" => {
+                PropertyValue::BinaryBlock(s.clone())
+            }
+            Some((kind, value)) if kind == "uuid" => PropertyValue::Uuid(uuid(value)?),
+            _ => return Err(invalid_value()),
+        },
+        _ => return Err(invalid_value()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "26509cec-4a2b-4935-87ab-762d89fbf0b0";
+
+    fn config(section: &str) -> Result<TaConfig, Error> {
+        let manifest = format!(
+            "[package]\nname = \"ta\"\n\n[package.metadata.optee.ta-header]\n{}",
+            section
+        );
+        TaConfig::from_manifest(&manifest, Path::new("."), "0.1.0", "from cargo")
+    }
+
+    #[test]
+    fn test_from_manifest() {
+        let config = config(&format!(
+            r#"
+uuid = "{UUID}"
+flags = ["TA_FLAG_SINGLE_INSTANCE", "TA_FLAG_MULTI_SESSION"]
+data-size = "4M"
+stack-size = 8192
+version = "0.2"
+trace-level = 2
+
+[package.metadata.optee.ta-header.properties]
+"org.example.name" = "key storage"
+"org.example.max_keys" = 16
+"org.example.serial" = {{ u64 = 1234567890123 }}
+"org.example.client" = {{ login = 4, uuid = "{UUID}" }}
+"#
+        ))
+        .unwrap();
+        assert_eq!(config.uuid.to_string(), UUID);
+        assert_eq!(config.ta_flags, (1 << 2) | (1 << 3));
+        assert_eq!(config.ta_data_size, 4 * 1024 * 1024);
+        assert_eq!(config.ta_stack_size, 8192);
+        assert_eq!(config.ta_version, "0.2");
+        assert_eq!(config.ta_description, "from cargo");
+        assert_eq!(config.trace_level, 2);
+        assert_eq!(config.ext_properties.len(), 4);
+        let property = |name: &str| {
+            &config
+                .ext_properties
+                .iter()
+                .find(|property| property.name == name)
+                .unwrap()
+                .value
+        };
+        assert!(matches!(
+            property("org.example.max_keys"),
+            PropertyValue::U32(16)
+        ));
+        assert!(matches!(
+            property("org.example.serial"),
+            PropertyValue::U64(1234567890123)
+        ));
+        assert!(matches!(
+            property("org.example.client"),
+            PropertyValue::Identity(4, _)
+        ));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("512K"), Some(512 * 1024));
+        assert_eq!(parse_size("4M"), Some(4 * 1024 * 1024));
+        assert_eq!(parse_size("4096M"), None);
+        assert_eq!(parse_size("K"), None);
+        assert_eq!(parse_size("4G"), None);
+    }
+
+    #[test]
+    fn test_defaults() {
+        // Without the section, the UUID is read from ../uuid.txt
+        let manifest = "[package]\nname = \"ta\"\n";
+        let dir = std::env::temp_dir().join("optee-utee-build-test-defaults/ta");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("../uuid.txt"), format!("{}\n", UUID)).unwrap();
+        let config = TaConfig::from_manifest(manifest, &dir, "0.1.0", "test").unwrap();
+        assert_eq!(config.uuid.to_string(), UUID);
+        assert_eq!(config.ta_flags, 0);
+        assert_eq!(config.ta_data_size, 32 * 1024);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for section in [
+            "uid = \"x\"".to_string(),
+            format!("uuid = \"{UUID}\"\nuuid-path = \"uuid.txt\""),
+            format!("uuid = \"{UUID}\"\nflags = [\"TA_FLAG_UNKNOWN\"]"),
+            format!("uuid = \"{UUID}\"\ndata-size = \"4G\""),
+            format!("uuid = \"{UUID}\"\nstack-size = 0"),
+            format!("uuid = \"{UUID}\"\nflags = [\"TA_FLAG_MULTI_SESSION\"]"),
+            format!("uuid = \"{UUID}\"\nproperties = {{ \"a\" = -1 }}"),
+            format!("uuid = \"{UUID}\"\nproperties = {{ \"a\" = {{ u16 = 1 }} }}"),
+        ] {
+            assert!(
+                matches!(config(&section), Err(Error::InvalidConfig(_))),
+                "{}",
+                section
+            );
+        }
+    }
+}
@@ -17,6 +17,53 @@
 use crate::Error;
 use std::convert::TryInto;
 
+/// `TA_FLAG_SINGLE_INSTANCE` of `user_ta_header.h`: one instance of the TA
+/// serves all the sessions.
+pub const TA_FLAG_SINGLE_INSTANCE: u32 = 1 << 2;
+/// `TA_FLAG_MULTI_SESSION`: the instance accepts several sessions at once.
+pub const TA_FLAG_MULTI_SESSION: u32 = 1 << 3;
+/// `TA_FLAG_INSTANCE_KEEP_ALIVE`: the instance outlives its last session.
+pub const TA_FLAG_INSTANCE_KEEP_ALIVE: u32 = 1 << 4;
+
+/// Flags of `user_ta_header.h` by name, as accepted by
+/// [`ta_flag`].
+pub const TA_FLAG_NAMES: &[(&str, u32)] = &[
+    ("TA_FLAG_USER_MODE", 0),
+    ("TA_FLAG_EXEC_DDR", 0),
+    ("TA_FLAG_SINGLE_INSTANCE", 1 << 2),
+    ("TA_FLAG_MULTI_SESSION", 1 << 3),
+    ("TA_FLAG_INSTANCE_KEEP_ALIVE", 1 << 4),
+    ("TA_FLAG_SECURE_DATA_PATH", 1 << 5),
+    ("TA_FLAG_REMAP_SUPPORT", 1 << 6),
+    ("TA_FLAG_CACHE_MAINTENANCE", 1 << 7),
+    ("TA_FLAG_CONCURRENT", 1 << 8),
+    ("TA_FLAG_DEVICE_ENUM", 1 << 9),
+    ("TA_FLAG_DEVICE_ENUM_SUPP", 1 << 10),
+    ("TA_FLAG_DONT_CLOSE_HANDLE_ON_CORRUPT_OBJECT", 1 << 11),
+    ("TA_FLAG_DEVICE_ENUM_TEE_STORAGE_PRIVATE", 1 << 12),
+    ("TA_FLAG_INSTANCE_KEEP_CRASHED", 1 << 13),
+];
+
+/// Looks up a flag of [`TA_FLAG_NAMES`] by name, e.g. `"TA_FLAG_MULTI_SESSION"`.
+pub fn ta_flag(name: &str) -> Option<u32> {
+    TA_FLAG_NAMES
+        .iter()
+        .find(|(flag, _)| *flag == name)
+        .map(|(_, value)| *value)
+}
+
+/// Parses a size in bytes, given as an integer or with a `K` or `M` suffix
+/// like `"512K"`, `None` if it does not fit in a `u32`.
+pub fn parse_size(s: &str) -> Option<u32> {
+    let s = s.trim();
+    let (digits, unit) = match s.char_indices().last()? {
+        (i, 'K' | 'k') => (&s[..i], 1024),
+        (i, 'M' | 'm') => (&s[..i], 1024 * 1024),
+        _ => (s, 1),
+    };
+    digits.trim().parse::<u32>().ok()?.checked_mul(unit)
+}
+
 /// Configuration options for TA
 ///
 /// Examples
@@ -115,6 +162,26 @@ impl TaConfig {
         self.ta_flags = flags;
         self
     }
+    /// Sets `TA_FLAG_SINGLE_INSTANCE`.
+    pub fn single_instance(mut self) -> Self {
+        self.ta_flags |= TA_FLAG_SINGLE_INSTANCE;
+        self
+    }
+    /// Sets `TA_FLAG_MULTI_SESSION`, and `TA_FLAG_SINGLE_INSTANCE` which it
+    /// requires. The sessions share the instance, their contexts are kept
+    /// apart by the `optee_utee::SessionRegistry`.
+    pub fn multi_session(self) -> Self {
+        let mut config = self.single_instance();
+        config.ta_flags |= TA_FLAG_MULTI_SESSION;
+        config
+    }
+    /// Sets `TA_FLAG_INSTANCE_KEEP_ALIVE`, and `TA_FLAG_SINGLE_INSTANCE`
+    /// which it requires.
+    pub fn instance_keep_alive(self) -> Self {
+        let mut config = self.single_instance();
+        config.ta_flags |= TA_FLAG_INSTANCE_KEEP_ALIVE;
+        config
+    }
     pub fn ta_stack_size(mut self, stack_size: u32) -> Self {
         self.ta_stack_size = stack_size;
         self
@@ -135,10 +202,113 @@ impl TaConfig {
         self.ta_framework_stack_size = stack_size;
         self
     }
-    pub fn add_ext_property(mut self, name: &str, value: PropertyValue) -> Self {
-        self.ext_properties.push(Property::new(name, value));
+    /// Adds the custom property `name` to the properties of the TA, read in
+    /// the TA with `optee_utee::Property` or a key of
+    /// `optee_utee::define_property_key!`.
+    ///
+    /// ```rust
+    /// # use optee_utee_build::{Error, PropertyValue, TaConfig};
+    /// # fn main() -> Result<(), Error> {
+    /// # const UUID: &str = "d93c2970-b1a6-4b86-90ac-b42830e78d9b";
+    /// let ta_config = TaConfig::new_default(UUID, "0.1.0", "example")?
+    ///     .add_ext_property("org.example.max_keys", 16u32)
+    ///     .add_ext_property("org.example.vendor", "example")
+    ///     .add_ext_property("org.example.blob", PropertyValue::BinaryBlock("aGVsbG8=".to_string()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_ext_property(mut self, name: &str, value: impl Into<PropertyValue>) -> Self {
+        self.ext_properties.push(Property::new(name, value.into()));
         self
     }
+
+    /// Checks the sizes and strings of the config, called before generating
+    /// the header so an invalid config fails the build of the TA instead of
+    /// its loading.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.ta_flags & (TA_FLAG_MULTI_SESSION | TA_FLAG_INSTANCE_KEEP_ALIVE) != 0
+            && self.ta_flags & TA_FLAG_SINGLE_INSTANCE == 0
+        {
+            return Err(Error::InvalidConfig(
+                "TA_FLAG_MULTI_SESSION and TA_FLAG_INSTANCE_KEEP_ALIVE require \
+                TA_FLAG_SINGLE_INSTANCE"
+                    .to_string(),
+            ));
+        }
+        if self.ta_data_size == 0 {
+            return Err(Error::InvalidConfig(
+                "ta_data_size must not be 0".to_string(),
+            ));
+        }
+        if self.ta_stack_size == 0 {
+            return Err(Error::InvalidConfig(
+                "ta_stack_size must not be 0".to_string(),
+            ));
+        }
+        if self.ta_framework_stack_size < 2048 {
+            return Err(Error::InvalidConfig(format!(
+                "ta_framework_stack_size must be at least 2048, got {}",
+                self.ta_framework_stack_size
+            )));
+        }
+        if self
+            .ta_stack_size
+            .checked_add(self.ta_framework_stack_size)
+            .is_none()
+        {
+            return Err(Error::InvalidConfig(
+                "ta_stack_size and ta_framework_stack_size overflow u32".to_string(),
+            ));
+        }
+        let mut strings = vec![
+            ("ta_version", self.ta_version.as_str()),
+            ("ta_description", self.ta_description.as_str()),
+            ("trace_ext_prefix", self.trace_ext_prefix.as_str()),
+        ];
+        for (i, prop) in self.ext_properties.iter().enumerate() {
+            if prop.name.is_empty() {
+                return Err(Error::InvalidConfig(
+                    "property name must not be empty".to_string(),
+                ));
+            }
+            if RESERVED_PROPERTIES.contains(&prop.name.as_str()) {
+                return Err(Error::InvalidConfig(format!(
+                    "property {} is set by the header and cannot be overridden",
+                    prop.name
+                )));
+            }
+            if self.ext_properties[..i]
+                .iter()
+                .any(|other| other.name == prop.name)
+            {
+                return Err(Error::InvalidConfig(format!(
+                    "property {} is declared twice",
+                    prop.name
+                )));
+            }
+            if let PropertyValue::BinaryBlock(v) = &prop.value
+                && !is_base64(v)
+            {
+                return Err(Error::InvalidConfig(format!(
+                    "value of the binary block property {} is not base64: {:?}",
+                    prop.name, v
+                )));
+            }
+            strings.push(("property name", prop.name.as_str()));
+            if let PropertyValue::Str(v) | PropertyValue::BinaryBlock(v) = &prop.value {
+                strings.push(("property value", v.as_str()));
+            }
+        }
+        for (field, value) in strings {
+            if value.contains('\0') {
+                return Err(Error::InvalidConfig(format!(
+                    "{} must not contain a NUL character: {:?}",
+                    field, value
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 /// An enum of PropertyValue, with its type and value combined
@@ -173,6 +343,69 @@ pub enum PropertyValue {
     U64(u64),
 }
 
+impl From<bool> for PropertyValue {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+impl From<u32> for PropertyValue {
+    fn from(v: u32) -> Self {
+        Self::U32(v)
+    }
+}
+
+impl From<u64> for PropertyValue {
+    fn from(v: u64) -> Self {
+        Self::U64(v)
+    }
+}
+
+impl From<uuid::Uuid> for PropertyValue {
+    fn from(v: uuid::Uuid) -> Self {
+        Self::Uuid(v)
+    }
+}
+
+impl From<&str> for PropertyValue {
+    fn from(v: &str) -> Self {
+        Self::Str(v.to_string())
+    }
+}
+
+impl From<String> for PropertyValue {
+    fn from(v: String) -> Self {
+        Self::Str(v)
+    }
+}
+
+/// Properties written by the header itself, or answered by libutee for
+/// `gpd.ta.appID`, which the custom properties must not shadow.
+const RESERVED_PROPERTIES: &[&str] = &[
+    "gpd.ta.appID",
+    "gpd.ta.singleInstance",
+    "gpd.ta.multiSession",
+    "gpd.ta.instanceKeepAlive",
+    "gpd.ta.instanceKeepCrashed",
+    "gpd.ta.dataSize",
+    "gpd.ta.stackSize",
+    "gpd.ta.version",
+    "gpd.ta.description",
+    "gpd.ta.endian",
+    "gpd.ta.doesNotCloseHandleOnCorruptObject",
+];
+
+/// Whether `s` is padded base64, the encoding libutee decodes binary blocks
+/// from.
+fn is_base64(s: &str) -> bool {
+    let data = s.trim_end_matches('=');
+    s.len().is_multiple_of(4)
+        && s.len() - data.len() <= 2
+        && data
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+}
+
 /// A GP property pair, use it to set ta_properties
 ///
 /// must not append a '\0' in name, we will add it automatically if neccessary.
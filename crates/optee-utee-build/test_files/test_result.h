@@ -0,0 +1,15 @@
+/* Generated by optee-utee-build, do not edit. */
+
+#ifndef TA_HELLO_WORLD_H
+#define TA_HELLO_WORLD_H
+
+#define TA_HELLO_WORLD_UUID \
+	{ 0x8aaaf200, 0x2450, 0x11e4, \
+		{ 0xab, 0xe2, 0x00, 0x02, 0xa5, 0xd5, 0xc5, 0x1b } }
+
+#define TA_HELLO_WORLD_CMD_INC_VALUE 0
+#define TA_HELLO_WORLD_CMD_DEC_VALUE 1
+#define TA_HELLO_WORLD_CMD_GET_RANDOM2 16
+#define TA_HELLO_WORLD_CMD_RESET 32
+
+#endif /* TA_HELLO_WORLD_H */
@@ -0,0 +1,326 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Expansion of `#[derive(TaCommand)]` and `#[ta_dispatch]`.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+
+struct Command {
+    variant: syn::Ident,
+    method: syn::Ident,
+    id: TokenStream,
+    /// `None` for unit variants, which take no input.
+    input: Option<syn::Type>,
+    /// `None` if the command has no output.
+    output: Option<syn::Type>,
+}
+
+pub(crate) fn expand_ta_command(item: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    let syn::Data::Enum(data) = &item.data else {
+        return Err(syn::Error::new(
+            item.span(),
+            "`#[derive(TaCommand)]` is only supported on enums",
+        ));
+    };
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            item.generics.span(),
+            "`#[derive(TaCommand)]` does not support generic enums",
+        ));
+    }
+
+    let ident = &item.ident;
+    let vis = &item.vis;
+    let mut codec: syn::Path = syn::parse_quote!(optee_utee::dispatch::Bytes);
+    let mut handler = format_ident!("{}Handler", ident);
+    for attr in item
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("ta_command"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("codec") {
+                codec = meta.value()?.parse()?;
+            } else if meta.path.is_ident("handler") {
+                handler = meta.value()?.parse()?;
+            } else {
+                return Err(meta.error("expected `codec` or `handler`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let mut commands: Vec<Command> = Vec::with_capacity(data.variants.len());
+    for variant in &data.variants {
+        let mut id = None;
+        let mut output = None;
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("ta_command"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("id") {
+                    let expr: syn::Expr = meta.value()?.parse()?;
+                    id = Some(quote!((#expr) as u32));
+                } else if meta.path.is_ident("output") {
+                    output = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("expected `id` or `output`"));
+                }
+                Ok(())
+            })?;
+        }
+        // Like enum discriminants, IDs continue from the previous command
+        let id = id.unwrap_or_else(|| match commands.last() {
+            Some(previous) => {
+                let previous = &previous.id;
+                quote!((#previous) + 1)
+            }
+            None => quote!(0u32),
+        });
+        let input = match &variant.fields {
+            syn::Fields::Unit => None,
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                Some(fields.unnamed[0].ty.clone())
+            }
+            fields => {
+                return Err(syn::Error::new(
+                    fields.span(),
+                    "a command must be a unit variant or have a single unnamed field holding its input",
+                ));
+            }
+        };
+        let method = syn::Ident::new(
+            &snake_case(&variant.ident.to_string()),
+            variant.ident.span(),
+        );
+        if method == "dispatch" {
+            return Err(syn::Error::new(
+                variant.ident.span(),
+                "the handler method of this command would conflict with `dispatch`",
+            ));
+        }
+        commands.push(Command {
+            variant: variant.ident.clone(),
+            method,
+            id,
+            input,
+            output,
+        });
+    }
+
+    let decode_arms = commands.iter().map(|c| {
+        let Command { variant, id, .. } = c;
+        match &c.input {
+            Some(input) => quote! {
+                if cmd_id == #id {
+                    let input = <#codec as optee_utee::dispatch::Decode<#input>>::decode(input)?;
+                    return Ok(Self::#variant(input));
+                }
+            },
+            None => quote! {
+                if cmd_id == #id {
+                    <optee_utee::dispatch::Bytes as optee_utee::dispatch::Decode<()>>::decode(input)?;
+                    return Ok(Self::#variant);
+                }
+            },
+        }
+    });
+    let id_arms = commands.iter().map(|c| {
+        let Command { variant, id, .. } = c;
+        match &c.input {
+            Some(_) => quote!(Self::#variant(_) => #id,),
+            None => quote!(Self::#variant => #id,),
+        }
+    });
+    let methods = commands.iter().map(|c| {
+        let Command {
+            variant, method, ..
+        } = c;
+        let output = match &c.output {
+            Some(output) => quote!(#output),
+            None => quote!(()),
+        };
+        let doc = format!("Handles [`{ident}::{variant}`].");
+        match &c.input {
+            Some(input) => quote! {
+                #[doc = #doc]
+                fn #method(&mut self, input: #input) -> optee_utee::Result<#output>;
+            },
+            None => quote! {
+                #[doc = #doc]
+                fn #method(&mut self) -> optee_utee::Result<#output>;
+            },
+        }
+    });
+    let dispatch_arms = commands.iter().map(|c| {
+        let Command {
+            variant, method, ..
+        } = c;
+        let (pattern, call) = match &c.input {
+            Some(_) => (
+                quote!(#ident::#variant(input)),
+                quote!(self.#method(input)?),
+            ),
+            None => (quote!(#ident::#variant), quote!(self.#method()?)),
+        };
+        match &c.output {
+            Some(output) => quote! {
+                #pattern => {
+                    let output = #call;
+                    optee_utee::dispatch::encode_output::<#codec, #output>(&mut params.1, &output)
+                }
+            },
+            None => quote! {
+                #pattern => {
+                    #call;
+                    optee_utee::dispatch::output(&mut params.1, &[])
+                }
+            },
+        }
+    });
+    let handler_doc =
+        format!("Handlers of the commands of [`{ident}`], generated by `#[derive(TaCommand)]`.");
+
+    Ok(quote! {
+        impl optee_utee::dispatch::TaCommand for #ident {
+            fn decode(cmd_id: u32, input: &[u8]) -> optee_utee::Result<Self> {
+                #(#decode_arms)*
+                Err(optee_utee::ErrorKind::BadParameters.into())
+            }
+
+            fn id(&self) -> u32 {
+                match self {
+                    #(#id_arms)*
+                }
+            }
+        }
+
+        #[doc = #handler_doc]
+        #vis trait #handler {
+            #(#methods)*
+
+            /// Decodes the command `cmd_id` from `params`, calls its handler
+            /// and encodes the output into `params`.
+            fn dispatch(
+                &mut self,
+                cmd_id: u32,
+                params: &mut optee_utee::ParametersAny<'_>,
+            ) -> optee_utee::Result<()> {
+                params.2.as_none()?;
+                params.3.as_none()?;
+                let input = optee_utee::dispatch::input(&params.0)?;
+                let command = <#ident as optee_utee::dispatch::TaCommand>::decode(cmd_id, input)?;
+                match command {
+                    #(#dispatch_arms)*
+                }
+            }
+        }
+    })
+}
+
+pub(crate) fn expand_ta_dispatch(
+    args: TokenStream,
+    item: syn::ItemImpl,
+) -> syn::Result<TokenStream> {
+    let mut stateless = false;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("stateless") {
+            stateless = true;
+            Ok(())
+        } else {
+            Err(meta.error("expected `stateless`"))
+        }
+    });
+    syn::parse::Parser::parse2(parser, args)?;
+
+    let Some((None, trait_path, _)) = &item.trait_ else {
+        return Err(syn::Error::new(
+            item.span(),
+            "`#[ta_dispatch]` must be placed on an `impl XHandler for T` block",
+        ));
+    };
+    let self_ty = &item.self_ty;
+
+    let entry_point = if stateless {
+        quote! {
+            #[optee_utee::ta_invoke_command]
+            fn ta_dispatch_invoke_command(
+                cmd_id: u32,
+                params: &mut optee_utee::ParametersAny,
+            ) -> optee_utee::Result<()> {
+                let mut handler = <#self_ty as core::default::Default>::default();
+                <#self_ty as #trait_path>::dispatch(&mut handler, cmd_id, params)
+            }
+        }
+    } else {
+        quote! {
+            #[optee_utee::ta_invoke_command]
+            fn ta_dispatch_invoke_command(
+                sess_ctx: &mut #self_ty,
+                cmd_id: u32,
+                params: &mut optee_utee::ParametersAny,
+            ) -> optee_utee::Result<()> {
+                <#self_ty as #trait_path>::dispatch(sess_ctx, cmd_id, params)
+            }
+        }
+    };
+
+    Ok(quote! {
+        #item
+
+        #entry_point
+    })
+}
+
+/// Converts a `CamelCase` variant name into a `snake_case` method name,
+/// keeping acronyms together (`GetRSAKey` becomes `get_rsa_key`).
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if previous.is_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_uppercase() && next_lower)
+            {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::snake_case;
+
+    #[test]
+    fn test_snake_case() {
+        assert_eq!(snake_case("Hello"), "hello");
+        assert_eq!(snake_case("GetRandom"), "get_random");
+        assert_eq!(snake_case("GetRSAKey"), "get_rsa_key");
+        assert_eq!(snake_case("Sha256Digest"), "sha256_digest");
+        assert_eq!(snake_case("AES"), "aes");
+    }
+}
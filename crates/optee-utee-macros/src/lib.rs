@@ -23,6 +23,10 @@ use quote::quote;
 use syn::parse_macro_input;
 use syn::spanned::Spanned;
 
+mod dispatch;
+mod self_test;
+mod ta_config;
+
 /// Attribute to declare the entry point of creating TA.
 ///
 /// # Examples
@@ -59,7 +63,7 @@ pub fn ta_create(_args: TokenStream, input: TokenStream) -> TokenStream {
         pub extern "C" fn TA_CreateEntryPoint() -> optee_utee_sys::TEE_Result {
             match #f_ident() {
                 Ok(_) => optee_utee_sys::TEE_SUCCESS,
-                Err(e) => e.raw_code()
+                Err(e) => e.into_entry_point_code("TA_CreateEntryPoint")
             }
         }
 
@@ -118,7 +122,9 @@ pub fn ta_destroy(_args: TokenStream, input: TokenStream) -> TokenStream {
 /// of typed wrappers, `optee_utee::Parameters`, etc.)
 ///
 /// A session context `&mut T` can be defined as an optional second parameter;
-/// `T` must implement `Default`.
+/// `T` must implement `Default`. The context is registered in the
+/// `optee_utee::SessionRegistry` once the function succeeds, and the
+/// framework only gets its `optee_utee::SessionId`.
 ///
 /// # Examples
 ///
@@ -186,11 +192,11 @@ pub fn ta_open_session(_args: TokenStream, input: TokenStream) -> TokenStream {
                         optee_utee::FromRawParameters::from_raw(param_types, params)
                     } {
                         Ok(p) => p,
-                        Err(e) => return e.raw_code(),
+                        Err(e) => return e.into_entry_point_code("TA_OpenSessionEntryPoint"),
                     };
                     match #f_ident(&mut parameters) {
                         Ok(_) => optee_utee_sys::TEE_SUCCESS,
-                        Err(e) => e.raw_code()
+                        Err(e) => e.into_entry_point_code("TA_OpenSessionEntryPoint")
                     }
                 }
 
@@ -216,16 +222,17 @@ pub fn ta_open_session(_args: TokenStream, input: TokenStream) -> TokenStream {
                         optee_utee::FromRawParameters::from_raw(param_types, params)
                     } {
                         Ok(p) => p,
-                        Err(e) => return e.raw_code(),
+                        Err(e) => return e.into_entry_point_code("TA_OpenSessionEntryPoint"),
                     };
                     let mut ctx: #ctx_type = Default::default();
                     match #f_ident(&mut parameters, &mut ctx) {
                         Ok(_) =>
                         {
-                            *sess_ctx = alloc::boxed::Box::into_raw(alloc::boxed::Box::new(ctx)) as _;
+                            let id = optee_utee::SessionRegistry::<#ctx_type>::open(ctx);
+                            *sess_ctx = id.into_raw();
                             optee_utee_sys::TEE_SUCCESS
                         }
-                        Err(e) => e.raw_code()
+                        Err(e) => e.into_entry_point_code("TA_OpenSessionEntryPoint")
                     }
                 }
 
@@ -237,8 +244,10 @@ pub fn ta_open_session(_args: TokenStream, input: TokenStream) -> TokenStream {
     }
 }
 
-/// Attribute to declare the entry point of closing a session. Session context
-/// raw pointer (`*mut T`) can be defined as an optional parameter.
+/// Attribute to declare the entry point of closing a session. The session
+/// context `&mut T` can be defined as an optional parameter, it is removed
+/// from the `optee_utee::SessionRegistry` and dropped after the function
+/// returns.
 ///
 /// # Examples
 ///
@@ -290,16 +299,16 @@ pub fn ta_close_session(_args: TokenStream, input: TokenStream) -> TokenStream {
             };
 
             quote!(
-                // To eliminate the clippy error: this public function might dereference a raw pointer but is not marked `unsafe`
-                // we just expand the unsafe block, but the session-related macros need refactoring in the future
                 #[unsafe(no_mangle)]
-                pub unsafe extern "C" fn TA_CloseSessionEntryPoint(sess_ctx: *mut core::ffi::c_void) {
-                    if sess_ctx.is_null() {
-                        panic!("sess_ctx is null");
+                pub extern "C" fn TA_CloseSessionEntryPoint(sess_ctx: *mut core::ffi::c_void) {
+                    let id = match optee_utee::SessionId::from_raw(sess_ctx) {
+                        Some(id) => id,
+                        None => panic!("sess_ctx is null"),
+                    };
+                    match optee_utee::SessionRegistry::<#ctx_type>::close(id) {
+                        Ok(mut ctx) => #f_ident(&mut ctx),
+                        Err(e) => panic!("failed to close session {:?}: {:?}", id, e),
                     }
-                    let mut b = alloc::boxed::Box::from_raw(sess_ctx as *mut #ctx_type);
-                    #f_ident(&mut b);
-                    drop(b);
                 }
 
                 #f
@@ -319,6 +328,14 @@ pub fn ta_close_session(_args: TokenStream, input: TokenStream) -> TokenStream {
 /// A session context `&mut T` can be defined as an optional first parameter
 /// (before `cmd_id`).
 ///
+/// A `&optee_utee::cancellation::CancellationToken` can be defined as an
+/// optional last parameter, cancellations are then unmasked while the
+/// function runs so it can check whether the client cancelled the command.
+///
+/// With the `self_test` feature of `optee-utee`, the command
+/// `optee_utee::self_test::COMMAND_ID` runs the `#[ta_self_test]` module of
+/// the TA instead of the function.
+///
 /// # Examples
 ///
 /// ```ignore
@@ -353,6 +370,13 @@ pub fn ta_close_session(_args: TokenStream, input: TokenStream) -> TokenStream {
 ///     cmd_id: u32,
 ///     params: &mut Parameters,
 /// ) -> Result<()> { }
+/// // Cancellable by the client
+/// #[ta_invoke_command]
+/// fn invoke_command(
+///     cmd_id: u32,
+///     params: &mut ParametersAny,
+///     cancellation: &CancellationToken,
+/// ) -> Result<()> { }
 /// ```
 #[proc_macro_attribute]
 pub fn ta_invoke_command(_args: TokenStream, input: TokenStream) -> TokenStream {
@@ -360,24 +384,39 @@ pub fn ta_invoke_command(_args: TokenStream, input: TokenStream) -> TokenStream
     let f_sig = &f.sig;
     let f_ident = &f_sig.ident;
 
+    // an optional last `&CancellationToken` parameter
+    let cancellable = f_sig.inputs.last().is_some_and(is_cancellation_token_arg);
+    let inputs_len = f_sig.inputs.len() - usize::from(cancellable);
+
     // check the function signature
     let valid_signature = f_sig.constness.is_none()
         && matches!(f.vis, syn::Visibility::Inherited)
         && f_sig.abi.is_none()
-        && (f_sig.inputs.len() == 2 || f_sig.inputs.len() == 3)
+        && (inputs_len == 2 || inputs_len == 3)
         && f_sig.generics.where_clause.is_none()
         && f_sig.variadic.is_none();
 
     if !valid_signature {
         return syn::parse::Error::new(
             f.span(),
-            "`#[ta_invoke_command]` function must have signature `fn(u32, &mut P) -> Result<()>` or `fn(&mut T, u32, &mut P) -> Result<()>`",
+            "`#[ta_invoke_command]` function must have signature `fn(u32, &mut P) -> Result<()>` or `fn(&mut T, u32, &mut P) -> Result<()>`, optionally followed by `&CancellationToken`",
         )
         .to_compile_error()
         .into();
     }
 
-    match f_sig.inputs.len() {
+    let (cancellation, cancellation_arg) = if cancellable {
+        (
+            quote!(
+                let cancellation = optee_utee::cancellation::CancellationToken::unmasked();
+            ),
+            quote!(, &cancellation),
+        )
+    } else {
+        (quote!(), quote!())
+    };
+
+    match inputs_len {
         2 => {
             let tokens = quote!(
                 #[unsafe(no_mangle)]
@@ -387,17 +426,21 @@ pub fn ta_invoke_command(_args: TokenStream, input: TokenStream) -> TokenStream
                     param_types: optee_utee::RawParamTypes,
                     params: &mut optee_utee::RawParams,
                 ) -> optee_utee_sys::TEE_Result {
+                    if let Some(code) = optee_utee::self_test::intercept(cmd_id, param_types, params) {
+                        return code;
+                    }
                     let mut parameters = match unsafe {
                         optee_utee::FromRawParameters::from_raw(param_types, params)
                     } {
                         Ok(p) => p,
-                        Err(e) => return e.raw_code(),
+                        Err(e) => return e.into_entry_point_code("TA_InvokeCommandEntryPoint"),
                     };
-                    match #f_ident(cmd_id, &mut parameters) {
+                    #cancellation
+                    match #f_ident(cmd_id, &mut parameters #cancellation_arg) {
                         Ok(_) => {
                             optee_utee_sys::TEE_SUCCESS
                         },
-                        Err(e) => e.raw_code()
+                        Err(e) => e.into_entry_point_code("TA_InvokeCommandEntryPoint")
                     }
                 }
 
@@ -413,31 +456,31 @@ pub fn ta_invoke_command(_args: TokenStream, input: TokenStream) -> TokenStream
 
             quote!(
                 #[unsafe(no_mangle)]
-                pub unsafe extern "C" fn TA_InvokeCommandEntryPoint(
+                pub extern "C" fn TA_InvokeCommandEntryPoint(
                     sess_ctx: *mut core::ffi::c_void,
                     cmd_id: u32,
                     param_types: optee_utee::RawParamTypes,
                     params: &mut optee_utee::RawParams,
                 ) -> optee_utee_sys::TEE_Result {
-                    if sess_ctx.is_null() {
-                        return optee_utee_sys::TEE_ERROR_SECURITY;
+                    if let Some(code) = optee_utee::self_test::intercept(cmd_id, param_types, params) {
+                        return code;
                     }
+                    let id = match optee_utee::SessionId::from_raw(sess_ctx) {
+                        Some(id) => id,
+                        None => return optee_utee_sys::TEE_ERROR_SECURITY,
+                    };
                     let mut parameters = match unsafe {
                         optee_utee::FromRawParameters::from_raw(param_types, params)
                     } {
                         Ok(p) => p,
-                        Err(e) => return e.raw_code(),
+                        Err(e) => return e.into_entry_point_code("TA_InvokeCommandEntryPoint"),
                     };
-                    let mut b = alloc::boxed::Box::from_raw(sess_ctx as *mut #ctx_type);
-                    match #f_ident(&mut b, cmd_id, &mut parameters) {
-                        Ok(_) => {
-                            core::mem::forget(b);
-                            optee_utee_sys::TEE_SUCCESS
-                        },
-                        Err(e) => {
-                            core::mem::forget(b);
-                            e.raw_code()
-                        }
+                    #cancellation
+                    match optee_utee::SessionRegistry::<#ctx_type>::with(id, |ctx| {
+                        #f_ident(ctx, cmd_id, &mut parameters #cancellation_arg)
+                    }) {
+                        Ok(Ok(_)) => optee_utee_sys::TEE_SUCCESS,
+                        Ok(Err(e)) | Err(e) => e.into_entry_point_code("TA_InvokeCommandEntryPoint"),
                     }
                 }
 
@@ -449,6 +492,168 @@ pub fn ta_invoke_command(_args: TokenStream, input: TokenStream) -> TokenStream
     }
 }
 
+/// Derive typed command dispatch for an enum of TA commands.
+///
+/// Each variant is a command, either a unit variant without input or a
+/// variant with a single unnamed field holding the decoded input. The derive
+/// implements `optee_utee::dispatch::TaCommand` and generates a handler trait,
+/// `{Enum}Handler` by default, with one method per command named after the
+/// variant in snake case and a provided `dispatch` method. See the
+/// `optee_utee::dispatch` module for the parameter layout.
+///
+/// Attributes on the enum:
+/// * `#[ta_command(codec = Path)]`: the codec for inputs and outputs,
+///   `optee_utee::dispatch::Bytes` by default.
+/// * `#[ta_command(handler = Name)]`: the name of the handler trait.
+///
+/// Attributes on a variant:
+/// * `#[ta_command(id = expr)]`: the command ID, by default the ID of the
+///   previous command plus one, starting at 0.
+/// * `#[ta_command(output = Type)]`: the output of the command, none by
+///   default.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[derive(TaCommand)]
+/// #[ta_command(codec = optee_utee::dispatch::Json)]
+/// enum Command {
+///     #[ta_command(id = proto::Command::Sign as u32, output = Signature)]
+///     Sign(SignRequest),
+///     Reset,
+/// }
+///
+/// impl CommandHandler for Session {
+///     fn sign(&mut self, input: SignRequest) -> Result<Signature> { }
+///     fn reset(&mut self) -> Result<()> { }
+/// }
+/// ```
+#[proc_macro_derive(TaCommand, attributes(ta_command))]
+pub fn derive_ta_command(input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as syn::DeriveInput);
+    match dispatch::expand_ta_command(&item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Attribute to declare the entry point of invoking commands from the
+/// implementation of a handler trait generated by `#[derive(TaCommand)]`.
+///
+/// By default the implementing type is the session context, which has to be
+/// set up by `#[ta_open_session]`. With `#[ta_dispatch(stateless)]` a new
+/// value is created with `Default::default()` for every command instead.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[derive(Default)]
+/// struct Session { counter: u32 }
+///
+/// #[ta_dispatch]
+/// impl CommandHandler for Session {
+///     fn reset(&mut self) -> Result<()> {
+///         self.counter = 0;
+///         Ok(())
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn ta_dispatch(args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as syn::ItemImpl);
+    match dispatch::expand_ta_dispatch(args.into(), item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Attribute to declare the configuration of the TA, generating its header
+/// (UUID, flags, sizes, properties) in place of the `user_ta_header.rs` file
+/// included from the build script output, which then only has to call
+/// `optee_utee_build::link()`.
+///
+/// The UUID is given with `uuid`, or read from the file at `uuid_path`
+/// relative to the manifest of the TA (default: `../uuid.txt`). `version` and
+/// `description` default to the ones of the TA crate. Sizes are integers or
+/// strings with a `K` or `M` suffix, invalid sizes and strings fail the
+/// compilation.
+///
+/// Place it once on an item of the crate root, usually the `#[ta_create]`
+/// function.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[ta_config(
+///     flags = TA_FLAG_SINGLE_INSTANCE | TA_FLAG_MULTI_SESSION,
+///     data_size = "4M",
+///     stack_size = "512K",
+///     version = "0.2",
+///     description = "Key storage TA",
+/// )]
+/// #[ta_create]
+/// fn create() -> Result<()> {
+///     Ok(())
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn ta_config(args: TokenStream, input: TokenStream) -> TokenStream {
+    match ta_config::expand_ta_config(args.into(), input.into()) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Attribute to declare the self-tests of the TA, run by the client
+/// application with the command `optee_utee::self_test::COMMAND_ID` when the
+/// `self_test` feature of `optee-utee` is enabled.
+///
+/// Placed on an inline module, it collects the functions of the module
+/// marked with `#[ta_self_test]` too. They take no parameter and return a
+/// `Result<(), E>` where `E` converts into
+/// `optee_utee::self_test::Failure`, e.g. `optee_utee::Result<()>`. See the
+/// `optee_utee::self_test` module.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[cfg(feature = "self_test")]
+/// #[ta_self_test]
+/// mod self_tests {
+///     use optee_utee::self_test::TestResult;
+///
+///     #[ta_self_test]
+///     fn counter_starts_at_zero() -> TestResult {
+///         optee_utee::self_test_assert_eq!(super::Counter::default().get(), 0);
+///         Ok(())
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn ta_self_test(args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as syn::Item);
+    match self_test::expand_ta_self_test(args.into(), item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Whether `fn_arg` is `_: &CancellationToken`, possibly with a path.
+fn is_cancellation_token_arg(fn_arg: &syn::FnArg) -> bool {
+    if let syn::FnArg::Typed(ty) = fn_arg
+        && let syn::Type::Reference(type_ref) = ty.ty.as_ref()
+        && type_ref.mutability.is_none()
+        && let syn::Type::Path(path) = type_ref.elem.as_ref()
+    {
+        return path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "CancellationToken");
+    }
+    false
+}
+
 fn extract_fn_arg_mut_ref_type(fn_arg: &syn::FnArg) -> Result<&syn::Type, syn::parse::Error> {
     if let syn::FnArg::Typed(ty) = fn_arg
         && let syn::Type::Reference(type_ref) = ty.ty.as_ref()
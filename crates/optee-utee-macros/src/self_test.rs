@@ -0,0 +1,99 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Expansion of `#[ta_self_test]`.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+
+pub(crate) fn expand_ta_self_test(args: TokenStream, item: syn::Item) -> syn::Result<TokenStream> {
+    if !args.is_empty() {
+        return Err(syn::Error::new(
+            args.span(),
+            "`#[ta_self_test]` takes no arguments",
+        ));
+    }
+    let mut module = match item {
+        syn::Item::Mod(module) => module,
+        syn::Item::Fn(f) => {
+            return Err(syn::Error::new(
+                f.sig.ident.span(),
+                "a `#[ta_self_test]` function must be in a module marked with `#[ta_self_test]`",
+            ));
+        }
+        item => {
+            return Err(syn::Error::new(
+                item.span(),
+                "`#[ta_self_test]` must be placed on a module or on the functions inside it",
+            ));
+        }
+    };
+    let Some((_, items)) = &mut module.content else {
+        return Err(syn::Error::new(
+            module.span(),
+            "a `#[ta_self_test]` module must be declared inline",
+        ));
+    };
+
+    let mut tests = Vec::new();
+    for item in items.iter_mut() {
+        let syn::Item::Fn(f) = item else {
+            continue;
+        };
+        let attrs_len = f.attrs.len();
+        f.attrs.retain(|attr| !is_self_test_attr(attr));
+        if f.attrs.len() == attrs_len {
+            continue;
+        }
+        let sig = &f.sig;
+        let valid_signature = sig.constness.is_none()
+            && sig.asyncness.is_none()
+            && sig.abi.is_none()
+            && sig.inputs.is_empty()
+            && sig.generics.params.is_empty()
+            && sig.generics.where_clause.is_none()
+            && sig.variadic.is_none()
+            && !matches!(sig.output, syn::ReturnType::Default);
+        if !valid_signature {
+            return Err(syn::Error::new(
+                sig.span(),
+                "a `#[ta_self_test]` function must have signature `fn() -> Result<(), E>` with `E: Into<optee_utee::self_test::Failure>`",
+            ));
+        }
+        tests.push(sig.ident.clone());
+    }
+
+    items.push(syn::parse_quote! {
+        #[doc(hidden)]
+        #[unsafe(no_mangle)]
+        pub fn __optee_utee_self_tests(report: &mut optee_utee::self_test::Report<'_>) {
+            #(
+                report.run(concat!(module_path!(), "::", stringify!(#tests)), #tests);
+            )*
+        }
+    });
+    Ok(quote!(#module))
+}
+
+/// Whether `attr` is `#[ta_self_test]`, possibly with a path.
+fn is_self_test_attr(attr: &syn::Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "ta_self_test")
+}
@@ -0,0 +1,235 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Expansion of `#[ta_config]`.
+
+use optee_utee_build::{HeaderFileGenerator, TaConfig, ta_flag};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use std::path::PathBuf;
+use syn::spanned::Spanned;
+
+/// Default of `uuid_path`, the same as the one of cargo-optee.
+const DEFAULT_UUID_PATH: &str = "../uuid.txt";
+
+pub(crate) fn expand_ta_config(args: TokenStream, item: TokenStream) -> syn::Result<TokenStream> {
+    let mut uuid: Option<syn::LitStr> = None;
+    let mut uuid_path: Option<syn::LitStr> = None;
+    let mut flags = None;
+    let mut data_size = None;
+    let mut stack_size = None;
+    let mut framework_stack_size = None;
+    let mut version: Option<syn::LitStr> = None;
+    let mut description: Option<syn::LitStr> = None;
+    let mut trace_level: Option<syn::LitInt> = None;
+    let mut trace_ext_prefix: Option<syn::LitStr> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("uuid") {
+            uuid = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("uuid_path") {
+            uuid_path = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("flags") {
+            flags = Some(eval_flags(&meta.value()?.parse::<syn::Expr>()?)?);
+        } else if meta.path.is_ident("data_size") {
+            data_size = Some(parse_size(&meta.value()?.parse::<syn::Lit>()?)?);
+        } else if meta.path.is_ident("stack_size") {
+            stack_size = Some(parse_size(&meta.value()?.parse::<syn::Lit>()?)?);
+        } else if meta.path.is_ident("framework_stack_size") {
+            framework_stack_size = Some(parse_size(&meta.value()?.parse::<syn::Lit>()?)?);
+        } else if meta.path.is_ident("version") {
+            version = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("description") {
+            description = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("trace_level") {
+            trace_level = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("trace_ext_prefix") {
+            trace_ext_prefix = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error(
+                "expected `uuid`, `uuid_path`, `flags`, `data_size`, `stack_size`, \
+                `framework_stack_size`, `version`, `description`, `trace_level` or \
+                `trace_ext_prefix`",
+            ));
+        }
+        Ok(())
+    });
+    syn::parse::Parser::parse2(parser, args)?;
+
+    if let (Some(uuid), Some(_)) = (&uuid, &uuid_path) {
+        return Err(syn::Error::new(
+            uuid.span(),
+            "`uuid` and `uuid_path` are mutually exclusive",
+        ));
+    }
+    // Rebuilds the TA when the UUID file changes
+    let mut tracked_file = None;
+    let (uuid_str, uuid_span) = match uuid {
+        Some(uuid) => (uuid.value(), uuid.span()),
+        None => {
+            let (path, span) = match &uuid_path {
+                Some(path) => (path.value(), path.span()),
+                None => (DEFAULT_UUID_PATH.to_string(), Span::call_site()),
+            };
+            let path = manifest_dir(span)?.join(path);
+            let uuid = std::fs::read_to_string(&path).map_err(|e| {
+                syn::Error::new(span, format!("failed to read {}: {}", path.display(), e))
+            })?;
+            tracked_file = Some(path.display().to_string());
+            (uuid.trim().to_string(), span)
+        }
+    };
+
+    let cargo_env = |key: &str| std::env::var(key).unwrap_or_default();
+    let mut config = TaConfig::new_default(
+        &uuid_str,
+        &version.map_or_else(|| cargo_env("CARGO_PKG_VERSION"), |v| v.value()),
+        &description.map_or_else(|| cargo_env("CARGO_PKG_DESCRIPTION"), |v| v.value()),
+    )
+    .map_err(|e| syn::Error::new(uuid_span, e))?;
+    if let Some(flags) = flags {
+        config = config.ta_flags(flags);
+    }
+    if let Some(size) = data_size {
+        config = config.ta_data_size(size);
+    }
+    if let Some(size) = stack_size {
+        config = config.ta_stack_size(size);
+    }
+    if let Some(size) = framework_stack_size {
+        config = config.ta_framework_stack_size(size);
+    }
+    if let Some(level) = trace_level {
+        config = config.trace_level(level.base10_parse()?);
+    }
+    if let Some(prefix) = trace_ext_prefix {
+        config = config.trace_ext_prefix(prefix.value());
+    }
+
+    let header = HeaderFileGenerator::new()
+        .generate_tokens(&config)
+        .map_err(|e| syn::Error::new(Span::call_site(), e))?;
+    let tracked_file = tracked_file.map(|path| {
+        quote!(
+            const _: &[u8] = include_bytes!(#path);
+        )
+    });
+
+    Ok(quote! {
+        #item
+
+        #header
+        #tracked_file
+    })
+}
+
+fn manifest_dir(span: Span) -> syn::Result<PathBuf> {
+    std::env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .ok_or_else(|| syn::Error::new(span, "CARGO_MANIFEST_DIR is not set, use `uuid`"))
+}
+
+/// Evaluates `TA_FLAG_SINGLE_INSTANCE | TA_FLAG_MULTI_SESSION`, the flags may
+/// be paths like `optee_utee_sys::TA_FLAG_SINGLE_INSTANCE` or integers.
+fn eval_flags(expr: &syn::Expr) -> syn::Result<u32> {
+    match expr {
+        syn::Expr::Binary(binary) if matches!(binary.op, syn::BinOp::BitOr(_)) => {
+            Ok(eval_flags(&binary.left)? | eval_flags(&binary.right)?)
+        }
+        syn::Expr::Paren(paren) => eval_flags(&paren.expr),
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(int),
+            ..
+        }) => int.base10_parse(),
+        syn::Expr::Path(path) => {
+            let name = path
+                .path
+                .segments
+                .last()
+                .map(|segment| segment.ident.to_string())
+                .unwrap_or_default();
+            ta_flag(&name).ok_or_else(|| syn::Error::new(path.span(), "unknown TA flag"))
+        }
+        _ => Err(syn::Error::new(
+            expr.span(),
+            "expected TA flags combined with `|`",
+        )),
+    }
+}
+
+/// Parses a size in bytes, given as an integer or a string with a `K` or `M`
+/// suffix like `"512K"`.
+fn parse_size(lit: &syn::Lit) -> syn::Result<u32> {
+    let size = match lit {
+        syn::Lit::Int(int) => int.base10_parse().ok(),
+        syn::Lit::Str(s) => optee_utee_build::parse_size(&s.value()),
+        _ => None,
+    };
+    match size {
+        Some(0) => Err(syn::Error::new(lit.span(), "size must not be 0")),
+        Some(size) => Ok(size),
+        None => Err(syn::Error::new(
+            lit.span(),
+            "expected a size in bytes, like `4096`, `\"512K\"` or `\"4M\"`",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_flags() {
+        let expr = syn::parse_quote!(
+            optee_utee_sys::TA_FLAG_SINGLE_INSTANCE | (TA_FLAG_MULTI_SESSION | 0x10)
+        );
+        assert_eq!(eval_flags(&expr).unwrap(), (1 << 2) | (1 << 3) | (1 << 4));
+        assert!(eval_flags(&syn::parse_quote!(TA_FLAG_UNKNOWN)).is_err());
+        assert!(eval_flags(&syn::parse_quote!(TA_FLAG_SINGLE_INSTANCE + 1)).is_err());
+    }
+
+    #[test]
+    fn test_expand_ta_config() {
+        let tokens = expand_ta_config(
+            quote!(
+                uuid = "26509cec-4a2b-4935-87ab-762d89fbf0b0",
+                flags = TA_FLAG_SINGLE_INSTANCE,
+                data_size = "4M",
+                stack_size = 8192,
+                version = "0.2",
+                description = "test",
+            ),
+            quote!(
+                fn create() {}
+            ),
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("const TA_FLAGS : u32 = 4u32"));
+        assert!(tokens.contains("const TA_DATA_SIZE : u32 = 4194304u32"));
+        assert!(tokens.contains("b\"0.2\\0\""));
+
+        let err = expand_ta_config(
+            quote!(
+                uuid = "26509cec-4a2b-4935-87ab-762d89fbf0b0",
+                stack_size = "0"
+            ),
+            quote!(),
+        );
+        assert!(err.is_err());
+    }
+}
@@ -0,0 +1,88 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cancellation requests of the client.
+//!
+//! The test plays the client and requests the cancellation of the command
+//! with [`request`], which the TA sees once it unmasks cancellations:
+//!
+//! ```rust,ignore
+//! use optee_utee::mock;
+//!
+//! mock::cancellation::request();
+//! let err = long_running_command().unwrap_err();
+//! assert_eq!(err.kind(), ErrorKind::Cancel);
+//! ```
+
+use std::cell::Cell;
+
+thread_local! {
+    static REQUESTED: Cell<bool> = const { Cell::new(false) };
+    // Cancellations are masked when an entry point is called
+    static MASKED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Request the cancellation of the running command.
+pub fn request() {
+    REQUESTED.set(true);
+}
+
+pub(crate) fn reset() {
+    REQUESTED.set(false);
+    MASKED.set(true);
+}
+
+tee_api! {
+    fn TEE_GetCancellationFlag() -> bool {
+        REQUESTED.get() && !MASKED.get()
+    }
+}
+
+tee_api! {
+    fn TEE_UnmaskCancellation() -> bool {
+        MASKED.replace(false)
+    }
+}
+
+tee_api! {
+    fn TEE_MaskCancellation() -> bool {
+        MASKED.replace(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use optee_utee_sys as raw;
+
+    use super::*;
+
+    #[test]
+    fn test_cancellation_flag() {
+        crate::reset();
+        request();
+        // Masked until unmasked
+        assert!(!unsafe { raw::TEE_GetCancellationFlag() });
+        assert!(unsafe { raw::TEE_UnmaskCancellation() });
+        assert!(unsafe { raw::TEE_GetCancellationFlag() });
+        assert!(!unsafe { raw::TEE_MaskCancellation() });
+        assert!(!unsafe { raw::TEE_GetCancellationFlag() });
+
+        crate::reset();
+        unsafe { raw::TEE_UnmaskCancellation() };
+        assert!(!unsafe { raw::TEE_GetCancellationFlag() });
+    }
+}
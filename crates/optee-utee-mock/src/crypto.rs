@@ -0,0 +1,767 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Cryptographic operations backed by `ring`.
+//!
+//! The simulated algorithms are:
+//!
+//! | Class  | Algorithms |
+//! |--------|------------|
+//! | Digest | `TEE_ALG_SHA1`, `TEE_ALG_SHA256`, `TEE_ALG_SHA384`, `TEE_ALG_SHA512` |
+//! | MAC    | `TEE_ALG_HMAC_SHA1`, `TEE_ALG_HMAC_SHA256`, `TEE_ALG_HMAC_SHA384`, `TEE_ALG_HMAC_SHA512` |
+//! | AE     | `TEE_ALG_AES_GCM` with 128 or 256-bit keys, 96-bit nonces and 128-bit tags |
+//!
+//! Allocating an operation for any other algorithm fails with
+//! `TEE_ERROR_NOT_SUPPORTED`. AES-GCM buffers the data of `TEE_AEUpdate`,
+//! which outputs nothing, until the final call.
+//!
+//! `TEE_GenerateRandom` and `TEE_GenerateKey` return random bytes from the
+//! operating system.
+
+use std::ffi::c_void;
+
+use optee_utee_sys::{
+    TEE_ALG_AES_GCM, TEE_ALG_HMAC_SHA1, TEE_ALG_HMAC_SHA256, TEE_ALG_HMAC_SHA384,
+    TEE_ALG_HMAC_SHA512, TEE_ALG_SHA1, TEE_ALG_SHA256, TEE_ALG_SHA384, TEE_ALG_SHA512,
+    TEE_ERROR_MAC_INVALID, TEE_ERROR_NOT_SUPPORTED, TEE_ERROR_SHORT_BUFFER,
+    TEE_HANDLE_FLAG_INITIALIZED, TEE_HANDLE_FLAG_KEY_SET, TEE_MODE_DECRYPT, TEE_MODE_DIGEST,
+    TEE_MODE_ENCRYPT, TEE_MODE_MAC, TEE_OPERATION_AE, TEE_OPERATION_DIGEST, TEE_OPERATION_MAC,
+    TEE_ObjectHandle, TEE_OperationHandle, TEE_OperationInfo, TEE_Result, TEE_SUCCESS,
+    TEE_TYPE_AES, TEE_TYPE_GENERIC_SECRET, TEE_TYPE_HMAC_SHA1, TEE_TYPE_HMAC_SHA256,
+    TEE_TYPE_HMAC_SHA384, TEE_TYPE_HMAC_SHA512, TEE_USAGE_DECRYPT, TEE_USAGE_ENCRYPT,
+    TEE_USAGE_MAC,
+};
+use ring::{aead, digest, hmac, rand::SecureRandom};
+
+use crate::{object, slice, slice_mut, tee_panic, write_output};
+
+const GCM_NONCE_LEN: usize = 12;
+const GCM_TAG_LEN: usize = 16;
+
+/// Fill `buffer` with random bytes.
+pub(crate) fn fill_random(buffer: &mut [u8]) {
+    ring::rand::SystemRandom::new()
+        .fill(buffer)
+        .expect("the operating system should provide random bytes");
+}
+
+/// The algorithm and state of an operation of each class.
+enum State {
+    Digest {
+        algorithm: &'static digest::Algorithm,
+        context: digest::Context,
+    },
+    Mac {
+        algorithm: hmac::Algorithm,
+        key_type: u32,
+        key: Option<hmac::Key>,
+        /// The MAC being computed, between `TEE_MACInit` and the final call
+        context: Option<hmac::Context>,
+    },
+    Ae {
+        algorithm: &'static aead::Algorithm,
+        key: Option<aead::LessSafeKey>,
+        /// The encryption or decryption between `TEE_AEInit` and the final
+        /// call
+        gcm: Option<Gcm>,
+    },
+}
+
+struct Gcm {
+    nonce: [u8; GCM_NONCE_LEN],
+    aad: Vec<u8>,
+    data: Vec<u8>,
+}
+
+/// What a `TEE_OperationHandle` points to.
+struct Operation {
+    algorithm: u32,
+    mode: u32,
+    max_key_size: u32,
+    key_size: u32,
+    state: State,
+}
+
+impl Operation {
+    fn class(&self) -> u32 {
+        match self.state {
+            State::Digest { .. } => TEE_OPERATION_DIGEST,
+            State::Mac { .. } => TEE_OPERATION_MAC,
+            State::Ae { .. } => TEE_OPERATION_AE,
+        }
+    }
+
+    fn required_key_usage(&self) -> u32 {
+        match self.mode {
+            TEE_MODE_MAC => TEE_USAGE_MAC,
+            TEE_MODE_ENCRYPT => TEE_USAGE_ENCRYPT,
+            TEE_MODE_DECRYPT => TEE_USAGE_DECRYPT,
+            _ => 0,
+        }
+    }
+
+    fn has_key(&self) -> bool {
+        match &self.state {
+            State::Digest { .. } => false,
+            State::Mac { key, .. } => key.is_some(),
+            State::Ae { key, .. } => key.is_some(),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        match &self.state {
+            State::Digest { .. } => true,
+            State::Mac { context, .. } => context.is_some(),
+            State::Ae { gcm, .. } => gcm.is_some(),
+        }
+    }
+
+    /// Back to the state after the key was set.
+    fn reset(&mut self) {
+        match &mut self.state {
+            State::Digest { algorithm, context } => *context = digest::Context::new(algorithm),
+            State::Mac { context, .. } => *context = None,
+            State::Ae { gcm, .. } => *gcm = None,
+        }
+    }
+}
+
+/// # Safety
+///
+/// `handle` must be null or a handle allocated by `TEE_AllocateOperation`
+/// and not freed yet.
+unsafe fn operation<'a>(handle: TEE_OperationHandle, function: &str) -> &'a mut Operation {
+    if handle.is_null() {
+        tee_panic(function, "invalid operation handle");
+    }
+    unsafe { &mut *(handle as *mut Operation) }
+}
+
+/// The digest context of `operation`, panicking for other classes.
+fn digest_context<'a>(operation: &'a mut Operation, function: &str) -> &'a mut digest::Context {
+    match &mut operation.state {
+        State::Digest { context, .. } => context,
+        _ => tee_panic(function, "not a digest operation"),
+    }
+}
+
+/// The MAC being computed by `operation`, panicking if not initialized.
+fn mac_context<'a>(operation: &'a mut Operation, function: &str) -> &'a mut hmac::Context {
+    match &mut operation.state {
+        State::Mac {
+            context: Some(context),
+            ..
+        } => context,
+        State::Mac { .. } => tee_panic(function, "the MAC operation is not initialized"),
+        _ => tee_panic(function, "not a MAC operation"),
+    }
+}
+
+/// The key and the encryption or decryption of `operation`, panicking if not
+/// initialized.
+fn gcm<'a>(operation: &'a mut Operation, function: &str) -> (&'a aead::LessSafeKey, &'a mut Gcm) {
+    match &mut operation.state {
+        State::Ae {
+            key: Some(key),
+            gcm: Some(gcm),
+            ..
+        } => (key, gcm),
+        State::Ae { .. } => tee_panic(function, "the AE operation is not initialized"),
+        _ => tee_panic(function, "not an AE operation"),
+    }
+}
+
+/// The initial state of an operation, `None` if not simulated.
+fn initial_state(algorithm: u32, mode: u32, max_key_size: u32) -> Option<State> {
+    let digest = |algorithm: &'static digest::Algorithm| State::Digest {
+        algorithm,
+        context: digest::Context::new(algorithm),
+    };
+    let mac = |algorithm, key_type| State::Mac {
+        algorithm,
+        key_type,
+        key: None,
+        context: None,
+    };
+    let ae = |algorithm| State::Ae {
+        algorithm,
+        key: None,
+        gcm: None,
+    };
+    let state = match (algorithm, mode) {
+        (TEE_ALG_SHA1, TEE_MODE_DIGEST) => digest(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+        (TEE_ALG_SHA256, TEE_MODE_DIGEST) => digest(&digest::SHA256),
+        (TEE_ALG_SHA384, TEE_MODE_DIGEST) => digest(&digest::SHA384),
+        (TEE_ALG_SHA512, TEE_MODE_DIGEST) => digest(&digest::SHA512),
+        (TEE_ALG_HMAC_SHA1, TEE_MODE_MAC) => {
+            mac(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, TEE_TYPE_HMAC_SHA1)
+        }
+        (TEE_ALG_HMAC_SHA256, TEE_MODE_MAC) => mac(hmac::HMAC_SHA256, TEE_TYPE_HMAC_SHA256),
+        (TEE_ALG_HMAC_SHA384, TEE_MODE_MAC) => mac(hmac::HMAC_SHA384, TEE_TYPE_HMAC_SHA384),
+        (TEE_ALG_HMAC_SHA512, TEE_MODE_MAC) => mac(hmac::HMAC_SHA512, TEE_TYPE_HMAC_SHA512),
+        (TEE_ALG_AES_GCM, TEE_MODE_ENCRYPT | TEE_MODE_DECRYPT) => match max_key_size {
+            128 => ae(&aead::AES_128_GCM),
+            256 => ae(&aead::AES_256_GCM),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(state)
+}
+
+tee_api! {
+    fn TEE_AllocateOperation(
+        operation: *mut TEE_OperationHandle,
+        algorithm: u32,
+        mode: u32,
+        maxKeySize: u32,
+    ) -> TEE_Result {
+        unsafe { *operation = core::ptr::null_mut() };
+        let Some(state) = initial_state(algorithm, mode, maxKeySize) else {
+            return TEE_ERROR_NOT_SUPPORTED;
+        };
+        let allocated = Box::new(Operation {
+            algorithm,
+            mode,
+            max_key_size: maxKeySize,
+            key_size: 0,
+            state,
+        });
+        unsafe { *operation = Box::into_raw(allocated) as TEE_OperationHandle };
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_FreeOperation(operation: TEE_OperationHandle) {
+        if !operation.is_null() {
+            drop(unsafe { Box::from_raw(operation as *mut Operation) });
+        }
+    }
+}
+
+tee_api! {
+    fn TEE_ResetOperation(operation: TEE_OperationHandle) {
+        unsafe { self::operation(operation, "TEE_ResetOperation") }.reset();
+    }
+}
+
+tee_api! {
+    fn TEE_GetOperationInfo(operation: TEE_OperationHandle, operationInfo: *mut TEE_OperationInfo) {
+        let operation = unsafe { self::operation(operation, "TEE_GetOperationInfo") };
+        let digest_length = match &operation.state {
+            State::Digest { algorithm, .. } => algorithm.output_len(),
+            State::Mac { algorithm, .. } => algorithm.digest_algorithm().output_len(),
+            State::Ae { .. } => 0,
+        };
+        let mut handle_state = 0;
+        if operation.has_key() {
+            handle_state |= TEE_HANDLE_FLAG_KEY_SET;
+        }
+        if operation.is_active() {
+            handle_state |= TEE_HANDLE_FLAG_INITIALIZED;
+        }
+        unsafe {
+            *operationInfo = TEE_OperationInfo {
+                algorithm: operation.algorithm,
+                operationClass: operation.class(),
+                mode: operation.mode,
+                digestLength: digest_length as u32,
+                maxKeySize: operation.max_key_size,
+                keySize: operation.key_size,
+                requiredKeyUsage: operation.required_key_usage(),
+                handleState: handle_state,
+            }
+        };
+    }
+}
+
+tee_api! {
+    fn TEE_SetOperationKey(operation: TEE_OperationHandle, key: TEE_ObjectHandle) -> TEE_Result {
+        let function = "TEE_SetOperationKey";
+        let operation = unsafe { self::operation(operation, function) };
+        if operation.is_active() && operation.class() != TEE_OPERATION_DIGEST {
+            tee_panic(function, "the operation is active");
+        }
+        if key.is_null() {
+            match &mut operation.state {
+                State::Digest { .. } => tee_panic(function, "digests take no key"),
+                State::Mac { key, .. } => *key = None,
+                State::Ae { key, .. } => *key = None,
+            }
+            operation.key_size = 0;
+            return TEE_SUCCESS;
+        }
+        let object = unsafe { object::object(key, function) };
+        let Some(secret) = object.secret().filter(|_| object.initialized) else {
+            tee_panic(function, "the key object is not initialized");
+        };
+        if object.object_size > operation.max_key_size {
+            tee_panic(function, "the key is larger than the maximum key size");
+        }
+        if object.usage & operation.required_key_usage() != operation.required_key_usage() {
+            tee_panic(function, "the key usage does not allow the operation");
+        }
+        match &mut operation.state {
+            State::Digest { .. } => tee_panic(function, "digests take no key"),
+            State::Mac {
+                algorithm,
+                key_type,
+                key,
+                ..
+            } => {
+                if object.object_type != *key_type && object.object_type != TEE_TYPE_GENERIC_SECRET
+                {
+                    tee_panic(function, "the key type does not match the algorithm");
+                }
+                *key = Some(hmac::Key::new(*algorithm, secret));
+            }
+            State::Ae { algorithm, key, .. } => {
+                if object.object_type != TEE_TYPE_AES {
+                    tee_panic(function, "the key type does not match the algorithm");
+                }
+                let Ok(unbound) = aead::UnboundKey::new(algorithm, secret) else {
+                    tee_panic(function, "the key size does not match the operation");
+                };
+                *key = Some(aead::LessSafeKey::new(unbound));
+            }
+        }
+        operation.key_size = object.object_size;
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_DigestUpdate(operation: TEE_OperationHandle, chunk: *const c_void, chunkSize: usize) {
+        let function = "TEE_DigestUpdate";
+        let operation = unsafe { self::operation(operation, function) };
+        digest_context(operation, function).update(unsafe { slice(chunk, chunkSize) });
+    }
+}
+
+tee_api! {
+    fn TEE_DigestDoFinal(
+        operation: TEE_OperationHandle,
+        chunk: *const c_void,
+        chunkLen: usize,
+        hash: *mut c_void,
+        hashLen: *mut usize,
+    ) -> TEE_Result {
+        let function = "TEE_DigestDoFinal";
+        let operation = unsafe { self::operation(operation, function) };
+        // The operation is left unchanged if the output buffer is too short
+        let mut context = digest_context(operation, function).clone();
+        context.update(unsafe { slice(chunk, chunkLen) });
+        let result = unsafe { write_output(context.finish().as_ref(), hash, hashLen) };
+        if result == TEE_SUCCESS {
+            operation.reset();
+        }
+        result
+    }
+}
+
+tee_api! {
+    fn TEE_MACInit(operation: TEE_OperationHandle, IV: *const c_void, IVLen: usize) {
+        let _ = (IV, IVLen);
+        let function = "TEE_MACInit";
+        let operation = unsafe { self::operation(operation, function) };
+        match &mut operation.state {
+            State::Mac {
+                key: Some(key),
+                context,
+                ..
+            } => *context = Some(hmac::Context::with_key(key)),
+            State::Mac { .. } => tee_panic(function, "no key is set"),
+            _ => tee_panic(function, "not a MAC operation"),
+        }
+    }
+}
+
+tee_api! {
+    fn TEE_MACUpdate(operation: TEE_OperationHandle, chunk: *const c_void, chunkSize: usize) {
+        let function = "TEE_MACUpdate";
+        let operation = unsafe { self::operation(operation, function) };
+        mac_context(operation, function).update(unsafe { slice(chunk, chunkSize) });
+    }
+}
+
+/// The MAC of the data of `operation` followed by `message`.
+fn mac(operation: &mut Operation, message: &[u8], function: &str) -> hmac::Tag {
+    let mut context = mac_context(operation, function).clone();
+    context.update(message);
+    context.sign()
+}
+
+tee_api! {
+    fn TEE_MACComputeFinal(
+        operation: TEE_OperationHandle,
+        message: *const c_void,
+        messageLen: usize,
+        mac: *mut c_void,
+        macLen: *mut usize,
+    ) -> TEE_Result {
+        let function = "TEE_MACComputeFinal";
+        let operation = unsafe { self::operation(operation, function) };
+        let tag = self::mac(operation, unsafe { slice(message, messageLen) }, function);
+        let result = unsafe { write_output(tag.as_ref(), mac, macLen) };
+        if result == TEE_SUCCESS {
+            operation.reset();
+        }
+        result
+    }
+}
+
+tee_api! {
+    fn TEE_MACCompareFinal(
+        operation: TEE_OperationHandle,
+        message: *const c_void,
+        messageLen: usize,
+        mac: *const c_void,
+        macLen: usize,
+    ) -> TEE_Result {
+        let function = "TEE_MACCompareFinal";
+        let operation = unsafe { self::operation(operation, function) };
+        let tag = self::mac(operation, unsafe { slice(message, messageLen) }, function);
+        operation.reset();
+        let expected = unsafe { slice(mac, macLen) };
+        if !constant_time_eq(tag.as_ref(), expected) {
+            return TEE_ERROR_MAC_INVALID;
+        }
+        TEE_SUCCESS
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+tee_api! {
+    fn TEE_AEInit(
+        operation: TEE_OperationHandle,
+        nonce: *const c_void,
+        nonceLen: usize,
+        tagLen: u32,
+        AADLen: usize,
+        payloadLen: usize,
+    ) -> TEE_Result {
+        let _ = (AADLen, payloadLen);
+        let function = "TEE_AEInit";
+        let operation = unsafe { self::operation(operation, function) };
+        let State::Ae { key, gcm, .. } = &mut operation.state else {
+            tee_panic(function, "not an AE operation");
+        };
+        if key.is_none() {
+            tee_panic(function, "no key is set");
+        }
+        let Ok(nonce) = <[u8; GCM_NONCE_LEN]>::try_from(unsafe { slice(nonce, nonceLen) }) else {
+            return TEE_ERROR_NOT_SUPPORTED;
+        };
+        if tagLen as usize != GCM_TAG_LEN * 8 {
+            return TEE_ERROR_NOT_SUPPORTED;
+        }
+        *gcm = Some(Gcm {
+            nonce,
+            aad: Vec::new(),
+            data: Vec::new(),
+        });
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_AEUpdateAAD(operation: TEE_OperationHandle, AADdata: *const c_void, AADdataLen: usize) {
+        let function = "TEE_AEUpdateAAD";
+        let (_, gcm) = gcm(unsafe { self::operation(operation, function) }, function);
+        if !gcm.data.is_empty() {
+            tee_panic(function, "the payload was already updated");
+        }
+        gcm.aad.extend_from_slice(unsafe { slice(AADdata, AADdataLen) });
+    }
+}
+
+tee_api! {
+    fn TEE_AEUpdate(
+        operation: TEE_OperationHandle,
+        srcData: *const c_void,
+        srcLen: usize,
+        destData: *mut c_void,
+        destLen: *mut usize,
+    ) -> TEE_Result {
+        let _ = destData;
+        let function = "TEE_AEUpdate";
+        let (_, gcm) = gcm(unsafe { self::operation(operation, function) }, function);
+        gcm.data.extend_from_slice(unsafe { slice(srcData, srcLen) });
+        unsafe { *destLen = 0 };
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_AEEncryptFinal(
+        operation: TEE_OperationHandle,
+        srcData: *const c_void,
+        srcLen: usize,
+        destData: *mut c_void,
+        destLen: *mut usize,
+        tag: *mut c_void,
+        tagLen: *mut usize,
+    ) -> TEE_Result {
+        let function = "TEE_AEEncryptFinal";
+        let operation = unsafe { self::operation(operation, function) };
+        if operation.mode != TEE_MODE_ENCRYPT {
+            tee_panic(function, "not an encryption");
+        }
+        let (key, gcm) = gcm(operation, function);
+        let mut data = [gcm.data.as_slice(), unsafe { slice(srcData, srcLen) }].concat();
+        if unsafe { *destLen } < data.len() || unsafe { *tagLen } < GCM_TAG_LEN {
+            unsafe {
+                *destLen = data.len();
+                *tagLen = GCM_TAG_LEN;
+            }
+            return TEE_ERROR_SHORT_BUFFER;
+        }
+        let computed = key
+            .seal_in_place_separate_tag(
+                aead::Nonce::assume_unique_for_key(gcm.nonce),
+                aead::Aad::from(&gcm.aad),
+                &mut data,
+            )
+            .unwrap_or_else(|_| tee_panic(function, "the payload is too large"));
+        unsafe {
+            write_output(&data, destData, destLen);
+            write_output(computed.as_ref(), tag, tagLen);
+        }
+        operation.reset();
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_AEDecryptFinal(
+        operation: TEE_OperationHandle,
+        srcData: *const c_void,
+        srcLen: usize,
+        destData: *mut c_void,
+        destLen: *mut usize,
+        tag: *mut c_void,
+        tagLen: usize,
+    ) -> TEE_Result {
+        let function = "TEE_AEDecryptFinal";
+        let operation = unsafe { self::operation(operation, function) };
+        if operation.mode != TEE_MODE_DECRYPT {
+            tee_panic(function, "not a decryption");
+        }
+        let (key, gcm) = gcm(operation, function);
+        let mut data = [gcm.data.as_slice(), unsafe { slice(srcData, srcLen) }].concat();
+        if unsafe { *destLen } < data.len() {
+            unsafe { *destLen = data.len() };
+            return TEE_ERROR_SHORT_BUFFER;
+        }
+        let received = unsafe { slice(tag as *const c_void, tagLen) };
+        let result = if received.len() != GCM_TAG_LEN {
+            TEE_ERROR_MAC_INVALID
+        } else {
+            data.extend_from_slice(received);
+            match key.open_in_place(
+                aead::Nonce::assume_unique_for_key(gcm.nonce),
+                aead::Aad::from(&gcm.aad),
+                &mut data,
+            ) {
+                Ok(plaintext) => unsafe { write_output(plaintext, destData, destLen) },
+                Err(_) => TEE_ERROR_MAC_INVALID,
+            }
+        };
+        operation.reset();
+        result
+    }
+}
+
+tee_api! {
+    fn TEE_GenerateRandom(randomBuffer: *mut c_void, randomBufferLen: usize) {
+        fill_random(unsafe { slice_mut(randomBuffer, randomBufferLen) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use optee_utee_sys as raw;
+
+    use super::*;
+
+    fn allocate(algorithm: u32, mode: u32, max_key_size: u32) -> TEE_OperationHandle {
+        let mut operation = core::ptr::null_mut();
+        let result =
+            unsafe { raw::TEE_AllocateOperation(&mut operation, algorithm, mode, max_key_size) };
+        assert_eq!(result, TEE_SUCCESS);
+        operation
+    }
+
+    fn key(object_type: u32, secret: &[u8]) -> TEE_ObjectHandle {
+        let size = secret.len() as u32 * 8;
+        let mut object = core::ptr::null_mut();
+        let mut attribute = raw::TEE_Attribute {
+            attributeID: raw::TEE_ATTR_SECRET_VALUE,
+            content: raw::content {
+                value: raw::Value { a: 0, b: 0 },
+            },
+        };
+        unsafe {
+            assert_eq!(
+                raw::TEE_AllocateTransientObject(object_type, size, &mut object),
+                0
+            );
+            raw::TEE_InitRefAttribute(
+                &mut attribute,
+                raw::TEE_ATTR_SECRET_VALUE,
+                secret.as_ptr() as _,
+                secret.len(),
+            );
+            assert_eq!(raw::TEE_PopulateTransientObject(object, &attribute, 1), 0);
+        }
+        object
+    }
+
+    #[test]
+    fn test_digest() {
+        let operation = allocate(TEE_ALG_SHA256, TEE_MODE_DIGEST, 0);
+        let mut hash = [0u8; 32];
+        let mut len = 16;
+        unsafe {
+            raw::TEE_DigestUpdate(operation, b"a".as_ptr() as _, 1);
+            let result = raw::TEE_DigestDoFinal(
+                operation,
+                b"bc".as_ptr() as _,
+                2,
+                hash.as_mut_ptr() as _,
+                &mut len,
+            );
+            assert_eq!((result, len), (TEE_ERROR_SHORT_BUFFER, 32));
+            let result = raw::TEE_DigestDoFinal(
+                operation,
+                b"bc".as_ptr() as _,
+                2,
+                hash.as_mut_ptr() as _,
+                &mut len,
+            );
+            assert_eq!(result, TEE_SUCCESS);
+            raw::TEE_FreeOperation(operation);
+        }
+        assert_eq!(
+            hash.as_slice(),
+            digest::digest(&digest::SHA256, b"abc").as_ref()
+        );
+    }
+
+    #[test]
+    fn test_hmac() {
+        let operation = allocate(TEE_ALG_HMAC_SHA256, TEE_MODE_MAC, 256);
+        let key = key(TEE_TYPE_HMAC_SHA256, &[7; 32]);
+        let expected = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &[7; 32]), b"message");
+        unsafe {
+            assert_eq!(raw::TEE_SetOperationKey(operation, key), TEE_SUCCESS);
+            raw::TEE_MACInit(operation, core::ptr::null(), 0);
+            raw::TEE_MACUpdate(operation, b"mess".as_ptr() as _, 4);
+            let result = raw::TEE_MACCompareFinal(
+                operation,
+                b"age".as_ptr() as _,
+                3,
+                expected.as_ref().as_ptr() as _,
+                expected.as_ref().len(),
+            );
+            assert_eq!(result, TEE_SUCCESS);
+            raw::TEE_MACInit(operation, core::ptr::null(), 0);
+            let result = raw::TEE_MACCompareFinal(
+                operation,
+                b"other".as_ptr() as _,
+                5,
+                expected.as_ref().as_ptr() as _,
+                expected.as_ref().len(),
+            );
+            assert_eq!(result, TEE_ERROR_MAC_INVALID);
+            raw::TEE_FreeOperation(operation);
+            raw::TEE_FreeTransientObject(key);
+        }
+    }
+
+    /// Encrypt or decrypt `data` with AES-128-GCM, returning the output and
+    /// the result of the final call.
+    fn gcm(mode: u32, data: &[u8], tag: &mut [u8; GCM_TAG_LEN]) -> (TEE_Result, Vec<u8>) {
+        let operation = allocate(TEE_ALG_AES_GCM, mode, 128);
+        let key = key(TEE_TYPE_AES, &[1; 16]);
+        let nonce = [2u8; GCM_NONCE_LEN];
+        let mut output = vec![0u8; data.len()];
+        let mut output_len = output.len();
+        let mut tag_len = tag.len();
+        let result = unsafe {
+            assert_eq!(raw::TEE_SetOperationKey(operation, key), TEE_SUCCESS);
+            let result = raw::TEE_AEInit(operation, nonce.as_ptr() as _, nonce.len(), 128, 0, 0);
+            assert_eq!(result, TEE_SUCCESS);
+            raw::TEE_AEUpdateAAD(operation, b"header".as_ptr() as _, 6);
+            let result = if mode == TEE_MODE_ENCRYPT {
+                raw::TEE_AEEncryptFinal(
+                    operation,
+                    data.as_ptr() as _,
+                    data.len(),
+                    output.as_mut_ptr() as _,
+                    &mut output_len,
+                    tag.as_mut_ptr() as _,
+                    &mut tag_len,
+                )
+            } else {
+                raw::TEE_AEDecryptFinal(
+                    operation,
+                    data.as_ptr() as _,
+                    data.len(),
+                    output.as_mut_ptr() as _,
+                    &mut output_len,
+                    tag.as_mut_ptr() as _,
+                    tag_len,
+                )
+            };
+            raw::TEE_FreeOperation(operation);
+            raw::TEE_FreeTransientObject(key);
+            result
+        };
+        output.truncate(output_len);
+        (result, output)
+    }
+
+    #[test]
+    fn test_aes_gcm() {
+        let mut tag = [0u8; GCM_TAG_LEN];
+        let (result, ciphertext) = gcm(TEE_MODE_ENCRYPT, b"plaintext", &mut tag);
+        assert_eq!(result, TEE_SUCCESS);
+        assert_ne!(ciphertext.as_slice(), b"plaintext");
+
+        let (result, plaintext) = gcm(TEE_MODE_DECRYPT, &ciphertext, &mut tag);
+        assert_eq!(
+            (result, plaintext.as_slice()),
+            (TEE_SUCCESS, b"plaintext".as_slice())
+        );
+        tag[0] ^= 1;
+        let (result, _) = gcm(TEE_MODE_DECRYPT, &ciphertext, &mut tag);
+        assert_eq!(result, TEE_ERROR_MAC_INVALID);
+    }
+
+    #[test]
+    fn test_unsupported_algorithm() {
+        let mut operation = core::ptr::null_mut();
+        let result = unsafe {
+            raw::TEE_AllocateOperation(&mut operation, raw::TEE_ALG_MD5, TEE_MODE_DIGEST, 0)
+        };
+        assert_eq!(result, TEE_ERROR_NOT_SUPPORTED);
+    }
+}
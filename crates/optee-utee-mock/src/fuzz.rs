@@ -0,0 +1,603 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Adversarial invocations of the command entry point of a TA, to fuzz its
+//! parameter handling and the decoding of its commands.
+//!
+//! An [`Invocation`] is the command ID and the four parameters a client
+//! passes, including what a well-behaved client never sends: types the
+//! command does not expect, types the specification reserves, null memrefs
+//! with a size, empty buffers and truncated or garbage payloads.
+//! [`Invocation::invoke`] runs it through the `TA_InvokeCommandEntryPoint`
+//! generated by `#[ta_invoke_command]` or `#[ta_dispatch]`, and panics when
+//! the TA breaks the contract of the entry point: writing past the end of a
+//! memref, modifying an input memref, or returning success with an output
+//! size larger than the buffer.
+//!
+//! [`Invocation::from_bytes`] decodes an invocation from any input, for the
+//! fuzz targets of cargo-fuzz:
+//!
+//! ```rust,ignore
+//! #![no_main]
+//! use libfuzzer_sys::fuzz_target;
+//! use optee_utee::mock::{self, fuzz::Invocation};
+//!
+//! fuzz_target!(|data: &[u8]| {
+//!     mock::reset();
+//!     Invocation::from_bytes(data).invoke(TA_InvokeCommandEntryPoint, core::ptr::null_mut());
+//! });
+//! ```
+//!
+//! and [`Invocation::to_bytes`] encodes the invocations of a real client as
+//! the seeds of its corpus. In unit tests, [`Invocation::truncations`] checks
+//! that a command rejects every prefix of a valid payload:
+//!
+//! ```rust,ignore
+//! let request = Invocation::new(CMD_SIGN)
+//!     .param(0, Param::MemrefInput(Memref::Buffer(serde_json::to_vec(&sign_request)?)))
+//!     .param(1, Param::MemrefOutput(Memref::Buffer(vec![0; 64])));
+//! for truncated in request.truncations() {
+//!     let outcome = truncated.invoke(TA_InvokeCommandEntryPoint, core::ptr::null_mut());
+//!     assert_ne!(outcome.result, TEE_SUCCESS);
+//! }
+//! ```
+//!
+//! A TA whose entry point uses a session context must be given the context
+//! returned by its `TA_OpenSessionEntryPoint`.
+
+use std::ffi::c_void;
+
+use optee_utee_sys::{
+    Memref as RawMemref, TEE_NUM_PARAMS, TEE_PARAM_TYPE_MEMREF_INOUT, TEE_PARAM_TYPE_MEMREF_INPUT,
+    TEE_PARAM_TYPE_MEMREF_OUTPUT, TEE_PARAM_TYPE_NONE, TEE_PARAM_TYPE_VALUE_INOUT,
+    TEE_PARAM_TYPE_VALUE_INPUT, TEE_PARAM_TYPE_VALUE_OUTPUT, TEE_PARAM_TYPES, TEE_Param,
+    TEE_Result, TEE_SUCCESS, Value as RawValue,
+};
+
+/// The signature of the `TA_InvokeCommandEntryPoint` generated by the
+/// macros of optee-utee.
+pub type InvokeCommandEntryPoint =
+    extern "C" fn(*mut c_void, u32, u32, &mut [TEE_Param; TEE_NUM_PARAMS as usize]) -> TEE_Result;
+
+/// Parameter types the specification reserves, which OP-TEE passes on.
+const RESERVED_TYPES: [u32; 9] = [4, 8, 9, 10, 11, 12, 13, 14, 15];
+
+/// Bytes after every memref buffer, which the TA must not write to.
+const GUARD_SIZE: usize = 16;
+const GUARD_BYTE: u8 = 0xa5;
+
+/// A parameter as passed by the client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Param {
+    None,
+    ValueInput {
+        a: u32,
+        b: u32,
+    },
+    /// A value output, whose initial content the TA must not rely on.
+    ValueOutput {
+        a: u32,
+        b: u32,
+    },
+    ValueInout {
+        a: u32,
+        b: u32,
+    },
+    MemrefInput(Memref),
+    MemrefOutput(Memref),
+    MemrefInout(Memref),
+    /// A reserved type tag, with a zeroed content.
+    Reserved(u32),
+}
+
+/// The buffer of a memref parameter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Memref {
+    /// A buffer with this content, of its length.
+    Buffer(Vec<u8>),
+    /// A null buffer of this size, as passed by a client querying the size
+    /// of an output.
+    Null(usize),
+}
+
+/// What the client receives of a parameter once the command returned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// An input or a parameter without content.
+    None,
+    Value {
+        a: u32,
+        b: u32,
+    },
+    /// The size the TA reported and the content of the buffer up to that
+    /// size, or up to the end of the buffer for a short buffer.
+    Memref {
+        size: usize,
+        data: Vec<u8>,
+    },
+}
+
+/// The result of an [`Invocation`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub result: TEE_Result,
+    pub params: [Output; 4],
+}
+
+/// A command invocation of a client, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation {
+    pub cmd_id: u32,
+    pub params: [Param; 4],
+}
+
+impl Invocation {
+    /// Invocation of `cmd_id` without parameters.
+    pub fn new(cmd_id: u32) -> Self {
+        Self {
+            cmd_id,
+            params: [Param::None, Param::None, Param::None, Param::None],
+        }
+    }
+
+    /// Set the parameter at `index`.
+    pub fn param(mut self, index: usize, param: Param) -> Self {
+        self.params[index] = param;
+        self
+    }
+
+    /// The raw parameter types of the invocation.
+    pub fn param_types(&self) -> u32 {
+        let [t0, t1, t2, t3] = self.params.each_ref().map(Param::raw_type);
+        TEE_PARAM_TYPES(t0, t1, t2, t3)
+    }
+
+    /// Decode an invocation from `data`, any input decoding to one.
+    ///
+    /// The command ID comes first as 4 little-endian bytes, then each
+    /// parameter as a tag byte selecting its type followed by its content:
+    /// two little-endian `u32` for a value, and for a memref a flag byte, a
+    /// null buffer of a little-endian `u32` size if its lowest bit is set,
+    /// or a buffer of a little-endian `u16` length and its bytes. Missing
+    /// bytes read as zeros.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let mut reader = Reader(data);
+        let cmd_id = reader.u32();
+        let params = [(); 4].map(|_| Param::read(&mut reader));
+        Self { cmd_id, params }
+    }
+
+    /// Encode the invocation as [`from_bytes`](Self::from_bytes) decodes
+    /// it, e.g. to seed the corpus of a fuzz target. Memref buffers are cut
+    /// to 65535 bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.cmd_id.to_le_bytes().to_vec();
+        for param in &self.params {
+            param.write(&mut bytes);
+        }
+        bytes
+    }
+
+    /// The invocation with the buffer of an input or in/out memref cut
+    /// short, once for each shorter length of each such buffer.
+    pub fn truncations(&self) -> impl Iterator<Item = Invocation> + '_ {
+        (0..self.params.len()).flat_map(move |index| {
+            let len = match &self.params[index] {
+                Param::MemrefInput(Memref::Buffer(data))
+                | Param::MemrefInout(Memref::Buffer(data)) => data.len(),
+                _ => 0,
+            };
+            (0..len).map(move |truncated| {
+                let mut invocation = self.clone();
+                if let Param::MemrefInput(Memref::Buffer(data))
+                | Param::MemrefInout(Memref::Buffer(data)) = &mut invocation.params[index]
+                {
+                    data.truncate(truncated);
+                }
+                invocation
+            })
+        })
+    }
+
+    /// Invoke the command with `entry_point`, in the session of
+    /// `session_context`.
+    ///
+    /// # Panics
+    ///
+    /// If the TA writes past the end of a memref, modifies an input memref,
+    /// or returns `TEE_SUCCESS` with an output size larger than the buffer.
+    /// A panic of the TA cannot unwind out of the entry point and aborts.
+    pub fn invoke(
+        &self,
+        entry_point: InvokeCommandEntryPoint,
+        session_context: *mut c_void,
+    ) -> Outcome {
+        // Kept until the checks, with a guard after the content
+        let mut buffers: [Option<Vec<u8>>; 4] = self.params.each_ref().map(|param| {
+            param.buffer().map(|data| {
+                let mut buffer = data.to_vec();
+                buffer.resize(data.len() + GUARD_SIZE, GUARD_BYTE);
+                buffer
+            })
+        });
+        let mut raw_params: [TEE_Param; 4] = [0, 1, 2, 3].map(|index| {
+            let buffer = buffers[index].as_mut().map(|buffer| buffer.as_mut_ptr());
+            self.params[index].to_raw(buffer)
+        });
+
+        let result = entry_point(
+            session_context,
+            self.cmd_id,
+            self.param_types(),
+            &mut raw_params,
+        );
+
+        let params = [0, 1, 2, 3].map(|index| {
+            self.params[index].check(index, result, &raw_params[index], buffers[index].as_deref())
+        });
+        Outcome { result, params }
+    }
+}
+
+impl Param {
+    fn raw_type(&self) -> u32 {
+        match self {
+            Param::None => TEE_PARAM_TYPE_NONE,
+            Param::ValueInput { .. } => TEE_PARAM_TYPE_VALUE_INPUT,
+            Param::ValueOutput { .. } => TEE_PARAM_TYPE_VALUE_OUTPUT,
+            Param::ValueInout { .. } => TEE_PARAM_TYPE_VALUE_INOUT,
+            Param::MemrefInput(_) => TEE_PARAM_TYPE_MEMREF_INPUT,
+            Param::MemrefOutput(_) => TEE_PARAM_TYPE_MEMREF_OUTPUT,
+            Param::MemrefInout(_) => TEE_PARAM_TYPE_MEMREF_INOUT,
+            Param::Reserved(raw_type) => *raw_type,
+        }
+    }
+
+    fn memref(&self) -> Option<&Memref> {
+        match self {
+            Param::MemrefInput(memref)
+            | Param::MemrefOutput(memref)
+            | Param::MemrefInout(memref) => Some(memref),
+            _ => None,
+        }
+    }
+
+    /// The content of the buffer of a memref, unless null.
+    fn buffer(&self) -> Option<&[u8]> {
+        match self.memref()? {
+            Memref::Buffer(data) => Some(data),
+            Memref::Null(_) => None,
+        }
+    }
+
+    fn to_raw(&self, buffer: Option<*mut u8>) -> TEE_Param {
+        match self {
+            Param::ValueInput { a, b }
+            | Param::ValueOutput { a, b }
+            | Param::ValueInout { a, b } => TEE_Param {
+                value: RawValue { a: *a, b: *b },
+            },
+            Param::MemrefInput(memref)
+            | Param::MemrefOutput(memref)
+            | Param::MemrefInout(memref) => {
+                let (buffer, size) = match memref {
+                    Memref::Buffer(data) => (buffer.unwrap_or(std::ptr::null_mut()), data.len()),
+                    Memref::Null(size) => (std::ptr::null_mut(), *size),
+                };
+                TEE_Param {
+                    memref: RawMemref {
+                        buffer: buffer as *mut c_void,
+                        size,
+                    },
+                }
+            }
+            Param::None | Param::Reserved(_) => TEE_Param {
+                memref: RawMemref {
+                    buffer: std::ptr::null_mut(),
+                    size: 0,
+                },
+            },
+        }
+    }
+
+    /// Check what the TA did with the parameter at `index`, whose buffer
+    /// with its guard is `buffer`, and return what the client receives.
+    fn check(
+        &self,
+        index: usize,
+        result: TEE_Result,
+        raw_param: &TEE_Param,
+        buffer: Option<&[u8]>,
+    ) -> Output {
+        let capacity = self.buffer().map_or(0, <[u8]>::len);
+        if let Some(buffer) = buffer
+            && buffer[capacity..].iter().any(|byte| *byte != GUARD_BYTE)
+        {
+            panic!(
+                "TA wrote past the end of the {} bytes of memref {}",
+                capacity, index
+            );
+        }
+        match self {
+            Param::MemrefInput(Memref::Buffer(data)) => {
+                if buffer.is_some_and(|buffer| buffer[..capacity] != data[..]) {
+                    panic!("TA modified the buffer of memref input {}", index);
+                }
+                Output::None
+            }
+            Param::MemrefOutput(_) | Param::MemrefInout(_) => {
+                let size = unsafe { raw_param.memref.size };
+                if result == TEE_SUCCESS && size > capacity {
+                    panic!(
+                        "TA returned TEE_SUCCESS with {} bytes in memref {} of {} bytes",
+                        size, index, capacity
+                    );
+                }
+                let data =
+                    buffer.map_or(Vec::new(), |buffer| buffer[..size.min(capacity)].to_vec());
+                Output::Memref { size, data }
+            }
+            Param::ValueOutput { .. } | Param::ValueInout { .. } => {
+                let RawValue { a, b } = unsafe { raw_param.value };
+                Output::Value { a, b }
+            }
+            _ => Output::None,
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Self {
+        let tag = reader.u8();
+        let value = |reader: &mut Reader| (reader.u32(), reader.u32());
+        match tag % 8 {
+            0 => Param::None,
+            1 => {
+                let (a, b) = value(reader);
+                Param::ValueInput { a, b }
+            }
+            2 => {
+                let (a, b) = value(reader);
+                Param::ValueOutput { a, b }
+            }
+            3 => {
+                let (a, b) = value(reader);
+                Param::ValueInout { a, b }
+            }
+            4 => Param::MemrefInput(Memref::read(reader)),
+            5 => Param::MemrefOutput(Memref::read(reader)),
+            6 => Param::MemrefInout(Memref::read(reader)),
+            _ => Param::Reserved(RESERVED_TYPES[usize::from(tag / 8) % RESERVED_TYPES.len()]),
+        }
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        let mut value = |tag: u8, a: &u32, b: &u32| {
+            bytes.push(tag);
+            bytes.extend_from_slice(&a.to_le_bytes());
+            bytes.extend_from_slice(&b.to_le_bytes());
+        };
+        match self {
+            Param::None => bytes.push(0),
+            Param::ValueInput { a, b } => value(1, a, b),
+            Param::ValueOutput { a, b } => value(2, a, b),
+            Param::ValueInout { a, b } => value(3, a, b),
+            Param::MemrefInput(memref) => {
+                bytes.push(4);
+                memref.write(bytes);
+            }
+            Param::MemrefOutput(memref) => {
+                bytes.push(5);
+                memref.write(bytes);
+            }
+            Param::MemrefInout(memref) => {
+                bytes.push(6);
+                memref.write(bytes);
+            }
+            Param::Reserved(raw_type) => {
+                let index = RESERVED_TYPES
+                    .iter()
+                    .position(|reserved| reserved == raw_type)
+                    .unwrap_or(0);
+                bytes.push(7 + 8 * index as u8);
+            }
+        }
+    }
+}
+
+impl Memref {
+    fn read(reader: &mut Reader) -> Self {
+        if reader.u8() & 1 != 0 {
+            Memref::Null(reader.u32() as usize)
+        } else {
+            let len = reader.u16();
+            Memref::Buffer(reader.bytes(usize::from(len)).to_vec())
+        }
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Memref::Null(size) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(*size as u32).to_le_bytes());
+            }
+            Memref::Buffer(data) => {
+                let data = &data[..data.len().min(usize::from(u16::MAX))];
+                bytes.push(0);
+                bytes.extend_from_slice(&(data.len() as u16).to_le_bytes());
+                bytes.extend_from_slice(data);
+            }
+        }
+    }
+}
+
+/// Reads the fields of an invocation, missing bytes reading as zeros.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let (bytes, rest) = self.0.split_at(len.min(self.0.len()));
+        self.0 = rest;
+        bytes
+    }
+
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        let mut array = [0; N];
+        let bytes = self.bytes(N);
+        array[..bytes.len()].copy_from_slice(bytes);
+        array
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.array::<1>()[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.array())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.array())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use optee_utee_sys::{TEE_ERROR_BAD_PARAMETERS, TEE_ERROR_SHORT_BUFFER};
+
+    use super::*;
+
+    const CMD_ECHO: u32 = 0;
+    const CMD_OVERFLOW: u32 = 1;
+    const CMD_OVERSIZE: u32 = 2;
+    const CMD_MODIFY_INPUT: u32 = 3;
+
+    /// Copies memref input 0 to memref output 1, misbehaving on request.
+    extern "C" fn echo(
+        _: *mut c_void,
+        cmd_id: u32,
+        param_types: u32,
+        params: &mut [TEE_Param; 4],
+    ) -> TEE_Result {
+        let expected = TEE_PARAM_TYPES(
+            TEE_PARAM_TYPE_MEMREF_INPUT,
+            TEE_PARAM_TYPE_MEMREF_OUTPUT,
+            TEE_PARAM_TYPE_NONE,
+            TEE_PARAM_TYPE_NONE,
+        );
+        if param_types != expected {
+            return TEE_ERROR_BAD_PARAMETERS;
+        }
+        let (input, output) = unsafe { (params[0].memref, &mut params[1].memref) };
+        let input = unsafe { crate::slice(input.buffer, input.size) };
+        match cmd_id {
+            CMD_ECHO => {
+                let capacity = output.size;
+                output.size = input.len();
+                if capacity < input.len() {
+                    return TEE_ERROR_SHORT_BUFFER;
+                }
+                unsafe { crate::slice_mut(output.buffer, input.len()) }.copy_from_slice(input);
+            }
+            // Within the guard of the harness
+            CMD_OVERFLOW => unsafe { *(output.buffer as *mut u8).add(output.size) = 0 },
+            CMD_OVERSIZE => output.size += 1,
+            CMD_MODIFY_INPUT => unsafe { *(params[0].memref.buffer as *mut u8) = 0 },
+            _ => return TEE_ERROR_BAD_PARAMETERS,
+        }
+        TEE_SUCCESS
+    }
+
+    fn echo_invocation(cmd_id: u32, input: &[u8], output: Memref) -> Invocation {
+        Invocation::new(cmd_id)
+            .param(0, Param::MemrefInput(Memref::Buffer(input.to_vec())))
+            .param(1, Param::MemrefOutput(output))
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let invocation = echo_invocation(7, b"input", Memref::Null(64))
+            .param(2, Param::ValueInout { a: 1, b: u32::MAX })
+            .param(3, Param::Reserved(12));
+        assert_eq!(Invocation::from_bytes(&invocation.to_bytes()), invocation);
+
+        // Any input decodes, missing bytes reading as zeros
+        assert_eq!(Invocation::from_bytes(&[]), Invocation::new(0));
+        let invocation = Invocation::from_bytes(&[1, 0, 0, 0, 4, 0, 9, 0, 0xaa]);
+        assert_eq!(invocation.cmd_id, 1);
+        assert_eq!(
+            invocation.params[0],
+            Param::MemrefInput(Memref::Buffer(vec![0xaa]))
+        );
+    }
+
+    #[test]
+    fn test_invoke() {
+        let outcome = echo_invocation(CMD_ECHO, b"hello", Memref::Buffer(vec![0; 8]))
+            .invoke(echo, std::ptr::null_mut());
+        assert_eq!(outcome.result, TEE_SUCCESS);
+        assert_eq!(
+            outcome.params[1],
+            Output::Memref {
+                size: 5,
+                data: b"hello".to_vec()
+            }
+        );
+
+        // Short buffers and null buffers report the required size
+        for output in [Memref::Buffer(vec![0; 2]), Memref::Null(2)] {
+            let outcome = echo_invocation(CMD_ECHO, b"hello", output.clone())
+                .invoke(echo, std::ptr::null_mut());
+            assert_eq!(outcome.result, TEE_ERROR_SHORT_BUFFER);
+            let Output::Memref { size, .. } = outcome.params[1] else {
+                panic!("{:?}", outcome.params[1]);
+            };
+            assert_eq!(size, 5);
+        }
+
+        let outcome = Invocation::new(CMD_ECHO).invoke(echo, std::ptr::null_mut());
+        assert_eq!(outcome.result, TEE_ERROR_BAD_PARAMETERS);
+    }
+
+    #[test]
+    fn test_truncations() {
+        let invocation = echo_invocation(CMD_ECHO, b"abc", Memref::Buffer(vec![0; 8]));
+        let lengths: Vec<usize> = invocation
+            .truncations()
+            .map(|truncated| truncated.params[0].buffer().unwrap().len())
+            .collect();
+        assert_eq!(lengths, [0, 1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "wrote past the end of the 4 bytes of memref 1")]
+    fn test_overflow() {
+        echo_invocation(CMD_OVERFLOW, b"", Memref::Buffer(vec![0; 4]))
+            .invoke(echo, std::ptr::null_mut());
+    }
+
+    #[test]
+    #[should_panic(expected = "TEE_SUCCESS with 5 bytes in memref 1 of 4 bytes")]
+    fn test_oversize() {
+        echo_invocation(CMD_OVERSIZE, b"", Memref::Buffer(vec![0; 4]))
+            .invoke(echo, std::ptr::null_mut());
+    }
+
+    #[test]
+    #[should_panic(expected = "modified the buffer of memref input 0")]
+    fn test_modified_input() {
+        echo_invocation(CMD_MODIFY_INPUT, b"x", Memref::Buffer(vec![0; 4]))
+            .invoke(echo, std::ptr::null_mut());
+    }
+}
@@ -0,0 +1,186 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Host-side simulator of the TEE Internal Core API, to run the business
+//! logic of a TA under `cargo test` on the development machine.
+//!
+//! The crate implements the TEE functions of libutee on top of the standard
+//! library: an in-memory Trusted Storage, a fake clock, and digests, HMACs,
+//! AES-GCM and random numbers backed by `ring`. It is enabled through the
+//! `mock` feature of optee-utee, typically as a dev-dependency of the TA:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! optee-utee = { version = "0.9.0", features = ["mock"] }
+//! ```
+//!
+//! ```rust,ignore
+//! use optee_utee::{ObjectStorageConstants, mock};
+//!
+//! #[test]
+//! fn test_counter_is_persisted() {
+//!     mock::reset();
+//!     increment_counter().unwrap();
+//!     increment_counter().unwrap();
+//!     let stored = mock::storage::get(ObjectStorageConstants::Private as u32, b"counter");
+//!     assert_eq!(stored, Some(2u32.to_le_bytes().to_vec()));
+//! }
+//! ```
+//!
+//! The [`fuzz`] module drives the command entry point of the TA with
+//! adversarial parameters, for cargo-fuzz targets and unit tests.
+//!
+//! The state of the simulator is kept per thread, so the tests of the
+//! default test harness are isolated from each other. [`reset`] clears it
+//! for tests reusing a thread.
+//!
+//! Only a subset of the API is simulated: the Trusted Storage, transient
+//! objects holding secret keys, the time functions, the digest, MAC and AE
+//! operations listed in [`crypto`], random numbers, the [`cancellation`]
+//! flag, tracing and `TEE_Panic`, which panics. Calling any other TEE function fails to link, naming the
+//! missing function. The simulator cannot be combined with the `mock`
+//! feature of optee-utee-sys, whose mockall mocks define the same functions.
+#![cfg_attr(doc, doc = concat!(
+    "\n",
+    "## Feature flags\n",
+    document_features::document_features!(),
+))]
+#![allow(non_snake_case)]
+
+use std::ffi::{c_int, c_void};
+
+use optee_utee_sys::{TEE_Result, size_t};
+
+/// Define the simulated TEE function `$name`, and check at compile time that
+/// its signature is the one declared in optee-utee-sys.
+macro_rules! tee_api {
+    ($(#[$attr:meta])* fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty $body:block) => {
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
+        unsafe extern "C" fn $name($($arg: $ty),*) -> $ret $body
+
+        const _: unsafe extern "C" fn($($ty),*) -> $ret = optee_utee_sys::$name;
+    };
+    ($(#[$attr:meta])* fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $body:block) => {
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
+        unsafe extern "C" fn $name($($arg: $ty),*) $body
+
+        // Some functions without a result are declared returning `c_void`
+        const _: () = {
+            let _: unsafe extern "C" fn($($ty),*) -> _ = optee_utee_sys::$name;
+        };
+    };
+}
+
+pub mod cancellation;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+pub mod fuzz;
+mod object;
+pub mod storage;
+pub mod time;
+
+/// Clear the state of the simulator in the current thread: the Trusted
+/// Storage, the clock, the cancellation request and the trace level.
+///
+/// The objects and operations still allocated by the TA are left as is.
+pub fn reset() {
+    storage::clear();
+    time::reset();
+    cancellation::reset();
+    TRACE_LEVEL.set(DEFAULT_TRACE_LEVEL);
+}
+
+/// Panic with the TEE function and the reason of a `TEE_Panic` of the
+/// specification.
+#[track_caller]
+fn tee_panic(function: &str, reason: &str) -> ! {
+    panic!("TEE_Panic in {}: {}", function, reason)
+}
+
+/// Slice of `len` bytes at `ptr`, empty if `ptr` is null.
+///
+/// # Safety
+///
+/// Unless null, `ptr` must be valid for reads of `len` bytes.
+unsafe fn slice<'a>(ptr: *const c_void, len: usize) -> &'a [u8] {
+    if ptr.is_null() || len == 0 {
+        return &[];
+    }
+    unsafe { std::slice::from_raw_parts(ptr as *const u8, len) }
+}
+
+/// Mutable slice of `len` bytes at `ptr`, empty if `ptr` is null.
+///
+/// # Safety
+///
+/// Unless null, `ptr` must be valid for writes of `len` bytes.
+unsafe fn slice_mut<'a>(ptr: *mut c_void, len: usize) -> &'a mut [u8] {
+    if ptr.is_null() || len == 0 {
+        return &mut [];
+    }
+    unsafe { std::slice::from_raw_parts_mut(ptr as *mut u8, len) }
+}
+
+/// Write `data` to the output buffer `buffer` of `*len` bytes, setting `*len`
+/// to the length of `data`.
+///
+/// # Safety
+///
+/// `len` must be valid, and `buffer` valid for writes of `*len` bytes.
+unsafe fn write_output(data: &[u8], buffer: *mut c_void, len: *mut usize) -> TEE_Result {
+    let capacity = unsafe { *len };
+    unsafe { *len = data.len() };
+    if capacity < data.len() {
+        return optee_utee_sys::TEE_ERROR_SHORT_BUFFER;
+    }
+    unsafe { slice_mut(buffer, data.len()) }.copy_from_slice(data);
+    optee_utee_sys::TEE_SUCCESS
+}
+
+tee_api! {
+    fn TEE_Panic(panicCode: TEE_Result) {
+        panic!("TEE_Panic({:#010x})", panicCode)
+    }
+}
+
+// Level of the messages of the TA, as set by CFG_TEE_TA_LOG_LEVEL
+const DEFAULT_TRACE_LEVEL: c_int = 1;
+
+thread_local! {
+    static TRACE_LEVEL: std::cell::Cell<c_int> = const { std::cell::Cell::new(DEFAULT_TRACE_LEVEL) };
+}
+
+tee_api! {
+    /// Print the trace output of the TA to stdout, where the test harness
+    /// captures it.
+    fn _utee_log(buf: *const c_void, len: size_t) {
+        print!("{}", String::from_utf8_lossy(unsafe { slice(buf, len) }));
+    }
+}
+
+tee_api! {
+    fn trace_set_level(level: c_int) {
+        TRACE_LEVEL.set(level);
+    }
+}
+
+tee_api! {
+    fn trace_get_level() -> c_int {
+        TRACE_LEVEL.get()
+    }
+}
@@ -0,0 +1,404 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Object handles, transient objects and their attributes.
+
+use std::ffi::c_void;
+
+use optee_utee_sys::{
+    TEE_ATTR_FLAG_PUBLIC, TEE_ATTR_FLAG_VALUE, TEE_ATTR_SECRET_VALUE, TEE_Attribute,
+    TEE_ERROR_BAD_PARAMETERS, TEE_ERROR_ITEM_NOT_FOUND, TEE_ERROR_NOT_SUPPORTED,
+    TEE_HANDLE_FLAG_INITIALIZED, TEE_HANDLE_FLAG_PERSISTENT, TEE_ObjectHandle, TEE_ObjectInfo,
+    TEE_ObjectType, TEE_Result, TEE_SUCCESS, TEE_TYPE_AES, TEE_TYPE_DATA, TEE_TYPE_GENERIC_SECRET,
+    TEE_TYPE_HMAC_SHA1, TEE_TYPE_HMAC_SHA256, TEE_TYPE_HMAC_SHA384, TEE_TYPE_HMAC_SHA512,
+    TEE_USAGE_EXTRACTABLE, content,
+};
+
+use crate::storage::PersistentHandle;
+use crate::{slice, tee_panic, write_output};
+
+/// Usage of a new object, all flags set
+const ALL_USAGE: u32 = 0xFFFF_FFFF;
+
+/// A buffer attribute, the only kind the simulated object types have.
+#[derive(Clone)]
+pub(crate) struct Attribute {
+    pub id: u32,
+    pub value: Vec<u8>,
+}
+
+/// What a `TEE_ObjectHandle` points to.
+pub(crate) struct Object {
+    pub object_type: TEE_ObjectType,
+    /// Key size in bits
+    pub object_size: u32,
+    pub max_object_size: u32,
+    pub usage: u32,
+    pub initialized: bool,
+    pub attributes: Vec<Attribute>,
+    /// The data stream and position of a persistent object
+    pub persistent: Option<PersistentHandle>,
+}
+
+impl Object {
+    /// The value of the attribute `id`, if the object has it
+    pub fn attribute(&self, id: u32) -> Option<&[u8]> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.id == id)
+            .map(|attribute| attribute.value.as_slice())
+    }
+
+    /// The secret value of an initialized secret key object
+    #[cfg(feature = "crypto")]
+    pub fn secret(&self) -> Option<&[u8]> {
+        self.attribute(TEE_ATTR_SECRET_VALUE)
+            .filter(|_| self.initialized)
+    }
+}
+
+/// Make a handle owning `object`.
+pub(crate) fn into_handle(object: Object) -> TEE_ObjectHandle {
+    Box::into_raw(Box::new(object)) as TEE_ObjectHandle
+}
+
+/// The object of a handle returned by [`into_handle`].
+///
+/// # Safety
+///
+/// `handle` must be null or a handle returned by [`into_handle`] and not freed
+/// yet.
+pub(crate) unsafe fn object<'a>(handle: TEE_ObjectHandle, function: &str) -> &'a mut Object {
+    if handle.is_null() {
+        tee_panic(function, "invalid object handle");
+    }
+    unsafe { &mut *(handle as *mut Object) }
+}
+
+/// Free the object of a handle returned by [`into_handle`], null handles are
+/// ignored.
+///
+/// # Safety
+///
+/// As for [`object`], the handle must not be used afterwards.
+unsafe fn free(handle: TEE_ObjectHandle) {
+    if !handle.is_null() {
+        drop(unsafe { Box::from_raw(handle as *mut Object) });
+    }
+}
+
+/// Whether `object_type` is a secret key type the simulator supports, and
+/// whether `size` bits is a valid size for it.
+fn secret_key_size_is_valid(object_type: TEE_ObjectType, size: u32) -> Option<bool> {
+    let valid = match object_type {
+        TEE_TYPE_AES => matches!(size, 128 | 192 | 256),
+        TEE_TYPE_HMAC_SHA1 => (80..=512).contains(&size) && size.is_multiple_of(8),
+        TEE_TYPE_HMAC_SHA256 => (192..=1024).contains(&size) && size.is_multiple_of(8),
+        TEE_TYPE_HMAC_SHA384 | TEE_TYPE_HMAC_SHA512 => {
+            (256..=1024).contains(&size) && size.is_multiple_of(8)
+        }
+        TEE_TYPE_GENERIC_SECRET => (8..=4096).contains(&size) && size.is_multiple_of(8),
+        _ => return None,
+    };
+    Some(valid)
+}
+
+/// Initialize the uninitialized transient `object` with the secret `key`.
+fn set_secret(object: &mut Object, key: Vec<u8>, function: &str) -> TEE_Result {
+    if object.initialized {
+        tee_panic(function, "the object is already initialized");
+    }
+    let size = key.len() as u32 * 8;
+    if size > object.max_object_size
+        || secret_key_size_is_valid(object.object_type, size) != Some(true)
+    {
+        return TEE_ERROR_BAD_PARAMETERS;
+    }
+    object.attributes = vec![Attribute {
+        id: TEE_ATTR_SECRET_VALUE,
+        value: key,
+    }];
+    object.object_size = size;
+    object.initialized = true;
+    TEE_SUCCESS
+}
+
+tee_api! {
+    fn TEE_GetObjectInfo1(object: TEE_ObjectHandle, objectInfo: *mut TEE_ObjectInfo) -> TEE_Result {
+        let object = unsafe { self::object(object, "TEE_GetObjectInfo1") };
+        let mut info = TEE_ObjectInfo {
+            objectType: object.object_type,
+            objectSize: object.object_size,
+            maxObjectSize: object.max_object_size,
+            objectUsage: object.usage,
+            dataSize: 0,
+            dataPosition: 0,
+            handleFlags: if object.initialized {
+                TEE_HANDLE_FLAG_INITIALIZED
+            } else {
+                0
+            },
+        };
+        if let Some(persistent) = &object.persistent {
+            info.maxObjectSize = object.object_size;
+            info.dataSize = persistent.data_size();
+            info.dataPosition = persistent.position;
+            info.handleFlags |= TEE_HANDLE_FLAG_PERSISTENT | persistent.flags;
+        }
+        unsafe { *objectInfo = info };
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_CloseObject(object: TEE_ObjectHandle) {
+        unsafe { free(object) };
+    }
+}
+
+tee_api! {
+    fn TEE_AllocateTransientObject(
+        objectType: TEE_ObjectType,
+        maxObjectSize: u32,
+        object: *mut TEE_ObjectHandle,
+    ) -> TEE_Result {
+        unsafe { *object = core::ptr::null_mut() };
+        if secret_key_size_is_valid(objectType, maxObjectSize) != Some(true) {
+            return TEE_ERROR_NOT_SUPPORTED;
+        }
+        let handle = into_handle(Object {
+            object_type: objectType,
+            object_size: 0,
+            max_object_size: maxObjectSize,
+            usage: ALL_USAGE,
+            initialized: false,
+            attributes: Vec::new(),
+            persistent: None,
+        });
+        unsafe { *object = handle };
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_FreeTransientObject(object: TEE_ObjectHandle) {
+        if !object.is_null()
+            && unsafe { self::object(object, "TEE_FreeTransientObject") }
+                .persistent
+                .is_some()
+        {
+            tee_panic("TEE_FreeTransientObject", "the object is persistent");
+        }
+        unsafe { free(object) };
+    }
+}
+
+tee_api! {
+    fn TEE_ResetTransientObject(object: TEE_ObjectHandle) {
+        if object.is_null() {
+            return;
+        }
+        let object = unsafe { self::object(object, "TEE_ResetTransientObject") };
+        if object.persistent.is_some() {
+            tee_panic("TEE_ResetTransientObject", "the object is persistent");
+        }
+        object.attributes.clear();
+        object.object_size = 0;
+        object.usage = ALL_USAGE;
+        object.initialized = false;
+    }
+}
+
+tee_api! {
+    fn TEE_PopulateTransientObject(
+        object: TEE_ObjectHandle,
+        attrs: *const TEE_Attribute,
+        attrCount: u32,
+    ) -> TEE_Result {
+        let object = unsafe { self::object(object, "TEE_PopulateTransientObject") };
+        let attrs = if attrCount == 0 {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(attrs, attrCount as usize) }
+        };
+        let secret = attrs.iter().find(|attr| attr.attributeID == TEE_ATTR_SECRET_VALUE);
+        match secret {
+            Some(attr) => {
+                let key = unsafe { slice(attr.content.memref.buffer, attr.content.memref.size) };
+                set_secret(object, key.to_vec(), "TEE_PopulateTransientObject")
+            }
+            None => tee_panic(
+                "TEE_PopulateTransientObject",
+                "the TEE_ATTR_SECRET_VALUE attribute is missing",
+            ),
+        }
+    }
+}
+
+tee_api! {
+    fn TEE_InitRefAttribute(
+        attr: *mut TEE_Attribute,
+        attributeID: u32,
+        buffer: *const c_void,
+        length: usize,
+    ) {
+        if attributeID & TEE_ATTR_FLAG_VALUE != 0 {
+            tee_panic("TEE_InitRefAttribute", "the attribute is a value attribute");
+        }
+        unsafe {
+            (*attr).attributeID = attributeID;
+            (*attr).content = content {
+                memref: optee_utee_sys::Memref {
+                    buffer: buffer as *mut c_void,
+                    size: length,
+                },
+            };
+        }
+    }
+}
+
+tee_api! {
+    fn TEE_InitValueAttribute(attr: *mut TEE_Attribute, attributeID: u32, a: u32, b: u32) {
+        if attributeID & TEE_ATTR_FLAG_VALUE == 0 {
+            tee_panic("TEE_InitValueAttribute", "the attribute is a buffer attribute");
+        }
+        unsafe {
+            (*attr).attributeID = attributeID;
+            (*attr).content = content {
+                value: optee_utee_sys::Value { a, b },
+            };
+        }
+    }
+}
+
+#[cfg(feature = "crypto")]
+tee_api! {
+    fn TEE_GenerateKey(
+        object: TEE_ObjectHandle,
+        keySize: u32,
+        _params: *const TEE_Attribute,
+        _paramCount: u32,
+    ) -> TEE_Result {
+        let object = unsafe { self::object(object, "TEE_GenerateKey") };
+        if secret_key_size_is_valid(object.object_type, keySize) != Some(true) {
+            return TEE_ERROR_NOT_SUPPORTED;
+        }
+        let mut key = vec![0u8; keySize as usize / 8];
+        crate::crypto::fill_random(&mut key);
+        set_secret(object, key, "TEE_GenerateKey")
+    }
+}
+
+/// The attribute `attributeID` of `object`, panicking if it is a protected
+/// attribute of a non-extractable object.
+fn readable_attribute<'a>(
+    object: &'a Object,
+    attributeID: u32,
+    function: &str,
+) -> Option<&'a [u8]> {
+    if !object.initialized {
+        tee_panic(function, "the object is not initialized");
+    }
+    if attributeID & TEE_ATTR_FLAG_PUBLIC == 0 && object.usage & TEE_USAGE_EXTRACTABLE == 0 {
+        tee_panic(
+            function,
+            "the attribute is protected and the object not extractable",
+        );
+    }
+    object.attribute(attributeID)
+}
+
+tee_api! {
+    fn TEE_GetObjectBufferAttribute(
+        object: TEE_ObjectHandle,
+        attributeID: u32,
+        buffer: *mut c_void,
+        size: *mut usize,
+    ) -> TEE_Result {
+        let function = "TEE_GetObjectBufferAttribute";
+        let object = unsafe { self::object(object, function) };
+        match readable_attribute(object, attributeID, function) {
+            Some(value) => unsafe { write_output(value, buffer, size) },
+            None => TEE_ERROR_ITEM_NOT_FOUND,
+        }
+    }
+}
+
+tee_api! {
+    fn TEE_GetObjectValueAttribute(
+        object: TEE_ObjectHandle,
+        attributeID: u32,
+        a: *mut u32,
+        b: *mut u32,
+    ) -> TEE_Result {
+        let function = "TEE_GetObjectValueAttribute";
+        let object = unsafe { self::object(object, function) };
+        let _ = (a, b);
+        match readable_attribute(object, attributeID, function) {
+            Some(_) => tee_panic(function, "the attribute is a buffer"),
+            None => TEE_ERROR_ITEM_NOT_FOUND,
+        }
+    }
+}
+
+tee_api! {
+    fn TEE_RestrictObjectUsage1(object: TEE_ObjectHandle, objectUsage: u32) -> TEE_Result {
+        let object = unsafe { self::object(object, "TEE_RestrictObjectUsage1") };
+        object.usage &= objectUsage;
+        if let Some(persistent) = &object.persistent {
+            persistent.restrict_usage(objectUsage);
+        }
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_CopyObjectAttributes1(
+        destObject: TEE_ObjectHandle,
+        srcObject: TEE_ObjectHandle,
+    ) -> TEE_Result {
+        let function = "TEE_CopyObjectAttributes1";
+        let (src, dest) = unsafe { (object(srcObject, function), object(destObject, function)) };
+        if !src.initialized || dest.initialized || dest.persistent.is_some() {
+            tee_panic(function, "the source must be initialized, the destination a new transient object");
+        }
+        if src.object_type != dest.object_type && dest.object_type != TEE_TYPE_GENERIC_SECRET {
+            tee_panic(function, "the object types are not compatible");
+        }
+        if src.object_size > dest.max_object_size {
+            tee_panic(function, "the source is larger than the destination");
+        }
+        dest.attributes = src.attributes.clone();
+        dest.object_size = src.object_size;
+        dest.usage &= src.usage;
+        dest.initialized = true;
+        TEE_SUCCESS
+    }
+}
+
+/// An object of type `TEE_TYPE_DATA` without attributes, for persistent
+/// objects created without an attribute object.
+pub(crate) fn data_object() -> Object {
+    Object {
+        object_type: TEE_TYPE_DATA,
+        object_size: 0,
+        max_object_size: 0,
+        usage: ALL_USAGE,
+        initialized: true,
+        attributes: Vec::new(),
+        persistent: None,
+    }
+}
@@ -0,0 +1,654 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! In-memory Trusted Storage.
+//!
+//! Persistent objects live in the current thread until [`crate::reset`] or
+//! [`remove`]. Tests can prepare and inspect the data of the objects:
+//!
+//! ```rust,ignore
+//! use optee_utee::{ObjectStorageConstants, mock};
+//!
+//! let storage = ObjectStorageConstants::Private as u32;
+//! mock::storage::insert(storage, b"config", br#"{"retries":3}"#);
+//! ta_load_config().unwrap();
+//! assert_eq!(mock::storage::ids(storage), vec![b"config".to_vec()]);
+//! ```
+//!
+//! The access and sharing flags of the handles are enforced as in OP-TEE:
+//! conflicting opens fail with `TEE_ERROR_ACCESS_CONFLICT`, while reading
+//! without read access or deleting without write-meta access panics.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::rc::Rc;
+
+use optee_utee_sys::{
+    TEE_DATA_FLAG_ACCESS_READ, TEE_DATA_FLAG_ACCESS_WRITE, TEE_DATA_FLAG_ACCESS_WRITE_META,
+    TEE_DATA_FLAG_OVERWRITE, TEE_DATA_FLAG_SHARE_READ, TEE_DATA_FLAG_SHARE_WRITE,
+    TEE_DATA_MAX_POSITION, TEE_ERROR_ACCESS_CONFLICT, TEE_ERROR_BAD_PARAMETERS,
+    TEE_ERROR_ITEM_NOT_FOUND, TEE_ERROR_OVERFLOW, TEE_OBJECT_ID_MAX_LEN, TEE_ObjectEnumHandle,
+    TEE_ObjectHandle, TEE_ObjectInfo, TEE_Result, TEE_SUCCESS, TEE_TYPE_DATA, TEE_Whence, intmax_t,
+};
+
+use crate::object::{self, Attribute, Object};
+use crate::{slice, slice_mut, tee_panic};
+
+const TEE_STORAGE_PRIVATE_REE: u32 = 0x8000_0000;
+const TEE_STORAGE_PRIVATE_RPMB: u32 = 0x8000_0100;
+
+/// Storage ID and object ID
+type Key = (u32, Vec<u8>);
+
+/// A persistent object in the storage.
+struct Stored {
+    data: Vec<u8>,
+    object_type: u32,
+    object_size: u32,
+    usage: u32,
+    attributes: Vec<Attribute>,
+    /// Flags of the handles the object is open with
+    handles: Vec<u32>,
+}
+
+thread_local! {
+    static STORE: RefCell<BTreeMap<Key, Rc<RefCell<Stored>>>> = const {
+        RefCell::new(BTreeMap::new())
+    };
+}
+
+/// The data of the object `id` in the storage `storage_id`.
+pub fn get(storage_id: u32, id: &[u8]) -> Option<Vec<u8>> {
+    STORE.with_borrow(|store| {
+        store
+            .get(&(storage_id, id.to_vec()))
+            .map(|stored| stored.borrow().data.clone())
+    })
+}
+
+/// Store `data` as the object `id` in the storage `storage_id`, as if the TA
+/// had created it without attributes, replacing any object with this ID.
+pub fn insert(storage_id: u32, id: &[u8], data: impl Into<Vec<u8>>) {
+    let stored = Stored {
+        data: data.into(),
+        object_type: TEE_TYPE_DATA,
+        object_size: 0,
+        usage: 0xFFFF_FFFF,
+        attributes: Vec::new(),
+        handles: Vec::new(),
+    };
+    STORE.with_borrow_mut(|store| {
+        store.insert((storage_id, id.to_vec()), Rc::new(RefCell::new(stored)))
+    });
+}
+
+/// Remove the object `id` from the storage `storage_id`, returning whether it
+/// existed. The handles the TA still has to it keep working, as for an object
+/// deleted by another TA instance.
+pub fn remove(storage_id: u32, id: &[u8]) -> bool {
+    STORE.with_borrow_mut(|store| store.remove(&(storage_id, id.to_vec())).is_some())
+}
+
+/// The IDs of the objects in the storage `storage_id`, in ascending order.
+pub fn ids(storage_id: u32) -> Vec<Vec<u8>> {
+    STORE.with_borrow(|store| {
+        store
+            .keys()
+            .filter(|(storage, _)| *storage == storage_id)
+            .map(|(_, id)| id.clone())
+            .collect()
+    })
+}
+
+pub(crate) fn clear() {
+    STORE.with_borrow_mut(|store| store.clear());
+}
+
+/// The data stream and position of a handle to a persistent object.
+pub(crate) struct PersistentHandle {
+    key: Key,
+    stored: Rc<RefCell<Stored>>,
+    pub flags: u32,
+    pub position: usize,
+}
+
+impl PersistentHandle {
+    pub fn data_size(&self) -> usize {
+        self.stored.borrow().data.len()
+    }
+
+    pub fn restrict_usage(&self, usage: u32) {
+        self.stored.borrow_mut().usage &= usage;
+    }
+
+    fn require(&self, access: u32, function: &str) {
+        if self.flags & access == 0 {
+            tee_panic(function, "the object is not open with the required access");
+        }
+    }
+}
+
+impl Drop for PersistentHandle {
+    fn drop(&mut self) {
+        let handles = &mut self.stored.borrow_mut().handles;
+        if let Some(index) = handles.iter().position(|flags| *flags == self.flags) {
+            handles.remove(index);
+        }
+    }
+}
+
+/// Whether an object open with the handles `existing` can be opened again
+/// with `flags`.
+fn can_share(existing: &[u32], flags: u32) -> bool {
+    let allowed = |open: u32, new: u32| {
+        (new & TEE_DATA_FLAG_ACCESS_READ == 0 || open & TEE_DATA_FLAG_SHARE_READ != 0)
+            && (new & TEE_DATA_FLAG_ACCESS_WRITE == 0 || open & TEE_DATA_FLAG_SHARE_WRITE != 0)
+            && new & TEE_DATA_FLAG_ACCESS_WRITE_META == 0
+    };
+    existing
+        .iter()
+        .all(|&open| allowed(open, flags) && allowed(flags, open))
+}
+
+/// The key of an object, checking the storage and object IDs.
+///
+/// # Safety
+///
+/// `id` must be valid for reads of `len` bytes.
+unsafe fn key(storage_id: u32, id: *const c_void, len: usize) -> Result<Key, TEE_Result> {
+    if len > TEE_OBJECT_ID_MAX_LEN as usize {
+        return Err(TEE_ERROR_BAD_PARAMETERS);
+    }
+    match storage_id {
+        optee_utee_sys::TEE_STORAGE_PRIVATE
+        | TEE_STORAGE_PRIVATE_REE
+        | TEE_STORAGE_PRIVATE_RPMB => Ok((storage_id, unsafe { slice(id, len) }.to_vec())),
+        _ => Err(TEE_ERROR_ITEM_NOT_FOUND),
+    }
+}
+
+/// Open a new handle with `flags` to `stored`.
+fn open_handle(key: Key, stored: Rc<RefCell<Stored>>, flags: u32) -> TEE_ObjectHandle {
+    let mut object = {
+        let mut object_stored = stored.borrow_mut();
+        object_stored.handles.push(flags);
+        Object {
+            object_type: object_stored.object_type,
+            object_size: object_stored.object_size,
+            max_object_size: object_stored.object_size,
+            usage: object_stored.usage,
+            initialized: true,
+            attributes: object_stored.attributes.clone(),
+            persistent: None,
+        }
+    };
+    object.persistent = Some(PersistentHandle {
+        key,
+        stored,
+        flags: flags & !TEE_DATA_FLAG_OVERWRITE,
+        position: 0,
+    });
+    object::into_handle(object)
+}
+
+tee_api! {
+    fn TEE_OpenPersistentObject(
+        storageID: u32,
+        objectID: *const c_void,
+        objectIDLen: usize,
+        flags: u32,
+        object: *mut TEE_ObjectHandle,
+    ) -> TEE_Result {
+        unsafe { *object = core::ptr::null_mut() };
+        let key = match unsafe { key(storageID, objectID, objectIDLen) } {
+            Ok(key) => key,
+            Err(error) => return error,
+        };
+        let Some(stored) = STORE.with_borrow(|store| store.get(&key).cloned()) else {
+            return TEE_ERROR_ITEM_NOT_FOUND;
+        };
+        if !can_share(&stored.borrow().handles, flags) {
+            return TEE_ERROR_ACCESS_CONFLICT;
+        }
+        unsafe { *object = open_handle(key, stored, flags) };
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_CreatePersistentObject(
+        storageID: u32,
+        objectID: *const c_void,
+        objectIDLen: usize,
+        flags: u32,
+        attributes: TEE_ObjectHandle,
+        initialData: *const c_void,
+        initialDataLen: usize,
+        object: *mut TEE_ObjectHandle,
+    ) -> TEE_Result {
+        if !object.is_null() {
+            unsafe { *object = core::ptr::null_mut() };
+        }
+        let key = match unsafe { key(storageID, objectID, objectIDLen) } {
+            Ok(key) => key,
+            Err(error) => return error,
+        };
+        if let Some(existing) = STORE.with_borrow(|store| store.get(&key).cloned())
+            && (flags & TEE_DATA_FLAG_OVERWRITE == 0 || !existing.borrow().handles.is_empty())
+        {
+            return TEE_ERROR_ACCESS_CONFLICT;
+        }
+        let template = if attributes.is_null() {
+            object::data_object()
+        } else {
+            let template = unsafe { object::object(attributes, "TEE_CreatePersistentObject") };
+            if !template.initialized {
+                tee_panic("TEE_CreatePersistentObject", "the attributes object is not initialized");
+            }
+            Object {
+                attributes: template.attributes.clone(),
+                persistent: None,
+                ..*template
+            }
+        };
+        let stored = Rc::new(RefCell::new(Stored {
+            data: unsafe { slice(initialData, initialDataLen) }.to_vec(),
+            object_type: template.object_type,
+            object_size: template.object_size,
+            usage: template.usage,
+            attributes: template.attributes,
+            handles: Vec::new(),
+        }));
+        STORE.with_borrow_mut(|store| store.insert(key.clone(), stored.clone()));
+        if !object.is_null() {
+            unsafe { *object = open_handle(key, stored, flags) };
+        }
+        TEE_SUCCESS
+    }
+}
+
+/// The persistent part of the object of `handle`, panicking for transient
+/// objects.
+///
+/// # Safety
+///
+/// As for [`object::object`].
+unsafe fn persistent<'a>(handle: TEE_ObjectHandle, function: &str) -> &'a mut PersistentHandle {
+    match unsafe { object::object(handle, function) }
+        .persistent
+        .as_mut()
+    {
+        Some(persistent) => persistent,
+        None => tee_panic(function, "the object is not persistent"),
+    }
+}
+
+tee_api! {
+    fn TEE_CloseAndDeletePersistentObject1(object: TEE_ObjectHandle) -> TEE_Result {
+        if object.is_null() {
+            return TEE_SUCCESS;
+        }
+        let function = "TEE_CloseAndDeletePersistentObject1";
+        let persistent = unsafe { persistent(object, function) };
+        persistent.require(TEE_DATA_FLAG_ACCESS_WRITE_META, function);
+        STORE.with_borrow_mut(|store| {
+            if store
+                .get(&persistent.key)
+                .is_some_and(|stored| Rc::ptr_eq(stored, &persistent.stored))
+            {
+                store.remove(&persistent.key);
+            }
+        });
+        unsafe { optee_utee_sys::TEE_CloseObject(object) };
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_RenamePersistentObject(
+        object: TEE_ObjectHandle,
+        newObjectID: *const c_void,
+        newObjectIDLen: usize,
+    ) -> TEE_Result {
+        let function = "TEE_RenamePersistentObject";
+        let persistent = unsafe { persistent(object, function) };
+        persistent.require(TEE_DATA_FLAG_ACCESS_WRITE_META, function);
+        let new_key = match unsafe { key(persistent.key.0, newObjectID, newObjectIDLen) } {
+            Ok(key) => key,
+            Err(error) => return error,
+        };
+        if new_key == persistent.key {
+            return TEE_SUCCESS;
+        }
+        STORE.with_borrow_mut(|store| {
+            if store.contains_key(&new_key) {
+                return TEE_ERROR_ACCESS_CONFLICT;
+            }
+            if let Some(stored) = store.remove(&persistent.key) {
+                store.insert(new_key.clone(), stored);
+            }
+            persistent.key = new_key;
+            TEE_SUCCESS
+        })
+    }
+}
+
+tee_api! {
+    fn TEE_ReadObjectData(
+        object: TEE_ObjectHandle,
+        buffer: *mut c_void,
+        size: usize,
+        count: *mut usize,
+    ) -> TEE_Result {
+        let function = "TEE_ReadObjectData";
+        let persistent = unsafe { persistent(object, function) };
+        persistent.require(TEE_DATA_FLAG_ACCESS_READ, function);
+        let stored = persistent.stored.borrow();
+        let start = persistent.position.min(stored.data.len());
+        let read = size.min(stored.data.len() - start);
+        unsafe { slice_mut(buffer, read) }.copy_from_slice(&stored.data[start..start + read]);
+        drop(stored);
+        persistent.position += read;
+        unsafe { *count = read };
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_WriteObjectData(
+        object: TEE_ObjectHandle,
+        buffer: *const c_void,
+        size: usize,
+    ) -> TEE_Result {
+        let function = "TEE_WriteObjectData";
+        let persistent = unsafe { persistent(object, function) };
+        persistent.require(TEE_DATA_FLAG_ACCESS_WRITE, function);
+        let end = persistent.position + size;
+        if end > TEE_DATA_MAX_POSITION as usize {
+            return TEE_ERROR_OVERFLOW;
+        }
+        let mut stored = persistent.stored.borrow_mut();
+        if stored.data.len() < end {
+            stored.data.resize(end, 0);
+        }
+        stored.data[persistent.position..end].copy_from_slice(unsafe { slice(buffer, size) });
+        drop(stored);
+        persistent.position = end;
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_TruncateObjectData(object: TEE_ObjectHandle, size: usize) -> TEE_Result {
+        let function = "TEE_TruncateObjectData";
+        let persistent = unsafe { persistent(object, function) };
+        persistent.require(TEE_DATA_FLAG_ACCESS_WRITE, function);
+        persistent.stored.borrow_mut().data.resize(size, 0);
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_SeekObjectData(
+        object: TEE_ObjectHandle,
+        offset: intmax_t,
+        whence: TEE_Whence,
+    ) -> TEE_Result {
+        let persistent = unsafe { persistent(object, "TEE_SeekObjectData") };
+        let origin = match whence {
+            TEE_Whence::TEE_DATA_SEEK_SET => 0,
+            TEE_Whence::TEE_DATA_SEEK_CUR => persistent.position as i128,
+            TEE_Whence::TEE_DATA_SEEK_END => persistent.data_size() as i128,
+        };
+        let position = (origin + offset as i128).max(0);
+        if position > TEE_DATA_MAX_POSITION as i128 {
+            return TEE_ERROR_OVERFLOW;
+        }
+        persistent.position = position as usize;
+        TEE_SUCCESS
+    }
+}
+
+/// Object ID and object
+type Entry = (Vec<u8>, Rc<RefCell<Stored>>);
+
+/// What a `TEE_ObjectEnumHandle` points to.
+struct Enumerator {
+    /// The objects left to enumerate, `None` until started
+    remaining: Option<std::vec::IntoIter<Entry>>,
+}
+
+/// # Safety
+///
+/// `handle` must be null or a handle allocated by
+/// `TEE_AllocatePersistentObjectEnumerator` and not freed yet.
+unsafe fn enumerator<'a>(handle: TEE_ObjectEnumHandle, function: &str) -> &'a mut Enumerator {
+    if handle.is_null() {
+        tee_panic(function, "invalid enumerator handle");
+    }
+    unsafe { &mut *(handle as *mut Enumerator) }
+}
+
+tee_api! {
+    fn TEE_AllocatePersistentObjectEnumerator(
+        objectEnumerator: *mut TEE_ObjectEnumHandle,
+    ) -> TEE_Result {
+        let enumerator = Box::new(Enumerator { remaining: None });
+        unsafe { *objectEnumerator = Box::into_raw(enumerator) as TEE_ObjectEnumHandle };
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_FreePersistentObjectEnumerator(objectEnumerator: TEE_ObjectEnumHandle) {
+        if !objectEnumerator.is_null() {
+            drop(unsafe { Box::from_raw(objectEnumerator as *mut Enumerator) });
+        }
+    }
+}
+
+tee_api! {
+    fn TEE_ResetPersistentObjectEnumerator(objectEnumerator: TEE_ObjectEnumHandle) {
+        unsafe { enumerator(objectEnumerator, "TEE_ResetPersistentObjectEnumerator") }.remaining =
+            None;
+    }
+}
+
+tee_api! {
+    fn TEE_StartPersistentObjectEnumerator(
+        objectEnumerator: TEE_ObjectEnumHandle,
+        storageID: u32,
+    ) -> TEE_Result {
+        let enumerator =
+            unsafe { enumerator(objectEnumerator, "TEE_StartPersistentObjectEnumerator") };
+        let objects: Vec<_> = STORE.with_borrow(|store| {
+            store
+                .iter()
+                .filter(|((storage, _), _)| *storage == storageID)
+                .map(|((_, id), stored)| (id.clone(), stored.clone()))
+                .collect()
+        });
+        if objects.is_empty() {
+            enumerator.remaining = None;
+            return TEE_ERROR_ITEM_NOT_FOUND;
+        }
+        enumerator.remaining = Some(objects.into_iter());
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_GetNextPersistentObject(
+        objectEnumerator: TEE_ObjectEnumHandle,
+        objectInfo: *mut TEE_ObjectInfo,
+        objectID: *mut c_void,
+        objectIDLen: *mut usize,
+    ) -> TEE_Result {
+        let enumerator = unsafe { enumerator(objectEnumerator, "TEE_GetNextPersistentObject") };
+        let Some((id, stored)) = enumerator.remaining.as_mut().and_then(Iterator::next) else {
+            return TEE_ERROR_ITEM_NOT_FOUND;
+        };
+        let stored = stored.borrow();
+        if !objectInfo.is_null() {
+            unsafe {
+                *objectInfo = TEE_ObjectInfo {
+                    objectType: stored.object_type,
+                    objectSize: stored.object_size,
+                    maxObjectSize: stored.object_size,
+                    objectUsage: stored.usage,
+                    dataSize: stored.data.len(),
+                    dataPosition: 0,
+                    handleFlags: optee_utee_sys::TEE_HANDLE_FLAG_PERSISTENT
+                        | optee_utee_sys::TEE_HANDLE_FLAG_INITIALIZED,
+                }
+            };
+        }
+        // The buffer holds TEE_OBJECT_ID_MAX_LEN bytes
+        unsafe { slice_mut(objectID, id.len()) }.copy_from_slice(&id);
+        unsafe { *objectIDLen = id.len() };
+        TEE_SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use optee_utee_sys as raw;
+
+    use super::*;
+
+    const PRIVATE: u32 = raw::TEE_STORAGE_PRIVATE;
+
+    fn open(id: &[u8], flags: u32) -> Result<TEE_ObjectHandle, TEE_Result> {
+        let mut handle = core::ptr::null_mut();
+        match unsafe {
+            raw::TEE_OpenPersistentObject(PRIVATE, id.as_ptr() as _, id.len(), flags, &mut handle)
+        } {
+            TEE_SUCCESS => Ok(handle),
+            error => Err(error),
+        }
+    }
+
+    #[test]
+    fn test_create_write_read() {
+        crate::reset();
+        let id = b"counter";
+        let mut handle = core::ptr::null_mut();
+        let flags = TEE_DATA_FLAG_ACCESS_READ | TEE_DATA_FLAG_ACCESS_WRITE;
+        let data = b"hello";
+        let result = unsafe {
+            raw::TEE_CreatePersistentObject(
+                PRIVATE,
+                id.as_ptr() as _,
+                id.len(),
+                flags,
+                core::ptr::null_mut(),
+                data.as_ptr() as _,
+                data.len(),
+                &mut handle,
+            )
+        };
+        assert_eq!(result, TEE_SUCCESS);
+        unsafe {
+            assert_eq!(
+                raw::TEE_SeekObjectData(handle, 8, TEE_Whence::TEE_DATA_SEEK_SET),
+                0
+            );
+            assert_eq!(raw::TEE_WriteObjectData(handle, b"!".as_ptr() as _, 1), 0);
+            raw::TEE_CloseObject(handle);
+        }
+        assert_eq!(get(PRIVATE, id), Some(b"hello\0\0\0!".to_vec()));
+
+        let handle = open(id, TEE_DATA_FLAG_ACCESS_READ).unwrap();
+        let mut buffer = [0u8; 16];
+        let mut count = 0;
+        unsafe {
+            raw::TEE_ReadObjectData(handle, buffer.as_mut_ptr() as _, 4, &mut count);
+            assert_eq!(&buffer[..count], b"hell");
+            raw::TEE_ReadObjectData(handle, buffer.as_mut_ptr() as _, 16, &mut count);
+            assert_eq!(&buffer[..count], b"o\0\0\0!");
+            raw::TEE_CloseObject(handle);
+        }
+        assert_eq!(open(b"missing", 0), Err(TEE_ERROR_ITEM_NOT_FOUND));
+    }
+
+    #[test]
+    fn test_access_conflict() {
+        crate::reset();
+        insert(PRIVATE, b"shared", b"data".as_slice());
+        let reader = open(
+            b"shared",
+            TEE_DATA_FLAG_ACCESS_READ | TEE_DATA_FLAG_SHARE_READ,
+        )
+        .unwrap();
+        let second = open(
+            b"shared",
+            TEE_DATA_FLAG_ACCESS_READ | TEE_DATA_FLAG_SHARE_READ,
+        )
+        .unwrap();
+        assert_eq!(
+            open(b"shared", TEE_DATA_FLAG_ACCESS_WRITE),
+            Err(TEE_ERROR_ACCESS_CONFLICT)
+        );
+        unsafe {
+            raw::TEE_CloseObject(reader);
+            raw::TEE_CloseObject(second);
+        }
+
+        let owner = open(b"shared", TEE_DATA_FLAG_ACCESS_WRITE_META).unwrap();
+        assert_eq!(
+            open(b"shared", TEE_DATA_FLAG_SHARE_READ),
+            Err(TEE_ERROR_ACCESS_CONFLICT)
+        );
+        let result = unsafe { raw::TEE_CloseAndDeletePersistentObject1(owner) };
+        assert_eq!(result, TEE_SUCCESS);
+        assert!(ids(PRIVATE).is_empty());
+    }
+
+    #[test]
+    fn test_enumerate_and_rename() {
+        crate::reset();
+        insert(PRIVATE, b"b", b"2".as_slice());
+        insert(PRIVATE, b"a", b"1".as_slice());
+        let handle = open(b"b", TEE_DATA_FLAG_ACCESS_WRITE_META).unwrap();
+        let result = unsafe { raw::TEE_RenamePersistentObject(handle, b"c".as_ptr() as _, 1) };
+        assert_eq!(result, TEE_SUCCESS);
+        unsafe { raw::TEE_CloseObject(handle) };
+
+        let mut enumerator = core::ptr::null_mut();
+        let mut found = Vec::new();
+        unsafe {
+            raw::TEE_AllocatePersistentObjectEnumerator(&mut enumerator);
+            assert_eq!(
+                raw::TEE_StartPersistentObjectEnumerator(enumerator, PRIVATE),
+                0
+            );
+            let mut id = [0u8; TEE_OBJECT_ID_MAX_LEN as usize];
+            let mut len = 0;
+            while raw::TEE_GetNextPersistentObject(
+                enumerator,
+                core::ptr::null_mut(),
+                id.as_mut_ptr() as _,
+                &mut len,
+            ) == TEE_SUCCESS
+            {
+                found.push(id[..len].to_vec());
+            }
+            raw::TEE_FreePersistentObjectEnumerator(enumerator);
+        }
+        assert_eq!(found, vec![b"a".to_vec(), b"c".to_vec()]);
+    }
+}
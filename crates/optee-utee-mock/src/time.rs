@@ -0,0 +1,190 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Fake clock of the time functions.
+//!
+//! The system time starts at zero and only moves when the test calls
+//! [`advance`] or the TA calls `TEE_Wait`, which returns immediately, so
+//! time-dependent logic can be tested without sleeping:
+//!
+//! ```rust,ignore
+//! use std::time::Duration;
+//! use optee_utee::mock;
+//!
+//! let session = Session::start();
+//! mock::time::advance(Duration::from_secs(301));
+//! assert!(session.is_expired());
+//! ```
+//!
+//! The REE time is the system time plus an offset set with [`set_ree_time`].
+//! The TA persistent time is not set until the TA sets it, and then advances
+//! with the system time.
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use optee_utee_sys::{
+    TEE_ERROR_OVERFLOW, TEE_ERROR_TIME_NOT_SET, TEE_Result, TEE_SUCCESS, TEE_TIMEOUT_INFINITE,
+    TEE_Time,
+};
+
+use crate::tee_panic;
+
+thread_local! {
+    static SYSTEM_TIME: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+    static REE_OFFSET: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+    /// TA persistent time minus system time, `None` until set
+    static TA_OFFSET: Cell<Option<i128>> = const { Cell::new(None) };
+}
+
+/// Move the system time forward by `duration`.
+pub fn advance(duration: Duration) {
+    SYSTEM_TIME.set(SYSTEM_TIME.get() + duration);
+}
+
+/// The system time, as returned by `TEE_GetSystemTime`.
+pub fn now() -> Duration {
+    SYSTEM_TIME.get()
+}
+
+/// Set the REE time, typically to a time since the Unix epoch.
+pub fn set_ree_time(time: Duration) {
+    REE_OFFSET.set(time.saturating_sub(SYSTEM_TIME.get()));
+}
+
+pub(crate) fn reset() {
+    SYSTEM_TIME.set(Duration::ZERO);
+    REE_OFFSET.set(Duration::ZERO);
+    TA_OFFSET.set(None);
+}
+
+fn millis(time: Duration) -> i128 {
+    time.as_millis() as i128
+}
+
+/// # Safety
+///
+/// `time` must be valid for writes.
+unsafe fn write_time(time: *mut TEE_Time, millis: i128) -> TEE_Result {
+    let seconds = millis.div_euclid(1000);
+    let result = if (0..=u32::MAX as i128).contains(&seconds) {
+        TEE_SUCCESS
+    } else {
+        TEE_ERROR_OVERFLOW
+    };
+    unsafe {
+        *time = TEE_Time {
+            seconds: seconds as u32,
+            millis: millis.rem_euclid(1000) as u32,
+        }
+    };
+    result
+}
+
+tee_api! {
+    fn TEE_GetSystemTime(time: *mut TEE_Time) {
+        unsafe { write_time(time, millis(SYSTEM_TIME.get())) };
+    }
+}
+
+tee_api! {
+    fn TEE_GetREETime(time: *mut TEE_Time) {
+        unsafe { write_time(time, millis(REE_OFFSET.get() + SYSTEM_TIME.get())) };
+    }
+}
+
+tee_api! {
+    fn TEE_GetTAPersistentTime(time: *mut TEE_Time) -> TEE_Result {
+        match TA_OFFSET.get() {
+            Some(offset) => unsafe { write_time(time, offset + millis(SYSTEM_TIME.get())) },
+            None => {
+                unsafe { *time = TEE_Time { seconds: 0, millis: 0 } };
+                TEE_ERROR_TIME_NOT_SET
+            }
+        }
+    }
+}
+
+tee_api! {
+    fn TEE_SetTAPersistentTime(time: *const TEE_Time) -> TEE_Result {
+        let time = unsafe { &*time };
+        let millis_set = time.seconds as i128 * 1000 + time.millis as i128;
+        TA_OFFSET.set(Some(millis_set - millis(SYSTEM_TIME.get())));
+        TEE_SUCCESS
+    }
+}
+
+tee_api! {
+    fn TEE_Wait(timeout: u32) -> TEE_Result {
+        if timeout == TEE_TIMEOUT_INFINITE {
+            tee_panic("TEE_Wait", "waiting forever would hang the test");
+        }
+        advance(Duration::from_millis(timeout as u64));
+        TEE_SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use optee_utee_sys as raw;
+
+    use super::*;
+
+    fn system_time() -> (u32, u32) {
+        let mut time = TEE_Time {
+            seconds: 0,
+            millis: 0,
+        };
+        unsafe { raw::TEE_GetSystemTime(&mut time) };
+        (time.seconds, time.millis)
+    }
+
+    #[test]
+    fn test_clock() {
+        crate::reset();
+        assert_eq!(system_time(), (0, 0));
+        advance(Duration::from_millis(1500));
+        assert_eq!(unsafe { raw::TEE_Wait(1000) }, TEE_SUCCESS);
+        assert_eq!(system_time(), (2, 500));
+        assert_eq!(now(), Duration::from_millis(2500));
+
+        set_ree_time(Duration::from_secs(1_700_000_000));
+        advance(Duration::from_secs(1));
+        let mut time = TEE_Time {
+            seconds: 0,
+            millis: 0,
+        };
+        unsafe { raw::TEE_GetREETime(&mut time) };
+        assert_eq!(time.seconds, 1_700_000_001);
+    }
+
+    #[test]
+    fn test_ta_persistent_time() {
+        crate::reset();
+        let mut time = TEE_Time {
+            seconds: 0,
+            millis: 0,
+        };
+        let result = unsafe { raw::TEE_GetTAPersistentTime(&mut time) };
+        assert_eq!(result, TEE_ERROR_TIME_NOT_SET);
+
+        time.seconds = 100;
+        assert_eq!(unsafe { raw::TEE_SetTAPersistentTime(&time) }, TEE_SUCCESS);
+        advance(Duration::from_secs(5));
+        let result = unsafe { raw::TEE_GetTAPersistentTime(&mut time) };
+        assert_eq!((result, time.seconds), (TEE_SUCCESS, 105));
+    }
+}
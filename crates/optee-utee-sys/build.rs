@@ -18,10 +18,16 @@
 use std::env::{self, VarError};
 use std::path::PathBuf;
 
+#[cfg(feature = "generated_bindings")]
+#[path = "build/bindings.rs"]
+mod bindings;
+
 fn main() -> Result<(), VarError> {
     if !cfg!(feature = "no_link") {
         link();
     }
+    #[cfg(feature = "generated_bindings")]
+    bindings::check(&PathBuf::from(env::var("TA_DEV_KIT_DIR")?));
     Ok(())
 }
 
@@ -0,0 +1,381 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Checks the hand-maintained bindings against the ones bindgen generates
+//! from the headers of the TA dev kit, for the `generated_bindings` feature.
+//!
+//! Both sets of bindings are reduced to their ABI: the parameter and field
+//! types of the functions and structs, with the C integer types replaced by
+//! the Rust ones of the target and without the parameter names, and the
+//! values of the integer constants. The build fails with the differences
+//! between the declarations found in both.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The headers of the TA dev kit the bindings are declared by.
+const HEADERS: &[&str] = &[
+    "tee_api_types.h",
+    "tee_api_defines.h",
+    "utee_types.h",
+    "user_ta_header.h",
+    "tee_api.h",
+    "utee_syscalls.h",
+    "tee_tcpsocket.h",
+    "tee_udpsocket.h",
+    "tee_internal_api.h",
+    "tee_internal_api_extensions.h",
+    "__tee_tcpsocket_defines_extensions.h",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Function,
+    Constant,
+    Struct,
+    Alias,
+}
+
+impl Kind {
+    fn keyword(self) -> &'static str {
+        match self {
+            Kind::Function => "fn",
+            Kind::Constant => "const",
+            Kind::Struct => "struct",
+            Kind::Alias => "type",
+        }
+    }
+}
+
+/// The normalized declarations of a set of bindings, by kind and name.
+type Declarations = BTreeMap<(Kind, String), String>;
+
+pub fn check(ta_dev_kit_dir: &Path) {
+    let include = ta_dev_kit_dir.join("include");
+    let generated = generate(&include);
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("generated_bindings.rs");
+    fs::write(&out, &generated).expect("the generated bindings should be written");
+
+    let pointer_width = env::var("CARGO_CFG_TARGET_POINTER_WIDTH").unwrap_or_default();
+    let mut maintained = Declarations::new();
+    println!("cargo:rerun-if-env-changed=TA_DEV_KIT_DIR");
+    let src = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("src");
+    let mut sources: Vec<PathBuf> = fs::read_dir(src)
+        .expect("the sources should be readable")
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "rs"))
+        .collect();
+    sources.sort();
+    for source in sources {
+        println!("cargo:rerun-if-changed={}", source.display());
+        let text = fs::read_to_string(&source).expect("the sources should be readable");
+        parse(&text, &pointer_width, &mut maintained);
+    }
+    let mut headers = Declarations::new();
+    parse(&generated, &pointer_width, &mut headers);
+
+    let drift = diff(&maintained, &headers);
+    if !drift.is_empty() {
+        panic!(
+            "the bindings of optee-utee-sys do not match the headers in {}:\n\n{}\n\
+             The bindings generated from the headers are in {}",
+            include.display(),
+            drift,
+            out.display()
+        );
+    }
+}
+
+fn generate(include: &Path) -> String {
+    let mut builder = bindgen::Builder::default()
+        .use_core()
+        .layout_tests(false)
+        .generate_comments(false)
+        .clang_arg(format!("-I{}", include.display()))
+        .allowlist_file(format!("{}/.*", include.display()));
+    // The headers of OP-TEE are not available for the target of the TAs
+    // known to clang, only the data model matters
+    match env::var("CARGO_CFG_TARGET_ARCH").as_deref() {
+        Ok("aarch64") => builder = builder.clang_arg("--target=aarch64-linux-gnu"),
+        Ok("arm") => builder = builder.clang_arg("--target=arm-linux-gnueabihf"),
+        _ => {}
+    }
+    for header in HEADERS {
+        let path = include.join(header);
+        println!("cargo:rerun-if-changed={}", path.display());
+        builder = builder.header(path.display().to_string());
+    }
+    builder
+        .generate()
+        .unwrap_or_else(|e| panic!("bindgen failed on {}: {}", include.display(), e))
+        .to_string()
+}
+
+/// The differences between the declarations of both sets, for the functions
+/// maintained by hand and those of the other kinds declared by both.
+fn diff(maintained: &Declarations, headers: &Declarations) -> String {
+    let mut drift = String::new();
+    for ((kind, name), declaration) in maintained {
+        let header = headers.get(&(*kind, name.clone()));
+        if header == Some(declaration) || (header.is_none() && *kind != Kind::Function) {
+            continue;
+        }
+        // The anonymous types and the bitfields of the headers have no
+        // counterpart to compare with
+        if header.is_some_and(|header| header.contains("__bindgen") || header.contains("_bitfield"))
+        {
+            continue;
+        }
+        let _ = writeln!(drift, "{} {}", kind.keyword(), name);
+        let _ = writeln!(drift, "  optee-utee-sys: {}", declaration);
+        match header {
+            Some(header) => {
+                let _ = writeln!(drift, "  headers:        {}", header);
+            }
+            None => drift.push_str("  headers:        not declared\n"),
+        }
+    }
+    drift
+}
+
+/// Collects the declarations of `source` without a body, which are the
+/// bindings.
+fn parse(source: &str, pointer_width: &str, declarations: &mut Declarations) {
+    let tokens = tokenize(source);
+    let normalize = |tokens: &[String]| normalize(tokens, pointer_width);
+    let mut i = 0;
+    while i < tokens.len() {
+        let keyword = tokens[i].as_str();
+        i += 1;
+        let Some(name) = tokens.get(i).cloned() else {
+            break;
+        };
+        match keyword {
+            // Attributes may hold anything
+            "#" => {
+                if name == "!" {
+                    i += 1;
+                }
+                i = closing(&tokens, i);
+            }
+            "fn" => {
+                let open = i + 1;
+                if tokens.get(open).map(String::as_str) != Some("(") {
+                    continue;
+                }
+                let close = closing(&tokens, open);
+                let end = close
+                    + tokens[close..]
+                        .iter()
+                        .position(|t| t == ";" || t == "{")
+                        .unwrap_or(0);
+                if tokens.get(end).map(String::as_str) != Some(";") {
+                    continue;
+                }
+                let params: Vec<String> = split(&tokens[open + 1..close - 1])
+                    .iter()
+                    .map(|param| normalize(param))
+                    .collect();
+                let mut signature = format!("({})", params.join(", "));
+                if tokens.get(close).map(String::as_str) == Some("->") {
+                    signature.push_str(&format!(" -> {}", normalize(&tokens[close + 1..end])));
+                }
+                declarations.insert((Kind::Function, name), signature);
+                i = end;
+            }
+            "const" if tokens.get(i + 1).map(String::as_str) == Some(":") => {
+                let end = i + tokens[i..].iter().position(|t| t == ";").unwrap_or(0);
+                let equal = i + tokens[i..end].iter().position(|t| t == "=").unwrap_or(0);
+                if let Some(value) = integer(&tokens[equal + 1..end]) {
+                    declarations.insert((Kind::Constant, name), value.to_string());
+                }
+                i = end;
+            }
+            "struct" | "union" if tokens.get(i + 1).map(String::as_str) == Some("{") => {
+                let close = closing(&tokens, i + 1);
+                let fields: Vec<String> = split(&tokens[i + 2..close - 1])
+                    .iter()
+                    .map(|field| normalize(field))
+                    .collect();
+                // Opaque types have no layout to compare
+                if !fields.iter().any(|field| field.contains("_unused")) {
+                    declarations
+                        .insert((Kind::Struct, name), format!("{{ {} }}", fields.join(", ")));
+                }
+                i = close;
+            }
+            "type" if tokens.get(i + 1).map(String::as_str) == Some("=") => {
+                let end = i + tokens[i..].iter().position(|t| t == ";").unwrap_or(0);
+                declarations.insert((Kind::Alias, name), normalize(&tokens[i + 2..end]));
+                i = end;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Splits `source` into identifiers, literals and punctuation, without the
+/// comments.
+fn tokenize(source: &str) -> Vec<String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+        let length = if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if rest.starts_with(&['/', '/']) {
+            i += rest.iter().position(|&c| c == '\n').unwrap_or(rest.len());
+            continue;
+        } else if rest.starts_with(&['/', '*']) {
+            i += rest
+                .windows(2)
+                .position(|w| w == ['*', '/'])
+                .map_or(rest.len(), |p| p + 2);
+            continue;
+        } else if c == '"' {
+            1 + rest[1..]
+                .iter()
+                .position(|&c| c == '"')
+                .map_or(rest.len() - 1, |p| p + 1)
+        } else if c.is_alphanumeric() || c == '_' {
+            rest.iter()
+                .position(|&c| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len())
+        } else if rest.starts_with(&['.', '.', '.']) {
+            3
+        } else if rest.starts_with(&[':', ':']) || rest.starts_with(&['-', '>']) {
+            2
+        } else {
+            1
+        };
+        tokens.push(rest[..length].iter().collect());
+        i += length;
+    }
+    tokens
+}
+
+/// The index after the bracket closing the one at `open`.
+fn closing(tokens: &[String], open: usize) -> usize {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token.as_str() {
+            "(" | "[" | "{" | "<" => depth += 1,
+            ")" | "]" | "}" | ">" => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// Splits a list of parameters or fields on the commas outside brackets.
+fn split(tokens: &[String]) -> Vec<Vec<String>> {
+    let mut items = vec![Vec::new()];
+    let mut depth = 0usize;
+    for token in tokens {
+        match token.as_str() {
+            "(" | "[" | "{" | "<" => depth += 1,
+            ")" | "]" | "}" | ">" => depth = depth.saturating_sub(1),
+            "," if depth == 0 => {
+                items.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        items.last_mut().unwrap().push(token.clone());
+    }
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// The type of a type, a parameter or a field, without the names, the
+/// paths, the visibility and the attributes, and with the C integer types
+/// and the integer literals of Rust.
+fn normalize(tokens: &[String], pointer_width: &str) -> String {
+    let long = if pointer_width == "64" { "i64" } else { "i32" };
+    let unsigned_long = if pointer_width == "64" { "u64" } else { "u32" };
+    let mut normalized: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i].as_str();
+        let next = tokens.get(i + 1).map(String::as_str);
+        match (token, next) {
+            ("#", _) | ("pub", Some("(")) => {
+                i = closing(tokens, i + 1);
+                continue;
+            }
+            ("pub", _) | ("::", _) | (":", _) | (_, Some(":")) => {}
+            // The paths, but not `*const ::core::ffi::c_char`
+            (token, Some("::")) if token != "const" && token != "mut" => {}
+            (",", Some(")" | "]" | "}" | ">")) => {}
+            _ => normalized.push(match token {
+                "c_schar" => "i8".into(),
+                "c_uchar" => "u8".into(),
+                "c_short" => "i16".into(),
+                "c_ushort" => "u16".into(),
+                "c_int" => "i32".into(),
+                "c_uint" => "u32".into(),
+                "c_long" => long.into(),
+                "c_ulong" => unsigned_long.into(),
+                "c_longlong" => "i64".into(),
+                "c_ulonglong" => "u64".into(),
+                "size_t" => "usize".into(),
+                "intmax_t" => "i64".into(),
+                // Array lengths, `8usize` in the generated bindings
+                token if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                    integer(&tokens[i..=i]).map_or_else(|| token.into(), |value| value.to_string())
+                }
+                token => token.into(),
+            }),
+        }
+        i += 1;
+    }
+    normalized.join(" ")
+}
+
+/// The value of an integer literal, possibly negated or cast.
+fn integer(tokens: &[String]) -> Option<i128> {
+    let (negative, tokens) = match tokens.split_first() {
+        Some((minus, rest)) if minus == "-" => (true, rest),
+        _ => (false, tokens),
+    };
+    let literal = tokens.first()?.replace('_', "");
+    if tokens.len() != 1 && tokens.get(1).map(String::as_str) != Some("as") {
+        return None;
+    }
+    let literal = [
+        "u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize",
+    ]
+    .iter()
+    .find_map(|suffix| literal.strip_suffix(suffix))
+    .unwrap_or(&literal);
+    let value = match literal.strip_prefix("0x") {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => literal.parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
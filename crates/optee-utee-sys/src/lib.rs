@@ -36,6 +36,7 @@ pub use trace::*;
 pub use user_ta_header::*;
 pub use utee_syscalls::*;
 pub use utee_types::*;
+pub use version::*;
 
 mod tee_api;
 mod tee_api_defines;
@@ -49,6 +50,7 @@ mod trace;
 mod user_ta_header;
 mod utee_syscalls;
 mod utee_types;
+mod version;
 
 pub type size_t = usize;
 // https://github.com/OP-TEE/optee_os/blob/c2b0684fcd89929976a8726e6e3af922b48dd2c7/lib/libutils/isoc/include/stdint.h#L92
@@ -21,3 +21,4 @@ use std::sync::Mutex;
 pub static SERIAL_TEST_LOCK: Mutex<()> = Mutex::new(());
 
 pub mod object;
+pub mod storage;
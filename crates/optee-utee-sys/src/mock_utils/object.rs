@@ -19,6 +19,12 @@ use crate::TEE_ObjectHandle;
 
 pub struct MockHandle(crate::__TEE_ObjectHandle);
 
+impl Default for MockHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MockHandle {
     pub fn new() -> Self {
         Self(unsafe { core::mem::zeroed() })
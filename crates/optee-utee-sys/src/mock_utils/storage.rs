@@ -0,0 +1,331 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! In-memory Trusted Storage on top of the mocked API, for the unit tests of
+//! the crates using the secure storage.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::slice;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::SERIAL_TEST_LOCK;
+use crate::{self as raw, mock_api};
+
+/// `TEE_STORAGE_PRIVATE_RPMB` of the OP-TEE extensions.
+const STORAGE_PRIVATE_RPMB: u32 = 0x8000_0100;
+
+/// A storage ID and the ID of an object in it.
+type Key = (u32, Vec<u8>);
+
+struct Handle {
+    key: Key,
+    position: usize,
+    flags: u32,
+}
+
+#[derive(Default)]
+struct State {
+    objects: BTreeMap<Key, Vec<u8>>,
+    handles: BTreeMap<usize, Handle>,
+    next_handle: usize,
+    // Objects left to list by the enumerator
+    listing: Option<Vec<Key>>,
+    fail_create: bool,
+    without_rpmb: bool,
+}
+
+impl State {
+    fn open_handle(&mut self, key: Key, flags: u32, object: *mut raw::TEE_ObjectHandle) {
+        self.next_handle += 1;
+        let handle = Handle {
+            key,
+            position: 0,
+            flags,
+        };
+        self.handles.insert(self.next_handle, handle);
+        unsafe { *object = self.next_handle as raw::TEE_ObjectHandle };
+    }
+
+    /// Whether opening the object `key` with `flags` conflicts with its open
+    /// handles.
+    fn conflicts(&self, key: &Key, flags: u32) -> bool {
+        self.handles
+            .values()
+            .filter(|handle| handle.key == *key)
+            .any(|handle| !shares(handle.flags, flags) || !shares(flags, handle.flags))
+    }
+
+    /// Whether `storage_id` is unknown, as the storage IDs of the backends
+    /// OP-TEE is not built with are.
+    fn is_unknown(&self, storage_id: u32) -> bool {
+        self.without_rpmb && storage_id == STORAGE_PRIVATE_RPMB
+    }
+}
+
+/// Whether a handle opened with `open` lets another one access the object
+/// with `requested`, following the sharing rules of the GP specification: a
+/// handle writing the metadata is exclusive.
+fn shares(open: u32, requested: u32) -> bool {
+    (requested & raw::TEE_DATA_FLAG_ACCESS_READ == 0 || open & raw::TEE_DATA_FLAG_SHARE_READ != 0)
+        && (requested & raw::TEE_DATA_FLAG_ACCESS_WRITE == 0
+            || open & raw::TEE_DATA_FLAG_SHARE_WRITE != 0)
+        && (requested | open) & raw::TEE_DATA_FLAG_ACCESS_WRITE_META == 0
+}
+
+fn key(storage_id: u32, id: *const core::ffi::c_void, id_len: usize) -> Key {
+    let id = unsafe { slice::from_raw_parts(id as *const u8, id_len) };
+    (storage_id, id.to_vec())
+}
+
+/// In-memory Trusted Storage on top of the mocked API, handles are counters
+/// cast to pointers and never dereferenced. The objects of each storage ID
+/// are kept apart.
+///
+/// Opening an object fails with `AccessConflict` if its open handles do not
+/// share the requested access, and so does replacing an open object.
+///
+/// The storage holds [`SERIAL_TEST_LOCK`] and the expectations of the
+/// storage functions until it is dropped.
+pub struct MockStorage {
+    state: Arc<Mutex<State>>,
+    // Dropping the contexts clears the expectations.
+    _contexts: Vec<Box<dyn Any>>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Default for MockStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockStorage {
+    pub fn new() -> Self {
+        // A previous test failing while holding the lock must not fail the
+        // following ones.
+        let lock = SERIAL_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let state = Arc::new(Mutex::new(State::default()));
+        let mut contexts: Vec<Box<dyn Any>> = Vec::new();
+
+        let create = mock_api::TEE_CreatePersistentObject_context();
+        create.expect().returning({
+            let state = state.clone();
+            move |storage_id, id, id_len, flags, _, data, data_len, object| {
+                let mut state = state.lock().unwrap();
+                if state.is_unknown(storage_id) {
+                    return raw::TEE_ERROR_ITEM_NOT_FOUND;
+                }
+                if state.fail_create {
+                    return raw::TEE_ERROR_STORAGE_NO_SPACE;
+                }
+                let key = key(storage_id, id, id_len);
+                let data = unsafe { slice::from_raw_parts(data as *const u8, data_len) }.to_vec();
+                if state.objects.contains_key(&key)
+                    && (flags & raw::TEE_DATA_FLAG_OVERWRITE == 0
+                        || state.handles.values().any(|handle| handle.key == key))
+                {
+                    return raw::TEE_ERROR_ACCESS_CONFLICT;
+                }
+                state.objects.insert(key.clone(), data);
+                state.open_handle(key, flags, object);
+                raw::TEE_SUCCESS
+            }
+        });
+        contexts.push(Box::new(create));
+
+        let open = mock_api::TEE_OpenPersistentObject_context();
+        open.expect().returning({
+            let state = state.clone();
+            move |storage_id, id, id_len, flags, object| {
+                let mut state = state.lock().unwrap();
+                let key = key(storage_id, id, id_len);
+                if state.is_unknown(storage_id) || !state.objects.contains_key(&key) {
+                    return raw::TEE_ERROR_ITEM_NOT_FOUND;
+                }
+                if state.conflicts(&key, flags) {
+                    return raw::TEE_ERROR_ACCESS_CONFLICT;
+                }
+                state.open_handle(key, flags, object);
+                raw::TEE_SUCCESS
+            }
+        });
+        contexts.push(Box::new(open));
+
+        let info = mock_api::TEE_GetObjectInfo1_context();
+        info.expect().returning({
+            let state = state.clone();
+            move |object, info| {
+                let state = state.lock().unwrap();
+                let handle = &state.handles[&(object as usize)];
+                unsafe { (*info).dataSize = state.objects[&handle.key].len() };
+                raw::TEE_SUCCESS
+            }
+        });
+        contexts.push(Box::new(info));
+
+        let read = mock_api::TEE_ReadObjectData_context();
+        read.expect().returning({
+            let state = state.clone();
+            move |object, buffer, size, count| {
+                let mut state = state.lock().unwrap();
+                let State {
+                    objects, handles, ..
+                } = &mut *state;
+                let handle = handles.get_mut(&(object as usize)).unwrap();
+                let data = &objects[&handle.key][handle.position..];
+                let len = data.len().min(size);
+                unsafe {
+                    slice::from_raw_parts_mut(buffer as *mut u8, len).copy_from_slice(&data[..len]);
+                    *count = len;
+                }
+                handle.position += len;
+                raw::TEE_SUCCESS
+            }
+        });
+        contexts.push(Box::new(read));
+
+        let close = mock_api::TEE_CloseObject_context();
+        close.expect().returning({
+            let state = state.clone();
+            move |object| {
+                state.lock().unwrap().handles.remove(&(object as usize));
+            }
+        });
+        contexts.push(Box::new(close));
+
+        let delete = mock_api::TEE_CloseAndDeletePersistentObject1_context();
+        delete.expect().returning({
+            let state = state.clone();
+            move |object| {
+                let mut state = state.lock().unwrap();
+                if let Some(handle) = state.handles.remove(&(object as usize)) {
+                    state.objects.remove(&handle.key);
+                }
+                raw::TEE_SUCCESS
+            }
+        });
+        contexts.push(Box::new(delete));
+
+        let allocate = mock_api::TEE_AllocatePersistentObjectEnumerator_context();
+        allocate.expect().returning(|handle| {
+            unsafe { *handle = core::ptr::dangling_mut() };
+            raw::TEE_SUCCESS
+        });
+        contexts.push(Box::new(allocate));
+
+        let free = mock_api::TEE_FreePersistentObjectEnumerator_context();
+        free.expect().returning({
+            let state = state.clone();
+            move |_| state.lock().unwrap().listing = None
+        });
+        contexts.push(Box::new(free));
+
+        let start = mock_api::TEE_StartPersistentObjectEnumerator_context();
+        start.expect().returning({
+            let state = state.clone();
+            move |_, storage_id| {
+                let mut state = state.lock().unwrap();
+                let listing: Vec<Key> = state
+                    .objects
+                    .keys()
+                    .filter(|(other, _)| *other == storage_id)
+                    .rev()
+                    .cloned()
+                    .collect();
+                if listing.is_empty() {
+                    return raw::TEE_ERROR_ITEM_NOT_FOUND;
+                }
+                state.listing = Some(listing);
+                raw::TEE_SUCCESS
+            }
+        });
+        contexts.push(Box::new(start));
+
+        let next = mock_api::TEE_GetNextPersistentObject_context();
+        next.expect().returning({
+            let state = state.clone();
+            move |_, info, id, id_len| {
+                let mut state = state.lock().unwrap();
+                let Some(next) = state.listing.as_mut().and_then(Vec::pop) else {
+                    return raw::TEE_ERROR_ITEM_NOT_FOUND;
+                };
+                let next_id = &next.1;
+                unsafe {
+                    if !info.is_null() {
+                        (*info).dataSize = state.objects.get(&next).map_or(0, Vec::len);
+                    }
+                    core::ptr::copy_nonoverlapping(next_id.as_ptr(), id as *mut u8, next_id.len());
+                    *id_len = next_id.len();
+                }
+                raw::TEE_SUCCESS
+            }
+        });
+        contexts.push(Box::new(next));
+
+        Self {
+            state,
+            _contexts: contexts,
+            _lock: lock,
+        }
+    }
+
+    /// Store `data` as the object `id` of `TEE_STORAGE_PRIVATE`, without
+    /// going through the API.
+    pub fn insert_raw(&self, id: &[u8], data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        let key = (raw::TEE_STORAGE_PRIVATE, id.to_vec());
+        state.objects.insert(key, data.to_vec());
+    }
+
+    /// Return the data of the object `id` of `storage_id`.
+    pub fn get(&self, storage_id: u32, id: &[u8]) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        state.objects.get(&(storage_id, id.to_vec())).cloned()
+    }
+
+    /// Return whether an object `id` exists in any storage.
+    pub fn contains(&self, id: &[u8]) -> bool {
+        let state = self.state.lock().unwrap();
+        state.objects.keys().any(|(_, other)| other == id)
+    }
+
+    /// The storage IDs holding objects, in increasing order.
+    pub fn storage_ids(&self) -> Vec<u32> {
+        let state = self.state.lock().unwrap();
+        let mut storage_ids: Vec<u32> = state.objects.keys().map(|(id, _)| *id).collect();
+        storage_ids.dedup();
+        storage_ids
+    }
+
+    /// Make the creation of objects fail with `TEE_ERROR_STORAGE_NO_SPACE`.
+    pub fn set_fail_create(&self, fail: bool) {
+        self.state.lock().unwrap().fail_create = fail;
+    }
+
+    /// Answer the accesses to `TEE_STORAGE_PRIVATE_RPMB` as an OS built
+    /// without RPMB does, with `TEE_ERROR_ITEM_NOT_FOUND`.
+    pub fn set_without_rpmb(&self, without_rpmb: bool) {
+        self.state.lock().unwrap().without_rpmb = without_rpmb;
+    }
+
+    pub fn open_handles(&self) -> usize {
+        self.state.lock().unwrap().handles.len()
+    }
+}
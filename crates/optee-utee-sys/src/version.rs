@@ -0,0 +1,25 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+// The releases of OP-TEE OS whose TA dev kit these bindings were written
+// against, as `(major, minor)`, checked by optee-utee-build when building a
+// TA. Update together with `optee-version.txt` on each OP-TEE release.
+
+/// The oldest release of OP-TEE OS supported.
+pub const OPTEE_OS_VERSION_MIN: (u32, u32) = (4, 0);
+/// The newest release of OP-TEE OS supported.
+pub const OPTEE_OS_VERSION_MAX: (u32, u32) = (4, 10);
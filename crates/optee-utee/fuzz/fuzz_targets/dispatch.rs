@@ -0,0 +1,67 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Decodes the JSON input of typed commands from adversarial invocations,
+//! through the entry point generated by `#[ta_dispatch]`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use optee_utee::dispatch::Json;
+use optee_utee::mock::{self, fuzz::Invocation};
+use optee_utee::{Result, TaCommand, ta_dispatch};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Transfer {
+    from: String,
+    to: String,
+    amount: u64,
+    memo: Option<Vec<u8>>,
+}
+
+#[derive(TaCommand)]
+#[ta_command(codec = Json)]
+enum Command {
+    #[ta_command(output = Transfer)]
+    Echo(Transfer),
+    #[ta_command(output = u64)]
+    Sum(Vec<u64>),
+    Reset,
+}
+
+#[derive(Default)]
+struct Session;
+
+#[ta_dispatch(stateless)]
+impl CommandHandler for Session {
+    fn echo(&mut self, input: Transfer) -> Result<Transfer> {
+        Ok(input)
+    }
+
+    fn sum(&mut self, input: Vec<u64>) -> Result<u64> {
+        Ok(input.iter().fold(0, |sum, value| sum.wrapping_add(*value)))
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    mock::reset();
+    Invocation::from_bytes(data).invoke(TA_InvokeCommandEntryPoint, core::ptr::null_mut());
+});
@@ -0,0 +1,86 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Converts the parameters of adversarial invocations with `ParameterAny`
+//! and exercises the wrappers of each type, the command ID selecting how
+//! the outputs are written.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use optee_utee::dispatch::Json;
+use optee_utee::mock::{self, fuzz::Invocation};
+use optee_utee::{
+    OutputWriter, ParameterAny, ParameterMemrefRead, ParameterMemrefWrite, ParameterValueRead,
+    ParameterValueWrite, ParametersAny, Result, ta_invoke_command,
+};
+
+#[ta_invoke_command]
+fn invoke_command(cmd_id: u32, params: &mut ParametersAny) -> Result<()> {
+    let (p0, p1, p2, p3) = params;
+    for param in [p0, p1, p2, p3] {
+        exercise(cmd_id, param)?;
+    }
+    Ok(())
+}
+
+fn exercise(cmd_id: u32, param: &mut ParameterAny) -> Result<()> {
+    match param {
+        ParameterAny::None | ParameterAny::Unknown(..) => {}
+        ParameterAny::ValueInput(p) => {
+            let _ = p.get_a().wrapping_add(p.get_b());
+        }
+        ParameterAny::ValueOutput(p) => {
+            p.set_a(cmd_id);
+            p.set_b(!cmd_id);
+        }
+        ParameterAny::ValueInout(p) => {
+            let a = p.get_a();
+            p.set_a(p.get_b());
+            p.set_b(a);
+        }
+        ParameterAny::MemrefInput(p) => {
+            let _ = p.deserialize::<Json, serde_json::Value>();
+        }
+        ParameterAny::MemrefOutput(p) => write_output(cmd_id, p)?,
+        ParameterAny::MemrefInout(p) => {
+            let _ = p.snapshot().deserialize::<Json, serde_json::Value>();
+            write_output(cmd_id, p)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write an output whose size and writer are chosen by `cmd_id`.
+fn write_output<P: ParameterMemrefWrite>(cmd_id: u32, param: &mut P) -> Result<()> {
+    let data = vec![cmd_id as u8; (cmd_id >> 2) as usize % 512];
+    match cmd_id & 3 {
+        0 => param.set_output(&data),
+        1 => param.write_at(data.len() / 2, &data),
+        2 => {
+            let mut writer = OutputWriter::new(param);
+            writer.write(&data);
+            writer.write(&data);
+            writer.finish()
+        }
+        _ => param.serialize_into::<Json, _>(&data),
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    mock::reset();
+    Invocation::from_bytes(data).invoke(TA_InvokeCommandEntryPoint, core::ptr::null_mut());
+});
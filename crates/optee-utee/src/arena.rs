@@ -0,0 +1,534 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Bump allocation for short-lived, per-command data.
+//!
+//! The TA heap is small and fixed in size, and TAs that allocate many
+//! short-lived buffers on every invocation tend to fragment it over long
+//! uptimes. An [`Arena`] hands out memory from a single pre-allocated chunk by
+//! bumping an offset, and releases everything at once when it is reset.
+//!
+//! Most TAs should use [`scope`], which lends the TA-wide command arena to a
+//! closure and resets it as soon as the closure returns, so nothing allocated
+//! from it can outlive the command:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::{arena, Result};
+//! # fn handle(input: &[u8]) -> Result<()> {
+//! arena::scope(|arena| {
+//!     let scratch = arena.alloc_bytes(input.len())?;
+//!     scratch.copy_from_slice(input);
+//!     // ... work on `scratch` ...
+//!     Ok(())
+//! })
+//! # }
+//! ```
+//!
+//! The capacity of the command arena defaults to [`DEFAULT_CAPACITY`] and can
+//! be changed once at start-up, typically from `#[ta_create]`, with [`init`].
+//!
+//! The typed command dispatch and
+//! [`serialize_into`](crate::ParameterMemrefWrite::serialize_into) encode the
+//! outputs of the commands in the command arena, see
+//! [`encode_output`](crate::dispatch::encode_output). The capacity should
+//! then hold the largest output, larger ones are encoded on the heap.
+
+use crate::{ErrorKind, Result};
+use alloc::alloc::{Layout, alloc, dealloc};
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Capacity of the command arena when [`init`] has not been called.
+pub const DEFAULT_CAPACITY: usize = 16 * 1024;
+
+/// Alignment of the chunk backing an arena.
+const CHUNK_ALIGN: usize = 16;
+
+/// A fixed-capacity bump allocator.
+///
+/// Allocations are served from one chunk of the TA heap which is allocated when
+/// the arena is created. Individual allocations are never freed, the whole
+/// arena is recycled by [`Arena::reset`], which requires exclusive access and
+/// therefore cannot invalidate outstanding references.
+///
+/// Only `Copy` data can be placed in an arena, as destructors of values stored
+/// in it are never run.
+pub struct Arena {
+    chunk: NonNull<u8>,
+    capacity: usize,
+    offset: Cell<usize>,
+    peak: Cell<usize>,
+    writer_active: Cell<bool>,
+}
+
+impl Arena {
+    /// Allocate an arena of `capacity` bytes from the TA heap.
+    ///
+    /// # Errors
+    ///
+    /// 1) `OutOfMemory`: If the chunk cannot be allocated.
+    pub fn new(capacity: usize) -> Result<Self> {
+        let layout = Self::chunk_layout(capacity)?;
+        let chunk = match capacity {
+            0 => NonNull::<u128>::dangling().cast(),
+            _ => NonNull::new(unsafe { alloc(layout) }).ok_or(ErrorKind::OutOfMemory)?,
+        };
+        Ok(Self {
+            chunk,
+            capacity,
+            offset: Cell::new(0),
+            peak: Cell::new(0),
+            writer_active: Cell::new(false),
+        })
+    }
+
+    /// Returns the total number of bytes this arena can hand out.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of bytes handed out since the last reset, including
+    /// alignment padding.
+    pub fn used(&self) -> usize {
+        self.offset.get()
+    }
+
+    /// Returns the number of bytes still available.
+    pub fn remaining(&self) -> usize {
+        self.capacity - self.offset.get()
+    }
+
+    /// Returns the highest [`used`](Arena::used) value observed over the
+    /// lifetime of the arena, useful for sizing it.
+    pub fn peak(&self) -> usize {
+        self.peak.get()
+    }
+
+    /// Release every allocation at once.
+    pub fn reset(&mut self) {
+        self.offset.set(0);
+    }
+
+    /// Allocate `len` zeroed bytes.
+    ///
+    /// # Errors
+    ///
+    /// 1) `OutOfMemory`: If the arena does not have `len` bytes left.
+    /// 2) `Busy`: If an [`ArenaWriter`] of this arena is still alive.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_bytes(&self, len: usize) -> Result<&mut [u8]> {
+        let layout = Layout::array::<u8>(len).map_err(|_| ErrorKind::OutOfMemory)?;
+        let ptr = self.alloc_layout(layout)?;
+        unsafe {
+            ptr.as_ptr().write_bytes(0, len);
+            Ok(core::slice::from_raw_parts_mut(ptr.as_ptr(), len))
+        }
+    }
+
+    /// Move `value` into the arena.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Arena::alloc_bytes`].
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc<T: Copy>(&self, value: T) -> Result<&mut T> {
+        let ptr = self.alloc_layout(Layout::new::<T>())?.cast::<T>();
+        unsafe {
+            ptr.as_ptr().write(value);
+            Ok(&mut *ptr.as_ptr())
+        }
+    }
+
+    /// Copy `src` into the arena.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Arena::alloc_bytes`].
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> Result<&mut [T]> {
+        let layout = Layout::array::<T>(src.len()).map_err(|_| ErrorKind::OutOfMemory)?;
+        let ptr = self.alloc_layout(layout)?.cast::<T>();
+        unsafe {
+            ptr.as_ptr()
+                .copy_from_nonoverlapping(src.as_ptr(), src.len());
+            Ok(core::slice::from_raw_parts_mut(ptr.as_ptr(), src.len()))
+        }
+    }
+
+    /// Copy `src` into the arena.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Arena::alloc_bytes`].
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_str(&self, src: &str) -> Result<&mut str> {
+        let bytes = self.alloc_slice_copy(src.as_bytes())?;
+        Ok(unsafe { core::str::from_utf8_unchecked_mut(bytes) })
+    }
+
+    /// Start writing a byte string of unknown length at the end of the arena.
+    ///
+    /// The writer may use all the remaining space. Other allocations from this
+    /// arena fail with `Busy` until the writer is finished or dropped.
+    ///
+    /// # Example
+    ///
+    /// ``` rust,no_run
+    /// # use optee_utee::{arena::Arena, Result};
+    /// # use core::fmt::Write;
+    /// # fn main() -> Result<()> {
+    /// let arena = Arena::new(1024)?;
+    /// let mut writer = arena.writer()?;
+    /// write!(writer, "Hello, {}", "World").map_err(|_| optee_utee::ErrorKind::ShortBuffer)?;
+    /// let message: &[u8] = writer.finish();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// 1) `Busy`: If another [`ArenaWriter`] of this arena is still alive.
+    pub fn writer(&self) -> Result<ArenaWriter<'_>> {
+        if self.writer_active.replace(true) {
+            return Err(ErrorKind::Busy.into());
+        }
+        Ok(ArenaWriter {
+            arena: self,
+            start: self.offset.get(),
+            len: 0,
+        })
+    }
+
+    fn chunk_layout(capacity: usize) -> Result<Layout> {
+        Layout::from_size_align(capacity, CHUNK_ALIGN).map_err(|_| ErrorKind::OutOfMemory.into())
+    }
+
+    fn alloc_layout(&self, layout: Layout) -> Result<NonNull<u8>> {
+        if self.writer_active.get() {
+            return Err(ErrorKind::Busy.into());
+        }
+
+        let offset = self.offset.get();
+        let start = unsafe { self.chunk.as_ptr().add(offset) };
+        let padding = start.align_offset(layout.align());
+        let end = offset
+            .checked_add(padding)
+            .and_then(|v| v.checked_add(layout.size()))
+            .filter(|v| *v <= self.capacity)
+            .ok_or(ErrorKind::OutOfMemory)?;
+
+        self.bump_to(end);
+        Ok(unsafe { NonNull::new_unchecked(start.add(padding)) })
+    }
+
+    fn bump_to(&self, end: usize) {
+        self.offset.set(end);
+        if end > self.peak.get() {
+            self.peak.set(end);
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        if self.capacity != 0 {
+            // The layout has been validated in `Arena::new`.
+            if let Ok(layout) = Self::chunk_layout(self.capacity) {
+                unsafe { dealloc(self.chunk.as_ptr(), layout) };
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Arena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Arena")
+            .field("capacity", &self.capacity)
+            .field("used", &self.used())
+            .field("peak", &self.peak())
+            .finish()
+    }
+}
+
+/// An append-only byte buffer growing at the end of an [`Arena`].
+///
+/// Implements [`core::fmt::Write`], and `std::io::Write` with the `std`
+/// feature, so serializers can write into the arena directly instead of into a
+/// temporary `Vec`.
+pub struct ArenaWriter<'a> {
+    arena: &'a Arena,
+    start: usize,
+    len: usize,
+}
+
+impl<'a> ArenaWriter<'a> {
+    /// Returns the bytes written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.arena.chunk.as_ptr().add(self.start), self.len) }
+    }
+
+    /// Append `data`.
+    ///
+    /// # Errors
+    ///
+    /// 1) `OutOfMemory`: If the arena does not have enough space left, nothing
+    ///    is written in that case.
+    pub fn write_bytes(&mut self, data: &[u8]) -> Result<()> {
+        let end = self
+            .start
+            .checked_add(self.len)
+            .and_then(|v| v.checked_add(data.len()))
+            .filter(|v| *v <= self.arena.capacity)
+            .ok_or(ErrorKind::OutOfMemory)?;
+        unsafe {
+            self.arena
+                .chunk
+                .as_ptr()
+                .add(self.start + self.len)
+                .copy_from_nonoverlapping(data.as_ptr(), data.len());
+        }
+        self.len = end - self.start;
+        Ok(())
+    }
+
+    /// Commit the written bytes to the arena and return them.
+    pub fn finish(self) -> &'a mut [u8] {
+        let (start, len) = (self.start, self.len);
+        self.arena.bump_to(start + len);
+        let arena = self.arena;
+        drop(self);
+        unsafe { core::slice::from_raw_parts_mut(arena.chunk.as_ptr().add(start), len) }
+    }
+}
+
+impl Drop for ArenaWriter<'_> {
+    fn drop(&mut self) {
+        self.arena.writer_active.set(false);
+    }
+}
+
+impl fmt::Write for ArenaWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for ArenaWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_bytes(buf)
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::OutOfMemory))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The TA-wide arena lent out by [`scope`].
+struct CommandArena {
+    arena: UnsafeCell<Option<Arena>>,
+    in_use: AtomicBool,
+}
+
+// SAFETY: `arena` is only accessed by the holder of the `in_use` flag.
+unsafe impl Sync for CommandArena {}
+
+impl CommandArena {
+    const fn new() -> Self {
+        Self {
+            arena: UnsafeCell::new(None),
+            in_use: AtomicBool::new(false),
+        }
+    }
+}
+
+#[cfg(not(test))]
+fn command_arena() -> &'static CommandArena {
+    static COMMAND_ARENA: CommandArena = CommandArena::new();
+    &COMMAND_ARENA
+}
+
+// The tests run in parallel threads, each of them gets its own arena.
+#[cfg(test)]
+fn command_arena() -> &'static CommandArena {
+    extern crate std;
+    std::thread_local! {
+        static COMMAND_ARENA: &'static CommandArena =
+            std::boxed::Box::leak(std::boxed::Box::new(CommandArena::new()));
+    }
+    COMMAND_ARENA.with(|arena| *arena)
+}
+
+/// Clears the `in_use` flag of the command arena when dropped.
+struct CommandArenaGuard;
+
+impl CommandArenaGuard {
+    fn acquire() -> Result<Self> {
+        command_arena()
+            .in_use
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .map(|_| Self)
+            .map_err(|_| ErrorKind::Busy.into())
+    }
+
+    fn arena(&mut self) -> &mut Option<Arena> {
+        // SAFETY: the guard holds the `in_use` flag.
+        unsafe { &mut *command_arena().arena.get() }
+    }
+}
+
+impl Drop for CommandArenaGuard {
+    fn drop(&mut self) {
+        command_arena().in_use.store(false, Ordering::Release);
+    }
+}
+
+/// Replace the command arena with a new one of `capacity` bytes.
+///
+/// # Errors
+///
+/// 1) `Busy`: If called from within [`scope`].
+/// 2) `OutOfMemory`: If the arena cannot be allocated.
+pub fn init(capacity: usize) -> Result<()> {
+    let mut guard = CommandArenaGuard::acquire()?;
+    // Free the old chunk first so both never occupy the heap at the same time.
+    *guard.arena() = None;
+    *guard.arena() = Some(Arena::new(capacity)?);
+    Ok(())
+}
+
+/// Run `f` with the command arena and reset the arena afterwards.
+///
+/// The arena is created with [`DEFAULT_CAPACITY`] on first use if [`init`] has
+/// not been called.
+///
+/// # Errors
+///
+/// 1) `Busy`: If called from within another `scope`.
+/// 2) `OutOfMemory`: If the arena cannot be allocated.
+/// 3) Any error returned by `f`.
+pub fn scope<R, F>(f: F) -> Result<R>
+where
+    F: FnOnce(&Arena) -> Result<R>,
+{
+    let mut guard = CommandArenaGuard::acquire()?;
+    let slot = guard.arena();
+    if slot.is_none() {
+        *slot = Some(Arena::new(DEFAULT_CAPACITY)?);
+    }
+    let arena = slot.as_mut().ok_or(ErrorKind::BadState)?;
+
+    let result = f(arena);
+    arena.reset();
+    result
+}
+
+/// Returns the peak usage of the command arena, or `None` before it has been
+/// created.
+pub fn peak() -> Option<usize> {
+    let mut guard = CommandArenaGuard::acquire().ok()?;
+    guard.arena().as_ref().map(Arena::peak)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn test_alloc_and_reset() {
+        let mut arena = Arena::new(64).unwrap();
+        let a = arena.alloc_bytes(10).unwrap();
+        assert_eq!(a, &[0u8; 10]);
+        let b = arena.alloc(0x1122_3344_u32).unwrap();
+        assert_eq!(*b, 0x1122_3344);
+        assert_eq!(b as *mut u32 as usize % core::mem::align_of::<u32>(), 0);
+        assert_eq!(arena.used(), 16);
+
+        assert_eq!(
+            arena.alloc_bytes(64).unwrap_err().kind(),
+            ErrorKind::OutOfMemory
+        );
+
+        arena.reset();
+        assert_eq!(arena.used(), 0);
+        assert_eq!(arena.peak(), 16);
+        assert_eq!(arena.alloc_bytes(64).unwrap().len(), 64);
+        assert_eq!(arena.remaining(), 0);
+    }
+
+    #[test]
+    fn test_alloc_copies() {
+        let arena = Arena::new(64).unwrap();
+        let s = arena.alloc_str("hello").unwrap();
+        let v = arena.alloc_slice_copy(&[1u16, 2, 3]).unwrap();
+        assert_eq!(s, "hello");
+        assert_eq!(v, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_writer() {
+        let arena = Arena::new(16).unwrap();
+        let head = arena.alloc_bytes(4).unwrap();
+        let mut writer = arena.writer().unwrap();
+        assert_eq!(arena.alloc_bytes(1).unwrap_err().kind(), ErrorKind::Busy);
+        assert_eq!(
+            arena.writer().err().map(|e| e.kind()),
+            Some(ErrorKind::Busy)
+        );
+
+        write!(writer, "{}-{}", 12, 34).unwrap();
+        assert_eq!(writer.as_bytes(), b"12-34");
+        assert!(writer.write_bytes(&[0u8; 8]).is_err());
+        let written = writer.finish();
+        assert_eq!(written, b"12-34");
+        assert_eq!(head.len(), 4);
+        assert_eq!(arena.used(), 9);
+
+        // dropping an unfinished writer releases nothing
+        let mut writer = arena.writer().unwrap();
+        writer.write_bytes(b"abc").unwrap();
+        drop(writer);
+        assert_eq!(arena.used(), 9);
+        assert!(arena.alloc_bytes(7).is_ok());
+    }
+
+    #[test]
+    fn test_scope() {
+        init(32).unwrap();
+        let used = scope(|arena| {
+            arena.alloc_bytes(20)?;
+            assert_eq!(scope(|_| Ok(())).unwrap_err().kind(), ErrorKind::Busy);
+            assert_eq!(init(8).unwrap_err().kind(), ErrorKind::Busy);
+            Ok(arena.used())
+        })
+        .unwrap();
+        assert_eq!(used, 20);
+
+        // the arena is reset after each scope
+        scope(|arena| {
+            assert_eq!(arena.used(), 0);
+            arena.alloc_bytes(32).map(|_| ())
+        })
+        .unwrap();
+        assert_eq!(peak(), Some(32));
+    }
+}
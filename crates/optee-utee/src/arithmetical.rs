@@ -15,10 +15,13 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use crate::{Error, Result};
+use crate::{Error, ErrorKind, Result};
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
-use core::{cmp::max, fmt};
+use core::{
+    cmp::{Ordering, max},
+    fmt, ops,
+};
 use optee_utee_sys as raw;
 
 pub type BigIntUnit = u32;
@@ -163,7 +166,7 @@ impl BigInt {
     }
 
     pub fn divide(op1: &Self, op2: &Self) -> (Self, Self) {
-        let q_bits = max(1, Self::get_bit_count(op1) - Self::get_bit_count(op2) + 1);
+        let q_bits = Self::get_bit_count(op1).saturating_sub(Self::get_bit_count(op2)) + 1;
         let r_bits = Self::get_bit_count(op2);
         let mut quotient = Self::new(q_bits);
         let mut remainder = Self::new(r_bits);
@@ -301,12 +304,152 @@ impl BigInt {
     }
 }
 
+// Safe wrappers which check the preconditions the TEE would otherwise panic
+// on, and helpers for values used in cryptographic protocols.
+impl BigInt {
+    /// Create a non-negative `BigInt` from its big-endian representation,
+    /// large enough to hold `bytes`.
+    pub fn from_bytes_be(bytes: &[u8]) -> Result<Self> {
+        let bits = u32::try_from(bytes.len())
+            .ok()
+            .and_then(|len| len.checked_mul(8))
+            .ok_or(ErrorKind::BadParameters)?;
+        let mut res = Self::new(bits);
+        res.convert_from_octet_string(bytes, 0)?;
+        Ok(res)
+    }
+
+    /// Create a `BigInt` holding `value`.
+    pub fn from_i32(value: i32) -> Self {
+        let mut res = Self::new(32);
+        res.convert_from_s32(value);
+        res
+    }
+
+    /// Return the big-endian representation of the absolute value, without
+    /// leading zeros.
+    pub fn to_bytes_be(&self) -> Result<Vec<u8>> {
+        self.convert_to_octet_string()
+    }
+
+    /// Return whether the value is less than zero.
+    pub fn is_negative(&self) -> bool {
+        self.compare_s32(0) < 0
+    }
+
+    /// The number of bits the value can hold.
+    fn capacity_bits(&self) -> u32 {
+        (self.0.len() as u32 - 2) * 32
+    }
+
+    /// Compute `self^exp mod n`.
+    ///
+    /// # Errors
+    ///
+    /// 1) `BadParameters`: If `n` is not an odd number greater than 1, or
+    ///    `exp` is negative.
+    /// 2) `NotSupported`: If the TEE does not support the size of `n`.
+    pub fn mod_exp(&self, exp: &Self, n: &Self) -> Result<Self> {
+        if n.compare_s32(1) <= 0 || !n.get_bit(0) || exp.is_negative() {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        // The base must be in [0, n - 1]
+        let base = Self::module(self, n);
+        let context = BigIntFMMContext::new(n.get_bit_count(), n.clone())?;
+        Self::exp_mod(&base, exp, n, &context)
+    }
+
+    /// Compute the inverse of `self` modulo `n`, the value `x` in [1, n - 1]
+    /// such that `self * x mod n == 1`.
+    ///
+    /// # Errors
+    ///
+    /// `BadParameters`: If `n` is not greater than 1, or `self` has no inverse
+    /// because it is not coprime with `n`.
+    pub fn inverse(&self, n: &Self) -> Result<Self> {
+        if n.compare_s32(1) <= 0 || !Self::relative_prime(self, n) {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        Ok(Self::inv_mod(self, n))
+    }
+}
+
+impl Clone for BigInt {
+    fn clone(&self) -> Self {
+        let mut res = Self::new(self.capacity_bits());
+        // Cannot fail, the copy has the same capacity
+        let _ = res.assign(self);
+        res
+    }
+}
+
+impl PartialEq for BigInt {
+    fn eq(&self, other: &Self) -> bool {
+        self.compare_big_int(other) == 0
+    }
+}
+
+impl Eq for BigInt {}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.compare_big_int(other).cmp(&0)
+    }
+}
+
+impl ops::Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: Self) -> BigInt {
+        BigInt::add(self, rhs)
+    }
+}
+
+impl ops::Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: Self) -> BigInt {
+        BigInt::sub(self, rhs)
+    }
+}
+
+impl ops::Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: Self) -> BigInt {
+        BigInt::multiply(self, rhs)
+    }
+}
+
+impl ops::Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::neg(self)
+    }
+}
+
 impl fmt::Display for BigInt {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:x?}", self.0)
     }
 }
 
+// Unlike Display, does not print the value, which may be secret
+impl fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BigInt")
+            .field("capacity_bits", &self.capacity_bits())
+            .finish_non_exhaustive()
+    }
+}
+
 pub struct BigIntFMMContext(Vec<BigIntFMMContextUnit>);
 
 impl BigIntFMMContext {
@@ -376,3 +519,48 @@ impl BigIntFMM {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use optee_utee_sys::{mock_api, mock_utils::SERIAL_TEST_LOCK};
+
+    use super::*;
+
+    fn big_int() -> BigInt {
+        BigInt(vec![0; 4])
+    }
+
+    #[test]
+    // A value without inverse is rejected instead of panicking in the TEE.
+    fn test_inverse_not_coprime() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let cmp = mock_api::TEE_BigIntCmpS32_context();
+        let relative_prime = mock_api::TEE_BigIntRelativePrime_context();
+        let inv_mod = mock_api::TEE_BigIntInvMod_context();
+
+        cmp.expect().return_const(1);
+        relative_prime.expect().times(1).return_const(false);
+        inv_mod.expect().never();
+
+        let err = big_int().inverse(&big_int()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadParameters);
+    }
+
+    #[test]
+    fn test_mod_exp_even_modulus() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let cmp = mock_api::TEE_BigIntCmpS32_context();
+        let get_bit = mock_api::TEE_BigIntGetBit_context();
+        let exp_mod = mock_api::TEE_BigIntExpMod_context();
+
+        cmp.expect().return_const(1);
+        get_bit.expect().times(1).returning(|_, index| {
+            assert_eq!(index, 0);
+            false
+        });
+        exp_mod.expect().never();
+
+        let err = big_int().mod_exp(&big_int(), &big_int()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadParameters);
+    }
+}
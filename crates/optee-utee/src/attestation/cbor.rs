@@ -0,0 +1,280 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Minimal CBOR encoder for the PSA attestation tokens, and the decoder
+//! reading them back. Only the definite-length items of RFC 8949 are
+//! supported.
+
+use alloc::vec::Vec;
+
+use crate::{ErrorKind, Result};
+
+pub(crate) const MAJOR_UNSIGNED: u8 = 0;
+pub(crate) const MAJOR_NEGATIVE: u8 = 1;
+pub(crate) const MAJOR_BYTES: u8 = 2;
+pub(crate) const MAJOR_TEXT: u8 = 3;
+pub(crate) const MAJOR_ARRAY: u8 = 4;
+pub(crate) const MAJOR_MAP: u8 = 5;
+pub(crate) const MAJOR_TAG: u8 = 6;
+
+/// Encodes the head of an item of type `major` with the argument `value`, in
+/// its shortest form.
+pub(crate) fn head(major: u8, value: u64) -> Vec<u8> {
+    let major = major << 5;
+    match value {
+        0..24 => alloc::vec![major | value as u8],
+        24..0x100 => alloc::vec![major | 24, value as u8],
+        0x100..0x1_0000 => [&[major | 25][..], &(value as u16).to_be_bytes()].concat(),
+        0x1_0000..0x1_0000_0000 => [&[major | 26][..], &(value as u32).to_be_bytes()].concat(),
+        _ => [&[major | 27][..], &value.to_be_bytes()].concat(),
+    }
+}
+
+pub(crate) fn integer(value: i64) -> Vec<u8> {
+    if value < 0 {
+        // -1 - n is encoded as n
+        head(MAJOR_NEGATIVE, !value as u64)
+    } else {
+        head(MAJOR_UNSIGNED, value as u64)
+    }
+}
+
+pub(crate) fn bytes(value: &[u8]) -> Vec<u8> {
+    [&head(MAJOR_BYTES, value.len() as u64)[..], value].concat()
+}
+
+pub(crate) fn text(value: &str) -> Vec<u8> {
+    [&head(MAJOR_TEXT, value.len() as u64)[..], value.as_bytes()].concat()
+}
+
+/// Encodes an array from its encoded elements.
+pub(crate) fn array(elements: &[&[u8]]) -> Vec<u8> {
+    [
+        &head(MAJOR_ARRAY, elements.len() as u64)[..],
+        &elements.concat(),
+    ]
+    .concat()
+}
+
+/// Encodes a map from its integer keys and encoded values, in order.
+pub(crate) fn map(entries: &[(i64, Vec<u8>)]) -> Vec<u8> {
+    let mut out = head(MAJOR_MAP, entries.len() as u64);
+    for (key, value) in entries {
+        out.extend_from_slice(&integer(*key));
+        out.extend_from_slice(value);
+    }
+    out
+}
+
+/// Encodes `item` with the tag `number`.
+pub(crate) fn tag(number: u64, item: &[u8]) -> Vec<u8> {
+    [&head(MAJOR_TAG, number)[..], item].concat()
+}
+
+/// Reads the items of a CBOR encoding one after the other, failing with
+/// `BadFormat` on malformed input.
+pub(crate) struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Self {
+        Self { input }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// Reads the head of the next item, returning its major type and its
+    /// argument.
+    pub(crate) fn read_head(&mut self) -> Result<(u8, u64)> {
+        let (&first, rest) = self.input.split_first().ok_or(ErrorKind::BadFormat)?;
+        let (major, info) = (first >> 5, first & 0x1f);
+        let size = match info {
+            0..24 => 0,
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            // Reserved values and indefinite lengths
+            _ => return Err(ErrorKind::BadFormat.into()),
+        };
+        if size > rest.len() {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        let (argument, rest) = rest.split_at(size);
+        self.input = rest;
+        let value = match size {
+            0 => u64::from(info),
+            _ => argument
+                .iter()
+                .fold(0, |value, b| (value << 8) | u64::from(*b)),
+        };
+        Ok((major, value))
+    }
+
+    /// Reads the head of the next item, which must have the type `major`,
+    /// returning its argument.
+    fn read_expected(&mut self, major: u8) -> Result<u64> {
+        match self.read_head()? {
+            (actual, value) if actual == major => Ok(value),
+            _ => Err(ErrorKind::BadFormat.into()),
+        }
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8]> {
+        let len = usize::try_from(len).map_err(|_| ErrorKind::BadFormat)?;
+        if len > self.input.len() {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        let (content, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(content)
+    }
+
+    pub(crate) fn read_unsigned(&mut self) -> Result<u64> {
+        self.read_expected(MAJOR_UNSIGNED)
+    }
+
+    pub(crate) fn read_integer(&mut self) -> Result<i64> {
+        let (major, value) = self.read_head()?;
+        let value = i64::try_from(value).map_err(|_| ErrorKind::BadFormat)?;
+        match major {
+            MAJOR_UNSIGNED => Ok(value),
+            MAJOR_NEGATIVE => Ok(-1 - value),
+            _ => Err(ErrorKind::BadFormat.into()),
+        }
+    }
+
+    pub(crate) fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_expected(MAJOR_BYTES)?;
+        self.take(len)
+    }
+
+    pub(crate) fn read_text(&mut self) -> Result<&'a str> {
+        let len = self.read_expected(MAJOR_TEXT)?;
+        core::str::from_utf8(self.take(len)?).map_err(|_| ErrorKind::BadFormat.into())
+    }
+
+    /// Reads the head of an array, returning its number of elements.
+    pub(crate) fn read_array(&mut self) -> Result<u64> {
+        self.read_expected(MAJOR_ARRAY)
+    }
+
+    /// Reads the head of a map, returning its number of entries.
+    pub(crate) fn read_map(&mut self) -> Result<u64> {
+        self.read_expected(MAJOR_MAP)
+    }
+
+    /// Reads the tag of the next item, returning its number.
+    pub(crate) fn read_tag(&mut self) -> Result<u64> {
+        self.read_expected(MAJOR_TAG)
+    }
+
+    /// Skips the next item and the items nested in it, e.g. the value of an
+    /// unknown claim.
+    pub(crate) fn skip(&mut self) -> Result<()> {
+        // Counting the pending items rather than recursing, so deeply nested
+        // input cannot exhaust the stack
+        let mut pending: u64 = 1;
+        while pending > 0 {
+            pending -= 1;
+            let nested = match self.read_head()? {
+                (MAJOR_BYTES | MAJOR_TEXT, len) => {
+                    self.take(len)?;
+                    0
+                }
+                (MAJOR_ARRAY, len) => len,
+                (MAJOR_MAP, len) => len.checked_mul(2).ok_or(ErrorKind::BadFormat)?,
+                (MAJOR_TAG, _) => 1,
+                _ => 0,
+            };
+            pending = pending.checked_add(nested).ok_or(ErrorKind::BadFormat)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heads() {
+        assert_eq!(integer(0), [0x00]);
+        assert_eq!(integer(23), [0x17]);
+        assert_eq!(integer(24), [0x18, 0x18]);
+        assert_eq!(integer(2399), [0x19, 0x09, 0x5f]);
+        assert_eq!(integer(0x1_0000), [0x1a, 0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(integer(-1), [0x20]);
+        assert_eq!(integer(-7), [0x26]);
+        assert_eq!(integer(-75008), [0x3a, 0x00, 0x01, 0x24, 0xff]);
+        assert_eq!(
+            head(MAJOR_UNSIGNED, u64::MAX),
+            [0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn test_items() {
+        assert_eq!(bytes(&[1, 2]), [0x42, 1, 2]);
+        assert_eq!(text("a"), [0x61, b'a']);
+        assert_eq!(array(&[&integer(1), &integer(2)]), [0x82, 0x01, 0x02]);
+        assert_eq!(map(&[(1, integer(-7))]), [0xa1, 0x01, 0x26]);
+        assert_eq!(tag(18, &array(&[])), [0xd2, 0x80]);
+    }
+
+    #[test]
+    fn test_reader() {
+        let encoded = tag(
+            18,
+            &array(&[
+                &bytes(&[0xaa; 300]),
+                &map(&[(10, text("nonce")), (-1, array(&[&integer(3)]))]),
+                &integer(-75008),
+            ]),
+        );
+        let mut reader = Reader::new(&encoded);
+        assert_eq!(reader.read_tag().unwrap(), 18);
+        assert_eq!(reader.read_array().unwrap(), 3);
+        assert_eq!(reader.read_bytes().unwrap(), [0xaa; 300]);
+        assert_eq!(reader.read_map().unwrap(), 2);
+        assert_eq!(reader.read_integer().unwrap(), 10);
+        assert_eq!(reader.read_text().unwrap(), "nonce");
+        assert_eq!(reader.read_integer().unwrap(), -1);
+        reader.skip().unwrap();
+        assert!(reader.read_unsigned().is_err());
+        assert!(reader.is_empty());
+
+        let mut reader = Reader::new(&encoded);
+        reader.skip().unwrap();
+        assert!(reader.is_empty());
+
+        // Truncated, indefinite length, invalid UTF-8, wrong type
+        assert!(Reader::new(&encoded[..encoded.len() - 1]).skip().is_err());
+        assert!(Reader::new(&[0x5f, 0x41, 0x00, 0xff]).read_bytes().is_err());
+        assert!(Reader::new(&[0x61, 0xff]).read_text().is_err());
+        assert!(Reader::new(&[0x41, 0x00]).read_text().is_err());
+        // Nested deeper than any stack, and more items than the input holds
+        assert!(Reader::new(&[0x81; 100_000]).skip().is_err());
+        assert!(
+            Reader::new(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])
+                .skip()
+                .is_err()
+        );
+    }
+}
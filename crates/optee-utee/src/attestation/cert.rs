@@ -0,0 +1,311 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{Evidence, Report, der};
+use crate::{
+    AlgorithmId, Asymmetric, AttributeId, AttributeValue, Digest, ElementId, GenericObject,
+    OperationMode, Random, Result, TransientObject, TransientObjectType,
+};
+
+/// Size of the NIST P-256 curve in bits.
+const KEY_SIZE: usize = 256;
+/// Size of a coordinate or scalar of the curve in bytes.
+const COORDINATE_SIZE: usize = KEY_SIZE / 8;
+
+const OID_EC_PUBLIC_KEY: &[u32] = &[1, 2, 840, 10045, 2, 1];
+const OID_PRIME256V1: &[u32] = &[1, 2, 840, 10045, 3, 1, 7];
+const OID_ECDSA_WITH_SHA256: &[u32] = &[1, 2, 840, 10045, 4, 3, 2];
+const OID_COMMON_NAME: &[u32] = &[2, 5, 4, 3];
+
+/// Latest time representable in a certificate, 9999-12-31T23:59:59Z, which
+/// RFC 5280 uses for certificates without a well-defined expiration date.
+const NO_EXPIRATION: u64 = 253_402_300_799;
+
+/// ECDSA NIST P-256 key pair, signing certificates with ECDSA-SHA256.
+pub struct SigningKey {
+    object: TransientObject,
+}
+
+impl SigningKey {
+    /// Generate a new key pair.
+    pub fn generate() -> Result<Self> {
+        let object = TransientObject::allocate(TransientObjectType::EcdsaKeypair, KEY_SIZE)?;
+        let curve = AttributeValue::from_value(
+            AttributeId::EccCurve,
+            ElementId::EccCurveNistP256 as u32,
+            0,
+        );
+        object.generate_key(KEY_SIZE, &[curve.into()])?;
+        Ok(Self { object })
+    }
+
+    /// Use the key pair `object`, for example one loaded from the secure
+    /// storage, which must be an ECDSA NIST P-256 key pair.
+    pub fn from_object(object: TransientObject) -> Self {
+        Self { object }
+    }
+
+    /// The DER encoded `SubjectPublicKeyInfo` of the key.
+    pub fn public_key_der(&self) -> Result<Vec<u8>> {
+        let mut point = Vec::with_capacity(1 + 2 * COORDINATE_SIZE);
+        // Uncompressed point
+        point.push(0x04);
+        for id in [AttributeId::EccPublicValueX, AttributeId::EccPublicValueY] {
+            let mut coordinate = [0u8; COORDINATE_SIZE];
+            let size = self.object.ref_attribute(id, &mut coordinate)?;
+            // Coordinates are returned without their leading zeros
+            point.resize(point.len() + COORDINATE_SIZE - size, 0);
+            point.extend_from_slice(&coordinate[..size]);
+        }
+        Ok(der::sequence(&[
+            &der::sequence(&[&der::oid(OID_EC_PUBLIC_KEY), &der::oid(OID_PRIME256V1)]),
+            &der::bit_string(&point),
+        ]))
+    }
+
+    /// Sign `data` with ECDSA-SHA256, returning the DER encoded
+    /// `Ecdsa-Sig-Value`.
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let digest = sha256(data)?;
+        let mut op = Asymmetric::allocate(AlgorithmId::EcDsaSha256, OperationMode::Sign, KEY_SIZE)?;
+        op.set_key(&self.object)?;
+        let mut signature = [0u8; 2 * COORDINATE_SIZE];
+        let size = op.sign_digest(&[], &digest, &mut signature)?;
+        let (r, s) = signature[..size].split_at(size / 2);
+        Ok(der::sequence(&[
+            &der::unsigned_integer(r),
+            &der::unsigned_integer(s),
+        ]))
+    }
+}
+
+/// Builds an X.509 v3 certificate for a [`SigningKey`], optionally carrying
+/// an attestation [`Report`].
+///
+/// The subject and issuer names only have a common name. By default the
+/// serial number is random and the certificate is valid from the Unix epoch
+/// without expiration, as a TA rarely has a trusted clock.
+pub struct CertificateBuilder<'a> {
+    key: &'a SigningKey,
+    subject: String,
+    serial: Option<Vec<u8>>,
+    not_before: u64,
+    not_after: u64,
+    extensions: Vec<Vec<u8>>,
+    report: Option<(Vec<u32>, ReportGenerator<'a>)>,
+    challenge: Vec<u8>,
+}
+
+/// Produces the encoded evidence for a nonce.
+pub(super) type ReportGenerator<'a> = Box<dyn FnOnce(&[u8]) -> Result<Vec<u8>> + 'a>;
+
+impl<'a> CertificateBuilder<'a> {
+    /// Create a builder for a certificate of `key`, with the common name
+    /// `subject`.
+    pub fn new(key: &'a SigningKey, subject: &str) -> Self {
+        Self {
+            key,
+            subject: subject.into(),
+            serial: None,
+            not_before: 0,
+            not_after: NO_EXPIRATION,
+            extensions: Vec::new(),
+            report: None,
+            challenge: Vec::new(),
+        }
+    }
+
+    /// Set the big-endian serial number, at most 20 bytes.
+    pub fn serial(mut self, serial: &[u8]) -> Self {
+        self.serial = Some(serial.to_vec());
+        self
+    }
+
+    /// Make the certificate valid between the times `not_before` and
+    /// `not_after`, in seconds since the Unix epoch.
+    pub fn validity(mut self, not_before: u64, not_after: u64) -> Self {
+        self.not_before = not_before;
+        self.not_after = not_after;
+        self
+    }
+
+    /// Add the extension `oid` with the DER encoded `value`.
+    pub fn extension(mut self, oid: &[u32], critical: bool, value: &[u8]) -> Self {
+        self.extensions.push(extension(oid, critical, value));
+        self
+    }
+
+    /// Add a [`Report`] in the non-critical extension `oid`, whose nonce is
+    /// the SHA-256 digest of the `SubjectPublicKeyInfo` of the certificate.
+    ///
+    /// The report is generated when the certificate is signed.
+    pub fn attestation_report(self, oid: &[u32]) -> Self {
+        self.attestation_report_with(oid, Report::generate)
+    }
+
+    /// Like [`attestation_report`](Self::attestation_report), with the
+    /// evidence returned by `generate` for the nonce instead of a report of
+    /// [`Report::generate`].
+    pub fn attestation_report_with<E: Evidence>(
+        self,
+        oid: &[u32],
+        generate: impl FnOnce(&[u8]) -> Result<E> + 'a,
+    ) -> Self {
+        self.report_generator(oid, Box::new(move |nonce| Ok(generate(nonce)?.encode())))
+    }
+
+    /// Bind the report to the nonce of the verifier's [`Challenge`]: the nonce
+    /// of the report is the SHA-256 digest of the `SubjectPublicKeyInfo`
+    /// followed by `challenge`.
+    ///
+    /// [`Challenge`]: super::Challenge
+    pub fn challenge(mut self, challenge: &[u8]) -> Self {
+        self.challenge = challenge.to_vec();
+        self
+    }
+
+    pub(super) fn report_generator(mut self, oid: &[u32], generate: ReportGenerator<'a>) -> Self {
+        self.report = Some((oid.to_vec(), generate));
+        self
+    }
+
+    /// Sign the certificate with its own key, returning its DER encoding.
+    pub fn self_signed(self) -> Result<Vec<u8>> {
+        let issuer = self.subject.clone();
+        let key = self.key;
+        self.sign(key, &issuer)
+    }
+
+    /// Sign the certificate with `issuer_key`, the key of the certificate
+    /// with the common name `issuer`, returning its DER encoding.
+    ///
+    /// # Errors
+    ///
+    /// If generating the report or signing fails.
+    pub fn sign(mut self, issuer_key: &SigningKey, issuer: &str) -> Result<Vec<u8>> {
+        let public_key = self.key.public_key_der()?;
+        if let Some((oid, generate)) = self.report.take() {
+            let report = generate(&bound_nonce(&public_key, &self.challenge)?)?;
+            self.extensions.push(extension(&oid, false, &report));
+        }
+        let serial = match self.serial.take() {
+            Some(serial) => serial,
+            None => {
+                let mut serial = alloc::vec![0u8; 16];
+                Random::generate(&mut serial);
+                serial
+            }
+        };
+        let algorithm = der::sequence(&[&der::oid(OID_ECDSA_WITH_SHA256)]);
+        let tbs = self.tbs(&serial, &algorithm, issuer, &public_key);
+        let signature = issuer_key.sign(&tbs)?;
+        Ok(der::sequence(&[
+            &tbs,
+            &algorithm,
+            &der::bit_string(&signature),
+        ]))
+    }
+
+    /// Encode the `TBSCertificate`.
+    fn tbs(&self, serial: &[u8], algorithm: &[u8], issuer: &str, public_key: &[u8]) -> Vec<u8> {
+        let mut fields = alloc::vec![
+            // Version v3
+            der::explicit(0, &der::small_integer(2)),
+            der::unsigned_integer(serial),
+            algorithm.to_vec(),
+            name(issuer),
+            der::sequence(&[&der::time(self.not_before), &der::time(self.not_after)]),
+            name(&self.subject),
+            public_key.to_vec(),
+        ];
+        if !self.extensions.is_empty() {
+            let extensions: Vec<&[u8]> = self.extensions.iter().map(Vec::as_slice).collect();
+            fields.push(der::explicit(3, &der::sequence(&extensions)));
+        }
+        let fields: Vec<&[u8]> = fields.iter().map(Vec::as_slice).collect();
+        der::sequence(&fields)
+    }
+}
+
+/// Encode a `Name` with the common name `cn` only.
+fn name(cn: &str) -> Vec<u8> {
+    let attribute = der::sequence(&[&der::oid(OID_COMMON_NAME), &der::utf8_string(cn)]);
+    der::sequence(&[&der::constructed(der::TAG_SET, &[&attribute])])
+}
+
+fn extension(oid: &[u32], critical: bool, value: &[u8]) -> Vec<u8> {
+    let oid = der::oid(oid);
+    let value = der::octet_string(value);
+    if critical {
+        der::sequence(&[&oid, &der::boolean(true), &value])
+    } else {
+        // DEFAULT FALSE is omitted in DER
+        der::sequence(&[&oid, &value])
+    }
+}
+
+/// The nonce of the report of the key `public_key` for `challenge`.
+pub(super) fn bound_nonce(public_key: &[u8], challenge: &[u8]) -> Result<[u8; 32]> {
+    let op = Digest::allocate(AlgorithmId::Sha256)?;
+    op.update(public_key);
+    let mut hash = [0u8; 32];
+    op.do_final(challenge, &mut hash)?;
+    Ok(hash)
+}
+
+fn sha256(data: &[u8]) -> Result<[u8; 32]> {
+    let op = Digest::allocate(AlgorithmId::Sha256)?;
+    let mut hash = [0u8; 32];
+    op.do_final(data, &mut hash)?;
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn test_name() {
+        assert_eq!(
+            name("ta"),
+            [
+                0x30, 0x0d, 0x31, 0x0b, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x02, b't',
+                b'a'
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extension() {
+        let oid = [1, 2, 3];
+        assert_eq!(
+            extension(&oid, false, &[0x05, 0x00]),
+            [0x30, 0x08, 0x06, 0x02, 0x2a, 0x03, 0x04, 0x02, 0x05, 0x00]
+        );
+        assert_eq!(
+            extension(&oid, true, &[0x05, 0x00]),
+            [
+                0x30, 0x0b, 0x06, 0x02, 0x2a, 0x03, 0x01, 0x01, 0xff, 0x04, 0x02, 0x05, 0x00
+            ]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Minimal DER encoder for the certificates and reports, and the decoder
+//! reading them back.
+
+use alloc::vec::Vec;
+
+use crate::{Error, ErrorKind, Result};
+
+pub(crate) const TAG_BOOLEAN: u8 = 0x01;
+pub(crate) const TAG_INTEGER: u8 = 0x02;
+pub(crate) const TAG_BIT_STRING: u8 = 0x03;
+pub(crate) const TAG_OCTET_STRING: u8 = 0x04;
+pub(crate) const TAG_OID: u8 = 0x06;
+pub(crate) const TAG_UTF8_STRING: u8 = 0x0c;
+pub(crate) const TAG_UTC_TIME: u8 = 0x17;
+pub(crate) const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub(crate) const TAG_SEQUENCE: u8 = 0x30;
+pub(crate) const TAG_SET: u8 = 0x31;
+
+/// Encodes a value with `tag` and `content`.
+pub(crate) fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len() + 6);
+    out.push(tag);
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Encodes a constructed value with `tag` from its encoded elements.
+pub(crate) fn constructed(tag: u8, elements: &[&[u8]]) -> Vec<u8> {
+    tlv(tag, &elements.concat())
+}
+
+pub(crate) fn sequence(elements: &[&[u8]]) -> Vec<u8> {
+    constructed(TAG_SEQUENCE, elements)
+}
+
+/// Encodes the context-specific explicit tag `[number]`.
+pub(crate) fn explicit(number: u8, element: &[u8]) -> Vec<u8> {
+    tlv(0xa0 | number, element)
+}
+
+/// Encodes the unsigned big-endian integer `bytes`.
+pub(crate) fn unsigned_integer(bytes: &[u8]) -> Vec<u8> {
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    let bytes = &bytes[skip..];
+    let mut content = Vec::with_capacity(bytes.len() + 1);
+    // A leading 1 bit would make the integer negative
+    if bytes.first().is_none_or(|b| b & 0x80 != 0) {
+        content.push(0);
+    }
+    content.extend_from_slice(bytes);
+    tlv(TAG_INTEGER, &content)
+}
+
+pub(crate) fn small_integer(value: u64) -> Vec<u8> {
+    unsigned_integer(&value.to_be_bytes())
+}
+
+pub(crate) fn boolean(value: bool) -> Vec<u8> {
+    tlv(TAG_BOOLEAN, &[if value { 0xff } else { 0 }])
+}
+
+pub(crate) fn octet_string(bytes: &[u8]) -> Vec<u8> {
+    tlv(TAG_OCTET_STRING, bytes)
+}
+
+/// Encodes `bytes` as a bit string without unused bits.
+pub(crate) fn bit_string(bytes: &[u8]) -> Vec<u8> {
+    tlv(TAG_BIT_STRING, &[&[0u8][..], bytes].concat())
+}
+
+pub(crate) fn utf8_string(value: &str) -> Vec<u8> {
+    tlv(TAG_UTF8_STRING, value.as_bytes())
+}
+
+/// Encodes the object identifier with the components `arcs`, which has at
+/// least two.
+pub(crate) fn oid(arcs: &[u32]) -> Vec<u8> {
+    let mut content = Vec::new();
+    let (first, rest) = match arcs {
+        [a, b, rest @ ..] => (a * 40 + b, rest),
+        _ => panic!("an object identifier has at least two components"),
+    };
+    for arc in core::iter::once(first).chain(rest.iter().copied()) {
+        let groups = (0..5).rev().map(|i| (arc >> (7 * i)) as u8 & 0x7f);
+        let mut started = false;
+        for (i, group) in groups.enumerate() {
+            started |= group != 0 || i == 4;
+            if started {
+                content.push(if i == 4 { group } else { group | 0x80 });
+            }
+        }
+    }
+    tlv(TAG_OID, &content)
+}
+
+/// Encodes the time `seconds` since the Unix epoch as a UTCTime until 2049,
+/// and a GeneralizedTime from 2050, as required by RFC 5280.
+pub(crate) fn time(seconds: u64) -> Vec<u8> {
+    let days = seconds / 86400;
+    let second_of_day = seconds % 86400;
+    let (year, month, day) = civil_from_days(days);
+    let mut text = alloc::format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60
+    );
+    if (1950..2050).contains(&year) {
+        text.drain(..2);
+        tlv(TAG_UTC_TIME, text.as_bytes())
+    } else {
+        tlv(TAG_GENERALIZED_TIME, text.as_bytes())
+    }
+}
+
+/// The date of the day `days` since 1970-01-01 in the proleptic Gregorian
+/// calendar.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's algorithm, with eras starting on March 1st
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Decodes the content of a `UTCTime` or `GeneralizedTime` with `tag`, as
+/// encoded by [`time`], into seconds since the Unix epoch.
+pub(crate) fn parse_time(tag: u8, content: &[u8]) -> Result<u64> {
+    let digits = match (tag, content.split_last()) {
+        (TAG_UTC_TIME, Some((b'Z', digits))) if digits.len() == 12 => digits,
+        (TAG_GENERALIZED_TIME, Some((b'Z', digits))) if digits.len() == 14 => digits,
+        _ => return Err(ErrorKind::BadFormat.into()),
+    };
+    let number = |digits: &[u8]| {
+        digits.iter().try_fold(0u64, |value, digit| match digit {
+            b'0'..=b'9' => Ok(value * 10 + u64::from(digit - b'0')),
+            _ => Err(Error::from(ErrorKind::BadFormat)),
+        })
+    };
+    let (year, rest) = digits.split_at(digits.len() - 10);
+    let year = match number(year)? {
+        // Years 1950 to 2049 are encoded as UTCTime
+        year if tag == TAG_UTC_TIME && year < 50 => year + 2000,
+        year if tag == TAG_UTC_TIME => year + 1900,
+        year => year,
+    };
+    let mut fields = [0u64; 5];
+    for (field, pair) in fields.iter_mut().zip(rest.chunks(2)) {
+        *field = number(pair)?;
+    }
+    let [month, day, hour, minute, second] = fields;
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+        || year < 1970
+    {
+        return Err(ErrorKind::BadFormat.into());
+    }
+    Ok(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// The number of days since 1970-01-01 of a date in the proleptic Gregorian
+/// calendar, the inverse of [`civil_from_days`].
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Reads the values of a DER encoding one after the other, failing with
+/// `BadFormat` on malformed input.
+pub(crate) struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Self {
+        Self { input }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// Reads the next value, returning its tag, its content and its whole
+    /// encoding.
+    pub(crate) fn read_any(&mut self) -> Result<(u8, &'a [u8], &'a [u8])> {
+        let (&tag, rest) = self.input.split_first().ok_or(ErrorKind::BadFormat)?;
+        let (&first, rest) = rest.split_first().ok_or(ErrorKind::BadFormat)?;
+        let (len, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            // Indefinite lengths are not DER
+            let size = (first & 0x7f) as usize;
+            if size == 0 || size > core::mem::size_of::<usize>() || size > rest.len() {
+                return Err(ErrorKind::BadFormat.into());
+            }
+            let (bytes, rest) = rest.split_at(size);
+            let len = bytes.iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+            (len, rest)
+        };
+        if len > rest.len() {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        let header = self.input.len() - rest.len();
+        let (encoding, remaining) = self.input.split_at(header + len);
+        self.input = remaining;
+        Ok((tag, &encoding[header..], encoding))
+    }
+
+    /// Reads the next value, which must have `tag`, returning its content.
+    pub(crate) fn read(&mut self, tag: u8) -> Result<&'a [u8]> {
+        match self.read_any()? {
+            (actual, content, _) if actual == tag => Ok(content),
+            _ => Err(ErrorKind::BadFormat.into()),
+        }
+    }
+
+    /// Reads the next value if it has `tag`, returning its content.
+    pub(crate) fn read_optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>> {
+        if self.input.first() == Some(&tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn test_lengths() {
+        assert_eq!(octet_string(&[1, 2]), [0x04, 0x02, 1, 2]);
+        let long = octet_string(&[0; 200]);
+        assert_eq!(&long[..3], [0x04, 0x81, 200]);
+        let longer = octet_string(&[0; 300]);
+        assert_eq!(&longer[..4], [0x04, 0x82, 0x01, 0x2c]);
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(small_integer(0), [0x02, 0x01, 0x00]);
+        assert_eq!(small_integer(2), [0x02, 0x01, 0x02]);
+        assert_eq!(small_integer(0x80), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(unsigned_integer(&[0, 0, 0x7f, 1]), [0x02, 0x02, 0x7f, 1]);
+    }
+
+    #[test]
+    fn test_oid() {
+        // id-ecPublicKey
+        assert_eq!(
+            oid(&[1, 2, 840, 10045, 2, 1]),
+            [0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01]
+        );
+        // commonName
+        assert_eq!(oid(&[2, 5, 4, 3]), [0x06, 0x03, 0x55, 0x04, 0x03]);
+    }
+
+    #[test]
+    fn test_reader() {
+        let encoded = sequence(&[&small_integer(2), &octet_string(&[0xaa; 300])]);
+        let mut reader = Reader::new(&encoded);
+        let (tag, content, whole) = reader.read_any().unwrap();
+        assert_eq!(tag, TAG_SEQUENCE);
+        assert_eq!(whole, encoded);
+        assert!(reader.is_empty());
+
+        let mut fields = Reader::new(content);
+        assert_eq!(fields.read_optional(TAG_OCTET_STRING).unwrap(), None);
+        assert_eq!(fields.read(TAG_INTEGER).unwrap(), [2]);
+        assert!(fields.read(TAG_INTEGER).is_err());
+
+        let mut fields = Reader::new(content);
+        fields.read(TAG_INTEGER).unwrap();
+        assert_eq!(fields.read(TAG_OCTET_STRING).unwrap(), [0xaa; 300]);
+        assert!(fields.is_empty());
+
+        // Truncated, indefinite length, missing length
+        assert!(
+            Reader::new(&encoded[..encoded.len() - 1])
+                .read_any()
+                .is_err()
+        );
+        assert!(Reader::new(&[0x30, 0x80, 0x00, 0x00]).read_any().is_err());
+        assert!(Reader::new(&[0x04]).read_any().is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        assert_eq!(&time(0)[2..], b"700101000000Z");
+        assert_eq!(time(0)[0], TAG_UTC_TIME);
+        // 2024-02-29T12:34:56Z
+        assert_eq!(&time(1_709_210_096)[2..], b"240229123456Z");
+        // 2050-01-01T00:00:00Z
+        let generalized = time(2_524_608_000);
+        assert_eq!(generalized[0], TAG_GENERALIZED_TIME);
+        assert_eq!(&generalized[2..], b"20500101000000Z");
+        assert_eq!(&time(253_402_300_799)[2..], b"99991231235959Z");
+    }
+
+    #[test]
+    fn test_parse_time() {
+        for seconds in [0, 1_709_210_096, 2_524_608_000, 253_402_300_799] {
+            let encoded = time(seconds);
+            let (tag, content, _) = Reader::new(&encoded).read_any().unwrap();
+            assert_eq!(parse_time(tag, content).unwrap(), seconds);
+        }
+        for (tag, content) in [
+            (TAG_UTC_TIME, &b"240229123456"[..]),
+            (TAG_UTC_TIME, b"241329123456Z"),
+            (TAG_UTC_TIME, b"2402291234+6Z"),
+            (TAG_GENERALIZED_TIME, b"240229123456Z"),
+            (TAG_INTEGER, b"240229123456Z"),
+        ] {
+            assert!(parse_time(tag, content).is_err());
+        }
+    }
+}
@@ -0,0 +1,434 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Remote attestation reports and X.509 certificates carrying them.
+//!
+//! A [`Report`] is produced by the attestation pseudo TA of OP-TEE: a
+//! SHA-256 measurement of the memory of the calling TA, signed together with
+//! a caller chosen nonce by the attestation key of the device. A verifier
+//! holding the public key of the device, see [`AttestationKey`], checks the
+//! signature and compares the measurement with the one of the TA it expects.
+//!
+//! To bind a key of the TA to the report, [`CertificateBuilder`] creates a
+//! certificate for the key with the report in an extension, whose nonce is
+//! the SHA-256 digest of the certificate's `SubjectPublicKeyInfo`:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::attestation::{CertificateBuilder, SigningKey};
+//! # fn main() -> optee_utee::Result<()> {
+//! // The OID of the extension is chosen by the application
+//! const REPORT_OID: &[u32] = &[1, 3, 6, 1, 4, 1, 99999, 1];
+//!
+//! let key = SigningKey::generate()?;
+//! let cert = CertificateBuilder::new(&key, "my-ta")
+//!     .attestation_report(REPORT_OID)
+//!     .self_signed()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A certificate signed by a CA key provisioned to the TA is created with
+//! [`CertificateBuilder::sign`] instead.
+//!
+//! Such a report can be replayed by anyone who obtained the certificate and
+//! its key. A verifier requiring freshness issues a [`Challenge`], whose
+//! nonce the attested TA passes to [`CertificateBuilder::challenge`]: the
+//! nonce of the report becomes the digest of the `SubjectPublicKeyInfo`
+//! followed by the challenge, and [`Challenge::verify`] accepts it once,
+//! within the time to live of the challenge. With the `ra_tls` feature,
+//! [`RaTlsServerConfigBuilder`] and [`RaTlsClientConfigBuilder`] build the
+//! rustls configurations of TLS endpoints authenticated by such certificates.
+//!
+//! Peers which are not running OP-TEE attest with other formats of
+//! [`Evidence`], such as the [`PsaToken`] of Arm PSA platforms.
+//!
+//! Certificates of compromised devices or keys are rejected by the verifiers
+//! given a [`RevocationSource`], such as a [`RevocationList`] built from
+//! CRLs.
+//!
+//! The attestation pseudo TA is only
+//! available when OP-TEE is built with `CFG_ATTESTATION_PTA=y`, [`Report::generate`]
+//! fails with [`ItemNotFound`](crate::ErrorKind::ItemNotFound) otherwise.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use crate::{
+    Error, ErrorKind, ParamIndex, Random, Result, SystemTime, TaSessionBuilder, TeeParams, Uuid,
+};
+
+mod cbor;
+mod cert;
+mod der;
+mod psa;
+#[cfg(feature = "ra_tls")]
+mod ra_tls;
+mod revocation;
+
+pub use cert::{CertificateBuilder, SigningKey};
+pub use psa::{PsaClaims, PsaToken, SoftwareComponent};
+#[cfg(feature = "ra_tls")]
+pub use ra_tls::{RaTlsClientConfigBuilder, RaTlsServerCertVerifier, RaTlsServerConfigBuilder};
+pub use revocation::{CertificateId, RevocationList, RevocationSource, RevocationStatus};
+
+/// UUID of the attestation pseudo TA.
+const PTA_ATTESTATION_UUID: &str = "39800861-182a-4720-9b67-2bcd622bc0b5";
+const PTA_ATTESTATION_GET_PUBKEY: u32 = 0;
+const PTA_ATTESTATION_HASH_TA_MEMORY: u32 = 2;
+
+/// Size of a SHA-256 digest.
+const MEASUREMENT_SIZE: usize = 32;
+/// Large enough for the signature of a 4096 bits RSA key.
+const MAX_SIGNATURE_SIZE: usize = 512;
+
+/// Size of the nonce of a [`Challenge`].
+const CHALLENGE_SIZE: usize = 32;
+
+/// Version of the DER encoding of [`Report`].
+const REPORT_VERSION: u64 = 1;
+
+/// Measurement of the calling TA, signed by the attestation key of the device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    /// The nonce the report was generated for.
+    pub nonce: Vec<u8>,
+    /// SHA-256 digest of the memory of the TA.
+    pub measurement: [u8; MEASUREMENT_SIZE],
+    /// Signature of `nonce || measurement` with the attestation key, see
+    /// [`AttestationKey::algorithm`] for its scheme.
+    pub signature: Vec<u8>,
+}
+
+impl Report {
+    /// Measure the calling TA with the attestation pseudo TA, and have the
+    /// measurement signed together with `nonce`.
+    ///
+    /// # Errors
+    ///
+    /// If the attestation pseudo TA is not available or fails.
+    pub fn generate(nonce: &[u8]) -> Result<Self> {
+        let mut session = open_session()?;
+        let mut output = [0u8; MEASUREMENT_SIZE + MAX_SIGNATURE_SIZE];
+        let mut params = TeeParams::new()
+            .with_memref_in(ParamIndex::Arg0, nonce)
+            .with_memref_out(ParamIndex::Arg1, &mut output);
+        session.invoke_command(PTA_ATTESTATION_HASH_TA_MEMORY, &mut params)?;
+        let written = params[ParamIndex::Arg1]
+            .written_slice()
+            .ok_or(ErrorKind::BadFormat)?;
+        Self::from_output(nonce, written)
+    }
+
+    /// Split the output of the pseudo TA into the measurement and signature.
+    fn from_output(nonce: &[u8], output: &[u8]) -> Result<Self> {
+        if output.len() <= MEASUREMENT_SIZE {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        let (measurement, signature) = output.split_at(MEASUREMENT_SIZE);
+        Ok(Self {
+            nonce: nonce.to_vec(),
+            measurement: measurement.try_into().unwrap(),
+            signature: signature.to_vec(),
+        })
+    }
+
+    /// Encode the report as the DER of
+    ///
+    /// ```text
+    /// Report ::= SEQUENCE {
+    ///     version      INTEGER,  -- 1
+    ///     nonce        OCTET STRING,
+    ///     measurement  OCTET STRING,
+    ///     signature    OCTET STRING }
+    /// ```
+    pub fn to_der(&self) -> Vec<u8> {
+        der::sequence(&[
+            &der::small_integer(REPORT_VERSION),
+            &der::octet_string(&self.nonce),
+            &der::octet_string(&self.measurement),
+            &der::octet_string(&self.signature),
+        ])
+    }
+
+    /// Decode a report encoded by [`to_der`](Self::to_der).
+    ///
+    /// # Errors
+    ///
+    /// `BadFormat` if `der` is not a report of a supported version.
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        let mut outer = der::Reader::new(der);
+        let mut fields = der::Reader::new(outer.read(der::TAG_SEQUENCE)?);
+        if !outer.is_empty() || fields.read(der::TAG_INTEGER)? != [REPORT_VERSION as u8] {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        let nonce = fields.read(der::TAG_OCTET_STRING)?;
+        let measurement = fields.read(der::TAG_OCTET_STRING)?;
+        let signature = fields.read(der::TAG_OCTET_STRING)?;
+        if !fields.is_empty() {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        Ok(Self {
+            nonce: nonce.to_vec(),
+            measurement: measurement.try_into().map_err(|_| ErrorKind::BadFormat)?,
+            signature: signature.to_vec(),
+        })
+    }
+}
+
+/// A format of attestation evidence carried by certificates, bound to the key
+/// of the certificate by its nonce, the SHA-256 digest of the
+/// `SubjectPublicKeyInfo` followed by the nonce of the [`Challenge`], if any.
+///
+/// [`Report`] is the evidence produced by OP-TEE, and [`PsaToken`] the one of
+/// the platforms implementing the Arm Platform Security Architecture.
+/// Implementing this trait for the evidence of other TEEs lets
+/// [`CertificateBuilder`] and, with the `ra_tls` feature, the RA-TLS
+/// configurations embed and accept it.
+pub trait Evidence: Sized {
+    /// Encode the evidence as the value of the certificate extension.
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decode evidence encoded by [`encode`](Self::encode).
+    fn decode(bytes: &[u8]) -> Result<Self>;
+
+    /// The nonce the evidence was generated for.
+    fn nonce(&self) -> &[u8];
+}
+
+impl Evidence for Report {
+    fn encode(&self) -> Vec<u8> {
+        self.to_der()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        Self::from_der(bytes)
+    }
+
+    fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+}
+
+/// A nonce issued by a verifier to check that evidence is fresh.
+///
+/// The evidence must be generated for the nonce of the challenge, see
+/// [`CertificateBuilder::challenge`], and is accepted once within `ttl` of the
+/// creation of the challenge, measured with the [`SystemTime`] of the TA
+/// instance verifying it. A verifier with several pending challenges looks
+/// them up by nonce.
+#[derive(Debug)]
+pub struct Challenge {
+    nonce: [u8; CHALLENGE_SIZE],
+    issued: SystemTime,
+    ttl: Duration,
+    answered: AtomicBool,
+    clock: fn() -> SystemTime,
+}
+
+impl Challenge {
+    /// Create a challenge with a random nonce, expiring after `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        let mut nonce = [0u8; CHALLENGE_SIZE];
+        Random::generate(&mut nonce);
+        Self::with_clock(nonce, ttl, SystemTime::now)
+    }
+
+    fn with_clock(nonce: [u8; CHALLENGE_SIZE], ttl: Duration, clock: fn() -> SystemTime) -> Self {
+        Self {
+            nonce,
+            issued: clock(),
+            ttl,
+            answered: AtomicBool::new(false),
+            clock,
+        }
+    }
+
+    /// The nonce to send to the attested TA.
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    /// Whether the time to live of the challenge has elapsed.
+    pub fn is_expired(&self) -> bool {
+        (self.clock)() - self.issued > self.ttl
+    }
+
+    /// Check that `evidence` was generated for this challenge and the key
+    /// `public_key`, a DER encoded `SubjectPublicKeyInfo`, and consume the
+    /// challenge.
+    ///
+    /// The evidence itself, e.g. the signature and measurement of a
+    /// [`Report`], must still be checked by the caller.
+    ///
+    /// # Errors
+    ///
+    /// `Security` if the nonce of the evidence does not match, or the
+    /// challenge expired or was already answered.
+    pub fn verify<E: Evidence>(&self, evidence: &E, public_key: &[u8]) -> Result<()> {
+        if evidence.nonce() != cert::bound_nonce(public_key, &self.nonce)? {
+            return Err(Error::with_message(
+                ErrorKind::Security,
+                "Evidence not bound to the key and the challenge",
+            ));
+        }
+        self.redeem()
+    }
+
+    /// Consume the challenge, whose nonce was checked by the caller.
+    fn redeem(&self) -> Result<()> {
+        if self.is_expired() {
+            return Err(Error::with_message(
+                ErrorKind::Security,
+                "Challenge expired",
+            ));
+        }
+        if self.answered.swap(true, Ordering::SeqCst) {
+            return Err(Error::with_message(
+                ErrorKind::Security,
+                "Challenge already answered",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Public part of the attestation key of the device, which signs the reports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttestationKey {
+    /// Big-endian public exponent of the RSA key.
+    pub exponent: Vec<u8>,
+    /// Big-endian modulus of the RSA key.
+    pub modulus: Vec<u8>,
+    /// Signature scheme of the reports, an [`AlgorithmId`](crate::AlgorithmId)
+    /// value, `RsassaPkcs1PssMgf1Sha256` with the default OP-TEE configuration.
+    pub algorithm: u32,
+}
+
+impl AttestationKey {
+    /// Get the public attestation key from the attestation pseudo TA.
+    ///
+    /// # Errors
+    ///
+    /// If the attestation pseudo TA is not available or fails.
+    pub fn get() -> Result<Self> {
+        let mut session = open_session()?;
+        let mut exponent = [0u8; 8];
+        let mut modulus = [0u8; MAX_SIGNATURE_SIZE];
+        let mut params = TeeParams::new()
+            .with_memref_out(ParamIndex::Arg0, &mut exponent)
+            .with_memref_out(ParamIndex::Arg1, &mut modulus)
+            .with_value_out(ParamIndex::Arg2, 0, 0);
+        session.invoke_command(PTA_ATTESTATION_GET_PUBKEY, &mut params)?;
+        let written = |index| {
+            params[index]
+                .written_slice()
+                .map(<[u8]>::to_vec)
+                .ok_or(ErrorKind::BadFormat)
+        };
+        Ok(Self {
+            exponent: written(ParamIndex::Arg0)?,
+            modulus: written(ParamIndex::Arg1)?,
+            algorithm: params[ParamIndex::Arg2]
+                .output_value()
+                .ok_or(ErrorKind::BadFormat)?
+                .0,
+        })
+    }
+}
+
+fn open_session() -> Result<crate::TaSession> {
+    TaSessionBuilder::new(Uuid::parse_str(PTA_ATTESTATION_UUID)?).build()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn test_report_from_output() {
+        assert!(Report::from_output(b"nonce", &[0; MEASUREMENT_SIZE]).is_err());
+
+        let mut output = [7u8; MEASUREMENT_SIZE + 3];
+        output[MEASUREMENT_SIZE..].copy_from_slice(&[1, 2, 3]);
+        let report = Report::from_output(b"nonce", &output).expect("it should be ok");
+        assert_eq!(report.nonce, b"nonce");
+        assert_eq!(report.measurement, [7; MEASUREMENT_SIZE]);
+        assert_eq!(report.signature, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_report_to_der() {
+        let report = Report {
+            nonce: std::vec![0xaa; 2],
+            measurement: [0x11; MEASUREMENT_SIZE],
+            signature: std::vec![0x55; 3],
+        };
+        let mut expected = std::vec![
+            0x30,
+            3 + 4 + 34 + 5,
+            0x02,
+            0x01,
+            0x01,
+            0x04,
+            0x02,
+            0xaa,
+            0xaa
+        ];
+        expected.extend_from_slice(&[0x04, 0x20]);
+        expected.extend_from_slice(&[0x11; MEASUREMENT_SIZE]);
+        expected.extend_from_slice(&[0x04, 0x03, 0x55, 0x55, 0x55]);
+        assert_eq!(report.to_der(), expected);
+        assert_eq!(Report::from_der(&expected).unwrap(), report);
+
+        // Trailing data, unknown version, short measurement
+        expected.push(0);
+        assert!(Report::from_der(&expected).is_err());
+        expected.pop();
+        expected[4] = 2;
+        assert!(Report::from_der(&expected).is_err());
+        let mut encoded = report.to_der();
+        encoded[1] -= 1;
+        encoded[10] -= 1;
+        encoded.remove(11);
+        assert!(Report::from_der(&encoded).is_err());
+    }
+
+    /// A clock standing still, ten seconds after its origin.
+    pub(super) fn clock() -> SystemTime {
+        SystemTime::default() + Duration::from_secs(10)
+    }
+
+    #[test]
+    fn test_challenge() {
+        let challenge = Challenge::with_clock([7; CHALLENGE_SIZE], Duration::from_secs(5), clock);
+        assert_eq!(challenge.nonce(), [7; CHALLENGE_SIZE]);
+        assert!(!challenge.is_expired());
+        challenge.redeem().unwrap();
+        let err = challenge.redeem().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Security);
+
+        let mut challenge =
+            Challenge::with_clock([7; CHALLENGE_SIZE], Duration::from_secs(5), clock);
+        challenge.issued = SystemTime::default() + Duration::from_secs(5);
+        assert!(!challenge.is_expired());
+        challenge.issued = SystemTime::default() + Duration::from_secs(4);
+        assert!(challenge.is_expired());
+        assert_eq!(challenge.redeem().unwrap_err().kind(), ErrorKind::Security);
+    }
+}
@@ -0,0 +1,435 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! PSA attestation tokens, the evidence of the Arm platforms implementing
+//! the Platform Security Architecture, e.g. the Initial Attestation service
+//! of Trusted Firmware-M.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{Evidence, cbor};
+use crate::crypto::{EcdsaP256, EcdsaP256PublicKey};
+use crate::{Error, ErrorKind, Result};
+
+// Claims of the token, RFC 9783
+const CLAIM_NONCE: i64 = 10;
+const CLAIM_INSTANCE_ID: i64 = 256;
+const CLAIM_PROFILE: i64 = 265;
+const CLAIM_CLIENT_ID: i64 = 2394;
+const CLAIM_SECURITY_LIFECYCLE: i64 = 2395;
+const CLAIM_IMPLEMENTATION_ID: i64 = 2396;
+const CLAIM_BOOT_SEED: i64 = 2397;
+const CLAIM_SOFTWARE_COMPONENTS: i64 = 2399;
+
+// Fields of a software component
+const COMPONENT_MEASUREMENT_TYPE: i64 = 1;
+const COMPONENT_MEASUREMENT_VALUE: i64 = 2;
+const COMPONENT_VERSION: i64 = 4;
+const COMPONENT_SIGNER_ID: i64 = 5;
+
+/// Tag of a `COSE_Sign1` structure, RFC 9052.
+const COSE_SIGN1_TAG: u64 = 18;
+const COSE_HEADER_ALG: i64 = 1;
+/// ECDSA with SHA-256, the only algorithm supported.
+const COSE_ALG_ES256: i64 = -7;
+
+/// A measured software component of the platform.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SoftwareComponent {
+    /// Role of the component, e.g. `BL` or `PRoT`.
+    pub measurement_type: Option<String>,
+    /// Digest of the component.
+    pub measurement_value: Vec<u8>,
+    pub version: Option<String>,
+    /// Digest of the key the component is signed with.
+    pub signer_id: Vec<u8>,
+}
+
+/// The claims of a [`PsaToken`], those of the profile of RFC 9783 which
+/// identify the platform and its state. Other claims are ignored when
+/// decoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PsaClaims {
+    /// The profile the token follows, [`PsaClaims::PROFILE`] for RFC 9783.
+    pub profile: String,
+    /// The nonce the token was generated for.
+    pub nonce: Vec<u8>,
+    /// Identifies the attestation key of the platform, its first byte is 1.
+    pub instance_id: Vec<u8>,
+    /// Identifies the implementation of the immutable root of trust.
+    pub implementation_id: Vec<u8>,
+    /// The partition of the caller, negative for the non-secure world.
+    pub client_id: i64,
+    /// The lifecycle state of the platform, only the `secured` states 0x3000
+    /// to 0x30ff are trustworthy.
+    pub security_lifecycle: u64,
+    /// Random value changed on every boot.
+    pub boot_seed: Option<Vec<u8>>,
+    pub software_components: Vec<SoftwareComponent>,
+}
+
+impl PsaClaims {
+    /// Profile of the tokens following RFC 9783.
+    pub const PROFILE: &'static str = "tag:psacertified.org,2023:psa#tfm";
+
+    fn encode(&self) -> Vec<u8> {
+        let components: Vec<Vec<u8>> = self
+            .software_components
+            .iter()
+            .map(SoftwareComponent::encode)
+            .collect();
+        let components: Vec<&[u8]> = components.iter().map(Vec::as_slice).collect();
+        let mut claims = alloc::vec![
+            (CLAIM_PROFILE, cbor::text(&self.profile)),
+            (CLAIM_CLIENT_ID, cbor::integer(self.client_id)),
+            (
+                CLAIM_SECURITY_LIFECYCLE,
+                cbor::head(cbor::MAJOR_UNSIGNED, self.security_lifecycle),
+            ),
+            (
+                CLAIM_IMPLEMENTATION_ID,
+                cbor::bytes(&self.implementation_id),
+            ),
+            (CLAIM_SOFTWARE_COMPONENTS, cbor::array(&components)),
+            (CLAIM_NONCE, cbor::bytes(&self.nonce)),
+            (CLAIM_INSTANCE_ID, cbor::bytes(&self.instance_id)),
+        ];
+        if let Some(boot_seed) = &self.boot_seed {
+            claims.push((CLAIM_BOOT_SEED, cbor::bytes(boot_seed)));
+        }
+        cbor::map(&claims)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = cbor::Reader::new(bytes);
+        let (mut profile, mut nonce, mut instance_id, mut implementation_id) =
+            (None, None, None, None);
+        let (mut client_id, mut security_lifecycle, mut boot_seed, mut software_components) =
+            (None, None, None, None);
+        for _ in 0..reader.read_map()? {
+            match reader.read_integer()? {
+                CLAIM_PROFILE => profile = Some(reader.read_text()?.to_string()),
+                CLAIM_NONCE => nonce = Some(reader.read_bytes()?.to_vec()),
+                CLAIM_INSTANCE_ID => instance_id = Some(reader.read_bytes()?.to_vec()),
+                CLAIM_IMPLEMENTATION_ID => implementation_id = Some(reader.read_bytes()?.to_vec()),
+                CLAIM_CLIENT_ID => client_id = Some(reader.read_integer()?),
+                CLAIM_SECURITY_LIFECYCLE => security_lifecycle = Some(reader.read_unsigned()?),
+                CLAIM_BOOT_SEED => boot_seed = Some(reader.read_bytes()?.to_vec()),
+                CLAIM_SOFTWARE_COMPONENTS => {
+                    let mut components = Vec::new();
+                    for _ in 0..reader.read_array()? {
+                        components.push(SoftwareComponent::decode(&mut reader)?);
+                    }
+                    software_components = Some(components);
+                }
+                _ => reader.skip()?,
+            }
+        }
+        if !reader.is_empty() {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        let missing = || Error::with_message(ErrorKind::BadFormat, "Missing PSA claim");
+        Ok(Self {
+            profile: profile.ok_or_else(missing)?,
+            nonce: nonce.ok_or_else(missing)?,
+            instance_id: instance_id.ok_or_else(missing)?,
+            implementation_id: implementation_id.ok_or_else(missing)?,
+            client_id: client_id.ok_or_else(missing)?,
+            security_lifecycle: security_lifecycle.ok_or_else(missing)?,
+            boot_seed,
+            software_components: software_components.ok_or_else(missing)?,
+        })
+    }
+}
+
+impl SoftwareComponent {
+    fn encode(&self) -> Vec<u8> {
+        let mut fields = Vec::new();
+        if let Some(measurement_type) = &self.measurement_type {
+            fields.push((COMPONENT_MEASUREMENT_TYPE, cbor::text(measurement_type)));
+        }
+        fields.push((
+            COMPONENT_MEASUREMENT_VALUE,
+            cbor::bytes(&self.measurement_value),
+        ));
+        if let Some(version) = &self.version {
+            fields.push((COMPONENT_VERSION, cbor::text(version)));
+        }
+        fields.push((COMPONENT_SIGNER_ID, cbor::bytes(&self.signer_id)));
+        cbor::map(&fields)
+    }
+
+    fn decode(reader: &mut cbor::Reader) -> Result<Self> {
+        let (mut measurement_type, mut measurement_value, mut version, mut signer_id) =
+            (None, None, None, None);
+        for _ in 0..reader.read_map()? {
+            match reader.read_integer()? {
+                COMPONENT_MEASUREMENT_TYPE => {
+                    measurement_type = Some(reader.read_text()?.to_string())
+                }
+                COMPONENT_MEASUREMENT_VALUE => {
+                    measurement_value = Some(reader.read_bytes()?.to_vec())
+                }
+                COMPONENT_VERSION => version = Some(reader.read_text()?.to_string()),
+                COMPONENT_SIGNER_ID => signer_id = Some(reader.read_bytes()?.to_vec()),
+                _ => reader.skip()?,
+            }
+        }
+        Ok(Self {
+            measurement_type,
+            measurement_value: measurement_value.ok_or(ErrorKind::BadFormat)?,
+            version,
+            signer_id: signer_id.ok_or(ErrorKind::BadFormat)?,
+        })
+    }
+}
+
+/// A PSA attestation token of RFC 9783: the [`PsaClaims`] of a platform,
+/// signed by its attestation key as a `COSE_Sign1` structure with ES256.
+///
+/// As [`Evidence`], a token lets a TA accept the certificates of peers
+/// running on PSA platforms, e.g. a Cortex-M device with Trusted Firmware-M,
+/// whose nonce is bound to the key of the certificate. The verifier checks
+/// the signature with the attestation key of the platform and the claims
+/// with the values it expects:
+///
+/// ``` rust,no_run
+/// # use optee_utee::attestation::{Evidence, PsaClaims, PsaToken};
+/// # use optee_utee::crypto::EcdsaP256PublicKey;
+/// # use optee_utee::{ErrorKind, Result};
+/// # fn main() -> Result<()> {
+/// # let (encoded, platform_key, expected) = (&[][..], &[4u8; 65], &[0u8; 32]);
+/// let token = PsaToken::decode(encoded)?;
+/// token.verify(&EcdsaP256PublicKey::new(platform_key)?)?;
+/// let claims: &PsaClaims = &token.claims;
+/// if !(0x3000..0x3100).contains(&claims.security_lifecycle)
+///     || claims.software_components.iter().all(|c| c.measurement_value != expected)
+/// {
+///     return Err(ErrorKind::Security.into());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PsaToken {
+    pub claims: PsaClaims,
+    // The encoded protected header and claims, as signed
+    protected: Vec<u8>,
+    payload: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl PsaToken {
+    /// Sign `claims` with the attestation key `key`, e.g. for a TA standing
+    /// in for the attestation service of a PSA platform.
+    pub fn sign(claims: PsaClaims, key: &EcdsaP256) -> Result<Self> {
+        let protected = cbor::map(&[(COSE_HEADER_ALG, cbor::integer(COSE_ALG_ES256))]);
+        let payload = claims.encode();
+        let signature = key.sign(&to_be_signed(&protected, &payload))?.to_vec();
+        Ok(Self {
+            claims,
+            protected,
+            payload,
+            signature,
+        })
+    }
+
+    /// Verify the signature of the token with the attestation key `key` of
+    /// the platform.
+    ///
+    /// # Errors
+    ///
+    /// `SignatureInvalid`: If the signature does not match.
+    pub fn verify(&self, key: &EcdsaP256PublicKey) -> Result<()> {
+        key.verify(
+            &to_be_signed(&self.protected, &self.payload),
+            &self.signature,
+        )
+    }
+}
+
+/// The `Sig_structure` of a `COSE_Sign1` structure without external data,
+/// which is what the signature covers.
+fn to_be_signed(protected: &[u8], payload: &[u8]) -> Vec<u8> {
+    cbor::array(&[
+        &cbor::text("Signature1"),
+        &cbor::bytes(protected),
+        &cbor::bytes(&[]),
+        &cbor::bytes(payload),
+    ])
+}
+
+/// Check that the protected header `protected` selects ES256.
+fn check_algorithm(protected: &[u8]) -> Result<()> {
+    let mut reader = cbor::Reader::new(protected);
+    let mut algorithm = None;
+    for _ in 0..reader.read_map()? {
+        match reader.read_integer()? {
+            COSE_HEADER_ALG => algorithm = Some(reader.read_integer()?),
+            _ => reader.skip()?,
+        }
+    }
+    if !reader.is_empty() {
+        return Err(ErrorKind::BadFormat.into());
+    }
+    match algorithm {
+        Some(COSE_ALG_ES256) => Ok(()),
+        Some(_) => Err(Error::with_message(
+            ErrorKind::NotSupported,
+            "PSA token not signed with ES256",
+        )),
+        None => Err(ErrorKind::BadFormat.into()),
+    }
+}
+
+impl Evidence for PsaToken {
+    /// Encode the token as a tagged `COSE_Sign1` structure.
+    fn encode(&self) -> Vec<u8> {
+        cbor::tag(
+            COSE_SIGN1_TAG,
+            &cbor::array(&[
+                &cbor::bytes(&self.protected),
+                &cbor::map(&[]),
+                &cbor::bytes(&self.payload),
+                &cbor::bytes(&self.signature),
+            ]),
+        )
+    }
+
+    /// Decode a tagged `COSE_Sign1` structure signed with ES256. The
+    /// signature is not verified, see [`verify`](Self::verify).
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = cbor::Reader::new(bytes);
+        if reader.read_tag()? != COSE_SIGN1_TAG || reader.read_array()? != 4 {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        let protected = reader.read_bytes()?;
+        // The unprotected header carries nothing the token relies on
+        for _ in 0..reader.read_map()? {
+            reader.skip()?;
+            reader.skip()?;
+        }
+        let payload = reader.read_bytes()?;
+        let signature = reader.read_bytes()?;
+        if !reader.is_empty() || signature.len() != EcdsaP256::SIGNATURE_SIZE {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        check_algorithm(protected)?;
+        Ok(Self {
+            claims: PsaClaims::decode(payload)?,
+            protected: protected.to_vec(),
+            payload: payload.to_vec(),
+            signature: signature.to_vec(),
+        })
+    }
+
+    fn nonce(&self) -> &[u8] {
+        &self.claims.nonce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    fn claims() -> PsaClaims {
+        PsaClaims {
+            profile: PsaClaims::PROFILE.into(),
+            nonce: std::vec![0x11; 32],
+            instance_id: [&[0x01][..], &[0x22; 32]].concat(),
+            implementation_id: std::vec![0x33; 32],
+            client_id: -1,
+            security_lifecycle: 0x3000,
+            boot_seed: None,
+            software_components: std::vec![SoftwareComponent {
+                measurement_type: Some("BL".into()),
+                measurement_value: std::vec![0x44; 32],
+                version: None,
+                signer_id: std::vec![0x55; 32],
+            }],
+        }
+    }
+
+    fn token(claims: PsaClaims) -> PsaToken {
+        PsaToken {
+            protected: std::vec![0xa1, 0x01, 0x26],
+            payload: claims.encode(),
+            claims,
+            signature: std::vec![0x66; EcdsaP256::SIGNATURE_SIZE],
+        }
+    }
+
+    #[test]
+    fn test_claims() {
+        let mut claims = claims();
+        assert_eq!(PsaClaims::decode(&claims.encode()).unwrap(), claims);
+        claims.boot_seed = Some(std::vec![0x77; 32]);
+        claims.software_components[0].version = Some("1.2.0".into());
+        assert_eq!(PsaClaims::decode(&claims.encode()).unwrap(), claims);
+
+        // Unknown claims are skipped, missing ones are rejected
+        let mut encoded = claims.encode();
+        encoded[0] += 1;
+        encoded.extend_from_slice(&cbor::integer(2400));
+        encoded.extend_from_slice(&cbor::text(
+            "https://veraison.example/v1/challenge-response",
+        ));
+        assert_eq!(PsaClaims::decode(&encoded).unwrap(), claims);
+        let encoded = cbor::map(&[(CLAIM_NONCE, cbor::bytes(&claims.nonce))]);
+        assert_eq!(
+            PsaClaims::decode(&encoded).unwrap_err().kind(),
+            ErrorKind::BadFormat
+        );
+    }
+
+    #[test]
+    fn test_to_be_signed() {
+        let mut expected = std::vec![0x84, 0x6a];
+        expected.extend_from_slice(b"Signature1");
+        expected.extend_from_slice(&[0x43, 0xa1, 0x01, 0x26, 0x40, 0x42, 0xa0, 0x00]);
+        assert_eq!(to_be_signed(&[0xa1, 0x01, 0x26], &[0xa0, 0x00]), expected);
+    }
+
+    #[test]
+    fn test_token() {
+        let token = token(claims());
+        let encoded = token.encode();
+        assert_eq!(encoded[..6], [0xd2, 0x84, 0x43, 0xa1, 0x01, 0x26]);
+        let decoded = PsaToken::decode(&encoded).unwrap();
+        assert_eq!(decoded, token);
+        assert_eq!(decoded.nonce(), [0x11; 32]);
+
+        // Untagged, trailing data, truncated signature
+        assert!(PsaToken::decode(&encoded[1..]).is_err());
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(PsaToken::decode(&trailing).is_err());
+        let mut truncated = token.clone();
+        truncated.signature.pop();
+        assert!(PsaToken::decode(&truncated.encode()).is_err());
+
+        // ES384
+        let mut es384 = token.clone();
+        es384.protected = std::vec![0xa1, 0x01, 0x38, 0x22];
+        assert_eq!(
+            PsaToken::decode(&es384.encode()).unwrap_err().kind(),
+            ErrorKind::NotSupported
+        );
+    }
+}
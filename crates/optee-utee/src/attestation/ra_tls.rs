@@ -0,0 +1,984 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! rustls configurations of attested TLS (RA-TLS) endpoints.
+//!
+//! The server presents a self-signed certificate for a key generated in the
+//! TA, with a [`Report`] bound to the key in an extension, see
+//! [`CertificateBuilder::attestation_report`]. Instead of a chain of trust,
+//! the client checks that the report is bound to the key of the certificate
+//! and lets the application decide whether the report is acceptable; the
+//! handshake then proves that the server holds the key. The server name is
+//! not checked, the identity of the server is its measurement.
+//!
+//! Both sides are generic over the [`Evidence`] format, so a client can accept
+//! the evidence of servers running in other TEEs.
+//!
+//! To prevent the replay of a server's certificate and key, the client can
+//! require the evidence to answer a [`Challenge`] sent beforehand to the
+//! server, which builds its configuration for that connection only.
+//!
+//! Verifying evidence, e.g. the RSA signature of a [`Report`], can cost more
+//! than the rest of the handshake. A client connecting often to the same
+//! servers caches the certificates it accepted for a while, see
+//! [`RaTlsServerCertVerifier::cache`], and resumes the TLS sessions of
+//! servers it already verified. A resumed session skips the certificate
+//! altogether, and is bound to the report verified by the handshake that
+//! established it, as only the server holding its key could complete that
+//! handshake.
+//!
+//! [`Report`]: super::Report
+//!
+//! With a [`RevocationSource`], the certificates of compromised devices or
+//! keys are rejected, whether they are the end certificate or one of the
+//! intermediates sent by the server.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use rustls::client::Resumption;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::hash::{Hash, HashAlgorithm};
+use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::server::{
+    ClientHello, NoServerSessionStorage, ResolvesServerCert, ServerSessionMemoryCache,
+};
+use rustls::sign::{CertifiedKey, Signer};
+use rustls::time_provider::TimeProvider;
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, ServerConfig, SignatureAlgorithm,
+    SignatureScheme,
+};
+
+use super::cert::ReportGenerator;
+use super::{
+    CertificateBuilder, CertificateId, Challenge, Evidence, RevocationSource, RevocationStatus,
+    SigningKey, der,
+};
+use crate::{Error, ErrorKind, Result};
+
+/// Common name of the server certificates by default.
+const DEFAULT_SUBJECT: &str = "ra-tls";
+
+/// Builds the [`ServerConfig`] of an attested TLS server.
+///
+/// The TLS key is generated when building, and the certificate carries the
+/// evidence returned by the closure for the nonce binding it to the key,
+/// usually the report of [`Report::generate`](super::Report::generate):
+///
+/// ``` rust,no_run
+/// # use optee_utee::attestation::{RaTlsServerConfigBuilder, Report};
+/// # use std::sync::Arc;
+/// # fn main() -> optee_utee::Result<()> {
+/// # let provider: Arc<rustls::crypto::CryptoProvider> = unimplemented!();
+/// const REPORT_OID: &[u32] = &[1, 3, 6, 1, 4, 1, 99999, 1];
+///
+/// let config = RaTlsServerConfigBuilder::new(provider, REPORT_OID, Report::generate)
+///     .subject("my-ta")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RaTlsServerConfigBuilder<'a> {
+    provider: Arc<CryptoProvider>,
+    time_provider: Option<Arc<dyn TimeProvider>>,
+    report_oid: Vec<u32>,
+    subject: String,
+    report: ReportGenerator<'a>,
+    challenge: Vec<u8>,
+    session_cache: Option<usize>,
+}
+
+impl<'a> RaTlsServerConfigBuilder<'a> {
+    /// Create a builder for a server using the cryptography of `provider`,
+    /// with the evidence returned by `report` in the extension `report_oid`.
+    pub fn new<E: Evidence>(
+        provider: Arc<CryptoProvider>,
+        report_oid: &[u32],
+        report: impl FnOnce(&[u8]) -> Result<E> + 'a,
+    ) -> Self {
+        Self {
+            provider,
+            time_provider: None,
+            report_oid: report_oid.to_vec(),
+            subject: DEFAULT_SUBJECT.into(),
+            report: Box::new(move |nonce| Ok(report(nonce)?.encode())),
+            challenge: Vec::new(),
+            session_cache: None,
+        }
+    }
+
+    /// Bind the evidence to the nonce of the client's [`Challenge`], see
+    /// [`CertificateBuilder::challenge`].
+    pub fn challenge(mut self, challenge: &[u8]) -> Self {
+        self.challenge = challenge.to_vec();
+        self
+    }
+
+    /// Set the common name of the certificate, `ra-tls` by default.
+    pub fn subject(mut self, subject: &str) -> Self {
+        self.subject = subject.into();
+        self
+    }
+
+    /// Use `time_provider` instead of the system time of the standard
+    /// library.
+    pub fn time_provider(mut self, time_provider: Arc<dyn TimeProvider>) -> Self {
+        self.time_provider = Some(time_provider);
+        self
+    }
+
+    /// Keep the state of up to `capacity` sessions for their resumption by
+    /// the clients, 256 by default as in rustls, or none with 0.
+    pub fn session_cache(mut self, capacity: usize) -> Self {
+        self.session_cache = Some(capacity);
+        self
+    }
+
+    /// Generate the key and its certificate, and build the configuration.
+    ///
+    /// # Errors
+    ///
+    /// If generating the key or the report fails, or `NotSupported` if the
+    /// crypto provider supports no TLS version.
+    pub fn build(self) -> Result<ServerConfig> {
+        let key = SigningKey::generate()?;
+        let cert = CertificateBuilder::new(&key, &self.subject)
+            .report_generator(&self.report_oid, self.report)
+            .challenge(&self.challenge)
+            .self_signed()?;
+        let certified_key = CertifiedKey::new(
+            vec![CertificateDer::from(cert)],
+            Arc::new(TlsKey(Arc::new(TeeKey(key)))),
+        );
+        let builder = match self.time_provider {
+            Some(time_provider) => ServerConfig::builder_with_details(self.provider, time_provider),
+            None => ServerConfig::builder_with_provider(self.provider),
+        };
+        let mut config = builder
+            .with_safe_default_protocol_versions()
+            .map_err(|_| unsupported_provider())?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(SingleCert(Arc::new(certified_key))));
+        match self.session_cache {
+            Some(0) => config.session_storage = Arc::new(NoServerSessionStorage {}),
+            Some(capacity) => config.session_storage = ServerSessionMemoryCache::new(capacity),
+            None => {}
+        }
+        Ok(config)
+    }
+}
+
+/// Builds the [`ClientConfig`] of a client of attested TLS servers.
+///
+/// The certificate of the server is accepted if it carries evidence bound to
+/// its key, and the closure accepts the evidence. For a
+/// [`Report`](super::Report), the closure must check its signature with the
+/// [`AttestationKey`](super::AttestationKey) of the device and compare the
+/// measurement with the expected one:
+///
+/// ``` rust,no_run
+/// # use optee_utee::attestation::{RaTlsClientConfigBuilder, Report};
+/// # use std::sync::Arc;
+/// # fn main() -> optee_utee::Result<()> {
+/// # let provider: Arc<rustls::crypto::CryptoProvider> = unimplemented!();
+/// # const REPORT_OID: &[u32] = &[1, 3, 6, 1, 4, 1, 99999, 1];
+/// let config = RaTlsClientConfigBuilder::new(provider, REPORT_OID, |report: &Report| {
+///     // Check the signature and the measurement
+///     Ok(())
+/// })
+/// .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RaTlsClientConfigBuilder {
+    provider: Arc<CryptoProvider>,
+    time_provider: Option<Arc<dyn TimeProvider>>,
+    report_oid: Vec<u32>,
+    verify: Arc<ReportVerifier>,
+    challenge: Option<Arc<Challenge>>,
+    revocation: Option<Arc<dyn RevocationSource>>,
+    cache: Option<(Duration, usize)>,
+    resumption: Option<Resumption>,
+}
+
+/// Verifies the encoded evidence of a certificate, given the nonce binding
+/// it to the key of the certificate.
+type ReportVerifier = dyn Fn(&[u8], &[u8]) -> Result<()> + Send + Sync;
+
+fn report_verifier<E: Evidence>(
+    verify: impl Fn(&E) -> Result<()> + Send + Sync + 'static,
+) -> Arc<ReportVerifier> {
+    Arc::new(move |encoded, nonce| {
+        let evidence = E::decode(encoded)?;
+        if evidence.nonce() != nonce {
+            return Err(Error::with_message(
+                ErrorKind::Security,
+                "Evidence not bound to the key of the certificate",
+            ));
+        }
+        verify(&evidence)
+    })
+}
+
+impl RaTlsClientConfigBuilder {
+    /// Create a builder for a client using the cryptography of `provider`,
+    /// accepting the evidence in the extension `report_oid` for which
+    /// `verify` succeeds.
+    pub fn new<E: Evidence>(
+        provider: Arc<CryptoProvider>,
+        report_oid: &[u32],
+        verify: impl Fn(&E) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            provider,
+            time_provider: None,
+            report_oid: report_oid.to_vec(),
+            verify: report_verifier(verify),
+            challenge: None,
+            revocation: None,
+            cache: None,
+            resumption: None,
+        }
+    }
+
+    /// Only accept evidence answering `challenge`, see
+    /// [`RaTlsServerCertVerifier::challenge`]. Sessions are not resumed, as
+    /// a resumed session would not answer the challenge.
+    pub fn challenge(mut self, challenge: Arc<Challenge>) -> Self {
+        self.challenge = Some(challenge);
+        self
+    }
+
+    /// Reject the certificates revoked by `revocation`, see
+    /// [`RaTlsServerCertVerifier::revocation`].
+    pub fn revocation(mut self, revocation: Arc<dyn RevocationSource>) -> Self {
+        self.revocation = Some(revocation);
+        self
+    }
+
+    /// Accept the certificates accepted in the last `ttl` without verifying
+    /// their evidence again, see [`RaTlsServerCertVerifier::cache`].
+    pub fn cache(mut self, ttl: Duration, capacity: usize) -> Self {
+        self.cache = Some((ttl, capacity));
+        self
+    }
+
+    /// Resume sessions as configured by `resumption`, in memory for up to
+    /// 256 servers by default as in rustls.
+    pub fn resumption(mut self, resumption: Resumption) -> Self {
+        self.resumption = Some(resumption);
+        self
+    }
+
+    /// Use `time_provider` instead of the system time of the standard
+    /// library.
+    pub fn time_provider(mut self, time_provider: Arc<dyn TimeProvider>) -> Self {
+        self.time_provider = Some(time_provider);
+        self
+    }
+
+    /// Build the configuration, without client authentication.
+    ///
+    /// # Errors
+    ///
+    /// `NotSupported` if the crypto provider supports no TLS version or has
+    /// no SHA-256 implementation.
+    pub fn build(self) -> Result<ClientConfig> {
+        let mut verifier =
+            RaTlsServerCertVerifier::with_verifier(&self.provider, &self.report_oid, self.verify)?;
+        let resumption = match (&self.challenge, self.resumption) {
+            (Some(_), _) => Some(Resumption::disabled()),
+            (None, resumption) => resumption,
+        };
+        verifier.challenge = self.challenge;
+        verifier.revocation = self.revocation;
+        if let Some((ttl, capacity)) = self.cache {
+            verifier = verifier.cache(ttl, capacity);
+        }
+        let builder = match self.time_provider {
+            Some(time_provider) => ClientConfig::builder_with_details(self.provider, time_provider),
+            None => ClientConfig::builder_with_provider(self.provider),
+        };
+        let mut config = builder
+            .with_safe_default_protocol_versions()
+            .map_err(|_| unsupported_provider())?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        if let Some(resumption) = resumption {
+            config.resumption = resumption;
+        }
+        Ok(config)
+    }
+}
+
+/// The verifier of the certificates of attested TLS servers used by
+/// [`RaTlsClientConfigBuilder`], for clients needing another configuration.
+pub struct RaTlsServerCertVerifier {
+    report_oid: Vec<u8>,
+    sha256: &'static dyn Hash,
+    algorithms: WebPkiSupportedAlgorithms,
+    verify: Arc<ReportVerifier>,
+    challenge: Option<Arc<Challenge>>,
+    revocation: Option<Arc<dyn RevocationSource>>,
+    cache: Option<VerifiedCache>,
+}
+
+impl RaTlsServerCertVerifier {
+    /// Create a verifier using the cryptography of `provider`, accepting the
+    /// evidence in the extension `report_oid` for which `verify` succeeds.
+    ///
+    /// # Errors
+    ///
+    /// `NotSupported` if the crypto provider has no SHA-256 implementation.
+    pub fn new<E: Evidence>(
+        provider: &CryptoProvider,
+        report_oid: &[u32],
+        verify: impl Fn(&E) -> Result<()> + Send + Sync + 'static,
+    ) -> Result<Self> {
+        Self::with_verifier(provider, report_oid, report_verifier(verify))
+    }
+
+    fn with_verifier(
+        provider: &CryptoProvider,
+        report_oid: &[u32],
+        verify: Arc<ReportVerifier>,
+    ) -> Result<Self> {
+        // Only the TLS 1.3 suites give access to their hash
+        let sha256 = provider
+            .cipher_suites
+            .iter()
+            .filter_map(|suite| suite.tls13())
+            .map(|suite| suite.common.hash_provider)
+            .find(|hash| hash.algorithm() == HashAlgorithm::SHA256)
+            .ok_or_else(unsupported_provider)?;
+        Ok(Self {
+            report_oid: der::oid(report_oid),
+            sha256,
+            algorithms: provider.signature_verification_algorithms,
+            verify,
+            challenge: None,
+            revocation: None,
+            cache: None,
+        })
+    }
+
+    /// Only accept evidence answering `challenge`, whose nonce is the digest
+    /// of the `SubjectPublicKeyInfo` followed by the nonce of the challenge.
+    ///
+    /// The challenge is consumed by the first certificate accepted, so the
+    /// verifier accepts a single connection, and later ones fail like the
+    /// connections after the challenge expired.
+    pub fn challenge(mut self, challenge: Arc<Challenge>) -> Self {
+        self.challenge = Some(challenge);
+        self
+    }
+
+    /// Reject the certificates revoked by `revocation`: the end certificate
+    /// and the intermediates presented by the server are checked before the
+    /// report, and rejected if revoked or if their status is unknown.
+    pub fn revocation(mut self, revocation: Arc<dyn RevocationSource>) -> Self {
+        self.revocation = Some(revocation);
+        self
+    }
+
+    /// Accept the certificates whose evidence was accepted in the last `ttl`
+    /// without verifying it again, remembering up to `capacity` of them by
+    /// their SHA-256 digest.
+    ///
+    /// The closure verifying the evidence is not called for the cached
+    /// certificates, so the evidence must stay acceptable for `ttl` after
+    /// its verification. Revocation is still checked for every handshake,
+    /// and nothing is cached with a [`challenge`](Self::challenge), which
+    /// accepts a single certificate anyway.
+    pub fn cache(mut self, ttl: Duration, capacity: usize) -> Self {
+        self.cache = Some(VerifiedCache {
+            ttl: ttl.as_secs(),
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+        });
+        self
+    }
+
+    /// Check the revocation status of the DER encoded certificates `certs`
+    /// at `now`, in seconds since the Unix epoch.
+    fn verify_revocation<'c>(
+        &self,
+        certs: impl IntoIterator<Item = &'c [u8]>,
+        now: u64,
+    ) -> core::result::Result<(), CertificateError> {
+        let Some(revocation) = &self.revocation else {
+            return Ok(());
+        };
+        for cert in certs {
+            let id = CertificateId::from_der(cert).map_err(|_| CertificateError::BadEncoding)?;
+            match revocation.status(&id, now) {
+                RevocationStatus::Good => {}
+                RevocationStatus::Revoked => return Err(CertificateError::Revoked),
+                RevocationStatus::Unknown => {
+                    return Err(CertificateError::UnknownRevocationStatus);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the report carried by the DER encoded certificate `cert`.
+    fn verify_report(&self, cert: &[u8]) -> core::result::Result<(), CertificateError> {
+        let (public_key, report) =
+            parse_certificate(cert, &self.report_oid).map_err(|_| CertificateError::BadEncoding)?;
+        let report = report.ok_or(CertificateError::ApplicationVerificationFailure)?;
+        let mut nonce = self.sha256.start();
+        nonce.update(public_key);
+        if let Some(challenge) = &self.challenge {
+            nonce.update(challenge.nonce());
+        }
+        (self.verify)(report, nonce.finish().as_ref())
+            .and_then(|()| self.challenge.as_ref().map_or(Ok(()), |c| c.redeem()))
+            .map_err(|_| CertificateError::ApplicationVerificationFailure)
+    }
+}
+
+impl fmt::Debug for RaTlsServerCertVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RaTlsServerCertVerifier")
+            .field("report_oid", &self.report_oid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ServerCertVerifier for RaTlsServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> core::result::Result<ServerCertVerified, rustls::Error> {
+        let certs = core::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|cert| cert.as_ref());
+        self.verify_revocation(certs, now.as_secs())?;
+        let cache = self.cache.as_ref().filter(|_| self.challenge.is_none());
+        let Some(cache) = cache else {
+            self.verify_report(end_entity)?;
+            return Ok(ServerCertVerified::assertion());
+        };
+        let mut digest = self.sha256.start();
+        digest.update(end_entity);
+        let digest = digest.finish();
+        if !cache.contains(digest.as_ref(), now.as_secs()) {
+            self.verify_report(end_entity)?;
+            cache.insert(digest.as_ref(), now.as_secs());
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// The certificates whose evidence was accepted recently, by SHA-256 digest,
+/// with the time they were accepted in seconds since the Unix epoch, oldest
+/// first.
+struct VerifiedCache {
+    ttl: u64,
+    capacity: usize,
+    entries: Mutex<VecDeque<(Vec<u8>, u64)>>,
+}
+
+impl VerifiedCache {
+    fn contains(&self, digest: &[u8], now: u64) -> bool {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        // A clock going backwards does not extend the entries
+        entries.retain(|(_, verified)| *verified <= now && now - verified <= self.ttl);
+        entries.iter().any(|(cached, _)| cached == digest)
+    }
+
+    fn insert(&self, digest: &[u8], now: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back((digest.to_vec(), now));
+    }
+}
+
+/// Returns the DER encoded `SubjectPublicKeyInfo` of the certificate `cert`
+/// and the value of its extension `oid`, a DER encoded object identifier.
+fn parse_certificate<'c>(cert: &'c [u8], oid: &[u8]) -> Result<(&'c [u8], Option<&'c [u8]>)> {
+    let mut cert = der::Reader::new(der::Reader::new(cert).read(der::TAG_SEQUENCE)?);
+    let mut tbs = der::Reader::new(cert.read(der::TAG_SEQUENCE)?);
+    // Version, serial number, signature algorithm, issuer, validity, subject
+    tbs.read_optional(0xa0)?;
+    tbs.read(der::TAG_INTEGER)?;
+    for _ in 0..4 {
+        tbs.read(der::TAG_SEQUENCE)?;
+    }
+    let public_key = match tbs.read_any()? {
+        (der::TAG_SEQUENCE, _, encoding) => encoding,
+        _ => return Err(ErrorKind::BadFormat.into()),
+    };
+    // Issuer and subject unique identifiers
+    tbs.read_optional(0x81)?;
+    tbs.read_optional(0x82)?;
+    if let Some(extensions) = tbs.read_optional(0xa3)? {
+        let mut extensions =
+            der::Reader::new(der::Reader::new(extensions).read(der::TAG_SEQUENCE)?);
+        while !extensions.is_empty() {
+            let mut extension = der::Reader::new(extensions.read(der::TAG_SEQUENCE)?);
+            let (tag, _, id) = extension.read_any()?;
+            extension.read_optional(der::TAG_BOOLEAN)?;
+            let value = extension.read(der::TAG_OCTET_STRING)?;
+            if tag == der::TAG_OID && id == oid {
+                return Ok((public_key, Some(value)));
+            }
+        }
+    }
+    Ok((public_key, None))
+}
+
+/// Resolves the certificate of every client hello to the same one.
+#[derive(Debug)]
+struct SingleCert(Arc<CertifiedKey>);
+
+impl ResolvesServerCert for SingleCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.clone())
+    }
+}
+
+/// The TLS key of a server, signing the handshakes with the TEE Internal Core
+/// API.
+#[derive(Clone)]
+struct TlsKey(Arc<TeeKey>);
+
+struct TeeKey(SigningKey);
+
+// SAFETY: OP-TEE runs the entry points of a TA instance one at a time on a
+// single thread, the object handle of the key is never used concurrently.
+unsafe impl Send for TeeKey {}
+unsafe impl Sync for TeeKey {}
+
+impl fmt::Debug for TlsKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TlsKey")
+    }
+}
+
+impl rustls::sign::SigningKey for TlsKey {
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+        offered
+            .contains(&SignatureScheme::ECDSA_NISTP256_SHA256)
+            .then(|| Box::new(self.clone()) as Box<dyn Signer>)
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::ECDSA
+    }
+}
+
+impl Signer for TlsKey {
+    fn sign(&self, message: &[u8]) -> core::result::Result<Vec<u8>, rustls::Error> {
+        self.0
+            .0
+            .sign(message)
+            .map_err(|_| rustls::Error::General("Failed to sign the handshake".into()))
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        SignatureScheme::ECDSA_NISTP256_SHA256
+    }
+}
+
+fn unsupported_provider() -> Error {
+    Error::with_message(
+        ErrorKind::NotSupported,
+        "The crypto provider lacks TLS 1.3 or SHA-256",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::attestation::{Report, RevocationList};
+    use sha2::{Digest, Sha256};
+
+    const REPORT_OID: &[u32] = &[1, 3, 6, 1, 4, 1, 99999, 1];
+    const MEASUREMENT: [u8; 32] = [0x11; 32];
+
+    fn public_key(point: u8) -> Vec<u8> {
+        der::sequence(&[
+            &der::sequence(&[&der::oid(&[1, 2, 840, 10045, 2, 1])]),
+            &der::bit_string(&[point; 65]),
+        ])
+    }
+
+    /// A certificate for `public_key` with `extensions`, not signed as the
+    /// verifier does not check the signature.
+    fn certificate(public_key: &[u8], extensions: &[&[u8]]) -> Vec<u8> {
+        let algorithm = der::sequence(&[&der::oid(&[1, 2, 840, 10045, 4, 3, 2])]);
+        let name = der::sequence(&[]);
+        let tbs = der::sequence(&[
+            &der::explicit(0, &der::small_integer(2)),
+            &der::small_integer(1),
+            &algorithm,
+            &name,
+            &der::sequence(&[&der::time(0), &der::time(1)]),
+            &name,
+            public_key,
+            &der::explicit(3, &der::sequence(extensions)),
+        ]);
+        der::sequence(&[&tbs, &algorithm, &der::bit_string(&[0; 8])])
+    }
+
+    fn report_extension(public_key: &[u8]) -> Vec<u8> {
+        let report = Report {
+            nonce: Sha256::digest(public_key).to_vec(),
+            measurement: MEASUREMENT,
+            signature: vec![0x55; 4],
+        };
+        der::sequence(&[&der::oid(REPORT_OID), &der::octet_string(&report.to_der())])
+    }
+
+    fn verifier() -> RaTlsServerCertVerifier {
+        let provider = rustls::crypto::ring::default_provider();
+        RaTlsServerCertVerifier::new(&provider, REPORT_OID, |report: &Report| {
+            if report.measurement == MEASUREMENT {
+                Ok(())
+            } else {
+                Err(ErrorKind::Security.into())
+            }
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_certificate() {
+        let key = public_key(4);
+        let other = der::sequence(&[
+            &der::oid(&[2, 5, 29, 19]),
+            &der::boolean(true),
+            &der::octet_string(&der::sequence(&[])),
+        ]);
+        let cert = certificate(&key, &[&other, &report_extension(&key)]);
+        let oid = der::oid(REPORT_OID);
+        let (parsed_key, report) = parse_certificate(&cert, &oid).unwrap();
+        assert_eq!(parsed_key, key);
+        let report = Report::from_der(report.unwrap()).unwrap();
+        assert_eq!(report.measurement, MEASUREMENT);
+
+        let cert = certificate(&key, &[&other]);
+        assert_eq!(parse_certificate(&cert, &oid).unwrap(), (&key[..], None));
+        assert!(parse_certificate(&cert[..cert.len() - 1], &oid).is_err());
+    }
+
+    #[test]
+    fn test_verify_report() {
+        let verifier = verifier();
+        let key = public_key(4);
+        let cert = certificate(&key, &[&report_extension(&key)]);
+        verifier
+            .verify_server_cert(
+                &CertificateDer::from(cert),
+                &[],
+                &ServerName::try_from("localhost").unwrap(),
+                &[],
+                UnixTime::now(),
+            )
+            .unwrap();
+
+        // Report bound to another key
+        let cert = certificate(&public_key(5), &[&report_extension(&key)]);
+        assert_eq!(
+            verifier.verify_report(&cert),
+            Err(CertificateError::ApplicationVerificationFailure)
+        );
+
+        // Measurement rejected by the application
+        let report = Report {
+            nonce: Sha256::digest(&key).to_vec(),
+            measurement: [0; 32],
+            signature: Vec::new(),
+        };
+        let extension =
+            der::sequence(&[&der::oid(REPORT_OID), &der::octet_string(&report.to_der())]);
+        let cert = certificate(&key, &[&extension]);
+        assert_eq!(
+            verifier.verify_report(&cert),
+            Err(CertificateError::ApplicationVerificationFailure)
+        );
+
+        // No report, malformed certificate
+        let cert = certificate(&key, &[]);
+        assert_eq!(
+            verifier.verify_report(&cert),
+            Err(CertificateError::ApplicationVerificationFailure)
+        );
+        assert_eq!(
+            verifier.verify_report(&[0x30, 0x00]),
+            Err(CertificateError::BadEncoding)
+        );
+    }
+
+    /// Evidence of another TEE, encoded as a PCR value followed by the nonce.
+    struct Quote {
+        pcr: u8,
+        nonce: Vec<u8>,
+    }
+
+    impl Evidence for Quote {
+        fn encode(&self) -> Vec<u8> {
+            [&[self.pcr][..], &self.nonce].concat()
+        }
+
+        fn decode(bytes: &[u8]) -> Result<Self> {
+            let (pcr, nonce) = bytes.split_first().ok_or(ErrorKind::BadFormat)?;
+            Ok(Self {
+                pcr: *pcr,
+                nonce: nonce.to_vec(),
+            })
+        }
+
+        fn nonce(&self) -> &[u8] {
+            &self.nonce
+        }
+    }
+
+    #[test]
+    fn test_custom_evidence() {
+        let provider = rustls::crypto::ring::default_provider();
+        let verifier = RaTlsServerCertVerifier::new(&provider, REPORT_OID, |quote: &Quote| {
+            if quote.pcr == 7 {
+                Ok(())
+            } else {
+                Err(ErrorKind::Security.into())
+            }
+        })
+        .unwrap();
+        let key = public_key(4);
+        let quote = |pcr, key: &[u8]| {
+            let quote = Quote {
+                pcr,
+                nonce: Sha256::digest(key).to_vec(),
+            };
+            der::sequence(&[&der::oid(REPORT_OID), &der::octet_string(&quote.encode())])
+        };
+        assert!(
+            verifier
+                .verify_report(&certificate(&key, &[&quote(7, &key)]))
+                .is_ok()
+        );
+        assert!(
+            verifier
+                .verify_report(&certificate(&key, &[&quote(8, &key)]))
+                .is_err()
+        );
+        let other = public_key(5);
+        assert!(
+            verifier
+                .verify_report(&certificate(&key, &[&quote(7, &other)]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_challenge() {
+        let challenge = Arc::new(Challenge::with_clock(
+            [9; 32],
+            core::time::Duration::from_secs(5),
+            crate::attestation::tests::clock,
+        ));
+        let verifier = verifier().challenge(challenge.clone());
+        let key = public_key(4);
+        let extension = |nonce: Vec<u8>| {
+            let report = Report {
+                nonce,
+                measurement: MEASUREMENT,
+                signature: Vec::new(),
+            };
+            der::sequence(&[&der::oid(REPORT_OID), &der::octet_string(&report.to_der())])
+        };
+
+        // Report not answering the challenge
+        let cert = certificate(&key, &[&report_extension(&key)]);
+        assert_eq!(
+            verifier.verify_report(&cert),
+            Err(CertificateError::ApplicationVerificationFailure)
+        );
+
+        let nonce = Sha256::new()
+            .chain_update(&key)
+            .chain_update(challenge.nonce())
+            .finalize()
+            .to_vec();
+        let cert = certificate(&key, &[&extension(nonce)]);
+        assert_eq!(verifier.verify_report(&cert), Ok(()));
+        // Replayed
+        assert_eq!(
+            verifier.verify_report(&cert),
+            Err(CertificateError::ApplicationVerificationFailure)
+        );
+    }
+
+    #[test]
+    fn test_revocation() {
+        let key = public_key(4);
+        let cert = CertificateDer::from(certificate(&key, &[&report_extension(&key)]));
+        let verify = |verifier: &RaTlsServerCertVerifier, intermediates: &[CertificateDer<'_>]| {
+            verifier
+                .verify_server_cert(
+                    &cert,
+                    intermediates,
+                    &ServerName::try_from("localhost").unwrap(),
+                    &[],
+                    UnixTime::since_unix_epoch(core::time::Duration::from_secs(1000)),
+                )
+                .map(|_| ())
+        };
+
+        let mut list = RevocationList::new();
+        list.revoke_public_key(&public_key(5));
+        let with_list = verifier().revocation(Arc::new(list.clone()));
+        assert_eq!(verify(&with_list, &[]), Ok(()));
+        // A revoked intermediate
+        let intermediate = CertificateDer::from(certificate(&public_key(5), &[]));
+        assert_eq!(
+            verify(&with_list, &[intermediate]),
+            Err(CertificateError::Revoked.into())
+        );
+
+        list.revoke_public_key(&key);
+        let with_list = verifier().revocation(Arc::new(list));
+        assert_eq!(
+            verify(&with_list, &[]),
+            Err(CertificateError::Revoked.into())
+        );
+
+        struct Unavailable;
+        impl RevocationSource for Unavailable {
+            fn status(&self, _cert: &CertificateId<'_>, _now: u64) -> RevocationStatus {
+                RevocationStatus::Unknown
+            }
+        }
+        let unavailable = verifier().revocation(Arc::new(Unavailable));
+        assert_eq!(
+            verify(&unavailable, &[]),
+            Err(CertificateError::UnknownRevocationStatus.into())
+        );
+    }
+
+    #[test]
+    fn test_cache() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static VERIFIED: AtomicUsize = AtomicUsize::new(0);
+        let provider = rustls::crypto::ring::default_provider();
+        let verifier = RaTlsServerCertVerifier::new(&provider, REPORT_OID, |_: &Report| {
+            VERIFIED.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .unwrap()
+        .cache(Duration::from_secs(60), 1);
+        let verify = |key: &[u8], now| {
+            let cert = CertificateDer::from(certificate(key, &[&report_extension(key)]));
+            verifier
+                .verify_server_cert(
+                    &cert,
+                    &[],
+                    &ServerName::try_from("localhost").unwrap(),
+                    &[],
+                    UnixTime::since_unix_epoch(Duration::from_secs(now)),
+                )
+                .unwrap();
+            VERIFIED.load(Ordering::SeqCst)
+        };
+        let (key, other) = (public_key(4), public_key(5));
+        assert_eq!(verify(&key, 1000), 1);
+        assert_eq!(verify(&key, 1060), 1);
+        // Expired
+        assert_eq!(verify(&key, 1061), 2);
+        // Evicted by another certificate
+        assert_eq!(verify(&other, 1062), 3);
+        assert_eq!(verify(&key, 1062), 4);
+    }
+
+    #[test]
+    fn test_client_config() {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = RaTlsClientConfigBuilder::new(provider, REPORT_OID, |_: &Report| Ok(()))
+            .build()
+            .unwrap();
+        assert!(!config.client_auth_cert_resolver.has_certs());
+
+        let challenge = Arc::new(Challenge::with_clock(
+            [9; 32],
+            Duration::from_secs(5),
+            crate::attestation::tests::clock,
+        ));
+        let config = RaTlsClientConfigBuilder::new(
+            Arc::new(rustls::crypto::ring::default_provider()),
+            REPORT_OID,
+            |_: &Report| Ok(()),
+        )
+        .challenge(challenge)
+        .build()
+        .unwrap();
+        assert!(format!("{:?}", config.resumption).contains("NoClientSessionStorage"));
+
+        let no_tls13 = Arc::new(CryptoProvider {
+            cipher_suites: Vec::new(),
+            ..rustls::crypto::ring::default_provider()
+        });
+        let err = RaTlsClientConfigBuilder::new(no_tls13, REPORT_OID, |_: &Report| Ok(()))
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotSupported);
+    }
+}
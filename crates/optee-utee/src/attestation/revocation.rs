@@ -0,0 +1,320 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Revocation of the certificates accepted by the attestation verifiers.
+
+use alloc::vec::Vec;
+
+use super::der;
+use crate::{ErrorKind, Result};
+
+/// The fields of a certificate identifying it for revocation checks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CertificateId<'a> {
+    /// DER encoded `Name` of the issuer.
+    pub issuer: &'a [u8],
+    /// Content of the DER `INTEGER` of the serial number.
+    pub serial: &'a [u8],
+    /// DER encoded `SubjectPublicKeyInfo`.
+    pub public_key: &'a [u8],
+}
+
+impl<'a> CertificateId<'a> {
+    /// Parse the identifying fields of the DER encoded certificate `cert`.
+    ///
+    /// # Errors
+    ///
+    /// `BadFormat` if `cert` is not a DER encoded X.509 certificate.
+    pub fn from_der(cert: &'a [u8]) -> Result<Self> {
+        let mut cert = der::Reader::new(der::Reader::new(cert).read(der::TAG_SEQUENCE)?);
+        let mut tbs = der::Reader::new(cert.read(der::TAG_SEQUENCE)?);
+        tbs.read_optional(0xa0)?;
+        let serial = tbs.read(der::TAG_INTEGER)?;
+        tbs.read(der::TAG_SEQUENCE)?;
+        let issuer = read_sequence(&mut tbs)?;
+        // Validity and subject
+        tbs.read(der::TAG_SEQUENCE)?;
+        tbs.read(der::TAG_SEQUENCE)?;
+        let public_key = read_sequence(&mut tbs)?;
+        Ok(Self {
+            issuer,
+            serial,
+            public_key,
+        })
+    }
+}
+
+/// The revocation status of a certificate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RevocationStatus {
+    /// The certificate is not revoked.
+    Good,
+    /// The certificate is revoked.
+    Revoked,
+    /// The status is not known, e.g. the revocation list is out of date.
+    Unknown,
+}
+
+/// A source of the revocation status of certificates, checked for every
+/// certificate of the chain presented to an attestation verifier, e.g. with
+/// `RaTlsServerCertVerifier::revocation` of the `ra_tls` feature.
+///
+/// [`RevocationList`] is built from CRLs and lists of revoked keys.
+/// Deployments with another source, e.g. a status list pushed by a device
+/// management service, implement the trait for it.
+pub trait RevocationSource: Send + Sync {
+    /// The status of `cert` at `now`, in seconds since the Unix epoch.
+    fn status(&self, cert: &CertificateId<'_>, now: u64) -> RevocationStatus;
+}
+
+/// Revoked certificates, by issuer and serial number as listed by CRLs, or
+/// by public key for the self-signed certificates of attested TAs.
+///
+/// The signatures of the CRLs are not checked: they must come from a
+/// trusted source, such as the TA binary or a channel authenticated by the
+/// CA. The issuers are compared with the encoding of the names in the CRLs
+/// byte for byte.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RevocationList {
+    serials: Vec<(Vec<u8>, Vec<u8>)>,
+    public_keys: Vec<Vec<u8>>,
+    next_update: Option<u64>,
+}
+
+impl RevocationList {
+    /// An empty list, revoking nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A list of the certificates revoked by the DER encoded CRL `crl`.
+    ///
+    /// # Errors
+    ///
+    /// `BadFormat` if `crl` is not a DER encoded X.509 CRL.
+    pub fn from_crl(crl: &[u8]) -> Result<Self> {
+        let mut list = Self::new();
+        list.add_crl(crl)?;
+        Ok(list)
+    }
+
+    /// Add the certificates revoked by the DER encoded CRL `crl`.
+    ///
+    /// The status of every certificate becomes [`RevocationStatus::Unknown`]
+    /// after the next update announced by the CRL, until a list is built
+    /// from a newer CRL.
+    ///
+    /// # Errors
+    ///
+    /// `BadFormat` if `crl` is not a DER encoded X.509 CRL, in which case
+    /// the list is left unchanged.
+    pub fn add_crl(&mut self, crl: &[u8]) -> Result<()> {
+        let mut outer = der::Reader::new(crl);
+        let mut crl = der::Reader::new(outer.read(der::TAG_SEQUENCE)?);
+        if !outer.is_empty() {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        let mut tbs = der::Reader::new(crl.read(der::TAG_SEQUENCE)?);
+        tbs.read_optional(der::TAG_INTEGER)?;
+        tbs.read(der::TAG_SEQUENCE)?;
+        let issuer = read_sequence(&mut tbs)?;
+        let (tag, this_update, _) = tbs.read_any()?;
+        der::parse_time(tag, this_update)?;
+        let next_update = match tbs.read_optional(der::TAG_UTC_TIME)? {
+            Some(time) => Some(der::parse_time(der::TAG_UTC_TIME, time)?),
+            None => tbs
+                .read_optional(der::TAG_GENERALIZED_TIME)?
+                .map(|time| der::parse_time(der::TAG_GENERALIZED_TIME, time))
+                .transpose()?,
+        };
+        let mut serials = Vec::new();
+        if let Some(revoked) = tbs.read_optional(der::TAG_SEQUENCE)? {
+            let mut revoked = der::Reader::new(revoked);
+            while !revoked.is_empty() {
+                let mut entry = der::Reader::new(revoked.read(der::TAG_SEQUENCE)?);
+                serials.push((issuer.to_vec(), entry.read(der::TAG_INTEGER)?.to_vec()));
+            }
+        }
+        // The CRL extensions
+        tbs.read_optional(0xa0)?;
+        if !tbs.is_empty() {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        self.serials.extend(serials);
+        self.next_update = match (self.next_update, next_update) {
+            (Some(current), Some(next)) => Some(current.min(next)),
+            (current, next) => current.or(next),
+        };
+        Ok(())
+    }
+
+    /// Revoke the certificate with `serial`, the content of its DER
+    /// `INTEGER`, issued by `issuer`, a DER encoded `Name`.
+    pub fn revoke_serial(&mut self, issuer: &[u8], serial: &[u8]) {
+        self.serials.push((issuer.to_vec(), serial.to_vec()));
+    }
+
+    /// Revoke the certificates of `public_key`, a DER encoded
+    /// `SubjectPublicKeyInfo`, whoever issued them.
+    pub fn revoke_public_key(&mut self, public_key: &[u8]) {
+        self.public_keys.push(public_key.to_vec());
+    }
+
+    /// Whether `cert` is revoked by the list, regardless of its next update.
+    pub fn is_revoked(&self, cert: &CertificateId<'_>) -> bool {
+        self.serials
+            .iter()
+            .any(|(issuer, serial)| issuer == cert.issuer && serial == cert.serial)
+            || self.public_keys.iter().any(|key| key == cert.public_key)
+    }
+}
+
+impl RevocationSource for RevocationList {
+    fn status(&self, cert: &CertificateId<'_>, now: u64) -> RevocationStatus {
+        if self.is_revoked(cert) {
+            RevocationStatus::Revoked
+        } else if self
+            .next_update
+            .is_some_and(|next_update| now > next_update)
+        {
+            RevocationStatus::Unknown
+        } else {
+            RevocationStatus::Good
+        }
+    }
+}
+
+/// Reads a `SEQUENCE`, returning its whole encoding.
+fn read_sequence<'a>(reader: &mut der::Reader<'a>) -> Result<&'a [u8]> {
+    match reader.read_any()? {
+        (der::TAG_SEQUENCE, _, encoding) => Ok(encoding),
+        _ => Err(ErrorKind::BadFormat.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    fn name(cn: &str) -> Vec<u8> {
+        der::sequence(&[&der::constructed(
+            der::TAG_SET,
+            &[&der::sequence(&[
+                &der::oid(&[2, 5, 4, 3]),
+                &der::utf8_string(cn),
+            ])],
+        )])
+    }
+
+    fn public_key(point: u8) -> Vec<u8> {
+        der::sequence(&[
+            &der::sequence(&[&der::oid(&[1, 2, 840, 10045, 2, 1])]),
+            &der::bit_string(&[point; 65]),
+        ])
+    }
+
+    fn certificate(issuer: &str, serial: u64, public_key: &[u8]) -> Vec<u8> {
+        let algorithm = der::sequence(&[&der::oid(&[1, 2, 840, 10045, 4, 3, 2])]);
+        let tbs = der::sequence(&[
+            &der::explicit(0, &der::small_integer(2)),
+            &der::small_integer(serial),
+            &algorithm,
+            &name(issuer),
+            &der::sequence(&[&der::time(0), &der::time(1)]),
+            &name("device"),
+            public_key,
+        ]);
+        der::sequence(&[&tbs, &algorithm, &der::bit_string(&[0; 8])])
+    }
+
+    fn crl(issuer: &str, next_update: Option<u64>, serials: &[u64]) -> Vec<u8> {
+        let algorithm = der::sequence(&[&der::oid(&[1, 2, 840, 10045, 4, 3, 2])]);
+        let entries: Vec<Vec<u8>> = serials
+            .iter()
+            .map(|serial| der::sequence(&[&der::small_integer(*serial), &der::time(50)]))
+            .collect();
+        let entries: Vec<&[u8]> = entries.iter().map(Vec::as_slice).collect();
+        let revoked = der::sequence(&entries);
+        let next_update = next_update.map(der::time);
+        let issuer = name(issuer);
+        let this_update = der::time(100);
+        let version = der::small_integer(1);
+        let mut fields: Vec<&[u8]> = std::vec![&version, &algorithm, &issuer, &this_update];
+        if let Some(next_update) = &next_update {
+            fields.push(next_update);
+        }
+        if !serials.is_empty() {
+            fields.push(&revoked);
+        }
+        let tbs = der::sequence(&fields);
+        der::sequence(&[&tbs, &algorithm, &der::bit_string(&[0; 8])])
+    }
+
+    #[test]
+    fn test_certificate_id() {
+        let key = public_key(4);
+        let cert = certificate("ca", 0x80, &key);
+        let id = CertificateId::from_der(&cert).unwrap();
+        assert_eq!(id.issuer, name("ca"));
+        assert_eq!(id.serial, [0x00, 0x80]);
+        assert_eq!(id.public_key, key);
+        assert!(CertificateId::from_der(&cert[..cert.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_crl() {
+        let key = public_key(4);
+        let list = RevocationList::from_crl(&crl("ca", Some(1000), &[3, 5])).unwrap();
+        let status = |issuer, serial| {
+            let cert = certificate(issuer, serial, &key);
+            list.status(&CertificateId::from_der(&cert).unwrap(), 500)
+        };
+        assert_eq!(status("ca", 5), RevocationStatus::Revoked);
+        assert_eq!(status("ca", 4), RevocationStatus::Good);
+        // Same serial, another issuer
+        assert_eq!(status("other", 5), RevocationStatus::Good);
+
+        // Out of date
+        let cert = certificate("ca", 4, &key);
+        let id = CertificateId::from_der(&cert).unwrap();
+        assert_eq!(list.status(&id, 1001), RevocationStatus::Unknown);
+
+        // Without next update or revoked certificates
+        let list = RevocationList::from_crl(&crl("ca", None, &[])).unwrap();
+        assert_eq!(list.status(&id, u64::MAX), RevocationStatus::Good);
+
+        let mut encoded = crl("ca", None, &[3]);
+        encoded.push(0);
+        assert!(RevocationList::from_crl(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_revoked_public_key() {
+        let mut list = RevocationList::new();
+        list.revoke_public_key(&public_key(4));
+        list.revoke_serial(&name("ca"), &[7]);
+        let revoked = certificate("any", 1, &public_key(4));
+        let other = certificate("any", 1, &public_key(5));
+        let serial = certificate("ca", 7, &public_key(5));
+        let status = |cert: &[u8]| list.status(&CertificateId::from_der(cert).unwrap(), 0);
+        assert_eq!(status(&revoked), RevocationStatus::Revoked);
+        assert_eq!(status(&other), RevocationStatus::Good);
+        assert_eq!(status(&serial), RevocationStatus::Revoked);
+    }
+}
@@ -0,0 +1,147 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cancellation of the running command by the client.
+//!
+//! A client application can request the cancellation of a command it invoked,
+//! e.g. with `optee_teec::Session::invoke_command_with_timeout`. The TA is not
+//! interrupted: long-running commands poll the cancellation flag between steps
+//! and return [`ErrorKind::Cancel`] when it is set. Cancellations are masked
+//! when an entry point is called, and the flag always reads `false` until they
+//! are unmasked.
+//!
+//! A `#[ta_invoke_command]` function taking a `&CancellationToken` as last
+//! parameter gets cancellations unmasked for the duration of the command:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::cancellation::CancellationToken;
+//! # use optee_utee::{ParametersAny, Result};
+//! # fn step(_: u32) -> Result<()> { Ok(()) }
+//! // #[ta_invoke_command]
+//! fn invoke_command(
+//!     cmd_id: u32,
+//!     params: &mut ParametersAny,
+//!     cancellation: &CancellationToken,
+//! ) -> Result<()> {
+//!     for round in 0..1000 {
+//!         cancellation.check()?;
+//!         step(round)?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::{ErrorKind, Result};
+use core::marker::PhantomData;
+use optee_utee_sys as raw;
+
+/// Returns whether the cancellation of the current command was requested,
+/// always `false` while cancellations are masked.
+pub fn is_cancelled() -> bool {
+    unsafe { raw::TEE_GetCancellationFlag() }
+}
+
+/// Unmasks cancellations, returns whether they were masked before.
+pub fn unmask() -> bool {
+    unsafe { raw::TEE_UnmaskCancellation() }
+}
+
+/// Masks cancellations, returns whether they were masked before.
+pub fn mask() -> bool {
+    unsafe { raw::TEE_MaskCancellation() }
+}
+
+/// Cancellations unmasked for the duration of a command, see the
+/// [module documentation](self).
+///
+/// The previous mask state is restored when the token is dropped.
+pub struct CancellationToken {
+    was_masked: bool,
+    // Not `Send`: the mask state belongs to the running entry point.
+    _marker: PhantomData<*const ()>,
+}
+
+impl CancellationToken {
+    /// Unmasks cancellations until the returned token is dropped.
+    pub fn unmasked() -> Self {
+        Self {
+            was_masked: unmask(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns whether the cancellation of the command was requested.
+    pub fn is_cancelled(&self) -> bool {
+        is_cancelled()
+    }
+
+    /// Fails with `Cancel` if the cancellation of the command was requested,
+    /// to be called between the steps of a long-running command with `?`.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(ErrorKind::Cancel.into());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CancellationToken {
+    fn drop(&mut self) {
+        if self.was_masked {
+            mask();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use optee_utee_sys::{mock_api, mock_utils::SERIAL_TEST_LOCK};
+
+    #[test]
+    fn test_cancellation_token() {
+        let _lock = SERIAL_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let unmask_ctx = mock_api::TEE_UnmaskCancellation_context();
+        unmask_ctx.expect().times(1).return_const(true);
+        let flag_ctx = mock_api::TEE_GetCancellationFlag_context();
+        flag_ctx.expect().times(1).return_const(false);
+        flag_ctx.expect().times(1).return_const(true);
+        let mask_ctx = mock_api::TEE_MaskCancellation_context();
+        mask_ctx.expect().times(1).return_const(false);
+
+        let token = CancellationToken::unmasked();
+        assert!(token.check().is_ok());
+        assert_eq!(token.check().unwrap_err().kind(), ErrorKind::Cancel);
+        // Masks the cancellations again
+        drop(token);
+    }
+
+    #[test]
+    fn test_cancellation_token_keeps_unmasked() {
+        let _lock = SERIAL_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let unmask_ctx = mock_api::TEE_UnmaskCancellation_context();
+        unmask_ctx.expect().times(1).return_const(false);
+        let mask_ctx = mock_api::TEE_MaskCancellation_context();
+        mask_ctx.expect().never();
+
+        drop(CancellationToken::unmasked());
+    }
+}
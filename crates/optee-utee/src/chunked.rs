@@ -0,0 +1,259 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Requests and responses larger than the shared memory, transferred in
+//! chunks over several invocations of a command.
+//!
+//! The client application sends them with `optee_teec::ChunkedSession`, the
+//! TA keeps a [`ChunkedReceiver`] in its session context, which reassembles
+//! the request, runs the handler on it, and returns the response chunk by
+//! chunk:
+//!
+//! ```rust,ignore
+//! #[ta_invoke_command]
+//! fn invoke_command(
+//!     session: &mut Session,
+//!     cmd_id: u32,
+//!     params: &mut ParametersAny<'_>,
+//! ) -> Result<()> {
+//!     match cmd_id {
+//!         CMD_RESTORE_BACKUP => session
+//!             .receiver
+//!             .invoke(params, |backup| session.wallet.restore(&backup)),
+//!         _ => Err(ErrorKind::BadParameters.into()),
+//!     }
+//! }
+//! ```
+//!
+//! Every invocation of a chunked transfer has the same parameters:
+//!
+//! | Parameter | Type          | Content |
+//! |-----------|---------------|---------|
+//! | 0         | value input   | `a`: sequence number of the invocation in the transfer, from 0; `b`: flags |
+//! | 1         | memref input  | the next chunk of the request, empty when fetching |
+//! | 2         | memref output | the next chunk of the response |
+//! | 3         | value output  | `a`: number of bytes of the response left after this chunk |
+//!
+//! The flags are [`FLAG_FIRST`] on the first chunk of the request,
+//! [`FLAG_LAST`] on its last chunk, and [`FLAG_FETCH`] on the invocations
+//! fetching the rest of the response. The response starts in the invocation
+//! of the last request chunk. An invocation out of sequence, or failing,
+//! aborts the transfer.
+
+use alloc::vec::Vec;
+
+use crate::{
+    ErrorKind, ParameterMemrefRead, ParameterMemrefWrite, ParameterValueRead, ParameterValueWrite,
+    ParametersAny, Result,
+};
+
+/// Flag of the first chunk of a request, starting a new transfer.
+pub const FLAG_FIRST: u32 = 1 << 0;
+/// Flag of the last chunk of a request, after which the handler runs.
+pub const FLAG_LAST: u32 = 1 << 1;
+/// Flag of the invocations fetching the rest of the response.
+pub const FLAG_FETCH: u32 = 1 << 2;
+
+enum State {
+    Idle,
+    Receiving(Vec<u8>),
+    Sending { response: Vec<u8>, offset: usize },
+}
+
+/// Reassembles chunked requests and splits their responses, see the
+/// [module documentation](self).
+pub struct ChunkedReceiver {
+    max_request_size: usize,
+    next_sequence: u32,
+    state: State,
+}
+
+impl ChunkedReceiver {
+    /// The default upper bound of a request, 16 MiB.
+    pub const DEFAULT_MAX_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+
+    /// Creates a receiver accepting requests up to
+    /// [`DEFAULT_MAX_REQUEST_SIZE`](Self::DEFAULT_MAX_REQUEST_SIZE).
+    pub fn new() -> Self {
+        Self {
+            max_request_size: Self::DEFAULT_MAX_REQUEST_SIZE,
+            next_sequence: 0,
+            state: State::Idle,
+        }
+    }
+
+    /// Sets the largest request the receiver reassembles. A larger request
+    /// fails the transfer with `ErrorKind::ExcessData`.
+    pub fn max_request_size(mut self, max_request_size: usize) -> Self {
+        self.max_request_size = max_request_size;
+        self
+    }
+
+    /// Handles one invocation of a chunked transfer with `params`, calling
+    /// `handler` with the request once it is complete. The response of
+    /// `handler` is returned in the following chunks.
+    pub fn invoke<F>(&mut self, params: &mut ParametersAny<'_>, handler: F) -> Result<()>
+    where
+        F: FnOnce(Vec<u8>) -> Result<Vec<u8>>,
+    {
+        let (p0, p1, p2, p3) = params;
+        let header = p0.as_value_input()?;
+        let (sequence, flags) = (header.get_a(), header.get_b());
+        let chunk = p1.as_memref_input()?.get_buffer();
+        let output = p2.as_memref_output()?;
+        let result = self.step(sequence, flags, chunk, output.get_buffer_mut(), handler);
+        match result {
+            Ok((written, remaining)) => {
+                output.set_updated_size(written)?;
+                p3.as_value_output()?.set_a(remaining);
+                Ok(())
+            }
+            Err(e) => {
+                self.state = State::Idle;
+                Err(e)
+            }
+        }
+    }
+
+    /// Processes the invocation `sequence` with `flags`, returning the
+    /// number of bytes written to `output` and the number of bytes of the
+    /// response left.
+    fn step<F>(
+        &mut self,
+        sequence: u32,
+        flags: u32,
+        chunk: &[u8],
+        output: &mut [u8],
+        handler: F,
+    ) -> Result<(usize, u32)>
+    where
+        F: FnOnce(Vec<u8>) -> Result<Vec<u8>>,
+    {
+        if flags & FLAG_FIRST != 0 {
+            self.state = State::Receiving(Vec::new());
+            self.next_sequence = 0;
+        }
+        if sequence != self.next_sequence {
+            return Err(ErrorKind::BadState.into());
+        }
+        self.next_sequence = sequence.wrapping_add(1);
+
+        if flags & FLAG_FETCH == 0 {
+            let State::Receiving(request) = &mut self.state else {
+                return Err(ErrorKind::BadState.into());
+            };
+            if request.len() + chunk.len() > self.max_request_size {
+                return Err(ErrorKind::ExcessData.into());
+            }
+            request.extend_from_slice(chunk);
+            if flags & FLAG_LAST == 0 {
+                return Ok((0, 0));
+            }
+            let request = core::mem::take(request);
+            let response = handler(request)?;
+            if u32::try_from(response.len()).is_err() {
+                return Err(ErrorKind::ExcessData.into());
+            }
+            self.state = State::Sending {
+                response,
+                offset: 0,
+            };
+        } else if !chunk.is_empty() {
+            return Err(ErrorKind::BadParameters.into());
+        }
+
+        let State::Sending { response, offset } = &mut self.state else {
+            return Err(ErrorKind::BadState.into());
+        };
+        let written = output.len().min(response.len() - *offset);
+        output[..written].copy_from_slice(&response[*offset..*offset + written]);
+        *offset += written;
+        let remaining = response.len() - *offset;
+        if remaining == 0 {
+            self.state = State::Idle;
+        }
+        Ok((written, remaining as u32))
+    }
+}
+
+impl Default for ChunkedReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use alloc::vec;
+
+    use super::*;
+
+    fn echo(request: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(request.iter().rev().copied().collect())
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut receiver = ChunkedReceiver::new();
+        let mut output = [0u8; 4];
+        let first = receiver.step(0, FLAG_FIRST, b"abc", &mut output, echo);
+        assert_eq!(first.unwrap(), (0, 0));
+        let last = receiver.step(1, FLAG_LAST, b"defghi", &mut output, echo);
+        assert_eq!(last.unwrap(), (4, 5));
+        assert_eq!(&output, b"ihgf");
+        let fetched = receiver.step(2, FLAG_FETCH, b"", &mut output, echo);
+        assert_eq!(fetched.unwrap(), (4, 1));
+        assert_eq!(&output, b"edcb");
+        let fetched = receiver.step(3, FLAG_FETCH, b"", &mut output, echo);
+        assert_eq!(fetched.unwrap(), (1, 0));
+        assert_eq!(output[0], b'a');
+
+        // The transfer is over
+        let fetched = receiver.step(4, FLAG_FETCH, b"", &mut output, echo);
+        assert_eq!(fetched.unwrap_err().kind(), ErrorKind::BadState);
+    }
+
+    #[test]
+    fn test_out_of_sequence() {
+        let mut receiver = ChunkedReceiver::new();
+        let mut output = [0u8; 4];
+        receiver
+            .step(0, FLAG_FIRST, b"abc", &mut output, echo)
+            .unwrap();
+        let skipped = receiver.step(2, FLAG_LAST, b"def", &mut output, echo);
+        assert_eq!(skipped.unwrap_err().kind(), ErrorKind::BadState);
+
+        // A new transfer starts over
+        let restarted = receiver.step(0, FLAG_FIRST | FLAG_LAST, b"x", &mut output, echo);
+        assert_eq!(restarted.unwrap(), (1, 0));
+    }
+
+    #[test]
+    fn test_max_request_size() {
+        let mut receiver = ChunkedReceiver::new().max_request_size(4);
+        let mut output = [0u8; 4];
+        receiver
+            .step(0, FLAG_FIRST, b"abc", &mut output, echo)
+            .unwrap();
+        let result = receiver.step(1, FLAG_LAST, b"de", &mut output, echo);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::ExcessData);
+        let large = vec![0u8; 5];
+        let result = receiver.step(0, FLAG_FIRST | FLAG_LAST, &large, &mut output, echo);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::ExcessData);
+    }
+}
@@ -0,0 +1,202 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{check, key_size, secret_object};
+use crate::{
+    AE, AlgorithmId, ErrorKind, OperationMode, Result, TransientObject, TransientObjectType,
+};
+
+/// AES-GCM key, encrypting with 96-bit nonces and 128-bit tags.
+///
+/// A nonce must never be used twice with the same key, e.g. use a counter
+/// or [`Random`](crate::Random) nonces for keys encrypting few messages.
+pub struct AesGcm {
+    key: TransientObject,
+    key_size: usize,
+}
+
+impl AesGcm {
+    /// Size of the nonces in bytes.
+    pub const NONCE_SIZE: usize = 12;
+    /// Size of the tags in bytes.
+    pub const TAG_SIZE: usize = 16;
+    /// Size of an AES block in bytes, the most data the streaming contexts
+    /// buffer between calls.
+    pub const BLOCK_SIZE: usize = 16;
+
+    /// Use the 16, 24 or 32 bytes `key`.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        check(matches!(key.len(), 16 | 24 | 32))?;
+        Ok(Self {
+            key: secret_object(TransientObjectType::Aes, key)?,
+            key_size: key.len() * 8,
+        })
+    }
+
+    /// Generate a random key of 128, 192 or 256 bits.
+    pub fn generate(key_size: usize) -> Result<Self> {
+        check(matches!(key_size, 128 | 192 | 256))?;
+        let key = TransientObject::allocate(TransientObjectType::Aes, key_size)?;
+        key.generate_key(key_size, &[])?;
+        Ok(Self { key, key_size })
+    }
+
+    /// Use the AES key `object`, for example one loaded from the secure
+    /// storage.
+    pub fn from_object(object: TransientObject) -> Result<Self> {
+        Ok(Self {
+            key_size: key_size(&object)?,
+            key: object,
+        })
+    }
+
+    /// Encrypt `plaintext` and authenticate it along with `aad`, returning
+    /// the ciphertext followed by the tag.
+    pub fn encrypt(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let encryptor = self.encryptor(nonce, aad)?;
+        let mut output = vec![0u8; plaintext.len() + Self::TAG_SIZE];
+        let (ciphertext, tag) = output.split_at_mut(plaintext.len());
+        let (size, computed) = encryptor.finish(plaintext, ciphertext)?;
+        tag.copy_from_slice(&computed);
+        debug_assert_eq!(size, plaintext.len());
+        Ok(output)
+    }
+
+    /// Decrypt the output of [`encrypt`](Self::encrypt), returning the
+    /// plaintext.
+    ///
+    /// # Errors
+    ///
+    /// `MacInvalid`: If the ciphertext, the tag or `aad` have been modified.
+    pub fn decrypt(&self, nonce: &[u8], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        let size = sealed
+            .len()
+            .checked_sub(Self::TAG_SIZE)
+            .ok_or(ErrorKind::MacInvalid)?;
+        let (ciphertext, tag) = sealed.split_at(size);
+        let mut plaintext = vec![0u8; size];
+        let size = self
+            .decryptor(nonce, aad)?
+            .finish(ciphertext, &mut plaintext, tag)?;
+        plaintext.truncate(size);
+        Ok(plaintext)
+    }
+
+    /// Start encrypting a message in chunks, authenticated along with `aad`.
+    pub fn encryptor(&self, nonce: &[u8], aad: &[u8]) -> Result<AesGcmEncryptor> {
+        Ok(AesGcmEncryptor {
+            op: self.start(OperationMode::Encrypt, nonce, aad)?,
+        })
+    }
+
+    /// Start decrypting a message in chunks, authenticated along with `aad`.
+    pub fn decryptor(&self, nonce: &[u8], aad: &[u8]) -> Result<AesGcmDecryptor> {
+        Ok(AesGcmDecryptor {
+            op: self.start(OperationMode::Decrypt, nonce, aad)?,
+        })
+    }
+
+    fn start(&self, mode: OperationMode, nonce: &[u8], aad: &[u8]) -> Result<AE> {
+        check(nonce.len() == Self::NONCE_SIZE)?;
+        let op = AE::allocate(AlgorithmId::AesGcm, mode, self.key_size)?;
+        op.set_key(&self.key)?;
+        op.init(nonce, Self::TAG_SIZE * 8, 0, 0)?;
+        if !aad.is_empty() {
+            op.update_aad(aad);
+        }
+        Ok(op)
+    }
+}
+
+/// Encrypts a message in chunks, see [`AesGcm::encryptor`].
+pub struct AesGcmEncryptor {
+    op: AE,
+}
+
+impl AesGcmEncryptor {
+    /// Encrypt the chunk `src` into `dest`, returning the size written.
+    ///
+    /// The output may lag behind the input by less than a block, `dest` must
+    /// hold `src.len() + AesGcm::BLOCK_SIZE` bytes.
+    pub fn update(&mut self, src: &[u8], dest: &mut [u8]) -> Result<usize> {
+        self.op.update(src, dest)
+    }
+
+    /// Encrypt the last chunk `src` into `dest`, returning the size written
+    /// and the tag.
+    ///
+    /// `dest` must hold `src.len() + AesGcm::BLOCK_SIZE` bytes.
+    pub fn finish(self, src: &[u8], dest: &mut [u8]) -> Result<(usize, [u8; AesGcm::TAG_SIZE])> {
+        let mut tag = [0u8; AesGcm::TAG_SIZE];
+        let (size, tag_size) = self.op.encrypt_final(src, dest, &mut tag)?;
+        debug_assert_eq!(tag_size, AesGcm::TAG_SIZE);
+        Ok((size, tag))
+    }
+}
+
+/// Decrypts a message in chunks, see [`AesGcm::decryptor`].
+///
+/// The plaintext returned before [`finish`](Self::finish) succeeds is not
+/// authenticated yet, it must not be used if `finish` fails.
+pub struct AesGcmDecryptor {
+    op: AE,
+}
+
+impl AesGcmDecryptor {
+    /// Decrypt the chunk `src` into `dest`, returning the size written.
+    ///
+    /// The output may lag behind the input by less than a block, `dest` must
+    /// hold `src.len() + AesGcm::BLOCK_SIZE` bytes.
+    pub fn update(&mut self, src: &[u8], dest: &mut [u8]) -> Result<usize> {
+        self.op.update(src, dest)
+    }
+
+    /// Decrypt the last chunk `src` into `dest` and check the `tag`,
+    /// returning the size written.
+    ///
+    /// `dest` must hold `src.len() + AesGcm::BLOCK_SIZE` bytes.
+    ///
+    /// # Errors
+    ///
+    /// `MacInvalid`: If the message or its additional data have been
+    /// modified.
+    pub fn finish(self, src: &[u8], dest: &mut [u8], tag: &[u8]) -> Result<usize> {
+        check(tag.len() == AesGcm::TAG_SIZE)?;
+        self.op.decrypt_final(src, dest, tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_sizes() {
+        // Rejected before reaching the TEE
+        assert_eq!(
+            AesGcm::new(&[0u8; 20]).err().map(|e| e.kind()),
+            Some(ErrorKind::BadParameters)
+        );
+        assert_eq!(
+            AesGcm::generate(512).err().map(|e| e.kind()),
+            Some(ErrorKind::BadParameters)
+        );
+    }
+}
@@ -0,0 +1,109 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use super::{EcdsaP256, EcdsaP256PublicKey, check};
+use crate::{AlgorithmId, ParameterMemrefRead, Result};
+
+/// SHA-256 digest of a message given in chunks, e.g. a payload too large for
+/// the heap of the TA sent in several memrefs or commands.
+///
+/// ``` rust,no_run
+/// # use optee_utee::crypto::Digest;
+/// # use optee_utee::{ParameterMemrefInput, Result};
+/// # fn hash(chunks: &[ParameterMemrefInput]) -> Result<[u8; 32]> {
+/// let mut digest = Digest::new()?;
+/// for chunk in chunks {
+///     digest.update_memref(chunk);
+/// }
+/// digest.finalize()
+/// # }
+/// ```
+pub struct Digest {
+    op: crate::Digest,
+}
+
+impl Digest {
+    /// Size of the digests in bytes.
+    pub const SIZE: usize = 32;
+
+    /// Start computing the digest of a message.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            op: crate::Digest::allocate(AlgorithmId::Sha256)?,
+        })
+    }
+
+    /// Add the next chunk of the message.
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.op.update(chunk);
+    }
+
+    /// Add the content of the memref `param` as the next chunk of the
+    /// message.
+    ///
+    /// The buffer is hashed where the client application shared it, without
+    /// copying it: the TA must not read it again to use the data the digest
+    /// covers, as the client may have changed it since.
+    pub fn update_memref<P: ParameterMemrefRead>(&mut self, param: &P) {
+        self.update(param.get_buffer());
+    }
+
+    /// Return the digest of the message.
+    pub fn finalize(self) -> Result<[u8; Self::SIZE]> {
+        let mut hash = [0u8; Self::SIZE];
+        self.op.do_final(&[], &mut hash)?;
+        Ok(hash)
+    }
+}
+
+/// Verify the ECDSA P-256 `signature` of the message made of `chunks`, with
+/// `public_key`, hashing the chunks one after another so the message is
+/// never held in memory as a whole.
+///
+/// ``` rust,no_run
+/// # use optee_utee::crypto::{EcdsaP256PublicKey, verify_signed_input};
+/// # use optee_utee::{ParameterMemrefInput, ParameterMemrefRead, Result};
+/// # fn check(key: &EcdsaP256PublicKey, signature: &[u8], params: &[ParameterMemrefInput]) -> Result<()> {
+/// verify_signed_input(key, signature, params.iter().map(|p| p.get_buffer()))
+/// # }
+/// ```
+///
+/// The chunks are only read once. A TA processing a signed payload in
+/// shared memory, e.g. a firmware update, should keep what it hashed, for
+/// example by writing each chunk to the secure storage as it goes, and only
+/// commit it once the signature is verified.
+///
+/// # Errors
+///
+/// 1) `BadParameters`: If `signature` is not [`EcdsaP256::SIGNATURE_SIZE`]
+///    bytes long, which is checked before reading the chunks.
+/// 2) `SignatureInvalid`: If the signature does not match.
+pub fn verify_signed_input<I>(
+    public_key: &EcdsaP256PublicKey,
+    signature: &[u8],
+    chunks: I,
+) -> Result<()>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    check(signature.len() == EcdsaP256::SIGNATURE_SIZE)?;
+    let mut digest = Digest::new()?;
+    for chunk in chunks {
+        digest.update(chunk.as_ref());
+    }
+    public_key.verify_digest(&digest.finalize()?, signature)
+}
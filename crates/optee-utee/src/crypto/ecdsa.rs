@@ -0,0 +1,265 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::{check, read_padded, sha256};
+use crate::secure_mem::Zeroizing;
+use crate::{
+    AlgorithmId, Asymmetric, AttributeId, AttributeMemref, AttributeValue, ElementId, ErrorKind,
+    GenericObject, OperationMode, Result, TransientObject, TransientObjectType, UsageFlag,
+};
+
+/// Size of the NIST P-256 curve in bits.
+const KEY_SIZE: usize = 256;
+/// Size of a coordinate or scalar of the curve in bytes.
+const COORDINATE_SIZE: usize = KEY_SIZE / 8;
+
+/// ECDSA key pair on the NIST P-256 curve, signing SHA-256 digests.
+///
+/// Signatures are the 64 bytes `r || s`, the big-endian scalars concatenated,
+/// public keys the 65 bytes SEC 1 uncompressed points `0x04 || x || y`.
+///
+/// To sign a message given in chunks, hash it with a
+/// [`Digest`](super::Digest) and sign the digest with
+/// [`sign_digest`](Self::sign_digest), to verify it see
+/// [`verify_signed_input`](super::verify_signed_input).
+///
+/// The private key never has to leave the TEE object: after
+/// [`restrict_usage`](Self::restrict_usage) to [`UsageFlag::SIGN`] it cannot
+/// be extracted either, while the public key stays readable.
+///
+/// ``` rust,no_run
+/// # use optee_utee::UsageFlag;
+/// # use optee_utee::crypto::EcdsaP256;
+/// # fn main() -> optee_utee::Result<()> {
+/// # let (private_key, public_key) = ([1u8; 32], [4u8; 65]);
+/// let mut key_pair = EcdsaP256::import_keypair(&private_key, &public_key)?;
+/// key_pair.restrict_usage(UsageFlag::SIGN)?;
+/// let signature = key_pair.sign(b"message")?;
+/// key_pair.export_public()?.verify(b"message", &signature)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct EcdsaP256 {
+    object: TransientObject,
+}
+
+impl EcdsaP256 {
+    /// Size of the signatures in bytes.
+    pub const SIGNATURE_SIZE: usize = 2 * COORDINATE_SIZE;
+    /// Size of the public keys in bytes.
+    pub const PUBLIC_KEY_SIZE: usize = 1 + 2 * COORDINATE_SIZE;
+
+    /// Generate a new key pair.
+    pub fn generate() -> Result<Self> {
+        let object = TransientObject::allocate(TransientObjectType::EcdsaKeypair, KEY_SIZE)?;
+        object.generate_key(KEY_SIZE, &[curve().into()])?;
+        Ok(Self { object })
+    }
+
+    /// Import the key pair of the 32 bytes big-endian scalar `private_key`
+    /// and of the uncompressed point `public_key`, which must match.
+    ///
+    /// # Errors
+    ///
+    /// `BadParameters`: If the keys do not have the size and the format
+    /// above.
+    pub fn import_keypair(private_key: &[u8], public_key: &[u8]) -> Result<Self> {
+        check(private_key.len() == COORDINATE_SIZE)?;
+        let (x, y) = split_point(public_key)?;
+        let mut object = TransientObject::allocate(TransientObjectType::EcdsaKeypair, KEY_SIZE)?;
+        object.populate(&[
+            AttributeMemref::from_ref(AttributeId::EccPrivateValue, private_key).into(),
+            AttributeMemref::from_ref(AttributeId::EccPublicValueX, x).into(),
+            AttributeMemref::from_ref(AttributeId::EccPublicValueY, y).into(),
+            curve().into(),
+        ])?;
+        Ok(Self { object })
+    }
+
+    /// Use the key pair `object`, for example one loaded from the secure
+    /// storage, which must be an ECDSA NIST P-256 key pair.
+    pub fn from_object(object: TransientObject) -> Self {
+        Self { object }
+    }
+
+    /// The object holding the key pair, e.g. to store it in a
+    /// [`PersistentObject`](crate::PersistentObject).
+    pub fn object(&self) -> &TransientObject {
+        &self.object
+    }
+
+    /// Restrict the usages of the key pair to `usage`, for example to
+    /// [`UsageFlag::SIGN`] so the private key cannot be extracted. Usages
+    /// can only be removed.
+    pub fn restrict_usage(&mut self, usage: UsageFlag) -> Result<()> {
+        self.object.restrict_usage(usage)
+    }
+
+    /// The public key, in an object verifying the signatures of the key
+    /// pair.
+    pub fn export_public(&self) -> Result<EcdsaP256PublicKey> {
+        let mut object = TransientObject::allocate(TransientObjectType::EcdsaPublicKey, KEY_SIZE)?;
+        object.copy_attribute_from(&self.object)?;
+        Ok(EcdsaP256PublicKey { object })
+    }
+
+    /// The public key, as an uncompressed point.
+    pub fn public_key(&self) -> Result<[u8; Self::PUBLIC_KEY_SIZE]> {
+        let mut point = [0u8; Self::PUBLIC_KEY_SIZE];
+        point[0] = 0x04;
+        let (x, y) = point[1..].split_at_mut(COORDINATE_SIZE);
+        read_padded(&self.object, AttributeId::EccPublicValueX, x)?;
+        read_padded(&self.object, AttributeId::EccPublicValueY, y)?;
+        Ok(point)
+    }
+
+    /// The private key, as a 32 bytes big-endian scalar wiped when dropped,
+    /// e.g. to store it and load it back with
+    /// [`import_keypair`](Self::import_keypair).
+    ///
+    /// # Errors
+    ///
+    /// `AccessDenied`: If the usages of the key pair were restricted without
+    /// [`UsageFlag::EXTRACTABLE`], which the TEE would panic on.
+    pub fn private_key(&self) -> Result<Zeroizing<[u8; COORDINATE_SIZE]>> {
+        if !self
+            .object
+            .info()?
+            .object_usage()
+            .contains(UsageFlag::EXTRACTABLE)
+        {
+            return Err(ErrorKind::AccessDenied.into());
+        }
+        let mut private_key = Zeroizing::new([0u8; COORDINATE_SIZE]);
+        read_padded(
+            &self.object,
+            AttributeId::EccPrivateValue,
+            &mut *private_key,
+        )?;
+        Ok(private_key)
+    }
+
+    /// Sign `message`.
+    pub fn sign(&self, message: &[u8]) -> Result<[u8; Self::SIGNATURE_SIZE]> {
+        self.sign_digest(&sha256(message)?)
+    }
+
+    /// Sign the SHA-256 `digest` of a message.
+    pub fn sign_digest(&self, digest: &[u8; 32]) -> Result<[u8; Self::SIGNATURE_SIZE]> {
+        let op = operation(OperationMode::Sign, &self.object)?;
+        let mut signature = [0u8; Self::SIGNATURE_SIZE];
+        op.sign_digest(&[], digest, &mut signature)?;
+        Ok(signature)
+    }
+
+    /// Verify the `signature` of `message` made with this key pair.
+    ///
+    /// # Errors
+    ///
+    /// `SignatureInvalid`: If the signature does not match.
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<()> {
+        verify(&self.object, message, signature)
+    }
+}
+
+/// ECDSA public key on the NIST P-256 curve, verifying the signatures of
+/// [`EcdsaP256`] key pairs.
+pub struct EcdsaP256PublicKey {
+    object: TransientObject,
+}
+
+impl EcdsaP256PublicKey {
+    /// Use the uncompressed point `public_key`, as returned by
+    /// [`EcdsaP256::public_key`].
+    pub fn new(public_key: &[u8]) -> Result<Self> {
+        let (x, y) = split_point(public_key)?;
+        let mut object = TransientObject::allocate(TransientObjectType::EcdsaPublicKey, KEY_SIZE)?;
+        object.populate(&[
+            AttributeMemref::from_ref(AttributeId::EccPublicValueX, x).into(),
+            AttributeMemref::from_ref(AttributeId::EccPublicValueY, y).into(),
+            curve().into(),
+        ])?;
+        Ok(Self { object })
+    }
+
+    /// Verify the `signature` of `message`.
+    ///
+    /// # Errors
+    ///
+    /// `SignatureInvalid`: If the signature does not match.
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<()> {
+        verify(&self.object, message, signature)
+    }
+
+    /// Verify the `signature` of the SHA-256 `digest` of a message.
+    ///
+    /// # Errors
+    ///
+    /// `SignatureInvalid`: If the signature does not match.
+    pub fn verify_digest(&self, digest: &[u8; 32], signature: &[u8]) -> Result<()> {
+        verify_digest(&self.object, digest, signature)
+    }
+}
+
+/// The coordinates of the uncompressed point `public_key`.
+fn split_point(public_key: &[u8]) -> Result<(&[u8], &[u8])> {
+    check(public_key.len() == EcdsaP256::PUBLIC_KEY_SIZE && public_key[0] == 0x04)?;
+    Ok(public_key[1..].split_at(COORDINATE_SIZE))
+}
+
+pub(super) fn curve() -> AttributeValue {
+    AttributeValue::from_value(AttributeId::EccCurve, ElementId::EccCurveNistP256 as u32, 0)
+}
+
+fn operation(mode: OperationMode, key: &TransientObject) -> Result<Asymmetric> {
+    let mut op = Asymmetric::allocate(AlgorithmId::EcDsaSha256, mode, KEY_SIZE)?;
+    op.set_key(key)?;
+    Ok(op)
+}
+
+fn verify(key: &TransientObject, message: &[u8], signature: &[u8]) -> Result<()> {
+    verify_digest(key, &sha256(message)?, signature)
+}
+
+fn verify_digest(key: &TransientObject, digest: &[u8; 32], signature: &[u8]) -> Result<()> {
+    check(signature.len() == EcdsaP256::SIGNATURE_SIZE)?;
+    operation(OperationMode::Verify, key)?.verify_digest(&[], digest, signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    #[test]
+    fn test_import_invalid_keypair() {
+        // Rejected before reaching the TEE
+        for (private_key, public_key) in [
+            (&[1u8; 31][..], &[4u8; 65][..]),
+            (&[1u8; 32][..], &[4u8; 64][..]),
+            // Compressed points are not supported
+            (&[1u8; 32][..], &[2u8; 65][..]),
+        ] {
+            assert_eq!(
+                EcdsaP256::import_keypair(private_key, public_key)
+                    .err()
+                    .map(|e| e.kind()),
+                Some(ErrorKind::BadParameters)
+            );
+        }
+    }
+}
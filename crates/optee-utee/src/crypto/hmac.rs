@@ -0,0 +1,141 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::{check, key_size, secret_object, sha256};
+use crate::secure_mem::Zeroize;
+use crate::{AlgorithmId, Mac, Result, TransientObject, TransientObjectType};
+
+/// Size of the blocks of SHA-256 in bytes.
+const BLOCK_SIZE: usize = 64;
+
+/// HMAC-SHA256 key.
+pub struct HmacSha256 {
+    key: TransientObject,
+    key_size: usize,
+}
+
+impl HmacSha256 {
+    /// Size of the tags in bytes.
+    pub const TAG_SIZE: usize = 32;
+
+    /// Use `key`, of any size.
+    ///
+    /// The TEE only accepts keys of 24 to 128 bytes. Shorter keys are padded
+    /// with zeros to the block size and longer keys replaced by their
+    /// digest, as HMAC does itself, which gives the same tags.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let mut block = [0u8; BLOCK_SIZE];
+        let key = match key.len() {
+            0..24 => {
+                block[..key.len()].copy_from_slice(key);
+                &block[..]
+            }
+            24..=128 => key,
+            _ => {
+                block[..Self::TAG_SIZE].copy_from_slice(&sha256(key)?);
+                &block[..Self::TAG_SIZE]
+            }
+        };
+        let object = secret_object(TransientObjectType::HmacSha256, key);
+        let key_size = key.len() * 8;
+        block.zeroize();
+        Ok(Self {
+            key: object?,
+            key_size,
+        })
+    }
+
+    /// Generate a random key of `key_size` bits, a multiple of 8 from 192
+    /// to 1024.
+    pub fn generate(key_size: usize) -> Result<Self> {
+        check((192..=1024).contains(&key_size) && key_size.is_multiple_of(8))?;
+        let key = TransientObject::allocate(TransientObjectType::HmacSha256, key_size)?;
+        key.generate_key(key_size, &[])?;
+        Ok(Self { key, key_size })
+    }
+
+    /// Use the HMAC-SHA256 key `object`, for example one loaded from the
+    /// secure storage.
+    pub fn from_object(object: TransientObject) -> Result<Self> {
+        Ok(Self {
+            key_size: key_size(&object)?,
+            key: object,
+        })
+    }
+
+    /// Compute the tag of `message`.
+    pub fn mac(&self, message: &[u8]) -> Result<[u8; Self::TAG_SIZE]> {
+        let mut context = self.start()?;
+        context.update(message);
+        context.finalize()
+    }
+
+    /// Check that `tag` is the tag of `message`, in constant time.
+    ///
+    /// # Errors
+    ///
+    /// `MacInvalid`: If it is not.
+    pub fn verify(&self, message: &[u8], tag: &[u8]) -> Result<()> {
+        let mut context = self.start()?;
+        context.update(message);
+        context.verify(tag)
+    }
+
+    /// Start computing the tag of a message given in chunks.
+    pub fn start(&self) -> Result<HmacSha256Context> {
+        let op = self.operation()?;
+        op.init(&[]);
+        Ok(HmacSha256Context { op })
+    }
+
+    /// Allocate an operation with the key, to be initialized before each
+    /// message.
+    pub(super) fn operation(&self) -> Result<Mac> {
+        let op = Mac::allocate(AlgorithmId::HmacSha256, self.key_size)?;
+        op.set_key(&self.key)?;
+        Ok(op)
+    }
+}
+
+/// Computes the tag of a message given in chunks, see
+/// [`HmacSha256::start`].
+pub struct HmacSha256Context {
+    op: Mac,
+}
+
+impl HmacSha256Context {
+    /// Add the next chunk of the message.
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.op.update(chunk);
+    }
+
+    /// Return the tag of the message.
+    pub fn finalize(self) -> Result<[u8; HmacSha256::TAG_SIZE]> {
+        let mut tag = [0u8; HmacSha256::TAG_SIZE];
+        self.op.compute_final(&[], &mut tag)?;
+        Ok(tag)
+    }
+
+    /// Check that `tag` is the tag of the message, in constant time.
+    ///
+    /// # Errors
+    ///
+    /// `MacInvalid`: If it is not.
+    pub fn verify(self, tag: &[u8]) -> Result<()> {
+        self.op.compare_final(&[], tag)
+    }
+}
@@ -0,0 +1,275 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use core::ops::RangeInclusive;
+
+use super::{HmacSha256, check};
+use crate::secure_mem::Zeroize;
+use crate::{ErrorKind, ParamIndex, Result, TaSessionBuilder, TeeParams, Uuid};
+
+/// UUID of the system pseudo TA.
+const PTA_SYSTEM_UUID: &str = "3a2f8978-5dc0-11e8-9c2d-fa7ae01bbebc";
+const PTA_SYSTEM_DERIVE_TA_UNIQUE_KEY: u32 = 1;
+/// Sizes of the keys derived by the system pseudo TA.
+const TA_UNIQUE_KEY_SIZES: RangeInclusive<usize> = 16..=32;
+const TA_UNIQUE_KEY_MAX_EXTRA_DATA: usize = 1024;
+
+/// Size of the output of HMAC-SHA256.
+const HASH_SIZE: usize = HmacSha256::TAG_SIZE;
+
+/// HKDF with HMAC-SHA256, as specified by RFC 5869.
+///
+/// The input key material is usually a shared secret, e.g. from
+/// [`X25519::diffie_hellman`](super::X25519::diffie_hellman), a provisioned
+/// secret or the [TA unique key](Self::from_ta_unique_key). Each key derived
+/// from it is bound to a distinct `info`:
+///
+/// ``` rust,no_run
+/// # use optee_utee::crypto::{AesGcm, HkdfSha256};
+/// # fn main() -> optee_utee::Result<()> {
+/// let hkdf = HkdfSha256::from_ta_unique_key(b"my-ta storage")?;
+/// let mut key = [0u8; 32];
+/// hkdf.expand(b"wallet v1", &mut key)?;
+/// let wallet_key = AesGcm::new(&key)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct HkdfSha256 {
+    prk: HmacSha256,
+}
+
+impl HkdfSha256 {
+    /// The most key material derived for one `info`, in bytes.
+    pub const MAX_OUTPUT_SIZE: usize = 255 * HASH_SIZE;
+
+    /// Extract a pseudorandom key from the input key material `ikm` and
+    /// `salt`, empty if there is none.
+    pub fn extract(salt: &[u8], ikm: &[u8]) -> Result<Self> {
+        // No salt stands for a salt of zeros, which HMAC pads an empty key to
+        let mut prk = HmacSha256::new(salt)?.mac(ikm)?;
+        let hkdf = Self::from_prk(&prk);
+        prk.zeroize();
+        hkdf
+    }
+
+    /// Use the pseudorandom key `prk`, of at least 32 bytes, skipping the
+    /// extraction.
+    pub fn from_prk(prk: &[u8]) -> Result<Self> {
+        check(prk.len() >= HASH_SIZE)?;
+        Ok(Self {
+            prk: HmacSha256::new(prk)?,
+        })
+    }
+
+    /// Extract a pseudorandom key from a 32 bytes [`ta_unique_key`] and
+    /// `salt`, so the keys derived from it are only available to this TA on
+    /// this device.
+    pub fn from_ta_unique_key(salt: &[u8]) -> Result<Self> {
+        let mut ikm = [0u8; 32];
+        ta_unique_key(&[], &mut ikm)?;
+        let hkdf = Self::extract(salt, &ikm);
+        ikm.zeroize();
+        hkdf
+    }
+
+    /// Fill `okm` with key material bound to `info`.
+    ///
+    /// # Errors
+    ///
+    /// `BadParameters`: If `okm` is larger than
+    /// [`MAX_OUTPUT_SIZE`](Self::MAX_OUTPUT_SIZE).
+    pub fn expand(&self, info: &[u8], okm: &mut [u8]) -> Result<()> {
+        let op = self.prk.operation()?;
+        expand(|message| mac(&op, message), info, okm)
+    }
+}
+
+/// Derive `output` from `password` and `salt` with PBKDF2 and HMAC-SHA256,
+/// as specified by RFC 8018, running `iterations` iterations.
+///
+/// PBKDF2 is meant for low entropy secrets such as PINs or passphrases, the
+/// more iterations the slower to brute force, each running in the TEE.
+/// Derive from high entropy secrets with [`HkdfSha256`] instead.
+///
+/// # Errors
+///
+/// `BadParameters`: If `iterations` is 0.
+pub fn pbkdf2_hmac_sha256(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    output: &mut [u8],
+) -> Result<()> {
+    check(iterations > 0)?;
+    let op = HmacSha256::new(password)?.operation()?;
+    pbkdf2(|message| mac(&op, message), salt, iterations, output)
+}
+
+/// Derive `key`, of 16 to 32 bytes, from the hardware unique key of the
+/// device, the UUID of the calling TA and `extra_data`, of at most 1024
+/// bytes, with the system pseudo TA of OP-TEE.
+///
+/// The key is the same for every call of the TA with the same extra data on
+/// the same device, and differs for other TAs and devices. It never leaves
+/// the secure world.
+///
+/// # Errors
+///
+/// `BadParameters`: If the sizes are out of range, or if the system pseudo
+/// TA fails.
+pub fn ta_unique_key(extra_data: &[u8], key: &mut [u8]) -> Result<()> {
+    check(
+        TA_UNIQUE_KEY_SIZES.contains(&key.len())
+            && extra_data.len() <= TA_UNIQUE_KEY_MAX_EXTRA_DATA,
+    )?;
+    let mut session = TaSessionBuilder::new(Uuid::parse_str(PTA_SYSTEM_UUID)?).build()?;
+    let mut params = TeeParams::new()
+        .with_memref_in(ParamIndex::Arg0, extra_data)
+        .with_memref_out(ParamIndex::Arg1, key);
+    session.invoke_command(PTA_SYSTEM_DERIVE_TA_UNIQUE_KEY, &mut params)
+}
+
+/// HMAC of the concatenation of `message` with the key of `op`.
+fn mac(op: &crate::Mac, message: &[&[u8]]) -> Result<[u8; HASH_SIZE]> {
+    op.init(&[]);
+    for chunk in message {
+        op.update(chunk);
+    }
+    let mut tag = [0u8; HASH_SIZE];
+    op.compute_final(&[], &mut tag)?;
+    Ok(tag)
+}
+
+/// HKDF-Expand, where `prf` computes the HMAC of the concatenation of its
+/// input with the pseudorandom key.
+fn expand<F>(prf: F, info: &[u8], okm: &mut [u8]) -> Result<()>
+where
+    F: Fn(&[&[u8]]) -> Result<[u8; HASH_SIZE]>,
+{
+    check(okm.len() <= HkdfSha256::MAX_OUTPUT_SIZE)?;
+    let mut block = [0u8; HASH_SIZE];
+    for (index, chunk) in okm.chunks_mut(HASH_SIZE).enumerate() {
+        // T(0) is empty
+        let previous = if index == 0 { &[][..] } else { &block[..] };
+        let next = prf(&[previous, info, &[index as u8 + 1]])?;
+        block = next;
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+    block.zeroize();
+    Ok(())
+}
+
+/// PBKDF2, where `prf` computes the HMAC of the concatenation of its input
+/// with the password.
+fn pbkdf2<F>(prf: F, salt: &[u8], iterations: u32, output: &mut [u8]) -> Result<()>
+where
+    F: Fn(&[&[u8]]) -> Result<[u8; HASH_SIZE]>,
+{
+    for (index, chunk) in output.chunks_mut(HASH_SIZE).enumerate() {
+        let index = u32::try_from(index + 1).map_err(|_| ErrorKind::BadParameters)?;
+        let mut u = prf(&[salt, &index.to_be_bytes()])?;
+        let mut block = u;
+        for _ in 1..iterations {
+            u = prf(&[&u])?;
+            block.iter_mut().zip(&u).for_each(|(b, u)| *b ^= u);
+        }
+        chunk.copy_from_slice(&block[..chunk.len()]);
+        block.zeroize();
+        u.zeroize();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    /// Software HMAC-SHA256 with `key`, standing in for the TEE.
+    fn software_prf(key: &[u8]) -> impl Fn(&[&[u8]]) -> Result<[u8; HASH_SIZE]> + '_ {
+        move |message| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+            for chunk in message {
+                mac.update(chunk);
+            }
+            Ok(mac.finalize().into_bytes().into())
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        // RFC 5869, test case 1
+        let prk = hex::decode("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5")
+            .unwrap();
+        let info = hex::decode("f0f1f2f3f4f5f6f7f8f9").unwrap();
+        let mut okm = [0u8; 42];
+        expand(software_prf(&prk), &info, &mut okm).expect("it should be ok");
+        assert_eq!(
+            hex::encode(okm),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+
+        let mut too_long = std::vec![0u8; HkdfSha256::MAX_OUTPUT_SIZE + 1];
+        assert_eq!(
+            expand(software_prf(&prk), &info, &mut too_long)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::BadParameters
+        );
+    }
+
+    #[test]
+    fn test_pbkdf2() {
+        // RFC 7914, section 11
+        let mut output = [0u8; 64];
+        pbkdf2(software_prf(b"passwd"), b"salt", 1, &mut output).expect("it should be ok");
+        assert_eq!(
+            hex::encode(output),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+             49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+
+        let mut output = [0u8; 32];
+        pbkdf2(software_prf(b"password"), b"salt", 4096, &mut output).expect("it should be ok");
+        assert_eq!(
+            hex::encode(output),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn test_invalid_sizes() {
+        // Rejected before reaching the TEE
+        let kind = |result: Result<()>| result.unwrap_err().kind();
+        assert_eq!(
+            kind(pbkdf2_hmac_sha256(b"pin", b"salt", 0, &mut [0u8; 32])),
+            ErrorKind::BadParameters
+        );
+        assert_eq!(
+            kind(ta_unique_key(&[], &mut [0u8; 64])),
+            ErrorKind::BadParameters
+        );
+        assert_eq!(
+            kind(ta_unique_key(&[0u8; 1025], &mut [0u8; 32])),
+            ErrorKind::BadParameters
+        );
+        assert!(HkdfSha256::from_prk(&[0u8; 16]).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{AesGcm, HkdfSha256, X25519, check, ecdsa, read_padded};
+use crate::secure_mem::Zeroize;
+use crate::{
+    AlgorithmId, Attribute, AttributeId, AttributeMemref, DeriveKey, Result, TransientObject,
+    TransientObjectType,
+};
+
+/// Size of the keys and shared secrets of both algorithms in bits.
+const KEY_SIZE: usize = 256;
+/// Size of a coordinate of a P-256 point, or of an X25519 key, in bytes.
+const COORDINATE_SIZE: usize = KEY_SIZE / 8;
+
+/// The algorithms of [`KeyAgreement`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyAgreementAlgorithm {
+    /// ECDH on the NIST P-256 curve, with public keys as 65 bytes SEC 1
+    /// uncompressed points `0x04 || x || y`.
+    EcdhP256,
+    /// X25519, with public keys of 32 bytes.
+    X25519,
+}
+
+impl KeyAgreementAlgorithm {
+    /// Size of the public keys in bytes.
+    pub const fn public_key_size(self) -> usize {
+        match self {
+            Self::EcdhP256 => 1 + 2 * COORDINATE_SIZE,
+            Self::X25519 => COORDINATE_SIZE,
+        }
+    }
+
+    /// Size of the shared secrets in bytes.
+    pub const fn shared_secret_size(self) -> usize {
+        COORDINATE_SIZE
+    }
+}
+
+/// Key pair agreeing on keys with a peer, for channels encrypted between the
+/// TA and its client or another TA.
+///
+/// The X25519 key pairs are [`X25519`] ones, which a `KeyAgreement` can be
+/// built from to derive AES-GCM keys. The private key stays in a transient
+/// object of the TEE core, and the
+/// keys agreed on are returned as objects too: [`derive_aes_gcm`] runs the
+/// shared secret through HKDF-SHA256 into an [`AesGcm`] key, wiping the
+/// secret and the key material from the memory of the TA once the key object
+/// is populated.
+///
+/// ``` rust,no_run
+/// # use optee_utee::crypto::{AesGcm, KeyAgreement, KeyAgreementAlgorithm};
+/// # fn main() -> optee_utee::Result<()> {
+/// # let peer_public_key = [0u8; 65];
+/// let key_pair = KeyAgreement::generate(KeyAgreementAlgorithm::EcdhP256)?;
+/// // Sent to the peer, which derives the same key from the public key it
+/// // receives in exchange
+/// let public_key = key_pair.public_key()?;
+/// let channel = key_pair.derive_aes_gcm(&peer_public_key, b"session salt", b"channel v1")?;
+/// let sealed = channel.encrypt(&[0u8; AesGcm::NONCE_SIZE], b"", b"hello")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`derive_aes_gcm`]: Self::derive_aes_gcm
+pub struct KeyAgreement {
+    key_pair: KeyPair,
+}
+
+enum KeyPair {
+    EcdhP256(TransientObject),
+    X25519(X25519),
+}
+
+impl KeyAgreement {
+    /// Generate a new key pair for `algorithm`.
+    pub fn generate(algorithm: KeyAgreementAlgorithm) -> Result<Self> {
+        let key_pair = match algorithm {
+            KeyAgreementAlgorithm::EcdhP256 => {
+                let object = TransientObject::allocate(TransientObjectType::EcdhKeypair, KEY_SIZE)?;
+                object.generate_key(KEY_SIZE, &[ecdsa::curve().into()])?;
+                KeyPair::EcdhP256(object)
+            }
+            KeyAgreementAlgorithm::X25519 => KeyPair::X25519(X25519::generate()?),
+        };
+        Ok(Self { key_pair })
+    }
+
+    /// Use the key pair `object`, for example one loaded from the secure
+    /// storage, which must be an ECDH NIST P-256 or an X25519 key pair
+    /// matching `algorithm`.
+    pub fn from_object(algorithm: KeyAgreementAlgorithm, object: TransientObject) -> Self {
+        let key_pair = match algorithm {
+            KeyAgreementAlgorithm::EcdhP256 => KeyPair::EcdhP256(object),
+            KeyAgreementAlgorithm::X25519 => KeyPair::X25519(X25519::from_object(object)),
+        };
+        Self { key_pair }
+    }
+
+    pub fn algorithm(&self) -> KeyAgreementAlgorithm {
+        match self.key_pair {
+            KeyPair::EcdhP256(_) => KeyAgreementAlgorithm::EcdhP256,
+            KeyPair::X25519(_) => KeyAgreementAlgorithm::X25519,
+        }
+    }
+
+    /// The public key, sent to the peer.
+    pub fn public_key(&self) -> Result<Vec<u8>> {
+        match &self.key_pair {
+            KeyPair::EcdhP256(object) => {
+                let mut public_key = vec![0u8; KeyAgreementAlgorithm::EcdhP256.public_key_size()];
+                public_key[0] = 0x04;
+                let (x, y) = public_key[1..].split_at_mut(COORDINATE_SIZE);
+                read_padded(object, AttributeId::EccPublicValueX, x)?;
+                read_padded(object, AttributeId::EccPublicValueY, y)?;
+                Ok(public_key)
+            }
+            KeyPair::X25519(key_pair) => Ok(key_pair.public_key()?.to_vec()),
+        }
+    }
+
+    /// Compute the secret shared with the peer whose public key is
+    /// `peer_public_key`, returned as a generic secret object.
+    ///
+    /// The shared secret should not be used as a key directly but passed
+    /// through a key derivation function, see
+    /// [`derive_aes_gcm`](Self::derive_aes_gcm).
+    ///
+    /// # Errors
+    ///
+    /// `BadParameters`: If `peer_public_key` does not have the size and the
+    /// format of the public keys of the algorithm.
+    pub fn derive_secret(&self, peer_public_key: &[u8]) -> Result<TransientObject> {
+        let object = match &self.key_pair {
+            KeyPair::EcdhP256(object) => object,
+            KeyPair::X25519(key_pair) => return key_pair.derive_secret(peer_public_key),
+        };
+        let peer = ecdh_peer_attributes(peer_public_key)?;
+        let mut op = DeriveKey::allocate(AlgorithmId::EcDhDeriveSharedSecret, KEY_SIZE)?;
+        op.set_key(object)?;
+        let mut secret = TransientObject::allocate(TransientObjectType::GenericSecret, KEY_SIZE)?;
+        op.derive(&peer, &mut secret);
+        Ok(secret)
+    }
+
+    /// Agree on a 256-bit AES-GCM key with the peer whose public key is
+    /// `peer_public_key`, deriving it from the shared secret with
+    /// HKDF-SHA256, `salt` and `info`.
+    ///
+    /// Both sides must use the same `salt` and `info`, e.g. a transcript of
+    /// the public keys exchanged and a label of the protocol, and a
+    /// different `info` for each direction of a channel whose sides both
+    /// start their nonces at zero.
+    ///
+    /// # Errors
+    ///
+    /// `BadParameters`: If `peer_public_key` does not have the size and the
+    /// format of the public keys of the algorithm.
+    pub fn derive_aes_gcm(
+        &self,
+        peer_public_key: &[u8],
+        salt: &[u8],
+        info: &[u8],
+    ) -> Result<AesGcm> {
+        let secret = self.derive_secret(peer_public_key)?;
+        let mut shared = [0u8; COORDINATE_SIZE];
+        let mut key = [0u8; KEY_SIZE / 8];
+        let result = read_padded(&secret, AttributeId::SecretValue, &mut shared)
+            .and_then(|_| HkdfSha256::extract(salt, &shared))
+            .and_then(|hkdf| hkdf.expand(info, &mut key))
+            .and_then(|_| AesGcm::new(&key));
+        shared.zeroize();
+        key.zeroize();
+        result
+    }
+}
+
+impl From<X25519> for KeyAgreement {
+    fn from(key_pair: X25519) -> Self {
+        Self {
+            key_pair: KeyPair::X25519(key_pair),
+        }
+    }
+}
+
+/// The attributes of the P-256 public key of the peer for the derivation.
+fn ecdh_peer_attributes(peer_public_key: &[u8]) -> Result<Vec<Attribute>> {
+    check(peer_public_key.len() == KeyAgreementAlgorithm::EcdhP256.public_key_size())?;
+    check(peer_public_key[0] == 0x04)?;
+    let (x, y) = peer_public_key[1..].split_at(COORDINATE_SIZE);
+    Ok(vec![
+        AttributeMemref::from_ref(AttributeId::EccPublicValueX, x).into(),
+        AttributeMemref::from_ref(AttributeId::EccPublicValueY, y).into(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(KeyAgreementAlgorithm::EcdhP256.public_key_size(), 65);
+        assert_eq!(KeyAgreementAlgorithm::X25519.public_key_size(), 32);
+        assert_eq!(KeyAgreementAlgorithm::EcdhP256.shared_secret_size(), 32);
+    }
+
+    #[test]
+    fn test_from_x25519() {
+        let key_pair = KeyAgreement::from(X25519::from_object(TransientObject::null_object()));
+        assert_eq!(key_pair.algorithm(), KeyAgreementAlgorithm::X25519);
+    }
+
+    #[test]
+    fn test_invalid_peer_key() {
+        // Rejected before reaching the TEE
+        for (algorithm, peer_public_key) in [
+            (KeyAgreementAlgorithm::EcdhP256, &[4u8; 64][..]),
+            // Compressed points are not supported
+            (KeyAgreementAlgorithm::EcdhP256, &[2u8; 65][..]),
+            (KeyAgreementAlgorithm::X25519, &[9u8; 33][..]),
+        ] {
+            let key_pair = KeyAgreement::from_object(algorithm, TransientObject::null_object());
+            assert_eq!(
+                key_pair
+                    .derive_aes_gcm(peer_public_key, b"", b"")
+                    .err()
+                    .map(|e| e.kind()),
+                Some(ErrorKind::BadParameters)
+            );
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! High-level cryptography on top of the TEE cryptographic operations.
+//!
+//! The types of this module wrap the operations of [`crypto_op`](crate::crypto_op)
+//! for the common algorithms, so a TA does not need a software crypto
+//! library: the computations run in the OP-TEE core, which may offload them
+//! to a hardware accelerator, and the keys are kept in transient objects
+//! rather than in the memory of the TA.
+//!
+//! - [`AesGcm`]: authenticated encryption with AES-GCM.
+//! - [`HmacSha256`]: message authentication with HMAC-SHA256.
+//! - [`EcdsaP256`] and [`EcdsaP256PublicKey`]: signatures with ECDSA on the
+//!   NIST P-256 curve and SHA-256, with key pairs generated or imported into
+//!   TEE objects whose usages can be restricted.
+//! - [`X25519`]: key agreement with X25519.
+//! - [`KeyAgreement`]: key agreement with ECDH on P-256 or X25519, deriving
+//!   AES-GCM keys for encrypted channels.
+//! - [`Digest`]: SHA-256 digests of messages given in chunks, and
+//!   [`verify_signed_input`] verifying their signature without holding them
+//!   in memory.
+//! - [`HkdfSha256`] and [`pbkdf2_hmac_sha256`]: key derivation, from secrets
+//!   or from the hardware unique key of the device with [`ta_unique_key`].
+//! - `Sealed`: values only this TA on this device can decrypt, with the
+//!   `sealed` feature.
+//!
+//! Each of them has one-shot functions taking the whole message, and
+//! streaming contexts for messages processed in chunks:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::crypto::{AesGcm, HmacSha256};
+//! # fn main() -> optee_utee::Result<()> {
+//! let key = AesGcm::generate(256)?;
+//! let nonce = [0u8; AesGcm::NONCE_SIZE];
+//! let sealed = key.encrypt(&nonce, b"header", b"secret")?;
+//! assert_eq!(key.decrypt(&nonce, b"header", &sealed)?, b"secret");
+//!
+//! let mac_key = HmacSha256::new(&[0x0b; 32])?;
+//! let mut mac = mac_key.start()?;
+//! mac.update(b"first chunk, ");
+//! mac.update(b"second chunk");
+//! let tag = mac.finalize()?;
+//! mac_key.verify(b"first chunk, second chunk", &tag)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Key sizes are in bits, as in the rest of the crate, lengths of buffers in
+//! bytes. Inputs of the wrong length fail with
+//! [`BadParameters`](crate::ErrorKind::BadParameters) instead of panicking
+//! in the TEE.
+
+use crate::{
+    AttributeId, AttributeMemref, ErrorKind, GenericObject, Result, TransientObject,
+    TransientObjectType,
+};
+
+mod aes_gcm;
+mod digest;
+mod ecdsa;
+mod hmac;
+mod kdf;
+mod key_agreement;
+#[cfg(feature = "sealed")]
+mod sealed;
+mod x25519;
+
+pub use aes_gcm::{AesGcm, AesGcmDecryptor, AesGcmEncryptor};
+pub use digest::{Digest, verify_signed_input};
+pub use ecdsa::{EcdsaP256, EcdsaP256PublicKey};
+pub use hmac::{HmacSha256, HmacSha256Context};
+pub use kdf::{HkdfSha256, pbkdf2_hmac_sha256, ta_unique_key};
+pub use key_agreement::{KeyAgreement, KeyAgreementAlgorithm};
+#[cfg(feature = "sealed")]
+pub use sealed::Sealed;
+pub use x25519::X25519;
+
+/// Create a transient object of `object_type` holding the secret `key`.
+fn secret_object(object_type: TransientObjectType, key: &[u8]) -> Result<TransientObject> {
+    let mut object = TransientObject::allocate(object_type, key.len() * 8)?;
+    object.populate(&[AttributeMemref::from_ref(AttributeId::SecretValue, key).into()])?;
+    Ok(object)
+}
+
+/// The size in bits of the key held by `object`.
+fn key_size<T: GenericObject>(object: &T) -> Result<usize> {
+    Ok(object.info()?.object_size())
+}
+
+/// Fail with `BadParameters` unless `valid`.
+fn check(valid: bool) -> Result<()> {
+    if valid {
+        Ok(())
+    } else {
+        Err(ErrorKind::BadParameters.into())
+    }
+}
+
+/// Read the attribute `id` of `object` into `buffer`, left-padded with zeros
+/// as big numbers are returned without their leading zeros.
+fn read_padded<T: GenericObject>(object: &T, id: AttributeId, buffer: &mut [u8]) -> Result<()> {
+    let size = object.ref_attribute(id, buffer)?;
+    let padding = buffer.len() - size;
+    buffer.copy_within(..size, padding);
+    buffer[..padding].fill(0);
+    Ok(())
+}
+
+/// The SHA-256 digest of `message`.
+fn sha256(message: &[u8]) -> Result<[u8; Digest::SIZE]> {
+    let mut digest = Digest::new()?;
+    digest.update(message);
+    digest.finalize()
+}
@@ -0,0 +1,208 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use super::{AesGcm, ta_unique_key};
+use crate::dispatch::{Decode, Encode, Json};
+use crate::secure_mem::Zeroize;
+use crate::{ErrorKind, Random, Result};
+
+/// Marks a sealed blob.
+const MAGIC: [u8; 2] = *b"SL";
+/// Version of the blob layout: magic, layout version, nonce, then the
+/// AES-256-GCM encrypted value followed by the tag.
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+/// Extra data of the TA unique key the sealing key is, so it differs from
+/// the keys the TA derives for other purposes.
+const KEY_CONTEXT: &[u8] = b"optee_utee::crypto::Sealed v1";
+
+/// A value of type `T` sealed to the TA and the device.
+///
+/// The value is encoded as JSON and encrypted with AES-256-GCM under a
+/// [`ta_unique_key`], derived from the hardware unique key of the device and
+/// the UUID of the TA. The blob can be stored anywhere, e.g. handed to the
+/// normal world for a backup, but only the same TA on the same device can
+/// unseal it, and any modification of the blob is detected.
+///
+/// A `label` is authenticated along with the value and must be given again
+/// to unseal it, so a blob sealed for one purpose cannot be substituted for
+/// another:
+///
+/// ``` rust,no_run
+/// # use optee_utee::crypto::Sealed;
+/// # fn main() -> optee_utee::Result<()> {
+/// let sealed = Sealed::seal(b"wallet", &vec![1u8, 2, 3])?;
+/// let blob = sealed.into_bytes();
+///
+/// // Later, e.g. after the blob was restored from a backup
+/// let seed: Vec<u8> = Sealed::from_bytes(blob).unseal(b"wallet")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Sealed<T> {
+    blob: Vec<u8>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Sealed<T> {
+    /// Use `blob`, returned by [`into_bytes`](Self::into_bytes) earlier.
+    pub fn from_bytes(blob: Vec<u8>) -> Self {
+        Self {
+            blob,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The sealed blob.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.blob
+    }
+
+    /// Return the sealed blob.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.blob
+    }
+}
+
+impl<T: Serialize> Sealed<T> {
+    /// Seal `value` with `label`.
+    ///
+    /// # Errors
+    ///
+    /// 1) `BadFormat`: If `value` cannot be encoded.
+    /// 2) If the system pseudo TA deriving the key is not available.
+    pub fn seal(label: &[u8], value: &T) -> Result<Self> {
+        let mut plaintext = <Json as Encode<T>>::encode(value)?;
+        let mut nonce = [0u8; AesGcm::NONCE_SIZE];
+        Random::generate(&mut nonce);
+        let header = header();
+        let sealed = key()?.encrypt(&nonce, &aad(&header, label), &plaintext);
+        plaintext.zeroize();
+        let sealed = sealed?;
+
+        let mut blob = Vec::with_capacity(HEADER_LEN + nonce.len() + sealed.len());
+        blob.extend_from_slice(&header);
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&sealed);
+        Ok(Self::from_bytes(blob))
+    }
+}
+
+impl<T: DeserializeOwned> Sealed<T> {
+    /// Unseal the value sealed with `label`.
+    ///
+    /// # Errors
+    ///
+    /// 1) `BadFormat`: If the blob is not a sealed blob, was sealed by a
+    ///    newer, incompatible layout, or the value cannot be decoded as a `T`.
+    /// 2) `MacInvalid`: If the blob has been modified, the label differs, or
+    ///    it was sealed by another TA or on another device.
+    pub fn unseal(&self, label: &[u8]) -> Result<T> {
+        let (header, nonce, sealed) = split(&self.blob)?;
+        let mut plaintext = key()?.decrypt(nonce, &aad(header, label), sealed)?;
+        let value = <Json as Decode<T>>::decode(&plaintext);
+        plaintext.zeroize();
+        value
+    }
+}
+
+impl<T> Clone for Sealed<T> {
+    fn clone(&self) -> Self {
+        Self::from_bytes(self.blob.clone())
+    }
+}
+
+impl<T> fmt::Debug for Sealed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sealed")
+            .field("len", &self.blob.len())
+            .finish()
+    }
+}
+
+fn header() -> [u8; HEADER_LEN] {
+    [MAGIC[0], MAGIC[1], FORMAT_VERSION]
+}
+
+/// The additional data of the encryption: the header, so it cannot be
+/// changed, and the label.
+fn aad(header: &[u8], label: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(header.len() + label.len());
+    aad.extend_from_slice(header);
+    aad.extend_from_slice(label);
+    aad
+}
+
+/// Split `blob` into its header, nonce and encrypted value.
+fn split(blob: &[u8]) -> Result<(&[u8], &[u8], &[u8])> {
+    if blob.len() < HEADER_LEN + AesGcm::NONCE_SIZE + AesGcm::TAG_SIZE
+        || blob[..MAGIC.len()] != MAGIC
+        || blob[MAGIC.len()] != FORMAT_VERSION
+    {
+        return Err(ErrorKind::BadFormat.into());
+    }
+    let (header, rest) = blob.split_at(HEADER_LEN);
+    let (nonce, sealed) = rest.split_at(AesGcm::NONCE_SIZE);
+    Ok((header, nonce, sealed))
+}
+
+fn key() -> Result<AesGcm> {
+    let mut key = [0u8; 32];
+    ta_unique_key(KEY_CONTEXT, &mut key)?;
+    let aes = AesGcm::new(&key);
+    key.zeroize();
+    aes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let mut blob = header().to_vec();
+        blob.extend_from_slice(&[1u8; AesGcm::NONCE_SIZE]);
+        blob.extend_from_slice(&[2u8; AesGcm::TAG_SIZE + 5]);
+        let (header, nonce, sealed) = split(&blob).expect("it should be ok");
+        assert_eq!(header, b"SL\x01");
+        assert_eq!(nonce, [1u8; AesGcm::NONCE_SIZE]);
+        assert_eq!(sealed, [2u8; AesGcm::TAG_SIZE + 5]);
+
+        let kind = |blob: &[u8]| split(blob).unwrap_err().kind();
+        assert_eq!(kind(&blob[..HEADER_LEN + 8]), ErrorKind::BadFormat);
+        blob[2] = FORMAT_VERSION + 1;
+        assert_eq!(kind(&blob), ErrorKind::BadFormat);
+        blob[2] = FORMAT_VERSION;
+        blob[0] = b'K';
+        assert_eq!(kind(&blob), ErrorKind::BadFormat);
+    }
+
+    #[test]
+    fn test_unseal_rejects_other_blobs() {
+        // Rejected before deriving the key
+        let sealed: Sealed<u32> = Sealed::from_bytes(b"KV\x01 not a sealed blob".to_vec());
+        assert_eq!(sealed.unseal(b"").unwrap_err().kind(), ErrorKind::BadFormat);
+    }
+}
@@ -0,0 +1,102 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::check;
+use crate::{
+    AlgorithmId, AttributeId, AttributeMemref, DeriveKey, GenericObject, Result, TransientObject,
+    TransientObjectType,
+};
+
+/// Size of the keys and shared secrets in bits.
+const KEY_SIZE: usize = 256;
+
+/// X25519 key pair, agreeing on a shared secret with a peer.
+///
+/// The shared secret should not be used as a key directly but passed through
+/// a key derivation function along with the public keys.
+pub struct X25519 {
+    object: TransientObject,
+}
+
+impl X25519 {
+    /// Size of the public keys and shared secrets in bytes.
+    pub const SIZE: usize = KEY_SIZE / 8;
+
+    /// Generate a new key pair.
+    pub fn generate() -> Result<Self> {
+        let object = TransientObject::allocate(TransientObjectType::X25519Keypair, KEY_SIZE)?;
+        object.generate_key(KEY_SIZE, &[])?;
+        Ok(Self { object })
+    }
+
+    /// Use the key pair `object`, for example one loaded from the secure
+    /// storage, which must be an X25519 key pair.
+    pub fn from_object(object: TransientObject) -> Self {
+        Self { object }
+    }
+
+    /// The public key, sent to the peer.
+    pub fn public_key(&self) -> Result<[u8; Self::SIZE]> {
+        let mut public_key = [0u8; Self::SIZE];
+        let size = self
+            .object
+            .ref_attribute(AttributeId::X25519PublicValue, &mut public_key)?;
+        check(size == Self::SIZE)?;
+        Ok(public_key)
+    }
+
+    /// Compute the secret shared with the peer whose public key is
+    /// `peer_public_key`.
+    pub fn diffie_hellman(&self, peer_public_key: &[u8]) -> Result<[u8; Self::SIZE]> {
+        let secret = self.derive_secret(peer_public_key)?;
+        let mut shared = [0u8; Self::SIZE];
+        let size = secret.ref_attribute(AttributeId::SecretValue, &mut shared)?;
+        check(size == Self::SIZE)?;
+        Ok(shared)
+    }
+
+    /// Compute the secret shared with the peer whose public key is
+    /// `peer_public_key`, returned as a generic secret object so it does not
+    /// need to be copied into the memory of the TA.
+    pub fn derive_secret(&self, peer_public_key: &[u8]) -> Result<TransientObject> {
+        check(peer_public_key.len() == Self::SIZE)?;
+        let mut op = DeriveKey::allocate(AlgorithmId::X25519, KEY_SIZE)?;
+        op.set_key(&self.object)?;
+        let mut secret = TransientObject::allocate(TransientObjectType::GenericSecret, KEY_SIZE)?;
+        op.derive(
+            &[AttributeMemref::from_ref(AttributeId::X25519PublicValue, peer_public_key).into()],
+            &mut secret,
+        );
+        Ok(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    #[test]
+    fn test_invalid_peer_key() {
+        // Rejected before reaching the TEE
+        let key = X25519::from_object(TransientObject::null_object());
+        assert_eq!(
+            key.diffie_hellman(&[9u8; 31]).err().map(|e| e.kind()),
+            Some(ErrorKind::BadParameters)
+        );
+    }
+}
@@ -20,7 +20,8 @@ use core::{mem, ptr};
 
 use optee_utee_sys as raw;
 
-use crate::{Attribute, Error, GenericObject, Result, TransientObject};
+use crate::fault_injection::{self, FaultPoint};
+use crate::{Attribute, Error, ErrorKind, GenericObject, Result, TransientObject};
 
 /// Specify one of the available cryptographic operations.
 #[repr(u32)]
@@ -627,6 +628,7 @@ impl Cipher {
     /// 3) Hardware or cryptographic algorithm failure.
     /// 4) If the Implementation detects any other error.
     pub fn update(&self, src: &[u8], dest: &mut [u8]) -> Result<usize> {
+        let corrupt = fault_injection::check(FaultPoint::Cipher)?;
         let mut dest_size: usize = dest.len();
         match unsafe {
             raw::TEE_CipherUpdate(
@@ -637,7 +639,12 @@ impl Cipher {
                 &mut dest_size,
             )
         } {
-            raw::TEE_SUCCESS => Ok(dest_size),
+            raw::TEE_SUCCESS => {
+                if corrupt {
+                    fault_injection::corrupt(&mut dest[..dest_size]);
+                }
+                Ok(dest_size)
+            }
             code => Err(Error::from_raw_error(code)),
         }
     }
@@ -661,6 +668,7 @@ impl Cipher {
     /// 3) Hardware or cryptographic algorithm failure.
     /// 4) If the Implementation detects any other error.
     pub fn do_final(&self, src: &[u8], dest: &mut [u8]) -> Result<usize> {
+        let corrupt = fault_injection::check(FaultPoint::Cipher)?;
         let mut dest_size: usize = dest.len();
         match unsafe {
             raw::TEE_CipherDoFinal(
@@ -671,7 +679,12 @@ impl Cipher {
                 &mut dest_size,
             )
         } {
-            raw::TEE_SUCCESS => Ok(dest_size),
+            raw::TEE_SUCCESS => {
+                if corrupt {
+                    fault_injection::corrupt(&mut dest[..dest_size]);
+                }
+                Ok(dest_size)
+            }
             code => Err(Error::from_raw_error(code)),
         }
     }
@@ -1070,6 +1083,7 @@ impl AE {
     /// 5) Hardware or cryptographic algorithm failure.
     /// 6) If the Implementation detects any other error.
     pub fn update(&self, src: &[u8], dest: &mut [u8]) -> Result<usize> {
+        let corrupt = fault_injection::check(FaultPoint::AE)?;
         let mut dest_size: usize = dest.len();
         match unsafe {
             raw::TEE_AEUpdate(
@@ -1080,7 +1094,12 @@ impl AE {
                 &mut dest_size,
             )
         } {
-            raw::TEE_SUCCESS => Ok(dest_size),
+            raw::TEE_SUCCESS => {
+                if corrupt {
+                    fault_injection::corrupt(&mut dest[..dest_size]);
+                }
+                Ok(dest_size)
+            }
             code => Err(Error::from_raw_error(code)),
         }
     }
@@ -1153,6 +1172,7 @@ impl AE {
         dest: &mut [u8],
         tag: &mut [u8],
     ) -> Result<(usize, usize)> {
+        let corrupt = fault_injection::check(FaultPoint::AE)?;
         let mut dest_size: usize = dest.len();
         let mut tag_size: usize = tag.len();
         match unsafe {
@@ -1166,7 +1186,12 @@ impl AE {
                 &mut tag_size,
             )
         } {
-            raw::TEE_SUCCESS => Ok((dest_size, tag_size)),
+            raw::TEE_SUCCESS => {
+                if corrupt {
+                    fault_injection::corrupt(&mut tag[..tag_size]);
+                }
+                Ok((dest_size, tag_size))
+            }
             code => Err(Error::from_raw_error(code)),
         }
     }
@@ -1195,6 +1220,7 @@ impl AE {
     /// 4) Hardware or cryptographic algorithm failure.
     /// 5) If the Implementation detects any other error.
     pub fn decrypt_final(&self, src: &[u8], dest: &mut [u8], tag: &[u8]) -> Result<usize> {
+        let corrupt = fault_injection::check(FaultPoint::AE)?;
         let mut dest_size: usize = dest.len();
         match unsafe {
             raw::TEE_AEDecryptFinal(
@@ -1207,6 +1233,7 @@ impl AE {
                 tag.len(),
             )
         } {
+            raw::TEE_SUCCESS if corrupt => Err(Error::new(ErrorKind::MacInvalid)),
             raw::TEE_SUCCESS => Ok(dest_size),
             code => Err(Error::from_raw_error(code)),
         }
@@ -1319,6 +1346,7 @@ impl Asymmetric {
     // short, and example acipher utilizes this feature!
     // Define this function as unsafe because we need to return Ok for short buffer error.
     pub fn encrypt(&self, params: &[Attribute], src: &[u8]) -> Result<Vec<u8>> {
+        let corrupt = fault_injection::check(FaultPoint::Asymmetric)?;
         let p: Vec<raw::TEE_Attribute> = params.iter().map(|p| p.raw()).collect();
         let mut res_size: usize = self.info().key_size() as usize;
         let mut res_vec: Vec<u8> = vec![0u8; res_size];
@@ -1335,6 +1363,9 @@ impl Asymmetric {
         } {
             raw::TEE_SUCCESS => {
                 res_vec.truncate(res_size);
+                if corrupt {
+                    fault_injection::corrupt(&mut res_vec);
+                }
                 Ok(res_vec)
             }
             code => Err(Error::from_raw_error(code)),
@@ -1362,6 +1393,7 @@ impl Asymmetric {
     /// 3) Hardware or cryptographic algorithm failure.
     /// 4) If the Implementation detects any other error.
     pub fn decrypt(&self, params: &[Attribute], src: &[u8]) -> Result<Vec<u8>> {
+        let corrupt = fault_injection::check(FaultPoint::Asymmetric)?;
         let p: Vec<raw::TEE_Attribute> = params.iter().map(|p| p.raw()).collect();
         let mut res_size: usize = self.info().key_size() as usize;
         let mut res_vec: Vec<u8> = vec![0u8; res_size];
@@ -1378,6 +1410,9 @@ impl Asymmetric {
         } {
             raw::TEE_SUCCESS => {
                 res_vec.truncate(res_size);
+                if corrupt {
+                    fault_injection::corrupt(&mut res_vec);
+                }
                 Ok(res_vec)
             }
             code => Err(Error::from_raw_error(code)),
@@ -1411,6 +1446,7 @@ impl Asymmetric {
         digest: &[u8],
         signature: &mut [u8],
     ) -> Result<usize> {
+        let corrupt = fault_injection::check(FaultPoint::Asymmetric)?;
         let p: Vec<raw::TEE_Attribute> = params.iter().map(|p| p.raw()).collect();
         let mut signature_size: usize = signature.len();
         match unsafe {
@@ -1424,7 +1460,12 @@ impl Asymmetric {
                 &mut signature_size,
             )
         } {
-            raw::TEE_SUCCESS => Ok(signature_size),
+            raw::TEE_SUCCESS => {
+                if corrupt {
+                    fault_injection::corrupt(&mut signature[..signature_size]);
+                }
+                Ok(signature_size)
+            }
             code => Err(Error::from_raw_error(code)),
         }
     }
@@ -1456,6 +1497,7 @@ impl Asymmetric {
         digest: &[u8],
         signature: &[u8],
     ) -> Result<()> {
+        let corrupt = fault_injection::check(FaultPoint::Asymmetric)?;
         let p: Vec<raw::TEE_Attribute> = params.iter().map(|p| p.raw()).collect();
         match unsafe {
             raw::TEE_AsymmetricVerifyDigest(
@@ -1468,6 +1510,7 @@ impl Asymmetric {
                 signature.len(),
             )
         } {
+            raw::TEE_SUCCESS if corrupt => Err(Error::new(ErrorKind::SignatureInvalid)),
             raw::TEE_SUCCESS => Ok(()),
             code => Err(Error::from_raw_error(code)),
         }
@@ -1670,6 +1713,14 @@ impl Random {
     /// 1) Hardware or cryptographic algorithm failure.
     /// 2) If the Implementation detects any other error.
     pub fn generate(res_buffer: &mut [u8]) {
+        match fault_injection::check(FaultPoint::Random) {
+            Ok(false) => {}
+            Ok(true) => {
+                res_buffer.fill(0);
+                return;
+            }
+            Err(e) => panic!("TEE_GenerateRandom failed: {:?}", e),
+        }
         unsafe {
             raw::TEE_GenerateRandom(res_buffer.as_mut_ptr() as _, res_buffer.len() as _);
         }
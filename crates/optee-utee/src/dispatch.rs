@@ -0,0 +1,417 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Typed command dispatch.
+//!
+//! Most TAs decode a request from an input memref, match on the command ID,
+//! and encode a response into an output memref. With
+//! [`TaCommand`](macro@crate::TaCommand) and [`ta_dispatch`](crate::ta_dispatch)
+//! this is derived from an enum describing the commands:
+//!
+//! ```rust,ignore
+//! use optee_utee::dispatch::Json;
+//! use optee_utee::prelude::*;
+//!
+//! #[derive(TaCommand)]
+//! #[ta_command(codec = Json)]
+//! enum Command {
+//!     // input `HelloInput`, output `HelloOutput`, command ID 0
+//!     #[ta_command(output = HelloOutput)]
+//!     Hello(HelloInput),
+//!     // no input, no output, command ID 1
+//!     Reset,
+//! }
+//!
+//! // The derive generates the `CommandHandler` trait with one method per
+//! // command, named after the variant in snake case.
+//! #[derive(Default)]
+//! struct Session;
+//!
+//! #[ta_dispatch]
+//! impl CommandHandler for Session {
+//!     fn hello(&mut self, input: HelloInput) -> Result<HelloOutput> { ... }
+//!     fn reset(&mut self) -> Result<()> { ... }
+//! }
+//! ```
+//!
+//! `#[ta_dispatch]` generates the `#[ta_invoke_command]` entry point, which
+//! uses the session context of type `Session` (see `#[ta_open_session]`), or a
+//! fresh `Session::default()` per invocation with `#[ta_dispatch(stateless)]`.
+//!
+//! The client application passes the encoded input in parameter 0 and
+//! receives the encoded output in parameter 1, both may be `None` when empty.
+//! Parameters 2 and 3 must be `None`. An output that does not fit is
+//! reported with the GlobalPlatform short-buffer convention, see
+//! [`OutputWriter`](crate::OutputWriter).
+//!
+//! The command ID of a variant is its position in the enum, or the value of
+//! `#[ta_command(id = ...)]`, which accepts any constant expression castable
+//! to `u32` such as a variant of a command enum shared with the host in the
+//! proto crate. Unknown command IDs fail with `BadParameters`.
+
+use crate::arena::{self, ArenaWriter};
+use crate::parameter::ParameterAny;
+use crate::{ErrorKind, OutputWriter, ParameterMemrefRead, Result};
+use alloc::vec::Vec;
+
+/// An enum of commands, implemented by
+/// [`#[derive(TaCommand)]`](macro@crate::TaCommand).
+pub trait TaCommand: Sized {
+    /// Decodes the command with ID `cmd_id` and its input from `input`.
+    fn decode(cmd_id: u32, input: &[u8]) -> Result<Self>;
+
+    /// Returns the command ID of `self`.
+    fn id(&self) -> u32;
+}
+
+/// Decodes values of type `T` from the bytes sent by the client application.
+pub trait Decode<T> {
+    fn decode(bytes: &[u8]) -> Result<T>;
+}
+
+/// Decodes values of type `T` borrowing from the bytes sent by the client
+/// application, see
+/// [`MemrefSnapshot::deserialize_borrowed`](crate::MemrefSnapshot::deserialize_borrowed).
+pub trait DecodeBorrowed<'de, T> {
+    fn decode_borrowed(bytes: &'de [u8]) -> Result<T>;
+}
+
+/// Encodes values of type `T` into the bytes returned to the client
+/// application.
+pub trait Encode<T> {
+    fn encode(value: &T) -> Result<Vec<u8>>;
+
+    /// Encodes `value` at the end of the command arena, see
+    /// [`encode_output`]. Codecs able to write their output piecewise
+    /// override it to avoid the temporary buffer of [`encode`](Self::encode).
+    ///
+    /// # Errors
+    ///
+    /// `OutOfMemory`: If the encoded value does not fit in the arena.
+    fn encode_into(value: &T, writer: &mut ArenaWriter<'_>) -> Result<()> {
+        writer.write_bytes(&Self::encode(value)?)
+    }
+}
+
+/// The default codec, passing raw bytes through.
+///
+/// Supports `Vec<u8>` and `()`, which must be empty on input, and decoding
+/// borrowed `&[u8]`.
+pub struct Bytes;
+
+impl<'de> DecodeBorrowed<'de, &'de [u8]> for Bytes {
+    fn decode_borrowed(bytes: &'de [u8]) -> Result<&'de [u8]> {
+        Ok(bytes)
+    }
+}
+
+impl Decode<Vec<u8>> for Bytes {
+    fn decode(bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl Decode<()> for Bytes {
+    fn decode(bytes: &[u8]) -> Result<()> {
+        if !bytes.is_empty() {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        Ok(())
+    }
+}
+
+impl Encode<Vec<u8>> for Bytes {
+    fn encode(value: &Vec<u8>) -> Result<Vec<u8>> {
+        Ok(value.clone())
+    }
+
+    fn encode_into(value: &Vec<u8>, writer: &mut ArenaWriter<'_>) -> Result<()> {
+        writer.write_bytes(value)
+    }
+}
+
+impl Encode<()> for Bytes {
+    fn encode(_value: &()) -> Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    fn encode_into(_value: &(), _writer: &mut ArenaWriter<'_>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// JSON codec for any type implementing `serde::Serialize` and
+/// `serde::Deserialize`. Input that fails to decode is rejected with
+/// `BadFormat`.
+#[cfg(feature = "json")]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> Decode<T> for Json {
+    fn decode(bytes: &[u8]) -> Result<T> {
+        serde_json::from_slice(bytes).map_err(|_| ErrorKind::BadFormat.into())
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'de, T: serde::Deserialize<'de>> DecodeBorrowed<'de, T> for Json {
+    fn decode_borrowed(bytes: &'de [u8]) -> Result<T> {
+        serde_json::from_slice(bytes).map_err(|_| ErrorKind::BadFormat.into())
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: serde::Serialize> Encode<T> for Json {
+    fn encode(value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|_| ErrorKind::BadFormat.into())
+    }
+
+    #[cfg(feature = "std")]
+    fn encode_into(value: &T, writer: &mut ArenaWriter<'_>) -> Result<()> {
+        serde_json::to_writer(writer, value).map_err(|e| {
+            if e.is_io() {
+                ErrorKind::OutOfMemory.into()
+            } else {
+                ErrorKind::BadFormat.into()
+            }
+        })
+    }
+}
+
+/// Returns the encoded input carried by `param`, empty for `None`.
+pub fn input<'p>(param: &'p ParameterAny<'_>) -> Result<&'p [u8]> {
+    match param {
+        ParameterAny::None => Ok(&[]),
+        ParameterAny::MemrefInput(memref) => Ok(memref.get_buffer()),
+        _ => Err(ErrorKind::BadParameters.into()),
+    }
+}
+
+/// Encodes `value` with the codec `C` into `param`, as [`output`] does.
+///
+/// The value is encoded in the command arena, see [`arena::scope`], so the
+/// encoding does not allocate from the TA heap. It falls back to
+/// [`Encode::encode`] if the value does not fit in the arena or the arena is
+/// in use, e.g. when called from within `arena::scope`.
+pub fn encode_output<C: Encode<T>, T>(param: &mut ParameterAny<'_>, value: &T) -> Result<()> {
+    encode_with::<C, T, _>(value, |bytes| output(param, bytes))
+}
+
+/// Encodes `value` with the codec `C` and passes the encoding to `f`, see
+/// [`encode_output`].
+pub(crate) fn encode_with<C: Encode<T>, T, R>(
+    value: &T,
+    mut f: impl FnMut(&[u8]) -> Result<R>,
+) -> Result<R> {
+    let encoded = arena::scope(|arena| {
+        let mut writer = arena.writer()?;
+        match C::encode_into(value, &mut writer) {
+            Ok(()) => f(writer.finish()).map(Some),
+            Err(e) if e.kind() == ErrorKind::OutOfMemory => Ok(None),
+            Err(e) => Err(e),
+        }
+    });
+    match encoded {
+        Ok(Some(result)) => Ok(result),
+        Ok(None) => f(&C::encode(value)?),
+        Err(e) if e.kind() == ErrorKind::Busy => f(&C::encode(value)?),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes the encoded `output` into `param`, which may only be `None` if
+/// `output` is empty.
+pub fn output(param: &mut ParameterAny<'_>, output: &[u8]) -> Result<()> {
+    match param {
+        ParameterAny::None if output.is_empty() => Ok(()),
+        ParameterAny::MemrefOutput(memref) => {
+            let mut writer = OutputWriter::new(memref);
+            writer.write(output);
+            writer.finish()
+        }
+        _ => Err(ErrorKind::BadParameters.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw;
+    use crate::{FromRawParameters, ParametersAny};
+
+    #[test]
+    fn test_bytes_codec() {
+        assert_eq!(
+            <Bytes as Decode<Vec<u8>>>::decode(b"abc").unwrap(),
+            b"abc".to_vec()
+        );
+        assert!(<Bytes as Decode<()>>::decode(b"").is_ok());
+        assert_eq!(
+            <Bytes as Decode<()>>::decode(b"x").unwrap_err().kind(),
+            ErrorKind::BadParameters
+        );
+        assert!(<Bytes as Encode<()>>::encode(&()).unwrap().is_empty());
+    }
+
+    #[derive(crate::TaCommand)]
+    enum Command {
+        #[ta_command(output = Vec<u8>)]
+        Reverse(Vec<u8>),
+        Clear,
+        #[ta_command(id = 10)]
+        Push(Vec<u8>),
+        Count,
+    }
+
+    #[derive(Default)]
+    struct Session {
+        pushed: usize,
+    }
+
+    impl CommandHandler for Session {
+        fn reverse(&mut self, mut input: Vec<u8>) -> Result<Vec<u8>> {
+            input.reverse();
+            Ok(input)
+        }
+        fn clear(&mut self) -> Result<()> {
+            self.pushed = 0;
+            Ok(())
+        }
+        fn push(&mut self, input: Vec<u8>) -> Result<()> {
+            self.pushed += input.len();
+            Ok(())
+        }
+        fn count(&mut self) -> Result<()> {
+            Err(ErrorKind::NotImplemented.into())
+        }
+    }
+
+    fn memref(buffer: &mut [u8]) -> raw::TEE_Param {
+        raw::TEE_Param {
+            memref: raw::Memref {
+                buffer: buffer.as_mut_ptr() as _,
+                size: buffer.len(),
+            },
+        }
+    }
+
+    /// Dispatches `cmd_id` to `session` with `input` in p0 and an output
+    /// buffer of `capacity` bytes in p1, returning the reported output size.
+    fn invoke(session: &mut Session, cmd_id: u32, input: &[u8], capacity: usize) -> Result<usize> {
+        let mut input = input.to_vec();
+        let mut output = vec![0u8; capacity];
+        let mut raw_params = [
+            memref(&mut input),
+            memref(&mut output),
+            memref(&mut []),
+            memref(&mut []),
+        ];
+        let raw_types = raw::TEE_PARAM_TYPES(
+            raw::TEE_PARAM_TYPE_MEMREF_INPUT,
+            raw::TEE_PARAM_TYPE_MEMREF_OUTPUT,
+            raw::TEE_PARAM_TYPE_NONE,
+            raw::TEE_PARAM_TYPE_NONE,
+        );
+        let mut params: ParametersAny =
+            unsafe { FromRawParameters::from_raw(raw_types, &mut raw_params)? };
+        session.dispatch(cmd_id, &mut params)?;
+        Ok(unsafe { raw_params[1].memref.size })
+    }
+
+    #[test]
+    fn test_derived_ids() {
+        assert_eq!(Command::Reverse(vec![]).id(), 0);
+        assert_eq!(Command::Clear.id(), 1);
+        assert_eq!(Command::Push(vec![]).id(), 10);
+        assert_eq!(Command::Count.id(), 11);
+        assert!(matches!(
+            <Command as TaCommand>::decode(10, b"ab").unwrap(),
+            Command::Push(input) if input == b"ab"
+        ));
+        assert_eq!(
+            <Command as TaCommand>::decode(2, b"").err().unwrap().kind(),
+            ErrorKind::BadParameters
+        );
+        // Commands without input reject any
+        assert_eq!(
+            <Command as TaCommand>::decode(1, b"a")
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::BadParameters
+        );
+    }
+
+    #[test]
+    fn test_dispatch() {
+        #[cfg(feature = "strict_checks")]
+        let _access = crate::memory::tests::allow_access();
+        let mut session = Session::default();
+        assert_eq!(invoke(&mut session, 0, b"abc", 8).unwrap(), 3);
+        assert_eq!(invoke(&mut session, 10, b"abc", 8).unwrap(), 0);
+        assert_eq!(session.pushed, 3);
+        assert_eq!(invoke(&mut session, 1, b"", 8).unwrap(), 0);
+        assert_eq!(session.pushed, 0);
+        assert_eq!(
+            invoke(&mut session, 11, b"", 8).unwrap_err().kind(),
+            ErrorKind::NotImplemented
+        );
+        // Output that does not fit reports the required size
+        assert_eq!(
+            invoke(&mut session, 0, b"abc", 2).unwrap_err().kind(),
+            ErrorKind::ShortBuffer
+        );
+    }
+
+    #[test]
+    fn test_encode_output_in_arena() {
+        #[cfg(feature = "strict_checks")]
+        let _access = crate::memory::tests::allow_access();
+        let mut session = Session::default();
+        arena::init(4).unwrap();
+        assert_eq!(invoke(&mut session, 0, b"abc", 8).unwrap(), 3);
+        assert_eq!(arena::peak(), Some(3));
+        // Outputs larger than the arena, or encoded while it is in use, are
+        // encoded on the heap
+        assert_eq!(invoke(&mut session, 0, b"abcdef", 8).unwrap(), 6);
+        let size = arena::scope(|_| invoke(&mut session, 0, b"abc", 8)).unwrap();
+        assert_eq!(size, 3);
+        assert_eq!(arena::peak(), Some(3));
+        arena::init(arena::DEFAULT_CAPACITY).unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_codec() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let bytes = <Json as Encode<Point>>::encode(&Point { x: 1, y: 2 }).unwrap();
+        assert_eq!(bytes, br#"{"x":1,"y":2}"#);
+        assert_eq!(
+            <Json as Decode<Point>>::decode(&bytes).unwrap(),
+            Point { x: 1, y: 2 }
+        );
+        assert_eq!(
+            <Json as Decode<Point>>::decode(b"{").unwrap_err().kind(),
+            ErrorKind::BadFormat
+        );
+    }
+}
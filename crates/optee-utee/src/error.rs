@@ -36,10 +36,30 @@ use std::error;
 /// ````
 pub type Result<T> = result::Result<T, Error>;
 
+/// The error type for TEE operations.
+///
+/// Besides its [`ErrorKind`], an error raised by the TA itself can carry a
+/// static message describing what failed. Only the error code reaches the
+/// client, so the message is printed to the trace output when the error is
+/// returned from an entry point generated by the `#[ta_*]` attributes.
+///
+/// # Examples
+///
+/// ``` rust,no_run
+/// use optee_utee::{Error, ErrorKind, Result};
+///
+/// fn check_len(len: usize) -> Result<()> {
+///     if len > 64 {
+///         return Err(Error::with_message(ErrorKind::ExcessData, "key longer than 64 bytes"));
+///     }
+///     Ok(())
+/// }
+/// ```
 #[derive(Clone)]
 pub struct Error {
     kind: ErrorKind,
     origin: Option<ErrorOrigin>,
+    message: Option<&'static str>,
 }
 
 /// A list specifying general categories of TEE error and its corresponding code
@@ -200,7 +220,26 @@ impl From<u32> for ErrorKind {
 
 impl Error {
     pub fn new(kind: ErrorKind) -> Error {
-        Error { kind, origin: None }
+        Error {
+            kind,
+            origin: None,
+            message: None,
+        }
+    }
+
+    /// Creates a new instance of an `Error` of `kind` with a message
+    /// describing what failed.
+    ///
+    /// # Examples
+    ///
+    /// ``` no_run
+    /// use optee_utee::{Error, ErrorKind};
+    ///
+    /// let error = Error::with_message(ErrorKind::ItemNotFound, "no such user");
+    /// assert_eq!(error.message(), "no such user");
+    /// ```
+    pub fn with_message(kind: ErrorKind, message: &'static str) -> Error {
+        Error::new(kind).context(message)
     }
 
     /// Creates a new instance of an `Error` from a particular TEE error code.
@@ -214,10 +253,7 @@ impl Error {
     /// assert_eq!(error.kind(), optee_utee::ErrorKind::Security);
     /// ```
     pub fn from_raw_error(code: u32) -> Error {
-        Error {
-            kind: ErrorKind::from(code),
-            origin: None,
-        }
+        Error::new(ErrorKind::from(code))
     }
 
     pub fn with_origin(mut self, origin: ErrorOrigin) -> Self {
@@ -225,6 +261,28 @@ impl Error {
         self
     }
 
+    /// Sets the message of this error, e.g. to describe which step of the
+    /// TA an error returned by the TEE comes from.
+    ///
+    /// # Examples
+    ///
+    /// ``` no_run
+    /// use optee_utee::{ObjectStorageConstants, PersistentObject, DataFlag, Result};
+    ///
+    /// fn load_config() -> Result<PersistentObject> {
+    ///     PersistentObject::open(
+    ///         ObjectStorageConstants::Private,
+    ///         b"config",
+    ///         DataFlag::ACCESS_READ,
+    ///     )
+    ///     .map_err(|e| e.context("opening the config object"))
+    /// }
+    /// ```
+    pub fn context(mut self, message: &'static str) -> Self {
+        self.message = Some(message);
+        self
+    }
+
     /// Returns the corresponding `ErrorKind` for this error.
     ///
     /// # Examples
@@ -248,18 +306,34 @@ impl Error {
         self.kind.into()
     }
 
-    /// Returns corresponding error message of this error.
+    /// Returns the message of this error, or the description of its kind if
+    /// it has none.
     pub fn message(&self) -> &str {
-        self.kind().as_str()
+        self.message.unwrap_or_else(|| self.kind().as_str())
+    }
+
+    /// Returns the code an entry point returns for this error, printing the
+    /// error to the trace output first if it has a message.
+    ///
+    /// Used by the code generated by the `#[ta_*]` attributes.
+    #[doc(hidden)]
+    pub fn into_entry_point_code(self, entry_point: &str) -> u32 {
+        if self.message.is_some() {
+            crate::trace_println!("[!] {}: {}", entry_point, self);
+        }
+        self.raw_code()
     }
 }
 
 impl fmt::Debug for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(message) = self.message {
+            write!(fmt, "{}: ", message)?;
+        }
         write!(
             fmt,
             "{} (error code 0x{:x}, origin 0x{:x})",
-            self.message(),
+            self.kind().as_str(),
             self.raw_code(),
             self.origin().map(|v| v.into()).unwrap_or(0_u32),
         )
@@ -281,7 +355,7 @@ impl error::Error for Error {
 impl From<ErrorKind> for Error {
     #[inline]
     fn from(kind: ErrorKind) -> Error {
-        Error { kind, origin: None }
+        Error::new(kind)
     }
 }
 
@@ -313,3 +387,35 @@ impl From<u32> for ErrorOrigin {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let error = Error::new(ErrorKind::ItemNotFound);
+        assert_eq!(error.message(), "The requested data item is not found.");
+        assert_eq!(
+            error.to_string(),
+            "The requested data item is not found. (error code 0xffff0008, origin 0x0)"
+        );
+
+        let error = Error::with_message(ErrorKind::ItemNotFound, "no such user")
+            .with_origin(ErrorOrigin::Ta);
+        assert_eq!(error.kind(), ErrorKind::ItemNotFound);
+        assert_eq!(error.message(), "no such user");
+        assert_eq!(
+            error.to_string(),
+            "no such user: The requested data item is not found. (error code 0xffff0008, origin 0x4)"
+        );
+
+        let error = Error::from_raw_error(0xFFFF000F).context("checking the signature");
+        assert_eq!(error.kind(), ErrorKind::Security);
+        assert_eq!(error.message(), "checking the signature");
+    }
+}
@@ -0,0 +1,231 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Fault injection for exercising error paths in tests.
+//!
+//! With the `fault_injection` feature enabled, the secure storage and crypto
+//! wrappers of this crate count how often they are called and can be told to
+//! fail a chosen call, so that the cleanup and retry logic of a TA can be
+//! tested without a TEE that actually runs out of space or corrupts data:
+//!
+//! ``` rust,no_run
+//! # #[cfg(feature = "fault_injection")]
+//! # fn main() {
+//! # use optee_utee::fault_injection::{self, Fault, FaultPoint};
+//! # use optee_utee::ErrorKind;
+//! fault_injection::reset();
+//! // The second write from now fails as if the storage were full
+//! fault_injection::inject(FaultPoint::ObjectWrite, 2, Fault::Error(ErrorKind::StorageNoSpace));
+//! // ... run the code under test ...
+//! assert_eq!(fault_injection::call_count(FaultPoint::ObjectWrite), 2);
+//! # }
+//! # #[cfg(not(feature = "fault_injection"))]
+//! # fn main() {}
+//! ```
+//!
+//! An injected fault replaces the call to the TEE, so a failing
+//! [`ObjectCreate`](FaultPoint::ObjectCreate) does not create the object.
+//! Each fault point holds at most one pending fault, which is disarmed once it
+//! has fired.
+//!
+//! The state is global to the TA (or to the test binary on the host), so tests
+//! injecting faults must not run concurrently with other tests calling the
+//! same wrappers. The feature is meant for test builds only, it adds a counter
+//! update to every wrapped call.
+
+use crate::Result;
+#[cfg(feature = "fault_injection")]
+use crate::{Error, ErrorKind};
+#[cfg(feature = "fault_injection")]
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// The wrapped calls at which a fault can be injected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(usize)]
+pub enum FaultPoint {
+    /// [`PersistentObject::create`](crate::PersistentObject::create)
+    ObjectCreate,
+    /// [`PersistentObject::open`](crate::PersistentObject::open)
+    ObjectOpen,
+    /// [`PersistentObject::read`](crate::PersistentObject::read), a
+    /// [`Fault::Corrupt`] flips the bits of the first byte read
+    ObjectRead,
+    /// [`PersistentObject::write`](crate::PersistentObject::write)
+    ObjectWrite,
+    /// [`PersistentObject::truncate`](crate::PersistentObject::truncate)
+    ObjectTruncate,
+    /// [`PersistentObject::rename`](crate::PersistentObject::rename)
+    ObjectRename,
+    /// [`PersistentObject::close_and_delete`](crate::PersistentObject::close_and_delete)
+    ObjectDelete,
+    /// `update` and `do_final` of [`Cipher`](crate::Cipher), a
+    /// [`Fault::Corrupt`] flips the bits of the first output byte
+    Cipher,
+    /// `update`, `encrypt_final` and `decrypt_final` of [`AE`](crate::AE), a
+    /// [`Fault::Corrupt`] flips the bits of the first output byte of `update`,
+    /// of the first tag byte of `encrypt_final` and fails `decrypt_final` with
+    /// `MacInvalid`
+    AE,
+    /// `encrypt`, `decrypt`, `sign_digest` and `verify_digest` of
+    /// [`Asymmetric`](crate::Asymmetric), a [`Fault::Corrupt`] flips the bits
+    /// of the first output byte and fails `verify_digest` with
+    /// `SignatureInvalid`
+    Asymmetric,
+    /// [`Random::generate`](crate::Random::generate), which cannot return an
+    /// error: a [`Fault::Corrupt`] makes it return all zero bytes and a
+    /// [`Fault::Error`] panics, as `TEE_GenerateRandom` does on failure
+    Random,
+}
+
+#[cfg(feature = "fault_injection")]
+const FAULT_POINTS: usize = FaultPoint::Random as usize + 1;
+
+/// What happens when an injected fault fires.
+#[cfg(feature = "fault_injection")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fault {
+    /// The call fails with the given error without reaching the TEE.
+    Error(ErrorKind),
+    /// The call succeeds but its output is corrupted, see [`FaultPoint`] for
+    /// what that means for each call. Calls without output are not affected.
+    Corrupt,
+}
+
+/// Number of calls per fault point since the last [`reset`].
+#[cfg(feature = "fault_injection")]
+static CALLS: [AtomicU32; FAULT_POINTS] = [const { AtomicU32::new(0) }; FAULT_POINTS];
+/// Call number at which the pending fault fires, 0 if none is pending.
+#[cfg(feature = "fault_injection")]
+static TRIGGERS: [AtomicU32; FAULT_POINTS] = [const { AtomicU32::new(0) }; FAULT_POINTS];
+/// Raw error code of the pending fault, `TEE_SUCCESS` for [`Fault::Corrupt`].
+#[cfg(feature = "fault_injection")]
+static FAULTS: [AtomicU32; FAULT_POINTS] = [const { AtomicU32::new(0) }; FAULT_POINTS];
+
+/// Make the `nth` call of `point` from now on (counting from 1) fail with
+/// `fault`, replacing any fault still pending for `point`.
+#[cfg(feature = "fault_injection")]
+pub fn inject(point: FaultPoint, nth: u32, fault: Fault) {
+    let index = point as usize;
+    let code = match fault {
+        Fault::Error(kind) => kind.into(),
+        Fault::Corrupt => crate::raw::TEE_SUCCESS,
+    };
+    FAULTS[index].store(code, Ordering::SeqCst);
+    let calls = CALLS[index].load(Ordering::SeqCst);
+    TRIGGERS[index].store(calls.saturating_add(nth.max(1)), Ordering::SeqCst);
+}
+
+/// Number of calls of `point` since the last [`reset`], including the ones
+/// that failed because of an injected fault.
+#[cfg(feature = "fault_injection")]
+pub fn call_count(point: FaultPoint) -> u32 {
+    CALLS[point as usize].load(Ordering::SeqCst)
+}
+
+/// Whether a fault injected at `point` has not fired yet.
+#[cfg(feature = "fault_injection")]
+pub fn is_pending(point: FaultPoint) -> bool {
+    TRIGGERS[point as usize].load(Ordering::SeqCst) != 0
+}
+
+/// Disarm all pending faults and reset all call counts.
+#[cfg(feature = "fault_injection")]
+pub fn reset() {
+    for index in 0..FAULT_POINTS {
+        TRIGGERS[index].store(0, Ordering::SeqCst);
+        FAULTS[index].store(0, Ordering::SeqCst);
+        CALLS[index].store(0, Ordering::SeqCst);
+    }
+}
+
+/// Count a call of `point` and return the fault to apply to it. An
+/// [`Fault::Error`] is returned as the `Err` variant so callers can simply use
+/// `?`, `Ok(true)` means the output of the call has to be corrupted.
+#[cfg(feature = "fault_injection")]
+pub(crate) fn check(point: FaultPoint) -> Result<bool> {
+    let index = point as usize;
+    let calls = CALLS[index].fetch_add(1, Ordering::SeqCst).wrapping_add(1);
+    if TRIGGERS[index]
+        .compare_exchange(calls, 0, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return Ok(false);
+    }
+    match FAULTS[index].load(Ordering::SeqCst) {
+        crate::raw::TEE_SUCCESS => Ok(true),
+        code => Err(Error::from_raw_error(code)),
+    }
+}
+
+#[cfg(not(feature = "fault_injection"))]
+#[inline(always)]
+pub(crate) fn check(_point: FaultPoint) -> Result<bool> {
+    Ok(false)
+}
+
+/// Flip the bits of the first byte of `buf`, if any.
+pub(crate) fn corrupt(buf: &mut [u8]) {
+    if let Some(byte) = buf.first_mut() {
+        *byte = !*byte;
+    }
+}
+
+#[cfg(all(test, feature = "fault_injection"))]
+mod tests {
+    use super::*;
+    use optee_utee_sys::mock_utils::SERIAL_TEST_LOCK;
+
+    #[test]
+    fn test_fault_fires_once_at_nth_call() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        reset();
+
+        inject(
+            FaultPoint::ObjectWrite,
+            2,
+            Fault::Error(ErrorKind::StorageNoSpace),
+        );
+        assert!(!check(FaultPoint::ObjectWrite).unwrap());
+        assert!(is_pending(FaultPoint::ObjectWrite));
+        assert_eq!(
+            check(FaultPoint::ObjectWrite).unwrap_err().kind(),
+            ErrorKind::StorageNoSpace
+        );
+        assert!(!is_pending(FaultPoint::ObjectWrite));
+        assert!(!check(FaultPoint::ObjectWrite).unwrap());
+        assert_eq!(call_count(FaultPoint::ObjectWrite), 3);
+        // Other fault points are not affected
+        assert_eq!(call_count(FaultPoint::ObjectRead), 0);
+
+        inject(FaultPoint::ObjectRead, 1, Fault::Corrupt);
+        assert!(check(FaultPoint::ObjectRead).unwrap());
+        reset();
+    }
+
+    #[test]
+    fn test_reset_disarms() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        reset();
+
+        inject(FaultPoint::Random, 1, Fault::Corrupt);
+        reset();
+        assert!(!is_pending(FaultPoint::Random));
+        assert!(!check(FaultPoint::Random).unwrap());
+        assert_eq!(call_count(FaultPoint::Random), 1);
+        reset();
+    }
+}
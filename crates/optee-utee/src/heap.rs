@@ -0,0 +1,767 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The global allocator of the TA and allocation failure reporting.
+//!
+//! Without the `std` feature, the global allocator of the TA is selected by
+//! the features of this crate:
+//!
+//! - by default, the `malloc` of libutee, serving the pool whose size is set
+//!   with `ta_data_size` in the TA configuration;
+//! - with `alloc_bump`, a [`BumpAlloc`], which only frees the last
+//!   allocation and suits TAs allocating everything they need up front;
+//! - with `alloc_tlsf`, a [`TlsfAlloc`], a two-level segregated fit allocator
+//!   allocating and freeing in bounded time and keeping fragmentation low,
+//!   for long-running TAs with tight memory budgets.
+//!
+//! The bump and TLSF allocators serve a static region of [`HEAP_SIZE`] bytes,
+//! set at build time with the `OPTEE_UTEE_HEAP_SIZE` environment variable.
+//! The region is part of the data of the TA, so `ta_data_size` then only has
+//! to cover the allocations of libutee itself.
+//!
+//! Whatever the allocator, the allocation error hook is called with the
+//! layout of every allocation that fails, before the caller handles the
+//! failure, usually by aborting the TA. The default hook, [`default_hook`],
+//! prints the requested size to the trace output. A TA replaces it with
+//! [`set_alloc_error_hook`], typically from `#[ta_create]`:
+//!
+//! ``` rust,no_run
+//! # use core::alloc::Layout;
+//! # use optee_utee::heap;
+//! fn oom_hook(layout: Layout) {
+//!     heap::default_hook(layout);
+//!     optee_utee::trace_println!("[!] heap: {} bytes", heap::HEAP_SIZE);
+//! }
+//!
+//! heap::set_alloc_error_hook(oom_hook);
+//! ```
+//!
+//! The hook runs while the allocation fails, so it must not allocate.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+#[cfg(all(feature = "alloc_bump", feature = "alloc_tlsf"))]
+compile_error!("the `alloc_bump` and `alloc_tlsf` features are mutually exclusive");
+
+/// A function called with the layout of a failed allocation.
+pub type AllocErrorHook = fn(Layout);
+
+/// Default size of the region of [`BumpAlloc`] and [`TlsfAlloc`].
+pub const DEFAULT_HEAP_SIZE: usize = 64 * 1024;
+
+/// Size of the region served by the `alloc_bump` and `alloc_tlsf` allocators,
+/// `OPTEE_UTEE_HEAP_SIZE` bytes or [`DEFAULT_HEAP_SIZE`] if it is not set.
+pub const HEAP_SIZE: usize = match option_env!("OPTEE_UTEE_HEAP_SIZE") {
+    Some(size) => parse_size(size),
+    None => DEFAULT_HEAP_SIZE,
+};
+
+const fn parse_size(size: &str) -> usize {
+    let bytes = size.as_bytes();
+    assert!(!bytes.is_empty(), "OPTEE_UTEE_HEAP_SIZE is empty");
+    let mut value = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(
+            bytes[i].is_ascii_digit(),
+            "OPTEE_UTEE_HEAP_SIZE must be a number of bytes"
+        );
+        value = value * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    value
+}
+
+#[cfg(all(
+    not(feature = "std"),
+    not(any(feature = "alloc_bump", feature = "alloc_tlsf"))
+))]
+#[global_allocator]
+static ALLOCATOR: Reporting<libc_alloc::LibcAlloc> = Reporting(libc_alloc::LibcAlloc);
+
+// Unit tests keep the allocator of the standard library, the static region
+// being too small for the test harness
+#[cfg(all(not(feature = "std"), not(test), feature = "alloc_bump"))]
+#[global_allocator]
+static ALLOCATOR: Reporting<BumpAlloc<HEAP_SIZE>> = Reporting(BumpAlloc::new());
+
+#[cfg(all(not(feature = "std"), not(test), feature = "alloc_tlsf"))]
+#[global_allocator]
+static ALLOCATOR: Reporting<TlsfAlloc<HEAP_SIZE>> = Reporting(TlsfAlloc::new());
+
+// Null for the default hook
+static HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Register `hook` to be called when an allocation fails, replacing the
+/// previous hook.
+pub fn set_alloc_error_hook(hook: AllocErrorHook) {
+    HOOK.store(hook as *mut (), Ordering::SeqCst);
+}
+
+/// Unregister the current allocation error hook, restoring [`default_hook`],
+/// and return it.
+pub fn take_alloc_error_hook() -> AllocErrorHook {
+    load_hook(HOOK.swap(ptr::null_mut(), Ordering::SeqCst))
+}
+
+fn load_hook(hook: *mut ()) -> AllocErrorHook {
+    if hook.is_null() {
+        return default_hook;
+    }
+    // SAFETY: non-null values are only stored by set_alloc_error_hook, from
+    // an AllocErrorHook.
+    unsafe { core::mem::transmute::<*mut (), AllocErrorHook>(hook) }
+}
+
+/// The default allocation error hook, printing the requested size to the
+/// trace output.
+pub fn default_hook(layout: Layout) {
+    // The trace syscall is not mocked, so unit tests cannot link it
+    #[cfg(not(test))]
+    crate::trace_println!(
+        "[!] TA memory allocation of {} bytes (align {}) failed",
+        layout.size(),
+        layout.align()
+    );
+    #[cfg(test)]
+    let _ = layout;
+}
+
+/// Calls the allocation error hook when the allocations of `A` fail.
+///
+/// With `std`, the global allocator is the one of the standard library.
+#[cfg(any(not(feature = "std"), test))]
+struct Reporting<A>(A);
+
+#[cfg(any(not(feature = "std"), test))]
+impl<A> Reporting<A> {
+    fn check(&self, ptr: *mut u8, layout: Layout) -> *mut u8 {
+        if ptr.is_null() {
+            load_hook(HOOK.load(Ordering::SeqCst))(layout);
+        }
+        ptr
+    }
+}
+
+#[cfg(any(not(feature = "std"), test))]
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Reporting<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.check(unsafe { self.0.alloc(layout) }, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.check(unsafe { self.0.alloc_zeroed(layout) }, layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.0.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.0.realloc(ptr, layout, new_size) };
+        // SAFETY: the caller guarantees that the new layout is valid.
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        self.check(new_ptr, new_layout)
+    }
+}
+
+/// The memory served by the allocators, aligned for any primitive type.
+#[repr(C, align(16))]
+struct Region<const SIZE: usize>(UnsafeCell<[u8; SIZE]>);
+
+impl<const SIZE: usize> Region<SIZE> {
+    const fn new() -> Self {
+        Self(UnsafeCell::new([0; SIZE]))
+    }
+
+    fn start(&self) -> *mut u8 {
+        self.0.get().cast()
+    }
+}
+
+/// An allocator serving a region of `SIZE` bytes by bumping an offset.
+///
+/// Only the last allocation is freed, or grown in place by `realloc`, so
+/// memory is reclaimed when allocations are freed in reverse order and leaked
+/// otherwise. Allocations take constant time and never fragment, which makes
+/// the allocator a good fit for TAs allocating their state at creation and
+/// running in fixed memory afterwards.
+pub struct BumpAlloc<const SIZE: usize> {
+    region: Region<SIZE>,
+    next: AtomicUsize,
+}
+
+// SAFETY: the region is only reached through the allocations, whose ranges
+// are reserved atomically.
+unsafe impl<const SIZE: usize> Sync for BumpAlloc<SIZE> {}
+
+impl<const SIZE: usize> BumpAlloc<SIZE> {
+    pub const fn new() -> Self {
+        Self {
+            region: Region::new(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Size of the region in bytes.
+    pub const fn capacity(&self) -> usize {
+        SIZE
+    }
+
+    /// Bytes allocated from the region, including the padding of alignment.
+    pub fn used(&self) -> usize {
+        self.next.load(Ordering::SeqCst)
+    }
+
+    /// The offset of the allocation of `layout` if it started at `next`, and
+    /// the offset of its end.
+    fn reserve(&self, next: usize, layout: Layout) -> Option<(usize, usize)> {
+        let start = self.region.start() as usize;
+        let offset = (start + next).checked_next_multiple_of(layout.align())? - start;
+        let end = offset.checked_add(layout.size())?;
+        (end <= SIZE).then_some((offset, end))
+    }
+}
+
+impl<const SIZE: usize> Default for BumpAlloc<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<const SIZE: usize> GlobalAlloc for BumpAlloc<SIZE> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut offset = 0;
+        let reserved = self
+            .next
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |next| {
+                let (start, end) = self.reserve(next, layout)?;
+                offset = start;
+                Some(end)
+            });
+        match reserved {
+            // SAFETY: the allocation is within the region.
+            Ok(_) => unsafe { self.region.start().add(offset) },
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let offset = ptr as usize - self.region.start() as usize;
+        // Fails, leaking the memory, unless it is the last allocation
+        let _ = self.next.compare_exchange(
+            offset + layout.size(),
+            offset,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let offset = ptr as usize - self.region.start() as usize;
+        if offset + new_size <= SIZE
+            && self
+                .next
+                .compare_exchange(
+                    offset + layout.size(),
+                    offset + new_size,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok()
+        {
+            return ptr;
+        }
+        if new_size <= layout.size() {
+            return ptr;
+        }
+        // SAFETY: the caller guarantees that the new layout is valid.
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        let new_ptr = unsafe { self.alloc(new_layout) };
+        if !new_ptr.is_null() {
+            // SAFETY: both allocations are valid for the copied size and do
+            // not overlap.
+            unsafe {
+                ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+                self.dealloc(ptr, layout);
+            }
+        }
+        new_ptr
+    }
+}
+
+/// Alignment of the blocks of [`TlsfAlloc`], and granularity of their sizes.
+const ALIGN: usize = 2 * size_of::<usize>();
+const ALIGN_LOG2: u32 = ALIGN.trailing_zeros();
+/// Size of the header preceding the payload of every block.
+const HEADER_SIZE: usize = size_of::<BlockHeader>();
+/// Smallest payload, large enough for the links of a free block.
+const MIN_PAYLOAD: usize = 2 * size_of::<usize>();
+/// Smallest block, header included.
+const MIN_BLOCK: usize = HEADER_SIZE + MIN_PAYLOAD;
+/// Number of second-level lists of a first-level class, as a power of two.
+const SL_LOG2: u32 = 4;
+const SL_COUNT: usize = 1 << SL_LOG2;
+/// Blocks smaller than this are all in the first first-level class, in
+/// second-level lists spaced by `ALIGN` bytes.
+const FL_SHIFT: u32 = SL_LOG2 + ALIGN_LOG2;
+const SMALL_BLOCK: usize = 1 << FL_SHIFT;
+/// The payload size of the largest block, 1 GiB.
+const MAX_PAYLOAD: usize = 1 << 30;
+/// Number of first-level classes, for sizes up to twice `MAX_PAYLOAD` once
+/// rounded up by `mapping_search`.
+const FL_COUNT: usize = (32 - FL_SHIFT) as usize;
+
+const FREE: usize = 1;
+
+/// The header of a block, followed by its payload, which starts with the
+/// links of the free list of the block while it is free.
+#[repr(C)]
+struct BlockHeader {
+    /// The previous block in the region, null for the first one.
+    prev_phys: *mut BlockHeader,
+    /// Size of the payload, a multiple of `ALIGN`, or'ed with `FREE`.
+    size: usize,
+}
+
+/// Links of a free block, at the start of its payload.
+#[repr(C)]
+struct FreeLinks {
+    next: *mut BlockHeader,
+    prev: *mut BlockHeader,
+}
+
+impl BlockHeader {
+    fn size(&self) -> usize {
+        self.size & !FREE
+    }
+
+    fn is_free(&self) -> bool {
+        self.size & FREE != 0
+    }
+}
+
+/// Operations on blocks, which must be valid blocks of an initialized heap.
+unsafe fn payload(block: *mut BlockHeader) -> *mut u8 {
+    unsafe { block.cast::<u8>().add(HEADER_SIZE) }
+}
+
+unsafe fn from_payload(ptr: *mut u8) -> *mut BlockHeader {
+    unsafe { ptr.sub(HEADER_SIZE).cast() }
+}
+
+unsafe fn links(block: *mut BlockHeader) -> *mut FreeLinks {
+    unsafe { payload(block).cast() }
+}
+
+unsafe fn next_phys(block: *mut BlockHeader) -> *mut BlockHeader {
+    unsafe { payload(block).add((*block).size()).cast() }
+}
+
+/// Splits `block` after `size` bytes of payload, returning the remainder,
+/// which the caller must mark and link.
+unsafe fn split(block: *mut BlockHeader, size: usize) -> *mut BlockHeader {
+    unsafe {
+        let rest = payload(block).add(size).cast::<BlockHeader>();
+        (*rest).prev_phys = block;
+        (*rest).size = (*block).size() - size - HEADER_SIZE;
+        (*block).size = size | ((*block).size & FREE);
+        (*next_phys(rest)).prev_phys = rest;
+        rest
+    }
+}
+
+/// Merges `next`, the block following `block`, into `block`.
+unsafe fn absorb(block: *mut BlockHeader, next: *mut BlockHeader) {
+    unsafe {
+        (*block).size += HEADER_SIZE + (*next).size();
+        (*next_phys(block)).prev_phys = block;
+    }
+}
+
+/// The first- and second-level indices of the list of blocks of `size`.
+fn mapping(size: usize) -> (usize, usize) {
+    if size < SMALL_BLOCK {
+        (0, size / (SMALL_BLOCK / SL_COUNT))
+    } else {
+        let fl = usize::BITS - 1 - size.leading_zeros();
+        let sl = (size >> (fl - SL_LOG2)) ^ SL_COUNT;
+        ((fl - FL_SHIFT + 1) as usize, sl)
+    }
+}
+
+/// The indices of the first list whose blocks are all at least `size` bytes.
+fn mapping_search(size: usize) -> (usize, usize) {
+    if size < SMALL_BLOCK {
+        return mapping(size);
+    }
+    let fl = usize::BITS - 1 - size.leading_zeros();
+    mapping(size + (1 << (fl - SL_LOG2)) - 1)
+}
+
+/// The free lists of a TLSF heap, indexed by size class.
+struct Lists {
+    initialized: bool,
+    fl_bitmap: usize,
+    sl_bitmaps: [usize; FL_COUNT],
+    heads: [[*mut BlockHeader; SL_COUNT]; FL_COUNT],
+}
+
+impl Lists {
+    const fn new() -> Self {
+        Self {
+            initialized: false,
+            fl_bitmap: 0,
+            sl_bitmaps: [0; FL_COUNT],
+            heads: [[ptr::null_mut(); SL_COUNT]; FL_COUNT],
+        }
+    }
+
+    /// Turns the `size` bytes at `start` into one free block followed by a
+    /// used block of size 0, which stops merges at the end of the region.
+    unsafe fn init(&mut self, start: *mut u8, size: usize) {
+        self.initialized = true;
+        let size = size.min(MAX_PAYLOAD + 2 * HEADER_SIZE) & !(ALIGN - 1);
+        if size < MIN_BLOCK + HEADER_SIZE {
+            return;
+        }
+        unsafe {
+            let block = start.cast::<BlockHeader>();
+            (*block).prev_phys = ptr::null_mut();
+            (*block).size = (size - 2 * HEADER_SIZE) | FREE;
+            let sentinel = next_phys(block);
+            (*sentinel).prev_phys = block;
+            (*sentinel).size = 0;
+            self.insert(block);
+        }
+    }
+
+    unsafe fn insert(&mut self, block: *mut BlockHeader) {
+        let (fl, sl) = mapping(unsafe { (*block).size() });
+        let head = self.heads[fl][sl];
+        unsafe {
+            (*block).size |= FREE;
+            *links(block) = FreeLinks {
+                next: head,
+                prev: ptr::null_mut(),
+            };
+            if !head.is_null() {
+                (*links(head)).prev = block;
+            }
+        }
+        self.heads[fl][sl] = block;
+        self.fl_bitmap |= 1 << fl;
+        self.sl_bitmaps[fl] |= 1 << sl;
+    }
+
+    unsafe fn remove(&mut self, block: *mut BlockHeader) {
+        let (fl, sl) = mapping(unsafe { (*block).size() });
+        unsafe {
+            let FreeLinks { next, prev } = *links(block);
+            if !next.is_null() {
+                (*links(next)).prev = prev;
+            }
+            if prev.is_null() {
+                self.heads[fl][sl] = next;
+                if next.is_null() {
+                    self.sl_bitmaps[fl] &= !(1 << sl);
+                    if self.sl_bitmaps[fl] == 0 {
+                        self.fl_bitmap &= !(1 << fl);
+                    }
+                }
+            } else {
+                (*links(prev)).next = next;
+            }
+            (*block).size &= !FREE;
+        }
+    }
+
+    /// Removes and returns a free block of at least `size` bytes.
+    fn take(&mut self, size: usize) -> Option<*mut BlockHeader> {
+        let (mut fl, sl) = mapping_search(size);
+        if fl >= FL_COUNT {
+            return None;
+        }
+        let mut sl_map = self.sl_bitmaps[fl] & (!0usize << sl);
+        if sl_map == 0 {
+            let fl_map = self.fl_bitmap & (!0usize).checked_shl(fl as u32 + 1).unwrap_or(0);
+            if fl_map == 0 {
+                return None;
+            }
+            fl = fl_map.trailing_zeros() as usize;
+            sl_map = self.sl_bitmaps[fl];
+        }
+        let block = self.heads[fl][sl_map.trailing_zeros() as usize];
+        // SAFETY: the block is in the list, so it is a valid free block.
+        unsafe { self.remove(block) };
+        Some(block)
+    }
+
+    unsafe fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let size = match layout.size().checked_next_multiple_of(ALIGN) {
+            Some(size) => size.max(MIN_PAYLOAD),
+            None => return ptr::null_mut(),
+        };
+        // Room for a free block before the aligned payload
+        let search = if layout.align() > ALIGN {
+            size.checked_add(layout.align() + MIN_BLOCK)
+        } else {
+            Some(size)
+        };
+        let Some(mut block) = search
+            .filter(|search| *search <= MAX_PAYLOAD)
+            .and_then(|search| self.take(search))
+        else {
+            return ptr::null_mut();
+        };
+        unsafe {
+            if layout.align() > ALIGN {
+                let start = payload(block) as usize;
+                let mut aligned = start.next_multiple_of(layout.align());
+                if aligned != start && aligned - start < MIN_BLOCK {
+                    aligned = (start + MIN_BLOCK).next_multiple_of(layout.align());
+                }
+                if aligned != start {
+                    let leading = block;
+                    block = split(leading, aligned - start - HEADER_SIZE);
+                    self.insert(leading);
+                }
+            }
+            if (*block).size() >= size + MIN_BLOCK {
+                let rest = split(block, size);
+                self.insert(rest);
+            }
+            payload(block)
+        }
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8) {
+        unsafe {
+            let mut block = from_payload(ptr);
+            let next = next_phys(block);
+            if (*next).is_free() {
+                self.remove(next);
+                absorb(block, next);
+            }
+            let prev = (*block).prev_phys;
+            if !prev.is_null() && (*prev).is_free() {
+                self.remove(prev);
+                absorb(prev, block);
+                block = prev;
+            }
+            self.insert(block);
+        }
+    }
+}
+
+/// A two-level segregated fit (TLSF) allocator serving a region of `SIZE`
+/// bytes.
+///
+/// Free blocks are kept in lists indexed by their size class, found in
+/// constant time with bitmaps, and merged with their free neighbours when
+/// they are freed. Allocating and freeing take bounded time, and the
+/// fragmentation stays low over long uptimes. Each allocation costs a
+/// header of two words.
+pub struct TlsfAlloc<const SIZE: usize> {
+    region: Region<SIZE>,
+    lists: UnsafeCell<Lists>,
+    locked: AtomicBool,
+}
+
+// SAFETY: the lists and the region are only accessed with the lock held.
+unsafe impl<const SIZE: usize> Sync for TlsfAlloc<SIZE> {}
+
+impl<const SIZE: usize> TlsfAlloc<SIZE> {
+    pub const fn new() -> Self {
+        Self {
+            region: Region::new(),
+            lists: UnsafeCell::new(Lists::new()),
+            locked: AtomicBool::new(false),
+        }
+    }
+
+    /// Size of the region in bytes.
+    pub const fn capacity(&self) -> usize {
+        SIZE
+    }
+
+    /// Runs `f` on the lists with the lock held, initializing them first.
+    fn with_lists<R>(&self, f: impl FnOnce(&mut Lists) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        // SAFETY: the lock is held.
+        let lists = unsafe { &mut *self.lists.get() };
+        if !lists.initialized {
+            // SAFETY: the region is only used by these lists.
+            unsafe { lists.init(self.region.start(), SIZE) };
+        }
+        let result = f(lists);
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+impl<const SIZE: usize> Default for TlsfAlloc<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<const SIZE: usize> GlobalAlloc for TlsfAlloc<SIZE> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with_lists(|lists| unsafe { lists.alloc(layout) })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        self.with_lists(|lists| unsafe { lists.dealloc(ptr) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("65536"), 64 * 1024);
+        let expected = option_env!("OPTEE_UTEE_HEAP_SIZE").map_or(DEFAULT_HEAP_SIZE, parse_size);
+        assert_eq!(HEAP_SIZE, expected);
+    }
+
+    #[test]
+    fn test_bump() {
+        let heap = Box::new(BumpAlloc::<256>::new());
+        unsafe {
+            let a = heap.alloc(layout(3, 1));
+            let b = heap.alloc(layout(8, 8));
+            assert_eq!(b as usize % 8, 0);
+            assert_eq!(b as usize - a as usize, 8);
+            assert_eq!(heap.used(), 16);
+
+            // Only the last allocation is freed or grown in place
+            heap.dealloc(a, layout(3, 1));
+            assert_eq!(heap.used(), 16);
+            assert_eq!(heap.realloc(b, layout(8, 8), 32), b);
+            assert_eq!(heap.used(), 40);
+            heap.dealloc(b, layout(32, 8));
+            assert_eq!(heap.used(), 8);
+
+            assert!(heap.alloc(layout(249, 1)).is_null());
+            assert!(!heap.alloc(layout(248, 1)).is_null());
+        }
+    }
+
+    #[test]
+    fn test_tlsf_mapping() {
+        assert_eq!(mapping(0), (0, 0));
+        assert_eq!(mapping(SMALL_BLOCK - 1), (0, SL_COUNT - 1));
+        assert_eq!(mapping(SMALL_BLOCK), (1, 0));
+        assert_eq!(mapping(2 * SMALL_BLOCK - 1), (1, SL_COUNT - 1));
+        assert_eq!(mapping(2 * SMALL_BLOCK), (2, 0));
+        // Rounded up to the next list, whose blocks are all large enough
+        assert_eq!(mapping_search(SMALL_BLOCK + 1), (1, 1));
+        assert_eq!(mapping_search(2 * SMALL_BLOCK), (2, 0));
+    }
+
+    // Allocates the `layouts`, checks that the allocations are aligned and do
+    // not overlap, then frees them in a scrambled order.
+    fn exercise<const SIZE: usize>(heap: &TlsfAlloc<SIZE>, layouts: &[Layout]) {
+        let mut allocations = Vec::new();
+        for (i, layout) in layouts.iter().enumerate() {
+            let ptr = unsafe { heap.alloc(*layout) };
+            assert!(!ptr.is_null(), "{:?}", layout);
+            assert_eq!(ptr as usize % layout.align(), 0);
+            unsafe { ptr::write_bytes(ptr, i as u8, layout.size()) };
+            allocations.push((ptr, *layout, i as u8));
+        }
+        for (ptr, layout, fill) in &allocations {
+            let data = unsafe { core::slice::from_raw_parts(*ptr, layout.size()) };
+            assert!(data.iter().all(|b| b == fill));
+        }
+        let n = allocations.len();
+        for i in 0..n {
+            let (ptr, layout, _) = allocations[(i * 7) % n];
+            unsafe { heap.dealloc(ptr, layout) };
+        }
+    }
+
+    #[test]
+    fn test_tlsf() {
+        const SIZE: usize = 16 * 1024;
+        let heap = Box::new(TlsfAlloc::<SIZE>::new());
+        let layouts: Vec<Layout> = (0..50)
+            .map(|i| layout(1 + (i * 37) % 300, 1 << (i % 4)))
+            .collect();
+        for _ in 0..3 {
+            exercise(&heap, &layouts);
+        }
+        exercise(&heap, &[layout(24, 64), layout(100, 256), layout(8, 8)]);
+
+        // Everything was merged back into one block, found by the searches
+        // of sizes at least one second-level class below it
+        let whole = SIZE - 2 * HEADER_SIZE;
+        let large = layout(whole - whole / SL_COUNT, 8);
+        let ptr = unsafe { heap.alloc(large) };
+        assert!(!ptr.is_null());
+        assert!(unsafe { heap.alloc(large) }.is_null());
+        unsafe { heap.dealloc(ptr, large) };
+        assert!(unsafe { heap.alloc(layout(whole + 1, 8)) }.is_null());
+    }
+
+    static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+    fn record_hook(layout: Layout) {
+        FAILED.store(layout.size(), Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_alloc_error_hook() {
+        let heap = Box::new(Reporting(BumpAlloc::<64>::new()));
+        set_alloc_error_hook(record_hook);
+        unsafe {
+            let ptr = heap.alloc(layout(32, 1));
+            assert!(!ptr.is_null());
+            assert_eq!(FAILED.load(Ordering::SeqCst), 0);
+            assert!(heap.alloc(layout(100, 1)).is_null());
+            assert_eq!(FAILED.load(Ordering::SeqCst), 100);
+            assert!(heap.realloc(ptr, layout(32, 1), 200).is_null());
+            assert_eq!(FAILED.load(Ordering::SeqCst), 200);
+        }
+        assert_eq!(
+            take_alloc_error_hook() as *const (),
+            record_hook as *const ()
+        );
+        assert_eq!(
+            take_alloc_error_hook() as *const (),
+            default_hook as *const ()
+        );
+    }
+}
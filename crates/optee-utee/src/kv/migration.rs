@@ -0,0 +1,127 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::dispatch::{Decode, Encode, Json};
+use crate::{Error, ErrorKind, Result};
+
+/// Upgrades an encoded value from one schema version to the next.
+type Step = Box<dyn Fn(&[u8]) -> Result<Vec<u8>>>;
+
+/// The migrations of the values of a [`SecureKvStore`](super::SecureKvStore)
+/// from the first version of their type to `V`, the current one.
+///
+/// The first version of the schema is 1, the type the values had before the
+/// store was migrated, and each [`migration`](Self::migration) adds a version:
+///
+/// ``` rust,no_run
+/// # use optee_utee::kv::{SecureKvStore, StateMigrator};
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Serialize, Deserialize)]
+/// struct AccountV1 { owner: String, balance: u32 }
+/// #[derive(Serialize, Deserialize)]
+/// struct AccountV2 { owner: String, balance: u64 }
+/// #[derive(Serialize, Deserialize)]
+/// struct Account { owner: String, balance: u64, frozen: bool }
+///
+/// # fn main() -> optee_utee::Result<()> {
+/// let migrator = StateMigrator::<AccountV1>::new()
+///     // Version 1 to 2
+///     .migration(|old: AccountV1| AccountV2 {
+///         owner: old.owner,
+///         balance: old.balance.into(),
+///     })
+///     // Version 2 to 3
+///     .migration(|old: AccountV2| Account {
+///         owner: old.owner,
+///         balance: old.balance,
+///         frozen: false,
+///     });
+/// let store: SecureKvStore<u32, Account> =
+///     SecureKvStore::open(b"accounts")?.migrate(&migrator)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Migrations are only ever appended: a TA must keep the ones of the versions
+/// it may find in the storage of the devices it is installed on.
+pub struct StateMigrator<V> {
+    steps: Vec<Step>,
+    _marker: PhantomData<fn() -> V>,
+}
+
+impl<V> StateMigrator<V> {
+    /// A migrator without migrations, where `V` is the first version of the
+    /// values.
+    pub fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Adds the migration of the values of the current version to the next
+    /// one, of type `W`.
+    pub fn migration<W, F>(mut self, f: F) -> StateMigrator<W>
+    where
+        V: DeserializeOwned + 'static,
+        W: Serialize + 'static,
+        F: Fn(V) -> W + 'static,
+    {
+        self.steps.push(Box::new(move |encoded| {
+            let old = <Json as Decode<V>>::decode(encoded)?;
+            <Json as Encode<W>>::encode(&f(old))
+        }));
+        StateMigrator {
+            steps: self.steps,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The schema version of the values of type `V`, 1 plus the number of
+    /// migrations.
+    pub fn version(&self) -> u32 {
+        self.steps.len() as u32 + 1
+    }
+
+    /// Upgrades a value encoded at schema version `from` to the current one.
+    pub(super) fn upgrade(&self, from: u32, encoded: &[u8]) -> Result<Vec<u8>> {
+        if from == 0 || from > self.version() {
+            return Err(Error::with_message(
+                ErrorKind::BadFormat,
+                "entry written with an unknown schema version",
+            ));
+        }
+        let mut encoded = encoded.to_vec();
+        for step in &self.steps[from as usize - 1..] {
+            encoded = step(&encoded)?;
+        }
+        Ok(encoded)
+    }
+}
+
+impl<V> Default for StateMigrator<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
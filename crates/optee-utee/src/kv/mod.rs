@@ -0,0 +1,821 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Typed key-value store in the Trusted Storage.
+//!
+//! A [`SecureKvStore`] keeps every entry in its own
+//! [`PersistentObject`](crate::PersistentObject), so an entry is written with a
+//! single object creation and a failed or interrupted write leaves the
+//! previous value in place:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::kv::SecureKvStore;
+//! # fn main() -> optee_utee::Result<()> {
+//! let store: SecureKvStore<String, u64> = SecureKvStore::open(b"counters")?;
+//! let count = store.get(&"boot".to_string())?.unwrap_or(0);
+//! store.put(&"boot".to_string(), &(count + 1))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Keys and values are encoded as JSON. The object identifier of an entry is
+//! the namespace of the store, a NUL byte and the encoded key, so stores with
+//! different namespaces never see each other's entries, and the identifier
+//! must fit into
+//! [`TeeObjectIdMaxLen`](crate::MiscellaneousConstants::TeeObjectIdMaxLen)
+//! bytes.
+//!
+//! Every entry carries a version, which starts at 1 and is incremented by each
+//! write. [`SecureKvStore::put_if_version`] only writes if the entry is still
+//! at the version the caller read, which lets a TA detect that another
+//! instance or session changed the entry in between.
+//!
+//! The writes of a store hold its lock, an object opened exclusively and named
+//! by the namespace and the NUL byte alone, so the check and the write of
+//! `put_if_version` are not interleaved with the writes of the other
+//! instances of a multi-instance TA. A write finding the lock taken fails
+//! with `AccessConflict` rather than waiting, as the other instance may hold
+//! it for a whole command.
+//!
+//! A store may be limited with a [`Quota`] on its entries, e.g. a store per
+//! client of a TA serving several client applications, see
+//! [`SecureKvStore::with_quota`].
+//!
+//! Every entry also records the schema version of its value, so that a TA
+//! changing the type of the values of a store keeps the entries written by
+//! its previous versions: [`SecureKvStore::migrate`] upgrades them with the
+//! migrations registered in a [`StateMigrator`].
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::dispatch::{Decode, Encode, Json};
+use crate::storage::{self, Quota, Usage};
+use crate::{
+    DataFlag, Error, ErrorKind, MiscellaneousConstants, ObjectStorageConstants, PersistentObject,
+    PersistentObjectIter, Result,
+};
+
+mod migration;
+
+pub use migration::StateMigrator;
+
+/// Marks the data stream of an entry.
+const MAGIC: [u8; 2] = *b"KV";
+/// Version of the entry layout: magic, layout version, entry version as a
+/// little-endian `u64`, then the encoded value.
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 8;
+/// Layout of the entries of the schema versions above 1: the header is
+/// followed by the schema version as a little-endian `u32`. Entries of
+/// version 1 keep the first layout, readable by the previous releases.
+const SCHEMA_FORMAT_VERSION: u8 = 2;
+const SCHEMA_HEADER_LEN: usize = HEADER_LEN + 4;
+
+/// Separates the namespace from the encoded key in object identifiers, JSON
+/// never contains it unescaped.
+const SEPARATOR: u8 = 0;
+
+/// A store of values of type `V` by keys of type `K` in the Trusted Storage.
+///
+/// The store itself holds no state besides its namespace, any number of
+/// stores may be opened on the same namespace.
+pub struct SecureKvStore<K, V> {
+    storage_id: ObjectStorageConstants,
+    prefix: Vec<u8>,
+    quota: Quota,
+    /// Schema version of the values of type `V`
+    schema: u32,
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+impl<K: Serialize, V: Serialize + DeserializeOwned> SecureKvStore<K, V> {
+    /// Open the store named `namespace` in the private storage of the TA.
+    ///
+    /// # Errors
+    ///
+    /// `BadParameters`: If `namespace` contains a NUL byte or leaves no room
+    /// for keys in the object identifier.
+    pub fn open(namespace: &[u8]) -> Result<Self> {
+        Self::open_in(ObjectStorageConstants::Private, namespace)
+    }
+
+    /// Open the store named `namespace` in the storage `storage_id`, e.g.
+    /// [`StorageBackend::Rpmb`](crate::storage::StorageBackend::Rpmb) for
+    /// entries which must not be rolled back.
+    pub fn open_in(
+        storage_id: impl Into<ObjectStorageConstants>,
+        namespace: &[u8],
+    ) -> Result<Self> {
+        let storage_id = storage_id.into();
+        if namespace.contains(&SEPARATOR)
+            || namespace.len() + 1 >= MiscellaneousConstants::TeeObjectIdMaxLen as usize
+        {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        let mut prefix = namespace.to_vec();
+        prefix.push(SEPARATOR);
+        Ok(Self {
+            storage_id,
+            prefix,
+            quota: Quota::UNLIMITED,
+            schema: 1,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Limit the entries of the store to `quota`, the writes exceeding it
+    /// fail with `StorageNoSpace`. The size of an entry is the size of its
+    /// encoded value plus a header of 11 bytes, or 15 bytes after a
+    /// [`migrate`](Self::migrate) to a schema version above 1.
+    ///
+    /// The quota applies to this handle of the store: the writes through
+    /// other handles, or by several instances of the TA at the same time,
+    /// may exceed it.
+    pub fn with_quota(mut self, quota: Quota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Upgrade the entries written with a previous schema version with the
+    /// migrations of `migrator`, and write the new entries with its version.
+    /// To be called right after opening the store, before accessing the
+    /// entries.
+    ///
+    /// Each entry is replaced atomically, keeping its version: if the
+    /// migration is interrupted, the next one upgrades the remaining entries.
+    /// The quota of the store does not apply to the upgraded entries.
+    ///
+    /// # Errors
+    ///
+    /// 1) `BadFormat`: If an entry was written with a newer schema version
+    ///    than the one of `migrator`, or a migration cannot decode the value
+    ///    of an entry. The entries migrated before are kept.
+    /// 2) `CorruptObject`: If an object of the store is not a store entry.
+    pub fn migrate(mut self, migrator: &StateMigrator<V>) -> Result<Self> {
+        self.schema = migrator.version();
+        let lock = self.lock()?;
+        for id in self.ids()? {
+            let Some(mut object) =
+                self.open_object(&id, DataFlag::ACCESS_READ | DataFlag::SHARE_READ)?
+            else {
+                continue;
+            };
+            let data = storage::read_all(&mut object)?;
+            drop(object);
+            let header = parse_header(&data)?;
+            if header.schema == self.schema {
+                continue;
+            }
+            let encoded = migrator.upgrade(header.schema, &data[header.len..])?;
+            self.write_entry(&id, header.version, &encoded)?;
+        }
+        drop(lock);
+        Ok(self)
+    }
+
+    /// Return the number of entries of the store and their total size.
+    pub fn usage(&self) -> Result<Usage> {
+        storage::Namespace::new(self.storage_id, &self.prefix)?.usage()
+    }
+
+    /// Return the value of `key`, `None` if there is no such entry.
+    ///
+    /// # Errors
+    ///
+    /// 1) `BadFormat`: If the stored value cannot be decoded as a `V`, or the
+    ///    entry was written by a newer, incompatible layout or with another
+    ///    schema version than the one of the store.
+    /// 2) `CorruptObject`: If the entry is not a store entry.
+    /// 3) `BadParameters`: If the encoded key is too long.
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        Ok(self.get_versioned(key)?.map(|(value, _)| value))
+    }
+
+    /// Return the value of `key` and its version, `None` if there is no such
+    /// entry.
+    pub fn get_versioned(&self, key: &K) -> Result<Option<(V, u64)>> {
+        let id = self.object_id(key)?;
+        self.load(&id)
+    }
+
+    /// Return the version of the entry of `key`, `None` if there is no such
+    /// entry. Only the header of the entry is read.
+    pub fn version(&self, key: &K) -> Result<Option<u64>> {
+        let id = self.object_id(key)?;
+        self.current_version(&id)
+    }
+
+    /// Return whether there is an entry for `key`.
+    pub fn contains_key(&self, key: &K) -> Result<bool> {
+        let id = self.object_id(key)?;
+        Ok(self
+            .open_object(&id, DataFlag::ACCESS_READ | DataFlag::SHARE_READ)?
+            .is_some())
+    }
+
+    /// Set the value of `key`, replacing any previous value, and return the
+    /// new version of the entry.
+    ///
+    /// The entry is replaced atomically: if the write fails the previous value,
+    /// if any, is kept.
+    ///
+    /// # Errors
+    ///
+    /// 1) `StorageNoSpace`: If there is not enough space in the storage, or
+    ///    the write would exceed the quota of the store.
+    /// 2) `AccessConflict`: If another instance of the TA is writing the
+    ///    store, or reading the entry, at the same time.
+    /// 3) `BadParameters`: If the encoded key is too long.
+    /// 4) The errors of [`get`](Self::get) for the previous entry.
+    pub fn put(&self, key: &K, value: &V) -> Result<u64> {
+        let id = self.object_id(key)?;
+        let _lock = self.lock()?;
+        let version = self.current_version(&id)?.unwrap_or(0);
+        self.store(&id, version, value)
+    }
+
+    /// Set the value of `key` if its entry is still at version `expected`, or
+    /// does not exist if `expected` is `None`, and return the new version.
+    ///
+    /// The entry cannot be written by another instance of the TA between
+    /// the check of its version and the write, see the [module
+    /// documentation](self).
+    ///
+    /// # Errors
+    ///
+    /// `AccessConflict`: If the entry is at another version, nothing is
+    /// written then. Otherwise the errors of [`put`](Self::put).
+    pub fn put_if_version(&self, key: &K, value: &V, expected: Option<u64>) -> Result<u64> {
+        let id = self.object_id(key)?;
+        let _lock = self.lock()?;
+        let version = self.current_version(&id)?;
+        if version != expected {
+            return Err(ErrorKind::AccessConflict.into());
+        }
+        self.store(&id, version.unwrap_or(0), value)
+    }
+
+    /// Remove the entry of `key`, returning whether there was one.
+    pub fn remove(&self, key: &K) -> Result<bool> {
+        let id = self.object_id(key)?;
+        let _lock = self.lock()?;
+        storage::remove(self.storage_id, &id)
+    }
+
+    /// Return an iterator over the entries of the store, in no particular
+    /// order.
+    ///
+    /// Entries are read lazily, entries written or removed while iterating
+    /// may or may not be listed.
+    pub fn iter(&self) -> Result<Iter<'_, K, V>>
+    where
+        K: DeserializeOwned,
+    {
+        Ok(Iter {
+            store: self,
+            objects: PersistentObjectIter::new(self.storage_id)?,
+        })
+    }
+
+    /// Return the keys of all entries of the store, in no particular order.
+    pub fn keys(&self) -> Result<Vec<K>>
+    where
+        K: DeserializeOwned,
+    {
+        let mut keys = Vec::new();
+        for entry in PersistentObjectIter::new(self.storage_id)? {
+            let (id, _) = entry?;
+            if let Some(key) = self.key_of(&id) {
+                keys.push(key?);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Remove all entries of the store, returning how many were removed.
+    pub fn clear(&self) -> Result<usize> {
+        let _lock = self.lock()?;
+        let mut removed = 0;
+        for id in self.ids()? {
+            if storage::remove(self.storage_id, &id)? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Return the object identifiers of the entries of the store. Collected
+    /// first, as modifying objects while enumerating them is not guaranteed
+    /// to list the remaining ones.
+    fn ids(&self) -> Result<Vec<Vec<u8>>> {
+        let mut ids = Vec::new();
+        for entry in PersistentObjectIter::new(self.storage_id)? {
+            let (id, _) = entry?;
+            if id.starts_with(&self.prefix) && id.len() > self.prefix.len() {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn object_id(&self, key: &K) -> Result<Vec<u8>> {
+        let encoded = <Json as Encode<K>>::encode(key)?;
+        if self.prefix.len() + encoded.len() > MiscellaneousConstants::TeeObjectIdMaxLen as usize {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        let mut id = self.prefix.clone();
+        id.extend_from_slice(&encoded);
+        Ok(id)
+    }
+
+    /// Decode the key of object `id`, `None` if it belongs to another store.
+    fn key_of(&self, id: &[u8]) -> Option<Result<K>>
+    where
+        K: DeserializeOwned,
+    {
+        // Encoded keys are never empty, the prefix alone is the lock
+        let encoded = id
+            .strip_prefix(self.prefix.as_slice())
+            .filter(|encoded| !encoded.is_empty())?;
+        Some(<Json as Decode<K>>::decode(encoded))
+    }
+
+    /// Take the lock of the store, released when the returned object is
+    /// dropped. Fails with `AccessConflict` if another instance holds it.
+    fn lock(&self) -> Result<PersistentObject> {
+        // Without share flags, any other handle of the object conflicts
+        match self.open_object(&self.prefix, DataFlag::ACCESS_READ)? {
+            Some(lock) => Ok(lock),
+            // Fails with `AccessConflict` too if created in between
+            None => PersistentObject::create(
+                self.storage_id,
+                &self.prefix,
+                DataFlag::ACCESS_READ,
+                None,
+                &[],
+            ),
+        }
+    }
+
+    fn open_object(&self, id: &[u8], flags: DataFlag) -> Result<Option<PersistentObject>> {
+        match PersistentObject::open(self.storage_id, id, flags) {
+            Ok(object) => Ok(Some(object)),
+            Err(e) if e.kind() == ErrorKind::ItemNotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn current_version(&self, id: &[u8]) -> Result<Option<u64>> {
+        let Some(mut object) =
+            self.open_object(id, DataFlag::ACCESS_READ | DataFlag::SHARE_READ)?
+        else {
+            return Ok(None);
+        };
+        let mut header = [0u8; SCHEMA_HEADER_LEN];
+        let read = object.read(&mut header)? as usize;
+        parse_header(&header[..read]).map(|header| Some(header.version))
+    }
+
+    fn load(&self, id: &[u8]) -> Result<Option<(V, u64)>> {
+        let Some(mut object) =
+            self.open_object(id, DataFlag::ACCESS_READ | DataFlag::SHARE_READ)?
+        else {
+            return Ok(None);
+        };
+        let data = storage::read_all(&mut object)?;
+        let header = parse_header(&data)?;
+        if header.schema != self.schema {
+            return Err(Error::with_message(
+                ErrorKind::BadFormat,
+                "entry written with another schema version",
+            ));
+        }
+        let value = <Json as Decode<V>>::decode(&data[header.len..])?;
+        Ok(Some((value, header.version)))
+    }
+
+    fn store(&self, id: &[u8], previous: u64, value: &V) -> Result<u64> {
+        let version = previous.checked_add(1).ok_or(ErrorKind::Overflow)?;
+        let encoded = <Json as Encode<V>>::encode(value)?;
+        let header_len = if self.schema == 1 {
+            HEADER_LEN
+        } else {
+            SCHEMA_HEADER_LEN
+        };
+        storage::check_quota(
+            self.storage_id,
+            &self.prefix,
+            self.quota,
+            id,
+            header_len + encoded.len(),
+        )?;
+        self.write_entry(id, version, &encoded)?;
+        Ok(version)
+    }
+
+    /// Write the entry `id` at `version` with the encoded value `encoded` of
+    /// the schema version of the store.
+    fn write_entry(&self, id: &[u8], version: u64, encoded: &[u8]) -> Result<()> {
+        let mut data = Vec::with_capacity(SCHEMA_HEADER_LEN + encoded.len());
+        data.extend_from_slice(&MAGIC);
+        if self.schema == 1 {
+            data.push(FORMAT_VERSION);
+            data.extend_from_slice(&version.to_le_bytes());
+        } else {
+            data.push(SCHEMA_FORMAT_VERSION);
+            data.extend_from_slice(&version.to_le_bytes());
+            data.extend_from_slice(&self.schema.to_le_bytes());
+        }
+        data.extend_from_slice(encoded);
+        // Creating with OVERWRITE replaces an existing object atomically, and
+        // the initial data is written as part of the creation
+        PersistentObject::create(
+            self.storage_id,
+            id,
+            DataFlag::ACCESS_READ
+                | DataFlag::ACCESS_WRITE
+                | DataFlag::ACCESS_WRITE_META
+                | DataFlag::OVERWRITE,
+            None,
+            &data,
+        )?;
+        Ok(())
+    }
+}
+
+/// The header at the start of the data of an entry.
+struct Header {
+    /// Version of the entry, incremented by each write
+    version: u64,
+    /// Schema version of the value
+    schema: u32,
+    /// Length of the header, the encoded value follows
+    len: usize,
+}
+
+/// Parse the header at the start of `data`.
+fn parse_header(data: &[u8]) -> Result<Header> {
+    if data.len() < HEADER_LEN || data[..MAGIC.len()] != MAGIC {
+        return Err(ErrorKind::CorruptObject.into());
+    }
+    let mut version = [0u8; 8];
+    version.copy_from_slice(&data[MAGIC.len() + 1..HEADER_LEN]);
+    let version = u64::from_le_bytes(version);
+    match data[MAGIC.len()] {
+        FORMAT_VERSION => Ok(Header {
+            version,
+            schema: 1,
+            len: HEADER_LEN,
+        }),
+        SCHEMA_FORMAT_VERSION => {
+            let mut schema = [0u8; 4];
+            schema.copy_from_slice(
+                data.get(HEADER_LEN..SCHEMA_HEADER_LEN)
+                    .ok_or(ErrorKind::CorruptObject)?,
+            );
+            Ok(Header {
+                version,
+                schema: u32::from_le_bytes(schema),
+                len: SCHEMA_HEADER_LEN,
+            })
+        }
+        _ => Err(ErrorKind::BadFormat.into()),
+    }
+}
+
+/// An iterator over the entries of a [`SecureKvStore`], yielding each key,
+/// value and version. An error ends the iteration.
+pub struct Iter<'a, K, V> {
+    store: &'a SecureKvStore<K, V>,
+    objects: PersistentObjectIter,
+}
+
+impl<K, V> Iterator for Iter<'_, K, V>
+where
+    K: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    type Item = Result<(K, V, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let id = match self.objects.next()? {
+                Ok((id, _)) => id,
+                Err(e) => return Some(Err(e)),
+            };
+            let Some(key) = self.store.key_of(&id) else {
+                continue;
+            };
+            let entry = key.and_then(|key| Ok((key, self.store.load(&id)?)));
+            match entry {
+                Ok((key, Some((value, version)))) => return Some(Ok((key, value, version))),
+                // Removed since it was listed
+                Ok((_, None)) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::{String, ToString};
+
+    use serde::Deserialize;
+
+    use super::*;
+    use optee_utee_sys::mock_utils::storage::MockStorage;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Account {
+        owner: String,
+        balance: u64,
+    }
+
+    fn account(owner: &str, balance: u64) -> Account {
+        Account {
+            owner: owner.to_string(),
+            balance,
+        }
+    }
+
+    #[test]
+    fn test_put_get_remove() {
+        let storage = MockStorage::new();
+        let store: SecureKvStore<u32, Account> = SecureKvStore::open(b"accounts").unwrap();
+
+        assert_eq!(store.get(&1).unwrap(), None);
+        assert!(!store.contains_key(&1).unwrap());
+        assert_eq!(store.put(&1, &account("alice", 10)).unwrap(), 1);
+        assert_eq!(store.get(&1).unwrap(), Some(account("alice", 10)));
+        assert!(store.contains_key(&1).unwrap());
+
+        assert!(store.remove(&1).unwrap());
+        assert!(!store.remove(&1).unwrap());
+        assert_eq!(store.get(&1).unwrap(), None);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    fn test_versions() {
+        let _storage = MockStorage::new();
+        let store: SecureKvStore<String, u64> = SecureKvStore::open(b"counters").unwrap();
+        let key = "boot".to_string();
+
+        assert_eq!(store.version(&key).unwrap(), None);
+        assert_eq!(store.put(&key, &1).unwrap(), 1);
+        assert_eq!(store.put(&key, &2).unwrap(), 2);
+        assert_eq!(store.version(&key).unwrap(), Some(2));
+        assert_eq!(store.get_versioned(&key).unwrap(), Some((2, 2)));
+
+        // A removed entry starts over
+        store.remove(&key).unwrap();
+        assert_eq!(store.put(&key, &3).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_put_if_version() {
+        let _storage = MockStorage::new();
+        let store: SecureKvStore<String, u64> = SecureKvStore::open(b"counters").unwrap();
+        let key = "boot".to_string();
+
+        assert_eq!(store.put_if_version(&key, &1, None).unwrap(), 1);
+        let err = store.put_if_version(&key, &2, None).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AccessConflict);
+        let err = store.put_if_version(&key, &2, Some(2)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AccessConflict);
+        assert_eq!(store.get(&key).unwrap(), Some(1));
+
+        assert_eq!(store.put_if_version(&key, &2, Some(1)).unwrap(), 2);
+        assert_eq!(store.get(&key).unwrap(), Some(2));
+    }
+
+    #[test]
+    // The writes of another instance holding the lock are not interleaved.
+    fn test_locked_store() {
+        let storage = MockStorage::new();
+        let store: SecureKvStore<String, u64> = SecureKvStore::open(b"counters").unwrap();
+        let key = "boot".to_string();
+        assert_eq!(store.put(&key, &1).unwrap(), 1);
+
+        let lock = PersistentObject::open(
+            ObjectStorageConstants::Private,
+            b"counters\0",
+            DataFlag::ACCESS_READ,
+        )
+        .unwrap();
+        let err = store.put_if_version(&key, &2, Some(1)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AccessConflict);
+        assert_eq!(
+            store.put(&key, &2).unwrap_err().kind(),
+            ErrorKind::AccessConflict
+        );
+        assert_eq!(
+            store.remove(&key).unwrap_err().kind(),
+            ErrorKind::AccessConflict
+        );
+        // Reads do not take the lock
+        assert_eq!(store.get_versioned(&key).unwrap(), Some((1, 1)));
+        drop(lock);
+
+        assert_eq!(store.put_if_version(&key, &2, Some(1)).unwrap(), 2);
+        // The lock is not an entry
+        assert_eq!(store.keys().unwrap(), [key]);
+        assert_eq!(store.usage().unwrap().objects, 1);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    // A failed write leaves the previous value in place.
+    fn test_failed_put_keeps_value() {
+        let storage = MockStorage::new();
+        let store: SecureKvStore<u32, Account> = SecureKvStore::open(b"accounts").unwrap();
+
+        store.put(&1, &account("alice", 10)).unwrap();
+        storage.set_fail_create(true);
+        let err = store.put(&1, &account("alice", 0)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::StorageNoSpace);
+        storage.set_fail_create(false);
+
+        assert_eq!(
+            store.get_versioned(&1).unwrap(),
+            Some((account("alice", 10), 1))
+        );
+    }
+
+    #[test]
+    fn test_enumerate_namespace() {
+        let storage = MockStorage::new();
+        let accounts: SecureKvStore<u32, Account> = SecureKvStore::open(b"accounts").unwrap();
+        let other: SecureKvStore<u32, Account> = SecureKvStore::open(b"account").unwrap();
+        storage.insert_raw(b"unrelated", b"data");
+
+        accounts.put(&1, &account("alice", 10)).unwrap();
+        accounts.put(&2, &account("bob", 20)).unwrap();
+        accounts.put(&2, &account("bob", 25)).unwrap();
+        other.put(&3, &account("carol", 30)).unwrap();
+
+        let mut keys = accounts.keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, [1, 2]);
+
+        let mut entries: Vec<_> = accounts.iter().unwrap().map(Result::unwrap).collect();
+        entries.sort_by_key(|(key, _, _)| *key);
+        assert_eq!(
+            entries,
+            [(1, account("alice", 10), 1), (2, account("bob", 25), 2)]
+        );
+
+        assert_eq!(accounts.clear().unwrap(), 2);
+        assert!(accounts.keys().unwrap().is_empty());
+        assert_eq!(other.keys().unwrap(), [3]);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    fn test_empty_storage() {
+        let _storage = MockStorage::new();
+        let store: SecureKvStore<u32, u32> = SecureKvStore::open(b"empty").unwrap();
+
+        assert!(store.keys().unwrap().is_empty());
+        assert!(store.iter().unwrap().next().is_none());
+        assert_eq!(store.clear().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let storage = MockStorage::new();
+        let store: SecureKvStore<u32, u32> = SecureKvStore::open(b"ns").unwrap();
+
+        storage.insert_raw(b"ns\x001", b"not an entry");
+        assert_eq!(store.get(&1).unwrap_err().kind(), ErrorKind::CorruptObject);
+
+        storage.insert_raw(b"ns\x002", b"KV\x03\x01\0\0\0\0\0\0\x001");
+        assert_eq!(store.get(&2).unwrap_err().kind(), ErrorKind::BadFormat);
+
+        storage.insert_raw(b"ns\x003", b"KV\x01\x01\0\0\0\0\0\0\0\"x\"");
+        assert_eq!(store.get(&3).unwrap_err().kind(), ErrorKind::BadFormat);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    fn test_quota() {
+        let _storage = MockStorage::new();
+        let store: SecureKvStore<u32, u32> = SecureKvStore::open(b"ns")
+            .unwrap()
+            .with_quota(Quota::default().max_objects(2));
+        let other: SecureKvStore<u32, u32> = SecureKvStore::open(b"other").unwrap();
+
+        store.put(&1, &10).unwrap();
+        store.put(&2, &20).unwrap();
+        store.put(&2, &21).unwrap();
+        let err = store.put(&3, &30).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::StorageNoSpace);
+        other.put(&3, &30).unwrap();
+
+        // 11 bytes of header and 2 of value per entry
+        assert_eq!(
+            store.usage().unwrap(),
+            Usage {
+                objects: 2,
+                bytes: 26
+            }
+        );
+        store.remove(&1).unwrap();
+        store.put(&3, &30).unwrap();
+    }
+
+    #[test]
+    fn test_migrate() {
+        #[derive(Serialize, Deserialize, Debug)]
+        struct AccountV1 {
+            owner: String,
+            balance: u32,
+        }
+        #[derive(Serialize, Deserialize, Debug)]
+        struct AccountV2 {
+            name: String,
+            balance: u64,
+        }
+
+        fn migrator() -> StateMigrator<Account> {
+            StateMigrator::<AccountV1>::new()
+                .migration(|old: AccountV1| AccountV2 {
+                    name: old.owner,
+                    balance: old.balance.into(),
+                })
+                .migration(|old: AccountV2| account(&old.name, old.balance * 10))
+        }
+
+        let storage = MockStorage::new();
+        let v1: SecureKvStore<u32, AccountV1> = SecureKvStore::open(b"accounts").unwrap();
+        let v1_account = |owner: &str, balance| AccountV1 {
+            owner: owner.to_string(),
+            balance,
+        };
+        v1.put(&1, &v1_account("alice", 1)).unwrap();
+        v1.put(&1, &v1_account("alice", 2)).unwrap();
+        v1.put(&2, &v1_account("bob", 3)).unwrap();
+
+        let store: SecureKvStore<u32, Account> = SecureKvStore::open(b"accounts")
+            .unwrap()
+            .migrate(&migrator())
+            .unwrap();
+        assert_eq!(migrator().version(), 3);
+        // The versions of the entries are kept
+        assert_eq!(
+            store.get_versioned(&1).unwrap(),
+            Some((account("alice", 20), 2))
+        );
+        assert_eq!(store.get(&2).unwrap(), Some(account("bob", 30)));
+        assert_eq!(store.put(&3, &account("carol", 40)).unwrap(), 1);
+
+        // Migrating again changes nothing, and the previous schema is rejected
+        let store = SecureKvStore::open(b"accounts")
+            .unwrap()
+            .migrate(&migrator())
+            .unwrap();
+        assert_eq!(store.get(&3).unwrap(), Some(account("carol", 40)));
+        let err = v1.get(&1).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadFormat);
+        let err = SecureKvStore::<u32, AccountV1>::open(b"accounts")
+            .unwrap()
+            .migrate(&StateMigrator::new())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::BadFormat);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    fn test_invalid_ids() {
+        let _storage = MockStorage::new();
+
+        assert!(SecureKvStore::<u32, u32>::open(b"a\0b").is_err());
+        assert!(SecureKvStore::<u32, u32>::open(&[b'a'; 63]).is_err());
+
+        let store: SecureKvStore<String, u32> = SecureKvStore::open(b"ns").unwrap();
+        let err = store.put(&"k".repeat(64), &1).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadParameters);
+    }
+}
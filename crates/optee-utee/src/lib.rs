@@ -26,17 +26,15 @@
 // Requires `alloc`.
 #[macro_use]
 extern crate alloc;
-
-#[cfg(not(feature = "std"))]
-use libc_alloc::LibcAlloc;
-
-#[cfg(not(feature = "std"))]
-#[global_allocator]
-static ALLOCATOR: LibcAlloc = LibcAlloc;
+// Lets the code generated by the macros refer to this crate in its own tests.
+#[cfg(test)]
+extern crate self as optee_utee;
 
 #[cfg(all(not(feature = "std"), not(feature = "no_panic_handler")))]
 #[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    let message = info.message();
+    panic::run_hook(&panic::PanicReport::new(info.location(), &message));
     unsafe {
         optee_utee_sys::TEE_Panic(0);
     }
@@ -59,26 +57,33 @@ mod unwind_stubs {
 
 pub use arithmetical::*;
 pub use crypto_op::*;
+pub use dispatch::TaCommand;
 pub use error::{Error, ErrorKind, Result};
 pub use extension::*;
 pub use identity::{Identity, LoginType};
 pub use object::*;
 pub use optee_utee_macros::{
-    ta_close_session, ta_create, ta_destroy, ta_invoke_command, ta_open_session,
+    TaCommand, ta_close_session, ta_config, ta_create, ta_destroy, ta_dispatch, ta_invoke_command,
+    ta_open_session, ta_self_test,
 };
 pub use parameter::{
     FromRawParameter, FromRawParameters, ParamType, ParameterAny, ParametersAny, ParametersNone,
     RawParamType, RawParamTypes, RawParams, deprecated,
     memref::{
-        ParameterMemrefInout, ParameterMemrefInput, ParameterMemrefOutput, ParameterMemrefRead,
-        ParameterMemrefWrite,
+        MemrefSnapshot, OutputWriter, ParameterMemrefInout, ParameterMemrefInput,
+        ParameterMemrefOutput, ParameterMemrefRead, ParameterMemrefWrite,
     },
     none::ParameterNone,
+    raw_param_types,
     value::{
         ParameterValueInout, ParameterValueInput, ParameterValueOutput, ParameterValueRead,
         ParameterValueWrite,
     },
 };
+pub use property::{Property, PropertySet};
+#[cfg(feature = "rand_core")]
+pub use rng::OpteeRng;
+pub use session_registry::{SessionId, SessionRegistry};
 pub use ta_session::{TaSession, TaSessionBuilder};
 pub use tee_parameter::{ParamIndex, TeeParams};
 pub use time::*;
@@ -87,30 +92,64 @@ pub use uuid::*;
 pub mod trace;
 #[macro_use]
 mod macros;
+pub mod arena;
 pub mod arithmetical;
+#[cfg(feature = "attestation")]
+pub mod attestation;
+pub mod cancellation;
+pub mod chunked;
+pub mod crypto;
 pub mod crypto_op;
+pub mod dispatch;
 mod error;
 pub mod extension;
+#[cfg(feature = "fault_injection")]
+pub mod fault_injection;
+#[cfg(not(feature = "fault_injection"))]
+mod fault_injection;
+pub mod heap;
 pub mod identity;
+#[cfg(feature = "kv")]
+pub mod kv;
+#[cfg(feature = "log")]
+pub mod logger;
+pub mod memory;
 pub mod net;
 pub mod object;
+pub mod panic;
 mod parameter;
 pub mod property;
+pub mod ring;
+#[cfg(feature = "rand_core")]
+mod rng;
+pub mod rollback;
+pub mod secure_mem;
+pub mod self_test;
+pub mod server;
+pub mod session_registry;
+pub mod storage;
 mod ta_session;
 mod tee_parameter;
 pub mod time;
+pub mod tui;
+pub mod user_auth;
 pub mod uuid;
 
 // Re-export optee_utee_sys so developers don't have to add it to their cargo
 // dependencies.
 pub use optee_utee_sys as raw;
 
+// Host-side simulator of the TEE functions, see the crate documentation.
+#[cfg(feature = "mock")]
+pub use optee_utee_mock as mock;
+
 pub mod prelude {
     pub use crate::{
-        FromRawParameter, FromRawParameters, ParameterAny, ParameterMemrefInout,
+        FromRawParameter, FromRawParameters, OutputWriter, ParameterAny, ParameterMemrefInout,
         ParameterMemrefInput, ParameterMemrefOutput, ParameterMemrefRead, ParameterMemrefWrite,
         ParameterNone, ParameterValueInout, ParameterValueInput, ParameterValueOutput,
-        ParameterValueRead, ParameterValueWrite, ParametersAny, ParametersNone, ta_close_session,
-        ta_create, ta_destroy, ta_invoke_command, ta_open_session, trace_print, trace_println,
+        ParameterValueRead, ParameterValueWrite, ParametersAny, ParametersNone, TaCommand,
+        ta_close_session, ta_config, ta_create, ta_destroy, ta_dispatch, ta_invoke_command,
+        ta_open_session, ta_self_test, trace_print, trace_println,
     };
 }
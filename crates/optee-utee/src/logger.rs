@@ -0,0 +1,225 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Logger printing the records of the [`log`] crate to the trace output.
+//!
+//! Once installed, typically from `#[ta_create]`, the `log` macros can be used
+//! in the TA and in any crate it shares with the host:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::logger;
+//! # fn create() -> optee_utee::Result<()> {
+//! logger::Builder::new()
+//!     .level(log::LevelFilter::Debug)
+//!     .module_path(true)
+//!     .init()
+//!     .expect("no other logger is installed");
+//! log::info!("TA created");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! which prints `[INFO] my_ta: TA created`.
+//!
+//! Records above the level of the logger are dropped at runtime, the level
+//! can be changed at any time with [`set_level`]. To remove the logging code
+//! of some levels from the TA altogether, enable one of the `max_level_*` or
+//! `release_max_level_*` features of the `log` crate in the TA's Cargo.toml.
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+static LOGGER: TraceLogger = TraceLogger {
+    module_path: AtomicBool::new(false),
+};
+
+struct TraceLogger {
+    module_path: AtomicBool,
+}
+
+impl Log for TraceLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        emit(format_args!(
+            "{}\n",
+            Line {
+                record,
+                module_path: self.module_path.load(Ordering::Relaxed),
+            }
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+/// A record as printed to the trace output.
+struct Line<'a> {
+    record: &'a Record<'a>,
+    module_path: bool,
+}
+
+impl fmt::Display for Line<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] ", self.record.level())?;
+        if self.module_path
+            && let Some(module_path) = self.record.module_path()
+        {
+            write!(f, "{}: ", module_path)?;
+        }
+        write!(f, "{}", self.record.args())
+    }
+}
+
+// The trace syscall is not mocked, so unit tests capture the output instead
+#[cfg(not(test))]
+fn emit(args: fmt::Arguments) {
+    crate::trace::Trace::_print(args);
+}
+
+#[cfg(test)]
+extern crate std;
+
+#[cfg(test)]
+static CAPTURED: std::sync::Mutex<std::string::String> =
+    std::sync::Mutex::new(std::string::String::new());
+
+#[cfg(test)]
+fn emit(args: fmt::Arguments) {
+    use fmt::Write;
+    let _ = CAPTURED.lock().unwrap().write_fmt(args);
+}
+
+/// Configures and installs the logger.
+pub struct Builder {
+    level: LevelFilter,
+    module_path: bool,
+}
+
+impl Builder {
+    /// Create a builder for a logger printing records up to `Info`, without
+    /// module paths.
+    pub fn new() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            module_path: false,
+        }
+    }
+
+    /// Print the records up to `level`.
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Prefix the records with the module path they were logged from.
+    pub fn module_path(mut self, enabled: bool) -> Self {
+        self.module_path = enabled;
+        self
+    }
+
+    /// Install the logger for the `log` macros.
+    ///
+    /// # Errors
+    ///
+    /// If a logger is already installed, the settings are left unchanged then.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        log::set_logger(&LOGGER)?;
+        LOGGER
+            .module_path
+            .store(self.module_path, Ordering::Relaxed);
+        log::set_max_level(self.level);
+        Ok(())
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Install the logger with the default settings of [`Builder::new`].
+pub fn init() -> Result<(), SetLoggerError> {
+    Builder::new().init()
+}
+
+/// Print the records up to `level` from now on.
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+
+    use log::Level;
+
+    use super::*;
+
+    fn line(level: Level, module_path: bool) -> std::string::String {
+        Line {
+            record: &Record::builder()
+                .level(level)
+                .module_path(Some("my_ta::storage"))
+                .args(format_args!("{} bytes", 42))
+                .build(),
+            module_path,
+        }
+        .to_string()
+    }
+
+    #[test]
+    fn test_line_format() {
+        assert_eq!(line(Level::Warn, false), "[WARN] 42 bytes");
+        assert_eq!(line(Level::Debug, true), "[DEBUG] my_ta::storage: 42 bytes");
+    }
+
+    #[test]
+    // The only test installing the logger, which is global to the process.
+    fn test_logger() {
+        Builder::new()
+            .level(LevelFilter::Info)
+            .module_path(true)
+            .init()
+            .expect("it should be ok");
+        assert!(init().is_err());
+
+        log::info!("shown");
+        log::debug!("hidden");
+        set_level(LevelFilter::Debug);
+        log::debug!("shown at debug");
+        set_level(LevelFilter::Off);
+        log::error!("hidden");
+
+        let captured = CAPTURED.lock().unwrap().clone();
+        let module = module_path!();
+        assert_eq!(
+            captured,
+            std::format!("[INFO] {module}: shown\n[DEBUG] {module}: shown at debug\n")
+        );
+    }
+}
@@ -0,0 +1,143 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Checks of the access rights of the TA to memory.
+//!
+//! The buffers of memref parameters come from the client, a TA must not
+//! trust them to lie in memory it is allowed to use. [`check_buffer_access`]
+//! asks the TEE core whether the TA can read or write a range, so a client
+//! passing an address of the secure world or an unmapped range is answered
+//! with an error instead of making the TA fault or leak its own memory:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::memory::{AccessFlags, check_buffer_access};
+//! # fn main() -> optee_utee::Result<()> {
+//! # let (buffer, size) = (core::ptr::null::<u8>(), 0);
+//! check_buffer_access(buffer, size, AccessFlags::READ | AccessFlags::ANY_OWNER)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `strict_checks` feature, the memref parameters are checked this
+//! way when they are converted, with the rights of their direction.
+
+use bitflags::bitflags;
+use optee_utee_sys as raw;
+
+use crate::{Error, Result};
+
+bitflags! {
+    /// The rights checked by [`check_buffer_access`].
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct AccessFlags: u32 {
+        /// The TA can read the memory.
+        const READ = raw::TEE_MEMORY_ACCESS_READ;
+        /// The TA can write the memory.
+        const WRITE = raw::TEE_MEMORY_ACCESS_WRITE;
+        /// The memory may be shared with the client or other TAs, as the
+        /// buffers of memref parameters are. Without this flag the memory
+        /// must be private to the TA.
+        const ANY_OWNER = raw::TEE_MEMORY_ACCESS_ANY_OWNER;
+    }
+}
+
+/// Check that the TA has the access rights `flags` on the `size` bytes at
+/// `buffer`. Empty ranges are always accessible.
+///
+/// # Errors
+///
+/// `AccessDenied`: If any byte of the range is not accessible with these
+/// rights.
+pub fn check_buffer_access(buffer: *const u8, size: usize, flags: AccessFlags) -> Result<()> {
+    if size == 0 {
+        return Ok(());
+    }
+    match unsafe { raw::TEE_CheckMemoryAccessRights(flags.bits(), buffer as _, size) } {
+        raw::TEE_SUCCESS => Ok(()),
+        code => Err(Error::from_raw_error(code)),
+    }
+}
+
+/// Check the access rights `flags` on the memory of `buffer`, e.g. to check
+/// that a slice built from the address of a client is not secure memory of
+/// the TA.
+pub fn check_slice_access(buffer: &[u8], flags: AccessFlags) -> Result<()> {
+    check_buffer_access(buffer.as_ptr(), buffer.len(), flags)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use optee_utee_sys::{mock_api, mock_utils::SERIAL_TEST_LOCK};
+
+    use super::*;
+    use crate::ErrorKind;
+
+    /// Let the access checks of the TEE core pass until the result is
+    /// dropped, for tests converting memref parameters with
+    /// `strict_checks`. Holds the lock of the mocked API.
+    #[cfg(feature = "strict_checks")]
+    pub(crate) fn allow_access() -> impl Sized {
+        let lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let check = mock_api::TEE_CheckMemoryAccessRights_context();
+        check.expect().returning(|flags, _, _| {
+            assert!(flags & raw::TEE_MEMORY_ACCESS_ANY_OWNER != 0);
+            raw::TEE_SUCCESS
+        });
+        // The expectation is cleared before the lock is released
+        (check, lock)
+    }
+
+    #[test]
+    fn test_check_buffer_access() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let buffer = [0u8; 4];
+        let address = buffer.as_ptr() as usize;
+        let check = mock_api::TEE_CheckMemoryAccessRights_context();
+        check
+            .expect()
+            .withf(move |flags, ptr, size| {
+                *flags == raw::TEE_MEMORY_ACCESS_READ | raw::TEE_MEMORY_ACCESS_WRITE
+                    && *ptr as usize == address
+                    && *size == 4
+            })
+            .return_const(raw::TEE_SUCCESS);
+        assert!(check_slice_access(&buffer, AccessFlags::READ | AccessFlags::WRITE).is_ok());
+    }
+
+    #[test]
+    fn test_access_denied() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let check = mock_api::TEE_CheckMemoryAccessRights_context();
+        check.expect().return_const(raw::TEE_ERROR_ACCESS_DENIED);
+        assert_eq!(
+            check_buffer_access(0x1000 as *const u8, 8, AccessFlags::READ)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::AccessDenied
+        );
+    }
+
+    #[test]
+    fn test_empty_range() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        // The TEE core is not asked, even for a null buffer
+        let check = mock_api::TEE_CheckMemoryAccessRights_context();
+        check.expect().never();
+        assert!(check_buffer_access(core::ptr::null(), 0, AccessFlags::WRITE).is_ok());
+        assert!(check_slice_access(&[], AccessFlags::READ).is_ok());
+    }
+}
@@ -0,0 +1,795 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! A minimal HTTP/1.1 client, for TAs calling REST APIs over a
+//! [`TcpStream`](super::TcpStream) or a TLS connection.
+//!
+//! ``` rust,no_run
+//! use optee_utee::net::http::Request;
+//! use optee_utee::net::TcpStream;
+//! # use optee_utee::net::Setup;
+//!
+//! # fn main() -> optee_utee::Result<()> {
+//! # let mut stream = TcpStream::open(Setup::new_v4("10.0.2.2", 80)?).unwrap();
+//! let response = Request::get("10.0.2.2", "/price?pair=ETH-USD")
+//!     .header("Accept", "application/json")
+//!     .send(&mut stream)?;
+//! if response.is_success() {
+//!     let price = core::str::from_utf8(response.body()).unwrap_or_default();
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A request is sent with `Connection: close` unless it sets the header, and
+//! the body of the response is read according to its `Transfer-Encoding`,
+//! including chunked, or its `Content-Length`, or until the server closes the
+//! connection. The response is bounded by [`Limits`], so that a server cannot
+//! exhaust the TA heap.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Error, ErrorKind, Result};
+
+/// The method of a [`Request`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+impl Method {
+    /// The name of the method in the request line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The headers of a request or a response, in their order, with names
+/// compared case-insensitively.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    /// An empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value of the first header `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The values of all the headers `name`, e.g. of `Set-Cookie`.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether there is a header `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets the header `name` to `value`, replacing the headers of that name.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append(name, value);
+    }
+
+    /// Adds a header `name`, keeping the ones of that name.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+
+    /// Removes the headers `name`.
+    pub fn remove(&mut self, name: &str) {
+        self.entries
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+
+    /// The names and values of the headers.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// The number of headers.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there is no header.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Bounds of a response, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Limit of the status line and the headers together.
+    pub headers: usize,
+    /// Limit of the body, after chunked decoding.
+    pub body: usize,
+}
+
+impl Default for Limits {
+    /// 16 KiB of headers and 1 MiB of body.
+    fn default() -> Self {
+        Self {
+            headers: 16 * 1024,
+            body: 1024 * 1024,
+        }
+    }
+}
+
+/// A connection a request is sent over.
+///
+/// With the `std` feature, it is implemented by the `std::io::Read` and
+/// `std::io::Write` streams, e.g. [`TcpStream`](super::TcpStream) and the
+/// TLS streams of `optee-tls`. Without it, it is implemented by the
+/// [`Socket`](super::Socket)s.
+pub trait Connection {
+    /// Sends some of `buf`, returning how much was sent.
+    fn send(&mut self, buf: &[u8]) -> Result<usize>;
+    /// Receives into `buf`, returning how much was received, 0 once the peer
+    /// closed the connection.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize>;
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::Read + std::io::Write> Connection for T {
+    fn send(&mut self, buf: &[u8]) -> Result<usize> {
+        loop {
+            match self.write(buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => return result.map_err(|_| io_error()),
+            }
+        }
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.read(buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                // A TLS peer closing the connection without close_notify,
+                // which responses delimited by the length or the chunks
+                // detect anyway
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(0),
+                result => return result.map_err(|_| io_error()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn io_error() -> Error {
+    Error::with_message(ErrorKind::Communication, "HTTP connection failed")
+}
+
+#[cfg(not(feature = "std"))]
+impl<T: super::SocketAdapter> Connection for super::Socket<T> {
+    fn send(&mut self, buf: &[u8]) -> Result<usize> {
+        super::Socket::send(self, buf).map_err(socket_error)
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match super::Socket::recv(self, buf) {
+            Err(super::SocketError::RemoteClosed) => Ok(0),
+            result => result.map_err(socket_error),
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn socket_error(error: super::SocketError) -> Error {
+    match error {
+        super::SocketError::Tee(kind) => kind.into(),
+        _ => Error::with_message(ErrorKind::Communication, "HTTP connection failed"),
+    }
+}
+
+/// An HTTP/1.1 request.
+#[derive(Clone, Debug)]
+pub struct Request {
+    method: Method,
+    host: String,
+    path: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    limits: Limits,
+}
+
+impl Request {
+    /// A `GET` request of `path` on `host`, which is sent as the `Host`
+    /// header and may include a port, e.g. `example.com:8080`.
+    pub fn get(host: &str, path: &str) -> Self {
+        Self::new(Method::Get, host, path, Vec::new())
+    }
+
+    /// A `POST` request of `body` to `path` on `host`, see
+    /// [`get`](Self::get).
+    pub fn post(host: &str, path: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self::new(Method::Post, host, path, body.into())
+    }
+
+    fn new(method: Method, host: &str, path: &str, body: Vec<u8>) -> Self {
+        Self {
+            method,
+            host: host.to_string(),
+            path: path.to_string(),
+            headers: HeaderMap::new(),
+            body,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Adds the header `name`, e.g. `Authorization` or `Content-Type`.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Sets the bounds of the response.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The method of the request.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The headers of the request, without the ones added when it is sent.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Sends the request over `conn` and reads the response.
+    ///
+    /// Fails with `BadParameters` if the request has line breaks in its
+    /// path or headers, with `BadFormat` if the response is not valid
+    /// HTTP/1.1, with `ExcessData` if it exceeds the [`Limits`], and with
+    /// `Communication` if the connection fails or is closed early.
+    pub fn send<C: Connection>(&self, conn: &mut C) -> Result<Response> {
+        let mut request = self.encode()?.into_bytes();
+        request.extend_from_slice(&self.body);
+        let mut pending = request.as_slice();
+        while !pending.is_empty() {
+            match conn.send(pending)? {
+                0 => {
+                    return Err(Error::with_message(
+                        ErrorKind::Communication,
+                        "HTTP connection closed while sending the request",
+                    ));
+                }
+                n => pending = &pending[n..],
+            }
+        }
+        Reader::new(conn, self.limits).response()
+    }
+
+    /// The request line and the headers.
+    fn encode(&self) -> Result<String> {
+        let is_valid = |field: &str| !field.contains(['\r', '\n']);
+        let is_token = |name: &str| !name.is_empty() && !name.contains([':', ' ', '\t']);
+        if !is_valid(&self.host)
+            || !is_valid(&self.path)
+            || !self.path.starts_with('/')
+            || self.path.contains(' ')
+            || self
+                .headers
+                .iter()
+                .any(|(name, value)| !is_token(name) || !is_valid(name) || !is_valid(value))
+        {
+            return Err(Error::with_message(
+                ErrorKind::BadParameters,
+                "Invalid HTTP request line or header",
+            ));
+        }
+
+        let mut head = alloc::format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\n",
+            self.method,
+            self.path,
+            self.host
+        );
+        for (name, value) in self.headers.iter() {
+            head.push_str(&alloc::format!("{}: {}\r\n", name, value));
+        }
+        if !self.headers.contains("Connection") {
+            head.push_str("Connection: close\r\n");
+        }
+        if (self.method == Method::Post || !self.body.is_empty())
+            && !self.headers.contains("Content-Length")
+        {
+            head.push_str(&alloc::format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+        Ok(head)
+    }
+}
+
+/// An HTTP/1.1 response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    status: u16,
+    reason: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// The status code, e.g. 200.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The reason phrase of the status, e.g. `OK`.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Whether the status is a success, 2xx.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body of the response, decoded if it was chunked.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Takes the body of the response.
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+}
+
+fn bad_format(message: &'static str) -> Error {
+    Error::with_message(ErrorKind::BadFormat, message)
+}
+
+fn closed() -> Error {
+    Error::with_message(
+        ErrorKind::Communication,
+        "HTTP connection closed before the end of the response",
+    )
+}
+
+/// Reads a response from a connection, through a buffer.
+struct Reader<'a, C> {
+    conn: &'a mut C,
+    limits: Limits,
+    buf: Vec<u8>,
+    start: usize,
+}
+
+impl<'a, C: Connection> Reader<'a, C> {
+    fn new(conn: &'a mut C, limits: Limits) -> Self {
+        Self {
+            conn,
+            limits,
+            buf: Vec::new(),
+            start: 0,
+        }
+    }
+
+    /// Receives more data, returning false once the peer closed the
+    /// connection.
+    fn fill(&mut self) -> Result<bool> {
+        self.buf.drain(..self.start);
+        self.start = 0;
+        let mut chunk = [0u8; 1024];
+        let n = self.conn.recv(&mut chunk)?;
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(n > 0)
+    }
+
+    /// Reads a line without its CRLF, of at most `limit` bytes.
+    fn line(&mut self, limit: usize) -> Result<String> {
+        let mut searched = 0;
+        loop {
+            let available = &self.buf[self.start..];
+            if let Some(pos) = available[searched..]
+                .windows(2)
+                .position(|window| window == b"\r\n")
+            {
+                let end = searched + pos;
+                if end > limit {
+                    break;
+                }
+                let line = core::str::from_utf8(&available[..end])
+                    .map_err(|_| bad_format("HTTP header is not valid UTF-8"))?
+                    .to_string();
+                self.start += end + 2;
+                return Ok(line);
+            }
+            // Leave room for the CR of a CRLF split across receptions
+            if available.len() > limit.saturating_add(1) {
+                break;
+            }
+            searched = available.len().saturating_sub(1);
+            if !self.fill()? {
+                return Err(closed());
+            }
+        }
+        Err(Error::with_message(
+            ErrorKind::ExcessData,
+            "HTTP response exceeded its header limit",
+        ))
+    }
+
+    /// Appends the next `len` bytes to `body`.
+    fn exact(&mut self, len: usize, body: &mut Vec<u8>) -> Result<()> {
+        let mut left = len;
+        while left > 0 {
+            if self.start == self.buf.len() && !self.fill()? {
+                return Err(closed());
+            }
+            let n = left.min(self.buf.len() - self.start);
+            body.extend_from_slice(&self.buf[self.start..self.start + n]);
+            self.start += n;
+            left -= n;
+        }
+        Ok(())
+    }
+
+    /// Appends everything until the peer closes the connection to `body`.
+    fn until_closed(&mut self, body: &mut Vec<u8>) -> Result<()> {
+        loop {
+            body.extend_from_slice(&self.buf[self.start..]);
+            self.start = self.buf.len();
+            if body.len() > self.limits.body {
+                return Err(too_large());
+            }
+            if !self.fill()? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn response(mut self) -> Result<Response> {
+        let mut budget = self.limits.headers;
+        loop {
+            let (status, reason) = parse_status_line(&self.head_line(&mut budget)?)?;
+            let mut headers = HeaderMap::new();
+            loop {
+                let line = self.head_line(&mut budget)?;
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line
+                    .split_once(':')
+                    .filter(|(name, _)| !name.is_empty() && !name.ends_with([' ', '\t']))
+                    .ok_or_else(|| bad_format("Invalid HTTP header"))?;
+                headers.append(name, value.trim_matches([' ', '\t']));
+            }
+            // Interim responses, e.g. 100 Continue, precede the final one
+            if (100..200).contains(&status) {
+                continue;
+            }
+            let body = self.body(status, &headers)?;
+            return Ok(Response {
+                status,
+                reason,
+                headers,
+                body,
+            });
+        }
+    }
+
+    /// Reads a line of the head of the response, within what is left of the
+    /// header limit in `budget`.
+    fn head_line(&mut self, budget: &mut usize) -> Result<String> {
+        let line = self.line(*budget)?;
+        *budget = budget.saturating_sub(line.len() + 2);
+        Ok(line)
+    }
+
+    fn body(&mut self, status: u16, headers: &HeaderMap) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        if status == 204 || status == 304 {
+            return Ok(body);
+        }
+        if let Some(encoding) = headers.get("Transfer-Encoding") {
+            let chunked = encoding
+                .rsplit(',')
+                .next()
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+            if !chunked {
+                return Err(bad_format("Unsupported HTTP transfer encoding"));
+            }
+            self.chunks(&mut body)?;
+        } else if let Some(length) = headers.get("Content-Length") {
+            let length: usize = length
+                .parse()
+                .map_err(|_| bad_format("Invalid HTTP Content-Length"))?;
+            if length > self.limits.body {
+                return Err(too_large());
+            }
+            self.exact(length, &mut body)?;
+        } else {
+            self.until_closed(&mut body)?;
+        }
+        Ok(body)
+    }
+
+    /// Decodes a chunked body into `body`, skipping the trailers.
+    fn chunks(&mut self, body: &mut Vec<u8>) -> Result<()> {
+        let line_limit = self.limits.headers;
+        loop {
+            let line = self.line(line_limit)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| bad_format("Invalid HTTP chunk size"))?;
+            if size == 0 {
+                while !self.line(line_limit)?.is_empty() {}
+                return Ok(());
+            }
+            if body.len().saturating_add(size) > self.limits.body {
+                return Err(too_large());
+            }
+            self.exact(size, body)?;
+            let mut crlf = Vec::with_capacity(2);
+            self.exact(2, &mut crlf)?;
+            if crlf != b"\r\n" {
+                return Err(bad_format("Invalid HTTP chunk"));
+            }
+        }
+    }
+}
+
+fn too_large() -> Error {
+    Error::with_message(
+        ErrorKind::ExcessData,
+        "HTTP response exceeded its body limit",
+    )
+}
+
+/// Parses `HTTP/1.1 200 OK` into the status and the reason.
+fn parse_status_line(line: &str) -> Result<(u16, String)> {
+    let invalid = || bad_format("Invalid HTTP status line");
+    let rest = line
+        .strip_prefix("HTTP/1.1 ")
+        .or_else(|| line.strip_prefix("HTTP/1.0 "))
+        .ok_or_else(invalid)?;
+    let (code, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+    if code.len() != 3 {
+        return Err(invalid());
+    }
+    let status = code.parse().map_err(|_| invalid())?;
+    Ok((status, reason.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// A connection receiving `input` a few bytes at a time and recording
+    /// what is sent.
+    struct Mock {
+        input: Vec<u8>,
+        pos: usize,
+        sent: Vec<u8>,
+    }
+
+    impl Mock {
+        fn new(input: &[u8]) -> Self {
+            Self {
+                input: input.to_vec(),
+                pos: 0,
+                sent: Vec::new(),
+            }
+        }
+    }
+
+    impl Connection for Mock {
+        fn send(&mut self, buf: &[u8]) -> Result<usize> {
+            // Partial writes
+            let n = buf.len().min(5);
+            self.sent.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = buf.len().min(7).min(self.input.len() - self.pos);
+            buf[..n].copy_from_slice(&self.input[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    fn send(request: &Request, response: &[u8]) -> Result<Response> {
+        request.send(&mut Mock::new(response))
+    }
+
+    #[test]
+    fn test_request() {
+        let mut conn = Mock::new(b"HTTP/1.1 204 No Content\r\n\r\n");
+        Request::get("example.com", "/a?b=c")
+            .header("Accept", "application/json")
+            .send(&mut conn)
+            .unwrap();
+        assert_eq!(
+            conn.sent,
+            b"GET /a?b=c HTTP/1.1\r\nHost: example.com\r\nAccept: application/json\r\n\
+              Connection: close\r\n\r\n"
+        );
+
+        let mut conn = Mock::new(b"HTTP/1.1 204 No Content\r\n\r\n");
+        Request::post("example.com", "/", "{}")
+            .header("connection", "keep-alive")
+            .send(&mut conn)
+            .unwrap();
+        assert_eq!(
+            conn.sent,
+            b"POST / HTTP/1.1\r\nHost: example.com\r\nconnection: keep-alive\r\n\
+              Content-Length: 2\r\n\r\n{}"
+        );
+
+        for request in [
+            Request::get("example.com", "/\r\nX: y"),
+            Request::get("example.com", "no-slash"),
+            Request::get("example.com", "/").header("X-Bad\n", "value"),
+            Request::get("example.com", "/").header("X-Bad", "value\r\n"),
+        ] {
+            assert_eq!(
+                send(&request, b"").unwrap_err().kind(),
+                ErrorKind::BadParameters
+            );
+        }
+    }
+
+    #[test]
+    fn test_response() {
+        let request = Request::get("example.com", "/");
+        let response = send(
+            &request,
+            b"HTTP/1.1 100 Continue\r\n\r\n\
+              HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nset-cookie: a\r\n\
+              Set-Cookie:b\r\nContent-Length: 5\r\n\r\nhello, ignored",
+        )
+        .unwrap();
+        assert_eq!((response.status(), response.reason()), (200, "OK"));
+        assert!(response.is_success());
+        assert_eq!(response.headers().get("content-type"), Some("text/plain"));
+        assert_eq!(
+            response.headers().get_all("Set-Cookie").collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert_eq!(response.body(), b"hello");
+
+        // Until the connection is closed
+        let response = send(&request, b"HTTP/1.0 404 Not Found\r\n\r\nnot here").unwrap();
+        assert_eq!(response.status(), 404);
+        assert!(!response.is_success());
+        assert_eq!(response.into_body(), b"not here");
+    }
+
+    #[test]
+    fn test_chunked() {
+        let request = Request::get("example.com", "/");
+        let response = send(
+            &request,
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\nTrailer: x\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(response.body(), b"hello, world");
+
+        for invalid in [
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nz\r\n"[..],
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\n\r\n",
+        ] {
+            assert_eq!(
+                send(&request, invalid).unwrap_err().kind(),
+                ErrorKind::BadFormat
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_response() {
+        let request = Request::get("example.com", "/");
+        for (response, kind) in [
+            (&b"HTTP/2 200 OK\r\n\r\n"[..], ErrorKind::BadFormat),
+            (b"HTTP/1.1 2000 OK\r\n\r\n", ErrorKind::BadFormat),
+            (b"HTTP/1.1 200 OK\r\nNo colon\r\n\r\n", ErrorKind::BadFormat),
+            (
+                b"HTTP/1.1 200 OK\r\nContent-Length: x\r\n\r\n",
+                ErrorKind::BadFormat,
+            ),
+            (b"HTTP/1.1 200 OK\r\n", ErrorKind::Communication),
+            (
+                b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort",
+                ErrorKind::Communication,
+            ),
+        ] {
+            assert_eq!(send(&request, response).unwrap_err().kind(), kind);
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let request = Request::get("example.com", "/").limits(Limits {
+            headers: 40,
+            body: 4,
+        });
+        assert!(
+            send(
+                &request,
+                b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nbody"
+            )
+            .is_ok()
+        );
+        for response in [
+            &b"HTTP/1.1 200 OK\r\nX-Long: 0123456789abcdefghij\r\n\r\n"[..],
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nbody!",
+            b"HTTP/1.1 200 OK\r\n\r\nbody!",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nbody!\r\n0\r\n\r\n",
+        ] {
+            assert_eq!(
+                send(&request, response).unwrap_err().kind(),
+                ErrorKind::ExcessData
+            );
+        }
+    }
+
+    #[test]
+    fn test_header_map() {
+        let mut headers = HeaderMap::new();
+        assert!(headers.is_empty());
+        headers.append("Accept", "a");
+        headers.append("accept", "b");
+        headers.insert("ACCEPT", "c");
+        headers.append("Host", "h");
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers.get("accept"), Some("c"));
+        headers.remove("host");
+        assert!(!headers.contains("Host"));
+        assert_eq!(headers.iter().collect::<Vec<_>>(), vec![("ACCEPT", "c")]);
+    }
+}
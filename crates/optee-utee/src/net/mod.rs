@@ -16,11 +16,14 @@
 // under the License.
 
 mod error;
+pub mod http;
 mod optee;
+mod resolver;
 mod socket;
 
 pub use error::SocketError;
 pub use optee::{Setup, TcpAdapter, TcpStream, UdpAdapter, UdpSocket};
+pub use resolver::{CMD_LOOKUP_HOST, RESOLVER_PLUGIN_UUID, Resolver, lookup_host};
 pub use socket::{Socket, SocketAdapter};
 
 #[cfg(feature = "std")]
@@ -67,20 +67,50 @@ pub type TcpStream = Socket<TcpAdapter>;
 /// A UdpSocket that is compatible with OP-TEE.
 pub type UdpSocket = Socket<UdpAdapter>;
 
-fn handle_socket_operation_error(handle: raw::TEE_iSocketHandle, code: u32) -> SocketError {
+/// The TCP interface of the GP TEE Sockets API.
+fn tcp_socket() -> *const raw::TEE_iSocket {
+    unsafe { raw::TEE_tcpSocket }
+}
+
+/// The UDP interface of the GP TEE Sockets API.
+fn udp_socket() -> *const raw::TEE_iSocket {
+    unsafe { raw::TEE_udpSocket }
+}
+
+fn handle_socket_operation_error(
+    interface: *const raw::TEE_iSocket,
+    handle: raw::TEE_iSocketHandle,
+    code: u32,
+) -> SocketError {
     match code {
         raw::TEE_ISOCKET_ERROR_PROTOCOL => {
-            let protocol_error = unsafe { ((*raw::TEE_tcpSocket).error)(handle) };
+            let protocol_error = unsafe { ((*interface).error)(handle) };
             SocketError::ErrorProtocol(protocol_error)
         }
         raw::TEE_ISOCKET_WARNING_PROTOCOL => {
-            let protocol_error = unsafe { ((*raw::TEE_tcpSocket).error)(handle) };
+            let protocol_error = unsafe { ((*interface).error)(handle) };
             SocketError::WarningProtocol(protocol_error)
         }
         _ => SocketError::from_raw_error(code, 0),
     }
 }
 
+/// Runs the protocol specific command `command` of the socket `handle` of
+/// `interface`, with `buf` as its argument.
+fn ioctl(
+    interface: *const raw::TEE_iSocket,
+    handle: raw::TEE_iSocketHandle,
+    command: u32,
+    buf: &mut [u8],
+) -> Result<(), SocketError> {
+    let mut length: u32 = buf.len() as _;
+    let ret = unsafe { ((*interface).ioctl)(handle, command, buf.as_mut_ptr() as _, &mut length) };
+    match ret {
+        raw::TEE_SUCCESS => Ok(()),
+        _ => Err(handle_socket_operation_error(interface, handle, ret)),
+    }
+}
+
 impl SocketAdapter for TcpAdapter {
     type Setup = Setup;
     type Handle = Self;
@@ -112,7 +142,7 @@ impl SocketAdapter for TcpAdapter {
         };
         match ret {
             raw::TEE_SUCCESS => Ok(length as usize),
-            _ => Err(handle_socket_operation_error(handle.0, ret)),
+            _ => Err(handle_socket_operation_error(tcp_socket(), handle.0, ret)),
         }
     }
     fn recv(handle: &mut Self::Handle, buf: &mut [u8], timeout: u32) -> Result<usize, SocketError> {
@@ -122,7 +152,7 @@ impl SocketAdapter for TcpAdapter {
         };
         match ret {
             raw::TEE_SUCCESS => Ok(length as usize),
-            _ => Err(handle_socket_operation_error(handle.0, ret)),
+            _ => Err(handle_socket_operation_error(tcp_socket(), handle.0, ret)),
         }
     }
 }
@@ -167,7 +197,7 @@ impl SocketAdapter for UdpAdapter {
         };
         match ret {
             raw::TEE_SUCCESS => Ok(length as usize),
-            _ => Err(handle_socket_operation_error(handle.0, ret)),
+            _ => Err(handle_socket_operation_error(udp_socket(), handle.0, ret)),
         }
     }
     fn recv(handle: &mut Self::Handle, buf: &mut [u8], timeout: u32) -> Result<usize, SocketError> {
@@ -177,7 +207,7 @@ impl SocketAdapter for UdpAdapter {
         };
         match ret {
             raw::TEE_SUCCESS => Ok(length as usize),
-            _ => Err(handle_socket_operation_error(handle.0, ret)),
+            _ => Err(handle_socket_operation_error(udp_socket(), handle.0, ret)),
         }
     }
 }
@@ -190,3 +220,40 @@ impl Drop for UdpAdapter {
         }
     }
 }
+
+impl TcpStream {
+    /// Sets the size of the receive buffer of the socket, with the
+    /// `TEE_TCP_SET_RECVBUF` command.
+    pub fn set_recv_buffer_size(&mut self, size: u32) -> Result<(), SocketError> {
+        let handle = self.handle_mut().0;
+        let mut size = size.to_ne_bytes();
+        ioctl(tcp_socket(), handle, raw::TEE_TCP_SET_RECVBUF, &mut size)
+    }
+    /// Sets the size of the send buffer of the socket, with the
+    /// `TEE_TCP_SET_SENDBUF` command.
+    pub fn set_send_buffer_size(&mut self, size: u32) -> Result<(), SocketError> {
+        let handle = self.handle_mut().0;
+        let mut size = size.to_ne_bytes();
+        ioctl(tcp_socket(), handle, raw::TEE_TCP_SET_SENDBUF, &mut size)
+    }
+}
+
+impl UdpSocket {
+    /// Sends the next datagrams to `addr` instead of the server address of
+    /// the setup, with the `TEE_UDP_CHANGEADDR` command. It will return
+    /// `BadParameters` if the address contains a `\0` character in the middle.
+    pub fn set_remote_addr(&mut self, addr: &str) -> Result<(), SocketError> {
+        let handle = self.handle_mut().0;
+        let addr =
+            CString::new(addr).map_err(|_| SocketError::Tee(crate::ErrorKind::BadParameters))?;
+        let mut addr = addr.into_bytes_with_nul();
+        ioctl(udp_socket(), handle, raw::TEE_UDP_CHANGEADDR, &mut addr)
+    }
+    /// Sends the next datagrams to `port` instead of the server port of the
+    /// setup, with the `TEE_UDP_CHANGEPORT` command.
+    pub fn set_remote_port(&mut self, port: u16) -> Result<(), SocketError> {
+        let handle = self.handle_mut().0;
+        let mut port = port.to_ne_bytes();
+        ioctl(udp_socket(), handle, raw::TEE_UDP_CHANGEPORT, &mut port)
+    }
+}
@@ -0,0 +1,156 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::SocketError;
+use crate::{ErrorKind, LoadablePlugin, Uuid};
+
+/// UUID of the supplicant plugin resolving host names for the TAs, served by
+/// `optee_teec::resolver` in the REE.
+pub const RESOLVER_PLUGIN_UUID: &str = "c4ed089a-0633-41de-bab4-6d4cfa7e8d9f";
+/// Command of the plugin resolving the host name in its buffer.
+pub const CMD_LOOKUP_HOST: u32 = 0;
+
+/// Size of the buffer of the plugin, which bounds the number of addresses
+/// returned, e.g. 30 IPv6 addresses.
+const RESPONSE_CAPACITY: usize = 512;
+
+/// Tag of an IPv4 address in the response, followed by its 4 bytes.
+const TAG_V4: u8 = 4;
+/// Tag of an IPv6 address in the response, followed by its 16 bytes.
+const TAG_V6: u8 = 6;
+
+/// Resolves host names through a supplicant plugin, as the GP TEE Sockets API
+/// has no resolver.
+///
+/// The host name is resolved by tee-supplicant with the resolver of the REE,
+/// so the addresses are as trusted as the REE: a TA connecting to them must
+/// authenticate the server, e.g. with TLS.
+pub struct Resolver {
+    plugin: LoadablePlugin,
+}
+
+impl Resolver {
+    /// A resolver using the plugin `uuid`, for a plugin routing
+    /// [`CMD_LOOKUP_HOST`] to `optee_teec::resolver::lookup_host` under
+    /// another UUID.
+    pub fn new(uuid: &Uuid) -> Self {
+        Self {
+            plugin: LoadablePlugin::new(uuid),
+        }
+    }
+
+    /// Resolves `host` to its addresses, in the order of the REE resolver.
+    ///
+    /// Returns `SocketError::Hostname` if `host` has no address, and
+    /// `SocketError::Tee(ErrorKind::ItemNotFound)` if the plugin is not
+    /// installed.
+    pub fn lookup_host(&self, host: &str) -> Result<Vec<IpAddr>, SocketError> {
+        if host.is_empty() || host.len() >= RESPONSE_CAPACITY || host.contains('\0') {
+            return Err(SocketError::Tee(ErrorKind::BadParameters));
+        }
+        let response = self
+            .plugin
+            .invoke_with_capacity(CMD_LOOKUP_HOST, 0, RESPONSE_CAPACITY)
+            .chain_write_body(host.as_bytes())
+            .call()?;
+        let addrs = decode(&response)?;
+        if addrs.is_empty() {
+            return Err(SocketError::Hostname);
+        }
+        Ok(addrs)
+    }
+}
+
+impl Default for Resolver {
+    /// A resolver using the plugin [`RESOLVER_PLUGIN_UUID`].
+    fn default() -> Self {
+        let uuid = Uuid::parse_str(RESOLVER_PLUGIN_UUID).expect("the plugin UUID is valid");
+        Self::new(&uuid)
+    }
+}
+
+/// Resolves `host` to its addresses with the plugin [`RESOLVER_PLUGIN_UUID`],
+/// see [`Resolver::lookup_host`].
+///
+/// ``` rust,no_run
+/// use core::net::IpAddr;
+/// use optee_utee::net::{Setup, SocketError, TcpStream, lookup_host};
+///
+/// fn connect(host: &str, port: u16) -> Result<TcpStream, SocketError> {
+///     let setup = match lookup_host(host)?[0] {
+///         addr @ IpAddr::V4(_) => Setup::new_v4(&addr.to_string(), port)?,
+///         addr @ IpAddr::V6(_) => Setup::new_v6(&addr.to_string(), port)?,
+///     };
+///     TcpStream::open(setup)
+/// }
+/// ```
+pub fn lookup_host(host: &str) -> Result<Vec<IpAddr>, SocketError> {
+    Resolver::default().lookup_host(host)
+}
+
+/// Decodes the addresses of the response of the plugin.
+fn decode(mut response: &[u8]) -> Result<Vec<IpAddr>, SocketError> {
+    let mut addrs = Vec::new();
+    while let Some((&tag, rest)) = response.split_first() {
+        let (addr, rest) = match tag {
+            TAG_V4 if rest.len() >= 4 => {
+                let (octets, rest) = rest.split_at(4);
+                let octets: [u8; 4] = octets.try_into().unwrap();
+                (IpAddr::V4(Ipv4Addr::from(octets)), rest)
+            }
+            TAG_V6 if rest.len() >= 16 => {
+                let (octets, rest) = rest.split_at(16);
+                let octets: [u8; 16] = octets.try_into().unwrap();
+                (IpAddr::V6(Ipv6Addr::from(octets)), rest)
+            }
+            _ => return Err(SocketError::Tee(ErrorKind::BadFormat)),
+        };
+        addrs.push(addr);
+        response = rest;
+    }
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_decode() {
+        let mut response = vec![TAG_V4, 127, 0, 0, 1, TAG_V6];
+        response.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        assert_eq!(
+            decode(&response).unwrap(),
+            [
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(Ipv6Addr::LOCALHOST)
+            ]
+        );
+        assert!(decode(&[]).unwrap().is_empty());
+        assert_eq!(
+            decode(&[TAG_V4, 127, 0]),
+            Err(SocketError::Tee(ErrorKind::BadFormat))
+        );
+        assert_eq!(
+            decode(&[5, 0, 0, 0, 0]),
+            Err(SocketError::Tee(ErrorKind::BadFormat))
+        );
+    }
+}
@@ -86,6 +86,10 @@ impl<T: SocketAdapter> Socket<T> {
     pub fn recv(&mut self, buf: &mut [u8]) -> Result<usize, SocketError> {
         T::recv(&mut self.handle, buf, self.recv_timeout)
     }
+    /// the handle of the adapter, for the protocol specific operations.
+    pub(super) fn handle_mut(&mut self) -> &mut T::Handle {
+        &mut self.handle
+    }
 }
 
 fn convert_duration_option_to_timeout(dur: Option<Duration>) -> crate::Result<u32> {
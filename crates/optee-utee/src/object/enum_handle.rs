@@ -16,11 +16,13 @@
 // under the License.
 
 use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem;
 
 use optee_utee_sys as raw;
 
-use super::ObjectInfo;
-use crate::{Error, Result};
+use super::{MiscellaneousConstants, ObjectInfo, ObjectStorageConstants};
+use crate::{Error, ErrorKind, Result};
 
 // TODO: The examples and detailed function explanation will be added after we
 // test this struct and its functions.
@@ -108,3 +110,177 @@ impl Drop for ObjectEnumHandle {
         }
     }
 }
+
+/// An iterator over the [PersistentObject](crate::PersistentObject)s in a
+/// Trusted Storage, yielding the identifier and information of each object.
+///
+/// The objects are listed in no particular order. Creating or deleting objects
+/// while iterating may or may not be reflected in the remaining items.
+///
+/// # Example
+///
+/// ``` rust,no_run
+/// # use optee_utee::{ObjectStorageConstants, PersistentObjectIter};
+/// # fn main() -> optee_utee::Result<()> {
+/// for entry in PersistentObjectIter::new(ObjectStorageConstants::Private)? {
+///     let (id, info) = entry?;
+///     optee_utee::trace_println!("{:?}: {} bytes", id, info.data_size());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct PersistentObjectIter {
+    handle: ObjectEnumHandle,
+    done: bool,
+}
+
+impl PersistentObjectIter {
+    /// Allocate an enumerator and start the enumeration of the objects in
+    /// `storage_id`.
+    ///
+    /// # Errors
+    ///
+    /// 1) `OutOfMemory`: If there are not enough resources to allocate the
+    ///    enumerator.
+    /// 2) `CorruptObject`: If the storage is corrupt.
+    /// 3) `StorageNotAvailable`: If the storage is not accessible.
+    ///
+    /// An empty storage is not an error, the iterator is empty instead.
+    pub fn new(storage_id: ObjectStorageConstants) -> Result<Self> {
+        let mut handle = ObjectEnumHandle::allocate()?;
+        let done = match handle.start(storage_id as u32) {
+            Ok(()) => false,
+            Err(e) if e.kind() == ErrorKind::ItemNotFound => true,
+            Err(e) => return Err(e),
+        };
+        Ok(Self { handle, done })
+    }
+}
+
+impl Iterator for PersistentObjectIter {
+    type Item = Result<(Vec<u8>, ObjectInfo)>;
+
+    /// Return the identifier and information of the next object, `None` once
+    /// all objects have been listed. An error ends the iteration.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut id = vec![0u8; MiscellaneousConstants::TeeObjectIdMaxLen as usize];
+        let mut info = ObjectInfo::from_raw(unsafe { mem::zeroed() });
+        match self.handle.get_next(Some(&mut info), &mut id) {
+            Ok(len) => {
+                id.truncate(len as usize);
+                Some(Ok((id, info)))
+            }
+            Err(e) => {
+                self.done = true;
+                if e.kind() == ErrorKind::ItemNotFound {
+                    None
+                } else {
+                    Some(Err(e))
+                }
+            }
+        }
+    }
+}
+
+impl core::iter::FusedIterator for PersistentObjectIter {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use optee_utee_sys::{mock_api, mock_utils::SERIAL_TEST_LOCK};
+
+    use super::*;
+
+    const IDS: [&[u8]; 2] = [b"first", b"second"];
+
+    fn expect_enumerator() -> (
+        mock_api::__TEE_AllocatePersistentObjectEnumerator::Context,
+        mock_api::__TEE_FreePersistentObjectEnumerator::Context,
+    ) {
+        let allocate = mock_api::TEE_AllocatePersistentObjectEnumerator_context();
+        let free = mock_api::TEE_FreePersistentObjectEnumerator_context();
+        allocate.expect().return_once_st(|handle| {
+            unsafe { *handle = core::ptr::dangling_mut() };
+            raw::TEE_SUCCESS
+        });
+        free.expect().times(1).return_const(());
+        (allocate, free)
+    }
+
+    #[test]
+    fn test_iterate_objects() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let _enumerator = expect_enumerator();
+        let start = mock_api::TEE_StartPersistentObjectEnumerator_context();
+        let next = mock_api::TEE_GetNextPersistentObject_context();
+
+        start.expect().return_once_st(|_, storage_id| {
+            assert_eq!(storage_id, ObjectStorageConstants::Private as u32);
+            raw::TEE_SUCCESS
+        });
+        let mut remaining = IDS.iter();
+        next.expect().returning_st(move |_, info, id, id_len| {
+            let Some(next_id) = remaining.next() else {
+                return raw::TEE_ERROR_ITEM_NOT_FOUND;
+            };
+            unsafe {
+                assert_eq!(*id_len, MiscellaneousConstants::TeeObjectIdMaxLen as usize);
+                core::ptr::copy_nonoverlapping(next_id.as_ptr(), id as *mut u8, next_id.len());
+                *id_len = next_id.len();
+                (*info).dataSize = next_id.len() * 10;
+            }
+            raw::TEE_SUCCESS
+        });
+
+        let mut iter =
+            PersistentObjectIter::new(ObjectStorageConstants::Private).expect("it should be ok");
+        for expected in IDS {
+            let (id, info) = iter.next().unwrap().expect("it should be ok");
+            assert_eq!(id, expected);
+            assert_eq!(info.data_size(), expected.len() * 10);
+        }
+        assert!(iter.next().is_none());
+        // The enumerator is not queried again once exhausted
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_empty_storage() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let _enumerator = expect_enumerator();
+        let start = mock_api::TEE_StartPersistentObjectEnumerator_context();
+        let next = mock_api::TEE_GetNextPersistentObject_context();
+
+        start
+            .expect()
+            .return_once_st(|_, _| raw::TEE_ERROR_ITEM_NOT_FOUND);
+        next.expect().never();
+
+        let mut iter =
+            PersistentObjectIter::new(ObjectStorageConstants::Private).expect("it should be ok");
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_error_ends_iteration() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let _enumerator = expect_enumerator();
+        let start = mock_api::TEE_StartPersistentObjectEnumerator_context();
+        let next = mock_api::TEE_GetNextPersistentObject_context();
+
+        start.expect().return_once_st(|_, _| raw::TEE_SUCCESS);
+        next.expect()
+            .times(1)
+            .returning_st(|_, _, _, _| raw::TEE_ERROR_CORRUPT_OBJECT);
+
+        let mut iter =
+            PersistentObjectIter::new(ObjectStorageConstants::Private).expect("it should be ok");
+        let err = iter.next().unwrap().err().expect("it should fail");
+        assert_eq!(err.kind(), ErrorKind::CorruptObject);
+        assert!(iter.next().is_none());
+    }
+}
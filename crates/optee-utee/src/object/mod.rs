@@ -25,7 +25,7 @@ mod persistent_object;
 mod transient_object;
 
 pub use attribute::*;
-pub use enum_handle::ObjectEnumHandle;
+pub use enum_handle::{ObjectEnumHandle, PersistentObjectIter};
 pub use generic_object::GenericObject;
 pub use object_define::*;
 pub use object_handle::ObjectHandle;
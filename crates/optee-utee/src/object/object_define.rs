@@ -39,6 +39,7 @@ impl From<Whence> for raw::TEE_Whence {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u32)]
 pub enum ObjectStorageConstants {
     Private = 0x00000001,
@@ -17,6 +17,8 @@
 
 use optee_utee_sys as raw;
 
+use super::UsageFlag;
+
 /// Represent the characteristics of an object.
 /// This info can be returned by [GenericObject](crate::GenericObject) function
 /// [info](crate::GenericObject::info)
@@ -75,4 +77,9 @@ impl ObjectInfo {
     pub fn object_type(&self) -> u32 {
         self.raw.objectType
     }
+
+    /// Return the `objectUsage` field of the raw structure `TEE_ObjectInfo`.
+    pub fn object_usage(&self) -> UsageFlag {
+        UsageFlag::from_bits_retain(self.raw.objectUsage)
+    }
 }
@@ -18,6 +18,7 @@
 use optee_utee_sys as raw;
 
 use super::{DataFlag, GenericObject, ObjectHandle, ObjectStorageConstants, Whence};
+use crate::fault_injection::{self, FaultPoint};
 use crate::{Error, Result};
 
 /// An object identified by an Object Identifier and including a Data Stream.
@@ -84,6 +85,7 @@ impl PersistentObject {
         object_id: &[u8],
         flags: DataFlag,
     ) -> Result<Self> {
+        fault_injection::check(FaultPoint::ObjectOpen)?;
         let mut handle: raw::TEE_ObjectHandle = core::ptr::null_mut();
         // Move as much code as possible out of unsafe blocks to maximize Rust’s
         // safety checks.
@@ -172,6 +174,7 @@ impl PersistentObject {
         attributes: Option<ObjectHandle>,
         initial_data: &[u8],
     ) -> Result<Self> {
+        fault_injection::check(FaultPoint::ObjectCreate)?;
         let mut handle: raw::TEE_ObjectHandle = core::ptr::null_mut();
         // Move as much code as possible out of unsafe blocks to maximize Rust’s
         // safety checks.
@@ -250,6 +253,7 @@ impl PersistentObject {
     /// # Ok(())
     /// # }
     pub fn close_and_delete(self) -> Result<()> {
+        fault_injection::check(FaultPoint::ObjectDelete)?;
         let result = match unsafe { raw::TEE_CloseAndDeletePersistentObject1(*self.as_raw_ref()) } {
             raw::TEE_SUCCESS => Ok(()),
             code => Err(Error::from_raw_error(code)),
@@ -312,6 +316,7 @@ impl PersistentObject {
     ///    function which is not explicitly associated with a defined return
     ///    code for this function.
     pub fn rename(&mut self, new_object_id: &[u8]) -> Result<()> {
+        fault_injection::check(FaultPoint::ObjectRename)?;
         match unsafe {
             raw::TEE_RenamePersistentObject(
                 *self.0.as_raw_ref(),
@@ -368,6 +373,7 @@ impl PersistentObject {
     ///    function which is not explicitly associated with a defined return
     ///    code for this function.
     pub fn read(&mut self, buf: &mut [u8]) -> Result<u32> {
+        let corrupt = fault_injection::check(FaultPoint::ObjectRead)?;
         let mut count: usize = 0;
         match unsafe {
             raw::TEE_ReadObjectData(
@@ -377,7 +383,12 @@ impl PersistentObject {
                 &mut count,
             )
         } {
-            raw::TEE_SUCCESS => Ok(count as u32),
+            raw::TEE_SUCCESS => {
+                if corrupt {
+                    fault_injection::corrupt(&mut buf[..count]);
+                }
+                Ok(count as u32)
+            }
             code => Err(Error::from_raw_error(code)),
         }
     }
@@ -430,6 +441,7 @@ impl PersistentObject {
     ///    function which is not explicitly associated with a defined return
     ///    code for this function.
     pub fn write(&mut self, buf: &[u8]) -> Result<()> {
+        fault_injection::check(FaultPoint::ObjectWrite)?;
         match unsafe { raw::TEE_WriteObjectData(*self.as_raw_ref(), buf.as_ptr() as _, buf.len()) }
         {
             raw::TEE_SUCCESS => Ok(()),
@@ -476,6 +488,7 @@ impl PersistentObject {
     ///    function which is not explicitly associated with a defined return
     ///    code for this function.
     pub fn truncate(&mut self, size: u32) -> Result<()> {
+        fault_injection::check(FaultPoint::ObjectTruncate)?;
         match unsafe { raw::TEE_TruncateObjectData(*self.as_raw_ref(), size as usize) } {
             raw::TEE_SUCCESS => Ok(()),
             code => Err(Error::from_raw_error(code)),
@@ -711,4 +724,34 @@ mod tests {
 
         obj.close_and_delete().expect_err("it should be err");
     }
+
+    #[test]
+    #[cfg(feature = "fault_injection")]
+    // An injected fault replaces the call to the TEE.
+    fn test_create_injected_fault() {
+        use crate::ErrorKind;
+        use crate::fault_injection::{self, Fault};
+
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let fn1 = mock_api::TEE_CreatePersistentObject_context();
+        fn1.expect().never();
+
+        fault_injection::reset();
+        fault_injection::inject(
+            FaultPoint::ObjectCreate,
+            1,
+            Fault::Error(ErrorKind::StorageNoSpace),
+        );
+        let err = PersistentObject::create(
+            ObjectStorageConstants::Private,
+            &[],
+            DataFlag::ACCESS_WRITE,
+            None,
+            &[],
+        )
+        .expect_err("it should be err");
+        fault_injection::reset();
+
+        assert_eq!(err.kind(), ErrorKind::StorageNoSpace);
+    }
 }
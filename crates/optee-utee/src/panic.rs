@@ -0,0 +1,350 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Panic reporting.
+//!
+//! When a TA panics, the panic hook is called with the location and message
+//! of the panic before the TA is aborted with `TEE_Panic`. The default hook,
+//! [`default_hook`], prints them to the trace output. A TA replaces it with
+//! [`set_panic_hook`], typically from `#[ta_create]`:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::panic::{self, PanicReport};
+//! fn crash_hook(report: &PanicReport<'_>) {
+//!     panic::default_hook(report);
+//!     // Keep the cause across the restart of the TA
+//!     let _ = panic::record_crash(report);
+//! }
+//!
+//! # fn create() -> optee_utee::Result<()> {
+//! if let Some(crash) = panic::take_crash_record()? {
+//!     optee_utee::trace_println!("[!] previous instance crashed: {}", crash);
+//! }
+//! panic::set_panic_hook(crash_hook);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The hook runs in the panicking TA, so it should do as little as possible. A
+//! panic in the hook itself skips the hook and aborts the TA right away.
+//!
+//! With the `no_panic_handler` feature, the TA provides its own
+//! `#[panic_handler]`, which may call [`run_hook`] to keep using the hook.
+
+use core::fmt::{self, Write};
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+
+use crate::{DataFlag, ErrorKind, ObjectStorageConstants, PersistentObject, Result};
+
+/// A function called when the TA panics.
+pub type PanicHook = fn(&PanicReport<'_>);
+
+/// Identifier of the persistent object written by [`record_crash`].
+pub const CRASH_RECORD_ID: &[u8] = b"optee_utee.crash_record";
+
+/// Maximum length of a crash record, longer messages are truncated.
+pub const CRASH_RECORD_MAX_LEN: usize = 256;
+
+// Null for the default hook
+static HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+static IN_HOOK: AtomicBool = AtomicBool::new(false);
+
+/// The location and message of a panic.
+pub struct PanicReport<'a> {
+    location: Option<&'a Location<'a>>,
+    message: &'a dyn fmt::Display,
+}
+
+impl<'a> PanicReport<'a> {
+    /// Create a report, for custom panic handlers calling [`run_hook`].
+    pub fn new(location: Option<&'a Location<'a>>, message: &'a dyn fmt::Display) -> Self {
+        Self { location, message }
+    }
+
+    /// Return the source file of the panic, if known.
+    pub fn file(&self) -> Option<&str> {
+        self.location.map(|location| location.file())
+    }
+
+    /// Return the line of the panic, if known.
+    pub fn line(&self) -> Option<u32> {
+        self.location.map(|location| location.line())
+    }
+
+    /// Return the column of the panic, if known.
+    pub fn column(&self) -> Option<u32> {
+        self.location.map(|location| location.column())
+    }
+
+    /// Return the panic message.
+    pub fn message(&self) -> &dyn fmt::Display {
+        self.message
+    }
+}
+
+impl fmt::Display for PanicReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(location) => write!(f, "panicked at {}: {}", location, self.message),
+            None => write!(f, "panicked: {}", self.message),
+        }
+    }
+}
+
+/// Register `hook` to be called when the TA panics, replacing the previous
+/// hook.
+pub fn set_panic_hook(hook: PanicHook) {
+    HOOK.store(hook as *mut (), Ordering::SeqCst);
+    #[cfg(feature = "std")]
+    install_std_hook();
+}
+
+/// Unregister the current panic hook, restoring [`default_hook`], and return
+/// it.
+pub fn take_panic_hook() -> PanicHook {
+    let hook = HOOK.swap(core::ptr::null_mut(), Ordering::SeqCst);
+    load_hook(hook)
+}
+
+fn load_hook(hook: *mut ()) -> PanicHook {
+    if hook.is_null() {
+        return default_hook;
+    }
+    // SAFETY: non-null values are only stored by set_panic_hook, from a
+    // PanicHook.
+    unsafe { core::mem::transmute::<*mut (), PanicHook>(hook) }
+}
+
+/// The default panic hook, printing the report to the trace output.
+pub fn default_hook(report: &PanicReport<'_>) {
+    // The trace syscall is not mocked, so unit tests cannot link it
+    #[cfg(not(test))]
+    crate::trace_println!("[!] TA {}", report);
+    #[cfg(test)]
+    let _ = report;
+}
+
+/// Call the panic hook with `report`, unless the hook itself is panicking.
+///
+/// Called by the panic handler of this crate, and by custom handlers with the
+/// `no_panic_handler` feature.
+pub fn run_hook(report: &PanicReport<'_>) {
+    if IN_HOOK.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    load_hook(HOOK.load(Ordering::SeqCst))(report);
+    IN_HOOK.store(false, Ordering::SeqCst);
+}
+
+/// Write `report` to the crash record in the private storage of the TA,
+/// replacing any previous one, to be read with [`take_crash_record`] after
+/// the TA was restarted.
+///
+/// Does not allocate, the record is truncated to [`CRASH_RECORD_MAX_LEN`]
+/// bytes.
+pub fn record_crash(report: &PanicReport<'_>) -> Result<()> {
+    let mut record = RecordBuffer::new();
+    // Truncation is not an error
+    let _ = write!(record, "{}", report);
+    PersistentObject::create(
+        ObjectStorageConstants::Private,
+        CRASH_RECORD_ID,
+        DataFlag::ACCESS_WRITE | DataFlag::ACCESS_WRITE_META | DataFlag::OVERWRITE,
+        None,
+        record.as_bytes(),
+    )?;
+    Ok(())
+}
+
+/// Read and delete the crash record written by [`record_crash`], `None` if
+/// the TA did not crash since the last call.
+pub fn take_crash_record() -> Result<Option<String>> {
+    let mut object = match PersistentObject::open(
+        ObjectStorageConstants::Private,
+        CRASH_RECORD_ID,
+        DataFlag::ACCESS_READ | DataFlag::ACCESS_WRITE_META,
+    ) {
+        Ok(object) => object,
+        Err(e) if e.kind() == ErrorKind::ItemNotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut record = [0u8; CRASH_RECORD_MAX_LEN];
+    let len = object.read(&mut record)? as usize;
+    object.close_and_delete()?;
+    Ok(Some(String::from_utf8_lossy(&record[..len]).into_owned()))
+}
+
+/// A fixed-size buffer truncating what is written to it, so that reports are
+/// formatted without allocating.
+struct RecordBuffer {
+    data: [u8; CRASH_RECORD_MAX_LEN],
+    len: usize,
+}
+
+impl RecordBuffer {
+    fn new() -> Self {
+        Self {
+            data: [0; CRASH_RECORD_MAX_LEN],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl Write for RecordBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let available = CRASH_RECORD_MAX_LEN - self.len;
+        // Cut at a character boundary, so the record stays valid UTF-8
+        let mut len = s.len().min(available);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.data[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        if len < s.len() {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+// std TAs unwind through the panic runtime of std, which calls its own hook
+#[cfg(feature = "std")]
+fn install_std_hook() {
+    use std::sync::Once;
+
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        std::panic::set_hook(std::boxed::Box::new(|info| {
+            let payload = info.payload();
+            let message: &dyn fmt::Display = if let Some(s) = payload.downcast_ref::<&str>() {
+                s
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                s
+            } else {
+                &"Box<dyn Any>"
+            };
+            run_hook(&PanicReport::new(info.location(), message));
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+    use std::sync::atomic::AtomicUsize;
+
+    use optee_utee_sys::{
+        self as raw,
+        mock_utils::{SERIAL_TEST_LOCK, storage::MockStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_report_display() {
+        let location = Location::caller();
+        let message = "out of bounds";
+        let report = PanicReport::new(Some(location), &message);
+        assert_eq!(report.file(), Some(file!()));
+        assert_eq!(
+            report.to_string(),
+            std::format!("panicked at {}: out of bounds", location)
+        );
+        assert_eq!(
+            PanicReport::new(None, &message).to_string(),
+            "panicked: out of bounds"
+        );
+    }
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn counting_hook(_report: &PanicReport<'_>) {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn reentrant_hook(report: &PanicReport<'_>) {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        run_hook(report);
+    }
+
+    #[test]
+    // The hook is called once, also when it panics again.
+    fn test_run_hook() {
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let message = "boom";
+        let report = PanicReport::new(None, &message);
+
+        // Stored directly, set_panic_hook would replace the hook of the test
+        // harness
+        CALLS.store(0, Ordering::SeqCst);
+        HOOK.store(counting_hook as *mut (), Ordering::SeqCst);
+        run_hook(&report);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+        HOOK.store(reentrant_hook as *mut (), Ordering::SeqCst);
+        run_hook(&report);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+        assert_eq!(take_panic_hook() as *const (), reentrant_hook as *const ());
+        assert_eq!(take_panic_hook() as *const (), default_hook as *const ());
+    }
+
+    #[test]
+    fn test_record_truncated() {
+        let mut record = RecordBuffer::new();
+        assert!(write!(record, "{}", "ä".repeat(CRASH_RECORD_MAX_LEN)).is_err());
+        assert_eq!(record.as_bytes().len(), CRASH_RECORD_MAX_LEN);
+        let mut record = RecordBuffer::new();
+        write!(record, "x{}", "ä".repeat(CRASH_RECORD_MAX_LEN)).unwrap_err();
+        // The last character does not fit entirely
+        assert_eq!(record.as_bytes().len(), CRASH_RECORD_MAX_LEN - 1);
+        assert!(core::str::from_utf8(record.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_crash_record() {
+        let storage = MockStorage::new();
+        assert_eq!(take_crash_record().expect("it should be ok"), None);
+
+        let message = "boom";
+        record_crash(&PanicReport::new(None, &message)).expect("it should be ok");
+        // A later crash replaces the record
+        let message = "boom again";
+        record_crash(&PanicReport::new(None, &message)).expect("it should be ok");
+        assert_eq!(
+            storage.get(raw::TEE_STORAGE_PRIVATE, CRASH_RECORD_ID),
+            Some(b"panicked: boom again".to_vec())
+        );
+        assert_eq!(storage.open_handles(), 0);
+
+        assert_eq!(
+            take_crash_record().expect("it should be ok").as_deref(),
+            Some("panicked: boom again")
+        );
+        assert_eq!(take_crash_record().expect("it should be ok"), None);
+    }
+}
@@ -53,8 +53,20 @@
 //!     p0.write_at(0, output)
 //! }
 //! ```
+//!
+//! **Migrating a TA whose commands take different parameter types:**
+//!
+//! ```rust,ignore
+//! fn invoke_command(cmd_id: u32, params: &mut Parameters) -> Result<()> {
+//!     let (mut p0, _, _, _) = unsafe {
+//!         params.expect::<ParameterMemrefInout, ParameterNone, ParameterNone, ParameterNone>()?
+//!     };
+//!     // extra codes here...
+//!     p0.write_at(0, output)
+//! }
+//! ```
 
-use super::{ParamType, RawParamTypes, RawParams};
+use super::{FromRawParameter, ParamType, RawParamTypes, RawParams, check_param_types};
 use crate::{Error, ErrorKind, FromRawParameters, Result, raw};
 use core::{marker, slice};
 
@@ -74,6 +86,53 @@ impl Parameters {
 
         Parameters(p0, p1, p2, p3)
     }
+
+    /// Check the types of all four parameters up front and convert them into
+    /// the typed wrappers, for commands taking different parameter types.
+    ///
+    /// Fails with `BadParameters` if any type differs, before any of the
+    /// parameters is converted:
+    ///
+    /// ```rust,ignore
+    /// let (input, mut output, mut value, _) = unsafe {
+    ///     params.expect::<ParameterMemrefInput, ParameterMemrefOutput, ParameterValueInout, ParameterNone>()?
+    /// };
+    /// output.set_output(input.get_buffer())?;
+    /// value.set_a(value.get_a() + 1);
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The raw pointers of the four parameters must be valid and point to
+    /// `TEE_Param`s initialized by the TEE runtime, as when the parameters
+    /// come from [`Parameters::from_raw`].
+    pub unsafe fn expect<'a, A, B, C, D>(&'a mut self) -> Result<(A, B, C, D)>
+    where
+        A: FromRawParameter<'a>,
+        B: FromRawParameter<'a>,
+        C: FromRawParameter<'a>,
+        D: FromRawParameter<'a>,
+    {
+        let Parameters(p0, p1, p2, p3) = self;
+        let raw_types = [&*p0, &*p1, &*p2, &*p3]
+            .into_iter()
+            .enumerate()
+            .fold(0, |raw_types, (index, param)| {
+                raw_types | (u32::from(param.param_type) << (index * 4))
+            });
+        check_param_types(
+            raw_types,
+            [A::PARAM_TYPE, B::PARAM_TYPE, C::PARAM_TYPE, D::PARAM_TYPE],
+        )?;
+        unsafe {
+            Ok((
+                A::from_raw(p0.param_type.into(), &mut *p0.raw)?,
+                B::from_raw(p1.param_type.into(), &mut *p1.raw)?,
+                C::from_raw(p2.param_type.into(), &mut *p2.raw)?,
+                D::from_raw(p3.param_type.into(), &mut *p3.raw)?,
+            ))
+        }
+    }
 }
 
 /// # Deprecated
@@ -127,8 +186,14 @@ pub struct ParamMemref<'parameter> {
 }
 
 impl<'parameter> ParamMemref<'parameter> {
+    /// The buffer, empty if the client passed a null buffer to query the
+    /// size of an output.
     pub fn buffer(&mut self) -> &mut [u8] {
-        unsafe { slice::from_raw_parts_mut((*self.raw).buffer as *mut u8, (*self.raw).size) }
+        let (buffer, size) = unsafe { ((*self.raw).buffer as *mut u8, (*self.raw).size) };
+        if buffer.is_null() {
+            return &mut [];
+        }
+        unsafe { slice::from_raw_parts_mut(buffer, size) }
     }
 
     pub fn param_type(&self) -> ParamType {
@@ -0,0 +1,231 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Runtime checks on memref parameters, enabled by the `memref_guard`
+//! feature.
+//!
+//! Memref buffers live in memory shared with the client application, which
+//! can modify them at any time while the TA is running. Reading such a buffer
+//! twice and expecting the same data (a double fetch) is a classic TOCTOU bug
+//! in TAs. The guard helps to find these during development:
+//!
+//! * The buffer address and size are snapshotted when the parameter is
+//!   converted, and re-validated before every access and before the updated
+//!   size is reported, trapping code that changes the raw `TEE_Param`
+//!   behind the wrapper's back.
+//! * A digest of the buffer acts as a canary: it is taken once the TA has
+//!   read or finished writing the buffer and checked on the next access and
+//!   when the parameter is dropped, trapping modifications the TA did not
+//!   make itself.
+//!
+//! A violation panics, which makes the TA call `TEE_Panic`. Without the
+//! feature [`MemrefGuard`] is a zero-sized type and all checks compile to
+//! nothing.
+
+use crate::raw::TEE_Param;
+
+#[cfg(feature = "memref_guard")]
+use core::cell::Cell;
+
+/// Bounds snapshot and contents canary of a memref parameter.
+#[cfg(feature = "memref_guard")]
+pub(super) struct MemrefGuard {
+    buffer: *const u8,
+    capacity: usize,
+    /// The size the raw parameter is expected to hold.
+    size: Cell<usize>,
+    /// Digest of the buffer contents, `None` while the TA may be writing.
+    digest: Cell<Option<u64>>,
+}
+
+#[cfg(not(feature = "memref_guard"))]
+pub(super) struct MemrefGuard;
+
+#[cfg(feature = "memref_guard")]
+impl MemrefGuard {
+    /// Snapshot the bounds of `raw_param`.
+    pub(super) fn new(raw_param: &TEE_Param) -> Self {
+        let (buffer, size) =
+            unsafe { (raw_param.memref.buffer as *const u8, raw_param.memref.size) };
+        Self {
+            buffer,
+            capacity: size,
+            size: Cell::new(size),
+            digest: Cell::new(None),
+        }
+    }
+
+    /// Trap if the bounds in `raw_param` differ from the snapshot.
+    pub(super) fn check_bounds(&self, raw_param: &TEE_Param) {
+        let (buffer, size) =
+            unsafe { (raw_param.memref.buffer as *const u8, raw_param.memref.size) };
+        if buffer != self.buffer {
+            panic!(
+                "memref guard: buffer moved from {:p} to {:p}",
+                self.buffer, buffer
+            );
+        }
+        if size != self.size.get() {
+            panic!(
+                "memref guard: size changed from {} to {}",
+                self.size.get(),
+                size
+            );
+        }
+    }
+
+    /// Trap if the buffer changed since it was last sealed.
+    pub(super) fn check_contents(&self) {
+        if let Some(digest) = self.digest.get()
+            && digest != self.digest_buffer()
+        {
+            // Do not trap again when the guard is dropped while unwinding
+            self.digest.set(None);
+            panic!(
+                "memref guard: buffer at {:p} modified behind the TA's back",
+                self.buffer
+            );
+        }
+    }
+
+    /// Remember the current contents, they must not change until the next
+    /// [`unseal`](Self::unseal).
+    pub(super) fn seal(&self) {
+        self.digest.set(Some(self.digest_buffer()));
+    }
+
+    /// Check the contents and stop tracking them while the TA writes.
+    pub(super) fn unseal(&self) {
+        self.check_contents();
+        self.digest.set(None);
+    }
+
+    /// Check the bounds before the TA reports `size` to the client
+    /// application.
+    pub(super) fn update_size(&self, raw_param: &TEE_Param, size: usize) {
+        self.check_bounds(raw_param);
+        self.size.set(size);
+    }
+
+    fn digest_buffer(&self) -> u64 {
+        if self.capacity == 0 || self.buffer.is_null() {
+            return 0;
+        }
+        let buffer = unsafe { core::slice::from_raw_parts(self.buffer, self.capacity) };
+        // FNV-1a, enough to notice modifications and cheap to compute
+        buffer.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
+
+#[cfg(feature = "memref_guard")]
+impl Drop for MemrefGuard {
+    fn drop(&mut self) {
+        self.check_contents();
+    }
+}
+
+#[cfg(not(feature = "memref_guard"))]
+impl MemrefGuard {
+    #[inline(always)]
+    pub(super) fn new(_raw_param: &TEE_Param) -> Self {
+        Self
+    }
+    #[inline(always)]
+    pub(super) fn check_bounds(&self, _raw_param: &TEE_Param) {}
+    #[inline(always)]
+    pub(super) fn check_contents(&self) {}
+    #[inline(always)]
+    pub(super) fn seal(&self) {}
+    #[inline(always)]
+    pub(super) fn unseal(&self) {}
+    #[inline(always)]
+    pub(super) fn update_size(&self, _raw_param: &TEE_Param, _size: usize) {}
+}
+
+#[cfg(all(test, feature = "memref_guard"))]
+mod tests {
+    extern crate std;
+
+    use super::super::memref::{
+        ParameterMemrefInput, ParameterMemrefOutput, ParameterMemrefRead, ParameterMemrefWrite,
+    };
+    use super::super::{FromRawParameter, ParamType};
+    use crate::raw::{self, TEE_Param};
+
+    fn memref(buffer: &mut [u8]) -> TEE_Param {
+        TEE_Param {
+            memref: raw::Memref {
+                buffer: buffer.as_mut_ptr() as _,
+                size: buffer.len(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_unmodified_buffers_pass() {
+        let mut buffer = [1u8; 8];
+        let mut raw_param = memref(&mut buffer);
+        {
+            let input = unsafe {
+                ParameterMemrefInput::from_raw(ParamType::MemrefInput.into(), &mut raw_param)
+            }
+            .unwrap();
+            assert_eq!(input.get_buffer(), &[1u8; 8]);
+            assert_eq!(input.get_buffer(), &[1u8; 8]);
+        }
+
+        let mut output = unsafe {
+            ParameterMemrefOutput::from_raw(ParamType::MemrefOutput.into(), &mut raw_param)
+        }
+        .unwrap();
+        output.set_output([2u8; 4]).unwrap();
+        output.get_buffer_mut()[4] = 3;
+        output.set_updated_size(5).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "modified behind the TA's back")]
+    fn test_input_modified_between_reads() {
+        let mut buffer = [1u8; 8];
+        let pointer = buffer.as_mut_ptr();
+        let mut raw_param = memref(&mut buffer);
+        let input = unsafe {
+            ParameterMemrefInput::from_raw(ParamType::MemrefInput.into(), &mut raw_param)
+        }
+        .unwrap();
+        let _ = input.get_buffer();
+        // the client application writes to the shared buffer
+        unsafe { *pointer = 0 };
+        let _ = input.get_buffer();
+    }
+
+    #[test]
+    #[should_panic(expected = "size changed from 8 to 16")]
+    fn test_raw_size_modified() {
+        let mut buffer = [1u8; 8];
+        let mut raw_param = memref(&mut buffer);
+        let raw_pointer: *mut TEE_Param = &mut raw_param;
+        let mut output = unsafe {
+            ParameterMemrefOutput::from_raw(ParamType::MemrefOutput.into(), &mut raw_param)
+        }
+        .unwrap();
+        unsafe { (*raw_pointer).memref.size = 16 };
+        output.set_updated_size(4).unwrap();
+    }
+}
@@ -29,6 +29,10 @@
 //! * Three concrete wrappers encoding the data direction:
 //!   [`ParameterMemrefInput`], [`ParameterMemrefOutput`],
 //!   [`ParameterMemrefInout`].
+//! * [`OutputWriter`] for producing variable-sized output following the
+//!   GlobalPlatform short-buffer convention.
+//! * [`MemrefSnapshot`] for decoding input from a private copy of the
+//!   buffer.
 //!
 //! # Direction guarantees
 //!
@@ -37,9 +41,53 @@
 //! | `ParameterMemrefInput` | ✓ | ✗ |
 //! | `ParameterMemrefOutput` | ✗ | ✓ |
 //! | `ParameterMemrefInout` | ✓ | ✓ |
+//!
+//! # Guard mode
+//!
+//! With the `memref_guard` feature, the wrappers re-validate the buffer
+//! bounds before every access and trap when the shared buffer or the raw
+//! parameter is modified behind the TA's back. This is meant for development
+//! builds, without the feature the checks compile to nothing.
+//!
+//! # Strict checks
+//!
+//! With the `strict_checks` feature, the conversion of the parameters fails
+//! with `AccessDenied` unless the TA can read the buffer of an input, write
+//! the buffer of an output, or both for an in/out parameter, see the
+//! [`memory`](crate::memory) module.
+//!
+//! # Serialized values
+//!
+//! The buffer is shared with the client application, which can modify it
+//! while the TA reads it. A decoder reading the same byte twice may then see
+//! two different values, so serialized input is decoded from a copy taken
+//! once with [`ParameterMemrefRead::deserialize`] or
+//! [`ParameterMemrefRead::snapshot`], using the codecs of the
+//! [`dispatch`](crate::dispatch) module:
+//!
+//! ```rust,ignore
+//! use optee_utee::dispatch::Json;
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Request<'a> {
+//!     name: &'a str,
+//! }
+//!
+//! let input = p0.snapshot();
+//! let request: Request = input.deserialize_borrowed::<Json, _>()?;
+//! p1.serialize_into::<Json, _>(&greet(request.name))?;
+//! ```
+
+use alloc::vec::Vec;
 
+use super::guard::MemrefGuard;
 use super::{FromRawParameter, ParamType, RawParamType, check_type_is};
-use crate::{ErrorKind, Result, raw::TEE_Param};
+use crate::dispatch::{Decode, DecodeBorrowed, Encode};
+use crate::memory::AccessFlags;
+use crate::{
+    ErrorKind, Result,
+    raw::{self, TEE_Param},
+};
 
 /// Read-only access to a memory-reference parameter's buffer.
 ///
@@ -52,6 +100,22 @@ pub trait ParameterMemrefRead {
     /// full buffer capacity, not the number of valid bytes (which may have
     /// been updated by a prior write).
     fn get_buffer(&self) -> &[u8];
+
+    /// Returns a copy of the buffer contents, which the client application
+    /// cannot modify anymore.
+    fn snapshot(&self) -> MemrefSnapshot {
+        MemrefSnapshot {
+            bytes: self.get_buffer().to_vec(),
+        }
+    }
+
+    /// Decodes a `T` with the codec `C` from a copy of the buffer contents.
+    ///
+    /// Use [`MemrefSnapshot::deserialize_borrowed`] for a `T` borrowing from
+    /// the input.
+    fn deserialize<C: Decode<T>, T>(&self) -> Result<T> {
+        self.snapshot().deserialize::<C, T>()
+    }
 }
 
 /// Write access to a memory-reference parameter's buffer.
@@ -110,13 +174,63 @@ pub trait ParameterMemrefWrite {
     /// [`ParameterMemrefWrite::set_updated_size`] unless the caller has already
     /// checked the bounds.
     unsafe fn set_updated_size_unchecked(&mut self, size: usize);
+
+    /// Encodes `value` with the codec `C` into the buffer, then updates the
+    /// reported size.
+    ///
+    /// An encoded value that does not fit is reported like with
+    /// [`OutputWriter`]: the required size is passed back and
+    /// `ErrorKind::ShortBuffer` is returned. The value is encoded in the
+    /// command arena, see [`encode_output`](crate::dispatch::encode_output).
+    fn serialize_into<C: Encode<T>, T>(&mut self, value: &T) -> Result<()>
+    where
+        Self: Sized,
+    {
+        crate::dispatch::encode_with::<C, T, _>(value, |bytes| {
+            let mut writer = OutputWriter::new(&mut *self);
+            writer.write(bytes);
+            writer.finish()
+        })
+    }
+}
+
+/// A copy of the buffer of a memref parameter, see
+/// [`ParameterMemrefRead::snapshot`].
+pub struct MemrefSnapshot {
+    bytes: Vec<u8>,
+}
+
+impl MemrefSnapshot {
+    /// Returns the copied bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the copied bytes, consuming the snapshot.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Decodes a `T` with the codec `C`.
+    pub fn deserialize<C: Decode<T>, T>(&self) -> Result<T> {
+        C::decode(&self.bytes)
+    }
+
+    /// Decodes a `T` borrowing from the snapshot with the codec `C`, e.g. a
+    /// struct with `&str` fields with the `Json` codec.
+    pub fn deserialize_borrowed<'de, C: DecodeBorrowed<'de, T>, T>(&'de self) -> Result<T> {
+        C::decode_borrowed(&self.bytes)
+    }
 }
 
 /// A memory-reference input parameter.
 ///
 /// The host passes a read-only buffer to the TA. The length is the
 /// original buffer size as specified by the host.
-pub struct ParameterMemrefInput<'a>(&'a TEE_Param);
+pub struct ParameterMemrefInput<'a> {
+    raw_param: &'a TEE_Param,
+    guard: MemrefGuard,
+}
 
 /// A memory-reference in/out parameter.
 ///
@@ -125,6 +239,7 @@ pub struct ParameterMemrefInput<'a>(&'a TEE_Param);
 pub struct ParameterMemrefInout<'a> {
     capacity: usize,
     raw_param: &'a mut TEE_Param,
+    guard: MemrefGuard,
 }
 
 /// A memory-reference output parameter.
@@ -135,73 +250,208 @@ pub struct ParameterMemrefInout<'a> {
 pub struct ParameterMemrefOutput<'a> {
     capacity: usize,
     raw_param: &'a mut TEE_Param,
+    guard: MemrefGuard,
 }
 
 impl<'a> FromRawParameter<'a> for ParameterMemrefInput<'a> {
+    const PARAM_TYPE: Option<RawParamType> = Some(raw::TEE_PARAM_TYPE_MEMREF_INPUT);
+
     unsafe fn from_raw(raw_type: RawParamType, raw_param: &'a mut TEE_Param) -> Result<Self> {
         check_type_is(raw_type, ParamType::MemrefInput)?;
-        Ok(Self(raw_param))
+        check_access(raw_param, AccessFlags::READ)?;
+        Ok(Self {
+            guard: MemrefGuard::new(raw_param),
+            raw_param,
+        })
     }
 }
 impl<'a> FromRawParameter<'a> for ParameterMemrefInout<'a> {
+    const PARAM_TYPE: Option<RawParamType> = Some(raw::TEE_PARAM_TYPE_MEMREF_INOUT);
+
     unsafe fn from_raw(raw_type: RawParamType, raw_param: &'a mut TEE_Param) -> Result<Self> {
         check_type_is(raw_type, ParamType::MemrefInout)?;
+        check_access(raw_param, AccessFlags::READ.union(AccessFlags::WRITE))?;
         Ok(Self {
-            capacity: unsafe { raw_param.memref.size },
+            capacity: capacity(raw_param),
+            guard: MemrefGuard::new(raw_param),
             raw_param,
         })
     }
 }
 impl<'a> FromRawParameter<'a> for ParameterMemrefOutput<'a> {
+    const PARAM_TYPE: Option<RawParamType> = Some(raw::TEE_PARAM_TYPE_MEMREF_OUTPUT);
+
     unsafe fn from_raw(raw_type: RawParamType, raw_param: &'a mut TEE_Param) -> Result<Self> {
         check_type_is(raw_type, ParamType::MemrefOutput)?;
+        check_access(raw_param, AccessFlags::WRITE)?;
         Ok(Self {
-            capacity: unsafe { raw_param.memref.size },
+            capacity: capacity(raw_param),
+            guard: MemrefGuard::new(raw_param),
             raw_param,
         })
     }
 }
 
+/// The number of bytes the TA can write to the buffer of `raw_param`, none
+/// for the null buffer a client passes to query the size of an output.
+fn capacity(raw_param: &TEE_Param) -> usize {
+    let (buffer, size) = unsafe { (raw_param.memref.buffer, raw_param.memref.size) };
+    if buffer.is_null() { 0 } else { size }
+}
+
+/// The first `len` bytes of the buffer of `raw_param`, empty if it is null.
+///
+/// # Safety
+///
+/// Unless null, the buffer must be valid for reads of `len` bytes.
+unsafe fn buffer<'b>(raw_param: &TEE_Param, len: usize) -> &'b [u8] {
+    let buffer = unsafe { raw_param.memref.buffer } as *const u8;
+    if buffer.is_null() {
+        return &[];
+    }
+    unsafe { core::slice::from_raw_parts(buffer, len) }
+}
+
+/// The first `len` bytes of the buffer of `raw_param`, empty if it is null.
+///
+/// # Safety
+///
+/// Unless null, the buffer must be valid for writes of `len` bytes.
+unsafe fn buffer_mut<'b>(raw_param: &TEE_Param, len: usize) -> &'b mut [u8] {
+    let buffer = unsafe { raw_param.memref.buffer } as *mut u8;
+    if buffer.is_null() {
+        return &mut [];
+    }
+    unsafe { core::slice::from_raw_parts_mut(buffer, len) }
+}
+
+/// With the `strict_checks` feature, fail unless the TA has the rights
+/// `flags` on the buffer of `raw_param`. A null buffer is never accessed, it
+/// is not checked.
+#[cfg(feature = "strict_checks")]
+fn check_access(raw_param: &TEE_Param, flags: AccessFlags) -> Result<()> {
+    let (buffer, size) = unsafe { (raw_param.memref.buffer as *const u8, raw_param.memref.size) };
+    if buffer.is_null() {
+        return Ok(());
+    }
+    crate::memory::check_buffer_access(buffer, size, flags | AccessFlags::ANY_OWNER)
+}
+
+#[cfg(not(feature = "strict_checks"))]
+#[inline(always)]
+fn check_access(_raw_param: &TEE_Param, _flags: AccessFlags) -> Result<()> {
+    Ok(())
+}
+
 impl<'a> ParameterMemrefWrite for ParameterMemrefInout<'a> {
     fn get_buffer_mut(&mut self) -> &mut [u8] {
-        unsafe {
-            core::slice::from_raw_parts_mut(self.raw_param.memref.buffer as *mut u8, self.capacity)
-        }
+        self.guard.check_bounds(self.raw_param);
+        self.guard.unseal();
+        unsafe { buffer_mut(self.raw_param, self.capacity) }
     }
     fn get_capacity(&self) -> usize {
         self.capacity
     }
     unsafe fn set_updated_size_unchecked(&mut self, size: usize) {
+        self.guard.update_size(self.raw_param, size);
         self.raw_param.memref.size = size;
+        self.guard.seal();
     }
 }
 
 impl<'a> ParameterMemrefWrite for ParameterMemrefOutput<'a> {
     fn get_buffer_mut(&mut self) -> &mut [u8] {
-        unsafe {
-            core::slice::from_raw_parts_mut(self.raw_param.memref.buffer as *mut u8, self.capacity)
-        }
+        self.guard.check_bounds(self.raw_param);
+        self.guard.unseal();
+        unsafe { buffer_mut(self.raw_param, self.capacity) }
     }
     fn get_capacity(&self) -> usize {
         self.capacity
     }
     unsafe fn set_updated_size_unchecked(&mut self, size: usize) {
+        self.guard.update_size(self.raw_param, size);
         self.raw_param.memref.size = size;
+        self.guard.seal();
     }
 }
 
 impl<'a> ParameterMemrefRead for ParameterMemrefInout<'a> {
     fn get_buffer(&self) -> &[u8] {
-        unsafe {
-            core::slice::from_raw_parts(self.raw_param.memref.buffer as *const u8, self.capacity)
-        }
+        self.guard.check_bounds(self.raw_param);
+        self.guard.check_contents();
+        self.guard.seal();
+        unsafe { buffer(self.raw_param, self.capacity) }
     }
 }
 
 impl<'a> ParameterMemrefRead for ParameterMemrefInput<'a> {
     fn get_buffer(&self) -> &[u8] {
-        unsafe {
-            core::slice::from_raw_parts(self.0.memref.buffer as *const u8, self.0.memref.size)
+        self.guard.check_bounds(self.raw_param);
+        self.guard.check_contents();
+        self.guard.seal();
+        unsafe { buffer(self.raw_param, self.raw_param.memref.size) }
+    }
+}
+
+/// Writes variable-sized output into a memref following the GlobalPlatform
+/// short-buffer convention.
+///
+/// Data is appended with [`OutputWriter::write`]. Once it no longer fits
+/// the capacity, further data is only counted. [`OutputWriter::finish`]
+/// then reports either the number of bytes written, or the required size
+/// together with `ErrorKind::ShortBuffer`, so the client application can
+/// retry with a large enough buffer (see `optee_teec::OutputReader`).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let mut writer = OutputWriter::new(&mut p1);
+/// writer.write(&header);
+/// writer.write(&payload);
+/// writer.finish()?;
+/// ```
+pub struct OutputWriter<'p, P: ParameterMemrefWrite> {
+    param: &'p mut P,
+    required: usize,
+}
+
+impl<'p, P: ParameterMemrefWrite> OutputWriter<'p, P> {
+    /// Creates a writer appending from the start of `param`'s buffer.
+    pub fn new(param: &'p mut P) -> Self {
+        Self { param, required: 0 }
+    }
+
+    /// Appends `data`, or only counts it if the output no longer fits.
+    pub fn write<T: AsRef<[u8]>>(&mut self, data: T) {
+        let input = data.as_ref();
+        let new_size = self.required.saturating_add(input.len());
+        if new_size <= self.param.get_capacity() {
+            self.param.get_buffer_mut()[self.required..new_size].copy_from_slice(input);
         }
+        self.required = new_size;
+    }
+
+    /// Returns the number of bytes the output requires so far.
+    pub fn required_size(&self) -> usize {
+        self.required
+    }
+
+    /// Returns whether everything written so far fits the buffer.
+    pub fn fits(&self) -> bool {
+        self.required <= self.param.get_capacity()
+    }
+
+    /// Reports the output size to the client application.
+    ///
+    /// If the output did not fit, the required size is reported instead and
+    /// `ErrorKind::ShortBuffer` is returned.
+    pub fn finish(self) -> Result<()> {
+        // The size is passed back on short buffer too, this is how the client
+        // application learns the required size.
+        unsafe { self.param.set_updated_size_unchecked(self.required) };
+        if !self.fits() {
+            return Err(ErrorKind::ShortBuffer.into());
+        }
+        Ok(())
     }
 }
@@ -27,7 +27,8 @@
 //! * **`FromRawParameter`** – single-parameter conversion from a raw
 //!   `TEE_Param` into a typed Rust wrapper.
 //! * **`FromRawParameters`** – batch conversion of all four parameters at once
-//!   (implemented for 4-tuples of `FromRawParameter` types).
+//!   (implemented for 4-tuples of `FromRawParameter` types, the types of all
+//!   four are checked before any is converted).
 //! * **Typed wrappers** – `ParameterNone`, `ParameterValueInput`,
 //!   `ParameterMemrefInput`, etc.
 //! * **Type-erased wrapper** – [`ParameterAny`] for scenarios where the
@@ -45,11 +46,16 @@
 //! | `params.0.as_memref()?.buffer()` | `param.get_buffer()` or `param.get_buffer_mut()` via [`crate::ParameterMemrefRead`]/[`crate::ParameterMemrefWrite`] |
 //! | `params.0.set_updated_size(n)` | `param.set_updated_size(n)` via [`crate::ParameterMemrefWrite`] |
 //!
+//! A TA whose commands take different parameter types can keep receiving
+//! [`deprecated::Parameters`] and convert them per command with
+//! [`deprecated::Parameters::expect`].
+//!
 //! See the [`deprecated`] module for per-type migration notes.
 
 use crate::{ErrorKind, Result, raw};
 
 pub mod deprecated;
+mod guard;
 pub mod memref;
 pub mod none;
 pub mod value;
@@ -77,6 +83,12 @@ pub type RawParams = [raw::TEE_Param; raw::TEE_NUM_PARAMS as usize];
 /// type and that `raw_param` has been correctly initialized by the TEE
 /// runtime before reading any fields.
 pub trait FromRawParameter<'a>: Sized {
+    /// The only raw type `Self` is constructed from, `None` if it accepts any.
+    ///
+    /// Lets [`FromRawParameters`] check the types of all four parameters
+    /// before constructing any of them.
+    const PARAM_TYPE: Option<RawParamType> = None;
+
     /// Construct `Self` from a raw parameter.
     ///
     /// # Safety
@@ -112,6 +124,10 @@ impl<
 > FromRawParameters<'a> for (A, B, C, D)
 {
     unsafe fn from_raw(raw_types: RawParamTypes, raw_params: &'a mut RawParams) -> Result<Self> {
+        check_param_types(
+            raw_types,
+            [A::PARAM_TYPE, B::PARAM_TYPE, C::PARAM_TYPE, D::PARAM_TYPE],
+        )?;
         Ok(unsafe {
             let [p0, p1, p2, p3] = raw_params;
             (
@@ -151,6 +167,19 @@ fn check_type_is(raw_type: RawParamType, exp_type: ParamType) -> Result<()> {
     Ok(())
 }
 
+/// Check the four slots of `raw_types` against the expected types, `None`
+/// accepting any type.
+fn check_param_types(raw_types: RawParamTypes, expected: [Option<RawParamType>; 4]) -> Result<()> {
+    for (index, expected) in (0..).zip(expected) {
+        if let Some(expected) = expected
+            && raw::TEE_PARAM_TYPE_GET(raw_types, index) != expected
+        {
+            return Err(ErrorKind::BadParameters.into());
+        }
+    }
+    Ok(())
+}
+
 /// A type-erased parameter that dispatches to the correct concrete wrapper
 /// based on the runtime type tag.
 ///
@@ -245,6 +274,20 @@ impl<'a> ParameterAny<'a> {
             _ => Err(ErrorKind::BadParameters.into()),
         }
     }
+
+    /// The raw type the parameter was received as.
+    pub fn raw_type(&self) -> RawParamType {
+        match self {
+            Self::None => raw::TEE_PARAM_TYPE_NONE,
+            Self::ValueInput(_) => raw::TEE_PARAM_TYPE_VALUE_INPUT,
+            Self::ValueInout(_) => raw::TEE_PARAM_TYPE_VALUE_INOUT,
+            Self::ValueOutput(_) => raw::TEE_PARAM_TYPE_VALUE_OUTPUT,
+            Self::MemrefInput(_) => raw::TEE_PARAM_TYPE_MEMREF_INPUT,
+            Self::MemrefInout(_) => raw::TEE_PARAM_TYPE_MEMREF_INOUT,
+            Self::MemrefOutput(_) => raw::TEE_PARAM_TYPE_MEMREF_OUTPUT,
+            Self::Unknown(raw_type, _) => *raw_type,
+        }
+    }
 }
 
 impl<'a> FromRawParameter<'a> for ParameterAny<'a> {
@@ -282,9 +325,259 @@ pub type ParametersAny<'a> = (
     ParameterAny<'a>,
     ParameterAny<'a>,
 );
+/// The raw types `params` were received as, e.g. to check them against the
+/// parameter types a command declares in its `optee_proto` protocol.
+pub fn raw_param_types(params: &ParametersAny) -> RawParamTypes {
+    raw::TEE_PARAM_TYPES(
+        params.0.raw_type(),
+        params.1.raw_type(),
+        params.2.raw_type(),
+        params.3.raw_type(),
+    )
+}
+
 pub type ParametersNone = (
     none::ParameterNone,
     none::ParameterNone,
     none::ParameterNone,
     none::ParameterNone,
 );
+
+#[cfg(test)]
+mod tests {
+    use super::deprecated::Parameters;
+    use super::memref::{
+        OutputWriter, ParameterMemrefInput, ParameterMemrefOutput, ParameterMemrefRead,
+    };
+    use super::none::ParameterNone;
+    use super::value::{ParameterValueInout, ParameterValueRead, ParameterValueWrite};
+    use super::*;
+    use crate::ParameterMemrefWrite;
+    use alloc::vec::Vec;
+
+    fn raw_types(types: [u32; 4]) -> RawParamTypes {
+        raw::TEE_PARAM_TYPES(types[0], types[1], types[2], types[3])
+    }
+
+    fn raw_params(input: &mut [u8], output: &mut [u8]) -> RawParams {
+        [
+            raw::TEE_Param {
+                memref: raw::Memref {
+                    buffer: input.as_mut_ptr() as _,
+                    size: input.len(),
+                },
+            },
+            raw::TEE_Param {
+                memref: raw::Memref {
+                    buffer: output.as_mut_ptr() as _,
+                    size: output.len(),
+                },
+            },
+            raw::TEE_Param {
+                value: raw::Value { a: 1, b: 2 },
+            },
+            raw::TEE_Param {
+                value: raw::Value { a: 0, b: 0 },
+            },
+        ]
+    }
+
+    const EXPECTED: [u32; 4] = [
+        raw::TEE_PARAM_TYPE_MEMREF_INPUT,
+        raw::TEE_PARAM_TYPE_MEMREF_OUTPUT,
+        raw::TEE_PARAM_TYPE_VALUE_INOUT,
+        raw::TEE_PARAM_TYPE_NONE,
+    ];
+
+    type Expected<'a> = (
+        ParameterMemrefInput<'a>,
+        ParameterMemrefOutput<'a>,
+        ParameterValueInout<'a>,
+        ParameterNone,
+    );
+
+    #[test]
+    fn test_check_param_types() {
+        let expected = EXPECTED.map(Some);
+        assert!(check_param_types(raw_types(EXPECTED), expected).is_ok());
+        let mut types = EXPECTED;
+        types[3] = raw::TEE_PARAM_TYPE_VALUE_INPUT;
+        assert_eq!(
+            check_param_types(raw_types(types), expected)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::BadParameters
+        );
+        // ParameterAny accepts any type
+        assert!(check_param_types(raw_types(types), [None, None, None, None]).is_ok());
+    }
+
+    #[test]
+    fn test_raw_param_types() {
+        #[cfg(feature = "strict_checks")]
+        let _access = crate::memory::tests::allow_access();
+        let (mut input, mut output) = ([1u8], [0u8; 2]);
+        let mut params = raw_params(&mut input, &mut output);
+        let params = unsafe { ParametersAny::from_raw(raw_types(EXPECTED), &mut params) }.unwrap();
+        assert_eq!(raw_param_types(&params), raw_types(EXPECTED));
+    }
+
+    #[test]
+    fn test_from_raw_parameters() {
+        #[cfg(feature = "strict_checks")]
+        let _access = crate::memory::tests::allow_access();
+        let (mut input, mut output) = ([1u8, 2, 3], [0u8; 4]);
+        let mut params = raw_params(&mut input, &mut output);
+        let (input, mut output, mut value, _) =
+            unsafe { Expected::from_raw(raw_types(EXPECTED), &mut params) }.unwrap();
+        assert_eq!(input.get_buffer(), &[1, 2, 3]);
+        output.set_output(input.get_buffer()).unwrap();
+        value.set_a(value.get_a() + value.get_b());
+        assert_eq!(output_of(&params), (3, 3));
+
+        let mut types = EXPECTED;
+        types[0] = raw::TEE_PARAM_TYPE_MEMREF_INOUT;
+        let result = unsafe { Expected::from_raw(raw_types(types), &mut params) };
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_null_memrefs() {
+        #[cfg(feature = "strict_checks")]
+        let _access = crate::memory::tests::allow_access();
+        // A client querying the size of the output passes a null buffer
+        let mut params = raw_params(&mut [], &mut []);
+        for param in &mut params[..2] {
+            param.memref = raw::Memref {
+                buffer: core::ptr::null_mut(),
+                size: 16,
+            };
+        }
+        let (input, mut output, _, _) =
+            unsafe { Expected::from_raw(raw_types(EXPECTED), &mut params) }.unwrap();
+        assert!(input.get_buffer().is_empty());
+        assert!(output.get_buffer_mut().is_empty());
+        assert_eq!(
+            output.set_output([1u8]).unwrap_err().kind(),
+            ErrorKind::ShortBuffer
+        );
+        let mut writer = OutputWriter::new(&mut output);
+        writer.write([1u8, 2]);
+        assert_eq!(writer.finish().unwrap_err().kind(), ErrorKind::ShortBuffer);
+        assert_eq!(output_of(&params).0, 2);
+    }
+
+    #[cfg(feature = "strict_checks")]
+    #[test]
+    fn test_strict_checks() {
+        use optee_utee_sys::{mock_api, mock_utils::SERIAL_TEST_LOCK};
+
+        let _lock = SERIAL_TEST_LOCK.lock().expect("should get the lock");
+        let (mut input, mut output) = ([1u8], [0u8; 2]);
+        let output_buffer = output.as_ptr() as usize;
+        let mut params = raw_params(&mut input, &mut output);
+        // The TA may read the input but not write the output
+        let check = mock_api::TEE_CheckMemoryAccessRights_context();
+        check.expect().returning(move |flags, buffer, _| {
+            assert_ne!(flags & raw::TEE_MEMORY_ACCESS_ANY_OWNER, 0);
+            if buffer as usize == output_buffer {
+                assert_eq!(
+                    flags & raw::TEE_MEMORY_ACCESS_WRITE,
+                    raw::TEE_MEMORY_ACCESS_WRITE
+                );
+                raw::TEE_ERROR_ACCESS_DENIED
+            } else {
+                assert_eq!(flags & raw::TEE_MEMORY_ACCESS_WRITE, 0);
+                raw::TEE_SUCCESS
+            }
+        });
+        let result = unsafe { Expected::from_raw(raw_types(EXPECTED), &mut params) };
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::AccessDenied);
+    }
+
+    fn output_of(params: &RawParams) -> (usize, u32) {
+        unsafe { (params[1].memref.size, params[2].value.a) }
+    }
+
+    #[test]
+    fn test_serialized_memrefs() {
+        #[cfg(feature = "strict_checks")]
+        let _access = crate::memory::tests::allow_access();
+        use crate::dispatch::Bytes;
+
+        let (mut input, mut output) = ([1u8, 2, 3], [0u8; 2]);
+        let mut params = raw_params(&mut input, &mut output);
+        let (input, mut output, _, _) =
+            unsafe { Expected::from_raw(raw_types(EXPECTED), &mut params) }.unwrap();
+        let snapshot = input.snapshot();
+        assert_eq!(
+            snapshot.deserialize_borrowed::<Bytes, &[u8]>().unwrap(),
+            &[1, 2, 3]
+        );
+        let bytes: Vec<u8> = input.deserialize::<Bytes, _>().unwrap();
+        assert_eq!(bytes, [1, 2, 3]);
+
+        output.serialize_into::<Bytes, _>(&vec![4u8]).unwrap();
+        // Output that does not fit reports the required size
+        assert_eq!(
+            output
+                .serialize_into::<Bytes, _>(&bytes)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::ShortBuffer
+        );
+        assert_eq!(output_of(&params).0, 3);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_memrefs() {
+        #[cfg(feature = "strict_checks")]
+        let _access = crate::memory::tests::allow_access();
+        use crate::dispatch::Json;
+
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Greeting<'a> {
+            name: &'a str,
+        }
+
+        let mut input = br#"{"name":"TA"}"#.to_vec();
+        let mut output = [0u8; 32];
+        let mut params = raw_params(&mut input, &mut output);
+        let (input, mut output, _, _) =
+            unsafe { Expected::from_raw(raw_types(EXPECTED), &mut params) }.unwrap();
+        let snapshot = input.snapshot();
+        let greeting: Greeting = snapshot.deserialize_borrowed::<Json, _>().unwrap();
+        assert_eq!(greeting.name, "TA");
+        output.serialize_into::<Json, _>(&greeting).unwrap();
+        assert_eq!(&output_buffer(&params), br#"{"name":"TA"}"#);
+    }
+
+    #[cfg(feature = "json")]
+    fn output_buffer(params: &RawParams) -> Vec<u8> {
+        unsafe {
+            core::slice::from_raw_parts(params[1].memref.buffer as *const u8, params[1].memref.size)
+        }
+        .to_vec()
+    }
+
+    #[test]
+    fn test_deprecated_parameters_expect() {
+        #[cfg(feature = "strict_checks")]
+        let _access = crate::memory::tests::allow_access();
+        let (mut input_buffer, mut output_buffer) = ([4u8, 5], [0u8; 4]);
+        let mut raw = raw_params(&mut input_buffer, &mut output_buffer);
+        let mut params = Parameters::from_raw(&mut raw, raw_types(EXPECTED));
+        let (input, mut output, _, _): Expected = unsafe { params.expect() }.unwrap();
+        output.set_output(input.get_buffer()).unwrap();
+        assert!(
+            unsafe {
+                params
+                    .expect::<ParameterMemrefOutput, ParameterNone, ParameterNone, ParameterNone>()
+            }
+            .is_err()
+        );
+        assert_eq!(output_of(&raw), (2, 1));
+        assert_eq!(output_buffer, [4, 5, 0, 0]);
+    }
+}
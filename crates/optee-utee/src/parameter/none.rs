@@ -16,11 +16,16 @@
 // under the License.
 
 use super::{FromRawParameter, ParamType, RawParamType, check_type_is};
-use crate::{Result, raw::TEE_Param};
+use crate::{
+    Result,
+    raw::{self, TEE_Param},
+};
 
 pub struct ParameterNone;
 
 impl<'a> FromRawParameter<'a> for ParameterNone {
+    const PARAM_TYPE: Option<RawParamType> = Some(raw::TEE_PARAM_TYPE_NONE);
+
     unsafe fn from_raw(raw_type: RawParamType, _raw_param: &'a mut TEE_Param) -> Result<Self> {
         check_type_is(raw_type, ParamType::None)?;
         Ok(Self)
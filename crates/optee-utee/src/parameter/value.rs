@@ -37,7 +37,10 @@
 //! | `ParameterValueInout` | ✓ | ✓ |
 
 use super::{FromRawParameter, ParamType, RawParamType, check_type_is};
-use crate::{Result, raw::TEE_Param};
+use crate::{
+    Result,
+    raw::{self, TEE_Param},
+};
 
 /// Read-only access to the two `u32` fields of a value parameter.
 ///
@@ -83,6 +86,8 @@ pub struct ParameterValueOutput<'a>(&'a mut TEE_Param);
 pub struct ParameterValueInout<'a>(&'a mut TEE_Param);
 
 impl<'a> FromRawParameter<'a> for ParameterValueInput {
+    const PARAM_TYPE: Option<RawParamType> = Some(raw::TEE_PARAM_TYPE_VALUE_INPUT);
+
     unsafe fn from_raw(raw_type: RawParamType, raw_param: &'a mut TEE_Param) -> Result<Self> {
         check_type_is(raw_type, ParamType::ValueInput)?;
         Ok(Self {
@@ -93,6 +98,8 @@ impl<'a> FromRawParameter<'a> for ParameterValueInput {
 }
 
 impl<'a> FromRawParameter<'a> for ParameterValueOutput<'a> {
+    const PARAM_TYPE: Option<RawParamType> = Some(raw::TEE_PARAM_TYPE_VALUE_OUTPUT);
+
     unsafe fn from_raw(raw_type: RawParamType, raw_param: &'a mut TEE_Param) -> Result<Self> {
         check_type_is(raw_type, ParamType::ValueOutput)?;
         Ok(Self(raw_param))
@@ -100,6 +107,8 @@ impl<'a> FromRawParameter<'a> for ParameterValueOutput<'a> {
 }
 
 impl<'a> FromRawParameter<'a> for ParameterValueInout<'a> {
+    const PARAM_TYPE: Option<RawParamType> = Some(raw::TEE_PARAM_TYPE_VALUE_INOUT);
+
     unsafe fn from_raw(raw_type: RawParamType, raw_param: &'a mut TEE_Param) -> Result<Self> {
         check_type_is(raw_type, ParamType::ValueInout)?;
         Ok(Self(raw_param))
@@ -17,13 +17,16 @@
 
 use crate::{Error, ErrorKind, Result};
 use crate::{Identity, Uuid};
-use alloc::{ffi::CString, string::String, vec::Vec};
+use alloc::{string::String, vec::Vec};
+// Returned by `PropertyKey::key`, and named by `define_property_key!`.
+pub use alloc::ffi::CString;
 use optee_utee_sys as raw;
 
 /// Represents a TEE property set according to the TEE Internal API.
 /// The property set is a collection of properties that can be
 /// queried from the TEE. The property set is identified by a
 /// handle, which is a pointer to a TEE_PropSetHandle structure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PropertySet {
     TeeImplementation,
     CurrentClient,
@@ -233,6 +236,88 @@ impl PropertyValue for Identity {
     }
 }
 
+/// A property looked up by name, for the properties without a
+/// [`PropertyKey`], such as the custom properties of a TA set with
+/// `TaConfig::add_ext_property` of `optee-utee-build`.
+///
+/// The value is read with the getter of its type:
+///
+/// ``` rust,no_run
+/// # use optee_utee::{Property, PropertySet};
+/// # fn main() -> optee_utee::Result<()> {
+/// let retries = Property::new(PropertySet::CurrentTa, "org.example.retries").as_u32()?;
+/// let device_id = Property::new(PropertySet::TeeImplementation, "gpd.tee.deviceID").as_uuid()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// A getter fails with [`ItemNotFound`](ErrorKind::ItemNotFound) if the
+/// property does not exist, and with [`BadFormat`](ErrorKind::BadFormat) if
+/// its value cannot be converted to the type.
+#[derive(Clone, Copy, Debug)]
+pub struct Property<'a> {
+    set: PropertySet,
+    name: &'a str,
+}
+
+impl<'a> Property<'a> {
+    /// Create the property `name` of the property set `set`.
+    pub fn new(set: PropertySet, name: &'a str) -> Self {
+        Self { set, name }
+    }
+
+    /// The property set of the property.
+    pub fn set(&self) -> PropertySet {
+        self.set
+    }
+
+    /// The name of the property.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Read the value as a string.
+    pub fn as_string(&self) -> Result<String> {
+        self.get()
+    }
+
+    /// Read the value as a boolean.
+    pub fn as_bool(&self) -> Result<bool> {
+        self.get()
+    }
+
+    /// Read the value as a 32-bit integer.
+    pub fn as_u32(&self) -> Result<u32> {
+        self.get()
+    }
+
+    /// Read the value as a 64-bit integer.
+    pub fn as_u64(&self) -> Result<u64> {
+        self.get()
+    }
+
+    /// Read the value as a binary block, which is Base64 encoded in the property.
+    pub fn as_binary_block(&self) -> Result<Vec<u8>> {
+        self.get()
+    }
+
+    /// Read the value as a UUID.
+    pub fn as_uuid(&self) -> Result<Uuid> {
+        self.get()
+    }
+
+    /// Read the value as an identity.
+    pub fn as_identity(&self) -> Result<Identity> {
+        self.get()
+    }
+
+    fn get<T: PropertyValue>(&self) -> Result<T> {
+        // A name with a NUL byte cannot name a property
+        let key = CString::new(self.name).map_err(|_| ErrorKind::ItemNotFound)?;
+        unsafe { T::from_raw(self.set.as_raw(), key) }
+    }
+}
+
 /// Represents a TEE property key.
 /// The property key is used to identify a specific property
 /// within a property set. The property key is a string that
@@ -252,25 +337,47 @@ pub trait PropertyKey {
 /// Macro to define a property key.
 /// This macro generates a struct that implements the
 /// PropertyKey trait.
+///
+/// TAs use it for typed keys of their custom properties, declared with
+/// `TaConfig::add_ext_property` of optee-utee-build:
+///
+/// ``` rust,no_run
+/// # use optee_utee::define_property_key;
+/// # use optee_utee::property::PropertyKey;
+/// # fn main() -> optee_utee::Result<()> {
+/// define_property_key!(
+///     /// Maximum number of keys stored by the TA.
+///     MaxKeys,
+///     CurrentTa,
+///     "org.example.max_keys",
+///     u32
+/// );
+///
+/// let max_keys = MaxKeys.get()?;
+/// # Ok(())
+/// # }
+/// ```
 #[macro_export]
 macro_rules! define_property_key {
     (
+        $(#[$attr:meta])*
         $name:ident,
         $set:ident,
         $key:literal,
         $output:ty
     ) => {
+        $(#[$attr])*
         pub struct $name;
 
-        impl PropertyKey for $name {
+        impl $crate::property::PropertyKey for $name {
             type Output = $output;
 
-            fn key(&self) -> CString {
-                CString::new($key).unwrap_or_default()
+            fn key(&self) -> $crate::property::CString {
+                $crate::property::CString::new($key).unwrap_or_default()
             }
 
-            fn set(&self) -> PropertySet {
-                PropertySet::$set
+            fn set(&self) -> $crate::property::PropertySet {
+                $crate::property::PropertySet::$set
             }
         }
     };
@@ -469,3 +576,23 @@ define_property_key!(
     "gpd.tee.event.maxSources",
     u32
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    define_property_key!(
+        /// A custom property of the TA.
+        MaxKeys,
+        CurrentTa,
+        "org.example.max_keys",
+        u32
+    );
+
+    #[test]
+    fn test_define_property_key() {
+        assert_eq!(MaxKeys.key().as_bytes(), b"org.example.max_keys");
+        assert_eq!(MaxKeys.set(), PropertySet::CurrentTa);
+        assert_eq!(TaAppId.key().as_bytes(), b"gpd.ta.appID");
+    }
+}
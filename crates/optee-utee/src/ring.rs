@@ -0,0 +1,430 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! A ring buffer in shared memory, streaming messages between the client
+//! application and the TA without an invocation per message.
+//!
+//! The client application allocates the shared memory and formats it with
+//! `optee_teec::RingSender`, to stream messages to the TA, or
+//! `optee_teec::RingReceiver`, to stream messages from it. It pushes or pops
+//! messages directly in the shared memory, and rings the doorbell, an
+//! invocation of a command of the TA, when the ring is full or empty, for the
+//! TA to [`receive`] or [`send`] the messages:
+//!
+//! ```rust,ignore
+//! #[ta_invoke_command]
+//! fn invoke_command(cmd_id: u32, params: &mut ParametersAny<'_>) -> Result<()> {
+//!     match Command::from(cmd_id) {
+//!         // Frames streamed by the client application
+//!         Command::Frames => ring::receive(params, |frame| decoder.decode(frame)),
+//!         // Log lines streamed to the client application
+//!         Command::Logs => ring::send(params, |ring| {
+//!             while let Some(line) = logs.front() {
+//!                 if !ring.push(line)? {
+//!                     break;
+//!                 }
+//!                 logs.pop_front();
+//!             }
+//!             Ok(())
+//!         }),
+//!         _ => Err(ErrorKind::BadParameters.into()),
+//!     }
+//! }
+//! ```
+//!
+//! The doorbell has the parameters:
+//!
+//! | Parameter | Type          | Content |
+//! |-----------|---------------|---------|
+//! | 0         | memref inout  | the whole shared memory of the ring |
+//! | 1         | value output  | `a`: number of messages the TA received or sent |
+//!
+//! # Layout
+//!
+//! The ring is a header, followed by [`DATA_OFFSET`] by the data of the
+//! capacity set in the header, a multiple of 8. All the fields are little
+//! endian, and the producer and consumer positions are on separate cache
+//! lines:
+//!
+//! | Offset | Size | Content |
+//! |--------|------|---------|
+//! | 0      | 4    | magic, [`MAGIC`] |
+//! | 4      | 4    | version of the layout, [`VERSION`] |
+//! | 8      | 4    | capacity of the data, in bytes |
+//! | 64     | 8    | head: bytes pushed since the ring was formatted |
+//! | 72     | 4    | sequence number of the next message pushed |
+//! | 128    | 8    | tail: bytes popped since the ring was formatted |
+//! | 136    | 4    | sequence number of the next message popped |
+//!
+//! A message is stored at the head, modulo the capacity, as its length and
+//! its sequence number, 4 bytes each, followed by its bytes, padded to a
+//! multiple of 8. The sequence numbers detect a ring used by more than one
+//! producer or consumer, and the positions are checked at every access, so a
+//! corrupted ring fails with `BadFormat` rather than reading out of bounds.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use crate::{Error, ErrorKind, ParameterMemrefWrite, ParameterValueWrite, ParametersAny, Result};
+
+/// Magic of a ring, `ORNG`.
+pub const MAGIC: u32 = u32::from_le_bytes(*b"ORNG");
+/// Version of the layout of the ring.
+pub const VERSION: u32 = 1;
+/// Offset of the data in the ring.
+pub const DATA_OFFSET: usize = 192;
+
+const CAPACITY_OFFSET: usize = 8;
+const HEAD_OFFSET: usize = 64;
+const PUSHED_OFFSET: usize = 72;
+const TAIL_OFFSET: usize = 128;
+const POPPED_OFFSET: usize = 136;
+/// Size of the length and sequence number of a message.
+const RECORD_HEADER: usize = 8;
+
+/// The size of the shared memory of a ring of `capacity` bytes of data.
+pub const fn ring_size(capacity: usize) -> usize {
+    DATA_OFFSET + capacity
+}
+
+/// The space a message of `len` bytes takes in the ring, `None` if it
+/// overflows, e.g. for a length read from a corrupted ring on 32-bit TAs.
+fn record_size(len: usize) -> Option<usize> {
+    len.checked_next_multiple_of(8)?.checked_add(RECORD_HEADER)
+}
+
+fn corrupted() -> Error {
+    Error::with_message(ErrorKind::BadFormat, "corrupted ring buffer")
+}
+
+/// A ring in shared memory, used by a single producer and a single consumer,
+/// see the [module documentation](self).
+pub struct Ring<'a> {
+    base: *mut u8,
+    capacity: usize,
+    _marker: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> Ring<'a> {
+    /// Formats `memory` as an empty ring of the largest capacity it fits.
+    ///
+    /// Fails with `BadParameters` if `memory` is not aligned to 8 bytes or
+    /// has no room for data.
+    pub fn init(memory: &'a mut [u8]) -> Result<Self> {
+        let capacity = memory.len().saturating_sub(DATA_OFFSET) / 8 * 8;
+        if memory.as_ptr().align_offset(8) != 0 || capacity == 0 || capacity > u32::MAX as usize {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        memory[..DATA_OFFSET].fill(0);
+        memory[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        memory[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        memory[CAPACITY_OFFSET..CAPACITY_OFFSET + 4]
+            .copy_from_slice(&(capacity as u32).to_le_bytes());
+        Self::attach(memory)
+    }
+
+    /// Uses the ring formatted in `memory`.
+    ///
+    /// Fails with `BadParameters` if `memory` is not aligned to 8 bytes, and
+    /// with `BadFormat` if it does not hold a ring of this version.
+    pub fn attach(memory: &'a mut [u8]) -> Result<Self> {
+        if memory.as_ptr().align_offset(8) != 0 {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        if memory.len() < DATA_OFFSET {
+            return Err(corrupted());
+        }
+        let field =
+            |offset: usize| u32::from_le_bytes(memory[offset..offset + 4].try_into().unwrap());
+        let capacity = field(CAPACITY_OFFSET) as usize;
+        if field(0) != MAGIC
+            || field(4) != VERSION
+            || capacity == 0
+            || !capacity.is_multiple_of(8)
+            || capacity > memory.len() - DATA_OFFSET
+        {
+            return Err(corrupted());
+        }
+        Ok(Self {
+            base: memory.as_mut_ptr(),
+            capacity,
+            _marker: PhantomData,
+        })
+    }
+
+    /// The capacity of the data, in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The largest message the ring can hold.
+    pub fn max_message_len(&self) -> usize {
+        self.capacity - RECORD_HEADER
+    }
+
+    fn u64_at(&self, offset: usize) -> &AtomicU64 {
+        // SAFETY: the offsets are within the header, aligned to 8 as the
+        // base, and the memory outlives self.
+        unsafe { AtomicU64::from_ptr(self.base.add(offset) as *mut u64) }
+    }
+
+    fn u32_at(&self, offset: usize) -> &AtomicU32 {
+        // SAFETY: see u64_at().
+        unsafe { AtomicU32::from_ptr(self.base.add(offset) as *mut u32) }
+    }
+
+    /// The head and the tail, checked to be consistent.
+    fn positions(&self, head: Ordering, tail: Ordering) -> Result<(u64, u64)> {
+        let head = u64::from_le(self.u64_at(HEAD_OFFSET).load(head));
+        let tail = u64::from_le(self.u64_at(TAIL_OFFSET).load(tail));
+        match head.checked_sub(tail) {
+            Some(used) if used <= self.capacity as u64 && tail.is_multiple_of(8) => {
+                Ok((head, tail))
+            }
+            _ => Err(corrupted()),
+        }
+    }
+
+    /// Copies `data` into the data of the ring at `position`, wrapping
+    /// around the end.
+    fn write_at(&mut self, position: u64, data: &[u8]) {
+        let offset = (position % self.capacity as u64) as usize;
+        let first = data.len().min(self.capacity - offset);
+        // SAFETY: both parts are within the data of the ring, which the
+        // consumer does not access until the head is moved past them.
+        unsafe {
+            let start = self.base.add(DATA_OFFSET);
+            ptr::copy_nonoverlapping(data.as_ptr(), start.add(offset), first);
+            ptr::copy_nonoverlapping(data[first..].as_ptr(), start, data.len() - first);
+        }
+    }
+
+    /// Copies the data of the ring at `position` into `data`, wrapping around
+    /// the end.
+    fn read_at(&self, position: u64, data: &mut [u8]) {
+        let offset = (position % self.capacity as u64) as usize;
+        let first = data.len().min(self.capacity - offset);
+        // SAFETY: both parts are within the data of the ring, which the
+        // producer does not access until the tail is moved past them.
+        unsafe {
+            let start = self.base.add(DATA_OFFSET);
+            ptr::copy_nonoverlapping(start.add(offset), data.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(start, data[first..].as_mut_ptr(), data.len() - first);
+        }
+    }
+
+    /// Pushes `message`, returning false if the ring is too full to hold it.
+    ///
+    /// Fails with `ExcessData` if the message is longer than
+    /// [`max_message_len`](Self::max_message_len).
+    pub fn push(&mut self, message: &[u8]) -> Result<bool> {
+        let size = record_size(message.len()).unwrap_or(usize::MAX);
+        if size > self.capacity {
+            return Err(Error::with_message(
+                ErrorKind::ExcessData,
+                "message larger than the ring buffer",
+            ));
+        }
+        let (head, tail) = self.positions(Ordering::Relaxed, Ordering::Acquire)?;
+        if size as u64 > self.capacity as u64 - (head - tail) {
+            return Ok(false);
+        }
+        let sequence = u32::from_le(self.u32_at(PUSHED_OFFSET).load(Ordering::Relaxed));
+        let mut header = [0u8; RECORD_HEADER];
+        header[..4].copy_from_slice(&(message.len() as u32).to_le_bytes());
+        header[4..].copy_from_slice(&sequence.to_le_bytes());
+        self.write_at(head, &header);
+        self.write_at(head + RECORD_HEADER as u64, message);
+        self.u32_at(PUSHED_OFFSET)
+            .store(sequence.wrapping_add(1).to_le(), Ordering::Relaxed);
+        self.u64_at(HEAD_OFFSET)
+            .store((head + size as u64).to_le(), Ordering::Release);
+        Ok(true)
+    }
+
+    /// Pops the next message, `None` if the ring is empty.
+    ///
+    /// Fails with `BadFormat` if the message is out of sequence or does not
+    /// fit in what was pushed.
+    pub fn pop(&mut self) -> Result<Option<Vec<u8>>> {
+        let (head, tail) = self.positions(Ordering::Acquire, Ordering::Relaxed)?;
+        if head == tail {
+            return Ok(None);
+        }
+        let mut header = [0u8; RECORD_HEADER];
+        self.read_at(tail, &mut header);
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let sequence = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let expected = u32::from_le(self.u32_at(POPPED_OFFSET).load(Ordering::Relaxed));
+        let size = match record_size(len) {
+            Some(size) if sequence == expected && size as u64 <= head - tail => size,
+            _ => return Err(corrupted()),
+        };
+        let mut message = vec![0u8; len];
+        self.read_at(tail + RECORD_HEADER as u64, &mut message);
+        self.u32_at(POPPED_OFFSET)
+            .store(expected.wrapping_add(1).to_le(), Ordering::Relaxed);
+        self.u64_at(TAIL_OFFSET)
+            .store((tail + size as u64).to_le(), Ordering::Release);
+        Ok(Some(message))
+    }
+
+    /// The sequence number of the next message pushed.
+    fn pushed(&self) -> u32 {
+        u32::from_le(self.u32_at(PUSHED_OFFSET).load(Ordering::Relaxed))
+    }
+}
+
+/// Answers the doorbell of a ring streaming messages to the TA, passing
+/// every message in the ring to `handler`, see the [module
+/// documentation](self).
+///
+/// A failure of `handler` fails the invocation, the message it failed on
+/// being consumed.
+pub fn receive<F>(params: &mut ParametersAny<'_>, mut handler: F) -> Result<()>
+where
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    let mut ring = Ring::attach(params.0.as_memref_inout()?.get_buffer_mut())?;
+    let mut received = 0;
+    while let Some(message) = ring.pop()? {
+        received += 1;
+        handler(&message)?;
+    }
+    params.1.as_value_output()?.set_a(received);
+    Ok(())
+}
+
+/// Answers the doorbell of a ring streaming messages from the TA, where
+/// `producer` pushes the messages to send, see the [module
+/// documentation](self).
+pub fn send<F>(params: &mut ParametersAny<'_>, producer: F) -> Result<()>
+where
+    F: FnOnce(&mut Ring<'_>) -> Result<()>,
+{
+    let mut ring = Ring::attach(params.0.as_memref_inout()?.get_buffer_mut())?;
+    let first = ring.pushed();
+    producer(&mut ring)?;
+    let sent = ring.pushed().wrapping_sub(first);
+    params.1.as_value_output()?.set_a(sent);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Memory aligned to 8 bytes for a ring of `capacity` bytes.
+    fn aligned(capacity: usize) -> Vec<u64> {
+        vec![0u64; ring_size(capacity) / 8]
+    }
+
+    fn bytes(memory: &mut [u64]) -> &mut [u8] {
+        // SAFETY: any byte pattern is a valid u64 and u8.
+        unsafe { core::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u8, memory.len() * 8) }
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut memory = aligned(64);
+        let mut ring = Ring::init(bytes(&mut memory)).unwrap();
+        assert_eq!((ring.capacity(), ring.max_message_len()), (64, 56));
+        assert_eq!(ring.pop().unwrap(), None);
+
+        // Wraps around the end many times
+        for round in 0..20u8 {
+            let first = vec![round; (round % 9) as usize];
+            let second = vec![round; 13];
+            assert!(ring.push(&first).unwrap());
+            assert!(ring.push(&second).unwrap());
+            assert_eq!(ring.pop().unwrap().as_deref(), Some(first.as_slice()));
+            assert_eq!(ring.pop().unwrap().as_deref(), Some(second.as_slice()));
+        }
+        assert_eq!(ring.pop().unwrap(), None);
+    }
+
+    #[test]
+    fn test_full() {
+        let mut memory = aligned(64);
+        let mut ring = Ring::init(bytes(&mut memory)).unwrap();
+        assert_eq!(
+            ring.push(&[0u8; 57]).unwrap_err().kind(),
+            ErrorKind::ExcessData
+        );
+        assert!(ring.push(&[1u8; 20]).unwrap());
+        assert!(ring.push(&[2u8; 20]).unwrap());
+        assert!(!ring.push(&[3u8; 1]).unwrap());
+        assert_eq!(ring.pop().unwrap(), Some(vec![1u8; 20]));
+        assert!(ring.push(&[3u8; 1]).unwrap());
+    }
+
+    #[test]
+    fn test_attach() {
+        let mut memory = aligned(64);
+        Ring::init(bytes(&mut memory))
+            .unwrap()
+            .push(b"kept")
+            .unwrap();
+        let mut ring = Ring::attach(bytes(&mut memory)).unwrap();
+        assert_eq!(ring.pop().unwrap().as_deref(), Some(&b"kept"[..]));
+
+        // Not a ring
+        let mut memory = aligned(64);
+        let err = Ring::attach(bytes(&mut memory)).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::BadFormat);
+        // Unaligned
+        let mut memory = aligned(64);
+        let err = Ring::init(&mut bytes(&mut memory)[1..]).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::BadParameters);
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let mut memory = aligned(64);
+        Ring::init(bytes(&mut memory)).unwrap().push(b"a").unwrap();
+        // A tail past the head
+        bytes(&mut memory)[TAIL_OFFSET] = 8 * 3;
+        let mut ring = Ring::attach(bytes(&mut memory)).unwrap();
+        assert_eq!(ring.pop().unwrap_err().kind(), ErrorKind::BadFormat);
+
+        // A message out of sequence
+        let mut memory = aligned(64);
+        Ring::init(bytes(&mut memory)).unwrap().push(b"a").unwrap();
+        bytes(&mut memory)[DATA_OFFSET + 4] = 1;
+        let mut ring = Ring::attach(bytes(&mut memory)).unwrap();
+        assert_eq!(ring.pop().unwrap_err().kind(), ErrorKind::BadFormat);
+
+        // A message longer than what was pushed
+        let mut memory = aligned(64);
+        Ring::init(bytes(&mut memory)).unwrap().push(b"a").unwrap();
+        bytes(&mut memory)[DATA_OFFSET] = 9;
+        let mut ring = Ring::attach(bytes(&mut memory)).unwrap();
+        assert_eq!(ring.pop().unwrap_err().kind(), ErrorKind::BadFormat);
+
+        // A length whose record size overflows
+        let mut memory = aligned(64);
+        Ring::init(bytes(&mut memory)).unwrap().push(b"a").unwrap();
+        bytes(&mut memory)[DATA_OFFSET..DATA_OFFSET + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut ring = Ring::attach(bytes(&mut memory)).unwrap();
+        assert_eq!(ring.pop().unwrap_err().kind(), ErrorKind::BadFormat);
+        assert_eq!(record_size(usize::MAX - 3), None);
+        assert_eq!(record_size(usize::MAX - 7), None);
+        assert_eq!(record_size(1), Some(16));
+    }
+}
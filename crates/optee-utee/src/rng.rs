@@ -0,0 +1,58 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use crate::Random;
+use rand_core::{CryptoRng, RngCore, impls};
+
+/// The random number generator of the TEE, as a [`rand_core`] generator for
+/// the crates taking one, e.g. to generate keys with `ed25519-dalek` or
+/// `k256`.
+///
+/// ``` rust,no_run
+/// # use optee_utee::OpteeRng;
+/// use rand_core::RngCore;
+///
+/// let mut secret = [0u8; 32];
+/// OpteeRng.fill_bytes(&mut secret);
+/// let id = OpteeRng.next_u64();
+/// ```
+///
+/// Every call gets its bytes from `TEE_GenerateRandom`, which panics the TA
+/// if it fails, so `try_fill_bytes` never returns an error.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OpteeRng;
+
+impl RngCore for OpteeRng {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        Random::generate(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for OpteeRng {}
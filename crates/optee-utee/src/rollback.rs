@@ -0,0 +1,266 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Anti-rollback version counters.
+//!
+//! The objects of the REE FS backend are encrypted and authenticated, but the
+//! normal world can replace the files with an older copy, which the TA then
+//! reads back as valid. A [`MonotonicCounter`] is kept in RPMB, which the
+//! normal world cannot roll back: a TA stores the value of the counter along
+//! with its state, bumps both on every update, and rejects a state older
+//! than the counter:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::rollback::MonotonicCounter;
+//! # struct Config { version: u64 }
+//! # fn load() -> optee_utee::Result<Config> { Ok(Config { version: 0 }) }
+//! # fn store(_: &Config) -> optee_utee::Result<()> { Ok(()) }
+//! # fn main() -> optee_utee::Result<()> {
+//! let mut counter = MonotonicCounter::open(b"config_version")?;
+//! let mut config = load()?;
+//! counter.check(config.version)?;
+//!
+//! // Update the state, then the counter: a crash in between leaves a state
+//! // newer than the counter, which `check` accepts.
+//! config.version = counter.read()? + 1;
+//! store(&config)?;
+//! counter.advance_to(config.version)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! On an OS built without RPMB, the counters fall back to the default
+//! backend with a warning in the trace output, and are only as protected as
+//! the other objects, see [`MonotonicCounter::is_rollback_protected`].
+
+use crate::storage::StorageBackend;
+use crate::{DataFlag, Error, ErrorKind, PersistentObject, Result};
+use alloc::vec::Vec;
+
+/// Prefix of the object IDs of the counters, so they never replace an object
+/// of the TA.
+const ID_PREFIX: &[u8] = b"\0optee-utee/rollback/";
+
+/// A persistent counter which only goes up, see the
+/// [module documentation](self).
+///
+/// The value is read from the storage on every access, so that the counter
+/// opened by several sessions or instances of a TA stays consistent, as long
+/// as they do not update it concurrently.
+#[derive(Debug)]
+pub struct MonotonicCounter {
+    object_id: Vec<u8>,
+    backend: StorageBackend,
+}
+
+impl MonotonicCounter {
+    /// Opens the counter `name` in RPMB, creating it at 0 if it does not
+    /// exist, or in the default backend if the OS is built without RPMB.
+    ///
+    /// # Errors
+    ///
+    /// 1) `StorageNotAvailable`: If RPMB is built in but not accessible, e.g.
+    ///    its key is not provisioned. The counter does not fall back to
+    ///    another backend then, which would fork its value.
+    /// 2) `CorruptObject`: If the counter is not a counter.
+    pub fn open(name: &[u8]) -> Result<Self> {
+        match Self::open_in(StorageBackend::Rpmb, name) {
+            // The storage ID of a backend OP-TEE is not built with is unknown.
+            Err(e) if e.kind() == ErrorKind::ItemNotFound => {
+                // The trace syscall is not mocked, so unit tests cannot link it
+                #[cfg(not(test))]
+                crate::trace_println!(
+                    "[!] RPMB is not available, counter {:?} is not protected against rollback",
+                    alloc::string::String::from_utf8_lossy(name)
+                );
+                Self::open_in(StorageBackend::Default, name)
+            }
+            result => result,
+        }
+    }
+
+    /// Opens the counter `name` in `backend`, creating it at 0 if it does not
+    /// exist.
+    ///
+    /// # Errors
+    ///
+    /// 1) `ItemNotFound`: If the OS is built without `backend`.
+    /// 2) `CorruptObject`: If the counter is not a counter.
+    pub fn open_in(backend: StorageBackend, name: &[u8]) -> Result<Self> {
+        let mut object_id = ID_PREFIX.to_vec();
+        object_id.extend_from_slice(name);
+        let counter = Self { object_id, backend };
+        match counter.read() {
+            Ok(_) => Ok(counter),
+            Err(e) if e.kind() == ErrorKind::ItemNotFound => {
+                match PersistentObject::create(
+                    backend.storage_id(),
+                    &counter.object_id,
+                    DataFlag::ACCESS_WRITE,
+                    None,
+                    &0u64.to_le_bytes(),
+                ) {
+                    // Created by another instance in the meantime
+                    Ok(_) => Ok(counter),
+                    Err(e) if e.kind() == ErrorKind::AccessConflict => Ok(counter),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The backend the counter is stored in.
+    pub fn backend(&self) -> StorageBackend {
+        self.backend
+    }
+
+    /// Returns whether the counter is stored in RPMB, and thus cannot be
+    /// rolled back by the normal world.
+    pub fn is_rollback_protected(&self) -> bool {
+        self.backend == StorageBackend::Rpmb
+    }
+
+    /// Returns the value of the counter.
+    pub fn read(&self) -> Result<u64> {
+        let mut object = PersistentObject::open(
+            self.backend.storage_id(),
+            &self.object_id,
+            DataFlag::ACCESS_READ | DataFlag::SHARE_READ,
+        )?;
+        // One more byte to detect a longer object
+        let mut buf = [0u8; 9];
+        if object.read(&mut buf)? != 8 {
+            return Err(Error::with_message(
+                ErrorKind::CorruptObject,
+                "rollback counter has an invalid size",
+            ));
+        }
+        let mut value = [0u8; 8];
+        value.copy_from_slice(&buf[..8]);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    /// Increments the counter, returns the new value.
+    ///
+    /// # Errors
+    ///
+    /// 1) `Overflow`: If the counter is at `u64::MAX`.
+    pub fn increment(&mut self) -> Result<u64> {
+        let value = self.read()?.checked_add(1).ok_or(ErrorKind::Overflow)?;
+        self.write(value)?;
+        Ok(value)
+    }
+
+    /// Sets the counter to `value`, which must not be lower than the
+    /// current value.
+    ///
+    /// # Errors
+    ///
+    /// 1) `Security`: If `value` is lower than the value of the counter.
+    pub fn advance_to(&mut self, value: u64) -> Result<()> {
+        let current = self.read()?;
+        if value < current {
+            return Err(Error::with_message(
+                ErrorKind::Security,
+                "rollback counter cannot go back",
+            ));
+        }
+        if value > current {
+            self.write(value)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that a state of version `version` is not older than the
+    /// counter, i.e. it has not been rolled back.
+    ///
+    /// # Errors
+    ///
+    /// 1) `Security`: If `version` is lower than the value of the counter.
+    pub fn check(&self, version: u64) -> Result<()> {
+        if version < self.read()? {
+            return Err(Error::with_message(
+                ErrorKind::Security,
+                "state is older than its rollback counter",
+            ));
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, value: u64) -> Result<()> {
+        // Replaces the object atomically, the counter is never left half
+        // written.
+        PersistentObject::create(
+            self.backend.storage_id(),
+            &self.object_id,
+            DataFlag::ACCESS_WRITE | DataFlag::OVERWRITE,
+            None,
+            &value.to_le_bytes(),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use optee_utee_sys::{self as raw, mock_utils::storage::MockStorage};
+
+    use super::*;
+
+    #[test]
+    fn test_counter_in_rpmb() {
+        let storage = MockStorage::new();
+        let mut counter = MonotonicCounter::open(b"config_version").unwrap();
+        assert!(counter.is_rollback_protected());
+        assert_eq!(counter.read().unwrap(), 0);
+        assert_eq!(counter.increment().unwrap(), 1);
+        counter.advance_to(5).unwrap();
+        counter.advance_to(5).unwrap();
+        let err = counter.advance_to(4).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Security);
+
+        // The value is kept when the counter is opened again
+        let counter = MonotonicCounter::open(b"config_version").unwrap();
+        assert_eq!(counter.read().unwrap(), 5);
+        assert!(counter.check(5).is_ok());
+        assert_eq!(counter.check(4).unwrap_err().kind(), ErrorKind::Security);
+        assert_eq!(
+            storage.storage_ids(),
+            [StorageBackend::Rpmb.storage_id() as u32]
+        );
+    }
+
+    #[test]
+    fn test_counter_without_rpmb() {
+        let storage = MockStorage::new();
+        storage.set_without_rpmb(true);
+        let mut counter = MonotonicCounter::open(b"config_version").unwrap();
+        assert!(!counter.is_rollback_protected());
+        assert_eq!(counter.backend(), StorageBackend::Default);
+        assert_eq!(counter.increment().unwrap(), 1);
+        assert_eq!(storage.storage_ids(), [raw::TEE_STORAGE_PRIVATE]);
+    }
+
+    #[test]
+    fn test_counter_overflow() {
+        let _storage = MockStorage::new();
+        let mut counter = MonotonicCounter::open(b"counter").unwrap();
+        counter.advance_to(u64::MAX).unwrap();
+        assert_eq!(counter.increment().unwrap_err().kind(), ErrorKind::Overflow);
+    }
+}
@@ -0,0 +1,247 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Handling of secrets in the memory of the TA.
+//!
+//! The TA heap is not cleared when it is freed, and the compiler may remove
+//! writes to memory that is not read anymore, such as a `fill(0)` right
+//! before a buffer is dropped. Secrets kept in Rust values, e.g. private keys,
+//! derived keys or PINs, would then stay in memory after their last use.
+//!
+//! - [`Zeroize`] wipes a value with volatile writes the compiler keeps.
+//! - [`Zeroizing`] wraps a value to wipe it when it is dropped.
+//! - [`ct_eq`] compares secrets, e.g. MACs or PINs, in a time independent of
+//!   their contents, and [`ct_cmp`] orders secret big-endian numbers, e.g.
+//!   the octet strings of [`BigInt`](crate::BigInt) values, the same way.
+//!
+//! ``` rust,no_run
+//! # use optee_utee::secure_mem::{Zeroizing, ct_eq};
+//! # fn read_pin() -> Vec<u8> { vec![] }
+//! # let expected: &[u8] = b"1234";
+//! let pin = Zeroizing::new(read_pin());
+//! if ct_eq(&pin, expected) {
+//!     // ...
+//! }
+//! // The PIN is wiped here
+//! ```
+//!
+//! Secrets held in TEE objects, e.g. the keys of the [`crypto`](crate::crypto)
+//! module, never enter the memory of the TA and need none of this.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{Ordering, compiler_fence};
+
+/// Values that can be wiped from memory.
+pub trait Zeroize {
+    /// Overwrite the value with zeros, with writes the compiler does not
+    /// remove.
+    fn zeroize(&mut self);
+}
+
+impl Zeroize for [u8] {
+    fn zeroize(&mut self) {
+        for byte in self.iter_mut() {
+            // A volatile write cannot be elided even if the buffer is not
+            // read anymore
+            unsafe { core::ptr::write_volatile(byte, 0) };
+        }
+        // Nor moved after the memory is freed
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl<const N: usize> Zeroize for [u8; N] {
+    fn zeroize(&mut self) {
+        self.as_mut_slice().zeroize();
+    }
+}
+
+impl Zeroize for Vec<u8> {
+    /// Wipe the whole allocation, including the capacity beyond the length
+    /// which may hold the bytes of a previous length, and clear the vector.
+    fn zeroize(&mut self) {
+        self.as_mut_slice().zeroize();
+        for byte in self.spare_capacity_mut() {
+            unsafe { core::ptr::write_volatile(byte.as_mut_ptr(), 0) };
+        }
+        compiler_fence(Ordering::SeqCst);
+        self.clear();
+    }
+}
+
+impl Zeroize for String {
+    fn zeroize(&mut self) {
+        // The zero bytes keep the string valid UTF-8
+        unsafe { self.as_mut_vec() }.zeroize();
+    }
+}
+
+/// A value wiped when it is dropped.
+///
+/// The value is reached through `Deref`, but moving it out, or a copy of
+/// it, escapes the wiping. Its `Debug` output does not show it.
+///
+/// Growing a `Zeroizing<Vec<u8>>` or `Zeroizing<String>` past its capacity
+/// reallocates it, and the previous buffer is freed without being wiped.
+/// Reserve the final capacity up front, e.g. with `Vec::with_capacity`,
+/// before writing secrets into it.
+pub struct Zeroizing<T: Zeroize>(T);
+
+impl<T: Zeroize> Zeroizing<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Zeroize> From<T> for Zeroizing<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Zeroize> Deref for Zeroizing<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> DerefMut for Zeroizing<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Zeroizing<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Zeroizing<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Zeroizing(..)")
+    }
+}
+
+/// Return whether `a` and `b` are equal, in a time depending only on their
+/// lengths, which are not secret.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y));
+    // Keeps the compiler from stopping at the first difference
+    core::hint::black_box(diff) == 0
+}
+
+/// Compare the unsigned big-endian numbers `a` and `b`, in a time depending
+/// only on their lengths, which are not secret. The shorter one is read as
+/// left-padded with zeros.
+///
+/// Secret numbers must be held at a fixed length, as the length of a number
+/// without its leading zeros reveals its magnitude.
+///
+/// Exporting a secret [`BigInt`](crate::BigInt) into such an octet string
+/// runs in the TEE in a time which may depend on the value, so secrets
+/// compared with this function are best kept as octet strings in the TA.
+pub fn ct_cmp(a: &[u8], b: &[u8]) -> cmp::Ordering {
+    let mut greater = 0u8;
+    let mut less = 0u8;
+    let len = a.len().max(b.len());
+    // Byte `i` of `number` padded to `len` bytes
+    let byte =
+        |number: &[u8], i: usize| (i + number.len()).checked_sub(len).map_or(0, |i| number[i]);
+    for i in 0..len {
+        let (x, y) = (byte(a, i) as i16, byte(b, i) as i16);
+        // 1 until the first differing byte
+        let undecided = !(greater | less) & 1;
+        // The sign bit of the difference is set if it is negative
+        greater |= undecided & ((y - x) >> 8) as u8 & 1;
+        less |= undecided & ((x - y) >> 8) as u8 & 1;
+    }
+    // Keeps the compiler from stopping at the first difference
+    (core::hint::black_box(greater) as i8 - less as i8).cmp(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(ct_eq(b"", b""));
+        assert!(ct_eq(b"1234", b"1234"));
+        assert!(!ct_eq(b"1234", b"1235"));
+        assert!(!ct_eq(b"1234", b"12345"));
+    }
+
+    #[test]
+    fn test_ct_cmp() {
+        assert_eq!(ct_cmp(&[], &[]), cmp::Ordering::Equal);
+        assert_eq!(ct_cmp(&[1, 2], &[1, 2]), cmp::Ordering::Equal);
+        assert_eq!(ct_cmp(&[1, 3], &[1, 2]), cmp::Ordering::Greater);
+        assert_eq!(ct_cmp(&[1, 2], &[1, 3]), cmp::Ordering::Less);
+        // The first differing byte decides
+        assert_eq!(ct_cmp(&[2, 0], &[1, 255]), cmp::Ordering::Greater);
+        assert_eq!(ct_cmp(&[0, 255], &[255, 0]), cmp::Ordering::Less);
+        // Shorter numbers are padded
+        assert_eq!(ct_cmp(&[0, 0, 7], &[7]), cmp::Ordering::Equal);
+        assert_eq!(ct_cmp(&[1], &[1, 0]), cmp::Ordering::Less);
+        assert_eq!(ct_cmp(&[1, 0], &[]), cmp::Ordering::Greater);
+        for x in 0..=255u8 {
+            for y in [0, 1, 127, 128, 254, 255] {
+                assert_eq!(ct_cmp(&[x], &[y]), x.cmp(&y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_zeroize() {
+        let mut key = [7u8; 16];
+        key.zeroize();
+        assert_eq!(key, [0u8; 16]);
+
+        let mut pin = vec![1u8, 2, 3, 4];
+        pin.truncate(2);
+        pin.zeroize();
+        assert!(pin.is_empty());
+        // The truncated bytes are wiped too
+        assert_eq!(unsafe { pin.as_ptr().add(2).read() }, 0);
+
+        let mut password = String::from("secret");
+        password.zeroize();
+        assert!(password.is_empty());
+    }
+
+    #[test]
+    fn test_zeroizing() {
+        // Reserved up front, pushing does not reallocate
+        let mut secret = Zeroizing::new(Vec::with_capacity(5));
+        secret.extend_from_slice(&[1u8; 4]);
+        let buffer = secret.as_ptr();
+        secret.push(2);
+        assert_eq!(secret.as_ptr(), buffer);
+        assert_eq!(*secret, [1, 1, 1, 1, 2]);
+        assert_eq!(format!("{:?}", secret), "Zeroizing(..)");
+    }
+}
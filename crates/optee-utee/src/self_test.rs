@@ -0,0 +1,361 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Self-tests shipped inside the TA.
+//!
+//! Code depending on the TEE, such as the secure storage or the crypto
+//! operations, can only be tested in the TEE. Functions marked with
+//! `#[ta_self_test]` in a module marked with `#[ta_self_test]` are run by the
+//! client application with the reserved command [`COMMAND_ID`], e.g. with
+//! `optee_teec::SelfTestReport::run`, which also renders the results as a
+//! JUnit report for the CI:
+//!
+//! ``` rust,ignore
+//! #[cfg(feature = "self_test")]
+//! #[ta_self_test]
+//! mod self_tests {
+//!     use optee_utee::self_test::TestResult;
+//!     use optee_utee::{self_test_assert, self_test_assert_eq};
+//!
+//!     #[ta_self_test]
+//!     fn seal_roundtrip() -> TestResult {
+//!         let sealed = super::seal(b"secret")?;
+//!         self_test_assert_eq!(super::unseal(&sealed)?, b"secret");
+//!         Ok(())
+//!     }
+//!
+//!     #[ta_self_test]
+//!     fn random_is_not_constant() -> optee_utee::Result<()> {
+//!         // ...
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! The command is answered by the `#[ta_invoke_command]` entry point before
+//! the parameters reach the TA, once the `self_test` feature is enabled.
+//! Release builds should leave both the feature and the module out, usually
+//! behind a feature of the TA enabling `optee-utee/self_test`. With the
+//! feature enabled the TA must have exactly one `#[ta_self_test]` module,
+//! linking fails otherwise.
+//!
+//! A test fails when it returns an error. A panic aborts the TA as anywhere
+//! else, so assertions should use [`self_test_assert!`](crate::self_test_assert)
+//! and [`self_test_assert_eq!`](crate::self_test_assert_eq), which return a
+//! [`Failure`] instead.
+//!
+//! The client passes an optional filter in parameter 0, a memref input
+//! holding a substring of the names of the tests to run, and receives the
+//! report in the memref output of parameter 1 and the number of passed and
+//! failed tests in the value output of parameter 2. Parameter 3 must be
+//! `None`. A report that does not fit is reported with the short-buffer
+//! convention, and the tests are run again by the retried command, so they
+//! must not depend on the state left by a previous run.
+//!
+//! The report holds one line per test, with the name, `ok` or `FAILED`, the
+//! duration in microseconds and the message of the failure separated by tabs.
+
+use crate::time::SystemTime;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The command ID running the self-tests, reserved by the SDK.
+pub const COMMAND_ID: u32 = 0xFFFF_5E1F;
+
+/// The failure of a self-test, with a message for the report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    message: String,
+}
+
+impl Failure {
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // Used by the assertion macros, which cannot rely on `alloc::format!` in
+    // the crate of the TA.
+    #[doc(hidden)]
+    pub fn from_args(args: fmt::Arguments) -> Self {
+        Self::new(alloc::fmt::format(args))
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<crate::Error> for Failure {
+    fn from(e: crate::Error) -> Self {
+        Self::new(e.to_string())
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<&str> for Failure {
+    fn from(message: &str) -> Self {
+        Self::new(message)
+    }
+}
+
+/// The result of a self-test.
+pub type TestResult = core::result::Result<(), Failure>;
+
+/// Fails the self-test with a [`Failure`] if the condition is false.
+#[macro_export]
+macro_rules! self_test_assert {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            return Err($crate::self_test::Failure::from_args(format_args!(
+                "assertion failed: {} at {}:{}",
+                stringify!($cond),
+                file!(),
+                line!()
+            ))
+            .into());
+        }
+    };
+}
+
+/// Fails the self-test with a [`Failure`] if the two values differ.
+#[macro_export]
+macro_rules! self_test_assert_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left != *right {
+                    return Err($crate::self_test::Failure::from_args(format_args!(
+                        "assertion `left == right` failed: {:?} != {:?} at {}:{}",
+                        left,
+                        right,
+                        file!(),
+                        line!()
+                    ))
+                    .into());
+                }
+            }
+        }
+    };
+}
+
+/// The results of the self-tests, written by the code generated by
+/// `#[ta_self_test]`.
+pub struct Report<'a> {
+    filter: &'a str,
+    output: Vec<u8>,
+    passed: u32,
+    failed: u32,
+    clock: fn() -> SystemTime,
+}
+
+impl<'a> Report<'a> {
+    /// Creates a report running the tests whose name contains `filter`.
+    pub fn new(filter: &'a str) -> Self {
+        Self::with_clock(filter, SystemTime::now)
+    }
+
+    fn with_clock(filter: &'a str, clock: fn() -> SystemTime) -> Self {
+        Self {
+            filter,
+            output: Vec::new(),
+            passed: 0,
+            failed: 0,
+            clock,
+        }
+    }
+
+    /// Runs the test `name` unless it is filtered out, and records its
+    /// result.
+    pub fn run<E: Into<Failure>>(&mut self, name: &str, test: fn() -> core::result::Result<(), E>) {
+        if !name.contains(self.filter) {
+            return;
+        }
+        let start = (self.clock)();
+        let result: TestResult = test().map_err(Into::into);
+        let micros = (self.clock)().saturating_duration_since(start).as_micros();
+        let (status, message) = match &result {
+            Ok(()) => {
+                self.passed += 1;
+                ("ok", "")
+            }
+            Err(failure) => {
+                self.failed += 1;
+                ("FAILED", failure.message())
+            }
+        };
+        // Tabs and line breaks of the message would break the format
+        let message: String = message
+            .trim()
+            .chars()
+            .map(|c| if c == '\t' || c == '\n' { ' ' } else { c })
+            .collect();
+        let line = format!("{}\t{}\t{}\t{}\n", name, status, micros, message);
+        self.output.extend_from_slice(line.as_bytes());
+    }
+
+    /// The number of tests that passed.
+    pub fn passed(&self) -> u32 {
+        self.passed
+    }
+
+    /// The number of tests that failed.
+    pub fn failed(&self) -> u32 {
+        self.failed
+    }
+
+    /// The report sent to the client application.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.output
+    }
+}
+
+#[cfg(feature = "self_test")]
+unsafe extern "Rust" {
+    // Defined by the `#[ta_self_test]` module of the TA.
+    fn __optee_utee_self_tests(report: &mut Report<'_>);
+}
+
+/// Runs the self-tests if `cmd_id` is [`COMMAND_ID`], returning the result of
+/// the command, or `None` for the other commands. Called by the
+/// `#[ta_invoke_command]` entry point.
+#[doc(hidden)]
+#[inline(always)]
+pub fn intercept(
+    cmd_id: u32,
+    param_types: crate::RawParamTypes,
+    params: &mut crate::RawParams,
+) -> Option<optee_utee_sys::TEE_Result> {
+    #[cfg(feature = "self_test")]
+    if cmd_id == COMMAND_ID {
+        return Some(match run_command(param_types, params) {
+            Ok(()) => optee_utee_sys::TEE_SUCCESS,
+            Err(e) => e.into_entry_point_code("TA_InvokeCommandEntryPoint"),
+        });
+    }
+    let _ = (cmd_id, param_types, params);
+    None
+}
+
+#[cfg(feature = "self_test")]
+fn run_command(
+    param_types: crate::RawParamTypes,
+    params: &mut crate::RawParams,
+) -> crate::Result<()> {
+    use crate::{ErrorKind, FromRawParameters, ParameterValueWrite, ParametersAny};
+
+    let mut params: ParametersAny = unsafe { FromRawParameters::from_raw(param_types, params)? };
+    params.3.as_none()?;
+    let filter = core::str::from_utf8(crate::dispatch::input(&params.0)?)
+        .map_err(|_| crate::Error::new(ErrorKind::BadFormat))?;
+    let mut report = Report::new(filter);
+    unsafe { __optee_utee_self_tests(&mut report) };
+
+    let counts = params.2.as_value_output()?;
+    counts.set_a(report.passed());
+    counts.set_b(report.failed());
+    crate::dispatch::output(&mut params.1, report.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, ErrorKind};
+
+    #[crate::ta_self_test]
+    mod self_tests {
+        use super::TestResult;
+
+        #[crate::ta_self_test]
+        fn passes() -> TestResult {
+            let values = [1, 2];
+            self_test_assert!(values[0] < values[1]);
+            Ok(())
+        }
+
+        #[crate::ta_self_test]
+        fn fails_with_error() -> crate::Result<()> {
+            Err(crate::ErrorKind::AccessDenied.into())
+        }
+
+        #[crate::ta_self_test]
+        fn fails_assert_eq() -> TestResult {
+            self_test_assert_eq!(1 + 1, 3);
+            Ok(())
+        }
+
+        #[crate::ta_self_test]
+        fn fails_multiline() -> TestResult {
+            Err("first\tline\nsecond line\n".into())
+        }
+
+        fn not_a_test() -> TestResult {
+            Err("not collected".into())
+        }
+
+        pub(super) fn helper() -> TestResult {
+            not_a_test()
+        }
+    }
+
+    fn clock() -> SystemTime {
+        SystemTime::default()
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = Report::with_clock("", clock);
+        self_tests::__optee_utee_self_tests(&mut report);
+        assert_eq!(report.passed(), 1);
+        assert_eq!(report.failed(), 3);
+        let report = core::str::from_utf8(report.as_bytes()).unwrap();
+        let lines: Vec<Vec<&str>> = report
+            .lines()
+            .map(|line| line.split('\t').collect())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0][0].ends_with("self_tests::passes"));
+        assert_eq!(lines[0][1..], ["ok", "0", ""]);
+        assert_eq!(lines[1][1], "FAILED");
+        assert_eq!(lines[1][3], Error::new(ErrorKind::AccessDenied).to_string());
+        assert!(lines[2][3].starts_with("assertion `left == right` failed: 2 != 3"));
+        // The tab and line breaks of the message are replaced
+        assert_eq!(lines[3][3], "first line second line");
+        assert!(self_tests::helper().is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut report = Report::with_clock("fails_", clock);
+        self_tests::__optee_utee_self_tests(&mut report);
+        assert_eq!(report.passed(), 0);
+        assert_eq!(report.failed(), 3);
+    }
+}
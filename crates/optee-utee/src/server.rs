@@ -0,0 +1,380 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! A runtime for TAs serving many sessions.
+//!
+//! A multi-session TA (`TA_FLAG_SINGLE_INSTANCE | TA_FLAG_MULTI_SESSION`)
+//! usually keeps per-session state in its session contexts and some state
+//! shared by all the sessions, e.g. a configuration loaded at creation or a
+//! table of connections. [`TaServer`] gives both a structure:
+//!
+//! - the per-session state is the session context `S` of the entry points,
+//!   see `#[ta_open_session]`, typically a state machine;
+//! - the commands are routed to handlers by [`Route`]s, each accepted only in
+//!   the session states it declares with [`Route::when`];
+//! - the shared state `G` is a singleton owned by the server, set up by
+//!   [`TaServer::start`] and reached by the handlers through
+//!   [`Request::shared`], one at a time.
+//!
+//! ``` rust,no_run
+//! # use optee_utee::prelude::*;
+//! # use optee_utee::{ErrorKind, Result};
+//! use optee_utee::server::{Request, Route, TaServer};
+//!
+//! #[derive(Default)]
+//! enum Session {
+//!     #[default]
+//!     Idle,
+//!     Counting(u32),
+//! }
+//!
+//! struct Stats {
+//!     total: u64,
+//! }
+//!
+//! static SERVER: TaServer<Session, Stats> = TaServer::new(&[
+//!     Route::new(0, start),
+//!     Route::new(1, count).when(|session| matches!(session, Session::Counting(_))),
+//! ]);
+//!
+//! fn start(request: &mut Request<'_, Session, Stats>, _: &mut ParametersAny<'_>) -> Result<()> {
+//!     *request.session() = Session::Counting(0);
+//!     Ok(())
+//! }
+//!
+//! fn count(request: &mut Request<'_, Session, Stats>, _: &mut ParametersAny<'_>) -> Result<()> {
+//!     if let Session::Counting(n) = request.session() {
+//!         *n += 1;
+//!     }
+//!     request.shared(|stats| stats.total += 1)
+//! }
+//!
+//! #[ta_create]
+//! fn create() -> Result<()> {
+//!     SERVER.start(Stats { total: 0 })
+//! }
+//!
+//! #[ta_invoke_command]
+//! fn invoke_command(
+//!     session: &mut Session,
+//!     cmd_id: u32,
+//!     params: &mut ParametersAny<'_>,
+//! ) -> Result<()> {
+//!     SERVER.invoke(session, cmd_id, params)
+//! }
+//! ```
+//!
+//! OP-TEE runs one entry point of a TA instance at a time, so the shared
+//! state is never contended: [`Request::shared`] only fails with
+//! `AccessConflict` when it is called again from within its closure.
+
+use crate::{ErrorKind, ParametersAny, Result, SessionId, SessionRegistry};
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A command handler, called with the request and the parameters of the
+/// command.
+pub type Handler<S, G> = fn(&mut Request<'_, S, G>, &mut ParametersAny<'_>) -> Result<()>;
+
+/// The handler of a command ID, and the session states accepting it.
+pub struct Route<S: 'static, G: 'static> {
+    id: u32,
+    handler: Handler<S, G>,
+    accepts: Option<fn(&S) -> bool>,
+}
+
+impl<S: 'static, G: 'static> Route<S, G> {
+    /// Routes the command `id` to `handler`, in any session state.
+    pub const fn new(id: u32, handler: Handler<S, G>) -> Self {
+        Self {
+            id,
+            handler,
+            accepts: None,
+        }
+    }
+
+    /// Only accepts the command in the session states for which `accepts`
+    /// returns true, failing it with `BadState` in the others.
+    pub const fn when(mut self, accepts: fn(&S) -> bool) -> Self {
+        self.accepts = Some(accepts);
+        self
+    }
+
+    /// The command ID of the route.
+    pub const fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// The runtime of a TA whose session contexts are `S` and whose shared state
+/// is `G`, see the [module documentation](self).
+pub struct TaServer<S: 'static, G: 'static = ()> {
+    routes: &'static [Route<S, G>],
+    shared: UnsafeCell<Option<G>>,
+    locked: AtomicBool,
+}
+
+// SAFETY: the shared state is only accessed by the holder of the `locked`
+// flag.
+unsafe impl<S, G: Send> Sync for TaServer<S, G> {}
+
+impl<S: 'static, G: 'static> TaServer<S, G> {
+    /// A server routing the commands with `routes`, where the first route of
+    /// a command ID wins.
+    pub const fn new(routes: &'static [Route<S, G>]) -> Self {
+        Self {
+            routes,
+            shared: UnsafeCell::new(None),
+            locked: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets up the shared state, typically from `#[ta_create]`.
+    ///
+    /// # Errors
+    ///
+    /// `BadState`: If the server is already started.
+    pub fn start(&self, shared: G) -> Result<()> {
+        self.with_lock(|state| match state {
+            Some(_) => Err(ErrorKind::BadState.into()),
+            None => {
+                *state = Some(shared);
+                Ok(())
+            }
+        })?
+    }
+
+    /// Takes the shared state back, e.g. from `#[ta_destroy]`, `None` if the
+    /// server is not started.
+    pub fn stop(&self) -> Option<G> {
+        self.with_lock(Option::take).ok().flatten()
+    }
+
+    /// Runs the command `cmd_id` of the session `session` with `params`.
+    ///
+    /// # Errors
+    ///
+    /// 1) `BadParameters`: If no route has the command ID `cmd_id`.
+    /// 2) `BadState`: If the route does not accept the command in the state
+    ///    of `session`.
+    /// 3) The errors of the handler.
+    pub fn invoke(
+        &self,
+        session: &mut S,
+        cmd_id: u32,
+        params: &mut ParametersAny<'_>,
+    ) -> Result<()> {
+        let route = self
+            .routes
+            .iter()
+            .find(|route| route.id == cmd_id)
+            .ok_or(ErrorKind::BadParameters)?;
+        if route.accepts.is_some_and(|accepts| !accepts(session)) {
+            return Err(ErrorKind::BadState.into());
+        }
+        let mut request = Request {
+            server: self,
+            session,
+            cmd_id,
+        };
+        (route.handler)(&mut request, params)
+    }
+
+    /// Runs `f` with the shared state.
+    ///
+    /// # Errors
+    ///
+    /// 1) `BadState`: If the server is not started.
+    /// 2) `AccessConflict`: If the shared state is already in use, i.e.
+    ///    `shared` is called from within `f`.
+    pub fn shared<R>(&self, f: impl FnOnce(&mut G) -> R) -> Result<R> {
+        self.with_lock(|state| state.as_mut().map(f).ok_or(ErrorKind::BadState.into()))?
+    }
+
+    /// Runs `f` with the context of every open session but the one lent
+    /// out, e.g. the session of the running command, and returns how many
+    /// sessions it ran on.
+    pub fn for_each_session(&self, mut f: impl FnMut(SessionId, &mut S)) -> usize {
+        SessionRegistry::<S>::ids()
+            .into_iter()
+            .filter(|id| SessionRegistry::<S>::with(*id, |session| f(*id, session)).is_ok())
+            .count()
+    }
+
+    /// The command IDs of the routes, in routing order.
+    pub fn commands(&self) -> Vec<u32> {
+        self.routes.iter().map(Route::id).collect()
+    }
+
+    fn with_lock<R>(&self, f: impl FnOnce(&mut Option<G>) -> R) -> Result<R> {
+        if self.locked.swap(true, Ordering::Acquire) {
+            return Err(ErrorKind::AccessConflict.into());
+        }
+        // Clears the flag even if `f` panics.
+        struct Release<'a>(&'a AtomicBool);
+        impl Drop for Release<'_> {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::Release);
+            }
+        }
+        let _release = Release(&self.locked);
+        // SAFETY: the `locked` flag grants exclusive access to the state.
+        Ok(f(unsafe { &mut *self.shared.get() }))
+    }
+}
+
+/// A command being run by a [`TaServer`].
+pub struct Request<'a, S: 'static, G: 'static> {
+    server: &'a TaServer<S, G>,
+    session: &'a mut S,
+    cmd_id: u32,
+}
+
+impl<S: 'static, G: 'static> Request<'_, S, G> {
+    /// The context of the session running the command.
+    pub fn session(&mut self) -> &mut S {
+        self.session
+    }
+
+    /// The ID of the command.
+    pub fn command_id(&self) -> u32 {
+        self.cmd_id
+    }
+
+    /// Runs `f` with the shared state of the server, see
+    /// [`TaServer::shared`].
+    pub fn shared<R>(&self, f: impl FnOnce(&mut G) -> R) -> Result<R> {
+        self.server.shared(f)
+    }
+
+    /// The server running the command, e.g. to reach the other sessions
+    /// with [`TaServer::for_each_session`].
+    pub fn server(&self) -> &TaServer<S, G> {
+        self.server
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FromRawParameters, ParameterValueRead, RawParams};
+    use optee_utee_sys as raw;
+
+    #[derive(Default, Debug, PartialEq)]
+    enum Session {
+        #[default]
+        Idle,
+        Open(u32),
+    }
+
+    type TestRequest<'a> = Request<'a, Session, Vec<u32>>;
+
+    fn open(request: &mut TestRequest<'_>, params: &mut ParametersAny<'_>) -> Result<()> {
+        let value = params.0.as_value_input()?.get_a();
+        *request.session() = Session::Open(value);
+        request.shared(|log| log.push(value))
+    }
+
+    fn add(request: &mut TestRequest<'_>, params: &mut ParametersAny<'_>) -> Result<()> {
+        let value = params.0.as_value_input()?.get_a();
+        if let Session::Open(total) = request.session() {
+            *total += value;
+        }
+        // The shared state is not reentrant
+        request.shared(|_| request.shared(|_| ()))?
+    }
+
+    fn is_open(session: &Session) -> bool {
+        matches!(session, Session::Open(_))
+    }
+
+    static SERVER: TaServer<Session, Vec<u32>> =
+        TaServer::new(&[Route::new(0, open), Route::new(1, add).when(is_open)]);
+
+    fn invoke(session: &mut Session, cmd_id: u32, value: u32) -> Result<()> {
+        let mut raw_params: RawParams = unsafe { core::mem::zeroed() };
+        raw_params[0].value.a = value;
+        let types = raw::TEE_PARAM_TYPES(
+            raw::TEE_PARAM_TYPE_VALUE_INPUT,
+            raw::TEE_PARAM_TYPE_NONE,
+            raw::TEE_PARAM_TYPE_NONE,
+            raw::TEE_PARAM_TYPE_NONE,
+        );
+        let mut params = unsafe { ParametersAny::from_raw(types, &mut raw_params) }?;
+        SERVER.invoke(session, cmd_id, &mut params)
+    }
+
+    #[test]
+    fn test_server() {
+        let mut session = Session::default();
+        // Not started
+        assert_eq!(
+            invoke(&mut session, 0, 1).unwrap_err().kind(),
+            ErrorKind::BadState
+        );
+        SERVER.start(Vec::new()).unwrap();
+        assert_eq!(
+            SERVER.start(Vec::new()).unwrap_err().kind(),
+            ErrorKind::BadState
+        );
+        assert_eq!(SERVER.commands(), [0, 1]);
+
+        // Rejected by the state of the session, or unknown
+        assert_eq!(
+            invoke(&mut Session::Idle, 1, 1).unwrap_err().kind(),
+            ErrorKind::BadState
+        );
+        assert_eq!(
+            invoke(&mut session, 2, 1).unwrap_err().kind(),
+            ErrorKind::BadParameters
+        );
+
+        invoke(&mut session, 0, 5).unwrap();
+        assert_eq!(
+            invoke(&mut session, 1, 2).unwrap_err().kind(),
+            ErrorKind::AccessConflict
+        );
+        assert_eq!(session, Session::Open(7));
+        invoke(&mut Session::Idle, 0, 3).unwrap();
+        assert_eq!(SERVER.shared(|log| log.clone()).unwrap(), [5, 3]);
+
+        assert_eq!(SERVER.stop(), Some(vec![5, 3]));
+        assert_eq!(SERVER.stop(), None);
+    }
+
+    #[test]
+    fn test_for_each_session() {
+        struct Context(u32);
+
+        static SERVER: TaServer<Context> = TaServer::new(&[]);
+        let first = SessionRegistry::<Context>::open(Context(1));
+        let second = SessionRegistry::<Context>::open(Context(2));
+        SessionRegistry::<Context>::with(first, |_| {
+            // The borrowed session is skipped
+            let count = SERVER.for_each_session(|id, context| {
+                assert_eq!(id, second);
+                context.0 += 10;
+            });
+            assert_eq!(count, 1);
+        })
+        .unwrap();
+        assert_eq!(SERVER.for_each_session(|_, _| ()), 2);
+        assert_eq!(SessionRegistry::<Context>::close(second).unwrap().0, 12);
+        assert!(SessionRegistry::<Context>::close(first).is_ok());
+    }
+}
@@ -0,0 +1,322 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Contexts of the open sessions of a TA.
+//!
+//! The session entry points receive a raw `*mut c_void` session context. The
+//! `#[ta_open_session]`, `#[ta_invoke_command]` and `#[ta_close_session]`
+//! macros do not store a pointer to the context there, but a [`SessionId`]
+//! of the context in the TA-wide registry, which owns it until the session
+//! is closed. A session closed twice, or a context the framework did not get
+//! from `#[ta_open_session]`, is then an error instead of a double free.
+//!
+//! [`SessionRegistry`] also gives access to the other sessions, e.g. for a
+//! multi-session TA (`TA_FLAG_SINGLE_INSTANCE | TA_FLAG_MULTI_SESSION`)
+//! notifying all its clients:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::{Result, SessionRegistry};
+//! # struct Session { pending: u32 }
+//! # fn notify() -> Result<()> {
+//! for id in SessionRegistry::<Session>::ids() {
+//!     SessionRegistry::<Session>::with(id, |session| session.pending += 1)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{ErrorKind, Result};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::num::NonZeroU32;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Identifier of an open session in the [`SessionRegistry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SessionId(NonZeroU32);
+
+impl SessionId {
+    /// The session context handed to the framework by `TA_OpenSessionEntryPoint`.
+    pub fn into_raw(self) -> *mut c_void {
+        self.0.get() as usize as *mut c_void
+    }
+
+    /// The session ID from the session context of an entry point, `None` for
+    /// a null context.
+    pub fn from_raw(sess_ctx: *mut c_void) -> Option<Self> {
+        u32::try_from(sess_ctx as usize)
+            .ok()
+            .and_then(NonZeroU32::new)
+            .map(Self)
+    }
+}
+
+/// A session context, boxed in the registry so it does not move when the map
+/// does.
+struct Slot<T> {
+    /// Set while the context is lent out by [`SessionRegistry::with`].
+    borrowed: AtomicBool,
+    context: UnsafeCell<T>,
+}
+
+struct Registry {
+    sessions: UnsafeCell<BTreeMap<SessionId, Box<dyn Any>>>,
+    next_id: UnsafeCell<u32>,
+    locked: AtomicBool,
+}
+
+// SAFETY: `sessions` and `next_id` are only accessed by the holder of the
+// `locked` flag, the contexts by the holder of the `borrowed` flag of their
+// slot.
+unsafe impl Sync for Registry {}
+
+static REGISTRY: Registry = Registry {
+    sessions: UnsafeCell::new(BTreeMap::new()),
+    next_id: UnsafeCell::new(1),
+    locked: AtomicBool::new(false),
+};
+
+/// Clears the `locked` flag of the registry when dropped.
+struct RegistryGuard;
+
+impl RegistryGuard {
+    fn acquire() -> Self {
+        // The flag is only held for map operations, never while calling out
+        // to the TA, so it cannot be held by the current thread.
+        while REGISTRY
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        Self
+    }
+
+    fn sessions(&mut self) -> &mut BTreeMap<SessionId, Box<dyn Any>> {
+        // SAFETY: the guard holds the `locked` flag.
+        unsafe { &mut *REGISTRY.sessions.get() }
+    }
+
+    fn next_id(&mut self) -> SessionId {
+        // SAFETY: the guard holds the `locked` flag.
+        let next_id = unsafe { &mut *REGISTRY.next_id.get() };
+        loop {
+            let id = NonZeroU32::new(*next_id).map(SessionId);
+            *next_id = next_id.wrapping_add(1);
+            if let Some(id) = id
+                && !self.sessions().contains_key(&id)
+            {
+                return id;
+            }
+        }
+    }
+}
+
+impl Drop for RegistryGuard {
+    fn drop(&mut self) {
+        REGISTRY.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Typed access to the sessions of the TA whose context is a `T`.
+///
+/// The registry is TA-wide: the contexts are registered by `#[ta_open_session]`
+/// and removed by `#[ta_close_session]`, this type only selects which of them
+/// are visible, so it is never instantiated.
+pub struct SessionRegistry<T>(PhantomData<fn() -> T>);
+
+impl<T: 'static> SessionRegistry<T> {
+    /// Registers the context of a new session.
+    pub fn open(context: T) -> SessionId {
+        let slot: Box<dyn Any> = Box::new(Slot {
+            borrowed: AtomicBool::new(false),
+            context: UnsafeCell::new(context),
+        });
+        let mut guard = RegistryGuard::acquire();
+        let id = guard.next_id();
+        guard.sessions().insert(id, slot);
+        id
+    }
+
+    /// Runs `f` with the context of the session `id`.
+    ///
+    /// # Errors
+    ///
+    /// 1) `ItemNotFound`: If there is no session `id` with a `T` context.
+    /// 2) `AccessConflict`: If the context is already lent out, i.e. `with`
+    ///    is called for the session from within `f`.
+    pub fn with<R, F>(id: SessionId, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let slot = {
+            let mut guard = RegistryGuard::acquire();
+            let slot = guard
+                .sessions()
+                .get(&id)
+                .and_then(|slot| slot.downcast_ref::<Slot<T>>())
+                .ok_or(ErrorKind::ItemNotFound)?;
+            if slot.borrowed.swap(true, Ordering::Acquire) {
+                return Err(ErrorKind::AccessConflict.into());
+            }
+            // SAFETY: the slot is boxed and `close` does not free it while
+            // it is borrowed, so it outlives the guard.
+            unsafe { &*(slot as *const Slot<T>) }
+        };
+        // Clears the `borrowed` flag even if `f` panics.
+        struct Release<'a>(&'a AtomicBool);
+        impl Drop for Release<'_> {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::Release);
+            }
+        }
+        let _release = Release(&slot.borrowed);
+        // SAFETY: the `borrowed` flag grants exclusive access to the context.
+        Ok(f(unsafe { &mut *slot.context.get() }))
+    }
+
+    /// Removes the session `id` from the registry and returns its context.
+    ///
+    /// # Errors
+    ///
+    /// 1) `ItemNotFound`: If there is no session `id` with a `T` context, in
+    ///    particular if it has already been closed.
+    /// 2) `AccessConflict`: If the context is lent out by [`with`](Self::with).
+    pub fn close(id: SessionId) -> Result<T> {
+        let slot = {
+            let mut guard = RegistryGuard::acquire();
+            let sessions = guard.sessions();
+            match sessions.get(&id).and_then(|s| s.downcast_ref::<Slot<T>>()) {
+                None => return Err(ErrorKind::ItemNotFound.into()),
+                Some(slot) if slot.borrowed.load(Ordering::Acquire) => {
+                    return Err(ErrorKind::AccessConflict.into());
+                }
+                Some(_) => sessions.remove(&id).ok_or(ErrorKind::ItemNotFound)?,
+            }
+        };
+        let slot = slot
+            .downcast::<Slot<T>>()
+            .map_err(|_| ErrorKind::ItemNotFound)?;
+        Ok(slot.context.into_inner())
+    }
+
+    /// The IDs of the open sessions with a `T` context, in opening order
+    /// unless the IDs wrapped around.
+    pub fn ids() -> Vec<SessionId> {
+        let mut guard = RegistryGuard::acquire();
+        guard
+            .sessions()
+            .iter()
+            .filter(|(_, slot)| slot.is::<Slot<T>>())
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// The number of open sessions with a `T` context.
+    pub fn len() -> usize {
+        let mut guard = RegistryGuard::acquire();
+        guard
+            .sessions()
+            .values()
+            .filter(|slot| slot.is::<Slot<T>>())
+            .count()
+    }
+
+    /// Whether there is no open session with a `T` context.
+    pub fn is_empty() -> bool {
+        Self::len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test uses its own context type, as the registry is shared by the
+    // tests running in parallel.
+
+    #[test]
+    fn test_open_with_close() {
+        struct Context(u32);
+
+        let first = SessionRegistry::<Context>::open(Context(1));
+        let second = SessionRegistry::<Context>::open(Context(2));
+        assert_ne!(first, second);
+        assert_eq!(SessionRegistry::<Context>::ids(), [first, second]);
+
+        SessionRegistry::<Context>::with(first, |ctx| ctx.0 += 10).unwrap();
+        assert_eq!(SessionRegistry::<Context>::close(first).unwrap().0, 11);
+        assert_eq!(SessionRegistry::<Context>::len(), 1);
+
+        // Closing twice is an error, not a double free.
+        let err = SessionRegistry::<Context>::close(first).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ItemNotFound);
+        let err = SessionRegistry::<Context>::with(first, |_| ()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ItemNotFound);
+
+        assert_eq!(SessionRegistry::<Context>::close(second).unwrap().0, 2);
+        assert!(SessionRegistry::<Context>::is_empty());
+    }
+
+    #[test]
+    fn test_context_type_mismatch() {
+        struct Context;
+        struct Other;
+
+        let id = SessionRegistry::<Context>::open(Context);
+        let err = SessionRegistry::<Other>::with(id, |_| ()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ItemNotFound);
+        assert!(SessionRegistry::<Other>::close(id).is_err());
+        assert!(SessionRegistry::<Other>::ids().is_empty());
+        assert!(SessionRegistry::<Context>::close(id).is_ok());
+    }
+
+    #[test]
+    fn test_nested_access() {
+        struct Context(Vec<u32>);
+
+        let first = SessionRegistry::<Context>::open(Context(Vec::new()));
+        let second = SessionRegistry::<Context>::open(Context(Vec::new()));
+        SessionRegistry::<Context>::with(first, |_| {
+            // Other sessions are accessible, the borrowed one is not.
+            SessionRegistry::<Context>::with(second, |ctx| ctx.0.push(1)).unwrap();
+            let err = SessionRegistry::<Context>::with(first, |_| ()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::AccessConflict);
+            let err = SessionRegistry::<Context>::close(first).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::AccessConflict);
+        })
+        .unwrap();
+        assert_eq!(SessionRegistry::<Context>::close(second).unwrap().0, [1]);
+        assert!(SessionRegistry::<Context>::close(first).is_ok());
+    }
+
+    #[test]
+    fn test_raw_session_id() {
+        struct Context;
+
+        let id = SessionRegistry::<Context>::open(Context);
+        assert_eq!(SessionId::from_raw(id.into_raw()), Some(id));
+        assert_eq!(SessionId::from_raw(core::ptr::null_mut()), None);
+        assert!(SessionRegistry::<Context>::close(id).is_ok());
+    }
+}
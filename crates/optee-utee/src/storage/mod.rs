@@ -0,0 +1,270 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Backends of the Trusted Storage.
+//!
+//! OP-TEE keeps the persistent objects either in the file system of the
+//! normal world (REE FS), encrypted and authenticated, or in the Replay
+//! Protected Memory Block of the eMMC (RPMB), which also protects them from
+//! being rolled back to an older version, e.g. the state of a wallet before
+//! a payment. `ObjectStorageConstants::Private` is the backend the OS was
+//! built to use by default, a TA selects another one per object with its
+//! storage ID:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::storage::StorageBackend;
+//! # use optee_utee::{DataFlag, ErrorKind, PersistentObject};
+//! # fn main() -> optee_utee::Result<()> {
+//! if !StorageBackend::Rpmb.is_available()? {
+//!     return Err(ErrorKind::StorageNotAvailable.into());
+//! }
+//! PersistentObject::create(
+//!     StorageBackend::Rpmb.into(),
+//!     b"nonce",
+//!     DataFlag::ACCESS_WRITE,
+//!     None,
+//!     &0u64.to_le_bytes(),
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The failures of the storage, a full RPMB partition in particular, are
+//! told apart from the other errors with [`StorageError`].
+//!
+//! A TA serving several clients keeps the objects of each in a
+//! [`Namespace`], listed and limited by a [`Quota`] separately.
+//!
+//! Bulk data which does not fit the budget of the Trusted Storage goes to a
+//! [`ReeKvStore`] instead, plain files of the REE which the TA encrypts and
+//! authenticates itself.
+
+use crate::{DataFlag, Error, ErrorKind, ObjectStorageConstants, PersistentObject, Result};
+use alloc::vec::Vec;
+use core::fmt;
+
+mod namespace;
+mod ree_kv;
+
+pub use namespace::{Namespace, Quota, Usage};
+#[cfg(feature = "kv")]
+pub(crate) use namespace::{check_quota, read_all, remove};
+pub use ree_kv::{
+    CMD_REE_KV_DELETE, CMD_REE_KV_GET, CMD_REE_KV_LIST, CMD_REE_KV_PUT, REE_KV_MAX_KEY_LEN,
+    REE_KV_PLUGIN_UUID, ReeKvStore,
+};
+
+/// Object created and deleted to check if a backend is available, in its
+/// own namespace so it never replaces an object of the TA.
+const PROBE_OBJECT_ID: &[u8] = b"\0optee-utee/storage-probe";
+
+/// A backend of the Trusted Storage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StorageBackend {
+    /// The default backend of the OS, `TEE_STORAGE_PRIVATE`.
+    Default,
+    /// The file system of the normal world, `TEE_STORAGE_PRIVATE_REE`.
+    Ree,
+    /// The RPMB partition of the eMMC, `TEE_STORAGE_PRIVATE_RPMB`.
+    Rpmb,
+}
+
+impl StorageBackend {
+    /// All the backends, the default one first.
+    pub const ALL: [Self; 3] = [Self::Default, Self::Ree, Self::Rpmb];
+
+    /// The storage ID of the backend.
+    pub fn storage_id(self) -> ObjectStorageConstants {
+        match self {
+            Self::Default => ObjectStorageConstants::Private,
+            Self::Ree => ObjectStorageConstants::PrivateRee,
+            Self::Rpmb => ObjectStorageConstants::PrivateRpmb,
+        }
+    }
+
+    /// Return whether the backend can store objects, i.e. the OS was built
+    /// with it and it is accessible, for example the RPMB key is
+    /// provisioned. A full backend is available.
+    ///
+    /// There is no query of the backends in the TEE API, so this creates and
+    /// deletes an empty object: a TA checking a backend should do it once,
+    /// rather than before every access.
+    pub fn is_available(self) -> Result<bool> {
+        let object = match PersistentObject::create(
+            self.storage_id(),
+            PROBE_OBJECT_ID,
+            DataFlag::ACCESS_WRITE_META | DataFlag::OVERWRITE,
+            None,
+            &[],
+        ) {
+            Ok(object) => object,
+            // The storage IDs of the backends OP-TEE is not built with are
+            // unknown.
+            Err(e) if e.kind() == ErrorKind::ItemNotFound => return Ok(false),
+            Err(e) => {
+                return match StorageError::from(e.kind()) {
+                    StorageError::NotAvailable => Ok(false),
+                    StorageError::NoSpace => Ok(true),
+                    _ => Err(e),
+                };
+            }
+        };
+        object.close_and_delete()?;
+        Ok(true)
+    }
+
+    /// The available backends, see [`is_available`](Self::is_available).
+    pub fn available() -> Result<Vec<Self>> {
+        let mut backends = Vec::new();
+        for backend in Self::ALL {
+            if backend.is_available()? {
+                backends.push(backend);
+            }
+        }
+        Ok(backends)
+    }
+}
+
+impl From<StorageBackend> for ObjectStorageConstants {
+    fn from(backend: StorageBackend) -> Self {
+        backend.storage_id()
+    }
+}
+
+/// Category of an error of the Trusted Storage.
+///
+/// The TEE API has two codes for some failures, which this folds together:
+///
+/// ``` rust,no_run
+/// # use optee_utee::storage::StorageError;
+/// # use optee_utee::{DataFlag, ObjectStorageConstants, PersistentObject};
+/// # fn main() -> optee_utee::Result<()> {
+/// # let state = [0u8; 64];
+/// match PersistentObject::create(
+///     ObjectStorageConstants::PrivateRpmb,
+///     b"state",
+///     DataFlag::ACCESS_WRITE | DataFlag::OVERWRITE,
+///     None,
+///     &state,
+/// ) {
+///     Ok(_) => Ok(()),
+///     Err(e) if StorageError::from(e.kind()) == StorageError::NoSpace => {
+///         // prune old entries and retry
+///         # Ok(())
+///     }
+///     Err(e) => Err(e),
+/// }
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageError {
+    /// There is not enough space left in the backend, `StorageNoSpace`.
+    NoSpace,
+    /// The backend is not accessible, `StorageNotAvailable` or
+    /// `StorageNotAvailable2`.
+    NotAvailable,
+    /// The object or the backend is corrupt, `CorruptObject` or
+    /// `CorruptObject2`.
+    Corrupt,
+    /// Any other error.
+    Other(ErrorKind),
+}
+
+impl From<ErrorKind> for StorageError {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::StorageNoSpace => Self::NoSpace,
+            ErrorKind::StorageNotAvailable | ErrorKind::StorageNotAvailable2 => Self::NotAvailable,
+            ErrorKind::CorruptObject | ErrorKind::CorruptObject2 => Self::Corrupt,
+            kind => Self::Other(kind),
+        }
+    }
+}
+
+impl From<&Error> for StorageError {
+    fn from(error: &Error) -> Self {
+        error.kind().into()
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoSpace => f.write_str("the storage is full"),
+            Self::NotAvailable => f.write_str("the storage is not available"),
+            Self::Corrupt => f.write_str("the storage is corrupt"),
+            Self::Other(kind) => f.write_str(kind.as_str()),
+        }
+    }
+}
+
+impl core::error::Error for StorageError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use optee_utee_sys::{self as raw, mock_api, mock_utils::SERIAL_TEST_LOCK};
+
+    #[test]
+    fn test_storage_error() {
+        assert_eq!(
+            StorageError::from(ErrorKind::StorageNoSpace),
+            StorageError::NoSpace
+        );
+        assert_eq!(
+            StorageError::from(&Error::new(ErrorKind::StorageNotAvailable2)),
+            StorageError::NotAvailable
+        );
+        assert_eq!(
+            StorageError::from(ErrorKind::CorruptObject2),
+            StorageError::Corrupt
+        );
+        assert_eq!(
+            StorageError::from(ErrorKind::AccessConflict),
+            StorageError::Other(ErrorKind::AccessConflict)
+        );
+    }
+
+    #[test]
+    fn test_is_available() {
+        let _lock = SERIAL_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let create_ctx = mock_api::TEE_CreatePersistentObject_context();
+        create_ctx
+            .expect()
+            .withf(|storage_id, _, _, _, _, _, _, _| *storage_id == raw::TEE_STORAGE_PRIVATE)
+            .returning(|_, _, _, _, _, _, _, object| {
+                unsafe { *object = 1usize as raw::TEE_ObjectHandle };
+                raw::TEE_SUCCESS
+            });
+        create_ctx
+            .expect()
+            .withf(|storage_id, _, _, _, _, _, _, _| *storage_id == 0x8000_0000)
+            .return_const(raw::TEE_ERROR_STORAGE_NOT_AVAILABLE);
+        create_ctx
+            .expect()
+            .withf(|storage_id, _, _, _, _, _, _, _| *storage_id == 0x8000_0100)
+            .return_const(raw::TEE_ERROR_ITEM_NOT_FOUND);
+        let delete_ctx = mock_api::TEE_CloseAndDeletePersistentObject1_context();
+        delete_ctx.expect().times(1).return_const(raw::TEE_SUCCESS);
+
+        assert_eq!(
+            StorageBackend::available().unwrap(),
+            [StorageBackend::Default]
+        );
+    }
+}
@@ -0,0 +1,378 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use crate::{
+    DataFlag, Error, ErrorKind, GenericObject, MiscellaneousConstants, ObjectStorageConstants,
+    PersistentObject, PersistentObjectIter, Result,
+};
+use alloc::vec::Vec;
+
+/// Limits of the objects of a [`Namespace`], `None` for no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Maximum number of objects.
+    pub max_objects: Option<usize>,
+    /// Maximum total size of the data of the objects, in bytes.
+    pub max_bytes: Option<usize>,
+}
+
+impl Quota {
+    /// No limit.
+    pub const UNLIMITED: Self = Self {
+        max_objects: None,
+        max_bytes: None,
+    };
+
+    /// Limits the number of objects to `max`.
+    pub fn max_objects(mut self, max: usize) -> Self {
+        self.max_objects = Some(max);
+        self
+    }
+
+    /// Limits the total size of the data of the objects to `max` bytes.
+    pub fn max_bytes(mut self, max: usize) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    /// Returns whether `usage` is within the limits.
+    pub fn allows(&self, usage: Usage) -> bool {
+        self.max_objects.is_none_or(|max| usage.objects <= max)
+            && self.max_bytes.is_none_or(|max| usage.bytes <= max)
+    }
+
+    fn is_unlimited(&self) -> bool {
+        *self == Self::UNLIMITED
+    }
+}
+
+/// Storage used by the objects of a [`Namespace`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Number of objects.
+    pub objects: usize,
+    /// Total size of the data of the objects, in bytes. The attributes and
+    /// the overhead of the backend are not counted.
+    pub bytes: usize,
+}
+
+/// The objects whose identifier starts with a prefix, e.g. those of one of
+/// the clients of a TA serving several client applications, with a
+/// [`Quota`] so that a client cannot fill the storage of the others.
+///
+/// ``` rust,no_run
+/// # use optee_utee::storage::{Namespace, Quota};
+/// # use optee_utee::ObjectStorageConstants;
+/// # fn main() -> optee_utee::Result<()> {
+/// # let client_id = 42u32;
+/// let mut prefix = b"client/".to_vec();
+/// prefix.extend_from_slice(&client_id.to_le_bytes());
+/// let namespace = Namespace::new(ObjectStorageConstants::Private, &prefix)?
+///     .with_quota(Quota::default().max_objects(16).max_bytes(64 * 1024));
+/// // Fails with `StorageNoSpace` once the quota is reached
+/// namespace.write(b"certificate", &[0u8; 1024])?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The quota is checked by listing the objects of the namespace before each
+/// write: objects written by other means, or by several instances of the TA
+/// at the same time, may exceed it.
+///
+/// The object named by the prefix alone is not one of the objects of the
+/// namespace, it is reserved for its users, e.g. as the lock of a
+/// [`SecureKvStore`](crate::kv::SecureKvStore).
+#[derive(Clone, Debug)]
+pub struct Namespace {
+    storage_id: ObjectStorageConstants,
+    prefix: Vec<u8>,
+    quota: Quota,
+}
+
+impl Namespace {
+    /// The objects whose identifier starts with `prefix` in the storage
+    /// `storage_id`, without quota.
+    ///
+    /// # Errors
+    ///
+    /// `BadParameters`: If `prefix` leaves no room for the identifiers of the
+    /// objects.
+    pub fn new(storage_id: impl Into<ObjectStorageConstants>, prefix: &[u8]) -> Result<Self> {
+        if prefix.len() >= MiscellaneousConstants::TeeObjectIdMaxLen as usize {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        Ok(Self {
+            storage_id: storage_id.into(),
+            prefix: prefix.to_vec(),
+            quota: Quota::UNLIMITED,
+        })
+    }
+
+    /// Sets the quota of the namespace.
+    pub fn with_quota(mut self, quota: Quota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// The quota of the namespace.
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// The prefix of the identifiers of the objects of the namespace.
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Returns the identifiers of the objects of the namespace, without the
+    /// prefix, in no particular order.
+    pub fn list(&self) -> Result<Vec<Vec<u8>>> {
+        let mut ids = Vec::new();
+        for entry in PersistentObjectIter::new(self.storage_id)? {
+            let (id, _) = entry?;
+            match id.strip_prefix(self.prefix.as_slice()) {
+                Some(id) if !id.is_empty() => ids.push(id.to_vec()),
+                _ => {}
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Returns the storage used by the objects of the namespace.
+    pub fn usage(&self) -> Result<Usage> {
+        Ok(usage(self.storage_id, &self.prefix, &[])?.0)
+    }
+
+    /// Returns the data of the object `id`, `None` if there is no such
+    /// object.
+    pub fn read(&self, id: &[u8]) -> Result<Option<Vec<u8>>> {
+        match PersistentObject::open(
+            self.storage_id,
+            &self.object_id(id)?,
+            DataFlag::ACCESS_READ | DataFlag::SHARE_READ,
+        ) {
+            Ok(mut object) => read_all(&mut object).map(Some),
+            Err(e) if e.kind() == ErrorKind::ItemNotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets the data of the object `id`, replacing it atomically if it
+    /// exists.
+    ///
+    /// # Errors
+    ///
+    /// 1) `StorageNoSpace`: If the write would exceed the quota, nothing is
+    ///    written then, or if there is not enough space in the storage.
+    /// 2) `BadParameters`: If the identifier is empty or too long.
+    pub fn write(&self, id: &[u8], data: &[u8]) -> Result<()> {
+        let object_id = self.object_id(id)?;
+        check_quota(
+            self.storage_id,
+            &self.prefix,
+            self.quota,
+            &object_id,
+            data.len(),
+        )?;
+        PersistentObject::create(
+            self.storage_id,
+            &object_id,
+            DataFlag::ACCESS_WRITE | DataFlag::OVERWRITE,
+            None,
+            data,
+        )?;
+        Ok(())
+    }
+
+    /// Deletes the object `id`, returning whether there was one.
+    pub fn remove(&self, id: &[u8]) -> Result<bool> {
+        remove(self.storage_id, &self.object_id(id)?)
+    }
+
+    /// Deletes all the objects of the namespace, returning how many were
+    /// deleted.
+    pub fn clear(&self) -> Result<usize> {
+        // Collect first, deleting objects while enumerating is not guaranteed
+        // to list the remaining ones
+        let mut removed = 0;
+        for id in self.list()? {
+            if self.remove(&id)? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn object_id(&self, id: &[u8]) -> Result<Vec<u8>> {
+        if id.is_empty()
+            || self.prefix.len() + id.len() > MiscellaneousConstants::TeeObjectIdMaxLen as usize
+        {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        let mut object_id = self.prefix.clone();
+        object_id.extend_from_slice(id);
+        Ok(object_id)
+    }
+}
+
+/// Returns the usage of the objects whose identifier starts with `prefix`,
+/// but for the object named by the prefix alone, and the data size of the
+/// object `id` if it is one of them.
+fn usage(
+    storage_id: ObjectStorageConstants,
+    prefix: &[u8],
+    id: &[u8],
+) -> Result<(Usage, Option<usize>)> {
+    let mut usage = Usage::default();
+    let mut size = None;
+    for entry in PersistentObjectIter::new(storage_id)? {
+        let (object_id, info) = entry?;
+        if object_id.starts_with(prefix) && object_id.len() > prefix.len() {
+            usage.objects += 1;
+            usage.bytes += info.data_size();
+            if object_id == id {
+                size = Some(info.data_size());
+            }
+        }
+    }
+    Ok((usage, size))
+}
+
+/// Fails with `StorageNoSpace` if setting the data of the object `id` to
+/// `len` bytes would make the objects starting with `prefix` exceed `quota`.
+pub(crate) fn check_quota(
+    storage_id: ObjectStorageConstants,
+    prefix: &[u8],
+    quota: Quota,
+    id: &[u8],
+    len: usize,
+) -> Result<()> {
+    if quota.is_unlimited() {
+        return Ok(());
+    }
+    let (mut usage, size) = usage(storage_id, prefix, id)?;
+    match size {
+        Some(size) => usage.bytes -= size,
+        None => usage.objects += 1,
+    }
+    usage.bytes = usage.bytes.saturating_add(len);
+    if !quota.allows(usage) {
+        return Err(Error::with_message(
+            ErrorKind::StorageNoSpace,
+            "quota of the namespace exceeded",
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the whole data of `object` from the current position.
+pub(crate) fn read_all(object: &mut PersistentObject) -> Result<Vec<u8>> {
+    let mut data = vec![0u8; object.info()?.data_size()];
+    let mut filled = 0;
+    while filled < data.len() {
+        let read = object.read(&mut data[filled..])? as usize;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    data.truncate(filled);
+    Ok(data)
+}
+
+/// Deletes the object `id`, returning whether there was one.
+pub(crate) fn remove(storage_id: ObjectStorageConstants, id: &[u8]) -> Result<bool> {
+    match PersistentObject::open(
+        storage_id,
+        id,
+        DataFlag::ACCESS_READ | DataFlag::ACCESS_WRITE_META,
+    ) {
+        Ok(object) => {
+            object.close_and_delete()?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == ErrorKind::ItemNotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use optee_utee_sys::mock_utils::storage::MockStorage;
+
+    #[test]
+    fn test_namespace() {
+        let storage = MockStorage::new();
+        let alice = Namespace::new(ObjectStorageConstants::Private, b"client/alice/").unwrap();
+        let bob = Namespace::new(ObjectStorageConstants::Private, b"client/bob/").unwrap();
+        storage.insert_raw(b"unrelated", b"data");
+        // Reserved, not one of the objects
+        storage.insert_raw(b"client/alice/", b"lock");
+        let err = alice.write(b"", b"data").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadParameters);
+
+        alice.write(b"a", b"1234").unwrap();
+        alice.write(b"b", b"56").unwrap();
+        bob.write(b"a", b"7").unwrap();
+        assert_eq!(alice.read(b"a").unwrap().as_deref(), Some(&b"1234"[..]));
+        assert_eq!(alice.read(b"c").unwrap(), None);
+
+        let mut ids = alice.list().unwrap();
+        ids.sort();
+        assert_eq!(ids, [b"a", b"b"]);
+        assert_eq!(
+            alice.usage().unwrap(),
+            Usage {
+                objects: 2,
+                bytes: 6
+            }
+        );
+
+        assert_eq!(alice.clear().unwrap(), 2);
+        assert!(alice.list().unwrap().is_empty());
+        assert_eq!(bob.list().unwrap(), [b"a"]);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    fn test_quota() {
+        let _storage = MockStorage::new();
+        let namespace = Namespace::new(ObjectStorageConstants::Private, b"ns/")
+            .unwrap()
+            .with_quota(Quota::default().max_objects(2).max_bytes(8));
+
+        namespace.write(b"a", b"1234").unwrap();
+        // Replacing an object only counts its new size
+        namespace.write(b"a", b"123456").unwrap();
+        let err = namespace.write(b"b", b"123").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::StorageNoSpace);
+        namespace.write(b"b", b"12").unwrap();
+        let err = namespace.write(b"c", b"").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::StorageNoSpace);
+
+        assert!(namespace.remove(b"a").unwrap());
+        namespace.write(b"c", b"").unwrap();
+        assert_eq!(
+            namespace.usage().unwrap(),
+            Usage {
+                objects: 2,
+                bytes: 2
+            }
+        );
+    }
+}
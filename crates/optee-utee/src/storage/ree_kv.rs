@@ -0,0 +1,248 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use alloc::vec::Vec;
+
+use crate::{ErrorKind, LoadablePlugin, Result, Uuid};
+
+/// UUID of the supplicant plugin keeping values in the file system of the
+/// REE for the TAs, served by `optee_teec::ree_kv` in the REE.
+pub const REE_KV_PLUGIN_UUID: &str = "69614b85-e591-4db1-ae0c-6c697a39f4c5";
+/// Command of the plugin reading a value from an offset.
+pub const CMD_REE_KV_GET: u32 = 0;
+/// Command of the plugin writing a chunk of a value.
+pub const CMD_REE_KV_PUT: u32 = 1;
+/// Command of the plugin deleting a value.
+pub const CMD_REE_KV_DELETE: u32 = 2;
+/// Command of the plugin listing the keys starting with a prefix.
+pub const CMD_REE_KV_LIST: u32 = 3;
+
+/// Flag of the sub-command of the first chunk of a value.
+const PUT_FIRST: u32 = 1;
+/// Flag of the sub-command of the last chunk of a value.
+const PUT_LAST: u32 = 2;
+
+/// Maximum length of a key in bytes, with the namespace of the store.
+pub const REE_KV_MAX_KEY_LEN: usize = 127;
+
+/// Size of the buffer of the plugin, the most bytes of a value moved by an
+/// invocation.
+const CHUNK_SIZE: usize = 4096;
+
+/// Key-value store in the file system of the REE, served by a supplicant
+/// plugin, for bulk data which does not fit the budget of the Trusted
+/// Storage.
+///
+/// The REE reads, replaces, rolls back and deletes the values at will: a TA
+/// keeps there only what it encrypts and authenticates itself, e.g. with
+/// [`crypto::Sealed`](crate::crypto), and checks what it reads back. A value
+/// is replaced atomically, but one written by another TA while it is read in
+/// several chunks may come back torn.
+///
+/// The TAs sharing the plugin each keep their keys in their own namespace:
+///
+/// ``` rust,no_run
+/// # use optee_utee::storage::ReeKvStore;
+/// # fn main() -> optee_utee::Result<()> {
+/// # let sealed_log = [0u8; 0];
+/// let store = ReeKvStore::default().with_namespace(b"my-ta/");
+/// store.put(b"logs/1", &sealed_log)?;
+/// for key in store.keys(b"logs/")? {
+///     let sealed = store.get(&key)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct ReeKvStore {
+    plugin: LoadablePlugin,
+    namespace: Vec<u8>,
+}
+
+impl ReeKvStore {
+    /// A store using the plugin `uuid`, for a plugin serving
+    /// `optee_teec::ree_kv::ReeKv` under another UUID.
+    pub fn new(uuid: &Uuid) -> Self {
+        Self {
+            plugin: LoadablePlugin::new(uuid),
+            namespace: Vec::new(),
+        }
+    }
+
+    /// Prefixes the keys with `namespace`, which is hidden from the keys
+    /// listed by [`keys`](Self::keys).
+    pub fn with_namespace(mut self, namespace: &[u8]) -> Self {
+        self.namespace = namespace.to_vec();
+        self
+    }
+
+    /// Reads the value of `key`.
+    ///
+    /// # Errors
+    ///
+    /// `ItemNotFound`: If `key` has no value, or if the plugin is not
+    /// installed.
+    pub fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+        let key = self.key(key)?;
+        let mut value = Vec::new();
+        loop {
+            let offset = (value.len() as u64).to_le_bytes();
+            let chunk = self
+                .plugin
+                .invoke_with_capacity(CMD_REE_KV_GET, 0, CHUNK_SIZE)
+                .chain_write_body(&request(&key, &offset))
+                .call()?;
+            value.extend_from_slice(&chunk);
+            if chunk.len() < CHUNK_SIZE {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Writes `value` as the value of `key`, replacing the previous one only
+    /// once all of it is written.
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let key = self.key(key)?;
+        let chunk_size = CHUNK_SIZE - 2 - key.len();
+        let chunks = value.len().div_ceil(chunk_size).max(1);
+        for i in 0..chunks {
+            let chunk = &value[i * chunk_size..value.len().min((i + 1) * chunk_size)];
+            let mut flags = 0;
+            if i == 0 {
+                flags |= PUT_FIRST;
+            }
+            if i == chunks - 1 {
+                flags |= PUT_LAST;
+            }
+            self.plugin
+                .invoke(CMD_REE_KV_PUT, flags, &request(&key, chunk))?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the value of `key`.
+    ///
+    /// # Errors
+    ///
+    /// `ItemNotFound`: If `key` has no value.
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        let key = self.key(key)?;
+        self.plugin
+            .invoke(CMD_REE_KV_DELETE, 0, &request(&key, &[]))?;
+        Ok(())
+    }
+
+    /// The keys with a value starting with `prefix`, in ascending order.
+    pub fn keys(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let prefix = self.key(prefix)?;
+        let mut keys = Vec::new();
+        loop {
+            let response = self
+                .plugin
+                .invoke_with_capacity(CMD_REE_KV_LIST, keys.len() as u32, CHUNK_SIZE)
+                .chain_write_body(&request(&prefix, &[]))
+                .call()?;
+            if response.is_empty() {
+                return Ok(keys);
+            }
+            for key in decode_keys(&response)? {
+                let key = key
+                    .strip_prefix(self.namespace.as_slice())
+                    .ok_or(ErrorKind::BadFormat)?;
+                keys.push(key.to_vec());
+            }
+        }
+    }
+
+    /// `key` in the namespace of the store.
+    fn key(&self, key: &[u8]) -> Result<Vec<u8>> {
+        if self.namespace.len() + key.len() > REE_KV_MAX_KEY_LEN {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        let mut namespaced = self.namespace.clone();
+        namespaced.extend_from_slice(key);
+        Ok(namespaced)
+    }
+}
+
+impl Default for ReeKvStore {
+    /// A store using the plugin [`REE_KV_PLUGIN_UUID`], without namespace.
+    fn default() -> Self {
+        let uuid = Uuid::parse_str(REE_KV_PLUGIN_UUID).expect("the plugin UUID is valid");
+        Self::new(&uuid)
+    }
+}
+
+/// The request for `key`, followed by `payload`.
+fn request(key: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(2 + key.len() + payload.len());
+    request.extend_from_slice(&(key.len() as u16).to_le_bytes());
+    request.extend_from_slice(key);
+    request.extend_from_slice(payload);
+    request
+}
+
+/// Decodes the keys of the response of the plugin to a listing.
+fn decode_keys(mut response: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut keys = Vec::new();
+    while !response.is_empty() {
+        let (len, rest) = response.split_at_checked(2).ok_or(ErrorKind::BadFormat)?;
+        let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+        let (key, rest) = rest.split_at_checked(len).ok_or(ErrorKind::BadFormat)?;
+        keys.push(key);
+        response = rest;
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request() {
+        assert_eq!(request(b"key", b"\x01"), b"\x03\x00key\x01");
+        assert_eq!(request(b"", b""), b"\x00\x00");
+    }
+
+    #[test]
+    fn test_decode_keys() {
+        assert_eq!(
+            decode_keys(b"\x01\x00a\x02\x00bc").unwrap(),
+            [&b"a"[..], &b"bc"[..]]
+        );
+        assert!(decode_keys(b"").unwrap().is_empty());
+        for response in [&b"\x01"[..], &b"\x03\x00ab"[..]] {
+            assert_eq!(
+                decode_keys(response).unwrap_err().kind(),
+                ErrorKind::BadFormat
+            );
+        }
+    }
+
+    #[test]
+    fn test_key() {
+        let store = ReeKvStore::default().with_namespace(b"ta/");
+        assert_eq!(store.key(b"k").unwrap(), b"ta/k");
+        assert!(store.key(&[b'k'; REE_KV_MAX_KEY_LEN - 3]).is_ok());
+        assert_eq!(
+            store
+                .key(&[b'k'; REE_KV_MAX_KEY_LEN - 2])
+                .unwrap_err()
+                .kind(),
+            ErrorKind::BadParameters
+        );
+    }
+}
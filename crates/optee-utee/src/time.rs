@@ -15,8 +15,39 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use crate::{Error, Result};
+//! Time sources of the TEE.
+//!
+//! [`Time`] is a thin wrapper of the GlobalPlatform functions. [`SystemTime`],
+//! [`ReeTime`] and [`PersistentTime`] represent the three clocks as
+//! [`Duration`]s since their origin, so they can be compared and offset:
+//!
+//! ``` rust,no_run
+//! # use core::time::Duration;
+//! # use optee_utee::time::{PersistentTime, ReeTime, SystemTime};
+//! # fn main() -> optee_utee::Result<()> {
+//! let deadline = SystemTime::now() + Duration::from_secs(5);
+//! // ...
+//! if SystemTime::now() > deadline {
+//!     // timed out
+//! }
+//!
+//! // Trust the REE time once, e.g. when provisioning the device
+//! PersistentTime::set(PersistentTime::from_duration(
+//!     ReeTime::now().since_unix_epoch(),
+//! ))?;
+//! let now = PersistentTime::now()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `serde` feature the three types implement `Serialize` and
+//! `Deserialize` like the [`Duration`] they hold, to store them in the
+//! secure storage for example.
+
+use crate::{Error, ErrorKind, Result};
 use core::fmt;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::time::Duration;
 use optee_utee_sys as raw;
 
 /// A millisecond resolution structure for saving the time.
@@ -170,3 +201,262 @@ impl fmt::Display for Time {
         )
     }
 }
+
+fn raw_time() -> raw::TEE_Time {
+    raw::TEE_Time {
+        seconds: 0,
+        millis: 0,
+    }
+}
+
+fn to_duration(time: raw::TEE_Time) -> Duration {
+    Duration::from_secs(time.seconds.into()) + Duration::from_millis(time.millis.into())
+}
+
+/// The GlobalPlatform representation of `duration`, truncated to
+/// milliseconds.
+fn from_duration(duration: Duration) -> Result<raw::TEE_Time> {
+    Ok(raw::TEE_Time {
+        seconds: duration
+            .as_secs()
+            .try_into()
+            .map_err(|_| ErrorKind::Overflow)?,
+        millis: duration.subsec_millis(),
+    })
+}
+
+/// Implements the arithmetic shared by the time types, like the one of
+/// `std::time::Instant`.
+macro_rules! time_arithmetic {
+    ($name:ident) => {
+        impl $name {
+            /// The time since the origin of the clock.
+            pub fn as_duration(&self) -> Duration {
+                self.0
+            }
+
+            /// The time elapsed from `earlier` to `self`, or `None` if
+            /// `earlier` is later than `self`.
+            pub fn checked_duration_since(&self, earlier: Self) -> Option<Duration> {
+                self.0.checked_sub(earlier.0)
+            }
+
+            /// The time elapsed from `earlier` to `self`, or zero if
+            /// `earlier` is later than `self`.
+            pub fn saturating_duration_since(&self, earlier: Self) -> Duration {
+                self.0.saturating_sub(earlier.0)
+            }
+
+            /// `self` moved forward by `duration`, or `None` on overflow.
+            pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+                self.0.checked_add(duration).map(Self)
+            }
+
+            /// `self` moved backward by `duration`, or `None` if it would be
+            /// before the origin of the clock.
+            pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+                self.0.checked_sub(duration).map(Self)
+            }
+        }
+
+        impl Add<Duration> for $name {
+            type Output = Self;
+
+            /// # Panics
+            ///
+            /// On overflow, see [`checked_add`](Self::checked_add).
+            fn add(self, duration: Duration) -> Self {
+                self.checked_add(duration)
+                    .expect("overflow when adding a duration to a time")
+            }
+        }
+
+        impl AddAssign<Duration> for $name {
+            fn add_assign(&mut self, duration: Duration) {
+                *self = *self + duration;
+            }
+        }
+
+        impl Sub<Duration> for $name {
+            type Output = Self;
+
+            /// # Panics
+            ///
+            /// Before the origin of the clock, see
+            /// [`checked_sub`](Self::checked_sub).
+            fn sub(self, duration: Duration) -> Self {
+                self.checked_sub(duration)
+                    .expect("overflow when subtracting a duration from a time")
+            }
+        }
+
+        impl SubAssign<Duration> for $name {
+            fn sub_assign(&mut self, duration: Duration) {
+                *self = *self - duration;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Duration;
+
+            /// The time elapsed from `earlier` to `self`, zero if `earlier`
+            /// is later.
+            fn sub(self, earlier: Self) -> Duration {
+                self.saturating_duration_since(earlier)
+            }
+        }
+    };
+}
+
+/// The system time of the TA instance, which is never rolled back while the
+/// instance lives, from an arbitrary origin.
+///
+/// It measures time differences and deadlines, like `std::time::Instant`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SystemTime(Duration);
+
+time_arithmetic!(SystemTime);
+
+impl SystemTime {
+    /// The current system time.
+    ///
+    /// # Panics
+    ///
+    /// If the Implementation detects any error.
+    pub fn now() -> Self {
+        let mut time = raw_time();
+        unsafe { raw::TEE_GetSystemTime(&mut time) };
+        Self(to_duration(time))
+    }
+
+    /// The system time elapsed since `self`.
+    pub fn elapsed(&self) -> Duration {
+        Self::now() - *self
+    }
+}
+
+/// The time of the REE, as seconds since the Unix epoch on OP-TEE.
+///
+/// It is as trusted as the REE, which can set it to any value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct ReeTime(Duration);
+
+time_arithmetic!(ReeTime);
+
+impl ReeTime {
+    /// The current REE time.
+    ///
+    /// # Panics
+    ///
+    /// If the Implementation detects any error.
+    pub fn now() -> Self {
+        let mut time = raw_time();
+        unsafe { raw::TEE_GetREETime(&mut time) };
+        Self(to_duration(time))
+    }
+
+    /// The REE time as the time since the Unix epoch.
+    pub fn since_unix_epoch(&self) -> Duration {
+        self.0
+    }
+}
+
+/// The persistent time of the TA, a real time clock kept across reboots
+/// whose origin is set by the TA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct PersistentTime(Duration);
+
+time_arithmetic!(PersistentTime);
+
+impl PersistentTime {
+    /// The persistent time `duration` after the origin chosen by the TA.
+    pub fn from_duration(duration: Duration) -> Self {
+        Self(duration)
+    }
+
+    /// The current persistent time of the TA.
+    ///
+    /// # Errors
+    ///
+    /// 1) `TimeNotSet`: The time has never been set with [`set`](Self::set).
+    /// 2) `TimeNeedsReset`: The time may have been corrupted and must be set
+    ///    again.
+    /// 3) `Overflow`: The number of seconds overflows a `u32`.
+    ///
+    /// # Panics
+    ///
+    /// If the Implementation detects any other error.
+    pub fn now() -> Result<Self> {
+        let mut time = raw_time();
+        match unsafe { raw::TEE_GetTAPersistentTime(&mut time) } {
+            raw::TEE_SUCCESS => Ok(Self(to_duration(time))),
+            code => Err(Error::from_raw_error(code)),
+        }
+    }
+
+    /// Set the persistent time of the TA to `time`, from which it keeps
+    /// running.
+    ///
+    /// # Errors
+    ///
+    /// 1) `Overflow`: The number of seconds of `time` overflows a `u32`.
+    /// 2) `OutOfMemory`: If not enough memory is available.
+    /// 3) `StorageNoSpace`: If insufficient storage space is available.
+    ///
+    /// # Panics
+    ///
+    /// If the Implementation detects any other error.
+    pub fn set(time: Self) -> Result<()> {
+        let time = from_duration(time.0)?;
+        match unsafe { raw::TEE_SetTAPersistentTime(&time) } {
+            raw::TEE_SUCCESS => Ok(()),
+            code => Err(Error::from_raw_error(code)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_conversion() {
+        let time = raw::TEE_Time {
+            seconds: 12,
+            millis: 345,
+        };
+        assert_eq!(to_duration(time), Duration::from_millis(12_345));
+
+        let time = from_duration(Duration::from_micros(12_345_678)).expect("it should be ok");
+        assert_eq!((time.seconds, time.millis), (12, 345));
+
+        let too_late = Duration::from_secs(u64::from(u32::MAX) + 1);
+        assert!(matches!(
+            from_duration(too_late).map_err(|e| e.kind()),
+            Err(ErrorKind::Overflow)
+        ));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let start = SystemTime(Duration::from_secs(10));
+        let later = start + Duration::from_millis(1500);
+        assert!(later > start);
+        assert_eq!(later - start, Duration::from_millis(1500));
+        assert_eq!(start - later, Duration::ZERO);
+        assert_eq!(start.checked_duration_since(later), None);
+        assert_eq!(later - Duration::from_millis(1500), start);
+        assert_eq!(start.checked_sub(Duration::from_secs(11)), None);
+
+        let mut time = PersistentTime::from_duration(Duration::from_secs(1));
+        time += Duration::from_secs(2);
+        time -= Duration::from_secs(1);
+        assert_eq!(time.as_duration(), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,341 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Trusted user interface, for a TA to show what it is about to do and get
+//! the consent of the user or a PIN without going through the REE.
+//!
+//! [`TrustedUi`] abstracts the screens of the GP TEE Trusted User Interface
+//! API the TA flows need: a message, a confirmation with approve and deny
+//! buttons, and a PIN pad. OP-TEE does not implement that API, so a platform
+//! with a display and an input device owned by the secure world implements
+//! the trait over its driver, e.g. with a pseudo TA, and the TA writes its
+//! flows against the trait:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::Result;
+//! use optee_utee::tui::{Screen, TrustedUi, request_consent};
+//!
+//! fn sign_transaction(ui: &mut impl TrustedUi, summary: &str) -> Result<()> {
+//!     request_consent(ui, &Screen::new("Sign transaction?", summary))?;
+//!     // ... sign ...
+//!     Ok(())
+//! }
+//! ```
+//!
+//! With the `tui_console` feature, [`SecureConsole`] stands in for the
+//! trusted UI on QEMU and in unit tests: it prints the screens to the trace
+//! output, the secure console of QEMU, and answers them with the responses
+//! it was scripted with, as a TA cannot read the secure console. It is not
+//! trusted and is meant for development builds only.
+
+#[cfg(feature = "tui_console")]
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use crate::secure_mem::Zeroizing;
+use crate::{ErrorKind, Result};
+
+/// Maximum length of the title or the text of a screen in characters.
+pub const MAX_TEXT_LEN: usize = 512;
+
+/// Answer of the user to a confirmation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Consent {
+    /// The user pressed the approve button.
+    Approved,
+    /// The user pressed the deny button.
+    Denied,
+}
+
+/// A screen of the trusted UI, a title and the text below it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Screen<'a> {
+    pub title: &'a str,
+    pub text: &'a str,
+}
+
+impl<'a> Screen<'a> {
+    pub fn new(title: &'a str, text: &'a str) -> Self {
+        Self { title, text }
+    }
+}
+
+/// The display and the input of the user, following the screens of the GP
+/// TEE Trusted User Interface API.
+///
+/// The implementations check the text of the screens with [`check_text`]
+/// before showing them.
+pub trait TrustedUi {
+    /// Whether the screens are shown and the input is read by hardware the
+    /// REE cannot access, i.e. whether a consent obtained through it can be
+    /// trusted.
+    fn is_trusted(&self) -> bool;
+
+    /// Shows `screen` until the next call.
+    fn display(&mut self, screen: &Screen) -> Result<()>;
+
+    /// Shows `screen` with approve and deny buttons and returns the one the
+    /// user pressed.
+    ///
+    /// Fails with `Cancel` if the user closed the screen or it timed out.
+    fn confirm(&mut self, screen: &Screen) -> Result<Consent>;
+
+    /// Shows `screen` with a PIN pad and returns the digits the user entered,
+    /// as ASCII, within `length`.
+    ///
+    /// Fails with `Cancel` if the user closed the screen or it timed out.
+    fn enter_pin(
+        &mut self,
+        screen: &Screen,
+        length: RangeInclusive<usize>,
+    ) -> Result<Zeroizing<Vec<u8>>>;
+}
+
+/// Checks that `text` can be shown on a screen, like
+/// `TEE_TUICheckTextFormat`: at most [`MAX_TEXT_LEN`] characters, and no
+/// control character but line feeds, which could otherwise e.g. move the
+/// cursor of a console to overwrite what was shown.
+///
+/// Fails with `BadFormat` otherwise.
+pub fn check_text(text: &str) -> Result<()> {
+    if text.chars().count() > MAX_TEXT_LEN || text.chars().any(|c| c.is_control() && c != '\n') {
+        return Err(ErrorKind::BadFormat.into());
+    }
+    Ok(())
+}
+
+/// Asks the user to approve `screen` with `ui`.
+///
+/// Fails with `AccessDenied` if the user denied it, and with `BadFormat` if
+/// the screen cannot be shown. A consent obtained through a UI which is not
+/// [trusted](TrustedUi::is_trusted) is reported on the trace output.
+pub fn request_consent<U: TrustedUi + ?Sized>(ui: &mut U, screen: &Screen) -> Result<()> {
+    check_text(screen.title)?;
+    check_text(screen.text)?;
+    let consent = ui.confirm(screen)?;
+    if !ui.is_trusted() {
+        emit(format_args!(
+            "[!] consent to \"{}\" obtained through an untrusted UI\n",
+            screen.title
+        ));
+    }
+    match consent {
+        Consent::Approved => Ok(()),
+        Consent::Denied => Err(ErrorKind::AccessDenied.into()),
+    }
+}
+
+/// Scripted answer of a [`SecureConsole`].
+#[cfg(feature = "tui_console")]
+#[derive(Debug)]
+enum Answer {
+    Consent(Consent),
+    Pin(Zeroizing<Vec<u8>>),
+}
+
+/// Trusted UI stand-in printing the screens to the secure console and
+/// answering them with scripted responses, for QEMU and unit tests.
+///
+/// ``` rust,no_run
+/// # #[cfg(feature = "tui_console")]
+/// # fn main() -> optee_utee::Result<()> {
+/// use optee_utee::tui::{Consent, Screen, SecureConsole, request_consent};
+///
+/// let mut ui = SecureConsole::new()
+///     .answer_consent(Consent::Approved)
+///     .answer_pin(b"1234");
+/// request_consent(&mut ui, &Screen::new("Sign transaction?", "Pay 1 ETH"))?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "tui_console"))]
+/// # fn main() {}
+/// ```
+///
+/// A screen answered out of order, e.g. a PIN pad when a consent comes next,
+/// fails with `BadState`, and one without answer left with `Cancel`.
+#[cfg(feature = "tui_console")]
+#[derive(Debug, Default)]
+pub struct SecureConsole {
+    answers: VecDeque<Answer>,
+}
+
+#[cfg(feature = "tui_console")]
+impl SecureConsole {
+    /// A console without answers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the next confirmation with `consent`.
+    pub fn answer_consent(mut self, consent: Consent) -> Self {
+        self.answers.push_back(Answer::Consent(consent));
+        self
+    }
+
+    /// Answers the next PIN pad with `pin`.
+    pub fn answer_pin(mut self, pin: &[u8]) -> Self {
+        self.answers
+            .push_back(Answer::Pin(Zeroizing::new(pin.to_vec())));
+        self
+    }
+
+    /// The number of answers left.
+    pub fn remaining(&self) -> usize {
+        self.answers.len()
+    }
+
+    fn show(&self, screen: &Screen, controls: &str) -> Result<()> {
+        check_text(screen.title)?;
+        check_text(screen.text)?;
+        emit(format_args!("[tui] === {} ===\n", screen.title));
+        for line in screen.text.lines() {
+            emit(format_args!("[tui] {}\n", line));
+        }
+        emit(format_args!("[tui] {}\n", controls));
+        Ok(())
+    }
+
+    fn next_answer(&mut self) -> Result<Answer> {
+        self.answers.pop_front().ok_or(ErrorKind::Cancel.into())
+    }
+}
+
+#[cfg(feature = "tui_console")]
+impl TrustedUi for SecureConsole {
+    fn is_trusted(&self) -> bool {
+        false
+    }
+
+    fn display(&mut self, screen: &Screen) -> Result<()> {
+        self.show(screen, "")
+    }
+
+    fn confirm(&mut self, screen: &Screen) -> Result<Consent> {
+        self.show(screen, "[ Deny ]  [ Approve ]")?;
+        match self.next_answer()? {
+            Answer::Consent(consent) => {
+                emit(format_args!("[tui] answered: {:?}\n", consent));
+                Ok(consent)
+            }
+            Answer::Pin(_) => Err(ErrorKind::BadState.into()),
+        }
+    }
+
+    fn enter_pin(
+        &mut self,
+        screen: &Screen,
+        length: RangeInclusive<usize>,
+    ) -> Result<Zeroizing<Vec<u8>>> {
+        self.show(
+            screen,
+            &alloc::format!("PIN of {} to {} digits:", length.start(), length.end()),
+        )?;
+        match self.next_answer()? {
+            Answer::Pin(pin) => {
+                if !length.contains(&pin.len()) || !pin.iter().all(u8::is_ascii_digit) {
+                    return Err(ErrorKind::BadParameters.into());
+                }
+                emit(format_args!("[tui] answered: {}\n", "*".repeat(pin.len())));
+                Ok(pin)
+            }
+            Answer::Consent(_) => Err(ErrorKind::BadState.into()),
+        }
+    }
+}
+
+// The trace syscall is not mocked, so unit tests capture the output instead
+#[cfg(not(test))]
+fn emit(args: core::fmt::Arguments) {
+    crate::trace::Trace::_print(args);
+}
+
+#[cfg(test)]
+extern crate std;
+
+#[cfg(test)]
+std::thread_local! {
+    static CAPTURED: core::cell::RefCell<std::string::String> =
+        const { core::cell::RefCell::new(std::string::String::new()) };
+}
+
+#[cfg(test)]
+fn emit(args: core::fmt::Arguments) {
+    use core::fmt::Write;
+    CAPTURED.with(|captured| captured.borrow_mut().write_fmt(args).unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "tui_console")]
+    fn captured() -> std::string::String {
+        CAPTURED.with(|captured| captured.take())
+    }
+
+    #[test]
+    fn test_check_text() {
+        assert!(check_text("Pay 1 ETH\nto 0x1234").is_ok());
+        assert!(check_text(&"é".repeat(MAX_TEXT_LEN)).is_ok());
+        for text in ["\x1b[2J", "a\rb", &"a".repeat(MAX_TEXT_LEN + 1)] {
+            assert_eq!(check_text(text).unwrap_err().kind(), ErrorKind::BadFormat);
+        }
+    }
+
+    #[cfg(feature = "tui_console")]
+    #[test]
+    fn test_secure_console() {
+        let screen = Screen::new("Sign?", "Pay 1 ETH\nto 0x1234");
+        let mut ui = SecureConsole::new()
+            .answer_consent(Consent::Approved)
+            .answer_consent(Consent::Denied)
+            .answer_pin(b"1234");
+        captured();
+
+        request_consent(&mut ui, &screen).unwrap();
+        let output = captured();
+        assert!(output.contains("[tui] === Sign? ===\n[tui] Pay 1 ETH\n[tui] to 0x1234\n"));
+        assert!(output.contains("untrusted UI"));
+
+        assert_eq!(
+            request_consent(&mut ui, &screen).unwrap_err().kind(),
+            ErrorKind::AccessDenied
+        );
+        // Out of order
+        assert_eq!(ui.confirm(&screen).unwrap_err().kind(), ErrorKind::BadState);
+        assert_eq!(ui.remaining(), 0);
+        assert_eq!(
+            ui.enter_pin(&screen, 4..=8).unwrap_err().kind(),
+            ErrorKind::Cancel
+        );
+
+        let mut ui = SecureConsole::new().answer_pin(b"1234").answer_pin(b"12");
+        assert_eq!(&**ui.enter_pin(&screen, 4..=8).unwrap(), b"1234");
+        assert!(captured().contains("[tui] answered: ****\n"));
+        assert_eq!(
+            ui.enter_pin(&screen, 4..=8).unwrap_err().kind(),
+            ErrorKind::BadParameters
+        );
+        // Rejected before an answer is used
+        let mut ui = SecureConsole::new().answer_consent(Consent::Approved);
+        assert_eq!(
+            ui.confirm(&Screen::new("\x1b[1A", "")).unwrap_err().kind(),
+            ErrorKind::BadFormat
+        );
+        assert_eq!(ui.remaining(), 1);
+    }
+}
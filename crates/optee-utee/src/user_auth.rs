@@ -0,0 +1,263 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Verification of the user in the REE before a sensitive command, e.g. by
+//! Android Gatekeeper, a fingerprint sensor or PAM, through a supplicant
+//! plugin served by `optee_teec::user_auth`.
+//!
+//! The TA sends a random challenge and gets back a token in the layout of
+//! the `hw_auth_token_t` of Android, authenticated with an HMAC-SHA256 key
+//! the TA shares with the authenticator. [`UserAuth`] accepts the verdict
+//! only if the tag and the challenge match, so a REE which does not hold the
+//! key can deny the verification, but not fake it nor replay an old token:
+//!
+//! ``` rust,no_run
+//! # use optee_utee::crypto::HmacSha256;
+//! # use optee_utee::Result;
+//! use optee_utee::user_auth::{AuthenticatorTypes, UserAuth};
+//!
+//! fn sign_payment(auth_key: HmacSha256, payment: &[u8]) -> Result<()> {
+//!     let auth = UserAuth::with_key(auth_key);
+//!     let token = auth.verify_user("Sign a payment", AuthenticatorTypes::all())?;
+//!     // ... sign for the user token.user_id ...
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The key is provisioned to the authenticator and to the TA, e.g. sealed in
+//! its secure storage, at enrolment. A plugin signing the tokens with a key
+//! of its own only tells the TA what the REE claims.
+
+use alloc::vec::Vec;
+
+use bitflags::bitflags;
+
+use crate::crypto::HmacSha256;
+use crate::{ErrorKind, LoadablePlugin, Random, Result, Uuid};
+
+/// UUID of the supplicant plugin verifying the user for the TAs, served by
+/// `optee_teec::user_auth` in the REE.
+pub const USER_AUTH_PLUGIN_UUID: &str = "3e07acba-e1d1-4ed8-8823-403941be6a2d";
+/// Command of the plugin verifying the user.
+pub const CMD_VERIFY_USER: u32 = 0;
+
+/// Size of an encoded token in bytes.
+const TOKEN_SIZE: usize = 69;
+/// Size of the part of an encoded token the tag authenticates in bytes.
+const TOKEN_MESSAGE_SIZE: usize = TOKEN_SIZE - HmacSha256::TAG_SIZE;
+/// Version of the tokens.
+const TOKEN_VERSION: u8 = 0;
+
+/// Maximum length of the reason shown to the user in bytes.
+pub const MAX_REASON_LEN: usize = 256;
+
+bitflags! {
+    /// Authenticator types, as in Android.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct AuthenticatorTypes: u32 {
+        /// A password, PIN or pattern.
+        const PASSWORD = 1 << 0;
+        /// A biometric.
+        const FINGERPRINT = 1 << 1;
+    }
+}
+
+/// Verdict of a successful verification of the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuthToken {
+    /// The challenge of the TA.
+    pub challenge: u64,
+    /// Secure user ID of the user verified.
+    pub user_id: u64,
+    /// ID of the enrolment of the authenticator, e.g. of the fingerprints,
+    /// which changes when the user enrols new ones.
+    pub authenticator_id: u64,
+    /// The authenticator which verified the user.
+    pub authenticator_type: AuthenticatorTypes,
+    /// Time of the verification in milliseconds on the clock of the
+    /// authenticator.
+    pub timestamp_ms: u64,
+}
+
+impl AuthToken {
+    /// Decodes an encoded token into the token, its message and its tag.
+    fn decode(bytes: &[u8]) -> Result<(Self, &[u8], &[u8])> {
+        if bytes.len() != TOKEN_SIZE || bytes[0] != TOKEN_VERSION {
+            return Err(ErrorKind::BadFormat.into());
+        }
+        let (message, tag) = bytes.split_at(TOKEN_MESSAGE_SIZE);
+        let u64_le =
+            |range: core::ops::Range<usize>| u64::from_le_bytes(message[range].try_into().unwrap());
+        let token = Self {
+            challenge: u64_le(1..9),
+            user_id: u64_le(9..17),
+            authenticator_id: u64_le(17..25),
+            // Big-endian, as in Android
+            authenticator_type: AuthenticatorTypes::from_bits_retain(u32::from_be_bytes(
+                message[25..29].try_into().unwrap(),
+            )),
+            timestamp_ms: u64::from_be_bytes(message[29..37].try_into().unwrap()),
+        };
+        Ok((token, message, tag))
+    }
+}
+
+/// Verifies the user through a supplicant plugin, checking the tokens with
+/// the key shared with the authenticator.
+pub struct UserAuth {
+    plugin: LoadablePlugin,
+    key: HmacSha256,
+}
+
+impl UserAuth {
+    /// Verifies the user with the plugin `uuid`, for a plugin routing
+    /// [`CMD_VERIFY_USER`] to `optee_teec::user_auth` under another UUID.
+    pub fn new(uuid: &Uuid, key: HmacSha256) -> Self {
+        Self {
+            plugin: LoadablePlugin::new(uuid),
+            key,
+        }
+    }
+
+    /// Verifies the user with the plugin [`USER_AUTH_PLUGIN_UUID`].
+    pub fn with_key(key: HmacSha256) -> Self {
+        let uuid = Uuid::parse_str(USER_AUTH_PLUGIN_UUID).expect("the plugin UUID is valid");
+        Self::new(&uuid, key)
+    }
+
+    /// Asks the REE to verify the user with one of the `allowed`
+    /// authenticators, showing `reason`, and returns the verdict once
+    /// checked.
+    ///
+    /// # Errors
+    ///
+    /// - `AccessDenied`: If the user was not verified.
+    /// - `Cancel`: If the user dismissed the prompt.
+    /// - `MacInvalid`: If the token is not authenticated with the key.
+    /// - `Security`: If the token is for another challenge, or from an
+    ///   authenticator which is not allowed.
+    /// - `BadParameters`: If `reason` is longer than [`MAX_REASON_LEN`].
+    /// - `ItemNotFound`: If the plugin is not installed.
+    pub fn verify_user(&self, reason: &str, allowed: AuthenticatorTypes) -> Result<AuthToken> {
+        if reason.len() > MAX_REASON_LEN {
+            return Err(ErrorKind::BadParameters.into());
+        }
+        let mut challenge = [0u8; 8];
+        Random::generate(&mut challenge);
+        let challenge = u64::from_le_bytes(challenge);
+        let response = self
+            .plugin
+            .invoke_with_capacity(CMD_VERIFY_USER, 0, TOKEN_SIZE)
+            .chain_write_body(&request(challenge, allowed, reason))
+            .call()?;
+        let (token, message, tag) = AuthToken::decode(&response)?;
+        self.key.verify(message, tag)?;
+        check_verdict(&token, challenge, allowed)?;
+        Ok(token)
+    }
+}
+
+/// The request for `challenge`.
+fn request(challenge: u64, allowed: AuthenticatorTypes, reason: &str) -> Vec<u8> {
+    let mut request = Vec::with_capacity(12 + reason.len());
+    request.extend_from_slice(&challenge.to_le_bytes());
+    request.extend_from_slice(&allowed.bits().to_le_bytes());
+    request.extend_from_slice(reason.as_bytes());
+    request
+}
+
+/// Checks that the authenticated `token` answers `challenge` with one of the
+/// `allowed` authenticators.
+fn check_verdict(token: &AuthToken, challenge: u64, allowed: AuthenticatorTypes) -> Result<()> {
+    if token.challenge != challenge
+        || token.authenticator_type.is_empty()
+        || !allowed.contains(token.authenticator_type)
+    {
+        return Err(ErrorKind::Security.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn encoded(challenge: u64, authenticator_type: u32) -> Vec<u8> {
+        let mut token = vec![TOKEN_VERSION];
+        token.extend_from_slice(&challenge.to_le_bytes());
+        token.extend_from_slice(&3u64.to_le_bytes());
+        token.extend_from_slice(&4u64.to_le_bytes());
+        token.extend_from_slice(&authenticator_type.to_be_bytes());
+        token.extend_from_slice(&5u64.to_be_bytes());
+        token.extend_from_slice(&[0xaa; HmacSha256::TAG_SIZE]);
+        token
+    }
+
+    #[test]
+    fn test_decode() {
+        let bytes = encoded(7, 2);
+        let (token, message, tag) = AuthToken::decode(&bytes).unwrap();
+        assert_eq!(
+            token,
+            AuthToken {
+                challenge: 7,
+                user_id: 3,
+                authenticator_id: 4,
+                authenticator_type: AuthenticatorTypes::FINGERPRINT,
+                timestamp_ms: 5,
+            }
+        );
+        assert_eq!(message, &bytes[..TOKEN_MESSAGE_SIZE]);
+        assert_eq!(tag, [0xaa; HmacSha256::TAG_SIZE]);
+
+        let mut other_version = bytes.clone();
+        other_version[0] = 1;
+        for bytes in [&bytes[1..], &other_version[..]] {
+            assert_eq!(
+                AuthToken::decode(bytes).unwrap_err().kind(),
+                ErrorKind::BadFormat
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_verdict() {
+        let (token, _, _) = AuthToken::decode(&encoded(7, 2)).unwrap();
+        assert!(check_verdict(&token, 7, AuthenticatorTypes::all()).is_ok());
+        for (challenge, allowed) in [
+            (8, AuthenticatorTypes::all()),
+            (7, AuthenticatorTypes::PASSWORD),
+        ] {
+            assert_eq!(
+                check_verdict(&token, challenge, allowed)
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::Security
+            );
+        }
+        let (token, _, _) = AuthToken::decode(&encoded(7, 0)).unwrap();
+        assert!(check_verdict(&token, 7, AuthenticatorTypes::all()).is_err());
+    }
+
+    #[test]
+    fn test_request() {
+        assert_eq!(
+            request(1, AuthenticatorTypes::PASSWORD, "Sign"),
+            b"\x01\0\0\0\0\0\0\0\x01\0\0\0Sign"
+        );
+    }
+}
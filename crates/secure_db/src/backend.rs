@@ -15,40 +15,48 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Context, Result};
+use optee_utee::storage::StorageError;
 use optee_utee::{DataFlag, GenericObject, ObjectStorageConstants, PersistentObject};
 
 // Wrapper functions for OP-TEE raw API
+//
+// The `optee_utee::Error` of a failed call is kept in the chain of the
+// returned error, see `storage_error`.
 
 pub fn save_in_secure_storage(obj_id: &[u8], data: &[u8]) -> Result<()> {
+    save_in(ObjectStorageConstants::Private, obj_id, data)
+}
+
+pub fn load_from_secure_storage(obj_id: &[u8]) -> Result<Option<Vec<u8>>> {
+    load_from(ObjectStorageConstants::Private, obj_id)
+}
+
+pub fn delete_from_secure_storage(obj_id: &[u8]) -> Result<()> {
+    delete_from(ObjectStorageConstants::Private, obj_id)
+}
+
+pub fn save_in(storage: ObjectStorageConstants, obj_id: &[u8], data: &[u8]) -> Result<()> {
     let obj_data_flag = DataFlag::ACCESS_READ
         | DataFlag::ACCESS_WRITE
         | DataFlag::ACCESS_WRITE_META
         | DataFlag::OVERWRITE;
 
-    PersistentObject::create(
-        ObjectStorageConstants::Private,
-        obj_id,
-        obj_data_flag,
-        None,
-        data,
-    )
-    .map_err(|e| anyhow!("[-] {:?}: failed to create object: {:?}", &obj_id, e))?;
+    PersistentObject::create(storage, obj_id, obj_data_flag, None, data)
+        .with_context(|| format!("[-] {:?}: failed to create object", obj_id))?;
 
     Ok(())
 }
 
-pub fn load_from_secure_storage(obj_id: &[u8]) -> Result<Option<Vec<u8>>> {
+pub fn load_from(storage: ObjectStorageConstants, obj_id: &[u8]) -> Result<Option<Vec<u8>>> {
     match PersistentObject::open(
-        ObjectStorageConstants::Private,
+        storage,
         obj_id,
         DataFlag::ACCESS_READ | DataFlag::SHARE_READ,
     ) {
         Err(e) => match e.kind() {
             optee_utee::ErrorKind::ItemNotFound => Ok(None),
-            _ => {
-                bail!("[-] {:?}: failed to open object: {:?}", &obj_id, e);
-            }
+            _ => Err(e).with_context(|| format!("[-] {:?}: failed to open object", obj_id)),
         },
 
         Ok(mut object) => {
@@ -57,7 +65,7 @@ pub fn load_from_secure_storage(obj_id: &[u8]) -> Result<Option<Vec<u8>>> {
 
             let read_bytes = object.read(&mut buf)?;
             if read_bytes != obj_info.data_size() as u32 {
-                bail!("[-] {:?}: failed to read data", &obj_id);
+                bail!("[-] {:?}: failed to read data", obj_id);
             }
 
             Ok(Some(buf))
@@ -65,19 +73,22 @@ pub fn load_from_secure_storage(obj_id: &[u8]) -> Result<Option<Vec<u8>>> {
     }
 }
 
-pub fn delete_from_secure_storage(obj_id: &[u8]) -> Result<()> {
-    match PersistentObject::open(
-        ObjectStorageConstants::Private,
+pub fn delete_from(storage: ObjectStorageConstants, obj_id: &[u8]) -> Result<()> {
+    let object = PersistentObject::open(
+        storage,
         obj_id,
         DataFlag::ACCESS_READ | DataFlag::ACCESS_WRITE_META,
-    ) {
-        Err(e) => {
-            bail!("[-] {:?}: failed to open object: {:?}", &obj_id, e);
-        }
+    )
+    .with_context(|| format!("[-] {:?}: failed to open object", obj_id))?;
+    object.close_and_delete()?;
+    Ok(())
+}
 
-        Ok(object) => {
-            object.close_and_delete()?;
-            Ok(())
-        }
-    }
+/// The failure of the Trusted Storage which caused `error`, if any, e.g. to
+/// tell a full storage apart from other errors.
+pub fn storage_error(error: &anyhow::Error) -> Option<StorageError> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<optee_utee::Error>())
+        .map(StorageError::from)
 }
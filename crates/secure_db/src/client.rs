@@ -18,6 +18,7 @@
 use crate::SecureStorageDb;
 use crate::Storable;
 use anyhow::{anyhow, Result};
+use optee_utee::ObjectStorageConstants;
 use std::{
     collections::HashMap,
     convert::TryFrom,
@@ -40,6 +41,15 @@ impl SecureStorageClient {
         })
     }
 
+    pub fn open_in(storage: impl Into<ObjectStorageConstants>, db_name: &str) -> Result<Self> {
+        Ok(Self {
+            db: Arc::new(RwLock::new(SecureStorageDb::open_in(
+                storage,
+                db_name.to_string(),
+            )?)),
+        })
+    }
+
     pub fn get<V>(&self, key: &V::Key) -> Result<V>
     where
         V: Storable + serde::de::DeserializeOwned,
@@ -101,3 +111,175 @@ impl SecureStorageClient {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_storage::MockStorage;
+    use optee_utee::fault_injection::{self, Fault, FaultPoint};
+    use optee_utee::ErrorKind;
+    use serde::{Deserialize, Serialize};
+    use std::{sync::mpsc, thread, time::Duration};
+
+    const DB_NAME: &str = "test_db";
+    const SESSIONS: usize = 16;
+    const OPS_PER_SESSION: usize = 64;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct Record {
+        id: String,
+        value: u64,
+    }
+
+    impl Storable for Record {
+        type Key = String;
+
+        fn unique_id(&self) -> Self::Key {
+            self.id.clone()
+        }
+    }
+
+    // Run `f` on another thread, failing the test instead of hanging if it
+    // deadlocks.
+    fn run_with_timeout<F: FnOnce() + Send + 'static>(f: F) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            f();
+            let _ = sender.send(());
+        });
+        match receiver.recv_timeout(Duration::from_secs(60)) {
+            Ok(()) => handle.join().unwrap(),
+            // the thread panicked, propagate it
+            Err(mpsc::RecvTimeoutError::Disconnected) => handle.join().unwrap(),
+            Err(mpsc::RecvTimeoutError::Timeout) => panic!("deadlock: timed out"),
+        }
+    }
+
+    #[test]
+    // Many sessions interleaving puts, gets and deletes on their own records.
+    fn test_interleaved_sessions() {
+        let storage = MockStorage::new();
+        let client = Arc::new(SecureStorageClient::open(DB_NAME).unwrap());
+
+        run_with_timeout({
+            let client = client.clone();
+            move || {
+                thread::scope(|scope| {
+                    for session in 0..SESSIONS {
+                        let client = &client;
+                        scope.spawn(move || {
+                            for op in 0..OPS_PER_SESSION {
+                                let record = Record {
+                                    id: format!("{}-{}", session, op),
+                                    value: (session * OPS_PER_SESSION + op) as u64,
+                                };
+                                client.put(&record).unwrap();
+                                assert_eq!(client.get::<Record>(&record.id).unwrap(), record);
+                                if op % 2 == 1 {
+                                    client.delete_entry::<Record>(&record.id).unwrap();
+                                }
+                            }
+                        });
+                    }
+                });
+            }
+        });
+
+        let entries = client.list_entries::<Record>().unwrap();
+        assert_eq!(entries.len(), SESSIONS * OPS_PER_SESSION / 2);
+        for session in 0..SESSIONS {
+            for op in (0..OPS_PER_SESSION).step_by(2) {
+                let id = format!("{}-{}", session, op);
+                assert_eq!(entries[&id].value, (session * OPS_PER_SESSION + op) as u64);
+            }
+        }
+
+        // the persisted key list matches what the sessions observed
+        let reopened = SecureStorageClient::open(DB_NAME).unwrap();
+        assert_eq!(reopened.list_entries::<Record>().unwrap(), entries);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    // Concurrent writers of the same record, the last write wins as a whole.
+    fn test_concurrent_puts_same_record() {
+        let storage = MockStorage::new();
+        let client = Arc::new(SecureStorageClient::open(DB_NAME).unwrap());
+
+        run_with_timeout({
+            let client = client.clone();
+            move || {
+                thread::scope(|scope| {
+                    for session in 0..SESSIONS {
+                        let client = &client;
+                        scope.spawn(move || {
+                            for op in 0..OPS_PER_SESSION {
+                                let value = (session * OPS_PER_SESSION + op) as u64;
+                                client
+                                    .put(&Record {
+                                        id: "shared".to_string(),
+                                        value,
+                                    })
+                                    .unwrap();
+                                let read = client.get::<Record>(&"shared".to_string()).unwrap();
+                                assert!(read.value < (SESSIONS * OPS_PER_SESSION) as u64);
+                            }
+                        });
+                    }
+                });
+            }
+        });
+
+        let entries = client.list_entries::<Record>().unwrap();
+        assert_eq!(entries.len(), 1);
+        let reopened = SecureStorageClient::open(DB_NAME).unwrap();
+        assert_eq!(reopened.list_entries::<Record>().unwrap(), entries);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    // Storage running full in the middle of concurrent sessions must fail
+    // only the affected puts and leave every stored record readable.
+    fn test_storage_full_during_sessions() {
+        let storage = MockStorage::new();
+        let client = Arc::new(SecureStorageClient::open(DB_NAME).unwrap());
+        fault_injection::inject(
+            FaultPoint::ObjectCreate,
+            (SESSIONS * 8) as u32,
+            Fault::Error(ErrorKind::StorageNoSpace),
+        );
+
+        let failures = Arc::new(RwLock::new(Vec::new()));
+        run_with_timeout({
+            let client = client.clone();
+            let failures = failures.clone();
+            move || {
+                thread::scope(|scope| {
+                    for session in 0..SESSIONS {
+                        let (client, failures) = (&client, &failures);
+                        scope.spawn(move || {
+                            for op in 0..OPS_PER_SESSION {
+                                let record = Record {
+                                    id: format!("{}-{}", session, op),
+                                    value: op as u64,
+                                };
+                                if client.put(&record).is_err() {
+                                    failures.write().unwrap().push(record.id);
+                                }
+                            }
+                        });
+                    }
+                });
+            }
+        });
+
+        let failures = failures.read().unwrap();
+        assert_eq!(failures.len(), 1);
+        let entries = client.list_entries::<Record>().unwrap();
+        assert_eq!(entries.len(), SESSIONS * OPS_PER_SESSION - 1);
+        assert!(!entries.contains_key(&failures[0]));
+        let reopened = SecureStorageClient::open(DB_NAME).unwrap();
+        assert_eq!(reopened.list_entries::<Record>().unwrap(), entries);
+        assert_eq!(storage.open_handles(), 0);
+    }
+}
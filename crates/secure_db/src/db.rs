@@ -15,32 +15,47 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use crate::{delete_from_secure_storage, load_from_secure_storage, save_in_secure_storage};
-use anyhow::{bail, ensure, Result};
+use crate::{delete_from, load_from, save_in};
+use anyhow::{bail, ensure, Context, Result};
 use hashbrown::HashSet;
+use optee_utee::ObjectStorageConstants;
 use std::collections::HashMap;
 
 // SecureStorageDb is a key-value storage for TA to easily store and retrieve data.
 // First we store the key list in the secure storage, named as db_name.
 // Then we store the each key-value pairs in the secure storage.
+//
+// The key list and the entries are all kept in the storage the db is opened
+// in, e.g. `ObjectStorageConstants::PrivateRpmb` for rollback protection.
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SecureStorageDb {
     name: String,
+    storage: ObjectStorageConstants,
     key_list: HashSet<String>,
 }
 
 impl SecureStorageDb {
     pub fn open(name: String) -> Result<Self> {
-        match load_from_secure_storage(name.as_bytes())? {
+        Self::open_in(ObjectStorageConstants::Private, name)
+    }
+
+    pub fn open_in(storage: impl Into<ObjectStorageConstants>, name: String) -> Result<Self> {
+        let storage = storage.into();
+        match load_from(storage, name.as_bytes())? {
             Some(data) => {
                 let key_list = bincode::deserialize(&data)?;
-                Ok(Self { name, key_list })
+                Ok(Self {
+                    name,
+                    storage,
+                    key_list,
+                })
             }
             None => {
                 // create new db
                 Ok(Self {
                     name,
+                    storage,
                     // Note: `std::collections::HashSet` was replaced with
                     // `hashbrown::HashSet`, due to a write permission fault
                     // observed during testing. The exact cause of the issue is
@@ -52,42 +67,42 @@ impl SecureStorageDb {
     }
 
     pub fn put(&mut self, key: String, value: Vec<u8>) -> Result<()> {
-        match save_in_secure_storage(key.as_bytes(), &value) {
-            Ok(_) => {
-                self.key_list.insert(key);
-                self.store_key_list()?;
+        save_in(self.storage, key.as_bytes(), &value)
+            .context("[+] SecureStorage::insert(): save error")?;
+        if self.key_list.insert(key.clone()) {
+            // Keep the key list in memory consistent with the stored one if
+            // storing it fails, e.g. when storage is full.
+            if let Err(e) = self.store_key_list() {
+                self.key_list.remove(&key);
+                let _ = delete_from(self.storage, key.as_bytes());
+                return Err(e.context("[+] SecureStorage::insert(): key list save error"));
             }
-            Err(e) => {
-                bail!("[+] SecureStorage::insert(): save error: {}", e);
-            }
-        };
+        }
         Ok(())
     }
 
     pub fn get(&self, key: &str) -> Result<Vec<u8>> {
         ensure!(self.key_list.contains(key), "Key not found in key list");
-        match load_from_secure_storage(key.as_bytes()) {
-            Ok(Some(data)) => Ok(data),
-            Ok(None) => bail!("[+] SecureStorage::get(): object not found in db"),
-            Err(e) => {
-                bail!("[+] SecureStorage::get(): load error: {}", e);
-            }
+        match load_from(self.storage, key.as_bytes())
+            .context("[+] SecureStorage::get(): load error")?
+        {
+            Some(data) => Ok(data),
+            None => bail!("[+] SecureStorage::get(): object not found in db"),
         }
     }
 
     pub fn delete(&mut self, key: &str) -> Result<()> {
         // ensure key must exist
         ensure!(self.key_list.contains(key), "Key not found in key list");
-        match delete_from_secure_storage(key.as_bytes()) {
-            Ok(_) => {
-                self.key_list.remove(key);
-                self.store_key_list()?;
-            }
-            Err(e) => {
-                bail!("[+] SecureStorage::delete(): delete error: {}", e);
-            }
-        };
-        Ok(())
+        // Remove the key from the stored key list first, so that a failure
+        // leaves at most an unreferenced object rather than a dangling key.
+        self.key_list.remove(key);
+        if let Err(e) = self.store_key_list() {
+            self.key_list.insert(key.to_string());
+            return Err(e.context("[+] SecureStorage::delete(): key list save error"));
+        }
+        delete_from(self.storage, key.as_bytes())
+            .context("[+] SecureStorage::delete(): delete error")
     }
 
     pub fn clear(&mut self) -> Result<()> {
@@ -110,7 +125,146 @@ impl SecureStorageDb {
 
     fn store_key_list(&self) -> Result<()> {
         let key_list = bincode::serialize(&self.key_list)?;
-        save_in_secure_storage(self.name.as_bytes(), &key_list)?;
+        save_in(self.storage, self.name.as_bytes(), &key_list)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_storage::MockStorage;
+    use crate::storage_error;
+    use optee_utee::fault_injection::{self, Fault, FaultPoint};
+    use optee_utee::storage::StorageError;
+    use optee_utee::ErrorKind;
+
+    const DB_NAME: &str = "test_db";
+
+    // Make the `nth` object creation from now on fail as if storage were full.
+    fn storage_full_at(nth: u32) {
+        fault_injection::inject(
+            FaultPoint::ObjectCreate,
+            nth,
+            Fault::Error(ErrorKind::StorageNoSpace),
+        );
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let storage = MockStorage::new();
+        let mut db = SecureStorageDb::open(DB_NAME.to_string()).unwrap();
+        db.put("key".to_string(), b"value".to_vec()).unwrap();
+        assert_eq!(db.get("key").unwrap(), b"value");
+
+        // the key list is persisted
+        assert_eq!(SecureStorageDb::open(DB_NAME.to_string()).unwrap(), db);
+
+        db.delete("key").unwrap();
+        assert!(db.get("key").is_err());
+        assert!(!storage.contains(b"key"));
+        assert_eq!(SecureStorageDb::open(DB_NAME.to_string()).unwrap(), db);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    // If the value is stored but the key list is not, the put must be undone.
+    fn test_put_key_list_storage_full() {
+        let storage = MockStorage::new();
+        let mut db = SecureStorageDb::open(DB_NAME.to_string()).unwrap();
+        db.put("kept".to_string(), b"value".to_vec()).unwrap();
+
+        storage_full_at(2);
+        let err = db.put("key".to_string(), b"value".to_vec()).unwrap_err();
+        assert_eq!(storage_error(&err), Some(StorageError::NoSpace));
+
+        assert!(db.get("key").is_err());
+        assert!(!storage.contains(b"key"));
+        assert_eq!(db.get("kept").unwrap(), b"value");
+        assert_eq!(SecureStorageDb::open(DB_NAME.to_string()).unwrap(), db);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    fn test_put_value_storage_full() {
+        let _storage = MockStorage::new();
+        let mut db = SecureStorageDb::open(DB_NAME.to_string()).unwrap();
+
+        storage_full_at(1);
+        let err = db.put("key".to_string(), b"value".to_vec()).unwrap_err();
+        assert_eq!(storage_error(&err), Some(StorageError::NoSpace));
+
+        assert!(db.get("key").is_err());
+        assert_eq!(SecureStorageDb::open(DB_NAME.to_string()).unwrap(), db);
+    }
+
+    #[test]
+    // A key whose removal could not be persisted must still be readable.
+    fn test_delete_key_list_storage_full() {
+        let storage = MockStorage::new();
+        let mut db = SecureStorageDb::open(DB_NAME.to_string()).unwrap();
+        db.put("key".to_string(), b"value".to_vec()).unwrap();
+
+        storage_full_at(1);
+        assert!(db.delete("key").is_err());
+
+        assert_eq!(db.get("key").unwrap(), b"value");
+        assert_eq!(SecureStorageDb::open(DB_NAME.to_string()).unwrap(), db);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    // A failed object deletion leaves an unreferenced object behind, but the
+    // key must be gone and no handle may leak.
+    fn test_delete_object_fails() {
+        let storage = MockStorage::new();
+        let mut db = SecureStorageDb::open(DB_NAME.to_string()).unwrap();
+        db.put("key".to_string(), b"value".to_vec()).unwrap();
+
+        fault_injection::inject(
+            FaultPoint::ObjectDelete,
+            1,
+            Fault::Error(ErrorKind::StorageNotAvailable),
+        );
+        assert!(db.delete("key").is_err());
+
+        assert!(db.get("key").is_err());
+        assert!(storage.contains(b"key"));
+        assert_eq!(SecureStorageDb::open(DB_NAME.to_string()).unwrap(), db);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    // A corrupted key list must be reported instead of being used.
+    fn test_open_corrupt_key_list() {
+        let storage = MockStorage::new();
+        let mut db = SecureStorageDb::open(DB_NAME.to_string()).unwrap();
+        db.put("key".to_string(), b"value".to_vec()).unwrap();
+
+        fault_injection::inject(FaultPoint::ObjectRead, 1, Fault::Corrupt);
+        assert!(SecureStorageDb::open(DB_NAME.to_string()).is_err());
+        assert!(!fault_injection::is_pending(FaultPoint::ObjectRead));
+
+        assert_eq!(SecureStorageDb::open(DB_NAME.to_string()).unwrap(), db);
+        assert_eq!(storage.open_handles(), 0);
+    }
+
+    #[test]
+    fn test_get_storage_not_available() {
+        let storage = MockStorage::new();
+        let mut db = SecureStorageDb::open(DB_NAME.to_string()).unwrap();
+        db.put("key".to_string(), b"value".to_vec()).unwrap();
+
+        fault_injection::inject(
+            FaultPoint::ObjectOpen,
+            1,
+            Fault::Error(ErrorKind::StorageNotAvailable),
+        );
+        let err = db.get("key").unwrap_err();
+        assert_eq!(storage_error(&err), Some(StorageError::NotAvailable));
+
+        // the failure is transient, a retry succeeds
+        assert_eq!(db.get("key").unwrap(), b"value");
+        assert_eq!(storage.open_handles(), 0);
+    }
+}
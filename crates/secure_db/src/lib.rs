@@ -23,3 +23,5 @@ mod db;
 pub use db::*;
 mod storable;
 pub use storable::*;
+#[cfg(test)]
+mod mock_storage;
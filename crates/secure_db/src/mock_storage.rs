@@ -0,0 +1,50 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+// The in-memory secure storage of optee-utee-sys, so that the database can be
+// exercised on the host without a TEE.
+//
+// Storage failures are injected with `optee_utee::fault_injection`, whose
+// state is reset whenever a `MockStorage` is created or dropped.
+
+use optee_utee::fault_injection;
+use optee_utee_sys::mock_utils::storage;
+use std::ops::Deref;
+
+pub struct MockStorage(storage::MockStorage);
+
+impl MockStorage {
+    pub fn new() -> Self {
+        // Reset once the storage holds the lock of the mocked API
+        let storage = storage::MockStorage::new();
+        fault_injection::reset();
+        Self(storage)
+    }
+}
+
+impl Deref for MockStorage {
+    type Target = storage::MockStorage;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for MockStorage {
+    fn drop(&mut self) {
+        fault_injection::reset();
+    }
+}
@@ -16,7 +16,7 @@
 // under the License.
 
 use optee_teec::{Context, Operation, ParamType, Session, Uuid};
-use optee_teec::{ErrorKind, ParamNone, ParamTmpRef, ParamValue};
+use optee_teec::{ErrorKind, ParamNone, ParamValue};
 use proto::{Command, UUID};
 use std::{env, str};
 
@@ -35,12 +35,13 @@ fn enc_dec(session: &mut Session, plain_text: &[u8]) -> optee_teec::Result<()> {
 
     session.invoke_command(Command::GetSize as u32, &mut operation)?;
 
-    let mut cipher_text = vec![0u8; operation.parameters().0.a() as usize];
-    let p0 = ParamTmpRef::new_input(plain_text);
-    let p1 = ParamTmpRef::new_output(&mut cipher_text);
-    let mut operation2 = Operation::new(0, p0, p1, ParamNone, ParamNone);
-
-    session.invoke_command(Command::Encrypt as u32, &mut operation2)?;
+    // The key size is the expected cipher text size, the reader retries with
+    // a larger buffer if the TA reports that more is required.
+    let cipher_text = session.invoke_with_growable_output(
+        Command::Encrypt as u32,
+        plain_text,
+        operation.parameters().0.a() as usize,
+    )?;
     let plain_text_str = str::from_utf8(plain_text).map_err(|e| {
         eprintln!("Failed to convert plain text to UTF-8: {}", e);
         ErrorKind::BadFormat
@@ -52,12 +53,11 @@ fn enc_dec(session: &mut Session, plain_text: &[u8]) -> optee_teec::Result<()> {
         cipher_text
     );
 
-    let p0 = ParamTmpRef::new_input(&cipher_text);
-    let mut dec_res: Vec<u8> = vec![0u8; plain_text.len()];
-    let p1 = ParamTmpRef::new_output(&mut dec_res);
-    let mut operation2 = Operation::new(0, p0, p1, ParamNone, ParamNone);
-
-    session.invoke_command(Command::Decrypt as u32, &mut operation2)?;
+    let dec_res = session.invoke_with_growable_output(
+        Command::Decrypt as u32,
+        &cipher_text,
+        plain_text.len(),
+    )?;
     let dec_res_str = str::from_utf8(&dec_res).map_err(|e| {
         eprintln!("Failed to convert decrypted result to UTF-8: {}", e);
         ErrorKind::BadFormat
@@ -84,8 +84,9 @@ fn encrypt(rsa: &mut RsaCipher, (p0, p1, _, _): &mut ParametersAny<'_>) -> Resul
     )?;
     cipher.set_key(&rsa.key)?;
     let cipher_text = cipher.encrypt(&[], p0.get_buffer())?;
-    p1.set_output(cipher_text)?;
-    Ok(())
+    let mut writer = OutputWriter::new(p1);
+    writer.write(cipher_text);
+    writer.finish()
 }
 
 fn decrypt(rsa: &mut RsaCipher, (p0, p1, _, _): &mut ParametersAny<'_>) -> Result<()> {
@@ -98,7 +99,9 @@ fn decrypt(rsa: &mut RsaCipher, (p0, p1, _, _): &mut ParametersAny<'_>) -> Resul
     )?;
     cipher.set_key(&rsa.key)?;
     let plain_text = cipher.decrypt(&[], p0.get_buffer())?;
-    p1.set_output(plain_text)
+    let mut writer = OutputWriter::new(p1);
+    writer.write(plain_text);
+    writer.finish()
 }
 
 #[ta_invoke_command]
@@ -15,7 +15,7 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use optee_teec::{Context, Error, ErrorKind, ErrorOrigin, Operation, Param, Session, Uuid};
+use optee_teec::{Context, Error, Operation, Param, Session, Uuid};
 use optee_teec::{ParamNone, ParamTmpRef, ParamType, ParamValue};
 use proto::Command;
 
@@ -72,9 +72,7 @@ impl Connection {
     //   until the session is closed.
     pub fn valid(&self) -> optee_teec::Result<()> {
         if let Some(err) = self.last_err.as_ref() {
-            if err.kind() == ErrorKind::TargetDead
-                && err.origin().is_some_and(|x| x != ErrorOrigin::TA)
-            {
+            if err.is_target_dead() && err.origin().is_some() {
                 return Err(err.clone());
             }
         }
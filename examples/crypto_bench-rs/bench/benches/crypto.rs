@@ -0,0 +1,60 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The operations of the TA on the host, against the simulator of
+//! optee-utee-mock. The simulator has no asymmetric operations, so ECDSA
+//! signing is only timed by the TA on the device.
+
+extern crate alloc;
+
+#[path = "../../ta/src/operations.rs"]
+mod operations;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use optee_utee::crypto::AesGcm;
+use proto::PAYLOAD_SIZES;
+
+fn bench_operations(c: &mut Criterion) {
+    let key = AesGcm::generate(operations::KEY_SIZE).expect("should generate the key");
+    let mut group = c.benchmark_group("crypto");
+    for payload_size in PAYLOAD_SIZES {
+        let payload = vec![0xa5u8; payload_size];
+        let sealed = operations::seal(&key, &payload).expect("should seal the payload");
+        operations::open(&key, &sealed).expect("should open the payload");
+        operations::wrap_blob(&key, &payload).expect("should wrap the payload");
+        group.throughput(Throughput::Bytes(payload_size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("aes-gcm-128 encrypt", payload_size),
+            &payload,
+            |b, payload| b.iter(|| operations::seal(&key, payload)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("aes-gcm-128 decrypt", payload_size),
+            &sealed,
+            |b, sealed| b.iter(|| operations::open(&key, sealed)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("backup blob wrap", payload_size),
+            &payload,
+            |b, payload| b.iter(|| operations::wrap_blob(&key, payload)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_operations);
+criterion_main!(benches);
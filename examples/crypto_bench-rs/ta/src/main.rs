@@ -0,0 +1,96 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![no_main]
+
+extern crate alloc;
+
+mod operations;
+
+use alloc::format;
+use alloc::vec;
+use optee_bench::ta::Bench;
+use optee_utee::crypto::{AesGcm, EcdsaP256};
+use optee_utee::prelude::*;
+use optee_utee::{ErrorKind, ParameterAny, Result};
+use proto::{Command, PAYLOAD_SIZES};
+
+#[ta_create]
+fn create() -> Result<()> {
+    trace_println!("[+] TA create");
+    Ok(())
+}
+
+#[ta_open_session]
+fn open_session(_params: &mut ParametersNone) -> Result<()> {
+    trace_println!("[+] TA open session");
+    Ok(())
+}
+
+#[ta_close_session]
+fn close_session() {
+    trace_println!("[+] TA close session");
+}
+
+#[ta_destroy]
+fn destroy() {
+    trace_println!("[+] TA destroy");
+}
+
+#[ta_invoke_command]
+fn invoke_command(cmd_id: u32, params: &mut ParametersAny<'_>) -> Result<()> {
+    trace_println!("[+] TA invoke command");
+    match Command::from(cmd_id) {
+        Command::Bench => bench(&mut params.0),
+        _ => Err(ErrorKind::BadParameters.into()),
+    }
+}
+
+/// Time the operations over every payload size and write the report into
+/// `param`. Keys and payloads are prepared before the clock starts, and every
+/// operation runs once up front so that its errors are returned rather than
+/// timed.
+fn bench(param: &mut ParameterAny<'_>) -> Result<()> {
+    let mut bench = Bench::new();
+    let key = AesGcm::generate(operations::KEY_SIZE)?;
+    let signing_key = EcdsaP256::generate()?;
+    for payload_size in PAYLOAD_SIZES {
+        let payload = vec![0xa5u8; payload_size];
+        let sealed = operations::seal(&key, &payload)?;
+        operations::open(&key, &sealed)?;
+        signing_key.sign(&payload)?;
+        operations::wrap_blob(&key, &payload)?;
+
+        bench
+            .function(&format!("aes-gcm-128 encrypt {} B", payload_size), |b| {
+                b.iter(|| operations::seal(&key, &payload))
+            })
+            .function(&format!("aes-gcm-128 decrypt {} B", payload_size), |b| {
+                b.iter(|| operations::open(&key, &sealed))
+            })
+            .function(&format!("ecdsa-p256 sign {} B", payload_size), |b| {
+                b.iter(|| signing_key.sign(&payload))
+            })
+            .function(&format!("backup blob wrap {} B", payload_size), |b| {
+                b.iter(|| operations::wrap_blob(&key, &payload))
+            });
+    }
+    bench.finish(param)
+}
+
+include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));
@@ -0,0 +1,53 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The symmetric operations timed by the TA, also included by the host
+//! benchmarks running against the simulator of optee-utee-mock.
+
+use alloc::vec::Vec;
+use optee_utee::crypto::AesGcm;
+use optee_utee::{Random, Result};
+
+/// Size of the keys in bits.
+pub const KEY_SIZE: usize = 128;
+
+/// Seal `payload` under `key` with a random nonce, returning the nonce
+/// followed by the ciphertext and the tag.
+pub fn seal(key: &AesGcm, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; AesGcm::NONCE_SIZE];
+    Random::generate(&mut nonce);
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&key.encrypt(&nonce, &[], payload)?);
+    Ok(sealed)
+}
+
+/// Open the output of [`seal`].
+pub fn open(key: &AesGcm, sealed: &[u8]) -> Result<Vec<u8>> {
+    let (nonce, ciphertext) = sealed.split_at(AesGcm::NONCE_SIZE.min(sealed.len()));
+    key.decrypt(nonce, &[], ciphertext)
+}
+
+/// Envelope encryption of a backup blob: the payload is sealed under a fresh
+/// random data key, which is then sealed under the long-lived `wrapping_key`.
+/// Returns the wrapped data key followed by the sealed payload.
+pub fn wrap_blob(wrapping_key: &AesGcm, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut data_key = [0u8; KEY_SIZE / 8];
+    Random::generate(&mut data_key);
+    let mut blob = seal(wrapping_key, &data_key)?;
+    blob.extend_from_slice(&seal(&AesGcm::new(&data_key)?, payload)?);
+    Ok(blob)
+}
@@ -0,0 +1,94 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Client of the authenticator TA.
+
+use optee_teec::{
+    Context, ErrorKind, Operation, OutputReader, ParamNone, ParamTmpRef, Result, Session, Uuid,
+};
+use proto::{
+    Command, GetAssertionInput, GetAssertionOutput, InfoOutput, MakeCredentialInput,
+    MakeCredentialOutput, ResetOutput,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Initial size of the output buffer, grown when the TA asks for more.
+const OUTPUT_CAPACITY: usize = 1024;
+
+pub struct Authenticator {
+    session: Session,
+}
+
+impl Authenticator {
+    pub fn open(context: &mut Context) -> Result<Self> {
+        let uuid = Uuid::parse_str(proto::UUID)?;
+        Ok(Self {
+            session: context.open_session(uuid)?,
+        })
+    }
+
+    pub fn get_info(&mut self) -> Result<InfoOutput> {
+        self.invoke(Command::GetInfo, Vec::new())
+    }
+
+    /// Fails with `AccessConflict` if a credential of `exclude_list` is on
+    /// the authenticator, and with the `TEE_ERROR_STORAGE_NO_SPACE` code if
+    /// it is full.
+    pub fn make_credential(&mut self, input: &MakeCredentialInput) -> Result<MakeCredentialOutput> {
+        self.invoke(Command::MakeCredential, encode(input)?)
+    }
+
+    /// Fails with `ItemNotFound` if no credential matches.
+    pub fn get_assertion(&mut self, input: &GetAssertionInput) -> Result<GetAssertionOutput> {
+        self.invoke(Command::GetAssertion, encode(input)?)
+    }
+
+    pub fn reset(&mut self) -> Result<ResetOutput> {
+        self.invoke(Command::Reset, Vec::new())
+    }
+
+    /// Invoke `command` with the encoded `input`, passed as `None` when
+    /// empty, and decode its output.
+    fn invoke<O: DeserializeOwned>(&mut self, command: Command, input: Vec<u8>) -> Result<O> {
+        let session = &mut self.session;
+        let output = OutputReader::new(OUTPUT_CAPACITY).read(|buffer| {
+            let p1 = ParamTmpRef::new_output(buffer);
+            if input.is_empty() {
+                let mut operation = Operation::new(0, ParamNone, p1, ParamNone, ParamNone);
+                let result = session.invoke_command(command.into(), &mut operation);
+                (result, operation.parameters().1.updated_size())
+            } else {
+                let p0 = ParamTmpRef::new_input(&input);
+                let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+                let result = session.invoke_command(command.into(), &mut operation);
+                (result, operation.parameters().1.updated_size())
+            }
+        })?;
+        serde_json::from_slice(&output).map_err(|e| {
+            eprintln!("Failed to deserialize output: {}", e);
+            ErrorKind::BadFormat.into()
+        })
+    }
+}
+
+fn encode<I: Serialize>(input: &I) -> Result<Vec<u8>> {
+    serde_json::to_vec(input).map_err(|e| {
+        eprintln!("Failed to serialize input: {}", e);
+        ErrorKind::BadParameters.into()
+    })
+}
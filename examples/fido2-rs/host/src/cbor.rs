@@ -0,0 +1,249 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The subset of CBOR used by CTAP2: integers, byte and text strings,
+//! arrays, maps and simple booleans, of definite lengths.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    /// The entries in the order they are encoded, which CTAP2 requires to
+    /// be the canonical one.
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DecodeError;
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid or unsupported CBOR")
+    }
+}
+
+/// Nesting allowed in the decoded values, as CTAP2 does.
+const MAX_DEPTH: usize = 4;
+
+impl Value {
+    /// Decode the single value of `data`.
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader { data, depth: 0 };
+        let value = reader.value()?;
+        if !reader.data.is_empty() {
+            return Err(DecodeError);
+        }
+        Ok(value)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Integer(n) if *n >= 0 => header(out, 0, *n as u64),
+            Value::Integer(n) => header(out, 1, !*n as u64),
+            Value::Bytes(b) => {
+                header(out, 2, b.len() as u64);
+                out.extend_from_slice(b);
+            }
+            Value::Text(s) => {
+                header(out, 3, s.len() as u64);
+                out.extend_from_slice(s.as_bytes());
+            }
+            Value::Array(items) => {
+                header(out, 4, items.len() as u64);
+                items.iter().for_each(|item| item.encode_into(out));
+            }
+            Value::Map(entries) => {
+                header(out, 5, entries.len() as u64);
+                for (key, value) in entries {
+                    key.encode_into(out);
+                    value.encode_into(out);
+                }
+            }
+            Value::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+        }
+    }
+
+    /// The value of the entry with the integer key `key` of a map.
+    pub fn get(&self, key: i64) -> Option<&Value> {
+        self.get_by(&Value::Integer(key))
+    }
+
+    /// The value of the entry with the text key `key` of a map.
+    pub fn get_text(&self, key: &str) -> Option<&Value> {
+        self.get_by(&Value::Text(key.to_string()))
+    }
+
+    fn get_by(&self, key: &Value) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+fn header(out: &mut Vec<u8>, major: u8, argument: u64) {
+    let major = major << 5;
+    match argument {
+        0..=23 => out.push(major | argument as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, argument as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(argument as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(argument as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&argument.to_be_bytes());
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.data.len() {
+            return Err(DecodeError);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn argument(&mut self, info: u8) -> Result<u64, DecodeError> {
+        let size = match info {
+            0..=23 => return Ok(info as u64),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            // Indefinite lengths are not allowed by CTAP2
+            _ => return Err(DecodeError),
+        };
+        let mut bytes = [0u8; 8];
+        bytes[8 - size..].copy_from_slice(self.take(size)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn length(&mut self, info: u8) -> Result<usize, DecodeError> {
+        let length = self.argument(info)?;
+        // Every item takes at least a byte, which bounds the allocations
+        if length > self.data.len() as u64 {
+            return Err(DecodeError);
+        }
+        Ok(length as usize)
+    }
+
+    fn value(&mut self) -> Result<Value, DecodeError> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        Ok(match major {
+            0 => Value::Integer(i64::try_from(self.argument(info)?).map_err(|_| DecodeError)?),
+            1 => {
+                let n = i64::try_from(self.argument(info)?).map_err(|_| DecodeError)?;
+                Value::Integer(-1 - n)
+            }
+            2 => {
+                let length = self.length(info)?;
+                Value::Bytes(self.take(length)?.to_vec())
+            }
+            3 => {
+                let length = self.length(info)?;
+                let text = std::str::from_utf8(self.take(length)?).map_err(|_| DecodeError)?;
+                Value::Text(text.to_string())
+            }
+            4 | 5 => {
+                let length = self.length(info)?;
+                self.depth += 1;
+                if self.depth > MAX_DEPTH {
+                    return Err(DecodeError);
+                }
+                let value = if major == 4 {
+                    Value::Array(
+                        (0..length)
+                            .map(|_| self.value())
+                            .collect::<Result<_, _>>()?,
+                    )
+                } else {
+                    Value::Map(
+                        (0..length)
+                            .map(|_| Ok((self.value()?, self.value()?)))
+                            .collect::<Result<_, _>>()?,
+                    )
+                };
+                self.depth -= 1;
+                value
+            }
+            7 if info == 20 => Value::Bool(false),
+            7 if info == 21 => Value::Bool(true),
+            _ => return Err(DecodeError),
+        })
+    }
+}
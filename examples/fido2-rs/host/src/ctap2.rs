@@ -0,0 +1,241 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The authenticatorMakeCredential, authenticatorGetAssertion,
+//! authenticatorGetInfo and authenticatorReset commands of CTAP 2.0, decoded
+//! from CBOR and run by the TA.
+//!
+//! User presence is asserted on every request, as there is no button on the
+//! TA to test it with, and user verification is not supported.
+
+use crate::authenticator::Authenticator;
+use crate::cbor::Value;
+use optee_teec::ErrorKind;
+use proto::{GetAssertionInput, MakeCredentialInput, AAGUID, COSE_ALG_ES256};
+use std::convert::TryInto;
+
+const MAKE_CREDENTIAL: u8 = 0x01;
+const GET_ASSERTION: u8 = 0x02;
+const GET_INFO: u8 = 0x04;
+const RESET: u8 = 0x07;
+
+const OK: u8 = 0x00;
+const ERR_INVALID_COMMAND: u8 = 0x01;
+const ERR_INVALID_CBOR: u8 = 0x12;
+const ERR_MISSING_PARAMETER: u8 = 0x14;
+const ERR_CREDENTIAL_EXCLUDED: u8 = 0x19;
+const ERR_UNSUPPORTED_ALGORITHM: u8 = 0x26;
+const ERR_KEY_STORE_FULL: u8 = 0x28;
+const ERR_UNSUPPORTED_OPTION: u8 = 0x2B;
+const ERR_NO_CREDENTIALS: u8 = 0x2E;
+const ERR_OTHER: u8 = 0x7F;
+
+/// TEE_ERROR_STORAGE_NO_SPACE, returned by the TA when full.
+const TEE_ERROR_STORAGE_NO_SPACE: u32 = 0xFFFF_3041;
+
+/// Run the CTAP2 request `request`, a command byte followed by its CBOR
+/// parameters, and return the response, a status byte followed by the CBOR
+/// response on success.
+pub fn process(authenticator: &mut Authenticator, request: &[u8]) -> Vec<u8> {
+    let result = match request.split_first() {
+        Some((&MAKE_CREDENTIAL, params)) => {
+            decode(params).and_then(|params| make_credential(authenticator, &params))
+        }
+        Some((&GET_ASSERTION, params)) => {
+            decode(params).and_then(|params| get_assertion(authenticator, &params))
+        }
+        Some((&GET_INFO, _)) => get_info(authenticator),
+        Some((&RESET, _)) => reset(authenticator),
+        _ => Err(ERR_INVALID_COMMAND),
+    };
+    match result {
+        Ok(Some(response)) => {
+            let mut out = vec![OK];
+            out.extend_from_slice(&response.encode());
+            out
+        }
+        Ok(None) => vec![OK],
+        Err(status) => vec![status],
+    }
+}
+
+type Response = Result<Option<Value>, u8>;
+
+fn decode(params: &[u8]) -> Result<Value, u8> {
+    match Value::decode(params) {
+        Ok(value @ Value::Map(_)) => Ok(value),
+        _ => Err(ERR_INVALID_CBOR),
+    }
+}
+
+fn make_credential(authenticator: &mut Authenticator, params: &Value) -> Response {
+    let client_data_hash = client_data_hash(params.get(1))?;
+    let rp_id = required(params.get(2).and_then(|rp| rp.get_text("id")?.as_text()))?;
+    let user = required(params.get(3))?;
+    let user_id = required(user.get_text("id").and_then(Value::as_bytes))?;
+    let user_name = user
+        .get_text("name")
+        .and_then(Value::as_text)
+        .unwrap_or_default();
+    let algorithms = required(params.get(4).and_then(Value::as_array))?;
+    if !algorithms
+        .iter()
+        .any(|p| p.get_text("alg").and_then(Value::as_integer) == Some(COSE_ALG_ES256))
+    {
+        return Err(ERR_UNSUPPORTED_ALGORITHM);
+    }
+    check_options(params.get(7))?;
+
+    let input = MakeCredentialInput {
+        client_data_hash,
+        rp_id: rp_id.to_string(),
+        user_id: user_id.to_vec(),
+        user_name: user_name.to_string(),
+        exclude_list: credential_ids(params.get(5)),
+    };
+    let output = authenticator.make_credential(&input).map_err(status)?;
+    Ok(Some(Value::Map(vec![
+        (Value::Integer(1), Value::Text("packed".to_string())),
+        (Value::Integer(2), Value::Bytes(output.auth_data)),
+        (
+            Value::Integer(3),
+            Value::Map(vec![
+                (
+                    Value::Text("alg".to_string()),
+                    Value::Integer(COSE_ALG_ES256),
+                ),
+                (
+                    Value::Text("sig".to_string()),
+                    Value::Bytes(output.signature),
+                ),
+            ]),
+        ),
+    ])))
+}
+
+fn get_assertion(authenticator: &mut Authenticator, params: &Value) -> Response {
+    let rp_id = required(params.get(1).and_then(Value::as_text))?;
+    let client_data_hash = client_data_hash(params.get(2))?;
+    check_options(params.get(5))?;
+
+    let input = GetAssertionInput {
+        client_data_hash,
+        rp_id: rp_id.to_string(),
+        allow_list: credential_ids(params.get(3)),
+    };
+    let output = authenticator.get_assertion(&input).map_err(status)?;
+    let mut user = vec![(Value::Text("id".to_string()), Value::Bytes(output.user_id))];
+    if !output.user_name.is_empty() {
+        user.push((
+            Value::Text("name".to_string()),
+            Value::Text(output.user_name),
+        ));
+    }
+    Ok(Some(Value::Map(vec![
+        (
+            Value::Integer(1),
+            public_key_credential(output.credential_id),
+        ),
+        (Value::Integer(2), Value::Bytes(output.auth_data)),
+        (Value::Integer(3), Value::Bytes(output.signature)),
+        (Value::Integer(4), Value::Map(user)),
+        (Value::Integer(5), Value::Integer(output.credentials as i64)),
+    ])))
+}
+
+fn get_info(authenticator: &mut Authenticator) -> Response {
+    let info = authenticator.get_info().map_err(status)?;
+    Ok(Some(Value::Map(vec![
+        (
+            Value::Integer(1),
+            Value::Array(vec![Value::Text("FIDO_2_0".to_string())]),
+        ),
+        (Value::Integer(3), Value::Bytes(AAGUID.to_vec())),
+        (
+            Value::Integer(4),
+            Value::Map(vec![
+                (Value::Text("rk".to_string()), Value::Bool(true)),
+                (Value::Text("up".to_string()), Value::Bool(true)),
+                (Value::Text("plat".to_string()), Value::Bool(false)),
+            ]),
+        ),
+        (
+            Value::Integer(5),
+            Value::Integer(info.max_credentials as i64),
+        ),
+    ])))
+}
+
+fn reset(authenticator: &mut Authenticator) -> Response {
+    authenticator.reset().map_err(status)?;
+    Ok(None)
+}
+
+fn required<T>(value: Option<T>) -> Result<T, u8> {
+    value.ok_or(ERR_MISSING_PARAMETER)
+}
+
+fn client_data_hash(value: Option<&Value>) -> Result<[u8; 32], u8> {
+    required(value.and_then(Value::as_bytes))?
+        .try_into()
+        .map_err(|_| ERR_INVALID_CBOR)
+}
+
+/// Reject the user verification option, which needs a biometric sensor or a
+/// PIN the authenticator does not have.
+fn check_options(options: Option<&Value>) -> Result<(), u8> {
+    match options.and_then(|o| o.get_text("uv")?.as_bool()) {
+        Some(true) => Err(ERR_UNSUPPORTED_OPTION),
+        _ => Ok(()),
+    }
+}
+
+/// The IDs of a list of PublicKeyCredentialDescriptor.
+fn credential_ids(list: Option<&Value>) -> Vec<Vec<u8>> {
+    list.and_then(Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|descriptor| descriptor.get_text("id")?.as_bytes())
+        .map(<[u8]>::to_vec)
+        .collect()
+}
+
+fn public_key_credential(id: Vec<u8>) -> Value {
+    Value::Map(vec![
+        (Value::Text("id".to_string()), Value::Bytes(id)),
+        (
+            Value::Text("type".to_string()),
+            Value::Text("public-key".to_string()),
+        ),
+    ])
+}
+
+/// The CTAP2 status of an error of the TA.
+fn status(e: optee_teec::Error) -> u8 {
+    if e.raw_code() == TEE_ERROR_STORAGE_NO_SPACE {
+        return ERR_KEY_STORE_FULL;
+    }
+    match e.kind() {
+        ErrorKind::AccessConflict => ERR_CREDENTIAL_EXCLUDED,
+        ErrorKind::ItemNotFound => ERR_NO_CREDENTIALS,
+        ErrorKind::BadParameters => ERR_INVALID_CBOR,
+        _ => {
+            eprintln!("Authenticator TA failed: {}", e);
+            ERR_OTHER
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The CTAPHID framing of CTAP messages into the 64 bytes reports of a USB
+//! HID device, for a single channel transaction at a time.
+
+use crate::authenticator::Authenticator;
+use crate::ctap2;
+
+pub const REPORT_SIZE: usize = 64;
+
+const INIT_PAYLOAD: usize = REPORT_SIZE - 7;
+const CONT_PAYLOAD: usize = REPORT_SIZE - 5;
+/// The largest message, of an init packet and 128 continuation packets.
+const MAX_MESSAGE: usize = INIT_PAYLOAD + 128 * CONT_PAYLOAD;
+
+const BROADCAST_CID: u32 = 0xffff_ffff;
+
+const CMD_PING: u8 = 0x81;
+const CMD_INIT: u8 = 0x86;
+const CMD_WINK: u8 = 0x88;
+const CMD_CBOR: u8 = 0x90;
+const CMD_CANCEL: u8 = 0x91;
+const CMD_ERROR: u8 = 0xbf;
+
+const ERR_INVALID_CMD: u8 = 0x01;
+const ERR_INVALID_LEN: u8 = 0x03;
+const ERR_INVALID_SEQ: u8 = 0x04;
+const ERR_CHANNEL_BUSY: u8 = 0x06;
+const ERR_INVALID_CHANNEL: u8 = 0x0b;
+
+const CAPABILITY_WINK: u8 = 0x01;
+const CAPABILITY_CBOR: u8 = 0x04;
+/// No CTAP1/U2F messages.
+const CAPABILITY_NMSG: u8 = 0x08;
+
+/// A message being received.
+struct Transaction {
+    cid: u32,
+    cmd: u8,
+    length: usize,
+    data: Vec<u8>,
+    seq: u8,
+}
+
+pub struct Device {
+    authenticator: Authenticator,
+    next_cid: u32,
+    transaction: Option<Transaction>,
+}
+
+impl Device {
+    pub fn new(authenticator: Authenticator) -> Self {
+        Self {
+            authenticator,
+            next_cid: 1,
+            transaction: None,
+        }
+    }
+
+    /// Handle the output report `report` of the host, returning the input
+    /// reports of the response once a message is complete.
+    pub fn handle_report(&mut self, report: &[u8; REPORT_SIZE]) -> Vec<[u8; REPORT_SIZE]> {
+        let cid = u32::from_be_bytes([report[0], report[1], report[2], report[3]]);
+        if cid == 0 {
+            return error(cid, ERR_INVALID_CHANNEL);
+        }
+        let byte = report[4];
+        if byte & 0x80 == 0 {
+            return self.continuation(cid, byte, &report[5..]);
+        }
+
+        let length = u16::from_be_bytes([report[5], report[6]]) as usize;
+        match &self.transaction {
+            // Only INIT may interrupt a transaction of another channel
+            Some(t) if t.cid != cid && byte != CMD_INIT => {
+                return error(cid, ERR_CHANNEL_BUSY);
+            }
+            // CANCEL has no response, and nothing runs in the background to
+            // cancel
+            _ if byte == CMD_CANCEL => return Vec::new(),
+            _ => {}
+        }
+        if length > MAX_MESSAGE {
+            self.transaction = None;
+            return error(cid, ERR_INVALID_LEN);
+        }
+        let data = report[7..7 + length.min(INIT_PAYLOAD)].to_vec();
+        self.transaction = Some(Transaction {
+            cid,
+            cmd: byte,
+            length,
+            data,
+            seq: 0,
+        });
+        self.complete()
+    }
+
+    fn continuation(&mut self, cid: u32, seq: u8, payload: &[u8]) -> Vec<[u8; REPORT_SIZE]> {
+        let t = match &mut self.transaction {
+            Some(t) if t.cid == cid => t,
+            // Spurious continuation packets are ignored
+            None => return Vec::new(),
+            Some(_) => return error(cid, ERR_CHANNEL_BUSY),
+        };
+        if seq != t.seq {
+            self.transaction = None;
+            return error(cid, ERR_INVALID_SEQ);
+        }
+        t.seq += 1;
+        let remaining = t.length - t.data.len();
+        t.data
+            .extend_from_slice(&payload[..remaining.min(CONT_PAYLOAD)]);
+        self.complete()
+    }
+
+    /// Run the current transaction if all of its message is received.
+    fn complete(&mut self) -> Vec<[u8; REPORT_SIZE]> {
+        let Transaction { cid, cmd, data, .. } = match self.transaction.take() {
+            Some(t) if t.data.len() == t.length => t,
+            t => {
+                self.transaction = t;
+                return Vec::new();
+            }
+        };
+        match cmd {
+            CMD_INIT => self.init(cid, &data),
+            _ if cid == BROADCAST_CID => error(cid, ERR_INVALID_CHANNEL),
+            CMD_PING => fragment(cid, CMD_PING, &data),
+            CMD_WINK => fragment(cid, CMD_WINK, &[]),
+            CMD_CBOR => {
+                let response = ctap2::process(&mut self.authenticator, &data);
+                fragment(cid, CMD_CBOR, &response)
+            }
+            // CMD_MSG included, as CTAP1 is not supported
+            _ => error(cid, ERR_INVALID_CMD),
+        }
+    }
+
+    /// Allocate a channel, or resynchronize one.
+    fn init(&mut self, cid: u32, nonce: &[u8]) -> Vec<[u8; REPORT_SIZE]> {
+        if nonce.len() != 8 {
+            return error(cid, ERR_INVALID_LEN);
+        }
+        let new_cid = if cid == BROADCAST_CID {
+            let new_cid = self.next_cid;
+            self.next_cid = self.next_cid % (BROADCAST_CID - 1) + 1;
+            new_cid
+        } else {
+            cid
+        };
+        let mut response = nonce.to_vec();
+        response.extend_from_slice(&new_cid.to_be_bytes());
+        // CTAPHID protocol version, then the version of the device
+        response.extend_from_slice(&[2, 0, 1, 0]);
+        response.push(CAPABILITY_WINK | CAPABILITY_CBOR | CAPABILITY_NMSG);
+        fragment(cid, CMD_INIT, &response)
+    }
+}
+
+fn error(cid: u32, code: u8) -> Vec<[u8; REPORT_SIZE]> {
+    fragment(cid, CMD_ERROR, &[code])
+}
+
+/// Split the message `data` into an init packet and continuation packets.
+fn fragment(cid: u32, cmd: u8, data: &[u8]) -> Vec<[u8; REPORT_SIZE]> {
+    let mut packet = [0u8; REPORT_SIZE];
+    packet[..4].copy_from_slice(&cid.to_be_bytes());
+    packet[4] = cmd;
+    packet[5..7].copy_from_slice(&(data.len() as u16).to_be_bytes());
+    let (head, mut tail) = data.split_at(data.len().min(INIT_PAYLOAD));
+    packet[7..7 + head.len()].copy_from_slice(head);
+    let mut packets = vec![packet];
+
+    let mut seq = 0;
+    while !tail.is_empty() {
+        let (chunk, rest) = tail.split_at(tail.len().min(CONT_PAYLOAD));
+        let mut packet = [0u8; REPORT_SIZE];
+        packet[..4].copy_from_slice(&cid.to_be_bytes());
+        packet[4] = seq;
+        packet[5..5 + chunk.len()].copy_from_slice(chunk);
+        packets.push(packet);
+        tail = rest;
+        seq += 1;
+    }
+    packets
+}
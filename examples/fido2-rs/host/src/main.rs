@@ -0,0 +1,177 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+mod authenticator;
+mod cbor;
+mod ctap2;
+mod ctaphid;
+mod uhid;
+
+use authenticator::Authenticator;
+use cbor::Value;
+use optee_teec::{Context, ErrorKind};
+use sha2::{Digest, Sha256};
+
+const RP_ID: &str = "example.com";
+
+/// Run a CTAP2 request as a client would, returning the CBOR response.
+fn request(
+    authenticator: &mut Authenticator,
+    command: u8,
+    params: Value,
+) -> optee_teec::Result<Value> {
+    let mut request = vec![command];
+    request.extend_from_slice(&params.encode());
+    let response = ctap2::process(authenticator, &request);
+    match response.split_first() {
+        Some((0, body)) => Value::decode(body).map_err(|e| {
+            eprintln!("Invalid response: {}", e);
+            ErrorKind::BadFormat.into()
+        }),
+        _ => {
+            eprintln!("CTAP2 command {:#04x} failed: {:02x?}", command, response);
+            Err(ErrorKind::Generic.into())
+        }
+    }
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+fn client_data_hash(kind: &str, challenge: &str) -> Value {
+    let client_data = format!(
+        r#"{{"type":"{}","challenge":"{}","origin":"https://{}"}}"#,
+        kind, challenge, RP_ID
+    );
+    Value::Bytes(Sha256::digest(client_data.as_bytes()).to_vec())
+}
+
+/// The signature counter of authenticator data.
+fn sign_count(auth_data: &[u8]) -> u32 {
+    u32::from_be_bytes([auth_data[33], auth_data[34], auth_data[35], auth_data[36]])
+}
+
+/// Register a credential and log in with it twice, as a relying party would.
+fn demo(authenticator: &mut Authenticator) -> optee_teec::Result<()> {
+    let removed = authenticator.reset()?.removed;
+    println!("Reset the authenticator, removed {} credentials", removed);
+
+    let info = authenticator.get_info()?;
+    println!(
+        "Authenticator with {} of {} credentials, rollback protected: {}",
+        info.credentials, info.max_credentials, info.rollback_protected
+    );
+
+    let params = Value::Map(vec![
+        (
+            Value::Integer(1),
+            client_data_hash("webauthn.create", "register"),
+        ),
+        (
+            Value::Integer(2),
+            Value::Map(vec![(text("id"), text(RP_ID))]),
+        ),
+        (
+            Value::Integer(3),
+            Value::Map(vec![
+                (text("id"), Value::Bytes(b"user-1".to_vec())),
+                (text("name"), text("alice")),
+            ]),
+        ),
+        (
+            Value::Integer(4),
+            Value::Array(vec![Value::Map(vec![
+                (text("alg"), Value::Integer(proto::COSE_ALG_ES256)),
+                (text("type"), text("public-key")),
+            ])]),
+        ),
+    ]);
+    let response = request(authenticator, 0x01, params)?;
+    let auth_data = response
+        .get(2)
+        .and_then(Value::as_bytes)
+        .ok_or(ErrorKind::BadFormat)?;
+    // rpIdHash, flags, signCount, AAGUID, then the length of the ID
+    let id_len = u16::from_be_bytes([auth_data[53], auth_data[54]]) as usize;
+    let credential_id = auth_data[55..55 + id_len].to_vec();
+    println!("Registered credential {:02x?}", credential_id);
+
+    let mut last_count = sign_count(auth_data);
+    for challenge in ["login-1", "login-2"] {
+        let params = Value::Map(vec![
+            (Value::Integer(1), text(RP_ID)),
+            (
+                Value::Integer(2),
+                client_data_hash("webauthn.get", challenge),
+            ),
+            (
+                Value::Integer(3),
+                Value::Array(vec![Value::Map(vec![
+                    (text("id"), Value::Bytes(credential_id.clone())),
+                    (text("type"), text("public-key")),
+                ])]),
+            ),
+        ]);
+        let response = request(authenticator, 0x02, params)?;
+        let id = response
+            .get(1)
+            .and_then(|c| c.get_text("id")?.as_bytes())
+            .ok_or(ErrorKind::BadFormat)?;
+        let count = response
+            .get(2)
+            .and_then(Value::as_bytes)
+            .map(sign_count)
+            .ok_or(ErrorKind::BadFormat)?;
+        if id != credential_id.as_slice() || count <= last_count {
+            eprintln!("Unexpected assertion of {:02x?} with count {}", id, count);
+            return Err(ErrorKind::Generic.into());
+        }
+        println!("Got assertion with signature count {}", count);
+        last_count = count;
+    }
+    Ok(())
+}
+
+fn main() -> optee_teec::Result<()> {
+    let mut ctx = Context::new()?;
+    let mut authenticator = Authenticator::open(&mut ctx)?;
+
+    match std::env::args().nth(1).as_deref() {
+        None => {
+            demo(&mut authenticator)?;
+            println!("Success");
+        }
+        Some("hid") => {
+            let mut uhid = uhid::Uhid::create("OP-TEE FIDO2 authenticator").map_err(|e| {
+                eprintln!("Failed to create the UHID device: {}", e);
+                ErrorKind::AccessDenied
+            })?;
+            println!("Authenticator attached, press Ctrl-C to detach");
+            let mut device = ctaphid::Device::new(authenticator);
+            if let Err(e) = uhid.run(&mut device) {
+                eprintln!("UHID device failed: {}", e);
+                return Err(ErrorKind::Communication.into());
+            }
+        }
+        Some(_) => {
+            println!("Usage: fido2-rs [hid]");
+            return Err(ErrorKind::BadParameters.into());
+        }
+    }
+    Ok(())
+}
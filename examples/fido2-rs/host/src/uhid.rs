@@ -0,0 +1,111 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! A virtual USB HID device created with the UHID interface of Linux, so
+//! browsers and libfido2 find the authenticator like a security key.
+
+use crate::ctaphid::{Device, REPORT_SIZE};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+
+/// The size of `struct uhid_event`.
+const EVENT_SIZE: usize = 4376;
+
+const UHID_OUTPUT: u32 = 6;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+
+const BUS_USB: u16 = 0x03;
+
+/// The descriptor of a FIDO device, with 64 bytes input and output reports.
+const REPORT_DESCRIPTOR: [u8; 34] = [
+    0x06, 0xd0, 0xf1, // Usage Page (FIDO Alliance)
+    0x09, 0x01, // Usage (CTAPHID)
+    0xa1, 0x01, // Collection (Application)
+    0x09, 0x20, //   Usage (Input Report Data)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x40, //   Report Count (64)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x09, 0x21, //   Usage (Output Report Data)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x40, //   Report Count (64)
+    0x91, 0x02, //   Output (Data, Variable, Absolute)
+    0xc0, // End Collection
+];
+
+pub struct Uhid {
+    file: File,
+}
+
+impl Uhid {
+    /// Create the device, which is destroyed when `/dev/uhid` is closed.
+    pub fn create(name: &str) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/uhid")?;
+        let mut event = [0u8; EVENT_SIZE];
+        event[..4].copy_from_slice(&UHID_CREATE2.to_ne_bytes());
+        let name = name.as_bytes();
+        event[4..4 + name.len().min(127)].copy_from_slice(&name[..name.len().min(127)]);
+        event[260..262].copy_from_slice(&(REPORT_DESCRIPTOR.len() as u16).to_ne_bytes());
+        event[262..264].copy_from_slice(&BUS_USB.to_ne_bytes());
+        // The vendor and product IDs of the examples of the Linux kernel
+        event[264..268].copy_from_slice(&0x15d9u32.to_ne_bytes());
+        event[268..272].copy_from_slice(&0x0a37u32.to_ne_bytes());
+        event[280..280 + REPORT_DESCRIPTOR.len()].copy_from_slice(&REPORT_DESCRIPTOR);
+        file.write_all(&event)?;
+        Ok(Self { file })
+    }
+
+    /// Forward the output reports to `device` and its responses back, until
+    /// `/dev/uhid` fails.
+    pub fn run(&mut self, device: &mut Device) -> io::Result<()> {
+        let mut event = [0u8; EVENT_SIZE];
+        loop {
+            self.file.read_exact(&mut event)?;
+            if u32::from_ne_bytes([event[0], event[1], event[2], event[3]]) != UHID_OUTPUT {
+                continue;
+            }
+            let size = u16::from_ne_bytes([event[4100], event[4101]]) as usize;
+            // Without report IDs, the report starts after a zero report
+            // number
+            let data = match size {
+                REPORT_SIZE => &event[4..4 + REPORT_SIZE],
+                s if s == REPORT_SIZE + 1 => &event[5..5 + REPORT_SIZE],
+                _ => continue,
+            };
+            let mut report = [0u8; REPORT_SIZE];
+            report.copy_from_slice(data);
+            for response in device.handle_report(&report) {
+                self.input(&response)?;
+            }
+        }
+    }
+
+    fn input(&mut self, report: &[u8; REPORT_SIZE]) -> io::Result<()> {
+        let mut event = [0u8; EVENT_SIZE];
+        event[..4].copy_from_slice(&UHID_INPUT2.to_ne_bytes());
+        event[4..6].copy_from_slice(&(REPORT_SIZE as u16).to_ne_bytes());
+        event[6..6 + REPORT_SIZE].copy_from_slice(report);
+        self.file.write_all(&event)
+    }
+}
@@ -0,0 +1,116 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use num_enum::{FromPrimitive, IntoPrimitive};
+use serde::{Deserialize, Serialize};
+
+#[derive(FromPrimitive, IntoPrimitive, Debug, Copy, Clone)]
+#[repr(u32)]
+pub enum Command {
+    GetInfo,
+    MakeCredential,
+    GetAssertion,
+    Reset,
+    #[default]
+    Unknown,
+}
+
+/// The AAGUID identifying the model of the authenticator in its attested
+/// credential data.
+pub const AAGUID: [u8; 16] = [
+    0x8a, 0x5c, 0x3e, 0x61, 0x2b, 0x0d, 0x4f, 0x97, 0x9e, 0x13, 0x6c, 0x51, 0xd4, 0x7a, 0x20, 0xf8,
+];
+
+/// The number of credentials the TA keeps at most.
+pub const MAX_CREDENTIALS: u32 = 32;
+
+/// The length of the random credential IDs.
+pub const CREDENTIAL_ID_LEN: usize = 16;
+
+/// COSE algorithm identifier of ES256, ECDSA on P-256 with SHA-256, the only
+/// algorithm of the credentials.
+pub const COSE_ALG_ES256: i64 = -7;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InfoOutput {
+    pub aaguid: [u8; 16],
+    pub credentials: u32,
+    pub max_credentials: u32,
+    /// Whether the signature counter is kept in RPMB, so the normal world
+    /// cannot roll it back.
+    pub rollback_protected: bool,
+}
+
+/// authenticatorMakeCredential, with the client data already hashed.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MakeCredentialInput {
+    pub client_data_hash: [u8; 32],
+    pub rp_id: String,
+    pub user_id: Vec<u8>,
+    pub user_name: String,
+    /// IDs of credentials the user already has at the relying party, none
+    /// of which may be on this authenticator.
+    pub exclude_list: Vec<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MakeCredentialOutput {
+    pub credential_id: Vec<u8>,
+    /// The authenticator data with the attested credential data.
+    pub auth_data: Vec<u8>,
+    /// The DER signature of `auth_data || client_data_hash` with the key of
+    /// the credential, a `packed` self attestation.
+    pub signature: Vec<u8>,
+}
+
+/// authenticatorGetAssertion, with the client data already hashed.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetAssertionInput {
+    pub client_data_hash: [u8; 32],
+    pub rp_id: String,
+    /// IDs of the credentials the relying party accepts, or empty to use
+    /// any credential of the relying party.
+    pub allow_list: Vec<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetAssertionOutput {
+    pub credential_id: Vec<u8>,
+    pub auth_data: Vec<u8>,
+    /// The DER signature of `auth_data || client_data_hash`.
+    pub signature: Vec<u8>,
+    pub user_id: Vec<u8>,
+    pub user_name: String,
+    /// The number of credentials of the relying party matching the request.
+    pub credentials: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResetOutput {
+    pub removed: u32,
+}
+
+// If Uuid::parse_str() returns an InvalidLength error, there may be an extra
+// newline in your uuid.txt file. You can remove it by running
+// `truncate -s 36 uuid.txt`.
+pub const UUID: &str = &include_str!("../../uuid.txt");
@@ -0,0 +1,288 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use optee_utee::crypto::{Digest, EcdsaP256};
+use optee_utee::dispatch::Json;
+use optee_utee::kv::SecureKvStore;
+use optee_utee::prelude::*;
+use optee_utee::rollback::MonotonicCounter;
+use optee_utee::secure_mem::Zeroize;
+use optee_utee::storage::Quota;
+use optee_utee::{ErrorKind, Random, Result, UsageFlag};
+use proto::{
+    GetAssertionInput, GetAssertionOutput, InfoOutput, MakeCredentialInput, MakeCredentialOutput,
+    ResetOutput, AAGUID, CREDENTIAL_ID_LEN, MAX_CREDENTIALS,
+};
+use serde::{Deserialize, Serialize};
+
+/// Namespace of the store of the credentials.
+const CREDENTIALS: &[u8] = b"fido2/credentials";
+/// Name of the signature counter, shared by all the credentials.
+const SIGN_COUNT: &[u8] = b"fido2/sign_count";
+
+// Flags of the authenticator data
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+/// A discoverable credential, stored under its ID in hex.
+#[derive(Serialize, Deserialize)]
+struct Credential {
+    rp_id: String,
+    user_id: Vec<u8>,
+    user_name: String,
+    /// SEC 1 uncompressed point.
+    public_key: Vec<u8>,
+    private_key: Vec<u8>,
+}
+
+impl Credential {
+    /// The key pair of the credential, in a TEE object which can only sign.
+    fn key_pair(&self) -> Result<EcdsaP256> {
+        let mut key_pair = EcdsaP256::import_keypair(&self.private_key, &self.public_key)?;
+        key_pair.restrict_usage(UsageFlag::SIGN)?;
+        Ok(key_pair)
+    }
+}
+
+impl Drop for Credential {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
+// The command IDs are shared with the host through `proto::Command`.
+#[derive(TaCommand)]
+#[ta_command(codec = Json)]
+enum Command {
+    #[ta_command(id = proto::Command::GetInfo, output = InfoOutput)]
+    GetInfo,
+    #[ta_command(id = proto::Command::MakeCredential, output = MakeCredentialOutput)]
+    MakeCredential(MakeCredentialInput),
+    #[ta_command(id = proto::Command::GetAssertion, output = GetAssertionOutput)]
+    GetAssertion(GetAssertionInput),
+    #[ta_command(id = proto::Command::Reset, output = ResetOutput)]
+    Reset,
+}
+
+#[derive(Default)]
+struct Handler;
+
+#[ta_dispatch(stateless)]
+impl CommandHandler for Handler {
+    fn get_info(&mut self) -> Result<InfoOutput> {
+        Ok(InfoOutput {
+            aaguid: AAGUID,
+            credentials: credentials()?.keys()?.len() as u32,
+            max_credentials: MAX_CREDENTIALS,
+            rollback_protected: MonotonicCounter::open(SIGN_COUNT)?.is_rollback_protected(),
+        })
+    }
+
+    fn make_credential(&mut self, input: MakeCredentialInput) -> Result<MakeCredentialOutput> {
+        trace_println!("[+] TA make credential for {}", input.rp_id);
+        let store = credentials()?;
+        for id in &input.exclude_list {
+            if let Some(credential) = find(&store, id, &input.rp_id)? {
+                trace_println!("[+] user {} already registered", credential.user_name);
+                return Err(ErrorKind::AccessConflict.into());
+            }
+        }
+
+        let key_pair = EcdsaP256::generate()?;
+        let public_key = key_pair.public_key()?;
+        let mut credential_id = [0u8; CREDENTIAL_ID_LEN];
+        Random::generate(&mut credential_id);
+        let credential = Credential {
+            rp_id: input.rp_id.clone(),
+            user_id: input.user_id,
+            user_name: input.user_name,
+            public_key: public_key.to_vec(),
+            private_key: key_pair.private_key()?.to_vec(),
+        };
+        // Fails with `StorageNoSpace` once there are `MAX_CREDENTIALS`
+        store.put(&hex::encode(credential_id), &credential)?;
+
+        let flags = FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA;
+        let mut auth_data = auth_data(&input.rp_id, flags)?;
+        auth_data.extend_from_slice(&AAGUID);
+        auth_data.extend_from_slice(&(CREDENTIAL_ID_LEN as u16).to_be_bytes());
+        auth_data.extend_from_slice(&credential_id);
+        auth_data.extend_from_slice(&cose_key(&public_key));
+        // Self attestation: signed with the key of the credential itself
+        let signature = sign(&key_pair, &auth_data, &input.client_data_hash)?;
+        Ok(MakeCredentialOutput {
+            credential_id: credential_id.to_vec(),
+            auth_data,
+            signature,
+        })
+    }
+
+    fn get_assertion(&mut self, input: GetAssertionInput) -> Result<GetAssertionOutput> {
+        trace_println!("[+] TA get assertion for {}", input.rp_id);
+        let store = credentials()?;
+        let mut matching = Vec::new();
+        if input.allow_list.is_empty() {
+            for entry in store.iter()? {
+                let (id, credential, _) = entry?;
+                if credential.rp_id == input.rp_id {
+                    matching.push((id, credential));
+                }
+            }
+        } else {
+            for id in &input.allow_list {
+                if let Some(credential) = find(&store, id, &input.rp_id)? {
+                    matching.push((hex::encode(id), credential));
+                }
+            }
+        }
+        let count = matching.len() as u32;
+        let (id, credential) = matching.into_iter().next().ok_or(ErrorKind::ItemNotFound)?;
+
+        let auth_data = auth_data(&input.rp_id, FLAG_USER_PRESENT)?;
+        let signature = sign(&credential.key_pair()?, &auth_data, &input.client_data_hash)?;
+        Ok(GetAssertionOutput {
+            credential_id: hex::decode(id).map_err(|_| ErrorKind::CorruptObject)?,
+            auth_data,
+            signature,
+            user_id: credential.user_id.clone(),
+            user_name: credential.user_name.clone(),
+            credentials: count,
+        })
+    }
+
+    fn reset(&mut self) -> Result<ResetOutput> {
+        trace_println!("[+] TA reset");
+        // The signature counter is kept, it must never go backwards
+        let removed = credentials()?.clear()?;
+        Ok(ResetOutput {
+            removed: removed as u32,
+        })
+    }
+}
+
+fn credentials() -> Result<SecureKvStore<String, Credential>> {
+    let quota = Quota::UNLIMITED.max_objects(MAX_CREDENTIALS as usize);
+    Ok(SecureKvStore::open(CREDENTIALS)?.with_quota(quota))
+}
+
+/// The credential `id` if it belongs to `rp_id`. IDs of other lengths come
+/// from other authenticators.
+fn find(
+    store: &SecureKvStore<String, Credential>,
+    id: &[u8],
+    rp_id: &str,
+) -> Result<Option<Credential>> {
+    if id.len() != CREDENTIAL_ID_LEN {
+        return Ok(None);
+    }
+    Ok(store
+        .get(&hex::encode(id))?
+        .filter(|credential| credential.rp_id == rp_id))
+}
+
+/// The authenticator data without extensions: the SHA-256 of the RP ID, the
+/// flags and the signature counter, incremented for every signature.
+fn auth_data(rp_id: &str, flags: u8) -> Result<Vec<u8>> {
+    // In RPMB when available, so that a signature counter rolled back by the
+    // normal world does not let clones of the credentials go unnoticed
+    let sign_count = MonotonicCounter::open(SIGN_COUNT)?.increment()?;
+    let mut data = Vec::with_capacity(37);
+    data.extend_from_slice(&sha256(&[rp_id.as_bytes()])?);
+    data.push(flags);
+    data.extend_from_slice(&(sign_count as u32).to_be_bytes());
+    Ok(data)
+}
+
+/// The COSE_Key of the uncompressed point `public_key`:
+/// `{1: 2 (EC2), 3: -7 (ES256), -1: 1 (P-256), -2: x, -3: y}`.
+fn cose_key(public_key: &[u8; 65]) -> Vec<u8> {
+    let mut key = vec![0xa5, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01, 0x21, 0x58, 0x20];
+    key.extend_from_slice(&public_key[1..33]);
+    key.extend_from_slice(&[0x22, 0x58, 0x20]);
+    key.extend_from_slice(&public_key[33..]);
+    key
+}
+
+/// The DER signature of `auth_data || client_data_hash`, as WebAuthn
+/// expects ES256 signatures.
+fn sign(key_pair: &EcdsaP256, auth_data: &[u8], client_data_hash: &[u8; 32]) -> Result<Vec<u8>> {
+    let signature = key_pair.sign_digest(&sha256(&[auth_data, client_data_hash])?)?;
+    let (r, s) = signature.split_at(32);
+    let mut integers = Vec::with_capacity(70);
+    der_integer(&mut integers, r);
+    der_integer(&mut integers, s);
+    let mut der = vec![0x30, integers.len() as u8];
+    der.extend_from_slice(&integers);
+    Ok(der)
+}
+
+/// Append the DER INTEGER of the unsigned big-endian `value`.
+fn der_integer(der: &mut Vec<u8>, value: &[u8]) {
+    let start = value
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(value.len() - 1);
+    let value = &value[start..];
+    // A leading zero keeps the number positive
+    let padding = value[0] & 0x80 != 0;
+    der.push(0x02);
+    der.push((value.len() + padding as usize) as u8);
+    if padding {
+        der.push(0);
+    }
+    der.extend_from_slice(value);
+}
+
+fn sha256(parts: &[&[u8]]) -> Result<[u8; 32]> {
+    let mut digest = Digest::new()?;
+    for part in parts {
+        digest.update(part);
+    }
+    digest.finalize()
+}
+
+#[ta_create]
+fn create() -> Result<()> {
+    trace_println!("[+] TA create");
+    Ok(())
+}
+
+#[ta_open_session]
+fn open_session(_params: &mut ParametersNone) -> Result<()> {
+    trace_println!("[+] TA open session");
+    Ok(())
+}
+
+#[ta_close_session]
+fn close_session() {
+    trace_println!("[+] TA close session");
+}
+
+#[ta_destroy]
+fn destroy() {
+    trace_println!("[+] TA destroy");
+}
+
+include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));
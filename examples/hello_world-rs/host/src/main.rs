@@ -15,13 +15,11 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use optee_teec::{Context, Operation, ParamType, Session, Uuid};
-use optee_teec::{ParamNone, ParamValue};
+use optee_teec::{Context, Operation, Session, Uuid};
 use proto::{Command, UUID};
 
 fn hello_world(session: &mut Session) -> optee_teec::Result<()> {
-    let p0 = ParamValue::new(29, 0, ParamType::ValueInout);
-    let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
+    let mut operation = Operation::builder().value_inout(29, 0).build();
 
     println!("original value is {:?}", operation.parameters().0.a());
 
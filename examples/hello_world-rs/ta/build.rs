@@ -15,9 +15,9 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use optee_utee_build::{Error, TaConfig};
+use optee_utee_build::Error;
 
+// The TA configuration is declared with `#[ta_config]` in src/main.rs
 fn main() -> Result<(), Error> {
-    let config = TaConfig::new_default_with_cargo_env(proto::UUID)?;
-    optee_utee_build::build(config)
+    optee_utee_build::link()
 }
@@ -22,6 +22,7 @@ use optee_utee::prelude::*;
 use optee_utee::{ErrorKind, Result};
 use proto::Command;
 
+#[ta_config]
 #[ta_create]
 fn create() -> Result<()> {
     trace_println!("[+] TA create");
@@ -68,5 +69,3 @@ fn invoke_command(
         _ => Err(ErrorKind::BadParameters.into()),
     }
 }
-
-include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));
@@ -35,7 +35,7 @@ fn register_shared_key(session: &mut Session) -> optee_teec::Result<()> {
     let p0 = ParamTmpRef::new_input(&k);
     let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
 
-    session.invoke_command(Command::RegisterSharedKey as u32, &mut operation)?;
+    session.invoke_command(Command::RegisterSharedKey.into(), &mut operation)?;
     Ok(())
 }
 
@@ -44,7 +44,7 @@ fn get_hotp(session: &mut Session) -> optee_teec::Result<()> {
     let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
 
     for &expected_value in &RFC4226_TEST_VALUES {
-        session.invoke_command(Command::GetHOTP as u32, &mut operation)?;
+        session.invoke_command(Command::GetHOTP.into(), &mut operation)?;
         let (p0, _, _, _) = operation.parameters();
         let hotp_value = p0.a();
 
@@ -16,15 +16,16 @@
 // under the License.
 
 #![no_std]
-use num_enum::{FromPrimitive, IntoPrimitive};
+use optee_proto::OpteeProtocol;
+pub use optee_proto::Protocol;
 
-#[derive(FromPrimitive, IntoPrimitive)]
+#[derive(OpteeProtocol, Clone, Copy)]
 #[repr(u32)]
 pub enum Command {
-    RegisterSharedKey,
-    GetHOTP,
-    #[default]
-    Unknown,
+    #[protocol(params = "memref_in")]
+    RegisterSharedKey = 0,
+    #[protocol(params = "value_out")]
+    GetHOTP = 1,
 }
 
 // If Uuid::parse_str() returns an InvalidLength error, there may be an extra
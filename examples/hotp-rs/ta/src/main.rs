@@ -20,11 +20,13 @@
 
 extern crate alloc;
 
+use core::convert::TryFrom;
+
 use optee_utee::prelude::*;
 use optee_utee::{AlgorithmId, Mac};
 use optee_utee::{AttributeId, AttributeMemref, TransientObject, TransientObjectType};
 use optee_utee::{ErrorKind, Result};
-use proto::Command;
+use proto::{Command, Protocol};
 
 pub const SHA1_HASH_SIZE: usize = 20;
 pub const MAX_KEY_SIZE: usize = 64;
@@ -76,10 +78,16 @@ fn invoke_command(
     params: &mut ParametersAny<'_>,
 ) -> Result<()> {
     trace_println!("[+] TA invoke command");
-    match Command::from(cmd_id) {
+    let command = Command::try_from(cmd_id).map_err(|_| ErrorKind::BadParameters)?;
+    command
+        .check_params(optee_utee::raw_param_types(params))
+        .map_err(|e| {
+            trace_println!("[+] {}", e);
+            ErrorKind::BadParameters
+        })?;
+    match command {
         Command::RegisterSharedKey => register_shared_key(sess_ctx, params),
         Command::GetHOTP => get_hotp(sess_ctx, params),
-        _ => Err(ErrorKind::BadParameters.into()),
     }
 }
 
@@ -15,67 +15,43 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use optee_teec::{Context, ErrorKind, Operation, ParamNone, ParamTmpRef, Uuid};
+use optee_teec::ErrorKind;
+use optee_teec_cli::TaClient;
 
-type Result<T> = optee_teec::Result<T>;
+type Result<T> = optee_teec_cli::Result<T>;
 
 pub struct EnclaveClient {
-    uuid: String,
-    context: optee_teec::Context,
-    buffer: Vec<u8>,
+    client: TaClient,
 }
 
 impl EnclaveClient {
     pub fn open(url: &str) -> Result<Self> {
         let url = url::Url::parse(url).map_err(|e| {
             eprintln!("Invalid URL: {}, error: {}", url, e);
-            ErrorKind::BadParameters
+            optee_teec::Error::from(ErrorKind::BadParameters)
         })?;
         match url.scheme() {
             "trustzone-enclave" => Self::open_uuid(url.host_str().ok_or_else(|| {
                 eprintln!("Missing host in URL");
-                ErrorKind::BadParameters
+                optee_teec::Error::from(ErrorKind::BadParameters)
             })?),
             _ => unimplemented!(),
         }
     }
 
     fn open_uuid(uuid: &str) -> Result<Self> {
-        let context = Context::new()?;
         Ok(Self {
-            uuid: uuid.to_string(),
-            context,
-            buffer: vec![0; 128],
+            client: TaClient::open(uuid)?,
         })
     }
 
     pub fn invoke(&mut self, input: &proto::EnclaveInput) -> Result<proto::EnclaveOutput> {
-        let command_id = input.command as u32;
-        let mut serialized_input = serde_json::to_vec(input).map_err(|e| {
-            eprintln!("Failed to serialize input: {}", e);
-            ErrorKind::BadParameters
-        })?;
-
-        let p0 = ParamTmpRef::new_input(serialized_input.as_mut_slice());
-        let p1 = ParamTmpRef::new_output(&mut self.buffer);
-
-        let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
-
-        let uuid = Uuid::parse_str(&self.uuid)?;
-        let mut session = self.context.open_session(uuid)?;
-        session.invoke_command(command_id, &mut operation)?;
-        let len = operation.parameters().1.updated_size();
-
-        let output: proto::EnclaveOutput =
-            serde_json::from_slice(&self.buffer[0..len]).map_err(|e| {
-                eprintln!("Failed to deserialize output: {}", e);
-                ErrorKind::BadParameters
-            })?;
-        Ok(output)
+        // The output buffer grows if the TA needs more
+        self.client.call(input.command as u32, input)
     }
 }
 
-fn main() -> optee_teec::Result<()> {
+fn main() -> Result<()> {
     let url = format!("trustzone-enclave://{}", proto::UUID);
     let mut enclave = EnclaveClient::open(&url)?;
     let input = proto::EnclaveInput {
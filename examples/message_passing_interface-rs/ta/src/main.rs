@@ -21,25 +21,38 @@
 extern crate alloc;
 
 use alloc::format;
+use optee_utee::dispatch::Json;
 use optee_utee::prelude::*;
-use optee_utee::{ErrorKind, Result};
-use proto::Command;
+use optee_utee::Result;
+use proto::{EnclaveInput, EnclaveOutput};
 
-fn handle_invoke(command: Command, input: proto::EnclaveInput) -> Result<proto::EnclaveOutput> {
-    match command {
-        Command::Hello => {
-            let output = proto::EnclaveOutput {
-                message: format!("Hello, {}", input.message),
-            };
-            Ok(output)
-        }
-        Command::Bye => {
-            let output = proto::EnclaveOutput {
-                message: format!("Bye, {}", input.message),
-            };
-            Ok(output)
-        }
-        _ => Err(ErrorKind::BadParameters.into()),
+// The command IDs are shared with the host through `proto::Command`.
+#[derive(TaCommand)]
+#[ta_command(codec = Json)]
+enum Command {
+    #[ta_command(id = proto::Command::Hello, output = EnclaveOutput)]
+    Hello(EnclaveInput),
+    #[ta_command(id = proto::Command::Bye, output = EnclaveOutput)]
+    Bye(EnclaveInput),
+}
+
+#[derive(Default)]
+struct Handler;
+
+#[ta_dispatch(stateless)]
+impl CommandHandler for Handler {
+    fn hello(&mut self, input: EnclaveInput) -> Result<EnclaveOutput> {
+        trace_println!("[+] TA invoke command: Hello");
+        Ok(EnclaveOutput {
+            message: format!("Hello, {}", input.message),
+        })
+    }
+
+    fn bye(&mut self, input: EnclaveInput) -> Result<EnclaveOutput> {
+        trace_println!("[+] TA invoke command: Bye");
+        Ok(EnclaveOutput {
+            message: format!("Bye, {}", input.message),
+        })
     }
 }
 
@@ -65,30 +78,4 @@ fn destroy() {
     trace_println!("[+] TA destroy");
 }
 
-#[ta_invoke_command]
-fn invoke_command(
-    cmd_id: u32,
-    (p0, p1, _, _): &mut (
-        ParameterMemrefInput<'_>,
-        ParameterMemrefOutput<'_>,
-        ParameterNone,
-        ParameterNone,
-    ),
-) -> Result<()> {
-    trace_println!("[+] TA invoke command");
-    let input: proto::EnclaveInput = serde_json::from_slice(p0.get_buffer()).map_err(|e| {
-        trace_println!("Failed to deserialize input: {}", e);
-        ErrorKind::BadFormat
-    })?;
-    let output = handle_invoke(Command::from(cmd_id), input)?;
-
-    let output_vec = serde_json::to_vec(&output).map_err(|e| {
-        trace_println!("Failed to serialize output: {}", e);
-        ErrorKind::BadFormat
-    })?;
-    p1.set_output(output_vec)?;
-
-    Ok(())
-}
-
 include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));
@@ -15,9 +15,11 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use optee_utee_build::{Error, TaConfig};
+use optee_utee_build::{Error, PropertyValue, TaConfig};
 
 fn main() -> Result<(), Error> {
-    let config = TaConfig::new_default_with_cargo_env(proto::UUID)?;
+    let config = TaConfig::new_default_with_cargo_env(proto::UUID)?
+        .add_ext_property("org.teaclave.example.answer", PropertyValue::U32(42))
+        .add_ext_property("org.teaclave.example.vendor", "teaclave");
     optee_utee_build::build(config)
 }
@@ -25,11 +25,19 @@ use optee_utee::prelude::*;
 use optee_utee::property::{
     ClientIdentity, PropertyKey, TaDescription, TaMultiSession, TeeInternalCoreVersion,
 };
-use optee_utee::LoginType;
+use optee_utee::{define_property_key, LoginType, Property, PropertySet};
 
 use optee_utee::{ErrorKind, Result};
 use proto::Command;
 
+// a typed key of a custom property, declared in build.rs
+define_property_key!(
+    Vendor,
+    CurrentTa,
+    "org.teaclave.example.vendor",
+    alloc::string::String
+);
+
 #[ta_create]
 fn create() -> Result<()> {
     trace_println!("[+] TA create");
@@ -91,6 +99,23 @@ fn get_properties() -> Result<()> {
         return Err(ErrorKind::BadParameters.into());
     }
 
+    // properties without a predefined key are read by name
+    let answer = Property::new(PropertySet::CurrentTa, "org.teaclave.example.answer").as_u32()?;
+    trace_println!("[+] TA get custom property: {}", answer);
+    // the value set in build.rs
+    if answer != 42 {
+        return Err(ErrorKind::BadParameters.into());
+    }
+
+    let vendor = Vendor.get()?;
+    trace_println!("[+] TA get custom property: {}", vendor);
+    if vendor != "teaclave" {
+        return Err(ErrorKind::BadParameters.into());
+    }
+
+    let device_id = Property::new(PropertySet::TeeImplementation, "gpd.tee.deviceID").as_uuid()?;
+    trace_println!("[+] TA get device id: {}", device_id);
+
     Ok(())
 }
 
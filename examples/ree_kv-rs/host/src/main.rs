@@ -0,0 +1,91 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use optee_teec::{
+    Context, ErrorKind, Operation, ParamNone, ParamTmpRef, ParamType, ParamValue, Session, Uuid,
+};
+use proto::{Command, TA_UUID};
+
+/// Larger than a chunk of the plugin, so the value takes several round trips.
+const TEST_VALUE_SIZE: usize = 10000;
+
+fn write_value(session: &mut Session, key: &[u8], value: &[u8]) -> optee_teec::Result<()> {
+    let p0 = ParamTmpRef::new_input(key);
+    let p1 = ParamTmpRef::new_input(value);
+    let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+    session.invoke_command(Command::Write as u32, &mut operation)?;
+    println!("- Wrote {} bytes", value.len());
+    Ok(())
+}
+
+fn read_value(session: &mut Session, key: &[u8], value: &mut [u8]) -> optee_teec::Result<usize> {
+    let p0 = ParamTmpRef::new_input(key);
+    let p1 = ParamTmpRef::new_output(value);
+    let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+    session.invoke_command(Command::Read as u32, &mut operation)?;
+    let size = operation.parameters().1.updated_size();
+    println!("- Read back {} bytes", size);
+    Ok(size)
+}
+
+fn delete_value(session: &mut Session, key: &[u8]) -> optee_teec::Result<()> {
+    let p0 = ParamTmpRef::new_input(key);
+    let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
+    session.invoke_command(Command::Delete as u32, &mut operation)?;
+    println!("- Deleted the value");
+    Ok(())
+}
+
+fn count_values(session: &mut Session) -> optee_teec::Result<u32> {
+    let p0 = ParamValue::new(0, 0, ParamType::ValueOutput);
+    let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
+    session.invoke_command(Command::Count as u32, &mut operation)?;
+    Ok(operation.parameters().0.a())
+}
+
+fn main() -> optee_teec::Result<()> {
+    let mut ctx = Context::new()?;
+    let uuid = Uuid::parse_str(TA_UUID).map_err(|err| {
+        println!("Invalid TA_UUID: {:?}", err);
+        ErrorKind::BadParameters
+    })?;
+    let mut session = ctx.open_session(uuid)?;
+
+    let key = b"blob";
+    let value: Vec<u8> = (0..TEST_VALUE_SIZE).map(|i| i as u8).collect();
+    let mut read = vec![0u8; TEST_VALUE_SIZE];
+
+    write_value(&mut session, key, &value)?;
+    let size = read_value(&mut session, key, &mut read)?;
+    if read[..size] != value[..] {
+        println!("- Unexpected content read back from the REE");
+        return Err(ErrorKind::Generic.into());
+    }
+    println!(
+        "- {} value(s) in the store of the TA",
+        count_values(&mut session)?
+    );
+    delete_value(&mut session, key)?;
+
+    match read_value(&mut session, key, &mut read) {
+        Err(e) if e.kind() == ErrorKind::ItemNotFound => println!("- The value is gone"),
+        Err(e) => return Err(e),
+        Ok(_) => return Err(ErrorKind::Generic.into()),
+    }
+
+    println!("Success");
+    Ok(())
+}
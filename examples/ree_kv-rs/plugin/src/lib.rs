@@ -0,0 +1,57 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use optee_teec::{
+    macros::{plugin_init, plugin_invoke},
+    plugin_info,
+    ree_kv::{ReeKv, DEFAULT_ROOT, PLUGIN_UUID},
+    PluginInfo, PluginParameters, PluginRouter, Result,
+};
+
+const PLUGIN: PluginInfo = plugin_info!("ree_kv", PLUGIN_UUID);
+
+/// Environment variable of tee-supplicant overriding the root directory of
+/// the store.
+const ROOT_VAR: &str = "REE_KV_ROOT";
+
+static ROOT: LazyLock<PathBuf> = LazyLock::new(|| {
+    std::env::var_os(ROOT_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| DEFAULT_ROOT.into())
+});
+
+static ROUTER: LazyLock<PluginRouter> = LazyLock::new(|| ReeKv::new(ROOT.as_path()).router());
+
+#[plugin_init]
+fn init() -> Result<()> {
+    println!(
+        "*plugin*: init {}, version: {}, root: {}",
+        PLUGIN.name,
+        PLUGIN.version,
+        ROOT.display()
+    );
+    Ok(())
+}
+
+#[plugin_invoke]
+fn invoke(params: &mut PluginParameters) -> Result<()> {
+    ROUTER.dispatch(params)
+}
+
+include!(concat!(env!("OUT_DIR"), "/plugin_static.rs"));
@@ -0,0 +1,91 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+#![cfg_attr(not(feature = "std"), no_std)]
+#![no_main]
+
+extern crate alloc;
+
+use optee_utee::prelude::*;
+use optee_utee::storage::ReeKvStore;
+use optee_utee::{ErrorKind, Result};
+use proto::Command;
+
+/// Namespace of the keys of the TA in the store shared with the other TAs.
+const NAMESPACE: &[u8] = b"ree_kv-rs/";
+
+#[ta_create]
+fn create() -> Result<()> {
+    trace_println!("[+] TA create");
+    Ok(())
+}
+
+#[ta_open_session]
+fn open_session(_params: &mut ParametersNone) -> Result<()> {
+    trace_println!("[+] TA open session");
+    Ok(())
+}
+
+#[ta_close_session]
+fn close_session() {
+    trace_println!("[+] TA close session");
+}
+
+#[ta_destroy]
+fn destroy() {
+    trace_println!("[+] TA destroy");
+}
+
+#[ta_invoke_command]
+fn invoke_command(cmd_id: u32, params: &mut ParametersAny<'_>) -> Result<()> {
+    trace_println!("[+] TA invoke command");
+    let store = ReeKvStore::default().with_namespace(NAMESPACE);
+    match Command::from(cmd_id) {
+        Command::Write => write_value(&store, params),
+        Command::Read => read_value(&store, params),
+        Command::Delete => delete_value(&store, params),
+        Command::Count => count_values(&store, params),
+        _ => Err(ErrorKind::NotSupported.into()),
+    }
+}
+
+fn write_value(store: &ReeKvStore, (p0, p1, _, _): &mut ParametersAny<'_>) -> Result<()> {
+    // The value is public: a real TA seals what it keeps in the REE
+    let key = p0.as_memref_input()?.get_buffer().to_vec();
+    let value = p1.as_memref_input()?.get_buffer().to_vec();
+    store.put(&key, &value)?;
+    trace_println!("[+] TA wrote {} bytes to the REE", value.len());
+    Ok(())
+}
+
+fn read_value(store: &ReeKvStore, (p0, p1, _, _): &mut ParametersAny<'_>) -> Result<()> {
+    let key = p0.as_memref_input()?.get_buffer().to_vec();
+    let value = store.get(&key)?;
+    p1.as_memref_output()?.set_output(&value)
+}
+
+fn delete_value(store: &ReeKvStore, (p0, _, _, _): &mut ParametersAny<'_>) -> Result<()> {
+    let key = p0.as_memref_input()?.get_buffer().to_vec();
+    store.delete(&key)
+}
+
+fn count_values(store: &ReeKvStore, (p0, _, _, _): &mut ParametersAny<'_>) -> Result<()> {
+    let count = store.keys(b"")?.len();
+    p0.as_value_output()?.set_a(count as u32);
+    Ok(())
+}
+
+include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));
@@ -23,7 +23,7 @@ fn serde(session: &mut Session) -> optee_teec::Result<()> {
     let p0 = ParamTmpRef::new_output(&mut buffer);
     let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
 
-    session.invoke_command(Command::DefaultOp as u32, &mut operation)?;
+    session.invoke_command(Command::DefaultOp.into(), &mut operation)?;
     let updated_size = operation.parameters().0.updated_size();
 
     let p: Point = serde_json::from_slice(&buffer[..updated_size]).map_err(|e| {
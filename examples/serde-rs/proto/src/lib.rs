@@ -17,7 +17,7 @@
 
 #![no_std]
 
-use num_enum::{FromPrimitive, IntoPrimitive};
+use optee_proto::OpteeProtocol;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -26,12 +26,10 @@ pub struct Point {
     pub y: i32,
 }
 
-#[derive(FromPrimitive, IntoPrimitive)]
+#[derive(OpteeProtocol, Clone, Copy)]
 #[repr(u32)]
 pub enum Command {
-    DefaultOp,
-    #[default]
-    Unknown,
+    DefaultOp = 0,
 }
 
 // If Uuid::parse_str() returns an InvalidLength error, there may be an extra
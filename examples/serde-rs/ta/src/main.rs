@@ -20,6 +20,8 @@
 
 extern crate alloc;
 
+use core::convert::TryFrom;
+
 use optee_utee::prelude::*;
 use optee_utee::{ErrorKind, Result};
 use proto::{Command, Point};
@@ -49,7 +51,7 @@ fn destroy() {
 #[ta_invoke_command]
 fn invoke_command(cmd_id: u32, (p0, _, _, _): &mut ParametersAny<'_>) -> Result<()> {
     trace_println!("[+] TA invoke command");
-    match Command::from(cmd_id) {
+    match Command::try_from(cmd_id).map_err(|_| ErrorKind::BadParameters)? {
         Command::DefaultOp => {
             let output = p0.as_memref_output()?;
             let point = Point { x: 1, y: 2 };
@@ -75,7 +77,6 @@ fn invoke_command(cmd_id: u32, (p0, _, _, _): &mut ParametersAny<'_>) -> Result<
 
             Ok(())
         }
-        _ => Err(ErrorKind::BadParameters.into()),
     }
 }
 
@@ -15,44 +15,61 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use std::sync::LazyLock;
+
 use optee_teec::{
-    macros::{plugin_init, plugin_invoke},
-    ErrorKind, Result,
+    macros::{plugin_init, plugin_invoke, plugin_shutdown},
+    plugin_info, PluginInfo, PluginParameters, PluginRouter, PluginState, Result,
 };
 use proto::PluginCommand;
 
+const PLUGIN: PluginInfo = plugin_info!("syslog", proto::PLUGIN_UUID);
+
+static ROUTER: LazyLock<PluginRouter> =
+    LazyLock::new(|| PluginRouter::new().route(PluginCommand::Print.into(), print));
+
+// Number of values received from the TAs
+static RECEIVED: PluginState<usize> = PluginState::new();
+
 #[plugin_init]
 fn init() -> Result<()> {
-    println!("*plugin*: init, version: {}", env!("CARGO_PKG_VERSION"));
-    Ok(())
+    println!(
+        "*plugin*: init {}, version: {}",
+        PLUGIN.name, PLUGIN.version
+    );
+    RECEIVED.init(0)
 }
 
 #[plugin_invoke]
 fn invoke(params: &mut optee_teec::PluginParameters) -> optee_teec::Result<()> {
     println!("*plugin*: invoke");
-    match PluginCommand::from(params.cmd) {
-        PluginCommand::Print => {
-            let input = params.get_buffer();
-            println!(
-                "*plugin*: receive value: {:?} length {:?}",
-                input,
-                input.len()
-            );
-
-            let send_slice: [u8; 9] = [0x40; 9];
-            params.set_buf_from_slice(&send_slice)?;
-            println!(
-                "*plugin*: send value: {:?} length {:?} to ta",
-                send_slice,
-                send_slice.len()
-            );
-            Ok(())
-        }
-        _ => {
-            println!("Unsupported plugin command: {:?}", params.cmd);
-            Err(ErrorKind::BadParameters.into())
-        }
+    ROUTER.dispatch(params)
+}
+
+#[plugin_shutdown]
+fn shutdown() {
+    if let Some(received) = RECEIVED.shutdown() {
+        println!("*plugin*: shutdown after {} values", received);
     }
 }
 
+fn print(params: &mut PluginParameters) -> Result<()> {
+    let input = params.get_buffer();
+    println!(
+        "*plugin*: receive value: {:?} length {:?}",
+        input,
+        input.len()
+    );
+    RECEIVED.with(|received| *received += 1)?;
+
+    let send_slice: [u8; 9] = [0x40; 9];
+    params.set_buf_from_slice(&send_slice)?;
+    println!(
+        "*plugin*: send value: {:?} length {:?} to ta",
+        send_slice,
+        send_slice.len()
+    );
+    Ok(())
+}
+
 include!(concat!(env!("OUT_DIR"), "/plugin_static.rs"));
@@ -18,14 +18,13 @@
 #![no_main]
 
 use anyhow::Context;
-use lazy_static::lazy_static;
+use optee_tls::{TlsCommand, TlsContext, TlsSessionManager};
 use optee_utee::prelude::*;
+use optee_utee::server::{Request, Route, TaServer};
 use optee_utee::{ErrorKind, Result};
 use proto::Command;
 use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
-use std::collections::HashMap;
-use std::io::{Cursor, Read, Write};
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::Arc;
 
 // Register the custom getrandom implementation.
 //
@@ -37,165 +36,111 @@ use std::sync::{Arc, Mutex, RwLock};
 // `rustls_provider` crate and registered here.
 getrandom::register_custom_getrandom!(rustls_provider::optee_getrandom);
 
-lazy_static! {
-    static ref TLS_SESSIONS: RwLock<HashMap<u32, Mutex<rustls::ServerConnection>>> =
-        RwLock::new(HashMap::new());
+// The TLS sessions of all the TA sessions are shared, started in `create`.
+// The TLS data buffered inside the TA is bounded by the default
+// `BufferLimits` of optee-tls, which fit in `ta_data_size` (see build.rs).
+static SERVER: TaServer<TaSession, TlsSessionManager> = TaServer::new(&[
+    Route::new(Command::NewTlsSession as u32, new_tls_session),
+    Route::new(Command::CloseTlsSession as u32, close_tls_session),
+    Route::new(Command::DoTlsRead as u32, do_tls_read),
+    Route::new(Command::DoTlsWrite as u32, do_tls_write),
+]);
+
+// The TLS sessions opened by a TA session, closed with it.
+#[derive(Default)]
+struct TaSession {
+    tls_sessions: Vec<u32>,
 }
 
+type TlsRequest<'a> = Request<'a, TaSession, TlsSessionManager>;
+
 #[ta_create]
 fn create() -> Result<()> {
     trace_println!("[+] TA create");
-    Ok(())
+    let config = make_config().map_err(|e| {
+        trace_println!("[-] Failed to create TLS config: {:?}", e);
+        ErrorKind::Generic
+    })?;
+    SERVER.start(TlsSessionManager::new(config))
 }
 
 #[ta_open_session]
-fn open_session(_params: &mut ParametersNone) -> Result<()> {
+fn open_session(_params: &mut ParametersNone, _session: &mut TaSession) -> Result<()> {
     trace_println!("[+] TA open session");
     Ok(())
 }
 
 #[ta_close_session]
-fn close_session() {
+fn close_session(session: &mut TaSession) {
     trace_println!("[+] TA close session");
+    // Free the TLS sessions the client application left open
+    let _ = SERVER.shared(|tls| {
+        for session_id in session.tls_sessions.drain(..) {
+            let _ = tls.close(session_id);
+        }
+    });
 }
 
 #[ta_destroy]
 fn destroy() {
     trace_println!("[+] TA destroy");
+    SERVER.stop();
 }
 
 #[ta_invoke_command]
-fn invoke_command(cmd_id: u32, params: &mut ParametersAny<'_>) -> Result<()> {
+fn invoke_command(
+    session: &mut TaSession,
+    cmd_id: u32,
+    params: &mut ParametersAny<'_>,
+) -> Result<()> {
     trace_println!("[+] TA invoke command");
-    let session_id = params.0.as_value_input()?.get_a();
-    trace_println!("[+] session id: {}", session_id);
-    match Command::from(cmd_id) {
-        Command::NewTlsSession => {
-            trace_println!("[+] new_tls_session");
-            new_tls_session(session_id).map_err(|e| {
-                trace_println!("[-] Failed to create TLS session: {:?}", e);
-                ErrorKind::Generic.into()
-            })
-        }
-        Command::DoTlsRead => {
-            let p1 = params.1.as_memref_input()?;
-            let buffer = p1.get_buffer();
-            trace_println!("[+] do_tls_read");
-            do_tls_read(session_id, buffer).map_err(|e| {
-                trace_println!("[-] Failed to read TLS data: {:?}", e);
-                ErrorKind::Generic.into()
-            })
-        }
-        Command::DoTlsWrite => {
-            trace_println!("[+] do_tls_write");
-            let p1 = params.1.as_memref_output()?;
-            let lens = do_tls_write(session_id, p1.get_buffer_mut()).map_err(|e| {
-                trace_println!("[-] Failed to write TLS data: {:?}", e);
-                ErrorKind::Generic
-            })?;
-            p1.set_updated_size(lens)
-        }
-        Command::CloseTlsSession => {
-            trace_println!("[+] close_tls_session");
-            close_tls_session(session_id).map_err(|e| {
-                trace_println!("[-] Failed to close TLS session: {:?}", e);
-                ErrorKind::Generic.into()
-            })
-        }
-        _ => Err(ErrorKind::BadParameters.into()),
-    }
+    SERVER.invoke(session, cmd_id, params)
 }
 
-pub fn new_tls_session(session_id: u32) -> anyhow::Result<()> {
-    let tls_config = make_config().context("Failed to create TLS config")?;
-    let tls_session =
-        rustls::ServerConnection::new(tls_config).context("Failed to create TLS connection")?;
-
-    TLS_SESSIONS
-        .write()
-        .map_err(|_| anyhow::anyhow!("Failed to acquire write lock on TLS sessions"))?
-        .insert(session_id, Mutex::new(tls_session));
-
-    trace_println!("[+] TLS session {} created successfully", session_id);
+fn new_tls_session(request: &mut TlsRequest<'_>, params: &mut ParametersAny<'_>) -> Result<()> {
+    run(request, params, TlsCommand::NewSession)?;
+    let session_id = params.0.as_value_input()?.get_a();
+    request.session().tls_sessions.push(session_id);
     Ok(())
 }
 
-pub fn close_tls_session(session_id: u32) -> anyhow::Result<()> {
-    let mut sessions = TLS_SESSIONS.write().map_err(|_| {
-        anyhow::anyhow!(
-            "Failed to acquire write lock to close TLS session {}",
-            session_id
-        )
-    })?;
+fn close_tls_session(request: &mut TlsRequest<'_>, params: &mut ParametersAny<'_>) -> Result<()> {
+    run(request, params, TlsCommand::CloseSession)?;
+    let session_id = params.0.as_value_input()?.get_a();
+    request
+        .session()
+        .tls_sessions
+        .retain(|id| *id != session_id);
+    Ok(())
+}
 
-    if sessions.remove(&session_id).is_some() {
-        trace_println!("[+] TLS session {} closed", session_id);
-        Ok(())
-    } else {
-        Err(anyhow::anyhow!(
-            "TLS session {} not found for closing",
-            session_id
-        ))
-    }
+fn do_tls_read(request: &mut TlsRequest<'_>, params: &mut ParametersAny<'_>) -> Result<()> {
+    run(request, params, TlsCommand::Read)
 }
 
-pub fn do_tls_read(session_id: u32, buf: &[u8]) -> anyhow::Result<()> {
-    let mut rd = Cursor::new(buf);
-    let ts_guard = TLS_SESSIONS
-        .read()
-        .map_err(|_| anyhow::anyhow!("Failed to acquire read lock on TLS sessions"))?;
-
-    let session = ts_guard
-        .get(&session_id)
-        .ok_or_else(|| anyhow::anyhow!("TLS session {} not found", session_id))?;
-
-    let mut tls_session = session
-        .lock()
-        .map_err(|_| anyhow::anyhow!("Failed to acquire lock on TLS session {}", session_id))?;
-
-    tls_session
-        .read_tls(&mut rd)
-        .context("Failed to read TLS data")?;
-
-    tls_session
-        .process_new_packets()
-        .context("Failed to process TLS packets")?;
-
-    // Read and process all available plaintext.
-    let mut buf = Vec::new();
-    let _rc = tls_session.reader().read_to_end(&mut buf);
-    if !buf.is_empty() {
-        tls_session
-            .writer()
-            .write_all(&buf)
-            .context("Failed to write response data")?;
-    }
+fn do_tls_write(request: &mut TlsRequest<'_>, params: &mut ParametersAny<'_>) -> Result<()> {
+    run(request, params, TlsCommand::Write)
+}
 
-    Ok(())
+fn run(
+    request: &TlsRequest<'_>,
+    params: &mut ParametersAny<'_>,
+    command: TlsCommand,
+) -> Result<()> {
+    request.shared(|tls| tls.invoke_command(command, params, echo))?
 }
 
-pub fn do_tls_write(session_id: u32, buf: &mut [u8]) -> anyhow::Result<usize> {
-    let ts_guard = TLS_SESSIONS
-        .read()
-        .map_err(|_| anyhow::anyhow!("Failed to acquire read lock on TLS sessions"))?;
-
-    let session = ts_guard
-        .get(&session_id)
-        .ok_or_else(|| anyhow::anyhow!("TLS session {} not found", session_id))?;
-
-    let mut tls_session = session
-        .lock()
-        .map_err(|_| anyhow::anyhow!("Failed to acquire lock on TLS session {}", session_id))?;
-
-    let mut wr = Cursor::new(buf);
-    let mut rc = 0;
-    while tls_session.wants_write() {
-        rc += tls_session
-            .write_tls(&mut wr)
-            .context("Failed to write TLS data")?;
+// Send the decrypted application data back to the client.
+fn echo(session: &mut TlsContext) -> Result<()> {
+    let plaintext = session.read_plaintext()?;
+    if !plaintext.is_empty() {
+        trace_println!(
+            "[+] TLS session {}: echoing {} bytes",
+            session.session_id(),
+            plaintext.len()
+        );
     }
-
-    Ok(rc)
+    session.write_plaintext(&plaintext)
 }
 
 fn make_config() -> anyhow::Result<Arc<rustls::ServerConfig>> {
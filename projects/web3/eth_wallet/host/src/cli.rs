@@ -73,6 +73,75 @@ pub struct SignTransactionOpt {
     pub gas: u128,
 }
 
+#[derive(Debug, StructOpt)]
+pub struct SignEip1559TransactionOpt {
+    #[structopt(short, long, required = true, parse(try_from_str = decode_str_to_uuid))]
+    pub wallet_id: uuid::Uuid,
+    #[structopt(short, long, default_value = "m/44'/60'/0'/0/0")]
+    pub hd_path: String,
+    #[structopt(short, long, default_value = "5")]
+    pub chain_id: u64,
+    #[structopt(short, long, default_value = "0")]
+    pub nonce: u128,
+    #[structopt(short, long, required = true, parse(try_from_str = decode_hex_to_address))]
+    pub to: [u8; 20],
+    #[structopt(short, long, required = true)]
+    pub value: u128,
+    #[structopt(short = "m", long, default_value = "2000000000")]
+    pub max_fee_per_gas: u128,
+    #[structopt(short = "p", long, default_value = "1000000000")]
+    pub max_priority_fee_per_gas: u128,
+    #[structopt(short, long, default_value = "21000")]
+    pub gas: u128,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SignErc20TransferOpt {
+    #[structopt(short, long, required = true, parse(try_from_str = decode_str_to_uuid))]
+    pub wallet_id: uuid::Uuid,
+    #[structopt(short, long, default_value = "m/44'/60'/0'/0/0")]
+    pub hd_path: String,
+    #[structopt(short, long, default_value = "5")]
+    pub chain_id: u64,
+    #[structopt(short, long, default_value = "0")]
+    pub nonce: u128,
+    #[structopt(short = "k", long, required = true, parse(try_from_str = decode_hex_to_address))]
+    pub contract: [u8; 20],
+    #[structopt(short, long, required = true, parse(try_from_str = decode_hex_to_address))]
+    pub to: [u8; 20],
+    #[structopt(short, long, required = true)]
+    pub amount: u128,
+    #[structopt(short = "p", long, default_value = "1000000000")]
+    pub gas_price: u128,
+    #[structopt(short, long, default_value = "60000")]
+    pub gas: u128,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SetTokenPolicyOpt {
+    #[structopt(short, long, required = true, parse(try_from_str = decode_str_to_uuid))]
+    pub wallet_id: uuid::Uuid,
+    #[structopt(short = "k", long, required = true, parse(try_from_str = decode_hex_to_address))]
+    pub contract: [u8; 20],
+    /// The largest amount of a transfer or an approval, no limit if omitted
+    #[structopt(short, long)]
+    pub max_amount: Option<u128>,
+    /// The mnemonic of the wallet, required to raise or remove a limit
+    #[structopt(long)]
+    pub mnemonic: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SignTypedDataOpt {
+    #[structopt(short, long, required = true, parse(try_from_str = decode_str_to_uuid))]
+    pub wallet_id: uuid::Uuid,
+    #[structopt(short, long, default_value = "m/44'/60'/0'/0/0")]
+    pub hd_path: String,
+    /// The JSON file of the EIP-712 typed data
+    #[structopt(short, long, required = true, parse(from_os_str))]
+    pub file: std::path::PathBuf,
+}
+
 #[derive(Debug, StructOpt)]
 pub enum Command {
     /// Create a new wallet.
@@ -87,6 +156,18 @@ pub enum Command {
     /// Sign a transaction.
     #[structopt(name = "sign-transaction")]
     SignTransaction(SignTransactionOpt),
+    /// Sign an EIP-1559 (type 2) transaction.
+    #[structopt(name = "sign-eip1559-transaction")]
+    SignEip1559Transaction(SignEip1559TransactionOpt),
+    /// Sign an ERC-20 token transfer.
+    #[structopt(name = "sign-erc20-transfer")]
+    SignErc20Transfer(SignErc20TransferOpt),
+    /// Limit the ERC-20 transfers and approvals of a token.
+    #[structopt(name = "set-token-policy")]
+    SetTokenPolicy(SetTokenPolicyOpt),
+    /// Sign EIP-712 typed data.
+    #[structopt(name = "sign-typed-data")]
+    SignTypedData(SignTypedDataOpt),
     /// Run tests
     #[structopt(name = "test")]
     Test,
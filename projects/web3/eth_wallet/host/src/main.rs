@@ -18,13 +18,13 @@
 mod cli;
 mod tests;
 
-use optee_teec::{Context, Operation, Uuid};
+use optee_teec::{Context, ErrorKind, Operation, OutputReader, Uuid};
 use optee_teec::{ParamNone, ParamTmpRef};
 
 use anyhow::Result;
 use structopt::StructOpt;
 
-const OUTPUT_MAX_SIZE: usize = 1024;
+const OUTPUT_INITIAL_SIZE: usize = 1024;
 
 fn invoke_command(command: proto::Command, input: &[u8]) -> optee_teec::Result<Vec<u8>> {
     let mut ctx = Context::new()?;
@@ -33,32 +33,31 @@ fn invoke_command(command: proto::Command, input: &[u8]) -> optee_teec::Result<V
     let mut session = ctx.open_session(uuid)?;
 
     println!("CA: command: {:?}", command);
-    // input buffer
-    let p0 = ParamTmpRef::new_input(input);
-    // output buffer
-    let mut output = vec![0u8; OUTPUT_MAX_SIZE];
-    let p1 = ParamTmpRef::new_output(output.as_mut_slice());
-
-    let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
-    match session.invoke_command(command as u32, &mut operation) {
-        Ok(()) => {
-            println!("CA: invoke_command success");
-            let output_len = operation.parameters().1.updated_size();
-            Ok(output[..output_len].to_vec())
-        }
-        Err(e) => {
-            let output_len = operation.parameters().1.updated_size();
-            let err_message = String::from_utf8_lossy(&output[..output_len]);
+    let command_id = command as u32;
+    // The output buffer is grown if the TA reports a larger required size
+    let output = OutputReader::new(OUTPUT_INITIAL_SIZE).read(|output| {
+        // input buffer
+        let p0 = ParamTmpRef::new_input(input);
+        // output buffer
+        let p1 = ParamTmpRef::new_output(output);
+
+        let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+        let result = session.invoke_command(command_id, &mut operation);
+        let output_len = operation.parameters().1.updated_size();
+        if matches!(&result, Err(e) if e.kind() != ErrorKind::ShortBuffer) {
+            let err_message = String::from_utf8_lossy(&output[..output_len.min(output.len())]);
             println!("CA: invoke_command failed: {:?}", err_message);
-            Err(e)
         }
-    }
+        (result, output_len)
+    })?;
+    println!("CA: invoke_command success");
+    Ok(output)
 }
 
-pub fn create_wallet() -> Result<uuid::Uuid> {
+pub fn create_wallet() -> Result<proto::CreateWalletOutput> {
     let serialized_output = invoke_command(proto::Command::CreateWallet, &[])?;
     let output: proto::CreateWalletOutput = bincode::deserialize(&serialized_output)?;
-    Ok(output.wallet_id)
+    Ok(output)
 }
 
 pub fn remove_wallet(wallet_id: uuid::Uuid) -> Result<()> {
@@ -110,12 +109,83 @@ pub fn sign_transaction(
     Ok(output.signature)
 }
 
+pub fn sign_eip1559_transaction(
+    wallet_id: uuid::Uuid,
+    hd_path: &str,
+    transaction: proto::Eip1559Transaction,
+) -> Result<Vec<u8>> {
+    let input = proto::SignEip1559TransactionInput {
+        wallet_id,
+        hd_path: hd_path.to_string(),
+        transaction,
+    };
+    let serialized_output = invoke_command(
+        proto::Command::SignEip1559Transaction,
+        &bincode::serialize(&input)?,
+    )?;
+    let output: proto::SignTransactionOutput = bincode::deserialize(&serialized_output)?;
+    Ok(output.signature)
+}
+
+pub fn sign_erc20_transfer(
+    wallet_id: uuid::Uuid,
+    hd_path: &str,
+    transfer: proto::Erc20Transfer,
+) -> Result<Vec<u8>> {
+    let input = proto::SignErc20TransferInput {
+        wallet_id,
+        hd_path: hd_path.to_string(),
+        transfer,
+    };
+    let serialized_output = invoke_command(
+        proto::Command::SignErc20Transfer,
+        &bincode::serialize(&input)?,
+    )?;
+    let output: proto::SignTransactionOutput = bincode::deserialize(&serialized_output)?;
+    Ok(output.signature)
+}
+
+pub fn set_token_policy(
+    wallet_id: uuid::Uuid,
+    contract: [u8; 20],
+    max_amount: Option<u128>,
+    mnemonic: Option<&str>,
+) -> Result<()> {
+    let input = proto::SetTokenPolicyInput {
+        wallet_id,
+        contract,
+        max_amount,
+        mnemonic: mnemonic.map(str::to_string),
+    };
+    let _output = invoke_command(proto::Command::SetTokenPolicy, &bincode::serialize(&input)?)?;
+    Ok(())
+}
+
+pub fn sign_typed_data(
+    wallet_id: uuid::Uuid,
+    hd_path: &str,
+    typed_data: &str,
+) -> Result<proto::SignTypedDataOutput> {
+    let input = proto::SignTypedDataInput {
+        wallet_id,
+        hd_path: hd_path.to_string(),
+        typed_data: typed_data.to_string(),
+    };
+    let serialized_output =
+        invoke_command(proto::Command::SignTypedData, &bincode::serialize(&input)?)?;
+    let output: proto::SignTypedDataOutput = bincode::deserialize(&serialized_output)?;
+    Ok(output)
+}
+
 fn main() -> Result<()> {
     let args = cli::Opt::from_args();
     match args.command {
         cli::Command::CreateWallet(_opt) => {
-            let wallet_id = create_wallet()?;
-            println!("Wallet ID: {}", wallet_id);
+            let output = create_wallet()?;
+            println!("Wallet ID: {}", output.wallet_id);
+            // Kept offline, it is required to raise the limits of the token
+            // policies
+            println!("Mnemonic: {}", output.mnemonic);
         }
         cli::Command::RemoveWallet(opt) => {
             remove_wallet(opt.wallet_id)?;
@@ -138,6 +208,49 @@ fn main() -> Result<()> {
             )?;
             println!("Signature: {}", hex::encode(&signature));
         }
+        cli::Command::SignEip1559Transaction(opt) => {
+            let transaction = proto::Eip1559Transaction {
+                chain_id: opt.chain_id,
+                nonce: opt.nonce,
+                to: Some(opt.to),
+                value: opt.value,
+                max_priority_fee_per_gas: opt.max_priority_fee_per_gas,
+                max_fee_per_gas: opt.max_fee_per_gas,
+                gas: opt.gas,
+                data: vec![],
+                access_list: vec![],
+            };
+            let signature = sign_eip1559_transaction(opt.wallet_id, &opt.hd_path, transaction)?;
+            println!("Signature: {}", hex::encode(&signature));
+        }
+        cli::Command::SignErc20Transfer(opt) => {
+            let transfer = proto::Erc20Transfer {
+                chain_id: opt.chain_id,
+                nonce: opt.nonce,
+                contract: opt.contract,
+                recipient: opt.to,
+                amount: opt.amount,
+                gas_price: opt.gas_price,
+                gas: opt.gas,
+            };
+            let signature = sign_erc20_transfer(opt.wallet_id, &opt.hd_path, transfer)?;
+            println!("Signature: {}", hex::encode(&signature));
+        }
+        cli::Command::SetTokenPolicy(opt) => {
+            set_token_policy(
+                opt.wallet_id,
+                opt.contract,
+                opt.max_amount,
+                opt.mnemonic.as_deref(),
+            )?;
+            println!("Token policy set");
+        }
+        cli::Command::SignTypedData(opt) => {
+            let typed_data = std::fs::read_to_string(&opt.file)?;
+            let output = sign_typed_data(opt.wallet_id, &opt.hd_path, &typed_data)?;
+            println!("Hash: 0x{}", hex::encode(&output.hash));
+            println!("Signature: 0x{}", hex::encode(&output.signature));
+        }
         cli::Command::Test => {
             tests::tests::test_workflow();
             println!("Tests passed");
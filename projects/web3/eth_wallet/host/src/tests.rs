@@ -20,7 +20,10 @@ pub mod tests {
 
     pub fn test_workflow() {
         // Simulate the workflow of creating a wallet, deriving an address, and signing a transaction
-        let wallet_id = create_wallet().unwrap();
+        let proto::CreateWalletOutput {
+            wallet_id,
+            mnemonic,
+        } = create_wallet().unwrap();
         let address = derive_address(wallet_id, "m/44'/60'/0'/0/0").unwrap();
         let result = sign_transaction(
             wallet_id,
@@ -33,5 +36,112 @@ pub mod tests {
             21000,
         );
         assert!(result.is_ok());
+
+        let transaction = proto::Eip1559Transaction {
+            chain_id: 5,
+            nonce: 1,
+            to: Some(address),
+            value: 100,
+            max_priority_fee_per_gas: 1000000000,
+            max_fee_per_gas: 2000000000,
+            gas: 21000,
+            data: vec![],
+            access_list: vec![],
+        };
+        let signature =
+            sign_eip1559_transaction(wallet_id, "m/44'/60'/0'/0/0", transaction.clone()).unwrap();
+        // Typed transactions are prefixed with their type
+        assert_eq!(signature[0], 0x02);
+
+        // The priority fee cannot exceed the maximum fee
+        let transaction = proto::Eip1559Transaction {
+            max_priority_fee_per_gas: 3000000000,
+            ..transaction
+        };
+        assert!(sign_eip1559_transaction(wallet_id, "m/44'/60'/0'/0/0", transaction).is_err());
+
+        let contract = [0x11; 20];
+        let transfer = proto::Erc20Transfer {
+            chain_id: 5,
+            nonce: 2,
+            contract,
+            recipient: address,
+            amount: 1000,
+            gas_price: 1000000000,
+            gas: 60000,
+        };
+        assert!(sign_erc20_transfer(wallet_id, "m/44'/60'/0'/0/0", transfer.clone()).is_ok());
+        // Transfers above the limit of the token are refused by the TA
+        set_token_policy(wallet_id, contract, Some(999), None).unwrap();
+        assert!(sign_erc20_transfer(wallet_id, "m/44'/60'/0'/0/0", transfer.clone()).is_err());
+
+        // Whatever the command signing them, and for approvals too
+        let mut data = hex::decode("095ea7b3").unwrap();
+        data.extend_from_slice(&[0u8; 12]);
+        data.extend_from_slice(&address);
+        data.extend_from_slice(&[0u8; 30]);
+        data.extend_from_slice(&1000u16.to_be_bytes());
+        let approval = proto::Eip1559Transaction {
+            chain_id: 5,
+            nonce: 3,
+            to: Some(contract),
+            value: 0,
+            max_priority_fee_per_gas: 1000000000,
+            max_fee_per_gas: 2000000000,
+            gas: 60000,
+            data,
+            access_list: vec![],
+        };
+        assert!(sign_eip1559_transaction(wallet_id, "m/44'/60'/0'/0/0", approval.clone()).is_err());
+
+        // Lowering the limit is allowed, raising or removing it requires the
+        // mnemonic of the wallet
+        set_token_policy(wallet_id, contract, Some(500), None).unwrap();
+        assert!(set_token_policy(wallet_id, contract, Some(1000), None).is_err());
+        assert!(set_token_policy(wallet_id, contract, None, Some("abandon")).is_err());
+        set_token_policy(wallet_id, contract, Some(1000), Some(&mnemonic)).unwrap();
+        assert!(sign_erc20_transfer(wallet_id, "m/44'/60'/0'/0/0", transfer.clone()).is_ok());
+        assert!(sign_eip1559_transaction(wallet_id, "m/44'/60'/0'/0/0", approval).is_ok());
+        set_token_policy(wallet_id, contract, None, Some(&mnemonic)).unwrap();
+        assert!(sign_erc20_transfer(wallet_id, "m/44'/60'/0'/0/0", transfer).is_ok());
+
+        // The example of EIP-712
+        let typed_data = r#"{
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "version", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "Person": [
+                    {"name": "name", "type": "string"},
+                    {"name": "wallet", "type": "address"}
+                ],
+                "Mail": [
+                    {"name": "from", "type": "Person"},
+                    {"name": "to", "type": "Person"},
+                    {"name": "contents", "type": "string"}
+                ]
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": {
+                "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+                "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+                "contents": "Hello, Bob!"
+            }
+        }"#;
+        let output = sign_typed_data(wallet_id, "m/44'/60'/0'/0/0", typed_data).unwrap();
+        assert_eq!(
+            hex::encode(output.hash),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+        assert_eq!(output.signature.len(), 65);
     }
 }
@@ -69,3 +69,82 @@ pub struct SignTransactionInput {
 pub struct SignTransactionOutput {
     pub signature: Vec<u8>,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccessListItem {
+    pub address: [u8; 20],
+    pub storage_keys: Vec<[u8; 32]>,
+}
+
+/// An EIP-1559 (type 2) transaction, paying at most `max_fee_per_gas` per
+/// gas, of which at most `max_priority_fee_per_gas` on top of the base fee
+/// goes to the validator.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Eip1559Transaction {
+    pub chain_id: u64,
+    pub nonce: u128,
+    pub to: Option<[u8; 20]>,
+    pub value: u128,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas: u128,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignEip1559TransactionInput {
+    pub wallet_id: Uuid,
+    pub hd_path: String,
+    pub transaction: Eip1559Transaction,
+}
+
+/// A transfer of `amount` of the smallest unit of the ERC-20 token at
+/// `contract` to `recipient`, sent as a legacy transaction to the contract.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Erc20Transfer {
+    pub chain_id: u64,
+    pub nonce: u128,
+    pub contract: [u8; 20],
+    pub recipient: [u8; 20],
+    pub amount: u128,
+    pub gas_price: u128,
+    pub gas: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignErc20TransferInput {
+    pub wallet_id: Uuid,
+    pub hd_path: String,
+    pub transfer: Erc20Transfer,
+}
+
+/// Limits the transfers and approvals of the token at `contract` by the
+/// wallet to `max_amount`, or removes the limit with `None`. Raising or
+/// removing an existing limit requires the `mnemonic` of the wallet.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetTokenPolicyInput {
+    pub wallet_id: Uuid,
+    pub contract: [u8; 20],
+    pub max_amount: Option<u128>,
+    pub mnemonic: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetTokenPolicyOutput {}
+
+/// EIP-712 typed data, as the JSON sent to `eth_signTypedData_v4`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignTypedDataInput {
+    pub wallet_id: Uuid,
+    pub hd_path: String,
+    pub typed_data: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignTypedDataOutput {
+    /// The signing hash of the typed data
+    pub hash: [u8; 32],
+    /// `r || s || v`, with `v` of 27 or 28
+    pub signature: Vec<u8>,
+}
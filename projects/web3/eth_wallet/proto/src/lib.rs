@@ -28,6 +28,10 @@ pub enum Command {
     RemoveWallet,
     DeriveAddress,
     SignTransaction,
+    SignEip1559Transaction,
+    SignErc20Transfer,
+    SetTokenPolicy,
+    SignTypedData,
     #[default]
     Unknown,
 }
@@ -0,0 +1,304 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Hashing of EIP-712 typed data, as sent to `eth_signTypedData_v4`.
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryInto;
+
+use crate::hash::keccak_hash_to_bytes;
+
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: BTreeMap<String, Vec<Field>>,
+    pub primary_type: String,
+    pub domain: Map<String, Value>,
+    pub message: Map<String, Value>,
+}
+
+impl TypedData {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut typed_data: TypedData = serde_json::from_str(json)
+            .map_err(|e| anyhow!("[-] TypedData::from_json(): {:?}", e))?;
+        // The domain type is often left out, its fields are implied by the
+        // ones of the domain
+        if !typed_data.types.contains_key(DOMAIN_TYPE) {
+            let fields = [
+                ("name", "string"),
+                ("version", "string"),
+                ("chainId", "uint256"),
+                ("verifyingContract", "address"),
+                ("salt", "bytes32"),
+            ]
+            .iter()
+            .filter(|(name, _)| typed_data.domain.contains_key(*name))
+            .map(|(name, type_)| Field {
+                name: name.to_string(),
+                type_: type_.to_string(),
+            })
+            .collect();
+            typed_data.types.insert(DOMAIN_TYPE.to_string(), fields);
+        }
+        Ok(typed_data)
+    }
+
+    /// The hash signed: `keccak256(0x19 || 0x01 || domainSeparator ||
+    /// hashStruct(message))`.
+    pub fn signing_hash(&self) -> Result<[u8; 32]> {
+        let mut data = vec![0x19, 0x01];
+        data.extend_from_slice(&self.hash_struct(DOMAIN_TYPE, &self.domain)?);
+        data.extend_from_slice(&self.hash_struct(&self.primary_type, &self.message)?);
+        Ok(keccak(&data))
+    }
+
+    /// The token contract and the amount approved, as a 256-bit big-endian
+    /// word, if the typed data is an EIP-2612 `Permit`, so that it is checked
+    /// like an `approve` call. The `Permit` of DAI approves all or nothing
+    /// with `allowed` instead of `value`.
+    pub fn permit(&self) -> Result<Option<([u8; 20], [u8; 32])>> {
+        if self.primary_type != "Permit" {
+            return Ok(None);
+        }
+        let contract = self
+            .domain
+            .get("verifyingContract")
+            .ok_or_else(|| anyhow!("[-] EIP-712: Permit without verifyingContract"))?;
+        let contract = decode_hex(as_str(contract)?)?
+            .try_into()
+            .map_err(|_| anyhow!("[-] EIP-712: {} is not an address", contract))?;
+        let amount = match (self.message.get("value"), self.message.get("allowed")) {
+            (Some(value), _) => parse_integer(value, false)?,
+            (None, Some(Value::Bool(allowed))) => [if *allowed { 0xff } else { 0 }; 32],
+            _ => bail!("[-] EIP-712: Permit without value"),
+        };
+        Ok(Some((contract, amount)))
+    }
+
+    /// The fields of the domain and the message, one per line, for the
+    /// approval of the signature.
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        let sections = [
+            ("domain", &self.domain),
+            (&self.primary_type[..], &self.message),
+        ];
+        for &(title, fields) in sections.iter() {
+            description.push_str(&format!("{}:\n", title));
+            for (name, value) in fields {
+                description.push_str(&format!("  {}: {}\n", name, value));
+            }
+        }
+        description
+    }
+
+    fn fields(&self, type_: &str) -> Result<&Vec<Field>> {
+        self.types
+            .get(type_)
+            .ok_or_else(|| anyhow!("[-] EIP-712: undefined type {}", type_))
+    }
+
+    fn hash_struct(&self, type_: &str, value: &Map<String, Value>) -> Result<[u8; 32]> {
+        let mut data = keccak(self.encode_type(type_)?.as_bytes()).to_vec();
+        for field in self.fields(type_)? {
+            let field_value = value.get(&field.name).unwrap_or(&Value::Null);
+            data.extend_from_slice(&self.encode_value(&field.type_, field_value)?);
+        }
+        Ok(keccak(&data))
+    }
+
+    // `Type(fields)` of the type followed by the ones of the structs it
+    // references, sorted by name.
+    fn encode_type(&self, type_: &str) -> Result<String> {
+        let mut dependencies = BTreeSet::new();
+        self.collect_dependencies(type_, &mut dependencies)?;
+        dependencies.remove(type_);
+        let mut encoded = String::new();
+        for name in std::iter::once(type_).chain(dependencies.iter().map(String::as_str)) {
+            let fields: Vec<String> = self
+                .fields(name)?
+                .iter()
+                .map(|field| format!("{} {}", field.type_, field.name))
+                .collect();
+            encoded.push_str(&format!("{}({})", name, fields.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    fn collect_dependencies(&self, type_: &str, dependencies: &mut BTreeSet<String>) -> Result<()> {
+        if dependencies.contains(type_) {
+            return Ok(());
+        }
+        dependencies.insert(type_.to_string());
+        for field in self.fields(type_)? {
+            let base = base_type(&field.type_);
+            if self.types.contains_key(base) {
+                self.collect_dependencies(base, dependencies)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_value(&self, type_: &str, value: &Value) -> Result<[u8; 32]> {
+        if let Some(element_type) = array_element_type(type_) {
+            let elements = value
+                .as_array()
+                .ok_or_else(|| anyhow!("[-] EIP-712: {} is not an array", value))?;
+            let mut data = Vec::with_capacity(elements.len() * 32);
+            for element in elements {
+                data.extend_from_slice(&self.encode_value(element_type, element)?);
+            }
+            return Ok(keccak(&data));
+        }
+        if self.types.contains_key(type_) {
+            let fields = value
+                .as_object()
+                .ok_or_else(|| anyhow!("[-] EIP-712: {} is not a {}", value, type_))?;
+            return self.hash_struct(type_, fields);
+        }
+        match type_ {
+            "string" => Ok(keccak(as_str(value)?.as_bytes())),
+            "bytes" => Ok(keccak(&decode_hex(as_str(value)?)?)),
+            "bool" => match value {
+                Value::Bool(b) => Ok(uint_word(*b as u128)),
+                _ => bail!("[-] EIP-712: {} is not a bool", value),
+            },
+            "address" => {
+                let address = decode_hex(as_str(value)?)?;
+                if address.len() != 20 {
+                    bail!("[-] EIP-712: {} is not an address", value);
+                }
+                let mut word = [0u8; 32];
+                word[12..].copy_from_slice(&address);
+                Ok(word)
+            }
+            _ if type_.starts_with("bytes") => {
+                let bytes = decode_hex(as_str(value)?)?;
+                let size: usize = type_["bytes".len()..]
+                    .parse()
+                    .map_err(|_| anyhow!("[-] EIP-712: unknown type {}", type_))?;
+                if bytes.len() != size || size > 32 {
+                    bail!("[-] EIP-712: {} is not a {}", value, type_);
+                }
+                let mut word = [0u8; 32];
+                word[..size].copy_from_slice(&bytes);
+                Ok(word)
+            }
+            _ if type_.starts_with("uint") => parse_integer(value, false),
+            _ if type_.starts_with("int") => parse_integer(value, true),
+            _ => bail!("[-] EIP-712: unknown type {}", type_),
+        }
+    }
+}
+
+fn keccak(data: &[u8]) -> [u8; 32] {
+    // The hash of Keccak-256 is always 32 bytes
+    keccak_hash_to_bytes(data).try_into().unwrap()
+}
+
+// `Type` of `Type`, `Type[]` and `Type[2][]`.
+fn base_type(type_: &str) -> &str {
+    type_.split('[').next().unwrap_or(type_)
+}
+
+// `Type[2]` of `Type[2][]`, or `None` if `type_` is not an array.
+fn array_element_type(type_: &str) -> Option<&str> {
+    if type_.ends_with(']') {
+        type_.rfind('[').map(|index| &type_[..index])
+    } else {
+        None
+    }
+}
+
+fn as_str(value: &Value) -> Result<&str> {
+    value
+        .as_str()
+        .ok_or_else(|| anyhow!("[-] EIP-712: {} is not a string", value))
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(digits).map_err(|e| anyhow!("[-] EIP-712: invalid hex {}: {:?}", value, e))
+}
+
+fn uint_word(value: u128) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[16..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+// Integers are JSON numbers, or decimal or hexadecimal strings for the ones
+// too large for the numbers of JavaScript, encoded as 256-bit two's
+// complement words.
+fn parse_integer(value: &Value, signed: bool) -> Result<[u8; 32]> {
+    let invalid = || anyhow!("[-] EIP-712: {} is not an integer", value);
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.clone(),
+        _ => return Err(invalid()),
+    };
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) if signed => (true, digits),
+        Some(_) => return Err(invalid()),
+        None => (false, &text[..]),
+    };
+    let mut word = [0u8; 32];
+    if let Some(hex_digits) = digits.strip_prefix("0x") {
+        let bytes = decode_hex(&format!("{:0>64}", hex_digits)).map_err(|_| invalid())?;
+        if bytes.len() != 32 {
+            return Err(invalid());
+        }
+        word.copy_from_slice(&bytes);
+    } else {
+        if digits.is_empty() {
+            return Err(invalid());
+        }
+        for digit in digits.chars() {
+            let mut carry = digit.to_digit(10).ok_or_else(invalid)?;
+            for byte in word.iter_mut().rev() {
+                let product = *byte as u32 * 10 + carry;
+                *byte = product as u8;
+                carry = product >> 8;
+            }
+            if carry != 0 {
+                return Err(invalid());
+            }
+        }
+    }
+    if negative {
+        // Two's complement: invert and add one
+        let mut carry = 1u16;
+        for byte in word.iter_mut().rev() {
+            let sum = (!*byte) as u16 + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+    }
+    Ok(word)
+}
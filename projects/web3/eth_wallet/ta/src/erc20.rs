@@ -0,0 +1,142 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use uuid::Uuid;
+
+use crate::hash::keccak_hash_to_bytes;
+use proto::{Erc20Transfer, EthTransaction};
+use secure_db::Storable;
+
+/// The limit on the ERC-20 transfers and approvals of a token by a wallet,
+/// checked by the TA on every transaction it signs for the token contract.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenPolicy {
+    pub wallet_id: Uuid,
+    pub contract: [u8; 20],
+    pub max_amount: u128,
+}
+
+impl TokenPolicy {
+    pub fn key(wallet_id: &Uuid, contract: &[u8; 20]) -> String {
+        format!("{}-{}", wallet_id, hex::encode(contract))
+    }
+
+    /// Checks `amount`, a 256-bit big-endian word, against the limit.
+    pub fn check_amount(&self, amount: &[u8; 32]) -> Result<()> {
+        let (high, low) = amount.split_at(16);
+        if high.iter().any(|b| *b != 0) {
+            bail!(
+                "[-] ERC-20 policy: the amount exceeds the limit {} of the token",
+                self.max_amount
+            );
+        }
+        let amount = u128::from_be_bytes(low.try_into()?);
+        if amount > self.max_amount {
+            bail!(
+                "[-] ERC-20 policy: the amount {} exceeds the limit {} of the token",
+                amount,
+                self.max_amount
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks the calldata `data` of a transaction to the token contract.
+    /// The amounts of the calls moving or approving tokens are checked
+    /// against the limit, and other calls are refused, as the TA cannot tell
+    /// what they move. Transactions without calldata are allowed.
+    pub fn check_call(&self, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let index = TOKEN_CALLS
+            .iter()
+            .find(|(signature, _)| data.starts_with(&selector(signature)))
+            .map(|(_, index)| *index)
+            .ok_or_else(|| anyhow!("[-] ERC-20 policy: unknown call of the token contract"))?;
+        let offset = 4 + 32 * index;
+        let amount = data
+            .get(offset..offset + 32)
+            .ok_or_else(|| anyhow!("[-] ERC-20 policy: truncated call of the token contract"))?;
+        self.check_amount(amount.try_into()?)
+    }
+}
+
+impl Storable for TokenPolicy {
+    type Key = String;
+
+    fn unique_id(&self) -> Self::Key {
+        Self::key(&self.wallet_id, &self.contract)
+    }
+}
+
+// The calls moving or approving tokens, and the index of their amount among
+// their 32-byte arguments
+const TOKEN_CALLS: [(&str, usize); 5] = [
+    ("transfer(address,uint256)", 1),
+    ("approve(address,uint256)", 1),
+    ("increaseAllowance(address,uint256)", 1),
+    ("transferFrom(address,address,uint256)", 2),
+    // ERC-677, e.g. of the LINK token
+    ("transferAndCall(address,uint256,bytes)", 1),
+];
+
+fn selector(signature: &str) -> Vec<u8> {
+    keccak_hash_to_bytes(signature)[..4].to_vec()
+}
+
+// The calldata of `transfer(address,uint256)`: the selector followed by the
+// recipient and the amount, each left-padded to 32 bytes.
+fn transfer_data(recipient: &[u8; 20], amount: u128) -> Vec<u8> {
+    let mut data = selector("transfer(address,uint256)");
+    data.extend_from_slice(&[0u8; 12]);
+    data.extend_from_slice(recipient);
+    data.extend_from_slice(&[0u8; 16]);
+    data.extend_from_slice(&amount.to_be_bytes());
+    data
+}
+
+/// Builds the transaction calling the token contract for `transfer`. Like
+/// any other transaction, it is checked against the policy of the wallet for
+/// the token before being signed.
+pub fn transfer_transaction(transfer: &Erc20Transfer) -> Result<EthTransaction> {
+    if transfer.contract == [0u8; 20] {
+        bail!("[-] ERC-20 transfer: the token contract is the zero address");
+    }
+    if transfer.recipient == [0u8; 20] {
+        bail!("[-] ERC-20 transfer: the recipient is the zero address");
+    }
+    // Tokens sent to their own contract are usually lost
+    if transfer.recipient == transfer.contract {
+        bail!("[-] ERC-20 transfer: the recipient is the token contract");
+    }
+    if transfer.amount == 0 {
+        bail!("[-] ERC-20 transfer: the amount is zero");
+    }
+    Ok(EthTransaction {
+        chain_id: transfer.chain_id,
+        nonce: transfer.nonce,
+        to: Some(transfer.contract),
+        value: 0,
+        gas_price: transfer.gas_price,
+        gas: transfer.gas,
+        data: transfer_data(&transfer.recipient, transfer.amount),
+    })
+}
@@ -17,15 +17,21 @@
 
 #![no_main]
 
+mod eip712;
+mod erc20;
 mod hash;
 mod wallet;
 
+use optee_utee::arena::ArenaWriter;
+use optee_utee::dispatch::{Decode, Encode};
 use optee_utee::prelude::*;
 use optee_utee::{Error, ErrorKind};
 use proto::Command;
 use secure_db::SecureStorageClient;
 
 use anyhow::{anyhow, bail, Result};
+use erc20::TokenPolicy;
+use uuid::Uuid;
 use wallet::Wallet;
 
 const DB_NAME: &str = "eth_wallet_db";
@@ -83,6 +89,11 @@ fn remove_wallet(input: &proto::RemoveWalletInput) -> Result<proto::RemoveWallet
 
     let db_client = SecureStorageClient::open(DB_NAME)?;
     db_client.delete_entry::<Wallet>(&input.wallet_id)?;
+    for (key, policy) in db_client.list_entries::<TokenPolicy>()? {
+        if policy.wallet_id == input.wallet_id {
+            db_client.delete_entry::<TokenPolicy>(&key)?;
+        }
+    }
     dbg_println!("[+] Wallet removed");
 
     Ok(proto::RemoveWalletOutput {})
@@ -105,6 +116,37 @@ fn derive_address(input: &proto::DeriveAddressInput) -> Result<proto::DeriveAddr
     })
 }
 
+// The policy of the wallet for the token at `contract`, if any. A failure to
+// read the policies fails the signature rather than skipping the check.
+fn token_policy(
+    db_client: &SecureStorageClient,
+    wallet_id: &Uuid,
+    contract: &[u8; 20],
+) -> Result<Option<TokenPolicy>> {
+    let mut policies = db_client.list_entries::<TokenPolicy>()?;
+    Ok(policies.remove(&TokenPolicy::key(wallet_id, contract)))
+}
+
+// Checks a transaction of the wallet to `to` with the calldata `data`
+// against the policy of the token at `to`, if any. Every command signing a
+// transaction goes through this check.
+fn check_token_policy(
+    db_client: &SecureStorageClient,
+    wallet_id: &Uuid,
+    to: Option<[u8; 20]>,
+    data: &[u8],
+) -> Result<()> {
+    let contract = match to {
+        Some(contract) => contract,
+        // Contract creations call no token
+        None => return Ok(()),
+    };
+    match token_policy(db_client, wallet_id, &contract)? {
+        Some(policy) => policy.check_call(data),
+        None => Ok(()),
+    }
+}
+
 fn sign_transaction(input: &proto::SignTransactionInput) -> Result<proto::SignTransactionOutput> {
     let db_client = SecureStorageClient::open(DB_NAME)?;
     let wallet = db_client
@@ -112,28 +154,178 @@ fn sign_transaction(input: &proto::SignTransactionInput) -> Result<proto::SignTr
         .map_err(|e| anyhow!("[+] Sign transaction: error: wallet not found: {:?}", e))?;
     dbg_println!("[+] Sign transaction: wallet loaded");
 
+    check_token_policy(
+        &db_client,
+        &input.wallet_id,
+        input.transaction.to,
+        &input.transaction.data,
+    )?;
     let signature = wallet.sign_transaction(&input.hd_path, &input.transaction)?;
     dbg_println!("[+] Sign transaction: signature: {:?}", signature);
 
     Ok(proto::SignTransactionOutput { signature })
 }
 
-fn handle_invoke(command: Command, serialized_input: &[u8]) -> Result<Vec<u8>> {
+fn sign_eip1559_transaction(
+    input: &proto::SignEip1559TransactionInput,
+) -> Result<proto::SignTransactionOutput> {
+    let db_client = SecureStorageClient::open(DB_NAME)?;
+    let wallet = db_client.get::<Wallet>(&input.wallet_id).map_err(|e| {
+        anyhow!(
+            "[+] Sign EIP-1559 transaction: error: wallet not found: {:?}",
+            e
+        )
+    })?;
+    dbg_println!("[+] Sign EIP-1559 transaction: wallet loaded");
+
+    check_token_policy(
+        &db_client,
+        &input.wallet_id,
+        input.transaction.to,
+        &input.transaction.data,
+    )?;
+    let signature = wallet.sign_eip1559_transaction(&input.hd_path, &input.transaction)?;
+    dbg_println!("[+] Sign EIP-1559 transaction: signature: {:?}", signature);
+
+    Ok(proto::SignTransactionOutput { signature })
+}
+
+fn sign_erc20_transfer(
+    input: &proto::SignErc20TransferInput,
+) -> Result<proto::SignTransactionOutput> {
+    let db_client = SecureStorageClient::open(DB_NAME)?;
+    let wallet = db_client
+        .get::<Wallet>(&input.wallet_id)
+        .map_err(|e| anyhow!("[+] Sign ERC-20 transfer: error: wallet not found: {:?}", e))?;
+    dbg_println!("[+] Sign ERC-20 transfer: wallet loaded");
+
+    let transaction = erc20::transfer_transaction(&input.transfer)?;
+    check_token_policy(
+        &db_client,
+        &input.wallet_id,
+        transaction.to,
+        &transaction.data,
+    )?;
+    let signature = wallet.sign_transaction(&input.hd_path, &transaction)?;
+    dbg_println!("[+] Sign ERC-20 transfer: signature: {:?}", signature);
+
+    Ok(proto::SignTransactionOutput { signature })
+}
+
+fn set_token_policy(input: &proto::SetTokenPolicyInput) -> Result<proto::SetTokenPolicyOutput> {
+    let db_client = SecureStorageClient::open(DB_NAME)?;
+    // Only the policies of existing wallets are stored
+    let wallet = db_client
+        .get::<Wallet>(&input.wallet_id)
+        .map_err(|e| anyhow!("[+] Set token policy: error: wallet not found: {:?}", e))?;
+
+    // Anyone able to invoke the TA may add or lower a limit, but raising or
+    // removing one takes the mnemonic, without which the limit holds even
+    // against a compromised normal world
+    let current = token_policy(&db_client, &input.wallet_id, &input.contract)?;
+    let relaxed = match (&current, input.max_amount) {
+        (Some(current), Some(max_amount)) => max_amount > current.max_amount,
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+    if relaxed {
+        let mnemonic = input.mnemonic.as_deref().ok_or_else(|| {
+            anyhow!("[-] Set token policy: raising or removing a limit requires the mnemonic")
+        })?;
+        wallet.check_mnemonic(mnemonic)?;
+    }
+
+    let key = TokenPolicy::key(&input.wallet_id, &input.contract);
+    match input.max_amount {
+        Some(max_amount) => db_client.put(&TokenPolicy {
+            wallet_id: input.wallet_id,
+            contract: input.contract,
+            max_amount,
+        })?,
+        None => {
+            if current.is_some() {
+                db_client.delete_entry::<TokenPolicy>(&key)?;
+            }
+        }
+    }
+    dbg_println!("[+] Set token policy: {:?}", input.max_amount);
+
+    Ok(proto::SetTokenPolicyOutput {})
+}
+
+fn sign_typed_data(input: &proto::SignTypedDataInput) -> Result<proto::SignTypedDataOutput> {
+    let db_client = SecureStorageClient::open(DB_NAME)?;
+    let wallet = db_client
+        .get::<Wallet>(&input.wallet_id)
+        .map_err(|e| anyhow!("[+] Sign typed data: error: wallet not found: {:?}", e))?;
+    dbg_println!("[+] Sign typed data: wallet loaded");
+
+    let typed_data = eip712::TypedData::from_json(&input.typed_data)?;
+    if let Some((contract, amount)) = typed_data.permit()? {
+        if let Some(policy) = token_policy(&db_client, &input.wallet_id, &contract)? {
+            policy.check_amount(&amount)?;
+        }
+    }
+    let hash = typed_data.signing_hash()?;
+    // Shown on the secure console, so that what is signed can be checked
+    // independently of the normal world
+    trace_println!("[+] Sign typed data:\n{}", typed_data.describe());
+    let signature = wallet.sign_hash(&input.hd_path, &hash)?;
+    dbg_println!("[+] Sign typed data: signature: {:?}", signature);
+
+    Ok(proto::SignTypedDataOutput { hash, signature })
+}
+
+/// The bincode encoding of the inputs and outputs of the commands.
+struct Bincode;
+
+impl<T: serde::de::DeserializeOwned> Decode<T> for Bincode {
+    fn decode(bytes: &[u8]) -> optee_utee::Result<T> {
+        bincode::deserialize(bytes).map_err(|_| ErrorKind::BadFormat.into())
+    }
+}
+
+impl<T: serde::Serialize> Encode<T> for Bincode {
+    fn encode(value: &T) -> optee_utee::Result<Vec<u8>> {
+        bincode::serialize(value).map_err(|_| ErrorKind::BadFormat.into())
+    }
+
+    // Outputs are serialized in the command arena instead of a temporary
+    // buffer on the heap
+    fn encode_into(value: &T, writer: &mut ArenaWriter<'_>) -> optee_utee::Result<()> {
+        bincode::serialize_into(writer, value).map_err(|e| match *e {
+            bincode::ErrorKind::Io(_) => ErrorKind::OutOfMemory.into(),
+            _ => ErrorKind::BadFormat.into(),
+        })
+    }
+}
+
+/// Handles `command`, the outer error is reported to the host as a message,
+/// the inner one is the result of writing the output.
+fn handle_invoke(
+    command: Command,
+    input: &ParameterMemrefInput<'_>,
+    output: &mut ParameterMemrefOutput<'_>,
+) -> Result<optee_utee::Result<()>> {
     fn process<T: serde::de::DeserializeOwned, U: serde::Serialize, F: Fn(&T) -> Result<U>>(
-        serialized_input: &[u8],
+        input: &ParameterMemrefInput<'_>,
+        output: &mut ParameterMemrefOutput<'_>,
         handler: F,
-    ) -> Result<Vec<u8>> {
-        let input: T = bincode::deserialize(serialized_input)?;
-        let output = handler(&input)?;
-        let serialized_output = bincode::serialize(&output)?;
-        Ok(serialized_output)
+    ) -> Result<optee_utee::Result<()>> {
+        let input: T = Bincode::decode(input.get_buffer())?;
+        let value = handler(&input)?;
+        Ok(output.serialize_into::<Bincode, _>(&value))
     }
 
     match command {
-        Command::CreateWallet => process(serialized_input, create_wallet),
-        Command::RemoveWallet => process(serialized_input, remove_wallet),
-        Command::DeriveAddress => process(serialized_input, derive_address),
-        Command::SignTransaction => process(serialized_input, sign_transaction),
+        Command::CreateWallet => process(input, output, create_wallet),
+        Command::RemoveWallet => process(input, output, remove_wallet),
+        Command::DeriveAddress => process(input, output, derive_address),
+        Command::SignTransaction => process(input, output, sign_transaction),
+        Command::SignEip1559Transaction => process(input, output, sign_eip1559_transaction),
+        Command::SignErc20Transfer => process(input, output, sign_erc20_transfer),
+        Command::SetTokenPolicy => process(input, output, set_token_policy),
+        Command::SignTypedData => process(input, output, sign_typed_data),
         _ => bail!("Unsupported command"),
     }
 }
@@ -151,16 +343,14 @@ fn invoke_command(
     dbg_println!("[+] TA invoke command");
 
     p1.set_updated_size(0)?;
-    let output_vec = match handle_invoke(Command::from(cmd_id), p0.get_buffer()) {
-        Ok(output) => output,
+    match handle_invoke(Command::from(cmd_id), p0, p1) {
+        Ok(result) => result,
         Err(e) => {
             let err_message = format!("{:?}", e);
             p1.set_output(err_message)?;
-            return Err(Error::new(ErrorKind::BadParameters));
+            Err(Error::new(ErrorKind::BadParameters))
         }
-    };
-    p1.set_output(output_vec)?;
-    Ok(())
+    }
 }
 
 include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));
@@ -15,16 +15,18 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use bip32::{Mnemonic, XPrv};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::convert::{TryFrom, TryInto};
 use uuid::Uuid;
 
 use crate::hash::keccak_hash_to_bytes;
 use ethereum_tx_sign::Transaction;
+use optee_utee::secure_mem::ct_cmp;
 use optee_utee::Random;
-use proto::EthTransaction;
+use proto::{Eip1559Transaction, EthTransaction};
 use secure_db::Storable;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -70,6 +72,19 @@ impl Wallet {
         Ok(mnemonic.phrase().to_string())
     }
 
+    /// Checks that `phrase` is the mnemonic of the wallet, which only its
+    /// owner holds, e.g. to authorize raising the limit of a token policy.
+    pub fn check_mnemonic(&self, phrase: &str) -> Result<()> {
+        let mnemonic = self.get_mnemonic()?;
+        let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+        if phrase.len() != mnemonic.len()
+            || ct_cmp(phrase.as_bytes(), mnemonic.as_bytes()) != Ordering::Equal
+        {
+            bail!("[-] Wallet::check_mnemonic(): wrong mnemonic");
+        }
+        Ok(())
+    }
+
     pub fn get_seed(&self) -> Result<Vec<u8>> {
         let mnemonic = Mnemonic::from_entropy(
             self.entropy.as_slice().try_into()?,
@@ -106,7 +121,6 @@ impl Wallet {
     }
 
     pub fn sign_transaction(&self, hd_path: &str, transaction: &EthTransaction) -> Result<Vec<u8>> {
-        let xprv = self.derive_prv_key(hd_path)?;
         let legacy_transaction = ethereum_tx_sign::LegacyTransaction {
             chain: transaction.chain_id,
             nonce: transaction.nonce,
@@ -116,12 +130,68 @@ impl Wallet {
             value: transaction.value,
             data: transaction.data.clone(),
         };
-        let ecdsa = legacy_transaction.ecdsa(&xprv).map_err(|e| {
+        self.sign(hd_path, &legacy_transaction)
+    }
+
+    pub fn sign_eip1559_transaction(
+        &self,
+        hd_path: &str,
+        transaction: &Eip1559Transaction,
+    ) -> Result<Vec<u8>> {
+        // Nodes reject a priority fee above the maximum fee
+        if transaction.max_priority_fee_per_gas > transaction.max_fee_per_gas {
+            bail!(
+                "[-] Wallet::sign_eip1559_transaction(): max_priority_fee_per_gas {} exceeds max_fee_per_gas {}",
+                transaction.max_priority_fee_per_gas,
+                transaction.max_fee_per_gas
+            );
+        }
+        let access_list = transaction
+            .access_list
+            .iter()
+            .map(|item| ethereum_tx_sign::Access {
+                address: item.address,
+                storage_keys: item.storage_keys.clone(),
+            })
+            .collect();
+        let fee_market_transaction = ethereum_tx_sign::FeeMarketTransaction {
+            chain: transaction.chain_id,
+            nonce: transaction.nonce,
+            max_priority_fee_per_gas: transaction.max_priority_fee_per_gas,
+            max_fee_per_gas: transaction.max_fee_per_gas,
+            gas: transaction.gas,
+            to: transaction.to,
+            value: transaction.value,
+            data: transaction.data.clone(),
+            access_list: ethereum_tx_sign::AccessList(access_list),
+        };
+        self.sign(hd_path, &fee_market_transaction)
+    }
+
+    /// Signs the 32 bytes `hash`, e.g. the signing hash of EIP-712 typed
+    /// data, returning the 65 bytes signature `r || s || v` with `v` of 27
+    /// or 28.
+    pub fn sign_hash(&self, hd_path: &str, hash: &[u8; 32]) -> Result<Vec<u8>> {
+        let xprv = self.derive_prv_key(hd_path)?;
+        let secret_key = secp256k1::SecretKey::from_slice(&xprv)?;
+        let message = secp256k1::Message::from_slice(hash)?;
+        let (recovery_id, compact) = secp256k1::Secp256k1::signing_only()
+            .sign_ecdsa_recoverable(&message, &secret_key)
+            .serialize_compact();
+        let mut signature = compact.to_vec();
+        signature.push(27 + recovery_id.to_i32() as u8);
+        Ok(signature)
+    }
+
+    // Returns the signed transaction, RLP encoded and prefixed with its type
+    // for the typed transactions, ready to be broadcast.
+    fn sign<T: Transaction>(&self, hd_path: &str, transaction: &T) -> Result<Vec<u8>> {
+        let xprv = self.derive_prv_key(hd_path)?;
+        let ecdsa = transaction.ecdsa(&xprv).map_err(|e| {
             let ethereum_tx_sign::Error::Secp256k1(inner_error) = e;
             inner_error
         })?;
-        let signature = legacy_transaction.sign(&ecdsa);
-        Ok(signature)
+        Ok(transaction.sign(&ecdsa))
     }
 }
 
@@ -0,0 +1,39 @@
+#!/bin/bash
+
+# Licensed to the Apache Software Foundation (ASF) under one
+# or more contributor license agreements.  See the NOTICE file
+# distributed with this work for additional information
+# regarding copyright ownership.  The ASF licenses this file
+# to you under the Apache License, Version 2.0 (the
+# "License"); you may not use this file except in compliance
+# with the License.  You may obtain a copy of the License at
+#
+#   http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing,
+# software distributed under the License is distributed on an
+# "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+# KIND, either express or implied.  See the License for the
+# specific language governing permissions and limitations
+# under the License.
+
+set -xe
+
+# Include base script
+source setup.sh
+
+# Copy TA and host binary
+copy_ta_to_qemu ../examples/crypto_bench-rs/ta/target/$TARGET_TA/release/*.ta
+copy_ca_to_qemu ../examples/crypto_bench-rs/host/target/$TARGET_HOST/release/crypto_bench-rs
+
+# Run script specific commands in QEMU
+OUTPUT=$(run_in_qemu_with_timeout_secs "crypto_bench-rs" 60) || print_detail_and_exit
+
+# Script specific checks
+{
+    grep -q "aes-gcm-128 encrypt" <<< "$OUTPUT" &&
+    grep -q "aes-gcm-128 decrypt" <<< "$OUTPUT" &&
+    grep -q "ecdsa-p256 sign" <<< "$OUTPUT" &&
+    grep -q "backup blob wrap" <<< "$OUTPUT" &&
+    grep -q "Benchmark finished" <<< "$OUTPUT"
+} || print_detail_and_exit
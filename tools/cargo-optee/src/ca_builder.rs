@@ -19,12 +19,15 @@ use crate::cargo_command;
 use crate::common;
 use crate::common::{
     BuildMode, ChangeDirectoryGuard, get_package_name, get_target_and_cross_compile,
-    get_target_directory_from_metadata, print_cargo_command, print_output_and_bail,
-    read_uuid_from_file,
+    get_target_directory_from_metadata, hex, needs_build_std, print_cargo_command,
+    print_output_and_bail, read_uuid_from_file,
 };
 use crate::config::CaBuildConfig;
+use crate::message::{self, Message};
+use crate::status;
 
 use anyhow::{Result, bail};
+use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 
 // Main function to build the CA, optionally installing to a target directory
@@ -35,14 +38,18 @@ pub fn build_ca(config: CaBuildConfig, install_dir: Option<&Path>) -> Result<()>
     let component_type = if config.plugin { "Plugin" } else { "CA" };
     // Get the absolute path for better clarity
     let absolute_path = std::fs::canonicalize(&config.path).unwrap_or_else(|_| config.path.clone());
-    println!(
+    status!(
         "Building {} in directory: {}",
         component_type,
         absolute_path.display()
     );
 
-    // Step 1: Run clippy for code quality checks
-    run_clippy(&config)?;
+    // Step 1: Run clippy for code quality checks, unless skipped
+    if config.skip_checks {
+        status!("Skipping cargo fmt and clippy");
+    } else {
+        run_clippy(&config)?;
+    }
 
     // Step 2: Build the CA
     build_binary(&config)?;
@@ -55,16 +62,16 @@ pub fn build_ca(config: CaBuildConfig, install_dir: Option<&Path>) -> Result<()>
         .canonicalize()
         .unwrap_or_else(|_| final_binary.clone());
     if config.plugin {
-        println!("Plugin copied to: {}", absolute_final_binary.display());
+        status!("Plugin copied to: {}", absolute_final_binary.display());
     } else {
-        println!(
+        status!(
             "CA binary stripped and saved to: {}",
             absolute_final_binary.display()
         );
     }
 
     // Step 4: Install if requested
-    if let Some(install_dir) = install_dir {
+    let installed = if let Some(install_dir) = install_dir {
         use std::fs;
 
         // Check if install directory exists
@@ -82,20 +89,37 @@ pub fn build_ca(config: CaBuildConfig, install_dir: Option<&Path>) -> Result<()>
         let dest_path = install_dir.join(package_name);
         fs::copy(&final_binary, &dest_path)?;
 
-        println!(
-            "{} installed to: {:?}",
-            component_type,
-            dest_path.canonicalize().unwrap_or(dest_path)
-        );
+        let dest_path = dest_path.canonicalize().unwrap_or(dest_path);
+        status!("{} installed to: {:?}", component_type, dest_path);
+        Some(dest_path)
+    } else {
+        None
+    };
+
+    if message::is_json() {
+        let uuid = config
+            .uuid_path
+            .as_ref()
+            .map(|uuid_path| read_uuid_from_file(uuid_path))
+            .transpose()?;
+        message::emit(&Message::Artifact {
+            component: if config.plugin { "plugin" } else { "ca" },
+            package: &get_package_name()?,
+            path: &absolute_final_binary,
+            installed: installed.as_deref(),
+            uuid: uuid.as_deref(),
+            sha256: hex(&Sha256::digest(std::fs::read(&final_binary)?)),
+            shdr_digest: None,
+        });
     }
 
-    println!("{} build successfully!", component_type);
+    status!("{} build successfully!", component_type);
 
     Ok(())
 }
 
 fn run_clippy(config: &CaBuildConfig) -> Result<()> {
-    println!("Running cargo fmt and clippy...");
+    status!("Running cargo fmt and clippy...");
 
     // Run cargo fmt
     let fmt_output = cargo_command().arg("fmt").output()?;
@@ -109,16 +133,16 @@ fn run_clippy(config: &CaBuildConfig) -> Result<()> {
 
     let mut clippy_cmd = cargo_command();
     clippy_cmd.arg("clippy");
+    if needs_build_std(config.arch) {
+        clippy_cmd.arg("-Z").arg("build-std");
+    }
     clippy_cmd.arg("--target").arg(&target);
 
     // Set OPTEE_CLIENT_EXPORT environment variable for build scripts
     clippy_cmd.env("OPTEE_CLIENT_EXPORT", &config.optee_client_export);
 
     clippy_cmd.arg("--");
-    clippy_cmd.arg("-D").arg("warnings");
-    clippy_cmd.arg("-D").arg("clippy::unwrap_used");
-    clippy_cmd.arg("-D").arg("clippy::expect_used");
-    clippy_cmd.arg("-D").arg("clippy::panic");
+    clippy_cmd.args(config.lints.clippy_args());
 
     let clippy_output = clippy_cmd.output()?;
 
@@ -131,13 +155,16 @@ fn run_clippy(config: &CaBuildConfig) -> Result<()> {
 
 fn build_binary(config: &CaBuildConfig) -> Result<()> {
     let component_type = if config.plugin { "Plugin" } else { "CA" };
-    println!("Building {} binary...", component_type);
+    status!("Building {} binary...", component_type);
 
     // Determine target and cross-compile based on arch (CA runs in Normal World Linux)
     let (target, cross_compile) = get_target_and_cross_compile(config.arch, BuildMode::Ca)?;
 
     let mut build_cmd = cargo_command();
     build_cmd.arg("build");
+    if needs_build_std(config.arch) {
+        build_cmd.arg("-Z").arg("build-std");
+    }
     build_cmd.arg("--target").arg(&target);
 
     // Add --no-default-features if specified
@@ -167,10 +194,16 @@ fn build_binary(config: &CaBuildConfig) -> Result<()> {
         build_cmd.env(key, value);
     }
 
+    // Forward the warnings of the compiler as JSON messages
+    if message::is_json() {
+        build_cmd.arg("--message-format").arg("json");
+    }
+
     // Print the full cargo build command for debugging
     print_cargo_command(&build_cmd, "Building CA binary");
 
     let build_output = build_cmd.output()?;
+    message::emit_diagnostics(&build_output.stdout);
 
     if !build_output.status.success() {
         print_output_and_bail("build", &build_output)?;
@@ -188,7 +221,7 @@ fn post_build(config: &CaBuildConfig) -> Result<PathBuf> {
 }
 
 fn copy_plugin(config: &CaBuildConfig) -> Result<PathBuf> {
-    println!("Processing plugin...");
+    status!("Processing plugin...");
 
     // Determine target based on arch (CA runs in Normal World Linux)
     let (target, _cross_compile) = get_target_and_cross_compile(config.arch, BuildMode::Ca)?;
@@ -226,7 +259,7 @@ fn copy_plugin(config: &CaBuildConfig) -> Result<PathBuf> {
 }
 
 fn strip_binary(config: &CaBuildConfig) -> Result<PathBuf> {
-    println!("Stripping binary...");
+    status!("Stripping binary...");
 
     // Determine target and cross-compile based on arch (CA runs in Normal World Linux)
     let (target, cross_compile) = get_target_and_cross_compile(config.arch, BuildMode::Ca)?;
@@ -0,0 +1,223 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::cargo_command;
+use crate::common::{
+    ChangeDirectoryGuard, get_target_directory_from_metadata, print_output_and_bail,
+    read_uuid_from_file,
+};
+use crate::config::{ComponentType, find_package, resolve_uuid_path_of};
+use crate::project_uuid::workspace_ta_uuids;
+use crate::status;
+
+/// Options of `cargo optee clean`
+pub struct CleanConfig {
+    pub project_path: PathBuf,
+    /// Component to clean, only its package is cleaned. All the components
+    /// of the metadata and the whole target directory if `None`.
+    pub component: Option<ComponentType>,
+    /// Directory the components were installed to, whose installed binaries
+    /// are removed
+    pub install_dir: Option<PathBuf>,
+    /// UUID file of the TA or plugin, overriding the metadata
+    pub uuid_path: Option<PathBuf>,
+}
+
+/// Clean the build artifacts of a project, with the binaries cargo-optee
+/// writes next to them and installs.
+///
+/// The signed TAs of the target directory with a UUID none of the TAs of the
+/// workspace has anymore are removed too, so that a change of uuid.txt does
+/// not leave an old TA to be deployed. Other TAs of the install directory are
+/// left alone, as it is usually shared with other projects.
+pub fn clean_project(config: &CleanConfig) -> Result<()> {
+    status!("Cleaning build artifacts in: {:?}", config.project_path);
+    let package = find_package(&config.project_path)?;
+    let components = match config.component {
+        Some(component) => vec![component],
+        None => [ComponentType::Ta, ComponentType::Ca, ComponentType::Plugin]
+            .into_iter()
+            .filter(|c| {
+                package
+                    .metadata
+                    .get("optee")
+                    .and_then(|optee| optee.get(c.as_str()))
+                    .is_some()
+            })
+            .collect(),
+    };
+    // Resolved before changing directory, as paths given on the command line
+    // are relative to the current one
+    let mut artifacts = Vec::new();
+    for component in &components {
+        artifacts.extend(component_artifacts(config, *component, &package.name)?);
+    }
+    let install_dir = config
+        .install_dir
+        .as_ref()
+        .map(std::path::absolute)
+        .transpose()?;
+
+    let _guard = ChangeDirectoryGuard::new(&config.project_path)?;
+    let target_directory = get_target_directory_from_metadata()?;
+    let ta_uuids = if components.contains(&ComponentType::Ta) {
+        Some(
+            workspace_ta_uuids(&config.project_path)?
+                .into_values()
+                .collect::<HashSet<_>>(),
+        )
+    } else {
+        None
+    };
+
+    let mut cargo_clean = cargo_command();
+    cargo_clean.arg("clean");
+    if config.component.is_some() {
+        cargo_clean.arg("--package").arg(package.name.as_str());
+    }
+    let output = cargo_clean.output()?;
+    if !output.status.success() {
+        print_output_and_bail("cargo clean", &output)?;
+    }
+
+    // Also clean the intermediate cargo-optee directory if it exists
+    let intermediate_dir = target_directory.join("cargo-optee");
+    if config.component.is_none() && intermediate_dir.exists() {
+        fs::remove_dir_all(&intermediate_dir)?;
+        status!("Removed intermediate directory: {:?}", intermediate_dir);
+    }
+
+    // Left by a clean of a single package
+    for profile_dir in profile_dirs(&target_directory)? {
+        for built in artifacts.iter().filter_map(|a| a.built.as_ref()) {
+            remove_file(&profile_dir.join(built))?;
+        }
+        if let Some(uuids) = &ta_uuids {
+            remove_stale_tas(&profile_dir, uuids)?;
+        }
+    }
+    if let Some(install_dir) = install_dir {
+        for installed in artifacts.iter().filter_map(|a| a.installed.as_ref()) {
+            remove_file(&install_dir.join(installed))?;
+        }
+    }
+
+    status!("Build artifacts cleaned successfully");
+    Ok(())
+}
+
+/// A file written by cargo-optee in the profile directory, and its name once
+/// installed
+struct Artifact {
+    built: Option<String>,
+    installed: Option<String>,
+}
+
+fn component_artifacts(
+    config: &CleanConfig,
+    component: ComponentType,
+    package_name: &str,
+) -> Result<Vec<Artifact>> {
+    if component == ComponentType::Ca {
+        // Stripped in place, cargo clean removes the binary
+        return Ok(vec![Artifact {
+            built: None,
+            installed: Some(package_name.to_string()),
+        }]);
+    }
+
+    let uuid_path =
+        resolve_uuid_path_of(&config.project_path, component, config.uuid_path.clone())?;
+    let Ok(uuid) = read_uuid_from_file(&uuid_path) else {
+        status!(
+            "No UUID in {:?}, keeping the signed binaries of the {}",
+            uuid_path,
+            component.as_str().to_uppercase()
+        );
+        return Ok(Vec::new());
+    };
+    Ok(match component {
+        ComponentType::Ta => vec![
+            Artifact {
+                built: Some(format!("stripped_{}", package_name)),
+                installed: None,
+            },
+            Artifact {
+                built: Some(format!("{}.ta", uuid)),
+                installed: Some(format!("{}.ta", uuid)),
+            },
+            // Written by `cargo optee package`
+            Artifact {
+                built: Some(format!("{}.json", uuid)),
+                installed: None,
+            },
+        ],
+        _ => vec![Artifact {
+            built: Some(format!("{}.plugin.so", uuid)),
+            installed: Some(format!("{}.plugin.so", uuid)),
+        }],
+    })
+}
+
+/// The `<target>/<profile>` directories of the target directory
+fn profile_dirs(target_directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    if !target_directory.is_dir() {
+        return Ok(dirs);
+    }
+    for entry in fs::read_dir(target_directory)? {
+        let path = entry?.path();
+        for profile in ["debug", "release"] {
+            if path.join(profile).is_dir() {
+                dirs.push(path.join(profile));
+            }
+        }
+    }
+    Ok(dirs)
+}
+
+/// Remove the signed TAs of `profile_dir` whose UUID is not in `uuids`
+fn remove_stale_tas(profile_dir: &Path, uuids: &HashSet<Uuid>) -> Result<()> {
+    for entry in fs::read_dir(profile_dir)? {
+        let path = entry?.path();
+        let stale = path.extension().is_some_and(|ext| ext == "ta")
+            && path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| Uuid::parse_str(stem).ok())
+                .is_some_and(|uuid| !uuids.contains(&uuid));
+        if stale {
+            fs::remove_file(&path)?;
+            status!("Removed stale signed TA: {:?}", path);
+        }
+    }
+    Ok(())
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    if path.is_file() {
+        fs::remove_file(path)?;
+        status!("Removed: {:?}", path);
+    }
+    Ok(())
+}
@@ -18,7 +18,11 @@
 use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
 
-use crate::common::Arch;
+use crate::common::{Arch, parse_size};
+use crate::config::{ComponentType, TaProfile};
+use crate::message::MessageFormat;
+use crate::new_project::Template;
+use crate::sign::EncKeyType;
 
 #[derive(Debug, Parser)]
 #[command(version, about)]
@@ -31,8 +35,7 @@ pub struct Cli {
 pub enum Command {
     /// Build OP-TEE components
     #[clap(name = "build")]
-    #[command(subcommand)]
-    Build(BuildCommand),
+    Build(BuildArgs),
     /// Install OP-TEE components
     #[clap(name = "install")]
     #[command(subcommand)]
@@ -43,6 +46,271 @@ pub enum Command {
         #[command(flatten)]
         clean_cmd: CleanCommand,
     },
+    /// Report the size of a built Trusted Application (TA)
+    #[clap(name = "size")]
+    Size {
+        #[command(flatten)]
+        size_cmd: SizeCommand,
+    },
+    /// Create a new TA project with its host application and proto crate
+    #[clap(name = "new")]
+    New {
+        #[command(flatten)]
+        new_cmd: NewCommand,
+    },
+    /// Build a project and run its CA against its TA in the OP-TEE QEMU image
+    #[clap(name = "test")]
+    Test {
+        #[command(flatten)]
+        test_cmd: TestCommand,
+    },
+    /// Build a project, install it on QEMU or a device and run its CA
+    #[clap(name = "run")]
+    Run {
+        #[command(flatten)]
+        run_cmd: RunCommand,
+    },
+    /// Build and install a project, then rebuild and reinstall the changed components on every change
+    #[clap(name = "watch")]
+    Watch {
+        #[command(flatten)]
+        watch_cmd: WatchCommand,
+    },
+    /// Build a project, run it in QEMU halted for gdb and write a gdb script loading the symbols of its TA
+    #[clap(name = "debug")]
+    Debug {
+        #[command(flatten)]
+        debug_cmd: DebugCommand,
+    },
+    /// Build a Trusted Application (TA) and write a manifest with its UUID, version and measurements
+    #[clap(name = "package")]
+    Package {
+        #[command(flatten)]
+        package_cmd: PackageCommand,
+    },
+    /// Sign a stripped Trusted Application (TA), with the key in a file, an HSM or an external signer
+    #[clap(name = "sign")]
+    Sign {
+        #[command(flatten)]
+        sign_cmd: SignCommand,
+    },
+    /// Start, stop or inspect an OP-TEE QEMU emulator running in the background
+    #[clap(name = "emu")]
+    #[command(subcommand)]
+    Emu(EmuCommand),
+    /// Show, generate or set the UUID of a TA or plugin
+    #[clap(name = "uuid")]
+    #[command(subcommand)]
+    Uuid(UuidCommand),
+    /// Fetch the function traces of TAs built with `--profile ftrace`
+    #[clap(name = "trace")]
+    #[command(subcommand)]
+    Trace(TraceCommand),
+}
+
+impl Command {
+    /// Output format of the build, install, size and package commands, human
+    /// for the other ones
+    pub fn message_format(&self) -> MessageFormat {
+        match self {
+            Command::Build(BuildArgs {
+                cmd: None,
+                workspace,
+            }) => workspace.message_format,
+            Command::Build(BuildArgs {
+                cmd: Some(build_cmd),
+                ..
+            }) => match build_cmd {
+                BuildCommand::TA { build_cmd } => build_cmd.common.message_format,
+                BuildCommand::CA { build_cmd } => build_cmd.common.message_format,
+                BuildCommand::Plugin { build_cmd } => build_cmd.common.message_format,
+            },
+            Command::Install(install_cmd) => match install_cmd {
+                InstallCommand::TA { build_cmd, .. } => build_cmd.common.message_format,
+                InstallCommand::CA { build_cmd, .. } => build_cmd.common.message_format,
+                InstallCommand::Plugin { build_cmd, .. } => build_cmd.common.message_format,
+            },
+            Command::Size { size_cmd } => size_cmd.build_cmd.common.message_format,
+            Command::Package { package_cmd } => package_cmd.build_cmd.common.message_format,
+            _ => MessageFormat::Human,
+        }
+    }
+}
+
+/// Subcommands of `cargo optee emu`
+#[derive(Debug, Subcommand)]
+pub enum EmuCommand {
+    /// Boot the OP-TEE QEMU image in the background, downloading it if needed
+    #[clap(name = "up")]
+    Up(EmuUpArgs),
+    /// Stop the emulator
+    #[clap(name = "down")]
+    Down(EmuArgs),
+    /// Show whether the emulator is running, its consoles and how to connect to it
+    #[clap(name = "status")]
+    Status(EmuArgs),
+}
+
+/// Arguments of `cargo optee emu up`
+#[derive(Debug, Args)]
+pub struct EmuUpArgs {
+    /// Directory of the OP-TEE QEMU v8 image to boot (default: the image of --optee-version, downloaded to the cache directory)
+    #[arg(long = "image-dir")]
+    pub image_dir: Option<PathBuf>,
+
+    /// OP-TEE version of the image to download (default: the version the SDK is tested with)
+    #[arg(long = "optee-version", conflicts_with = "image_dir")]
+    pub optee_version: Option<String>,
+
+    /// Download the image with expanded TA memory, needed by large std TAs
+    #[arg(long = "expand-ta-memory", conflicts_with = "image_dir")]
+    pub expand_ta_memory: bool,
+
+    /// Folder shared with QEMU, mounted at /mnt/host in the guest (default: "shared")
+    #[arg(long = "shared-dir", default_value = "shared")]
+    pub shared_dir: PathBuf,
+
+    #[command(flatten)]
+    pub emu: EmuArgs,
+}
+
+/// Arguments selecting the emulator of `cargo optee emu`
+#[derive(Debug, Args)]
+pub struct EmuArgs {
+    /// Host port forwarded to SSH in QEMU, identifying the emulator (default: 54432)
+    #[arg(long = "ssh-port", default_value_t = 54432)]
+    pub ssh_port: u16,
+}
+
+/// Subcommands of `cargo optee uuid`
+#[derive(Debug, Subcommand)]
+pub enum UuidCommand {
+    /// Print the UUID in use
+    #[clap(name = "show")]
+    Show(UuidArgs),
+    /// Replace the UUID with a random one
+    #[clap(name = "new")]
+    New(UuidArgs),
+    /// Replace the UUID with the given one
+    #[clap(name = "set")]
+    Set {
+        /// The new UUID
+        uuid: uuid::Uuid,
+
+        #[command(flatten)]
+        args: UuidArgs,
+    },
+}
+
+/// Arguments of `cargo optee uuid`
+#[derive(Debug, Args)]
+pub struct UuidArgs {
+    /// Path to the Cargo.toml manifest file of the TA or plugin (default: Cargo.toml, or ta/Cargo.toml in the project root)
+    #[arg(long = "manifest-path")]
+    pub manifest_path: Option<PathBuf>,
+
+    /// Component the UUID identifies (default: ta)
+    #[arg(long = "component", value_enum, default_value_t = ComponentType::Ta)]
+    pub component: ComponentType,
+
+    /// UUID file path (default: from the metadata, or "../uuid.txt")
+    #[arg(long = "uuid-path")]
+    pub uuid_path: Option<PathBuf>,
+}
+
+/// Subcommands of `cargo optee trace`
+#[derive(Debug, Subcommand)]
+pub enum TraceCommand {
+    /// Copy the function graph of a TA from the emulator or a device and symbolize it
+    #[clap(name = "pull")]
+    Pull(TracePullArgs),
+}
+
+/// Arguments of `cargo optee trace pull`
+#[derive(Debug, Args)]
+pub struct TracePullArgs {
+    /// Path to the TA Cargo.toml manifest file (default: Cargo.toml, or ta/Cargo.toml in the project root)
+    #[arg(long = "manifest-path")]
+    pub manifest_path: Option<PathBuf>,
+
+    /// Pull from a device reachable over SSH, as `[user@]host` (default: the emulator started by `cargo optee emu up`)
+    #[arg(long = "ssh")]
+    pub ssh: Option<String>,
+
+    /// SSH port of the device, or host port forwarded to SSH in QEMU (default: 22 with --ssh, 54432 otherwise)
+    #[arg(long = "ssh-port")]
+    pub ssh_port: Option<u16>,
+
+    /// OP-TEE TA development kit export directory, whose symbolize.py symbolizes the trace
+    #[arg(long = "ta-dev-kit-dir")]
+    pub ta_dev_kit_dir: Option<PathBuf>,
+
+    /// UUID file path (default: from the metadata, or "../uuid.txt")
+    #[arg(long = "uuid-path")]
+    pub uuid_path: Option<PathBuf>,
+
+    /// File the function graph is written to (default: ftrace-<uuid>.txt)
+    #[arg(short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+
+    /// Write the trace as recorded, with addresses instead of function names
+    #[arg(long = "raw")]
+    pub raw: bool,
+}
+
+/// Arguments of `cargo optee build`, either a component or `--workspace`
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
+pub struct BuildArgs {
+    #[command(subcommand)]
+    pub cmd: Option<BuildCommand>,
+
+    #[command(flatten)]
+    pub workspace: WorkspaceBuildArgs,
+}
+
+/// Arguments of `cargo optee build --workspace`
+#[derive(Debug, Args)]
+pub struct WorkspaceBuildArgs {
+    /// Build every package of the workspace with [package.metadata.optee.ta], [package.metadata.optee.ca] or [package.metadata.optee.plugin]
+    #[arg(long = "workspace", required = true)]
+    pub workspace: bool,
+
+    /// Path to the Cargo.toml manifest file of the workspace
+    #[arg(long = "manifest-path")]
+    pub manifest_path: Option<PathBuf>,
+
+    /// Package to build, all of them by default. This flag can be repeated.
+    #[arg(short = 'p', long = "package", action = clap::ArgAction::Append)]
+    pub packages: Vec<String>,
+
+    /// Package not to build. This flag can be repeated.
+    #[arg(long = "exclude", action = clap::ArgAction::Append)]
+    pub exclude: Vec<String>,
+
+    /// Directory the components are installed to, in ta/, ca/ and plugin/ (default: optee in the target directory)
+    #[arg(long = "out-dir")]
+    pub out_dir: Option<PathBuf>,
+
+    /// Target architecture of every package (default: from the metadata, or aarch64)
+    #[arg(long = "arch")]
+    pub arch: Option<Arch>,
+
+    /// Enable debug build (default: false)
+    #[arg(long = "debug")]
+    pub debug: bool,
+
+    /// OP-TEE TA development kit export directory of every TA
+    #[arg(long = "ta-dev-kit-dir")]
+    pub ta_dev_kit_dir: Option<PathBuf>,
+
+    /// OP-TEE client export directory of every CA and plugin
+    #[arg(long = "optee-client-export")]
+    pub optee_client_export: Option<PathBuf>,
+
+    /// Output format: human-readable text or one JSON message per line (default: human)
+    #[arg(long = "message-format", value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
 }
 
 #[derive(Debug, Subcommand)]
@@ -107,6 +375,353 @@ pub struct CleanCommand {
     /// Path to the Cargo.toml manifest file
     #[arg(long = "manifest-path")]
     pub manifest_path: Option<PathBuf>,
+
+    /// Component to clean, only its package (default: the whole target directory and every component of the metadata)
+    #[arg(long = "component", value_enum)]
+    pub component: Option<ComponentType>,
+
+    /// Directory the components were installed to, to remove the installed binaries from
+    #[arg(long = "target-dir")]
+    pub target_dir: Option<PathBuf>,
+
+    /// UUID file path of the TA or plugin (default: from the metadata, or "../uuid.txt")
+    #[arg(long = "uuid-path")]
+    pub uuid_path: Option<PathBuf>,
+}
+
+/// Size command arguments
+#[derive(Debug, Args)]
+pub struct SizeCommand {
+    /// Number of largest symbols to list (default: 20)
+    #[arg(long = "top", default_value_t = 20)]
+    pub top: usize,
+
+    #[command(flatten)]
+    pub build_cmd: TABuildArgs,
+}
+
+/// New command arguments
+#[derive(Debug, Args)]
+pub struct NewCommand {
+    /// Name of the project, also the directory it is created in
+    pub name: String,
+
+    /// Project template (default: hello-world)
+    #[arg(long = "template", value_enum, default_value_t = Template::HelloWorld)]
+    pub template: Template,
+
+    /// Path to a local SDK checkout to take the SDK crates from (default: the SDK git repository)
+    #[arg(long = "sdk-path")]
+    pub sdk_path: Option<PathBuf>,
+}
+
+/// Test command arguments
+#[derive(Debug, Args)]
+pub struct TestCommand {
+    /// Directory of the OP-TEE QEMU v8 image, containing qemu-system-aarch64, bl1.bin, Image and rootfs.cpio.gz
+    #[arg(
+        long = "image-dir",
+        required_unless_present = "emu",
+        conflicts_with = "emu"
+    )]
+    pub image_dir: Option<PathBuf>,
+
+    /// Test in the emulator started by `cargo optee emu up` on --ssh-port, in its shared folder
+    #[arg(long = "emu")]
+    pub emu: bool,
+
+    /// Path to the TA Cargo.toml manifest file (default: ta/Cargo.toml)
+    #[arg(long = "ta-manifest", default_value = "ta/Cargo.toml")]
+    pub ta_manifest: PathBuf,
+
+    /// Path to the CA Cargo.toml manifest file (default: host/Cargo.toml)
+    #[arg(long = "ca-manifest", default_value = "host/Cargo.toml")]
+    pub ca_manifest: PathBuf,
+
+    /// Path to the plugin Cargo.toml manifest file, for projects with a plugin
+    #[arg(long = "plugin-manifest")]
+    pub plugin_manifest: Option<PathBuf>,
+
+    /// Folder shared with QEMU the components are pushed through (default: "shared")
+    #[arg(long = "shared-dir", default_value = "shared")]
+    pub shared_dir: PathBuf,
+
+    /// Command to run in QEMU (default: the CA binary)
+    #[arg(long = "run")]
+    pub run: Option<String>,
+
+    /// Text the output of the command must contain to pass. This flag can be repeated.
+    #[arg(long = "expect", action = clap::ArgAction::Append)]
+    pub expect: Vec<String>,
+
+    /// Timeout of the command in seconds (default: 60)
+    #[arg(long = "timeout", default_value_t = 60)]
+    pub timeout: u64,
+
+    /// Host port forwarded to SSH in QEMU (default: 54432)
+    #[arg(long = "ssh-port", default_value_t = 54432)]
+    pub ssh_port: u16,
+
+    /// Leave QEMU running after the test, later runs reuse it
+    #[arg(long = "keep-running")]
+    pub keep_running: bool,
+
+    /// Enable debug build (default: false)
+    #[arg(long = "debug")]
+    pub debug: bool,
+}
+
+/// Arguments of `cargo optee run`
+#[derive(Debug, Args)]
+#[command(group(
+    clap::ArgGroup::new("target")
+        .required(true)
+        .args(["image_dir", "emu", "ssh", "adb"])
+))]
+pub struct RunCommand {
+    /// Run in QEMU: directory of the OP-TEE QEMU v8 image, containing qemu-system-aarch64, bl1.bin, Image and rootfs.cpio.gz
+    #[arg(long = "image-dir")]
+    pub image_dir: Option<PathBuf>,
+
+    /// Run in the emulator started by `cargo optee emu up` on --ssh-port, in its shared folder
+    #[arg(long = "emu")]
+    pub emu: bool,
+
+    /// Run on a device reachable over SSH, as `[user@]host`
+    #[arg(long = "ssh")]
+    pub ssh: Option<String>,
+
+    /// Run on a device reachable with adb
+    #[arg(long = "adb")]
+    pub adb: bool,
+
+    /// Serial number of the adb device, when several are connected
+    #[arg(long = "adb-serial", conflicts_with_all = ["image_dir", "emu", "ssh"])]
+    pub adb_serial: Option<String>,
+
+    /// SSH port of the device, or host port forwarded to SSH in QEMU (default: 22 with --ssh, 54432 with --image-dir or --emu)
+    #[arg(long = "ssh-port")]
+    pub ssh_port: Option<u16>,
+
+    /// File or serial device the secure world console is read from (default: the QEMU serial log)
+    #[arg(long = "secure-log")]
+    pub secure_log: Option<PathBuf>,
+
+    /// Path to the TA Cargo.toml manifest file (default: ta/Cargo.toml)
+    #[arg(long = "ta-manifest", default_value = "ta/Cargo.toml")]
+    pub ta_manifest: PathBuf,
+
+    /// Path to the CA Cargo.toml manifest file (default: host/Cargo.toml)
+    #[arg(long = "ca-manifest", default_value = "host/Cargo.toml")]
+    pub ca_manifest: PathBuf,
+
+    /// Path to the plugin Cargo.toml manifest file, for projects with a plugin
+    #[arg(long = "plugin-manifest")]
+    pub plugin_manifest: Option<PathBuf>,
+
+    /// Folder the components are staged in, shared with QEMU (default: "shared")
+    #[arg(long = "shared-dir", default_value = "shared")]
+    pub shared_dir: PathBuf,
+
+    /// Command to run on the device (default: the CA binary)
+    #[arg(long = "run")]
+    pub run: Option<String>,
+
+    /// Leave QEMU running after the run, later runs reuse it
+    #[arg(long = "keep-running", conflicts_with_all = ["ssh", "adb"])]
+    pub keep_running: bool,
+
+    /// Enable debug build (default: false)
+    #[arg(long = "debug")]
+    pub debug: bool,
+
+    /// Arguments passed to the CA
+    #[arg(last = true)]
+    pub args: Vec<String>,
+}
+
+/// Arguments of `cargo optee debug`
+#[derive(Debug, Args)]
+pub struct DebugCommand {
+    /// Directory of the OP-TEE QEMU v8 image, containing qemu-system-aarch64, bl1.bin, Image and rootfs.cpio.gz
+    #[arg(long = "image-dir")]
+    pub image_dir: PathBuf,
+
+    /// Path to the TA Cargo.toml manifest file (default: ta/Cargo.toml)
+    #[arg(long = "ta-manifest", default_value = "ta/Cargo.toml")]
+    pub ta_manifest: PathBuf,
+
+    /// Path to the CA Cargo.toml manifest file (default: host/Cargo.toml)
+    #[arg(long = "ca-manifest", default_value = "host/Cargo.toml")]
+    pub ca_manifest: PathBuf,
+
+    /// Path to the plugin Cargo.toml manifest file, for projects with a plugin
+    #[arg(long = "plugin-manifest")]
+    pub plugin_manifest: Option<PathBuf>,
+
+    /// Folder the components and gdb scripts are staged in, shared with QEMU (default: "shared")
+    #[arg(long = "shared-dir", default_value = "shared")]
+    pub shared_dir: PathBuf,
+
+    /// Command to run in QEMU (default: the CA binary)
+    #[arg(long = "run")]
+    pub run: Option<String>,
+
+    /// Host port forwarded to SSH in QEMU (default: 54432)
+    #[arg(long = "ssh-port", default_value_t = 54432)]
+    pub ssh_port: u16,
+
+    /// Port of the gdb server of QEMU (default: 1234, as `-s`)
+    #[arg(long = "gdb-port", default_value_t = 1234)]
+    pub gdb_port: u16,
+
+    /// Leave QEMU running after the run instead of waiting for Enter to stop it
+    #[arg(long = "keep-running")]
+    pub keep_running: bool,
+
+    /// Build the components in release mode (default: debug, for the debug info)
+    #[arg(long = "release")]
+    pub release: bool,
+
+    /// Arguments passed to the CA
+    #[arg(last = true)]
+    pub args: Vec<String>,
+}
+
+/// Arguments of `cargo optee watch`
+#[derive(Debug, Args)]
+#[command(group(
+    clap::ArgGroup::new("target")
+        .required(true)
+        .args(["image_dir", "emu", "ssh", "adb"])
+))]
+pub struct WatchCommand {
+    /// Install in QEMU: directory of the OP-TEE QEMU v8 image, containing qemu-system-aarch64, bl1.bin, Image and rootfs.cpio.gz
+    #[arg(long = "image-dir")]
+    pub image_dir: Option<PathBuf>,
+
+    /// Install in the emulator started by `cargo optee emu up` on --ssh-port, in its shared folder
+    #[arg(long = "emu")]
+    pub emu: bool,
+
+    /// Install on a device reachable over SSH, as `[user@]host`
+    #[arg(long = "ssh")]
+    pub ssh: Option<String>,
+
+    /// Install on a device reachable with adb
+    #[arg(long = "adb")]
+    pub adb: bool,
+
+    /// Serial number of the adb device, when several are connected
+    #[arg(long = "adb-serial", conflicts_with_all = ["image_dir", "emu", "ssh"])]
+    pub adb_serial: Option<String>,
+
+    /// SSH port of the device, or host port forwarded to SSH in QEMU (default: 22 with --ssh, 54432 with --image-dir or --emu)
+    #[arg(long = "ssh-port")]
+    pub ssh_port: Option<u16>,
+
+    /// File or serial device the secure world console is read from (default: the QEMU serial log)
+    #[arg(long = "secure-log")]
+    pub secure_log: Option<PathBuf>,
+
+    /// Path to the TA Cargo.toml manifest file (default: ta/Cargo.toml)
+    #[arg(long = "ta-manifest", default_value = "ta/Cargo.toml")]
+    pub ta_manifest: PathBuf,
+
+    /// Path to the CA Cargo.toml manifest file (default: host/Cargo.toml)
+    #[arg(long = "ca-manifest", default_value = "host/Cargo.toml")]
+    pub ca_manifest: PathBuf,
+
+    /// Path to the plugin Cargo.toml manifest file, for projects with a plugin
+    #[arg(long = "plugin-manifest")]
+    pub plugin_manifest: Option<PathBuf>,
+
+    /// Folder the components are staged in, shared with QEMU (default: "shared")
+    #[arg(long = "shared-dir", default_value = "shared")]
+    pub shared_dir: PathBuf,
+
+    /// Command to run on the device after each installation, e.g. the CA with its arguments (default: none)
+    #[arg(long = "run")]
+    pub run: Option<String>,
+
+    /// Milliseconds between two scans of the sources (default: 500)
+    #[arg(long = "interval", default_value_t = 500)]
+    pub interval: u64,
+
+    /// Leave QEMU running after the watch, later runs reuse it
+    #[arg(long = "keep-running", conflicts_with_all = ["ssh", "adb"])]
+    pub keep_running: bool,
+
+    /// Enable debug build (default: false)
+    #[arg(long = "debug")]
+    pub debug: bool,
+}
+
+/// Arguments of `cargo optee package`
+#[derive(Debug, Args)]
+pub struct PackageCommand {
+    /// Path of the manifest (default: `<uuid>.json` next to the signed TA)
+    #[arg(long = "output")]
+    pub output: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub build_cmd: TABuildArgs,
+}
+
+/// Arguments of `cargo optee sign`
+#[derive(Debug, Args)]
+#[command(group(
+    clap::ArgGroup::new("signer")
+        .required(true)
+        .args(["key", "sign_command"])
+))]
+pub struct SignCommand {
+    /// Stripped TA ELF to sign, `stripped_<package>` in the target directory after `build ta`
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// UUID of the TA
+    #[arg(long = "uuid")]
+    pub uuid: String,
+
+    /// PEM private key file, or PKCS#11 URI (`pkcs11:...`) of the key in a token
+    #[arg(long = "key")]
+    pub key: Option<String>,
+
+    /// Shell command reading the digest on stdin and writing the raw signature to stdout
+    #[arg(long = "sign-command")]
+    pub sign_command: Option<String>,
+
+    /// PEM public key of the signing key, required unless --key is a PEM file
+    #[arg(long = "public-key")]
+    pub public_key: Option<PathBuf>,
+
+    /// Signed TA output path (default: `<uuid>.ta` next to the input)
+    #[arg(long = "out")]
+    pub output: Option<PathBuf>,
+
+    /// OP-TEE TA development kit export directory, providing sign_encrypt.py
+    #[arg(long = "ta-dev-kit-dir")]
+    pub ta_dev_kit_dir: PathBuf,
+
+    #[command(flatten)]
+    pub encryption: EncryptionArgs,
+}
+
+/// Encryption of the TA, shared by `build ta` and `sign`
+#[derive(Debug, Args)]
+pub struct EncryptionArgs {
+    /// Encrypt the TA with the key of --enc-key-file, or of the TA_ENC_KEY environment variable
+    #[arg(long = "encrypt")]
+    pub encrypt: bool,
+
+    /// File with the hex encoded AES key to encrypt the TA with, implies --encrypt
+    #[arg(long = "enc-key-file")]
+    pub enc_key_file: Option<PathBuf>,
+
+    /// Key the device decrypts the TA with (default: dev-specific)
+    #[arg(long = "enc-key-type", value_enum)]
+    pub enc_key_type: Option<EncKeyType>,
 }
 
 /// Common build command arguments shared across TA, CA, and Plugin builds
@@ -139,6 +754,22 @@ pub struct CommonBuildArgs {
     /// Custom features to enable (will append --features to cargo build)
     #[arg(long = "features")]
     pub features: Option<String>,
+
+    /// Skip cargo fmt and clippy before the build, for rapid iteration
+    #[arg(long = "skip-checks")]
+    pub skip_checks: bool,
+
+    /// Build in the official teaclave docker image, with the project mounted (default: from the metadata, or false)
+    #[arg(long = "in-docker")]
+    pub in_docker: bool,
+
+    /// Docker image to build in, implies --in-docker (default: the official no-std or std image)
+    #[arg(long = "docker-image")]
+    pub docker_image: Option<String>,
+
+    /// Output format: human-readable text or one JSON message per line (default: human)
+    #[arg(long = "message-format", value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
 }
 
 /// TA-specific build arguments
@@ -168,6 +799,25 @@ pub struct TABuildArgs {
     /// UUID file path (default: "../uuid.txt")
     #[arg(long = "uuid-path")]
     pub uuid_path: Option<PathBuf>,
+
+    /// Fail if the stripped TA exceeds this size, e.g. `4096`, `512K` or `1M`
+    #[arg(long = "size-budget", value_parser = parse_size)]
+    pub size_budget: Option<u64>,
+
+    /// Build independently of the build paths and time, then rebuild from scratch and check the stripped TA is identical
+    #[arg(long = "reproducible")]
+    pub reproducible: bool,
+
+    /// Build profile: release, debug (as --debug), or ftrace to record the function calls of the TA with the function tracer of OP-TEE (default: release)
+    #[arg(long = "profile", value_enum, conflicts_with = "debug")]
+    pub profile: Option<TaProfile>,
+
+    /// Size of the ftrace buffer of the TA with --profile ftrace, e.g. `2048` or `64K` (default: from the metadata, or 2048)
+    #[arg(long = "ftrace-buf-size", value_parser = parse_size)]
+    pub ftrace_buf_size: Option<u64>,
+
+    #[command(flatten)]
+    pub encryption: EncryptionArgs,
 }
 
 /// CA-specific build arguments
@@ -24,6 +24,7 @@ use std::process::{Command, Output};
 use toml::Value;
 
 use crate::cargo_command;
+use crate::status;
 
 /// RAII guard to ensure we return to the original directory
 pub struct ChangeDirectoryGuard {
@@ -51,6 +52,22 @@ pub enum Arch {
     Aarch64,
     /// ARM 32-bit architecture
     Arm,
+    /// RISC-V 64-bit architecture
+    Riscv64,
+    /// RISC-V 32-bit architecture
+    Riscv32,
+}
+
+impl Arch {
+    /// Name of the architecture as accepted by `--arch`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Arch::Aarch64 => "aarch64",
+            Arch::Arm => "arm",
+            Arch::Riscv64 => "riscv64",
+            Arch::Riscv32 => "riscv32",
+        }
+    }
 }
 
 impl std::str::FromStr for Arch {
@@ -60,6 +77,8 @@ impl std::str::FromStr for Arch {
         match s.to_lowercase().as_str() {
             "aarch64" | "arm64" => Ok(Arch::Aarch64),
             "arm" | "arm32" => Ok(Arch::Arm),
+            "riscv64" | "rv64" => Ok(Arch::Riscv64),
+            "riscv32" | "rv32" => Ok(Arch::Riscv32),
             _ => Err(format!("Invalid architecture: {}", s)),
         }
     }
@@ -81,7 +100,7 @@ pub enum BuildMode {
 
 /// Target configurations for different architectures and build modes
 /// Format: (Architecture, BuildMode, target, cross_compile_prefix)
-const TARGET_CONFIGS: [(Arch, BuildMode, &str, &str); 6] = [
+const TARGET_CONFIGS: [(Arch, BuildMode, &str, &str); 12] = [
     // ARM 32-bit configurations
     (
         Arch::Arm,
@@ -120,8 +139,54 @@ const TARGET_CONFIGS: [(Arch, BuildMode, &str, &str); 6] = [
         "aarch64-unknown-optee",
         "aarch64-linux-gnu-",
     ),
+    // RISC-V 64-bit configurations
+    (
+        Arch::Riscv64,
+        BuildMode::Ca,
+        "riscv64gc-unknown-linux-gnu",
+        "riscv64-linux-gnu-",
+    ),
+    (
+        Arch::Riscv64,
+        BuildMode::TaNoStd,
+        "riscv64gc-unknown-linux-gnu",
+        "riscv64-linux-gnu-",
+    ),
+    (
+        Arch::Riscv64,
+        BuildMode::TaStd,
+        "riscv64gc-unknown-optee",
+        "riscv64-linux-gnu-",
+    ),
+    // RISC-V 32-bit configurations
+    (
+        Arch::Riscv32,
+        BuildMode::Ca,
+        "riscv32gc-unknown-linux-gnu",
+        "riscv32-unknown-linux-gnu-",
+    ),
+    (
+        Arch::Riscv32,
+        BuildMode::TaNoStd,
+        "riscv32gc-unknown-linux-gnu",
+        "riscv32-unknown-linux-gnu-",
+    ),
+    (
+        Arch::Riscv32,
+        BuildMode::TaStd,
+        "riscv32gc-unknown-optee",
+        "riscv32-unknown-linux-gnu-",
+    ),
 ];
 
+/// Whether rustup has no prebuilt standard library for the Linux target of
+/// `arch`, so that it has to be built with `-Z build-std`.
+///
+/// riscv32gc-unknown-linux-gnu is a tier 3 target.
+pub fn needs_build_std(arch: Arch) -> bool {
+    arch == Arch::Riscv32
+}
+
 /// Unified function to derive target and cross-compile prefix from architecture and build mode
 pub fn get_target_and_cross_compile(arch: Arch, mode: BuildMode) -> Result<(String, String)> {
     for &(config_arch, config_mode, target, cross_compile_prefix) in &TARGET_CONFIGS {
@@ -158,7 +223,7 @@ pub fn print_output_and_bail(cmd_name: &str, output: &Output) -> Result<()> {
 
 /// Print cargo command for debugging
 pub fn print_cargo_command(cmd: &Command, description: &str) {
-    println!("{}...", description);
+    status!("{}...", description);
 
     // Extract program and args
     let program = cmd.get_program();
@@ -175,11 +240,11 @@ pub fn print_cargo_command(cmd: &Command, description: &str) {
 
     // Print environment variables
     if !envs.is_empty() {
-        println!("  Environment: {}", envs.join(" "));
+        status!("  Environment: {}", envs.join(" "));
     }
 
     // Print command
-    println!(
+    status!(
         "  Command: {} {}",
         program.to_string_lossy(),
         args.into_iter()
@@ -191,6 +256,15 @@ pub fn print_cargo_command(cmd: &Command, description: &str) {
 
 /// Get the target directory using cargo metadata
 pub fn get_target_directory_from_metadata() -> Result<PathBuf> {
+    metadata_directory("target_directory")
+}
+
+/// Root of the workspace of the project in the current directory
+pub fn get_workspace_root_from_metadata() -> Result<PathBuf> {
+    metadata_directory("workspace_root")
+}
+
+fn metadata_directory(key: &str) -> Result<PathBuf> {
     // We're already in the project directory, so no need for --manifest-path
     let output = cargo_command()
         .arg("metadata")
@@ -204,12 +278,17 @@ pub fn get_target_directory_from_metadata() -> Result<PathBuf> {
     }
 
     let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)?;
-    let target_directory = metadata
-        .get("target_directory")
+    let directory = metadata
+        .get(key)
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Could not get target directory from cargo metadata"))?;
+        .ok_or_else(|| anyhow::anyhow!("Could not get {} from cargo metadata", key))?;
+
+    Ok(PathBuf::from(directory))
+}
 
-    Ok(PathBuf::from(target_directory))
+/// Lowercase hexadecimal encoding of `bytes`
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
 }
 
 /// Read UUID from a file (e.g., uuid.txt)
@@ -267,28 +346,26 @@ pub fn join_format_and_check<P: AsRef<std::path::Path>>(
     Ok(final_path)
 }
 
-/// Clean build artifacts for any OP-TEE component (TA, CA, Plugin)
-pub fn clean_project(project_path: &std::path::Path) -> Result<()> {
-    println!("Cleaning build artifacts in: {:?}", project_path);
-
-    let output = cargo_command()
-        .arg("clean")
-        .current_dir(project_path)
-        .output()?;
-
-    if !output.status.success() {
-        print_output_and_bail("cargo clean", &output)?;
-    }
-
-    // Also clean the intermediate cargo-optee directory if it exists
-    let intermediate_dir = project_path.join("target").join("cargo-optee");
-    if intermediate_dir.exists() {
-        fs::remove_dir_all(&intermediate_dir)?;
-        println!("Removed intermediate directory: {:?}", intermediate_dir);
-    }
-
-    println!("Build artifacts cleaned successfully");
-    Ok(())
+/// Parse a size such as `4096`, `512K` or `1M` into a number of bytes
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 1024),
+        Some('M') => (&s[..s.len() - 1], 1024 * 1024),
+        _ => (s, 1),
+    };
+
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| {
+            format!(
+                "Invalid size: '{}'. Expected a byte count optionally suffixed with K or M",
+                s
+            )
+        })
 }
 
 /// Get the package name from Cargo.toml in the current directory
@@ -16,14 +16,17 @@
 // under the License.
 
 use anyhow::{Result, bail};
-use cargo_metadata::MetadataCommand;
+use cargo_metadata::{MetadataCommand, Package};
+use clap::ValueEnum;
 use serde_json::Value;
 use std::path::{Path, PathBuf};
 
-use crate::common::Arch;
+use crate::common::{Arch, parse_size};
+use crate::sign::{EncKeyType, Encryption};
+use crate::status;
 
 /// Component type for OP-TEE builds
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum ComponentType {
     /// Trusted Application (TA)
     Ta,
@@ -44,6 +47,21 @@ impl ComponentType {
     }
 }
 
+/// Build profile of a TA, `--profile` of the TA commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TaProfile {
+    /// Optimized build, the default
+    Release,
+    /// Unoptimized build with debug assertions, as `--debug`
+    Debug,
+    /// Release build with every function instrumented for the function tracer of OP-TEE
+    Ftrace,
+}
+
+/// Size of the ftrace buffer of the TA, the default of OP-TEE
+/// `CFG_FTRACE_BUF_SIZE`
+const DEFAULT_FTRACE_BUF_SIZE: u64 = 2048;
+
 /// Path type for validation
 enum PathType {
     /// Expects a directory
@@ -62,13 +80,20 @@ pub struct TaBuildConfig {
     pub env: Vec<(String, String)>, // Custom environment variables for cargo build
     pub no_default_features: bool,  // Disable default features
     pub features: Option<String>,   // Additional features to enable
+    pub skip_checks: bool,          // Skip cargo fmt and clippy
+    pub lints: Lints,               // Lints of clippy
     // ta specific variables
-    pub std: bool,               // Enable std feature
-    pub ta_dev_kit_dir: PathBuf, // Path to TA dev kit
-    pub signing_key: PathBuf,    // Path to signing key
+    pub std: bool,                      // Enable std feature
+    pub ta_dev_kit_dir: PathBuf,        // Path to TA dev kit
+    pub signing_key: PathBuf,           // Path to signing key
+    pub size_budget: Option<u64>,       // Maximum size of the stripped TA in bytes
+    pub reproducible: bool, // Build independently of the build paths and time, and verify it
+    pub encryption: Option<Encryption>, // Encrypt the TA before signing it
+    pub ftrace_buf_size: Option<u64>, // Instrument the TA for ftrace, with a buffer of this size
 }
 
 impl TaBuildConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn resolve(
         project_path: &Path,
         cmd_arch: Option<Arch>,
@@ -77,9 +102,17 @@ impl TaBuildConfig {
         common_env: Vec<(String, String)>,
         common_no_default_features: bool,
         common_features: Option<String>,
+        common_skip_checks: bool,
         cmd_std: Option<bool>,
         cmd_ta_dev_kit_dir: Option<PathBuf>,
         cmd_signing_key: Option<PathBuf>,
+        cmd_size_budget: Option<u64>,
+        cmd_reproducible: bool,
+        cmd_encrypt: bool,
+        cmd_enc_key_file: Option<PathBuf>,
+        cmd_enc_key_type: Option<EncKeyType>,
+        cmd_ftrace: bool,
+        cmd_ftrace_buf_size: Option<u64>,
     ) -> Result<Self> {
         // Get base configuration from metadata
         let metadata_config = MetadataConfig::resolve(project_path, ComponentType::Ta, cmd_arch)?;
@@ -136,6 +169,54 @@ impl TaBuildConfig {
             PathBuf::from("../uuid.txt"),
         )?;
 
+        // Handle size_budget: CLI > metadata > none (no limit)
+        let size_budget =
+            cmd_size_budget.or_else(|| metadata_config.as_ref().and_then(|c| c.size_budget));
+
+        // Handle reproducible: CLI flag > metadata > false
+        let reproducible =
+            cmd_reproducible || metadata_config.as_ref().is_some_and(|c| c.reproducible);
+
+        // Handle encryption: CLI flag or key file > metadata > none, with the
+        // key from the key file of the CLI > metadata > TA_ENC_KEY
+        let enc_key_file = cmd_enc_key_file
+            .or_else(|| {
+                metadata_config
+                    .as_ref()
+                    .and_then(|c| c.enc_key_file.clone())
+            })
+            .map(|path| {
+                resolve_path_relative_to_project(
+                    &path,
+                    project_path,
+                    PathType::File,
+                    "Encryption key file",
+                )
+            })
+            .transpose()?;
+        let encrypt = cmd_encrypt
+            || enc_key_file.is_some()
+            || metadata_config.as_ref().is_some_and(|c| c.encrypt);
+        let encryption = if encrypt {
+            let key_type = cmd_enc_key_type
+                .or_else(|| metadata_config.as_ref().and_then(|c| c.enc_key_type))
+                .unwrap_or(EncKeyType::DevSpecific);
+            Some(Encryption::resolve(enc_key_file.as_deref(), key_type)?)
+        } else {
+            None
+        };
+
+        // Handle ftrace: only with --profile ftrace, with the buffer size of
+        // CLI > metadata > default
+        let ftrace_buf_size = cmd_ftrace.then(|| {
+            cmd_ftrace_buf_size
+                .or_else(|| metadata_config.as_ref().and_then(|c| c.ftrace_buf_size))
+                .unwrap_or(DEFAULT_FTRACE_BUF_SIZE)
+        });
+        if ftrace_buf_size.is_some() && debug {
+            bail!("--profile ftrace is a release build and cannot be combined with debug");
+        }
+
         // Merge environment variables: metadata env + CLI env (CLI overrides metadata)
         let mut env = metadata_config
             .as_ref()
@@ -143,36 +224,62 @@ impl TaBuildConfig {
             .unwrap_or_default();
         env.extend(common_env);
 
+        // Lints are only read when the checks run
+        let lints = if common_skip_checks {
+            Lints::default()
+        } else {
+            Lints::resolve(project_path)?
+        };
+
         Ok(TaBuildConfig {
             arch,
             debug,
             std,
             ta_dev_kit_dir,
             signing_key,
+            size_budget,
+            reproducible,
+            encryption,
+            ftrace_buf_size,
             path: project_path.to_path_buf(),
             uuid_path: Some(uuid_path),
             env,
             no_default_features: common_no_default_features,
             features: common_features,
+            skip_checks: common_skip_checks,
+            lints,
         })
     }
 
     /// Print the final TA configuration parameters being used
     pub fn print_config(&self) {
-        println!("Building TA with:");
-        println!("  Arch: {:?}", self.arch);
-        println!("  Debug: {}", self.debug);
-        println!("  Std: {}", self.std);
-        println!("  TA dev kit dir: {:?}", self.ta_dev_kit_dir);
-        println!("  Signing key: {:?}", self.signing_key);
+        status!("Building TA with:");
+        status!("  Arch: {:?}", self.arch);
+        status!("  Debug: {}", self.debug);
+        status!("  Std: {}", self.std);
+        status!("  TA dev kit dir: {:?}", self.ta_dev_kit_dir);
+        status!("  Signing key: {:?}", self.signing_key);
         if let Some(ref uuid_path) = self.uuid_path {
             let absolute_uuid_path = uuid_path
                 .canonicalize()
                 .unwrap_or_else(|_| uuid_path.clone());
-            println!("  UUID path: {:?}", absolute_uuid_path);
+            status!("  UUID path: {:?}", absolute_uuid_path);
+        }
+        if let Some(size_budget) = self.size_budget {
+            status!("  Size budget: {} bytes", size_budget);
+        }
+        if let Some(ftrace_buf_size) = self.ftrace_buf_size {
+            status!("  Ftrace buffer size: {} bytes", ftrace_buf_size);
+        }
+        if self.reproducible {
+            status!("  Reproducible: true");
         }
+        if let Some(ref encryption) = self.encryption {
+            status!("  Encryption: {:?} key", encryption.key_type);
+        }
+        self.lints.print_config(self.skip_checks);
         if !self.env.is_empty() {
-            println!("  Environment variables: {} set", self.env.len());
+            status!("  Environment variables: {} set", self.env.len());
         }
     }
 }
@@ -187,12 +294,15 @@ pub struct CaBuildConfig {
     pub env: Vec<(String, String)>, // Custom environment variables for cargo build
     pub no_default_features: bool,  // Disable default features
     pub features: Option<String>,   // Additional features to enable
+    pub skip_checks: bool,          // Skip cargo fmt and clippy
+    pub lints: Lints,               // Lints of clippy
     // ca specific variables
     pub optee_client_export: PathBuf, // Path to OP-TEE client export
     pub plugin: bool,                 // Build as plugin (shared library)
 }
 
 impl CaBuildConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn resolve(
         project_path: &Path,
         cmd_arch: Option<Arch>,
@@ -201,6 +311,7 @@ impl CaBuildConfig {
         common_env: Vec<(String, String)>,
         common_no_default_features: bool,
         common_features: Option<String>,
+        common_skip_checks: bool,
         cmd_optee_client_export: Option<PathBuf>,
         plugin: bool,
     ) -> Result<Self> {
@@ -259,6 +370,13 @@ impl CaBuildConfig {
             .unwrap_or_default();
         env.extend(common_env);
 
+        // Lints are only read when the checks run
+        let lints = if common_skip_checks {
+            Lints::default()
+        } else {
+            Lints::resolve(project_path)?
+        };
+
         Ok(CaBuildConfig {
             arch,
             debug,
@@ -267,6 +385,8 @@ impl CaBuildConfig {
             env,
             no_default_features: common_no_default_features,
             features: common_features,
+            skip_checks: common_skip_checks,
+            lints,
             optee_client_export,
             plugin,
         })
@@ -275,21 +395,168 @@ impl CaBuildConfig {
     /// Print the final CA/Plugin configuration parameters being used
     pub fn print_config(&self) {
         let component_name = if self.plugin { "Plugin" } else { "CA" };
-        println!("Building {} with:", component_name);
-        println!("  Arch: {:?}", self.arch);
-        println!("  Debug: {}", self.debug);
-        println!("  OP-TEE client export: {:?}", self.optee_client_export);
+        status!("Building {} with:", component_name);
+        status!("  Arch: {:?}", self.arch);
+        status!("  Debug: {}", self.debug);
+        status!("  OP-TEE client export: {:?}", self.optee_client_export);
         if self.plugin
             && let Some(ref uuid_path) = self.uuid_path
         {
             let absolute_uuid_path = uuid_path
                 .canonicalize()
                 .unwrap_or_else(|_| uuid_path.clone());
-            println!("  UUID path: {:?}", absolute_uuid_path);
+            status!("  UUID path: {:?}", absolute_uuid_path);
         }
+        self.lints.print_config(self.skip_checks);
         if !self.env.is_empty() {
-            println!("  Environment variables: {} set", self.env.len());
+            status!("  Environment variables: {} set", self.env.len());
+        }
+    }
+}
+
+/// Lints clippy denies by default, before the build
+const DEFAULT_DENIED_LINTS: &[&str] = &[
+    "warnings",
+    "clippy::unwrap_used",
+    "clippy::expect_used",
+    "clippy::panic",
+];
+
+/// Lints of the clippy step, from `[package.metadata.optee.lints]`, shared by
+/// the TA, CA and plugin sections
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lints {
+    pub allow: Vec<String>, // Lints not to check, including default denied ones
+    pub deny: Vec<String>,  // Lints denied in addition to the default ones
+}
+
+impl Lints {
+    /// Read the lints from the metadata of the project, none if not set
+    pub fn resolve(project_path: &Path) -> Result<Self> {
+        let metadata = discover_app_metadata(project_path)?;
+        let Some(lints) = metadata.get("optee").and_then(|v| v.get("lints")) else {
+            return Ok(Self::default());
+        };
+
+        let parse_list = |key: &str| -> Result<Vec<String>> {
+            match lints.get(key) {
+                None => Ok(Vec::new()),
+                Some(Value::Array(arr)) => arr
+                    .iter()
+                    .map(|v| {
+                        v.as_str().map(String::from).ok_or_else(|| {
+                            anyhow::anyhow!("Invalid lint in optee.lints.{}: {}", key, v)
+                        })
+                    })
+                    .collect(),
+                Some(v) => bail!("optee.lints.{} must be an array of lints, got: {}", key, v),
+            }
+        };
+
+        Ok(Lints {
+            allow: parse_list("allow")?,
+            deny: parse_list("deny")?,
+        })
+    }
+
+    /// Arguments of clippy after `--`: the default denied lints that are not
+    /// allowed, the additional denied lints, then the allowed lints
+    pub fn clippy_args(&self) -> Vec<String> {
+        let mut denied: Vec<&str> = Vec::new();
+        let lints = DEFAULT_DENIED_LINTS
+            .iter()
+            .copied()
+            .filter(|lint| !self.allow.iter().any(|allowed| allowed == lint))
+            .chain(self.deny.iter().map(String::as_str));
+        for lint in lints {
+            if !denied.contains(&lint) {
+                denied.push(lint);
+            }
+        }
+
+        let mut args = Vec::new();
+        for lint in denied {
+            args.push("-D".to_string());
+            args.push(lint.to_string());
+        }
+        for lint in &self.allow {
+            args.push("-A".to_string());
+            args.push(lint.clone());
+        }
+        args
+    }
+
+    fn print_config(&self, skip_checks: bool) {
+        if skip_checks {
+            status!("  Skip checks: true");
+            return;
+        }
+        if !self.allow.is_empty() {
+            status!("  Allowed lints: {}", self.allow.join(", "));
+        }
+        if !self.deny.is_empty() {
+            status!("  Denied lints: {}", self.deny.join(", "));
+        }
+    }
+}
+
+/// Environment variable set in the container of `--in-docker`, so that the
+/// build started there does not start another container
+pub const IN_DOCKER_ENV: &str = "CARGO_OPTEE_IN_DOCKER";
+
+/// Configuration of a build run in a docker container
+#[derive(Clone)]
+pub struct DockerBuildConfig {
+    pub component_type: ComponentType,
+    pub arch: Arch,
+    pub std: bool,
+    pub image: Option<String>, // Image to build in (default: the official one)
+}
+
+impl DockerBuildConfig {
+    /// Returns `None` if the build runs on the host: neither `--in-docker`,
+    /// `--docker-image` nor `in-docker` in the metadata, or already in the
+    /// container.
+    pub fn resolve(
+        project_path: &Path,
+        component_type: ComponentType,
+        cmd_arch: Option<Arch>,
+        cmd_std: Option<bool>,
+        cmd_in_docker: bool,
+        cmd_docker_image: Option<String>,
+    ) -> Result<Option<Self>> {
+        if std::env::var_os(IN_DOCKER_ENV).is_some() {
+            return Ok(None);
         }
+        let metadata_config = MetadataConfig::resolve(project_path, component_type, cmd_arch)?;
+
+        // Handle in_docker: CLI flag or image > metadata > false
+        let in_docker = cmd_in_docker
+            || cmd_docker_image.is_some()
+            || metadata_config.as_ref().is_some_and(|c| c.in_docker);
+        if !in_docker {
+            return Ok(None);
+        }
+
+        let arch = cmd_arch
+            .or_else(|| metadata_config.as_ref().map(|c| c.arch))
+            .unwrap_or(Arch::Aarch64);
+        let std = component_type == ComponentType::Ta
+            && cmd_std
+                .or_else(|| metadata_config.as_ref().map(|c| c.std))
+                .unwrap_or(false);
+        let image = cmd_docker_image.or_else(|| {
+            metadata_config
+                .as_ref()
+                .and_then(|c| c.docker_image.clone())
+        });
+
+        Ok(Some(DockerBuildConfig {
+            component_type,
+            arch,
+            std,
+            image,
+        }))
     }
 }
 
@@ -304,6 +571,14 @@ struct MetadataConfig {
     pub optee_client_export: Option<PathBuf>,
     pub signing_key: Option<PathBuf>,
     pub uuid_path: Option<PathBuf>,
+    pub size_budget: Option<u64>,
+    pub ftrace_buf_size: Option<u64>,
+    pub reproducible: bool,
+    pub encrypt: bool,
+    pub enc_key_file: Option<PathBuf>,
+    pub enc_key_type: Option<EncKeyType>,
+    pub in_docker: bool,
+    pub docker_image: Option<String>,
     /// additional environment key-value pairs, that should be passed to underlying
     /// build commands
     pub env: Vec<(String, String)>,
@@ -347,6 +622,11 @@ impl MetadataConfig {
 
 /// Discover application metadata from the current project
 fn discover_app_metadata(project_path: &Path) -> Result<Value> {
+    Ok(find_package(project_path)?.metadata)
+}
+
+/// Find the package of the project in `project_path` in the cargo metadata
+pub fn find_package(project_path: &Path) -> Result<Package> {
     let cargo_toml_path = project_path.join("Cargo.toml");
     if !cargo_toml_path.exists() {
         bail!(
@@ -384,7 +664,7 @@ fn discover_app_metadata(project_path: &Path) -> Result<Value> {
             })?
     };
 
-    Ok(current_package.metadata.clone())
+    Ok(current_package.clone())
 }
 
 /// Extract build configuration from application package metadata with specific architecture
@@ -420,6 +700,8 @@ fn extract_build_config_with_arch(
     let arch_key = match arch {
         Arch::Aarch64 => "aarch64",
         Arch::Arm => "arm",
+        Arch::Riscv64 => "riscv64",
+        Arch::Riscv32 => "riscv32",
     };
 
     // Parse architecture-specific ta_dev_kit_dir (for TA only)
@@ -474,6 +756,66 @@ fn extract_build_config_with_arch(
         None
     };
 
+    // Parse size budget (for TA only), either a byte count or a string like "512K"
+    let size_budget = if component_type == ComponentType::Ta {
+        metadata_size(component_metadata, "size-budget")?
+    } else {
+        None
+    };
+
+    // Parse the ftrace buffer size (for TA only), used with --profile ftrace
+    let ftrace_buf_size = if component_type == ComponentType::Ta {
+        metadata_size(component_metadata, "ftrace-buf-size")?
+    } else {
+        None
+    };
+
+    // Parse reproducible (for TA only) with fallback to false
+    let reproducible = component_type == ComponentType::Ta
+        && component_metadata
+            .get("reproducible")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+    // Parse encryption (for TA only): the flag, the key file and the key type
+    let encrypt = component_type == ComponentType::Ta
+        && component_metadata
+            .get("encrypt")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+    let enc_key_file = if component_type == ComponentType::Ta {
+        component_metadata
+            .get("enc-key-file")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .map(PathBuf::from)
+    } else {
+        None
+    };
+    let enc_key_type = if component_type == ComponentType::Ta {
+        component_metadata
+            .get("enc-key-type")
+            .and_then(|v| v.as_str())
+            .and_then(|s| {
+                s.parse()
+                    .inspect_err(|e| eprintln!("Warning: Ignoring enc-key-type in metadata: {}", e))
+                    .ok()
+            })
+    } else {
+        None
+    };
+
+    // Parse in-docker with fallback to false, and the image to build in
+    let in_docker = component_metadata
+        .get("in-docker")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let docker_image = component_metadata
+        .get("docker-image")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(String::from);
+
     // Parse environment variables
     let env: Vec<(String, String)> = component_metadata
         .get("env")
@@ -516,10 +858,38 @@ fn extract_build_config_with_arch(
         optee_client_export,
         signing_key,
         uuid_path,
+        size_budget,
+        ftrace_buf_size,
+        reproducible,
+        encrypt,
+        enc_key_file,
+        enc_key_type,
+        in_docker,
+        docker_image,
         env,
     })
 }
 
+/// Parse the size `key` of the metadata, either a byte count or a string like
+/// "512K", failing rather than ignoring an invalid one
+fn metadata_size(component_metadata: &Value, key: &str) -> Result<Option<u64>> {
+    match component_metadata.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Number(n)) => match n.as_u64() {
+            Some(size) => Ok(Some(size)),
+            None => bail!("Invalid {} in metadata: {} is not a byte count", key, n),
+        },
+        Some(Value::String(s)) => parse_size(s)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid {} in metadata: {}", key, e)),
+        Some(v) => bail!(
+            "Invalid {} in metadata: expected a byte count or a size string, got: {}",
+            key,
+            v
+        ),
+    }
+}
+
 /// Resolve uuid_path with priority: CLI > metadata > default
 /// Returns the resolved absolute path
 fn resolve_uuid_path(
@@ -540,6 +910,29 @@ fn resolve_uuid_path(
     }
 }
 
+/// Resolve the UUID file of the TA or plugin in `project_path` with the same
+/// priority as its build: CLI > metadata > default (../uuid.txt)
+pub fn resolve_uuid_path_of(
+    project_path: &Path,
+    component_type: ComponentType,
+    cmd_uuid_path: Option<PathBuf>,
+) -> Result<PathBuf> {
+    let metadata = discover_app_metadata(project_path)?;
+    let metadata_uuid_path = metadata
+        .get("optee")
+        .and_then(|v| v.get(component_type.as_str()))
+        .and_then(|v| v.get("uuid-path"))
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from);
+    resolve_uuid_path(
+        cmd_uuid_path,
+        metadata_uuid_path,
+        project_path,
+        PathBuf::from("../uuid.txt"),
+    )
+}
+
 /// Generate error message for missing ta-dev-kit-dir configuration
 fn ta_dev_kit_dir_error() -> anyhow::Error {
     anyhow::anyhow!(
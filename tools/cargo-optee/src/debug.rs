@@ -0,0 +1,225 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use anyhow::{Result, bail};
+use std::fs::{self, File};
+use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::common::{ChangeDirectoryGuard, read_uuid_from_file};
+use crate::config::TaBuildConfig;
+use crate::qemu_test::{self, QemuOptions, StagingDirs};
+use crate::run::{self, Component, Connection, Target};
+use crate::ta_builder::locate_binary;
+
+/// Options of `cargo optee debug`
+pub struct DebugConfig {
+    /// The TA whose symbols are loaded in gdb
+    pub ta: TaBuildConfig,
+    pub image_dir: PathBuf,
+    pub shared_dir: PathBuf,
+    pub ssh_port: u16,
+    /// Port of the gdb server of QEMU
+    pub gdb_port: u16,
+    pub keep_running: bool,
+    /// Command to run instead of the CA binary
+    pub run: Option<String>,
+    /// Arguments passed to the CA
+    pub args: Vec<String>,
+}
+
+/// Boot QEMU halted with its gdb server, install the components staged in
+/// `staging` once gdb continued it and run the CA. When the TA is loaded, its
+/// load address is read from the secure world console and a gdb script
+/// adding the symbols of the unstripped TA at that address is written to the
+/// staging directory.
+pub fn debug(config: &DebugConfig, staging: &StagingDirs) -> Result<()> {
+    let uuid_path = config
+        .ta
+        .uuid_path
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("UUID path is required but not configured"))?;
+    let uuid = read_uuid_from_file(uuid_path)?;
+    let elf = {
+        let _guard = ChangeDirectoryGuard::new(&config.ta.path)?;
+        locate_binary(&config.ta)?.1.canonicalize()?
+    };
+
+    let gdb_dir = staging.root.join("gdb");
+    fs::create_dir_all(&gdb_dir)?;
+    let attach_script = gdb_dir.join("attach.gdb");
+    fs::write(
+        &attach_script,
+        format!(
+            "# Generated by cargo optee debug\n\
+             set pagination off\n\
+             target remote :{}\n\
+             continue\n",
+            config.gdb_port
+        ),
+    )?;
+    let symbols_script = gdb_dir.join(format!("ta-{}.gdb", uuid));
+    let _ = fs::remove_file(&symbols_script);
+    println!(
+        "Attach gdb to boot QEMU: gdb-multiarch -x {}",
+        attach_script.display()
+    );
+
+    let options = QemuOptions {
+        image_dir: config.image_dir.clone(),
+        shared_dir: config.shared_dir.clone(),
+        ssh_port: config.ssh_port,
+        keep_running: config.keep_running,
+        gdb_port: Some(config.gdb_port),
+    };
+    let (_, secure_log) = qemu_test::console_logs(&options, staging);
+    let target = Target::Qemu(options);
+    let connection = Connection::open(&target, None, staging)?;
+    let ca_name = connection.install(staging, &Component::ALL)?;
+    let command = run::with_args(config.run.clone().unwrap_or(ca_name), &config.args);
+
+    let followers = connection.follow_consoles();
+    let watcher = LoadWatcher::start(&secure_log, uuid.clone(), elf, symbols_script.clone())?;
+    let status = connection.run_streaming(&command);
+    run::stop_followers(followers);
+    let loaded = watcher.stop();
+
+    if !loaded {
+        eprintln!(
+            "Warning: no load address of TA {} found in {:?}, OP-TEE only logs it with \
+             CFG_TEE_CORE_LOG_LEVEL=3 or when the TA aborts",
+            uuid, secure_log
+        );
+    }
+    if let Target::Qemu(options) = &target {
+        qemu_test::print_kept_running(options);
+    }
+    if !config.keep_running {
+        // The gdb session ends with QEMU
+        println!("QEMU keeps running for gdb, press Enter to stop it");
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+    }
+
+    let status = status?;
+    if !status.success() {
+        bail!("`{}` failed with exit code: {:?}", command, status.code());
+    }
+    Ok(())
+}
+
+/// Watches the secure world console for the load address of the TA, and
+/// writes the gdb script adding its symbols every time the TA is loaded.
+struct LoadWatcher {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<bool>,
+}
+
+impl LoadWatcher {
+    fn start(secure_log: &Path, uuid: String, elf: PathBuf, script: PathBuf) -> Result<Self> {
+        let mut file = File::open(secure_log)
+            .map_err(|e| anyhow::anyhow!("Failed to open console {:?}: {}", secure_log, e))?;
+        // Only the loads of this run, with the addresses of the current boot
+        file.seek(SeekFrom::End(0))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || watch(file, &uuid, &elf, &script, &stop)
+        });
+        Ok(Self { stop, handle })
+    }
+
+    /// Stop watching, returns whether the TA was loaded
+    fn stop(self) -> bool {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().unwrap_or(false)
+    }
+}
+
+fn watch(mut file: File, uuid: &str, elf: &Path, script: &Path, stop: &AtomicBool) -> bool {
+    let mut buf = [0u8; 4096];
+    let mut line = Vec::new();
+    let mut loaded = false;
+    loop {
+        // Checked before reading, so the log up to the stop is parsed
+        let stopping = stop.load(Ordering::Relaxed);
+        let read = file.read(&mut buf).unwrap_or(0);
+        for &byte in &buf[..read] {
+            if byte != b'\n' {
+                line.push(byte);
+                continue;
+            }
+            if let Some(address) = load_address(&String::from_utf8_lossy(&line), uuid) {
+                match write_symbols_script(script, uuid, elf, address) {
+                    Ok(()) => {
+                        println!(
+                            "TA {} loaded at {:#x}, interrupt gdb and run: source {}",
+                            uuid,
+                            address,
+                            script.display()
+                        );
+                        loaded = true;
+                    }
+                    Err(e) => eprintln!("Warning: failed to write {:?}: {}", script, e),
+                }
+            }
+            line.clear();
+        }
+        if read == 0 {
+            if stopping {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+    loaded
+}
+
+/// The load address of TA `uuid` in a line of the secure world console:
+/// `ELF (<uuid>) at 0x...` logged by ldelf when it loads the TA, or
+/// `[0] <uuid> @ 0x...` in the dump of an abort.
+fn load_address(line: &str, uuid: &str) -> Option<u64> {
+    let (_, rest) = line.split_once(uuid)?;
+    let rest = rest
+        .strip_prefix(") at ")
+        .or_else(|| rest.strip_prefix(" @ "))?;
+    let hex = rest.strip_prefix("0x")?;
+    let end = hex
+        .find(|c: char| !c.is_ascii_hexdigit())
+        .unwrap_or(hex.len());
+    u64::from_str_radix(&hex[..end], 16).ok()
+}
+
+/// Write the gdb script adding the symbols of the unstripped TA `elf`, which
+/// is linked at 0, at the address it is loaded at.
+fn write_symbols_script(script: &Path, uuid: &str, elf: &Path, address: u64) -> Result<()> {
+    fs::write(
+        script,
+        format!(
+            "# Symbols of TA {}, loaded at {:#x}, generated by cargo optee debug\n\
+             add-symbol-file {} -o {:#x}\n",
+            uuid,
+            address,
+            elf.display(),
+            address
+        ),
+    )?;
+    Ok(())
+}
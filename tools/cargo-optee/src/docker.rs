@@ -0,0 +1,202 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::{Result, bail};
+use std::io::IsTerminal;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::common::Arch;
+use crate::config::{ComponentType, DockerBuildConfig, IN_DOCKER_ENV};
+use crate::message;
+use crate::run::shell_quote;
+use crate::sign::ENC_KEY_ENV;
+use crate::status;
+
+/// Images the CI builds the examples in
+const NO_STD_IMAGE: &str = "teaclave/teaclave-trustzone-emulator-nostd-expand-memory:latest";
+const STD_IMAGE: &str = "teaclave/teaclave-trustzone-emulator-std-expand-memory:latest";
+/// Where the images build OP-TEE, see scripts/setup/bootstrap_env
+const OPTEE_DIR: &str = "/opt/teaclave/optee";
+/// Cargo registry of the images, cached in a volume across builds
+const CARGO_REGISTRY: &str = "/opt/teaclave/cargo/registry";
+/// Where cargo-optee is installed in the container, cached in a volume
+const INSTALL_ROOT: &str = "/opt/cargo-optee";
+/// Options replaced by the container, with whether they take a value
+const HOST_OPTIONS: [(&str, bool); 4] = [
+    ("--in-docker", false),
+    ("--docker-image", true),
+    ("--ta-dev-kit-dir", true),
+    ("--optee-client-export", true),
+];
+
+/// Run the cargo-optee command line `args` in a container of the image of
+/// `config`, with the same version of cargo-optee and the development kits
+/// of the image.
+///
+/// The repository of the project (or its parent directory outside of a git
+/// repository) is mounted at the same path, so paths in the arguments, the
+/// metadata and the outputs are the same on the host and in the container.
+pub fn build_in_docker(
+    config: &DockerBuildConfig,
+    project_path: &Path,
+    install_dir: Option<&Path>,
+    args: &[String],
+) -> Result<()> {
+    let mount_dir = mount_dir(project_path)?;
+    let current_dir = std::env::current_dir()?.canonicalize()?;
+    for (dir, what) in [
+        (Some(current_dir.as_path()), "Current directory"),
+        (install_dir, "Install directory"),
+    ] {
+        if let Some(dir) = dir
+            && !std::path::absolute(dir)?.starts_with(&mount_dir)
+        {
+            bail!(
+                "{} {:?} is outside of the directory mounted in the container {:?}",
+                what,
+                dir,
+                mount_dir
+            );
+        }
+    }
+
+    let mut command = format!(
+        "([ -x {root}/bin/cargo-optee ] || cargo install --locked --root {root} cargo-optee@{version}) \
+        && {root}/bin/cargo-optee",
+        root = INSTALL_ROOT,
+        version = env!("CARGO_PKG_VERSION"),
+    );
+    for arg in container_args(config, args)? {
+        command.push(' ');
+        command.push_str(&shell_quote(&arg));
+    }
+    // Files created in the mounted directory belong to root in the container,
+    // give them back to the owner of the directory
+    let owner = std::fs::metadata(&mount_dir)?;
+    if owner.uid() != 0 {
+        command = format!(
+            "{}; status=$?; find {} -user 0 -exec chown {}:{} {{}} +; exit $status",
+            command,
+            shell_quote(&mount_dir.to_string_lossy()),
+            owner.uid(),
+            owner.gid()
+        );
+    }
+
+    let image =
+        config
+            .image
+            .as_deref()
+            .unwrap_or(if config.std { STD_IMAGE } else { NO_STD_IMAGE });
+    let mount = mount_dir.to_string_lossy();
+    let mut docker = Command::new("docker");
+    docker.args(["run", "--rm"]);
+    // A terminal would mix the progress on stderr into the JSON messages
+    if std::io::stdin().is_terminal() && !message::is_json() {
+        docker.arg("-it");
+    }
+    // The key to encrypt the TA with is passed on from the host environment
+    if std::env::var_os(ENC_KEY_ENV).is_some() {
+        docker.args(["-e", ENC_KEY_ENV]);
+    }
+    docker
+        .args(["-e", &format!("{}=1", IN_DOCKER_ENV)])
+        .args(["-v", &format!("{}:{}", mount, mount)])
+        .args(["-v", &format!("cargo-optee-registry:{}", CARGO_REGISTRY)])
+        .args([
+            "-v",
+            &format!("cargo-optee-{}:{}", env!("CARGO_PKG_VERSION"), INSTALL_ROOT),
+        ])
+        .arg("-w")
+        .arg(&current_dir)
+        .arg(image)
+        .arg(command);
+
+    status!(
+        "Building in docker image {} with {:?} mounted",
+        image,
+        mount_dir
+    );
+    let status = docker
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to run docker: {}", e))?;
+    if !status.success() {
+        bail!("Build in docker image {} failed", image);
+    }
+    Ok(())
+}
+
+/// The arguments of the build in the container: the ones of the host without
+/// the docker options and the paths of the development kits, which are
+/// replaced by the ones of the image
+fn container_args(config: &DockerBuildConfig, args: &[String]) -> Result<Vec<String>> {
+    let mut container_args = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, _)) => (name, true),
+            None => (arg.as_str(), false),
+        };
+        match HOST_OPTIONS.iter().find(|(option, _)| *option == name) {
+            Some((_, takes_value)) => {
+                if *takes_value && !inline_value {
+                    args.next();
+                }
+            }
+            None => container_args.push(arg.clone()),
+        }
+    }
+
+    let arch = match config.arch {
+        Arch::Aarch64 => "arm64",
+        Arch::Arm => "arm32",
+        Arch::Riscv64 | Arch::Riscv32 => bail!(
+            "The docker images have no OP-TEE build for {:?}",
+            config.arch
+        ),
+    };
+    match config.component_type {
+        ComponentType::Ta => {
+            container_args.push("--ta-dev-kit-dir".to_string());
+            container_args.push(format!(
+                "{}/optee_os/out/arm-plat-vexpress/export-ta_{}",
+                OPTEE_DIR, arch
+            ));
+        }
+        ComponentType::Ca | ComponentType::Plugin => {
+            container_args.push("--optee-client-export".to_string());
+            container_args.push(format!("{}/optee_client/export_{}", OPTEE_DIR, arch));
+        }
+    }
+    Ok(container_args)
+}
+
+/// The directory mounted in the container: the root of the git repository of
+/// the project, for the path dependencies on its other crates, or the parent
+/// of the project, for its `uuid.txt` and proto crate
+fn mount_dir(project_path: &Path) -> Result<PathBuf> {
+    let project_path = project_path.canonicalize()?;
+    if let Some(root) = project_path
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+    {
+        return Ok(root.to_path_buf());
+    }
+    Ok(project_path.parent().unwrap_or(&project_path).to_path_buf())
+}
@@ -0,0 +1,356 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::qemu_test::{self, SSH_TARGET, Ssh};
+
+/// OP-TEE version of the images downloaded by default, the one the SDK is
+/// tested with
+const DEFAULT_OPTEE_VERSION: &str = include_str!("../../../optee-version.txt");
+/// Where tests/setup.sh downloads the images from
+const IMAGE_URL: &str = "https://nightlies.apache.org/teaclave/teaclave-trustzone-sdk";
+/// Time QEMU gets to open its serial consoles
+const PTY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time QEMU gets to exit when stopped
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+/// File in the state directory describing the running emulator
+const STATE_FILE: &str = "state.json";
+
+/// Options of `cargo optee emu up`
+pub struct EmuUpConfig {
+    /// Directory of an image to boot instead of the downloaded one
+    pub image_dir: Option<PathBuf>,
+    pub optee_version: Option<String>,
+    /// Download the image with more memory for the TAs
+    pub expand_ta_memory: bool,
+    pub shared_dir: PathBuf,
+    pub ssh_port: u16,
+}
+
+/// An emulator started by `emu up`, saved in its state directory
+#[derive(Debug, Serialize, Deserialize)]
+struct EmuState {
+    pid: u32,
+    image_dir: PathBuf,
+    shared_dir: PathBuf,
+    ssh_port: u16,
+    /// Pseudo-terminals of the normal and secure world consoles
+    normal_pty: String,
+    secure_pty: String,
+}
+
+/// Boot the OP-TEE QEMU image in the background, with the consoles on
+/// pseudo-terminals, unless an emulator is already running on the port.
+pub fn up(config: &EmuUpConfig) -> Result<()> {
+    let state_dir = state_dir(config.ssh_port)?;
+    if let Some(state) = load_state(&state_dir)? {
+        println!("Emulator already running");
+        print_status(&state, &state_dir);
+        return Ok(());
+    }
+    if Ssh::new(SSH_TARGET, config.ssh_port).is_ready() {
+        bail!(
+            "Port {} is used by a QEMU not started by `cargo optee emu up`",
+            config.ssh_port
+        );
+    }
+
+    let image_dir = match &config.image_dir {
+        Some(image_dir) => image_dir
+            .canonicalize()
+            .with_context(|| format!("Image directory {:?}", image_dir))?,
+        None => cached_image(
+            config
+                .optee_version
+                .as_deref()
+                .unwrap_or(DEFAULT_OPTEE_VERSION.trim()),
+            config.expand_ta_memory,
+        )?,
+    };
+    fs::create_dir_all(&config.shared_dir)?;
+    let shared_dir = config.shared_dir.canonicalize()?;
+    fs::create_dir_all(&state_dir)?;
+    let qemu_log = state_dir.join("qemu.log");
+    let normal_log = state_dir.join("normal_world.log");
+    let secure_log = state_dir.join("secure_world.log");
+    let log = File::create(&qemu_log)?;
+
+    println!("Booting QEMU from {:?}...", image_dir);
+    let mut child = qemu_test::qemu_command(&image_dir, &shared_dir, config.ssh_port)?
+        .arg("-chardev")
+        .arg(format!("pty,id=normal,logfile={}", normal_log.display()))
+        .args(["-serial", "chardev:normal"])
+        .arg("-chardev")
+        .arg(format!("pty,id=secure,logfile={}", secure_log.display()))
+        .args(["-serial", "chardev:secure"])
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        // Not stopped by a Ctrl-C in the terminal of `emu up`
+        .process_group(0)
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to start QEMU: {}", e))?;
+
+    let booted = wait_for_ptys(&mut child, &qemu_log).and_then(|(normal_pty, secure_pty)| {
+        let ssh = Ssh::new(SSH_TARGET, config.ssh_port);
+        ssh.wait_until_ready(&mut child, Some(qemu_test::BOOT_TIMEOUT))?;
+        qemu_test::mount_shared_dir(&ssh)?;
+        Ok((normal_pty, secure_pty))
+    });
+    let (normal_pty, secure_pty) = match booted {
+        Ok(ptys) => ptys,
+        Err(e) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e).with_context(|| format!("QEMU output in {:?}", qemu_log));
+        }
+    };
+
+    let state = EmuState {
+        pid: child.id(),
+        image_dir,
+        shared_dir,
+        ssh_port: config.ssh_port,
+        normal_pty,
+        secure_pty,
+    };
+    fs::write(
+        state_dir.join(STATE_FILE),
+        serde_json::to_string_pretty(&state)?,
+    )?;
+    println!("Emulator ready");
+    print_status(&state, &state_dir);
+    Ok(())
+}
+
+/// Stop the emulator running on the port.
+pub fn down(ssh_port: u16) -> Result<()> {
+    let state_dir = state_dir(ssh_port)?;
+    let Some(state) = load_state(&state_dir)? else {
+        println!("No emulator running on port {}", ssh_port);
+        return Ok(());
+    };
+    println!("Stopping QEMU (pid {})...", state.pid);
+    let status = Command::new("kill")
+        .arg(state.pid.to_string())
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to run kill: {}", e))?;
+    if !status.success() {
+        bail!("Failed to stop QEMU (pid {})", state.pid);
+    }
+    let start = Instant::now();
+    while is_alive(state.pid) {
+        if start.elapsed() > STOP_TIMEOUT {
+            bail!(
+                "QEMU (pid {}) still running after {:?}",
+                state.pid,
+                STOP_TIMEOUT
+            );
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    fs::remove_file(state_dir.join(STATE_FILE))?;
+    println!("Emulator stopped");
+    Ok(())
+}
+
+/// Show whether an emulator is running on the port, and how to reach it.
+pub fn status(ssh_port: u16) -> Result<()> {
+    let state_dir = state_dir(ssh_port)?;
+    match load_state(&state_dir)? {
+        Some(state) => print_status(&state, &state_dir),
+        None => println!("No emulator running on port {}", ssh_port),
+    }
+    Ok(())
+}
+
+/// An emulator started by `emu up`, as seen by the commands using it
+pub struct RunningEmu {
+    pub image_dir: PathBuf,
+    pub shared_dir: PathBuf,
+    pub normal_log: PathBuf,
+    pub secure_log: PathBuf,
+}
+
+/// The emulator started by `emu up` on the port, if it is running
+pub fn running(ssh_port: u16) -> Option<RunningEmu> {
+    let state_dir = state_dir(ssh_port).ok()?;
+    let state = load_state(&state_dir).ok()??;
+    Some(RunningEmu {
+        image_dir: state.image_dir,
+        shared_dir: state.shared_dir,
+        normal_log: state_dir.join("normal_world.log"),
+        secure_log: state_dir.join("secure_world.log"),
+    })
+}
+
+fn print_status(state: &EmuState, state_dir: &Path) {
+    let ssh = if Ssh::new(SSH_TARGET, state.ssh_port).is_ready() {
+        "ready"
+    } else {
+        "not reachable"
+    };
+    println!("  pid:            {}", state.pid);
+    println!("  image:          {}", state.image_dir.display());
+    println!(
+        "  shared folder:  {} (/mnt/host in the guest)",
+        state.shared_dir.display()
+    );
+    println!(
+        "  SSH:            ssh -p {} {} ({})",
+        state.ssh_port, SSH_TARGET, ssh
+    );
+    println!(
+        "  normal world:   {} (log: {})",
+        state.normal_pty,
+        state_dir.join("normal_world.log").display()
+    );
+    println!(
+        "  secure world:   {} (log: {})",
+        state.secure_pty,
+        state_dir.join("secure_world.log").display()
+    );
+    println!(
+        "Attach to a console with e.g. `screen {}`, stop with `cargo optee emu down`",
+        state.normal_pty
+    );
+}
+
+/// Directory of the state and console logs of the emulator on `ssh_port`
+fn state_dir(ssh_port: u16) -> Result<PathBuf> {
+    Ok(cache_dir()?.join("emu").join(ssh_port.to_string()))
+}
+
+fn cache_dir() -> Result<PathBuf> {
+    dirs::cache_dir()
+        .map(|dir| dir.join("cargo-optee"))
+        .ok_or_else(|| anyhow::anyhow!("No cache directory for the emulator"))
+}
+
+/// The state of the emulator of `state_dir` if it is still running,
+/// removing the state of one that exited.
+fn load_state(state_dir: &Path) -> Result<Option<EmuState>> {
+    let path = state_dir.join(STATE_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let state: EmuState = serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid emulator state {:?}", path))?;
+    if is_alive(state.pid) {
+        Ok(Some(state))
+    } else {
+        fs::remove_file(&path)?;
+        Ok(None)
+    }
+}
+
+fn is_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The image of tests/setup.sh for `optee_version`, downloaded to the cache
+/// directory unless it is there already.
+fn cached_image(optee_version: &str, expand_ta_memory: bool) -> Result<PathBuf> {
+    let name = image_name(std::env::consts::ARCH, optee_version, expand_ta_memory);
+    let images = cache_dir()?.join("images");
+    let image_dir = images.join(&name);
+    if image_dir.join("qemu-system-aarch64").exists() {
+        return Ok(image_dir);
+    }
+
+    let url = format!("{}/{}.tar.gz", IMAGE_URL, name);
+    println!("Downloading {}...", url);
+    fs::create_dir_all(&images)?;
+    // Extracted aside, so an interrupted download is not taken for an image
+    let download = tempfile::tempdir_in(&images)?;
+    let mut curl = Command::new("curl")
+        .args(["-fsSL", &url])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run curl: {}", e))?;
+    let tar = Command::new("tar")
+        .arg("zx")
+        .arg("-C")
+        .arg(download.path())
+        .stdin(curl.stdout.take().expect("stdout is piped"))
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to run tar: {}", e))?;
+    if !curl.wait()?.success() || !tar.success() {
+        bail!("Failed to download the QEMU image from {}", url);
+    }
+    if image_dir.exists() {
+        fs::remove_dir_all(&image_dir)?;
+    }
+    fs::rename(download.path().join(&name), &image_dir)
+        .with_context(|| format!("{} does not contain {}", url, name))?;
+    Ok(image_dir)
+}
+
+/// Name of the image built for `host_arch` hosts, as in tests/setup.sh
+fn image_name(host_arch: &str, optee_version: &str, expand_ta_memory: bool) -> String {
+    let mut name = format!("{}-optee-{}-qemuv8-ubuntu-24.04", host_arch, optee_version);
+    if expand_ta_memory {
+        name.push_str("-expand-ta-memory");
+    }
+    name
+}
+
+/// Wait for QEMU to report the pseudo-terminals of the normal and secure
+/// world consoles in its output
+fn wait_for_ptys(qemu: &mut std::process::Child, qemu_log: &Path) -> Result<(String, String)> {
+    let start = Instant::now();
+    loop {
+        let output = fs::read_to_string(qemu_log).unwrap_or_default();
+        if let (Some(normal), Some(secure)) =
+            (find_pty(&output, "normal"), find_pty(&output, "secure"))
+        {
+            return Ok((normal, secure));
+        }
+        if let Some(status) = qemu.try_wait()? {
+            bail!("QEMU exited during boot with {}", status);
+        }
+        if start.elapsed() > PTY_TIMEOUT {
+            bail!("QEMU did not open its consoles after {:?}", PTY_TIMEOUT);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// The pseudo-terminal of the chardev `label` in QEMU's output, reported as
+/// `char device redirected to /dev/pts/3 (label normal)`
+fn find_pty(output: &str, label: &str) -> Option<String> {
+    let suffix = format!(" (label {})", label);
+    output.lines().find_map(|line| {
+        line.trim_end()
+            .strip_suffix(&suffix)?
+            .split("char device redirected to ")
+            .nth(1)
+            .map(str::to_string)
+    })
+}
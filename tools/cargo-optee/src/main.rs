@@ -17,21 +17,62 @@
 
 use clap::Parser;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 
 mod ca_builder;
+mod clean;
 mod cli;
 mod common;
 mod config;
+mod debug;
+mod docker;
+mod emu;
+mod message;
+mod new_project;
+mod package;
+mod project_uuid;
+mod qemu_test;
+mod run;
+mod sign;
+mod size_report;
 mod ta_builder;
+mod trace;
+mod watch;
+mod workspace;
 
-use cli::{BuildCommand, Cli, Command, CommonBuildArgs, InstallCommand};
+use cli::{
+    BuildArgs, BuildCommand, Cli, Command, CommonBuildArgs, DebugCommand, EmuCommand,
+    InstallCommand, PackageCommand, RunCommand, SignCommand, TABuildArgs, TestCommand,
+    TraceCommand, TracePullArgs, UuidArgs, UuidCommand, WatchCommand, WorkspaceBuildArgs,
+};
+
+/// Host port forwarded to SSH in QEMU by default
+const QEMU_SSH_PORT: u16 = 54432;
 
 fn main() {
+    let cli = Cli::parse_from(cli_args());
+    message::set_format(cli.cmd.message_format());
+    let result = execute_command(cli.cmd);
+
+    // Reported once by the host for the builds in docker
+    if env::var_os(config::IN_DOCKER_ENV).is_none() {
+        message::emit(&message::Message::Finished {
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        });
+    }
+    if let Err(e) = result {
+        eprintln!("Error: {:#}", e);
+        process::exit(1);
+    }
+}
+
+/// The command line arguments, without the program name
+fn cli_args() -> Vec<String> {
     // Drop extra `optee` argument provided by `cargo`.
     let mut found_optee = false;
-    let filtered_args: Vec<String> = env::args()
+    env::args()
         .filter(|x| {
             if found_optee {
                 true
@@ -40,45 +81,28 @@ fn main() {
                 x != "optee"
             }
         })
-        .collect();
-
-    let cli = Cli::parse_from(filtered_args);
-    let result = execute_command(cli.cmd);
-
-    if let Err(e) = result {
-        eprintln!("Error: {}", e);
-        process::exit(1);
-    }
+        .collect()
 }
 
 fn execute_command(cmd: Command) -> anyhow::Result<()> {
+    if build_in_docker(&cmd)? {
+        return Ok(());
+    }
     match cmd {
-        Command::Build(build_cmd) => match build_cmd {
-            BuildCommand::TA { build_cmd } => {
-                // Convert bool flags to Option<bool>: --std -> Some(true), --no-std -> Some(false), neither -> None
-                let std_mode = match (build_cmd.std, build_cmd.no_std) {
-                    (true, false) => Some(true),
-                    (false, true) => Some(false),
-                    _ => None,
-                };
-
-                execute_ta_command(
-                    build_cmd.common,
-                    std_mode,
-                    build_cmd.ta_dev_kit_dir,
-                    build_cmd.signing_key,
-                    build_cmd.uuid_path,
-                    None,
-                )
+        Command::Build(build_args) => match build_args.cmd {
+            None => execute_workspace_build(build_args.workspace),
+            Some(BuildCommand::TA { build_cmd }) => {
+                let ta_config = resolve_ta_config(build_cmd)?;
+                ta_builder::build_ta(ta_config, None)
             }
-            BuildCommand::CA { build_cmd } => execute_ca_command(
+            Some(BuildCommand::CA { build_cmd }) => execute_ca_command(
                 build_cmd.common,
                 build_cmd.optee_client_export,
                 None,
                 false,
                 None,
             ),
-            BuildCommand::Plugin { build_cmd } => execute_ca_command(
+            Some(BuildCommand::Plugin { build_cmd }) => execute_ca_command(
                 build_cmd.common,
                 build_cmd.optee_client_export,
                 build_cmd.uuid_path,
@@ -91,21 +115,8 @@ fn execute_command(cmd: Command) -> anyhow::Result<()> {
                 target_dir,
                 build_cmd,
             } => {
-                // Convert bool flags to Option<bool>: --std -> Some(true), --no-std -> Some(false), neither -> None
-                let std_mode = match (build_cmd.std, build_cmd.no_std) {
-                    (true, false) => Some(true),
-                    (false, true) => Some(false),
-                    _ => None,
-                };
-
-                execute_ta_command(
-                    build_cmd.common,
-                    std_mode,
-                    build_cmd.ta_dev_kit_dir,
-                    build_cmd.signing_key,
-                    build_cmd.uuid_path,
-                    Some(&target_dir),
-                )
+                let ta_config = resolve_ta_config(build_cmd)?;
+                ta_builder::build_ta(ta_config, Some(&target_dir))
             }
             InstallCommand::CA {
                 target_dir,
@@ -130,22 +141,535 @@ fn execute_command(cmd: Command) -> anyhow::Result<()> {
         },
         Command::Clean { clean_cmd } => {
             let project_path = resolve_project_path(clean_cmd.manifest_path.as_ref())?;
+            clean::clean_project(&clean::CleanConfig {
+                project_path,
+                component: clean_cmd.component,
+                install_dir: clean_cmd.target_dir,
+                uuid_path: clean_cmd.uuid_path,
+            })
+        }
+        Command::Size { size_cmd } => {
+            let ta_config = resolve_ta_config(size_cmd.build_cmd)?;
+            size_report::report_ta_size(&ta_config, size_cmd.top)
+        }
+        Command::New { new_cmd } => {
+            new_project::new_project(&new_cmd.name, new_cmd.template, new_cmd.sdk_path.as_deref())
+        }
+        Command::Test { test_cmd } => execute_test_command(test_cmd),
+        Command::Run { run_cmd } => execute_run_command(run_cmd),
+        Command::Watch { watch_cmd } => execute_watch_command(watch_cmd),
+        Command::Debug { debug_cmd } => execute_debug_command(debug_cmd),
+        Command::Sign { sign_cmd } => execute_sign_command(sign_cmd),
+        Command::Package { package_cmd } => execute_package_command(package_cmd),
+        Command::Emu(emu_cmd) => match emu_cmd {
+            EmuCommand::Up(args) => emu::up(&emu::EmuUpConfig {
+                image_dir: args.image_dir,
+                optee_version: args.optee_version,
+                expand_ta_memory: args.expand_ta_memory,
+                shared_dir: args.shared_dir,
+                ssh_port: args.emu.ssh_port,
+            }),
+            EmuCommand::Down(args) => emu::down(args.ssh_port),
+            EmuCommand::Status(args) => emu::status(args.ssh_port),
+        },
+        Command::Uuid(uuid_cmd) => match uuid_cmd {
+            UuidCommand::Show(args) => execute_uuid_command(args, project_uuid::UuidChange::Show),
+            UuidCommand::New(args) => execute_uuid_command(args, project_uuid::UuidChange::New),
+            UuidCommand::Set { uuid, args } => {
+                execute_uuid_command(args, project_uuid::UuidChange::Set(uuid))
+            }
+        },
+        Command::Trace(TraceCommand::Pull(args)) => execute_trace_pull_command(args),
+    }
+}
+
+/// Show or change the UUID of the TA or plugin of the manifest, or of the TA
+/// in `ta/` when run in the root of a project
+fn execute_uuid_command(args: UuidArgs, change: project_uuid::UuidChange) -> anyhow::Result<()> {
+    project_uuid::run(&project_uuid::UuidConfig {
+        project_path: resolve_ta_project_path(args.manifest_path.as_ref())?,
+        component: args.component,
+        uuid_path: args.uuid_path,
+        change,
+    })
+}
+
+/// Pull the function graph of the TA of the manifest, or of the TA in `ta/`
+/// when run in the root of a project
+fn execute_trace_pull_command(args: TracePullArgs) -> anyhow::Result<()> {
+    let project_path = resolve_ta_project_path(args.manifest_path.as_ref())?;
+    // Only the paths are needed, read from the metadata
+    let ta = config::TaBuildConfig::resolve(
+        &project_path,
+        None,
+        Some(false),
+        args.uuid_path,
+        Vec::new(),
+        false,
+        None,
+        true,
+        None,
+        args.ta_dev_kit_dir,
+        None,
+        None,
+        false,
+        false,
+        None,
+        None,
+        true,
+        None,
+    )?;
+    let (destination, port) = match args.ssh {
+        Some(destination) => (destination, args.ssh_port.unwrap_or(22)),
+        None => (
+            qemu_test::SSH_TARGET.to_string(),
+            args.ssh_port.unwrap_or(QEMU_SSH_PORT),
+        ),
+    };
+    trace::pull(&trace::TracePullConfig {
+        ta,
+        destination,
+        port,
+        output: args.output,
+        symbolize: !args.raw,
+    })
+}
 
-            // Clean build artifacts using the common function
-            crate::common::clean_project(&project_path)
+/// Run the build, install, size or package command in a container if
+/// requested, returns whether it was
+fn build_in_docker(cmd: &Command) -> anyhow::Result<bool> {
+    use config::ComponentType::{Ca, Plugin, Ta};
+    let (common, component_type, std_mode, install_dir) = match cmd {
+        Command::Build(BuildArgs {
+            cmd: Some(build_cmd),
+            ..
+        }) => match build_cmd {
+            BuildCommand::TA { build_cmd } => (&build_cmd.common, Ta, ta_std_mode(build_cmd), None),
+            BuildCommand::CA { build_cmd } => (&build_cmd.common, Ca, None, None),
+            BuildCommand::Plugin { build_cmd } => (&build_cmd.common, Plugin, None, None),
+        },
+        Command::Install(install_cmd) => match install_cmd {
+            InstallCommand::TA {
+                target_dir,
+                build_cmd,
+            } => (
+                &build_cmd.common,
+                Ta,
+                ta_std_mode(build_cmd),
+                Some(target_dir),
+            ),
+            InstallCommand::CA {
+                target_dir,
+                build_cmd,
+            } => (&build_cmd.common, Ca, None, Some(target_dir)),
+            InstallCommand::Plugin {
+                target_dir,
+                build_cmd,
+            } => (&build_cmd.common, Plugin, None, Some(target_dir)),
+        },
+        Command::Size { size_cmd } => (
+            &size_cmd.build_cmd.common,
+            Ta,
+            ta_std_mode(&size_cmd.build_cmd),
+            None,
+        ),
+        Command::Package { package_cmd } => (
+            &package_cmd.build_cmd.common,
+            Ta,
+            ta_std_mode(&package_cmd.build_cmd),
+            None,
+        ),
+        _ => return Ok(false),
+    };
+
+    let project_path = resolve_project_path(common.manifest_path.as_ref())?;
+    let Some(docker_config) = config::DockerBuildConfig::resolve(
+        &project_path,
+        component_type,
+        common.arch,
+        std_mode,
+        common.in_docker,
+        common.docker_image.clone(),
+    )?
+    else {
+        return Ok(false);
+    };
+    docker::build_in_docker(
+        &docker_config,
+        &project_path,
+        install_dir.map(|p| p.as_path()),
+        &cli_args()[1..],
+    )?;
+    Ok(true)
+}
+
+/// Build all the components of a workspace
+fn execute_workspace_build(args: WorkspaceBuildArgs) -> anyhow::Result<()> {
+    let manifest_path = match args.manifest_path {
+        Some(manifest_path) => manifest_path,
+        None => env::current_dir()?.join("Cargo.toml"),
+    };
+    // Paths given on the command line are relative to the current directory,
+    // not to each package
+    let absolute = |path: Option<PathBuf>| path.map(std::path::absolute).transpose();
+    workspace::build_workspace(&workspace::WorkspaceBuildConfig {
+        manifest_path,
+        packages: args.packages,
+        exclude: args.exclude,
+        out_dir: absolute(args.out_dir)?,
+        arch: args.arch,
+        debug: args.debug,
+        ta_dev_kit_dir: absolute(args.ta_dev_kit_dir)?,
+        optee_client_export: absolute(args.optee_client_export)?,
+    })
+}
+
+/// Build and sign the TA and write its manifest
+fn execute_package_command(package_cmd: PackageCommand) -> anyhow::Result<()> {
+    let ta_config = resolve_ta_config(package_cmd.build_cmd)?;
+    package::package_ta(ta_config, package_cmd.output.as_deref())
+}
+
+/// Sign a stripped TA with a PEM key, a PKCS#11 key or an external command
+fn execute_sign_command(sign_cmd: SignCommand) -> anyhow::Result<()> {
+    // The argument group guarantees exactly one signer
+    let signer = match (sign_cmd.key, sign_cmd.sign_command) {
+        (Some(key), _) => sign::Signer::from_key(&key),
+        (None, Some(command)) => sign::Signer::Command(command),
+        (None, None) => unreachable!(),
+    };
+    let output = sign_cmd.output.unwrap_or_else(|| {
+        sign_cmd
+            .input
+            .with_file_name(format!("{}.ta", sign_cmd.uuid))
+    });
+    let encryption = &sign_cmd.encryption;
+    let encryption = (encryption.encrypt || encryption.enc_key_file.is_some())
+        .then(|| {
+            sign::Encryption::resolve(
+                encryption.enc_key_file.as_deref(),
+                encryption
+                    .enc_key_type
+                    .unwrap_or(sign::EncKeyType::DevSpecific),
+            )
+        })
+        .transpose()?;
+    sign::sign_ta(&sign::SignConfig {
+        input: sign_cmd.input,
+        uuid: sign_cmd.uuid,
+        signer,
+        public_key: sign_cmd.public_key,
+        output,
+        ta_dev_kit_dir: sign_cmd.ta_dev_kit_dir,
+        encryption,
+    })
+}
+
+/// Build the TA, CA and plugin into the shared folder and run the test in QEMU
+fn execute_test_command(test_cmd: TestCommand) -> anyhow::Result<()> {
+    let (image_dir, shared_dir) = resolve_qemu_dirs(
+        test_cmd.emu,
+        Some(test_cmd.ssh_port),
+        test_cmd.image_dir,
+        test_cmd.shared_dir,
+    )?;
+    // Required by clap without --emu, and set from the emulator with it
+    let image_dir = image_dir.expect("--image-dir or --emu");
+    let staging = qemu_test::StagingDirs::create(&shared_dir)?;
+    build_staged(
+        &test_cmd.ta_manifest,
+        &test_cmd.ca_manifest,
+        test_cmd.plugin_manifest.as_ref(),
+        test_cmd.debug,
+        &staging,
+    )?;
+
+    qemu_test::run_test(
+        &qemu_test::QemuTestConfig {
+            qemu: qemu_test::QemuOptions {
+                image_dir,
+                shared_dir,
+                ssh_port: test_cmd.ssh_port,
+                keep_running: test_cmd.keep_running,
+                gdb_port: None,
+            },
+            run: test_cmd.run,
+            expect: test_cmd.expect,
+            timeout: std::time::Duration::from_secs(test_cmd.timeout),
+        },
+        &staging,
+    )
+}
+
+/// Build the TA, CA and plugin into the shared folder, install them on the
+/// target and run the CA
+fn execute_run_command(run_cmd: RunCommand) -> anyhow::Result<()> {
+    let (image_dir, shared_dir) = resolve_qemu_dirs(
+        run_cmd.emu,
+        run_cmd.ssh_port,
+        run_cmd.image_dir,
+        run_cmd.shared_dir,
+    )?;
+    let staging = qemu_test::StagingDirs::create(&shared_dir)?;
+    build_staged(
+        &run_cmd.ta_manifest,
+        &run_cmd.ca_manifest,
+        run_cmd.plugin_manifest.as_ref(),
+        run_cmd.debug,
+        &staging,
+    )?;
+
+    let target = resolve_target(
+        image_dir,
+        run_cmd.ssh,
+        run_cmd.adb_serial,
+        run_cmd.ssh_port,
+        shared_dir,
+        run_cmd.keep_running,
+    );
+    run::run(
+        &run::RunConfig {
+            target,
+            run: run_cmd.run,
+            args: run_cmd.args,
+            secure_log: run_cmd.secure_log,
+        },
+        &staging,
+    )
+}
+
+/// Build, install on the target and run the project, then rebuild and
+/// reinstall its components whenever their sources change
+fn execute_watch_command(watch_cmd: WatchCommand) -> anyhow::Result<()> {
+    let (image_dir, shared_dir) = resolve_qemu_dirs(
+        watch_cmd.emu,
+        watch_cmd.ssh_port,
+        watch_cmd.image_dir,
+        watch_cmd.shared_dir,
+    )?;
+    let staging = qemu_test::StagingDirs::create(&shared_dir)?;
+    let mut components = vec![
+        (run::Component::Ta, watch_cmd.ta_manifest),
+        (run::Component::Ca, watch_cmd.ca_manifest),
+    ];
+    if let Some(plugin_manifest) = watch_cmd.plugin_manifest {
+        components.push((run::Component::Plugin, plugin_manifest));
+    }
+    let components = components
+        .into_iter()
+        .map(|(component, manifest)| Ok((component, resolve_project_path(Some(&manifest))?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let target = resolve_target(
+        image_dir,
+        watch_cmd.ssh,
+        watch_cmd.adb_serial,
+        watch_cmd.ssh_port,
+        shared_dir,
+        watch_cmd.keep_running,
+    );
+    let config = watch::WatchConfig {
+        target,
+        components: components.clone(),
+        run: watch_cmd.run,
+        secure_log: watch_cmd.secure_log,
+        interval: std::time::Duration::from_millis(watch_cmd.interval),
+    };
+    let debug = watch_cmd.debug;
+    watch::watch(&config, &staging, |component| {
+        // Every component has a directory, see above
+        let (_, project_path) = components.iter().find(|(c, _)| *c == component).unwrap();
+        match component {
+            run::Component::Ta => build_ta_staged(project_path, debug, &staging.ta),
+            run::Component::Ca => build_ca_staged(project_path, false, debug, &staging.ca),
+            run::Component::Plugin => build_ca_staged(project_path, true, debug, &staging.plugin),
         }
+    })
+}
+
+/// Build the project into the shared folder and run it in QEMU booted for
+/// gdb, with a gdb script loading the symbols of the TA once it is loaded
+fn execute_debug_command(debug_cmd: DebugCommand) -> anyhow::Result<()> {
+    let debug = !debug_cmd.release;
+    let staging = qemu_test::StagingDirs::create(&debug_cmd.shared_dir)?;
+    build_staged(
+        &debug_cmd.ta_manifest,
+        &debug_cmd.ca_manifest,
+        debug_cmd.plugin_manifest.as_ref(),
+        debug,
+        &staging,
+    )?;
+
+    // Only the paths are needed, to find the unstripped TA just built
+    let ta = config::TaBuildConfig::resolve(
+        &resolve_project_path(Some(&debug_cmd.ta_manifest))?,
+        None,
+        Some(debug),
+        None,
+        Vec::new(),
+        false,
+        None,
+        true,
+        None,
+        None,
+        None,
+        None,
+        false,
+        false,
+        None,
+        None,
+        false,
+        None,
+    )?;
+    debug::debug(
+        &debug::DebugConfig {
+            ta,
+            image_dir: debug_cmd.image_dir,
+            shared_dir: debug_cmd.shared_dir,
+            ssh_port: debug_cmd.ssh_port,
+            gdb_port: debug_cmd.gdb_port,
+            keep_running: debug_cmd.keep_running,
+            run: debug_cmd.run,
+            args: debug_cmd.args,
+        },
+        &staging,
+    )
+}
+
+/// The image and shared folder of the QEMU to use: with `--emu` the ones of
+/// the emulator started by `emu up` on the port, the given ones otherwise
+fn resolve_qemu_dirs(
+    emu: bool,
+    ssh_port: Option<u16>,
+    image_dir: Option<PathBuf>,
+    shared_dir: PathBuf,
+) -> anyhow::Result<(Option<PathBuf>, PathBuf)> {
+    if !emu {
+        return Ok((image_dir, shared_dir));
     }
+    let port = ssh_port.unwrap_or(QEMU_SSH_PORT);
+    let emu = emu::running(port).ok_or_else(|| {
+        anyhow::anyhow!(
+            "No emulator running on port {}, start one with `cargo optee emu up`",
+            port
+        )
+    })?;
+    Ok((Some(emu.image_dir), emu.shared_dir))
 }
 
-/// Execute TA build or install (shared logic)
-fn execute_ta_command(
-    common: CommonBuildArgs,
-    std: Option<bool>,
-    ta_dev_kit_dir: Option<PathBuf>,
-    signing_key: Option<PathBuf>,
-    uuid_path: Option<PathBuf>,
-    install_target_dir: Option<&PathBuf>,
+/// The target selected by the arguments of `run` or `watch`, whose argument
+/// group guarantees exactly one target
+fn resolve_target(
+    image_dir: Option<PathBuf>,
+    ssh: Option<String>,
+    adb_serial: Option<String>,
+    ssh_port: Option<u16>,
+    shared_dir: PathBuf,
+    keep_running: bool,
+) -> run::Target {
+    if let Some(image_dir) = image_dir {
+        run::Target::Qemu(qemu_test::QemuOptions {
+            image_dir,
+            shared_dir,
+            ssh_port: ssh_port.unwrap_or(QEMU_SSH_PORT),
+            keep_running,
+            gdb_port: None,
+        })
+    } else if let Some(destination) = ssh {
+        run::Target::Ssh {
+            destination,
+            port: ssh_port.unwrap_or(22),
+        }
+    } else {
+        run::Target::Adb { serial: adb_serial }
+    }
+}
+
+/// Build the TA, the CA and the optional plugin into the staging directories
+fn build_staged(
+    ta_manifest: &PathBuf,
+    ca_manifest: &PathBuf,
+    plugin_manifest: Option<&PathBuf>,
+    debug: bool,
+    staging: &qemu_test::StagingDirs,
 ) -> anyhow::Result<()> {
+    build_ta_staged(
+        &resolve_project_path(Some(ta_manifest))?,
+        debug,
+        &staging.ta,
+    )?;
+    build_ca_staged(
+        &resolve_project_path(Some(ca_manifest))?,
+        false,
+        debug,
+        &staging.ca,
+    )?;
+    if let Some(plugin_manifest) = plugin_manifest {
+        build_ca_staged(
+            &resolve_project_path(Some(plugin_manifest))?,
+            true,
+            debug,
+            &staging.plugin,
+        )?;
+    }
+    Ok(())
+}
+
+/// Build and sign the TA of `project_path` into `install_dir`
+fn build_ta_staged(project_path: &Path, debug: bool, install_dir: &Path) -> anyhow::Result<()> {
+    // Everything else is read from the Cargo.toml metadata of the components
+    let ta_config = config::TaBuildConfig::resolve(
+        project_path,
+        None,
+        Some(debug),
+        None,
+        Vec::new(),
+        false,
+        None,
+        false,
+        None,
+        None,
+        None,
+        None,
+        false,
+        false,
+        None,
+        None,
+        false,
+        None,
+    )?;
+    ta_config.print_config();
+    ta_builder::build_ta(ta_config, Some(install_dir))
+}
+
+/// Build the CA or plugin of `project_path` into `install_dir`
+fn build_ca_staged(
+    project_path: &Path,
+    plugin: bool,
+    debug: bool,
+    install_dir: &Path,
+) -> anyhow::Result<()> {
+    let ca_config = config::CaBuildConfig::resolve(
+        project_path,
+        None,
+        Some(debug),
+        None,
+        Vec::new(),
+        false,
+        None,
+        false,
+        None,
+        plugin,
+    )?;
+    ca_config.print_config();
+    ca_builder::build_ca(ca_config, Some(install_dir))
+}
+
+/// Resolve the TA configuration shared by the build, install and size commands
+fn resolve_ta_config(build_cmd: TABuildArgs) -> anyhow::Result<config::TaBuildConfig> {
+    let std_mode = ta_std_mode(&build_cmd);
+    let common = build_cmd.common;
+
     // Resolve project path from manifest or current directory
     let project_path = resolve_project_path(common.manifest_path.as_ref())?;
 
@@ -153,20 +677,37 @@ fn execute_ta_command(
     let ta_config = config::TaBuildConfig::resolve(
         &project_path,
         common.arch,
-        Some(common.debug),
-        uuid_path,
+        Some(common.debug || build_cmd.profile == Some(config::TaProfile::Debug)),
+        build_cmd.uuid_path,
         common.env,
         common.no_default_features,
         common.features,
-        std, // None means read from config, Some(true/false) means CLI override
-        ta_dev_kit_dir,
-        signing_key,
+        common.skip_checks,
+        std_mode, // None means read from config, Some(true/false) means CLI override
+        build_cmd.ta_dev_kit_dir,
+        build_cmd.signing_key,
+        build_cmd.size_budget,
+        build_cmd.reproducible,
+        build_cmd.encryption.encrypt,
+        build_cmd.encryption.enc_key_file,
+        build_cmd.encryption.enc_key_type,
+        build_cmd.profile == Some(config::TaProfile::Ftrace),
+        build_cmd.ftrace_buf_size,
     )?;
 
     // Print the final configuration being used
     ta_config.print_config();
 
-    ta_builder::build_ta(ta_config, install_target_dir.map(|p| p.as_path()))
+    Ok(ta_config)
+}
+
+/// Convert bool flags to Option<bool>: --std -> Some(true), --no-std -> Some(false), neither -> None
+fn ta_std_mode(build_cmd: &TABuildArgs) -> Option<bool> {
+    match (build_cmd.std, build_cmd.no_std) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    }
 }
 
 /// Execute CA build or install (shared logic)
@@ -189,6 +730,7 @@ fn execute_ca_command(
         common.env,
         common.no_default_features,
         common.features,
+        common.skip_checks,
         optee_client_export,
         plugin,
     )?;
@@ -199,6 +741,19 @@ fn execute_ca_command(
     ca_builder::build_ca(ca_config, install_target_dir.map(|p| p.as_path()))
 }
 
+/// Resolve the project path of a TA like [`resolve_project_path`], falling
+/// back to `ta/` when run in the root of a project without a manifest
+fn resolve_ta_project_path(manifest_path: Option<&PathBuf>) -> anyhow::Result<PathBuf> {
+    let project_path = resolve_project_path(manifest_path)?;
+    if manifest_path.is_none()
+        && !project_path.join("Cargo.toml").exists()
+        && project_path.join("ta").join("Cargo.toml").exists()
+    {
+        return Ok(project_path.join("ta"));
+    }
+    Ok(project_path)
+}
+
 /// Resolve project path from manifest path or current directory
 fn resolve_project_path(manifest_path: Option<&PathBuf>) -> anyhow::Result<PathBuf> {
     if let Some(manifest) = manifest_path {
@@ -0,0 +1,128 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use cargo_metadata::diagnostic::DiagnosticLevel;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Format of the output of the build, install, size and package commands
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq)]
+pub enum MessageFormat {
+    /// Progress in free-form text on stdout
+    #[default]
+    Human,
+    /// One JSON message per line on stdout, the progress on stderr
+    Json,
+}
+
+static FORMAT: OnceLock<MessageFormat> = OnceLock::new();
+
+/// Set the format of the output, once at startup
+pub fn set_format(format: MessageFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Whether the messages are printed in JSON
+pub fn is_json() -> bool {
+    FORMAT.get() == Some(&MessageFormat::Json)
+}
+
+/// Print the progress of the build, on stdout or on stderr when stdout is
+/// reserved to the JSON messages
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::message::is_json() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Machine-readable message of `--message-format json`, tagged with a
+/// `reason` like the messages of cargo
+#[derive(Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum Message<'a> {
+    /// A built component, once it is signed or stripped, and installed if
+    /// requested
+    Artifact {
+        /// "ta", "ca" or "plugin"
+        component: &'a str,
+        package: &'a str,
+        path: &'a Path,
+        /// Path the component is installed to
+        installed: Option<&'a Path>,
+        /// UUID of TAs and plugins
+        uuid: Option<&'a str>,
+        /// SHA-256 of the file at `path`
+        sha256: String,
+        /// Hash in the signed header of TAs, which OP-TEE checks when
+        /// loading the TA
+        shdr_digest: Option<String>,
+    },
+    /// A warning or an error of the compiler, rendered as by cargo
+    Diagnostic {
+        package: &'a str,
+        level: &'a str,
+        rendered: &'a str,
+    },
+    /// The end of the command, with its error if it failed
+    Finished {
+        success: bool,
+        error: Option<String>,
+    },
+}
+
+/// Print `message` on stdout with `--message-format json`, nothing otherwise
+pub fn emit(message: &Message) {
+    if !is_json() {
+        return;
+    }
+    match serde_json::to_string(message) {
+        Ok(line) => println!("{}", line),
+        Err(e) => eprintln!("Warning: could not serialize a message: {}", e),
+    }
+}
+
+/// Emit the warnings and errors in `stdout`, the output of a cargo command
+/// run with `--message-format json`
+pub fn emit_diagnostics(stdout: &[u8]) {
+    for message in cargo_metadata::Message::parse_stream(stdout).flatten() {
+        let cargo_metadata::Message::CompilerMessage(message) = message else {
+            continue;
+        };
+        let level = match message.message.level {
+            DiagnosticLevel::Warning => "warning",
+            DiagnosticLevel::Error | DiagnosticLevel::Ice => "error",
+            _ => continue,
+        };
+        let rendered = message
+            .message
+            .rendered
+            .as_deref()
+            .unwrap_or(&message.message.message);
+        emit(&Message::Diagnostic {
+            package: &message.target.name,
+            level,
+            rendered,
+        });
+    }
+}
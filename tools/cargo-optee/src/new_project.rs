@@ -0,0 +1,240 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Git repository of the SDK, used for the SDK dependencies of new projects
+/// unless a local checkout is given with `--sdk-path`.
+const SDK_GIT_URL: &str = "https://github.com/apache/teaclave-trustzone-sdk.git";
+
+/// SDK crates the templates depend on, referenced as `{{sdk:<crate>}}`.
+const SDK_CRATES: [&str; 7] = [
+    "optee-utee",
+    "optee-utee-sys",
+    "optee-utee-build",
+    "optee-teec",
+    "optee-teec-build",
+    "optee-tls",
+    "rustls_provider",
+];
+
+/// Project template for `cargo optee new`
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq)]
+pub enum Template {
+    /// TA incrementing and decrementing a value passed by the host
+    HelloWorld,
+    /// TA exchanging JSON requests and responses with the host
+    Serde,
+    /// std TA terminating TLS connections relayed by the host
+    TlsServer,
+    /// TA invoking a supplicant plugin running in the normal world
+    Plugin,
+}
+
+/// A file of a template: its path in the new project and its contents
+type TemplateFile = (&'static str, &'static str);
+
+macro_rules! template_file {
+    ($dest:literal, $src:literal) => {
+        (
+            $dest,
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/", $src)),
+        )
+    };
+}
+
+const COMMON_FILES: [TemplateFile; 1] = [template_file!(".gitignore", "common/gitignore")];
+
+impl Template {
+    fn files(self) -> Vec<TemplateFile> {
+        let mut files = COMMON_FILES.to_vec();
+        match self {
+            Template::HelloWorld => files.extend([
+                template_file!("Makefile", "common/Makefile"),
+                template_file!("proto/Cargo.toml", "common/proto/Cargo.toml"),
+                template_file!("proto/src/lib.rs", "hello-world/proto/src/lib.rs"),
+                template_file!("ta/Cargo.toml", "common/ta/Cargo.toml"),
+                template_file!("ta/build.rs", "common/ta/build.rs"),
+                template_file!("ta/src/main.rs", "hello-world/ta/src/main.rs"),
+                template_file!("host/Cargo.toml", "common/host/Cargo.toml"),
+                template_file!("host/src/main.rs", "hello-world/host/src/main.rs"),
+            ]),
+            Template::Serde => files.extend([
+                template_file!("Makefile", "common/Makefile"),
+                template_file!("proto/Cargo.toml", "serde/proto/Cargo.toml"),
+                template_file!("proto/src/lib.rs", "serde/proto/src/lib.rs"),
+                template_file!("ta/Cargo.toml", "serde/ta/Cargo.toml"),
+                template_file!("ta/build.rs", "common/ta/build.rs"),
+                template_file!("ta/src/main.rs", "serde/ta/src/main.rs"),
+                template_file!("host/Cargo.toml", "serde/host/Cargo.toml"),
+                template_file!("host/src/main.rs", "serde/host/src/main.rs"),
+            ]),
+            Template::TlsServer => files.extend([
+                template_file!("Makefile", "common/Makefile"),
+                template_file!("proto/Cargo.toml", "common/proto/Cargo.toml"),
+                template_file!("proto/src/lib.rs", "tls-server/proto/src/lib.rs"),
+                template_file!("ta/Cargo.toml", "tls-server/ta/Cargo.toml"),
+                template_file!("ta/build.rs", "tls-server/ta/build.rs"),
+                template_file!("ta/src/main.rs", "tls-server/ta/src/main.rs"),
+                template_file!("ta/certs/generate.sh", "tls-server/ta/certs/generate.sh"),
+                template_file!("host/Cargo.toml", "common/host/Cargo.toml"),
+                template_file!("host/src/main.rs", "tls-server/host/src/main.rs"),
+            ]),
+            Template::Plugin => files.extend([
+                template_file!("Makefile", "plugin/Makefile"),
+                template_file!("proto/Cargo.toml", "common/proto/Cargo.toml"),
+                template_file!("proto/src/lib.rs", "plugin/proto/src/lib.rs"),
+                template_file!("ta/Cargo.toml", "common/ta/Cargo.toml"),
+                template_file!("ta/build.rs", "common/ta/build.rs"),
+                template_file!("ta/src/main.rs", "plugin/ta/src/main.rs"),
+                template_file!("host/Cargo.toml", "common/host/Cargo.toml"),
+                template_file!("host/src/main.rs", "plugin/host/src/main.rs"),
+                template_file!("plugin/Cargo.toml", "plugin/plugin/Cargo.toml"),
+                template_file!("plugin/build.rs", "plugin/plugin/build.rs"),
+                template_file!("plugin/src/lib.rs", "plugin/plugin/src/lib.rs"),
+            ]),
+        }
+        files
+    }
+}
+
+/// Create a new project named `name` in the directory `name` from `template`.
+///
+/// The SDK crates are taken from the SDK git repository, or from the local
+/// checkout at `sdk_path` if given.
+pub fn new_project(name: &str, template: Template, sdk_path: Option<&Path>) -> Result<()> {
+    validate_name(name)?;
+    let project_dir = PathBuf::from(name);
+    if project_dir.exists() {
+        bail!("Destination {:?} already exists", project_dir);
+    }
+
+    let sdk_path = match sdk_path {
+        Some(path) => {
+            let path = path
+                .canonicalize()
+                .map_err(|_| anyhow::anyhow!("SDK path {:?} does not exist", path))?;
+            if !path.join("crates").join("optee-utee").is_dir() {
+                bail!(
+                    "SDK path {:?} is not a Teaclave TrustZone SDK checkout",
+                    path
+                );
+            }
+            Some(path)
+        }
+        None => None,
+    };
+
+    let crate_name = name.replace('-', "_");
+    let mut replacements = vec![
+        ("{{name}}".to_string(), name.to_string()),
+        ("{{crate_name}}".to_string(), crate_name),
+    ];
+    for krate in SDK_CRATES {
+        let source = match &sdk_path {
+            Some(path) => format!(
+                "path = \"{}\"",
+                path.join("crates").join(krate).to_string_lossy()
+            ),
+            None => format!("git = \"{}\"", SDK_GIT_URL),
+        };
+        replacements.push((format!("{{{{sdk:{}}}}}", krate), source));
+    }
+
+    for (dest, contents) in template.files() {
+        let path = project_dir.join(dest);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = replacements
+            .iter()
+            .fold(contents.to_string(), |contents, (from, to)| {
+                contents.replace(from, to)
+            });
+        fs::write(&path, contents)?;
+        if dest.ends_with(".sh") {
+            make_executable(&path)?;
+        }
+    }
+
+    // Without a trailing newline, as the UUID files are included verbatim
+    fs::write(project_dir.join("uuid.txt"), Uuid::new_v4().to_string())?;
+    if template == Template::Plugin {
+        fs::write(
+            project_dir.join("plugin_uuid.txt"),
+            Uuid::new_v4().to_string(),
+        )?;
+    }
+
+    if let Some(value) = template.to_possible_value() {
+        println!(
+            "Created project `{}` from the {} template",
+            name,
+            value.get_name()
+        );
+    }
+    if template == Template::TlsServer {
+        println!(
+            "Run ta/certs/generate.sh to create the server certificate before building the TA"
+        );
+    }
+    println!(
+        "Adjust the [package.metadata.optee.*] sections in the Cargo.toml files to your OP-TEE paths, then run `make` in {:?}",
+        project_dir
+    );
+    Ok(())
+}
+
+/// Check that `name` can be used as a directory and package name
+fn validate_name(name: &str) -> Result<()> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!(
+            "Invalid project name '{}': it must start with a letter and only contain letters, digits, '-' and '_'",
+            name
+        );
+    }
+    if matches!(name, "ta" | "proto" | "plugin") {
+        bail!(
+            "Invalid project name '{}': it is the name of a project component",
+            name
+        );
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,154 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use crate::common::{ChangeDirectoryGuard, hex, read_uuid_from_file};
+use crate::config::{TaBuildConfig, find_package};
+use crate::status;
+use crate::ta_builder::{build_ta, locate_binary};
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Magic of the signed header of a TA, "HSTO"
+const SHDR_MAGIC: u32 = 0x4f54_5348;
+/// Size of the fixed part of the signed header, before the hash
+const SHDR_SIZE: usize = 20;
+
+/// Description of a signed TA, for the operators deploying it
+#[derive(Serialize)]
+struct Manifest {
+    uuid: String,
+    name: String,
+    version: String,
+    /// SHA-256 of the signed TA file
+    sha256: String,
+    /// Hash in the signed header of the TA, which OP-TEE checks when loading
+    /// the TA and the attestation pseudo TA reports
+    shdr_digest: String,
+    /// SHA-256 of the DER encoded public key of the signing key
+    signer_fingerprint: Option<String>,
+    build: BuildFlags,
+}
+
+#[derive(Serialize)]
+struct BuildFlags {
+    arch: &'static str,
+    std: bool,
+    profile: &'static str,
+    features: Option<String>,
+    no_default_features: bool,
+    /// Built with `--reproducible`
+    reproducible: bool,
+    /// Output of `rustc --version` in the TA directory
+    rustc: Option<String>,
+}
+
+/// Build and sign the TA, then write the manifest of the signed TA to
+/// `output`, `<uuid>.json` next to the signed TA by default
+pub fn package_ta(config: TaBuildConfig, output: Option<&Path>) -> Result<()> {
+    let uuid_path = config
+        .uuid_path
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("UUID path is required but not configured"))?;
+    let uuid = read_uuid_from_file(uuid_path)?;
+    let package = find_package(&config.path)?;
+    build_ta(config.clone(), None)?;
+
+    let _guard = ChangeDirectoryGuard::new(&config.path)?;
+    let (profile_dir, _, _) = locate_binary(&config)?;
+    let ta_path = profile_dir.join(format!("{}.ta", uuid));
+    let ta = fs::read(&ta_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read the signed TA {:?}: {}", ta_path, e))?;
+
+    let manifest = Manifest {
+        shdr_digest: hex(shdr_digest(&ta)?),
+        sha256: hex(&Sha256::digest(&ta)),
+        uuid,
+        name: package.name.clone(),
+        version: package.version.to_string(),
+        signer_fingerprint: signer_fingerprint(&config.signing_key),
+        build: BuildFlags {
+            arch: config.arch.as_str(),
+            std: config.std,
+            profile: if config.debug { "debug" } else { "release" },
+            features: config.features.clone(),
+            no_default_features: config.no_default_features,
+            reproducible: config.reproducible,
+            rustc: rustc_version(),
+        },
+    };
+
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| ta_path.with_extension("json"));
+    fs::write(&output, serde_json::to_string_pretty(&manifest)? + "\n")?;
+    status!(
+        "TA manifest written to: {:?}",
+        output.canonicalize().unwrap_or(output)
+    );
+    Ok(())
+}
+
+/// The hash of the signed header of the TA file `ta`
+pub fn shdr_digest(ta: &[u8]) -> Result<&[u8]> {
+    let field = |offset: usize, size: usize| -> Result<u32> {
+        let bytes = ta
+            .get(offset..offset + size)
+            .ok_or_else(|| anyhow::anyhow!("TA file is too short for a signed header"))?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| (value << 8) | u32::from(*byte)))
+    };
+    if field(0, 4)? != SHDR_MAGIC {
+        bail!("TA file does not start with a signed header");
+    }
+    let hash_size = field(16, 2)? as usize;
+    ta.get(SHDR_SIZE..SHDR_SIZE + hash_size)
+        .ok_or_else(|| anyhow::anyhow!("TA file is too short for the hash of its signed header"))
+}
+
+/// The SHA-256 of the public key of the PEM key `signing_key`, derived with
+/// openssl, or `None` with a warning if it cannot be
+fn signer_fingerprint(signing_key: &Path) -> Option<String> {
+    let output = Command::new("openssl")
+        .args(["pkey", "-pubout", "-outform", "DER", "-in"])
+        .arg(signing_key)
+        .stderr(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Some(hex(&Sha256::digest(&output.stdout))),
+        _ => {
+            eprintln!(
+                "Warning: could not derive the public key of {:?} with openssl, the manifest has no signer fingerprint",
+                signing_key
+            );
+            None
+        }
+    }
+}
+
+fn rustc_version() -> Option<String> {
+    let output = Command::new("rustc").arg("--version").output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,176 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::{Result, bail};
+use cargo_metadata::MetadataCommand;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::common::read_uuid_from_file;
+use crate::config::{ComponentType, resolve_uuid_path_of};
+use crate::status;
+
+/// Change of `cargo optee uuid`
+pub enum UuidChange {
+    /// Print the UUID
+    Show,
+    /// Replace the UUID with a random one
+    New,
+    /// Replace the UUID with the given one
+    Set(Uuid),
+}
+
+/// Options of `cargo optee uuid`
+pub struct UuidConfig {
+    pub project_path: PathBuf,
+    /// TA or plugin the UUID identifies
+    pub component: ComponentType,
+    /// UUID file, overriding the metadata
+    pub uuid_path: Option<PathBuf>,
+    pub change: UuidChange,
+}
+
+/// Show or change the UUID of the TA or plugin of a project.
+///
+/// A change writes the UUID file and replaces the previous UUID in the Rust
+/// sources next to it, e.g. `#[ta_config(uuid = "...")]` or the constants of
+/// a proto crate or build script, so that the TA and its clients agree on the
+/// new one.
+pub fn run(config: &UuidConfig) -> Result<()> {
+    if config.component == ComponentType::Ca {
+        bail!("Only TAs and plugins have a UUID");
+    }
+    let uuid_path = resolve_uuid_path_of(
+        &config.project_path,
+        config.component,
+        config.uuid_path.clone(),
+    )?;
+
+    let new_uuid = match config.change {
+        UuidChange::Show => {
+            println!("{}", parse_uuid_file(&uuid_path)?);
+            return warn_duplicates(&config.project_path);
+        }
+        UuidChange::New => Uuid::new_v4(),
+        UuidChange::Set(uuid) => uuid,
+    };
+    let old_uuid = if uuid_path.exists() {
+        Some(parse_uuid_file(&uuid_path)?)
+    } else {
+        None
+    };
+
+    // Without a trailing newline, as the UUID files are included verbatim
+    fs::write(&uuid_path, new_uuid.to_string())?;
+    status!("Wrote {} to {:?}", new_uuid, uuid_path);
+
+    if let Some(old_uuid) = old_uuid.filter(|old| *old != new_uuid) {
+        let root = uuid_path.parent().unwrap_or(Path::new("."));
+        for path in rust_sources(root)? {
+            let contents = fs::read_to_string(&path)?;
+            let rewritten = replace_uuid(&contents, &old_uuid, &new_uuid);
+            if rewritten != contents {
+                fs::write(&path, rewritten)?;
+                status!("Replaced {} in {:?}", old_uuid, path);
+            }
+        }
+    }
+    warn_duplicates(&config.project_path)
+}
+
+fn parse_uuid_file(uuid_path: &Path) -> Result<Uuid> {
+    let uuid = read_uuid_from_file(uuid_path)?;
+    Uuid::parse_str(&uuid)
+        .map_err(|e| anyhow::anyhow!("Invalid UUID {:?} in {}: {}", uuid, uuid_path.display(), e))
+}
+
+/// `contents` with the UUID `old` replaced by `new`, whether it is written
+/// in lowercase or uppercase
+fn replace_uuid(contents: &str, old: &Uuid, new: &Uuid) -> String {
+    let old_lower = old.to_string();
+    let old_upper = old_lower.to_uppercase();
+    contents
+        .replace(&old_lower, &new.to_string())
+        .replace(&old_upper, &new.to_string().to_uppercase())
+}
+
+/// The `.rs` files under `root`, outside of the target and hidden
+/// directories
+fn rust_sources(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if entry.file_type()?.is_dir() {
+                if name != "target" && !name.starts_with('.') {
+                    dirs.push(path);
+                }
+            } else if name.ends_with(".rs") {
+                sources.push(path);
+            }
+        }
+    }
+    Ok(sources)
+}
+
+/// The UUIDs of the TAs of the workspace of `project_path`, by package name,
+/// skipping the TAs whose UUID file cannot be read
+pub fn workspace_ta_uuids(project_path: &Path) -> Result<BTreeMap<String, Uuid>> {
+    let metadata = MetadataCommand::new()
+        .manifest_path(project_path.join("Cargo.toml"))
+        .no_deps()
+        .exec()?;
+    let mut uuids = BTreeMap::new();
+    for package in metadata.workspace_packages() {
+        let has_ta = package
+            .metadata
+            .get("optee")
+            .and_then(|optee| optee.get("ta"))
+            .is_some();
+        let Some(path) = package.manifest_path.parent().filter(|_| has_ta) else {
+            continue;
+        };
+        let uuid_path = resolve_uuid_path_of(path.as_std_path(), ComponentType::Ta, None)?;
+        if let Ok(uuid) = parse_uuid_file(&uuid_path) {
+            uuids.insert(package.name.to_string(), uuid);
+        }
+    }
+    Ok(uuids)
+}
+
+/// Warn about the TAs of the workspace of `project_path` sharing a UUID, of
+/// which OP-TEE only ever loads one
+pub fn warn_duplicates(project_path: &Path) -> Result<()> {
+    let mut packages: BTreeMap<Uuid, Vec<String>> = BTreeMap::new();
+    for (package, uuid) in workspace_ta_uuids(project_path)? {
+        packages.entry(uuid).or_default().push(package);
+    }
+    for (uuid, packages) in packages.iter().filter(|(_, p)| p.len() > 1) {
+        eprintln!(
+            "Warning: the TAs {} share the UUID {}, run `cargo optee uuid new` in all but one of them",
+            packages.join(", "),
+            uuid
+        );
+    }
+    Ok(())
+}
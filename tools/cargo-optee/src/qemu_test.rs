@@ -0,0 +1,490 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::{Result, bail};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Directory in the shared folder the built components are staged in
+const STAGING_DIR: &str = "cargo-optee-test";
+/// Mount point of the shared folder in the guest
+const GUEST_MOUNT: &str = "/mnt/host";
+/// Time QEMU gets to boot until SSH is reachable
+pub const BOOT_TIMEOUT: Duration = Duration::from_secs(120);
+/// Time each guest command setting up the test gets
+const SETUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Same options as tests/setup.sh, the guest gets a new host key on every boot
+const SSH_OPTIONS: [&str; 6] = [
+    "-o",
+    "StrictHostKeyChecking=no",
+    "-o",
+    "UserKnownHostsFile=/dev/null",
+    "-o",
+    "BatchMode=yes",
+];
+/// SSH destination of the guest, through the forwarded port
+pub const SSH_TARGET: &str = "root@127.0.0.1";
+
+/// Directories in the shared folder the TA, CA and plugin are installed to
+pub struct StagingDirs {
+    pub root: PathBuf,
+    pub ta: PathBuf,
+    pub ca: PathBuf,
+    pub plugin: PathBuf,
+}
+
+impl StagingDirs {
+    /// Create empty staging directories in `shared_dir`, removing the
+    /// components staged by a previous run. The serial logs of a QEMU kept
+    /// running are left in place.
+    pub fn create(shared_dir: &Path) -> Result<Self> {
+        // Absolute, as the builders change into the component directories
+        fs::create_dir_all(shared_dir)?;
+        let root = shared_dir.canonicalize()?.join(STAGING_DIR);
+        let dirs = Self {
+            ta: root.join("ta"),
+            ca: root.join("ca"),
+            plugin: root.join("plugin"),
+            root,
+        };
+        for dir in [&dirs.ta, &dirs.ca, &dirs.plugin] {
+            if dir.exists() {
+                fs::remove_dir_all(dir)?;
+            }
+            fs::create_dir_all(dir)?;
+        }
+        Ok(dirs)
+    }
+}
+
+/// Options of the QEMU instance a project is run in
+pub struct QemuOptions {
+    pub image_dir: PathBuf,
+    pub shared_dir: PathBuf,
+    pub ssh_port: u16,
+    pub keep_running: bool,
+    /// Port of the gdb server QEMU is booted with, its CPUs halted until gdb
+    /// continues them
+    pub gdb_port: Option<u16>,
+}
+
+/// Options of a test run in QEMU
+pub struct QemuTestConfig {
+    pub qemu: QemuOptions,
+    pub run: Option<String>,
+    pub expect: Vec<String>,
+    pub timeout: Duration,
+}
+
+/// A QEMU instance booted for the test, stopped when dropped unless it is
+/// kept running.
+pub struct Qemu {
+    child: Option<Child>,
+}
+
+impl Drop for Qemu {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Boot the OP-TEE QEMU image, or reuse the instance left running on the
+/// same port, and wait until it is reachable over SSH.
+///
+/// The serial consoles are logged to `normal_world.log` and
+/// `secure_world.log` in the staging directory.
+pub fn start_qemu(options: &QemuOptions, staging: &StagingDirs) -> Result<(Qemu, Ssh)> {
+    let ssh = Ssh::new(SSH_TARGET, options.ssh_port);
+    let ready = ssh.is_ready();
+    if ready && options.gdb_port.is_some() {
+        bail!(
+            "QEMU already running on port {}, stop it to boot one with the gdb server",
+            options.ssh_port
+        );
+    }
+    let mut qemu = if ready {
+        // Left running by an earlier `--keep-running` or `emu up`, its shared
+        // folder must be the one given now
+        if let Some(emu) = crate::emu::running(options.ssh_port)
+            && !staging.root.starts_with(&emu.shared_dir)
+        {
+            bail!(
+                "The emulator on port {} shares {:?}, pass it as --shared-dir",
+                options.ssh_port,
+                emu.shared_dir
+            );
+        }
+        println!(
+            "Reusing QEMU already reachable on port {}",
+            options.ssh_port
+        );
+        Qemu { child: None }
+    } else {
+        boot_qemu(options, staging)?
+    };
+    if let Some(child) = qemu.child.as_mut() {
+        // Halted QEMU only boots once gdb continues it
+        let timeout = options.gdb_port.is_none().then_some(BOOT_TIMEOUT);
+        ssh.wait_until_ready(child, timeout)?;
+    }
+    println!("QEMU SSH ready");
+    if options.keep_running {
+        // Keep QEMU for later runs even if this one fails
+        qemu.child.take();
+    }
+    Ok((qemu, ssh))
+}
+
+/// The logs of the normal and secure world consoles of the QEMU on the port
+/// of `options`: the ones of `emu up` if it started it, the ones in the
+/// staging directory otherwise.
+pub fn console_logs(options: &QemuOptions, staging: &StagingDirs) -> (PathBuf, PathBuf) {
+    match crate::emu::running(options.ssh_port) {
+        Some(emu) => (emu.normal_log, emu.secure_log),
+        None => (
+            staging.root.join("normal_world.log"),
+            staging.root.join("secure_world.log"),
+        ),
+    }
+}
+
+/// Tell how to reach a QEMU left running with `--keep-running`
+pub fn print_kept_running(options: &QemuOptions) {
+    if options.keep_running {
+        println!(
+            "QEMU left running, connect with: ssh -p {} {}",
+            options.ssh_port, SSH_TARGET
+        );
+    }
+}
+
+/// Boot the OP-TEE QEMU image, install the components staged in `staging`,
+/// run the test command and check its output.
+pub fn run_test(config: &QemuTestConfig, staging: &StagingDirs) -> Result<()> {
+    let (_qemu, ssh) = start_qemu(&config.qemu, staging)?;
+    let (_, secure_log) = console_logs(&config.qemu, staging);
+
+    install_components(&ssh, staging)?;
+
+    let run = match &config.run {
+        Some(run) => run.clone(),
+        None => single_file_name(&staging.ca)?,
+    };
+    println!("Running in QEMU: {}", run);
+    let result = match ssh.run(&run, config.timeout)? {
+        Some(output) => {
+            print!("{}", String::from_utf8_lossy(&output.stdout));
+            eprint!("{}", String::from_utf8_lossy(&output.stderr));
+            check_output(&output, &config.expect)
+        }
+        None => Err(anyhow::anyhow!(
+            "`{}` timed out after {:?}",
+            run,
+            config.timeout
+        )),
+    };
+
+    print_kept_running(&config.qemu);
+    match &result {
+        Ok(()) => println!("test result: ok"),
+        Err(_) => {
+            print_secure_log(&secure_log);
+            println!("test result: FAILED");
+        }
+    }
+    result
+}
+
+/// Check that `output` reports success and contains all `expect` strings
+fn check_output(output: &Output, expect: &[String]) -> Result<()> {
+    if !output.status.success() {
+        bail!(
+            "Test command failed with exit code: {:?}",
+            output.status.code()
+        );
+    }
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let missing: Vec<&String> = expect.iter().filter(|e| !text.contains(*e)).collect();
+    if !missing.is_empty() {
+        bail!("Expected output not found: {:?}", missing);
+    }
+    Ok(())
+}
+
+/// Start QEMU as tests/optee-qemuv8.sh does, with the serial consoles
+/// logged to the staging directory.
+fn boot_qemu(config: &QemuOptions, staging: &StagingDirs) -> Result<Qemu> {
+    let normal_log = staging.root.join("normal_world.log");
+    let secure_log = staging.root.join("secure_world.log");
+
+    println!("Booting QEMU from {:?}...", config.image_dir);
+    let mut cmd = qemu_command(&config.image_dir, &config.shared_dir, config.ssh_port)?;
+    cmd.arg("-serial")
+        .arg(format!("file:{}", normal_log.display()))
+        .arg("-serial")
+        .arg(format!("file:{}", secure_log.display()));
+    if let Some(gdb_port) = config.gdb_port {
+        // As `-s -S`, on the given port
+        cmd.arg("-gdb").arg(format!("tcp::{}", gdb_port)).arg("-S");
+        println!("QEMU halted until gdb continues it on port {}", gdb_port);
+    }
+    let child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to start QEMU: {}", e))?;
+    Ok(Qemu { child: Some(child) })
+}
+
+/// The QEMU command line of tests/optee-qemuv8.sh, without the serial
+/// consoles: the first `-serial` added is the normal world console, the
+/// second one the secure world console.
+pub fn qemu_command(image_dir: &Path, shared_dir: &Path, ssh_port: u16) -> Result<Command> {
+    let qemu_binary = image_dir.join("qemu-system-aarch64");
+    if !qemu_binary.exists() {
+        bail!(
+            "qemu-system-aarch64 not found in image directory: {:?}",
+            image_dir
+        );
+    }
+    let shared_dir = shared_dir.canonicalize()?;
+    let mut cmd = Command::new(&qemu_binary);
+    cmd.current_dir(image_dir)
+        .args(["-nodefaults", "-nographic", "-monitor", "none"])
+        .args(["-smp", "2"])
+        .args(["-machine", "virt,secure=on,acpi=off,gic-version=3"])
+        .args(["-cpu", "cortex-a57"])
+        .args([
+            "-d",
+            "unimp",
+            "-semihosting-config",
+            "enable=on,target=native",
+        ])
+        .args(["-m", "1057"])
+        .args(["-bios", "bl1.bin"])
+        .args(["-initrd", "rootfs.cpio.gz"])
+        .args([
+            "-append",
+            "console=ttyAMA0,115200 keep_bootcon root=/dev/vda2",
+        ])
+        .args(["-kernel", "Image"])
+        .arg("-fsdev")
+        .arg(format!(
+            "local,id=fsdev0,path={},security_model=none",
+            shared_dir.display()
+        ))
+        .args(["-device", "virtio-9p-device,fsdev=fsdev0,mount_tag=host"])
+        .arg("-netdev")
+        .arg(format!(
+            "user,id=vmnic,hostfwd=tcp:127.0.0.1:{}-:22",
+            ssh_port
+        ))
+        .args(["-device", "virtio-net-device,netdev=vmnic"]);
+    Ok(cmd)
+}
+
+/// Mount the shared folder at [`GUEST_MOUNT`] in the guest, unless it is
+/// already mounted.
+pub fn mount_shared_dir(ssh: &Ssh) -> Result<()> {
+    ssh.run_checked(
+        &format!(
+            "mkdir -p {mount}; \
+             grep -q ' {mount} ' /proc/mounts || mount -t 9p -o trans=virtio host {mount}",
+            mount = GUEST_MOUNT
+        ),
+        "Mounting the shared folder in QEMU",
+    )
+}
+
+/// Mount the shared folder in the guest and copy the staged components to
+/// where OP-TEE looks for them.
+fn install_components(ssh: &Ssh, staging: &StagingDirs) -> Result<()> {
+    let staged = format!("{}/{}", GUEST_MOUNT, STAGING_DIR);
+    mount_shared_dir(ssh)?;
+    let mut script = format!(
+        "set -e; \
+         cp {staged}/ta/*.ta /lib/optee_armtz/; chmod 0444 /lib/optee_armtz/*.ta; \
+         cp {staged}/ca/* /usr/bin/",
+        staged = staged
+    );
+    let has_plugin = fs::read_dir(&staging.plugin)?.next().is_some();
+    if has_plugin {
+        script.push_str(&format!(
+            "; cp {staged}/plugin/*.plugin.so /usr/lib/tee-supplicant/plugins/; \
+             chmod 0666 /usr/lib/tee-supplicant/plugins/*.so",
+            staged = staged
+        ));
+    }
+    ssh.run_checked(&script, "Installing the components in QEMU")?;
+
+    // tee-supplicant only loads plugins on start
+    if has_plugin {
+        ssh.run_checked("kill $(pidof tee-supplicant)", "Stopping tee-supplicant")?;
+        ssh.run_checked(
+            "nohup /usr/sbin/tee-supplicant > /tmp/tee_supplicant.log 2>&1 &",
+            "Restarting tee-supplicant",
+        )?;
+    }
+    Ok(())
+}
+
+/// Name of the only file in `dir`, the CA binary in the CA staging directory
+fn single_file_name(dir: &Path) -> Result<String> {
+    let names: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    match names.as_slice() {
+        [name] => Ok(name.clone()),
+        _ => bail!(
+            "Expected a single CA binary in {:?}, found {:?}, specify the command with --run",
+            dir,
+            names
+        ),
+    }
+}
+
+pub fn print_secure_log(secure_log: &Path) {
+    if let Ok(log) = fs::read_to_string(secure_log) {
+        eprintln!("Secure world log ({:?}):", secure_log);
+        eprintln!("{}", log);
+    }
+}
+
+/// SSH connection to the guest through the forwarded port, or to a device
+pub struct Ssh {
+    destination: String,
+    port: u16,
+}
+
+impl Ssh {
+    pub fn new(destination: &str, port: u16) -> Self {
+        Self {
+            destination: destination.to_string(),
+            port,
+        }
+    }
+
+    pub fn command(&self, remote: &str) -> Command {
+        let mut cmd = Command::new("ssh");
+        cmd.arg(&self.destination)
+            .arg("-p")
+            .arg(self.port.to_string())
+            .args(SSH_OPTIONS)
+            .arg(remote)
+            .stdin(Stdio::null());
+        cmd
+    }
+
+    pub fn is_ready(&self) -> bool {
+        matches!(
+            self.run("true", Duration::from_secs(5)),
+            Ok(Some(output)) if output.status.success()
+        )
+    }
+
+    /// Poll SSH until the guest accepts connections, failing if `qemu` exits
+    /// or the boot takes longer than `timeout`.
+    pub fn wait_until_ready(&self, qemu: &mut Child, timeout: Option<Duration>) -> Result<()> {
+        let start = Instant::now();
+        while !self.is_ready() {
+            if let Some(status) = qemu.try_wait()? {
+                bail!("QEMU exited during boot with {}", status);
+            }
+            if let Some(timeout) = timeout
+                && start.elapsed() > timeout
+            {
+                bail!("QEMU SSH not reachable after {:?}", timeout);
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+        Ok(())
+    }
+
+    /// Run `remote` in the guest, returning `None` if it does not finish
+    /// within `timeout`.
+    fn run(&self, remote: &str, timeout: Duration) -> Result<Option<Output>> {
+        let mut child = self
+            .command(remote)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to run ssh: {}", e))?;
+
+        // Drain the pipes while waiting, so a chatty command cannot block
+        let stdout = child.stdout.take().map(read_to_end);
+        let stderr = child.stderr.take().map(read_to_end);
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if start.elapsed() > timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+            thread::sleep(Duration::from_millis(100));
+        };
+        let join = |handle: Option<thread::JoinHandle<Vec<u8>>>| {
+            handle
+                .and_then(|handle| handle.join().ok())
+                .unwrap_or_default()
+        };
+        Ok(Some(Output {
+            status,
+            stdout: join(stdout),
+            stderr: join(stderr),
+        }))
+    }
+
+    /// Run `remote` with its output going to the terminal
+    pub fn run_streaming(&self, remote: &str) -> Result<ExitStatus> {
+        self.command(remote)
+            .status()
+            .map_err(|e| anyhow::anyhow!("Failed to run ssh: {}", e))
+    }
+
+    pub fn run_checked(&self, remote: &str, description: &str) -> Result<()> {
+        println!("{}...", description);
+        match self.run(remote, SETUP_TIMEOUT)? {
+            Some(output) if output.status.success() => Ok(()),
+            Some(output) => crate::common::print_output_and_bail(description, &output),
+            None => bail!("{} timed out after {:?}", description, SETUP_TIMEOUT),
+        }
+    }
+}
+
+fn read_to_end<R: Read + Send + 'static>(mut reader: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = reader.read_to_end(&mut buf);
+        buf
+    })
+}
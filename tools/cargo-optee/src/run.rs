@@ -0,0 +1,413 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::{Result, bail};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::qemu_test::{self, Qemu, QemuOptions, Ssh, StagingDirs};
+
+/// Where OP-TEE loads TAs from
+const TA_DIR: &str = "/lib/optee_armtz";
+/// Where the CA is installed, so it is in the PATH of the device
+const CA_DIR: &str = "/usr/bin";
+/// Where tee-supplicant loads plugins from
+const PLUGIN_DIR: &str = "/usr/lib/tee-supplicant/plugins";
+
+/// The device a project is run on
+pub enum Target {
+    /// The OP-TEE QEMU image, booted unless already running
+    Qemu(QemuOptions),
+    /// A device reachable over SSH
+    Ssh { destination: String, port: u16 },
+    /// A device reachable with adb, the only one or the one with this serial
+    Adb { serial: Option<String> },
+}
+
+/// Options of `cargo optee run`
+pub struct RunConfig {
+    pub target: Target,
+    /// Command to run instead of the CA binary
+    pub run: Option<String>,
+    /// Arguments passed to the CA
+    pub args: Vec<String>,
+    /// File or serial device the secure world console is read from
+    pub secure_log: Option<PathBuf>,
+}
+
+/// Connection to the device, to copy files to it and run commands on it
+trait Device {
+    fn push(&self, local: &Path, remote: &str) -> Result<()>;
+    fn run_checked(&self, remote: &str, description: &str) -> Result<()>;
+    fn run_streaming(&self, remote: &str) -> Result<ExitStatus>;
+}
+
+impl Device for Ssh {
+    fn push(&self, local: &Path, remote: &str) -> Result<()> {
+        // Through the shell rather than scp, which needs an SFTP server on
+        // the device with recent OpenSSH versions
+        let output = self
+            .command(&format!("cat > {}", remote))
+            .stdin(File::open(local)?)
+            .output()?;
+        if !output.status.success() {
+            return crate::common::print_output_and_bail(
+                &format!("Copying to {}", remote),
+                &output,
+            );
+        }
+        Ok(())
+    }
+
+    fn run_checked(&self, remote: &str, description: &str) -> Result<()> {
+        Ssh::run_checked(self, remote, description)
+    }
+
+    fn run_streaming(&self, remote: &str) -> Result<ExitStatus> {
+        Ssh::run_streaming(self, remote)
+    }
+}
+
+struct Adb {
+    serial: Option<String>,
+}
+
+impl Adb {
+    fn command(&self) -> Command {
+        let mut cmd = Command::new("adb");
+        if let Some(serial) = &self.serial {
+            cmd.arg("-s").arg(serial);
+        }
+        cmd.stdin(Stdio::null());
+        cmd
+    }
+}
+
+impl Device for Adb {
+    fn push(&self, local: &Path, remote: &str) -> Result<()> {
+        let output = self
+            .command()
+            .arg("push")
+            .arg(local)
+            .arg(remote)
+            .output()
+            .map_err(|e| anyhow::anyhow!("Failed to run adb: {}", e))?;
+        if !output.status.success() {
+            return crate::common::print_output_and_bail("adb push", &output);
+        }
+        Ok(())
+    }
+
+    fn run_checked(&self, remote: &str, description: &str) -> Result<()> {
+        println!("{}...", description);
+        let output = self
+            .command()
+            .args(["shell", remote])
+            .output()
+            .map_err(|e| anyhow::anyhow!("Failed to run adb: {}", e))?;
+        if !output.status.success() {
+            return crate::common::print_output_and_bail(description, &output);
+        }
+        Ok(())
+    }
+
+    fn run_streaming(&self, remote: &str) -> Result<ExitStatus> {
+        self.command()
+            .args(["shell", remote])
+            .status()
+            .map_err(|e| anyhow::anyhow!("Failed to run adb: {}", e))
+    }
+}
+
+/// A component of a project
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Component {
+    Ta,
+    Ca,
+    Plugin,
+}
+
+impl Component {
+    pub const ALL: [Component; 3] = [Component::Ta, Component::Ca, Component::Plugin];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Ta => "TA",
+            Component::Ca => "CA",
+            Component::Plugin => "plugin",
+        }
+    }
+}
+
+/// The target a project is installed on, with QEMU stopped when dropped
+/// unless it is kept running
+pub struct Connection {
+    _qemu: Option<Qemu>,
+    device: Box<dyn Device>,
+    /// Console logs of the worlds, by name
+    consoles: Vec<(&'static str, PathBuf)>,
+}
+
+impl Connection {
+    /// Connect to the target, booting QEMU if needed
+    pub fn open(target: &Target, secure_log: Option<&Path>, staging: &StagingDirs) -> Result<Self> {
+        let (qemu, device, mut consoles): (_, Box<dyn Device>, _) = match target {
+            Target::Qemu(options) => {
+                let (qemu, ssh) = qemu_test::start_qemu(options, staging)?;
+                let (normal_log, secure_log) = qemu_test::console_logs(options, staging);
+                let consoles = vec![("normal", normal_log), ("secure", secure_log)];
+                (Some(qemu), Box::new(ssh), consoles)
+            }
+            Target::Ssh { destination, port } => {
+                let ssh = Ssh::new(destination, *port);
+                if !ssh.is_ready() {
+                    bail!("Device not reachable over SSH: {}", destination);
+                }
+                (None, Box::new(ssh), Vec::new())
+            }
+            Target::Adb { serial } => (
+                None,
+                Box::new(Adb {
+                    serial: serial.clone(),
+                }),
+                Vec::new(),
+            ),
+        };
+        if let Some(secure_log) = secure_log {
+            consoles.retain(|(name, _)| *name != "secure");
+            consoles.push(("secure", secure_log.to_path_buf()));
+        }
+        Ok(Self {
+            _qemu: qemu,
+            device,
+            consoles,
+        })
+    }
+
+    /// Print what is written to the consoles from now on, until the
+    /// followers are stopped
+    pub fn follow_consoles(&self) -> Vec<LogFollower> {
+        let mut followers = Vec::new();
+        for (name, path) in &self.consoles {
+            match LogFollower::start(name, path) {
+                Ok(follower) => followers.push(follower),
+                Err(e) => eprintln!("Warning: not showing the {} world console: {}", name, e),
+            }
+        }
+        followers
+    }
+
+    /// Copy the staged `components` to where OP-TEE looks for them, and
+    /// return the file name of the CA.
+    pub fn install(&self, staging: &StagingDirs, components: &[Component]) -> Result<String> {
+        install(self.device.as_ref(), staging, components)
+    }
+
+    /// Run `command` on the target, streaming its output
+    pub fn run_streaming(&self, command: &str) -> Result<ExitStatus> {
+        println!("Running: {}", command);
+        self.device.run_streaming(command)
+    }
+}
+
+/// Install the components staged in `staging` on the target, run the CA and
+/// stream its output along with the console output of both worlds.
+pub fn run(config: &RunConfig, staging: &StagingDirs) -> Result<()> {
+    let connection = Connection::open(&config.target, config.secure_log.as_deref(), staging)?;
+    let ca_name = connection.install(staging, &Component::ALL)?;
+    let command = with_args(config.run.clone().unwrap_or(ca_name), &config.args);
+
+    // Only what is printed from now on, not the boot log
+    let followers = connection.follow_consoles();
+    let status = connection.run_streaming(&command);
+    stop_followers(followers);
+
+    if let Target::Qemu(options) = &config.target {
+        qemu_test::print_kept_running(options);
+    }
+    let status = status?;
+    if !status.success() {
+        bail!("`{}` failed with exit code: {:?}", command, status.code());
+    }
+    Ok(())
+}
+
+/// Append `args` to `command`, quoted for the shell of the device
+pub fn with_args(mut command: String, args: &[String]) -> String {
+    for arg in args {
+        command.push(' ');
+        command.push_str(&shell_quote(arg));
+    }
+    command
+}
+
+/// Stop printing the consoles, once they caught up with the end of a run
+pub fn stop_followers(followers: Vec<LogFollower>) {
+    thread::sleep(Duration::from_millis(500));
+    for follower in followers {
+        follower.stop();
+    }
+}
+
+/// Copy the staged `components` to where OP-TEE looks for them, and return
+/// the file name of the CA.
+fn install(device: &dyn Device, staging: &StagingDirs, components: &[Component]) -> Result<String> {
+    let ca = files(&staging.ca)?;
+    let ca_name = match ca.as_slice() {
+        [ca] => file_name(ca),
+        _ => bail!(
+            "Expected a single CA binary in {:?}, found {:?}",
+            staging.ca,
+            ca
+        ),
+    };
+
+    println!("Installing the components...");
+    let mut plugins_installed = false;
+    for &component in components {
+        let (staged, dir, mode) = match component {
+            Component::Ta => (&staging.ta, TA_DIR, "0444"),
+            Component::Ca => (&staging.ca, CA_DIR, "0755"),
+            Component::Plugin => (&staging.plugin, PLUGIN_DIR, "0666"),
+        };
+        for file in files(staged)? {
+            let remote = format!("{}/{}", dir, file_name(&file));
+            device.push(&file, &remote)?;
+            device.run_checked(
+                &format!("chmod {} {}", mode, remote),
+                &format!("Setting the mode of {}", remote),
+            )?;
+            plugins_installed |= component == Component::Plugin;
+        }
+    }
+
+    // tee-supplicant only loads plugins on start
+    if plugins_installed {
+        device.run_checked("kill $(pidof tee-supplicant)", "Stopping tee-supplicant")?;
+        device.run_checked(
+            "nohup /usr/sbin/tee-supplicant > /tmp/tee_supplicant.log 2>&1 &",
+            "Restarting tee-supplicant",
+        )?;
+    }
+    Ok(ca_name)
+}
+
+fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Quote `arg` for the shell of the device or of a container
+pub fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c))
+    {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Prints what is appended to a console log, or read from a serial device,
+/// with every line prefixed by the name of the console.
+pub struct LogFollower {
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl LogFollower {
+    fn start(name: &str, path: &Path) -> Result<Self> {
+        let mut file = File::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open console {:?}: {}", path, e))?;
+        let regular = file.metadata()?.is_file();
+        if regular {
+            file.seek(SeekFrom::End(0))?;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let prefix = format!("[{}] ", name);
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || follow(file, &prefix, &stop)
+        });
+        Ok(Self {
+            stop,
+            // Reads from a serial device block until there is output, do not
+            // wait for them
+            handle: regular.then_some(handle),
+        })
+    }
+
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn follow(mut file: File, prefix: &str, stop: &AtomicBool) {
+    let mut buf = [0u8; 4096];
+    let mut line = Vec::new();
+    loop {
+        // Checked before reading, so the output up to the stop is printed
+        let stopping = stop.load(Ordering::Relaxed);
+        let read = file.read(&mut buf).unwrap_or(0);
+        for &byte in &buf[..read] {
+            line.push(byte);
+            if byte == b'\n' {
+                print_line(prefix, &line);
+                line.clear();
+            }
+        }
+        if read == 0 {
+            if stopping {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+    if !line.is_empty() {
+        line.push(b'\n');
+        print_line(prefix, &line);
+    }
+}
+
+fn print_line(prefix: &str, line: &[u8]) {
+    // Serial consoles end lines with \r\n
+    let text = String::from_utf8_lossy(line);
+    let text = text.trim_end_matches(['\r', '\n']);
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}{}", prefix, text);
+}
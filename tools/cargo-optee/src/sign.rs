@@ -0,0 +1,364 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+use crate::common::{self, print_output_and_bail};
+use crate::status;
+
+use anyhow::{Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use clap::ValueEnum;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tempfile::TempDir;
+
+/// Environment variable with the hex encoded key TAs are encrypted with when
+/// no key file is configured, as `TA_ENC_KEY` of the OP-TEE build
+pub const ENC_KEY_ENV: &str = "TA_ENC_KEY";
+/// Magic of the signed header of a TA, "HSTO"
+const SHDR_MAGIC: u32 = 0x4f54_5348;
+/// Image type of an encrypted TA in the signed header
+const SHDR_ENCRYPTED_TA: u32 = 2;
+/// Size of the fixed part of the signed header, before the hash
+const SHDR_SIZE: usize = 20;
+/// TEE_ALG_AES_GCM, the only algorithm sign_encrypt.py encrypts with
+const TEE_ALG_AES_GCM: u32 = 0x4000_0810;
+
+/// Holder of the private key a TA is signed with
+pub enum Signer {
+    /// PEM private key file, read by sign_encrypt.py
+    Pem(PathBuf),
+    /// Key in a PKCS#11 token, e.g. an HSM, used through the pkcs11 provider
+    /// of OpenSSL
+    Pkcs11(String),
+    /// Shell command reading the digest of the TA on stdin and writing the
+    /// signature on stdout
+    Command(String),
+}
+
+impl Signer {
+    /// `key` is a PKCS#11 URI when it starts with `pkcs11:`, a PEM file
+    /// otherwise
+    pub fn from_key(key: &str) -> Self {
+        if key.starts_with("pkcs11:") {
+            Signer::Pkcs11(key.to_string())
+        } else {
+            Signer::Pem(PathBuf::from(key))
+        }
+    }
+}
+
+/// Key an encrypted TA is decrypted with on the device
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq)]
+pub enum EncKeyType {
+    /// Key derived from the hardware unique key of each device
+    DevSpecific,
+    /// Key shared by a class of devices
+    ClassWide,
+}
+
+impl EncKeyType {
+    /// Value of `--enc-key-type` of sign_encrypt.py
+    fn script_arg(&self) -> &'static str {
+        match self {
+            EncKeyType::DevSpecific => "SHDR_ENC_KEY_DEV_SPECIFIC",
+            EncKeyType::ClassWide => "SHDR_ENC_KEY_CLASS_WIDE",
+        }
+    }
+
+    /// Flags of the encrypted header of the TA
+    fn flags(&self) -> u32 {
+        match self {
+            EncKeyType::DevSpecific => 0,
+            EncKeyType::ClassWide => 1,
+        }
+    }
+}
+
+impl std::str::FromStr for EncKeyType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dev-specific" => Ok(EncKeyType::DevSpecific),
+            "class-wide" => Ok(EncKeyType::ClassWide),
+            _ => Err(format!("Invalid encryption key type: {}", s)),
+        }
+    }
+}
+
+/// Encryption of the TA with AES-GCM by sign_encrypt.py
+#[derive(Clone)]
+pub struct Encryption {
+    /// Hex encoded AES key
+    key: String,
+    pub key_type: EncKeyType,
+}
+
+impl Encryption {
+    /// Encryption with the hex key in the file `key_file`, or in the
+    /// environment variable `TA_ENC_KEY` without a file
+    pub fn resolve(key_file: Option<&Path>, key_type: EncKeyType) -> Result<Self> {
+        let key = match key_file {
+            Some(path) => fs::read_to_string(path).map_err(|e| {
+                anyhow::anyhow!("Failed to read the encryption key {:?}: {}", path, e)
+            })?,
+            None => std::env::var(ENC_KEY_ENV).map_err(|_| {
+                anyhow::anyhow!(
+                    "Encrypting the TA requires an encryption key file or {}",
+                    ENC_KEY_ENV
+                )
+            })?,
+        };
+        let key = key.trim().to_string();
+        let valid_hex = key.len() % 2 == 0 && key.bytes().all(|b| b.is_ascii_hexdigit());
+        if !valid_hex || ![32, 48, 64].contains(&key.len()) {
+            bail!("The encryption key must be a hex encoded 128, 192 or 256 bits AES key");
+        }
+        Ok(Encryption { key, key_type })
+    }
+}
+
+/// Options of `cargo optee sign`
+pub struct SignConfig {
+    /// Stripped TA ELF
+    pub input: PathBuf,
+    pub uuid: String,
+    pub signer: Signer,
+    /// PEM public key of the signer, sign_encrypt.py needs it when it does
+    /// not have the private key
+    pub public_key: Option<PathBuf>,
+    /// Signed TA
+    pub output: PathBuf,
+    pub ta_dev_kit_dir: PathBuf,
+    /// Encrypt the TA before signing it, `None` for a plain TA
+    pub encryption: Option<Encryption>,
+}
+
+/// Sign the stripped TA ELF `config.input` into a loadable `.ta` file.
+///
+/// With a PEM key sign_encrypt.py signs the TA itself. Otherwise the private
+/// key never reaches it: it produces the digest of the TA, the signer signs
+/// it, and sign_encrypt.py stitches the signature into the TA after
+/// verifying it with the public key.
+///
+/// An encrypted TA can only be produced with a PEM key: sign_encrypt.py
+/// encrypts the TA with a random nonce in the same step it signs it. The
+/// header of the encrypted TA is checked once written.
+pub fn sign_ta(config: &SignConfig) -> Result<()> {
+    if !config.input.exists() {
+        bail!("TA ELF not found: {:?}", config.input);
+    }
+    uuid::Uuid::parse_str(&config.uuid)
+        .map_err(|e| anyhow::anyhow!("Invalid UUID {:?}: {}", config.uuid, e))?;
+    let sign_script = common::join_and_check(
+        &config.ta_dev_kit_dir,
+        &["scripts", "sign_encrypt.py"],
+        "Sign script",
+    )?;
+    let sign_encrypt = |subcommand: &str, key: &Path| {
+        let mut cmd = Command::new("python3");
+        cmd.arg(&sign_script)
+            .arg(subcommand)
+            .arg("--uuid")
+            .arg(&config.uuid)
+            .arg("--key")
+            .arg(key)
+            .arg("--in")
+            .arg(&config.input);
+        cmd
+    };
+
+    match &config.signer {
+        Signer::Pem(key) => {
+            if !key.exists() {
+                bail!("Signing key not found at {:?}", key);
+            }
+            let mut cmd = sign_encrypt("sign-enc", key);
+            if let Some(encryption) = &config.encryption {
+                cmd.arg("--enc-key")
+                    .arg(&encryption.key)
+                    .arg("--enc-key-type")
+                    .arg(encryption.key_type.script_arg());
+            }
+            let output = cmd.arg("--out").arg(&config.output).output()?;
+            if !output.status.success() {
+                print_output_and_bail("sign_encrypt.py", &output)?;
+            }
+        }
+        _ if config.encryption.is_some() => {
+            bail!("Encrypting a TA requires a PEM signing key (--key <PEM-PATH>)");
+        }
+        signer => {
+            let public_key = config.public_key.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("A public key (--public-key) is required to sign without a PEM key")
+            })?;
+            if !public_key.exists() {
+                bail!("Public key not found at {:?}", public_key);
+            }
+            let temp_dir = TempDir::new()?;
+            let digest_path = temp_dir.path().join("ta.dig");
+            let signature_path = temp_dir.path().join("ta.sig");
+
+            let output = sign_encrypt("digest", public_key)
+                .arg("--dig")
+                .arg(&digest_path)
+                .output()?;
+            if !output.status.success() {
+                print_output_and_bail("sign_encrypt.py digest", &output)?;
+            }
+            // Both files are base64 encoded
+            let digest = BASE64.decode(fs::read_to_string(&digest_path)?.trim())?;
+            let signature = match signer {
+                Signer::Pkcs11(uri) => sign_with_pkcs11(uri, &digest, temp_dir.path())?,
+                Signer::Command(command) => sign_with_command(command, &digest)?,
+                Signer::Pem(_) => unreachable!(),
+            };
+            fs::write(&signature_path, BASE64.encode(signature))?;
+
+            let output = sign_encrypt("stitch", public_key)
+                .arg("--sig")
+                .arg(&signature_path)
+                .arg("--out")
+                .arg(&config.output)
+                .output()?;
+            if !output.status.success() {
+                print_output_and_bail("sign_encrypt.py stitch", &output)?;
+            }
+        }
+    }
+
+    if let Some(encryption) = &config.encryption {
+        verify_encrypted_header(&fs::read(&config.output)?, &config.uuid, encryption)?;
+        status!("ENCRYPT => {}", config.uuid);
+    }
+    status!("SIGN => {}", config.uuid);
+    let absolute_output_path = config
+        .output
+        .canonicalize()
+        .unwrap_or_else(|_| config.output.clone());
+    status!("TA signed and saved to: {:?}", absolute_output_path);
+    Ok(())
+}
+
+/// Check that the TA file `ta` is encrypted for `uuid` as requested by
+/// `encryption`: the signed header, the bootstrap header with the UUID and
+/// the header of the encryption follow each other
+fn verify_encrypted_header(ta: &[u8], uuid: &str, encryption: &Encryption) -> Result<()> {
+    let bytes = |offset: usize, size: usize| {
+        ta.get(offset..offset + size)
+            .ok_or_else(|| anyhow::anyhow!("Encrypted TA is too short for its headers"))
+    };
+    let field = |offset: usize, size: usize| -> Result<u32> {
+        Ok(bytes(offset, size)?
+            .iter()
+            .rev()
+            .fold(0, |value, byte| (value << 8) | u32::from(*byte)))
+    };
+    if field(0, 4)? != SHDR_MAGIC {
+        bail!("Encrypted TA does not start with a signed header");
+    }
+    if field(4, 4)? != SHDR_ENCRYPTED_TA {
+        bail!("sign_encrypt.py did not encrypt the TA, is the TA dev kit too old?");
+    }
+    // The bootstrap header follows the hash and the signature
+    let bootstrap = SHDR_SIZE + field(16, 2)? as usize + field(18, 2)? as usize;
+    let expected_uuid = uuid::Uuid::parse_str(uuid)?;
+    if bytes(bootstrap, 16)? != expected_uuid.as_bytes() {
+        bail!("UUID in the header of the encrypted TA is not {}", uuid);
+    }
+    // The encrypted header follows the UUID and the version of the TA
+    let ehdr = bootstrap + 20;
+    if field(ehdr, 4)? != TEE_ALG_AES_GCM {
+        bail!("Encrypted TA is not encrypted with AES-GCM");
+    }
+    if field(ehdr + 4, 4)? != encryption.key_type.flags() {
+        bail!(
+            "Encrypted TA does not use the {:?} key type",
+            encryption.key_type
+        );
+    }
+    Ok(())
+}
+
+/// Sign `digest` with RSASSA-PSS, the default algorithm of sign_encrypt.py,
+/// using the key `uri` of a PKCS#11 token
+fn sign_with_pkcs11(uri: &str, digest: &[u8], temp_dir: &Path) -> Result<Vec<u8>> {
+    let digest_path = temp_dir.join("ta.dig.bin");
+    let signature_path = temp_dir.join("ta.sig.bin");
+    fs::write(&digest_path, digest)?;
+    let output = Command::new("openssl")
+        .args([
+            "pkeyutl",
+            "-sign",
+            "-provider",
+            "pkcs11",
+            "-provider",
+            "default",
+        ])
+        .arg("-inkey")
+        .arg(uri)
+        .args([
+            "-pkeyopt",
+            "digest:sha256",
+            "-pkeyopt",
+            "rsa_padding_mode:pss",
+            "-pkeyopt",
+            "rsa_pss_saltlen:digest",
+        ])
+        .arg("-in")
+        .arg(&digest_path)
+        .arg("-out")
+        .arg(&signature_path)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run openssl: {}", e))?;
+    if !output.status.success() {
+        print_output_and_bail("openssl pkeyutl", &output)?;
+    }
+    Ok(fs::read(signature_path)?)
+}
+
+/// Sign `digest` with the external `command`, which gets the raw digest on
+/// stdin and prints the raw signature on stdout
+fn sign_with_command(command: &str, digest: &[u8]) -> Result<Vec<u8>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run the sign command: {}", e))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(digest)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "Sign command `{}` failed with exit code: {:?}",
+            command,
+            output.status.code()
+        );
+    }
+    if output.stdout.is_empty() {
+        bail!("Sign command `{}` printed no signature", command);
+    }
+    Ok(output.stdout)
+}
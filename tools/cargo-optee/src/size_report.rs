@@ -0,0 +1,238 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use crate::common::{
+    BuildMode, ChangeDirectoryGuard, get_target_and_cross_compile, print_output_and_bail,
+};
+use crate::config::TaBuildConfig;
+use crate::status;
+use crate::ta_builder::locate_binary;
+
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Sections reported in the per-crate table, everything else is summed as "other"
+const REPORTED_SECTIONS: [&str; 4] = [".text", ".rodata", ".data", ".bss"];
+
+/// A symbol from the ELF symbol table
+struct Symbol {
+    section: String,
+    size: u64,
+    name: String,
+}
+
+/// Print the section sizes, per-crate sizes and largest symbols of a built TA
+pub fn report_ta_size(config: &TaBuildConfig, top: usize) -> Result<()> {
+    let _guard = ChangeDirectoryGuard::new(&config.path)?;
+
+    let build_mode = if config.std {
+        BuildMode::TaStd
+    } else {
+        BuildMode::TaNoStd
+    };
+    let (_, cross_compile) = get_target_and_cross_compile(config.arch, build_mode)?;
+    let objdump = format!("{}objdump", cross_compile);
+
+    let (_, binary_path, stripped_path) = locate_binary(config)?;
+    if !stripped_path.exists() {
+        bail!(
+            "Stripped TA not found: {:?}\nPlease run `cargo-optee build ta` first",
+            stripped_path
+        );
+    }
+
+    // Section sizes of the stripped binary, which is what gets signed and shipped
+    let output = Command::new(&objdump)
+        .arg("-h")
+        .arg(&stripped_path)
+        .output()?;
+    if !output.status.success() {
+        print_output_and_bail(&objdump, &output)?;
+    }
+    let sections = parse_section_headers(&String::from_utf8_lossy(&output.stdout));
+
+    println!("Section sizes ({}):", stripped_path.display());
+    for (name, size) in &sections {
+        println!("  {:<24} {:>10}", name, size);
+    }
+    println!(
+        "  {:<24} {:>10}",
+        "Total",
+        sections.iter().map(|(_, size)| size).sum::<u64>()
+    );
+
+    // Symbols are only available in the unstripped binary
+    let output = Command::new(&objdump)
+        .arg("-t")
+        .arg("-C")
+        .arg(&binary_path)
+        .output()?;
+    if !output.status.success() {
+        print_output_and_bail(&objdump, &output)?;
+    }
+    let mut symbols = parse_symbol_table(&String::from_utf8_lossy(&output.stdout));
+
+    print_crate_sizes(&symbols);
+
+    symbols.sort_by_key(|symbol| std::cmp::Reverse(symbol.size));
+    println!();
+    println!("Top {} symbols:", top);
+    for symbol in symbols.iter().take(top) {
+        println!(
+            "  {:>10}  {:<10} {}",
+            symbol.size, symbol.section, symbol.name
+        );
+    }
+
+    println!();
+    match config.size_budget {
+        Some(size_budget) => check_size_budget(&stripped_path, size_budget),
+        None => {
+            println!(
+                "Stripped TA size: {} bytes",
+                fs::metadata(&stripped_path)?.len()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Fail if the stripped TA at `stripped_path` is larger than `size_budget` bytes
+pub fn check_size_budget(stripped_path: &Path, size_budget: u64) -> Result<()> {
+    let size = fs::metadata(stripped_path)?.len();
+
+    if size > size_budget {
+        bail!(
+            "Stripped TA size {} bytes exceeds the size budget of {} bytes by {} bytes\n\
+            Run `cargo-optee size` to find out which crates and symbols contribute the most",
+            size,
+            size_budget,
+            size - size_budget
+        );
+    }
+
+    status!(
+        "Stripped TA size: {} bytes ({:.1}% of the {} bytes budget)",
+        size,
+        size as f64 * 100.0 / size_budget as f64,
+        size_budget
+    );
+
+    Ok(())
+}
+
+/// Group symbol sizes by the crate they belong to and print them as a table
+fn print_crate_sizes(symbols: &[Symbol]) {
+    // crate name => sizes of REPORTED_SECTIONS followed by "other"
+    let mut crates: BTreeMap<&str, [u64; REPORTED_SECTIONS.len() + 1]> = BTreeMap::new();
+    for symbol in symbols {
+        let sizes = crates.entry(crate_of(&symbol.name)).or_default();
+        let index = REPORTED_SECTIONS
+            .iter()
+            .position(|s| symbol.section == *s || symbol.section.starts_with(&format!("{}.", s)))
+            .unwrap_or(REPORTED_SECTIONS.len());
+        sizes[index] += symbol.size;
+    }
+
+    let mut crates: Vec<_> = crates.into_iter().collect();
+    crates.sort_by_key(|(_, sizes)| std::cmp::Reverse(sizes.iter().sum::<u64>()));
+
+    status!();
+    status!("Size by crate (approximated from symbol names):");
+    status!(
+        "  {:<24} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "Crate",
+        ".text",
+        ".rodata",
+        ".data",
+        ".bss",
+        "other",
+        "total"
+    );
+    for (name, sizes) in crates {
+        status!(
+            "  {:<24} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            name,
+            sizes[0],
+            sizes[1],
+            sizes[2],
+            sizes[3],
+            sizes[4],
+            sizes.iter().sum::<u64>()
+        );
+    }
+}
+
+/// Parse the output of `objdump -h` into `(section name, size)` pairs
+fn parse_section_headers(output: &str) -> Vec<(String, u64)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            // e.g. "  0 .text  00001234  0000000000000000  0000000000000000  00010000  2**3"
+            let mut fields = line.split_whitespace();
+            fields.next()?.parse::<u32>().ok()?;
+            let name = fields.next()?;
+            let size = u64::from_str_radix(fields.next()?, 16).ok()?;
+            Some((name.to_string(), size))
+        })
+        .collect()
+}
+
+/// Parse the output of `objdump -t -C` into the list of sized symbols
+fn parse_symbol_table(output: &str) -> Vec<Symbol> {
+    output
+        .lines()
+        .filter_map(|line| {
+            // e.g. "0000000000001234 l     F .text\t0000000000000040 core::fmt::write"
+            let (address_flags_section, size_name) = line.split_once('\t')?;
+            let section = address_flags_section.split_whitespace().last()?;
+            if section.starts_with('*') {
+                // *UND*, *ABS* and *COM* symbols do not occupy space in the binary
+                return None;
+            }
+            let (size, name) = size_name.split_once(' ')?;
+            let size = u64::from_str_radix(size, 16).ok()?;
+            if size == 0 {
+                return None;
+            }
+            // Non-default visibility is printed between the size and the name
+            let name = name.trim();
+            let name = name
+                .strip_prefix(".hidden")
+                .or_else(|| name.strip_prefix(".protected"))
+                .unwrap_or(name);
+            Some(Symbol {
+                section: section.to_string(),
+                size,
+                name: name.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Best-effort crate name of a demangled symbol, e.g. `core` for
+/// `core::fmt::write` and `alloc` for `<alloc::vec::Vec<T> as Drop>::drop`
+fn crate_of(symbol: &str) -> &str {
+    let path = symbol.trim_start_matches('<');
+    match path.split_once("::") {
+        Some((head, _)) => head.rsplit(' ').next().unwrap_or(head),
+        None => "[non-rust]",
+    }
+}
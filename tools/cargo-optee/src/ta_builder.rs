@@ -19,12 +19,18 @@ use crate::cargo_command;
 use crate::common;
 use crate::common::{
     BuildMode, ChangeDirectoryGuard, get_package_name, get_target_and_cross_compile,
-    get_target_directory_from_metadata, print_cargo_command, print_output_and_bail,
-    read_uuid_from_file,
+    get_target_directory_from_metadata, get_workspace_root_from_metadata, hex, needs_build_std,
+    print_cargo_command, print_output_and_bail, read_uuid_from_file,
 };
 use crate::config::TaBuildConfig;
+use crate::message::{self, Message};
+use crate::package::shdr_digest;
+use crate::sign::{self, SignConfig, Signer};
+use crate::size_report::check_size_budget;
+use crate::status;
 
 use anyhow::{Result, bail};
+use sha2::{Digest, Sha256};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -34,6 +40,8 @@ use tempfile::TempDir;
 // Embed the target JSON files at compile time
 const AARCH64_TARGET_JSON: &str = include_str!("../aarch64-unknown-optee.json");
 const ARM_TARGET_JSON: &str = include_str!("../arm-unknown-optee.json");
+const RISCV64_TARGET_JSON: &str = include_str!("../riscv64gc-unknown-optee.json");
+const RISCV32_TARGET_JSON: &str = include_str!("../riscv32gc-unknown-optee.json");
 
 // Main function to build the TA, optionally installing to a target directory
 pub fn build_ta(config: TaBuildConfig, install_dir: Option<&Path>) -> Result<()> {
@@ -61,55 +69,71 @@ pub fn build_ta(config: TaBuildConfig, install_dir: Option<&Path>) -> Result<()>
 
     // Get the absolute path for better clarity
     let absolute_path = std::fs::canonicalize(&config.path).unwrap_or_else(|_| config.path.clone());
-    println!("Building TA in directory: {}", absolute_path.display());
+    status!("Building TA in directory: {}", absolute_path.display());
 
-    // Step 1: Run clippy for code quality checks
-    run_clippy(&config)?;
+    // Step 1: Run clippy for code quality checks, unless skipped
+    if config.skip_checks {
+        status!("Skipping cargo fmt and clippy");
+    } else {
+        run_clippy(&config)?;
+    }
 
     // Step 2: Build the TA
-    build_binary(&config)?;
+    build_binary(&config, None)?;
 
     // Step 3: Strip the binary
     let (stripped_path, target_dir) = strip_binary(&config)?;
 
-    // Step 4: Sign the TA
-    sign_ta(&config, &stripped_path, &target_dir)?;
+    // Step 3b: Rebuild from scratch and compare, for reproducible builds
+    if config.reproducible {
+        verify_reproducible(&config, &stripped_path)?;
+    }
+
+    // Step 4: Enforce the size budget, if configured
+    if let Some(size_budget) = config.size_budget {
+        check_size_budget(&stripped_path, size_budget)?;
+    }
+
+    // Step 5: Sign the TA
+    let (uuid, ta_file) = sign_ta(&config, &stripped_path, &target_dir)?;
 
-    // Step 5: Install if requested
-    if let Some(install_dir) = install_dir {
+    // Step 6: Install if requested
+    let installed = if let Some(install_dir) = install_dir {
         // Check if install directory exists
         if !install_dir.exists() {
             bail!("Install directory does not exist: {:?}", install_dir);
         }
 
-        let uuid_path = config
-            .uuid_path
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("UUID path is required but not configured"))?;
-        let uuid = read_uuid_from_file(uuid_path)?;
-        let ta_file = common::join_format_and_check::<&str>(
-            &target_dir,
-            &[],
-            &format!("{}.ta", uuid),
-            "Signed TA file",
-        )?;
-
         let dest_path = install_dir.join(format!("{}.ta", uuid));
-        fs::copy(ta_file, &dest_path)?;
+        fs::copy(&ta_file, &dest_path)?;
 
-        println!(
-            "TA installed to: {:?}",
-            dest_path.canonicalize().unwrap_or(dest_path)
-        );
+        let dest_path = dest_path.canonicalize().unwrap_or(dest_path);
+        status!("TA installed to: {:?}", dest_path);
+        Some(dest_path)
+    } else {
+        None
+    };
+
+    if message::is_json() {
+        let ta = fs::read(&ta_file)?;
+        message::emit(&Message::Artifact {
+            component: "ta",
+            package: &get_package_name()?,
+            path: &ta_file,
+            installed: installed.as_deref(),
+            uuid: Some(&uuid),
+            sha256: hex(&Sha256::digest(&ta)),
+            shdr_digest: Some(hex(shdr_digest(&ta)?)),
+        });
     }
 
-    println!("TA build successfully!");
+    status!("TA build successfully!");
 
     Ok(())
 }
 
 fn run_clippy(config: &TaBuildConfig) -> Result<()> {
-    println!("Running cargo fmt and clippy...");
+    status!("Running cargo fmt and clippy...");
 
     // Run cargo fmt (we're already in the project directory via ChangeDirectoryGuard)
     let fmt_output = cargo_command().arg("fmt").output()?;
@@ -119,13 +143,10 @@ fn run_clippy(config: &TaBuildConfig) -> Result<()> {
     }
 
     // Setup clippy command with common environment
-    let (mut clippy_cmd, _temp_dir) = setup_build_command(config, "clippy")?;
+    let (mut clippy_cmd, _temp_dir) = setup_build_command(config, "clippy", None)?;
 
     clippy_cmd.arg("--");
-    clippy_cmd.arg("-D").arg("warnings");
-    clippy_cmd.arg("-D").arg("clippy::unwrap_used");
-    clippy_cmd.arg("-D").arg("clippy::expect_used");
-    clippy_cmd.arg("-D").arg("clippy::panic");
+    clippy_cmd.args(config.lints.clippy_args());
 
     let clippy_output = clippy_cmd.output()?;
 
@@ -136,7 +157,9 @@ fn run_clippy(config: &TaBuildConfig) -> Result<()> {
     Ok(())
 }
 
-fn build_binary(config: &TaBuildConfig) -> Result<()> {
+/// Build the TA, in `target_dir` instead of the target directory of the
+/// project if set
+fn build_binary(config: &TaBuildConfig, target_dir: Option<&Path>) -> Result<()> {
     // Determine target and cross-compile based on arch and std mode
     let build_mode = if config.std {
         BuildMode::TaStd
@@ -146,7 +169,7 @@ fn build_binary(config: &TaBuildConfig) -> Result<()> {
     let (target, cross_compile) = get_target_and_cross_compile(config.arch, build_mode)?;
 
     // Setup build command with common environment (we're already in the project directory)
-    let (mut build_cmd, _temp_dir) = setup_build_command(config, "build")?;
+    let (mut build_cmd, _temp_dir) = setup_build_command(config, "build", target_dir)?;
 
     if !config.debug {
         build_cmd.arg("--release");
@@ -157,10 +180,16 @@ fn build_binary(config: &TaBuildConfig) -> Result<()> {
     let linker_cfg = format!("target.{}.linker=\"{}\"", target, linker);
     build_cmd.arg("--config").arg(&linker_cfg);
 
+    // Forward the warnings of the compiler as JSON messages
+    if message::is_json() {
+        build_cmd.arg("--message-format").arg("json");
+    }
+
     // Print the full cargo build command for debugging
     print_cargo_command(&build_cmd, "Building TA binary");
 
     let build_output = build_cmd.output()?;
+    message::emit_diagnostics(&build_output.stdout);
 
     if !build_output.status.success() {
         print_output_and_bail("build", &build_output)?;
@@ -169,22 +198,22 @@ fn build_binary(config: &TaBuildConfig) -> Result<()> {
     Ok(())
 }
 
-fn strip_binary(config: &TaBuildConfig) -> Result<(PathBuf, PathBuf)> {
-    println!("Stripping binary...");
-
+/// Locate the output directory and unstripped binary of the TA
+///
+/// Returns `(profile_dir, binary_path, stripped_path)`, the stripped binary may
+/// not exist yet.
+pub fn locate_binary(config: &TaBuildConfig) -> Result<(PathBuf, PathBuf, PathBuf)> {
     // Determine target based on arch and std mode
     let build_mode = if config.std {
         BuildMode::TaStd
     } else {
         BuildMode::TaNoStd
     };
-    let (target, cross_compile) = get_target_and_cross_compile(config.arch, build_mode)?;
-
-    let profile = if config.debug { "debug" } else { "release" };
+    let (target, _cross_compile) = get_target_and_cross_compile(config.arch, build_mode)?;
 
     // Use cargo metadata to get the target directory (supports workspace and CARGO_TARGET_DIR)
     let target_directory = get_target_directory_from_metadata()?;
-    let profile_dir = target_directory.join(target).join(profile);
+    let profile_dir = profile_dir(config, &target_directory, &target);
 
     // Get the actual package name from Cargo.toml (we're already in the project directory)
     let package_name = get_package_name()?;
@@ -193,23 +222,94 @@ fn strip_binary(config: &TaBuildConfig) -> Result<(PathBuf, PathBuf)> {
 
     let stripped_path = profile_dir.join(format!("stripped_{}", package_name));
 
+    Ok((profile_dir, binary_path, stripped_path))
+}
+
+/// Output directory of the build profile of `config` in `target_directory`
+fn profile_dir(config: &TaBuildConfig, target_directory: &Path, target: &str) -> PathBuf {
+    let profile = if config.debug { "debug" } else { "release" };
+    target_directory.join(target).join(profile)
+}
+
+fn strip_binary(config: &TaBuildConfig) -> Result<(PathBuf, PathBuf)> {
+    status!("Stripping binary...");
+
+    let build_mode = if config.std {
+        BuildMode::TaStd
+    } else {
+        BuildMode::TaNoStd
+    };
+    let (_, cross_compile) = get_target_and_cross_compile(config.arch, build_mode)?;
+
+    let (profile_dir, binary_path, stripped_path) = locate_binary(config)?;
+
+    strip(&cross_compile, &binary_path, &stripped_path)?;
+
+    Ok((stripped_path, profile_dir))
+}
+
+fn strip(cross_compile: &str, binary_path: &Path, stripped_path: &Path) -> Result<()> {
     let objcopy = format!("{}objcopy", cross_compile);
 
     let strip_output = Command::new(&objcopy)
         .arg("--strip-unneeded")
-        .arg(&binary_path)
-        .arg(&stripped_path)
+        .arg(binary_path)
+        .arg(stripped_path)
         .output()?;
 
     if !strip_output.status.success() {
         print_output_and_bail(&objcopy, &strip_output)?;
     }
 
-    Ok((stripped_path, profile_dir))
+    Ok(())
 }
 
-fn sign_ta(config: &TaBuildConfig, stripped_path: &Path, target_dir: &Path) -> Result<()> {
-    println!("Signing TA with signing key {:?}...", config.signing_key);
+/// Build the TA once more, from scratch in a temporary target directory,
+/// and check that the stripped binary, which the measurement of the signed
+/// TA covers, is identical to `stripped_path`
+fn verify_reproducible(config: &TaBuildConfig, stripped_path: &Path) -> Result<()> {
+    status!("Rebuilding TA from scratch to verify it is reproducible...");
+    let build_mode = if config.std {
+        BuildMode::TaStd
+    } else {
+        BuildMode::TaNoStd
+    };
+    let (target, cross_compile) = get_target_and_cross_compile(config.arch, build_mode)?;
+
+    let target_dir = TempDir::new()?;
+    build_binary(config, Some(target_dir.path()))?;
+    let binary_path = common::join_and_check(
+        &profile_dir(config, target_dir.path(), &target),
+        &[&get_package_name()?],
+        "Rebuilt binary",
+    )?;
+    let rebuilt_path = target_dir.path().join("stripped");
+    strip(&cross_compile, &binary_path, &rebuilt_path)?;
+
+    let first = Sha256::digest(fs::read(stripped_path)?);
+    let second = Sha256::digest(fs::read(&rebuilt_path)?);
+    if first != second {
+        bail!(
+            "TA build is not reproducible: the stripped TA has SHA-256 {} and {} when rebuilt",
+            hex(&first),
+            hex(&second)
+        );
+    }
+    status!(
+        "TA build is reproducible, stripped TA SHA-256: {}",
+        hex(&first)
+    );
+    Ok(())
+}
+
+/// Sign the stripped TA into `<uuid>.ta` in `target_dir`, returns the UUID
+/// and the path of the signed TA
+fn sign_ta(
+    config: &TaBuildConfig,
+    stripped_path: &Path,
+    target_dir: &Path,
+) -> Result<(String, PathBuf)> {
+    status!("Signing TA with signing key {:?}...", config.signing_key);
 
     // Read UUID from specified file
     let uuid_path = config
@@ -218,42 +318,19 @@ fn sign_ta(config: &TaBuildConfig, stripped_path: &Path, target_dir: &Path) -> R
         .ok_or_else(|| anyhow::anyhow!("UUID path is required but not configured"))?;
     let uuid = read_uuid_from_file(uuid_path)?;
 
-    // Validate signing key exists
-    if !config.signing_key.exists() {
-        bail!("Signing key not found at {:?}", config.signing_key);
-    }
-
-    // Sign script path
-    let sign_script = common::join_and_check(
-        &config.ta_dev_kit_dir,
-        &["scripts", "sign_encrypt.py"],
-        "Sign script",
-    )?;
-
     // Output path - use the actual target_dir
-    let output_path = target_dir.join(format!("{}.ta", uuid));
-
-    let sign_output = Command::new("python3")
-        .arg(&sign_script)
-        .arg("--uuid")
-        .arg(&uuid)
-        .arg("--key")
-        .arg(&config.signing_key)
-        .arg("--in")
-        .arg(stripped_path)
-        .arg("--out")
-        .arg(&output_path)
-        .output()?;
-
-    if !sign_output.status.success() {
-        print_output_and_bail("sign_encrypt.py", &sign_output)?;
-    }
-
-    println!("SIGN => {}", uuid);
-    let absolute_output_path = output_path.canonicalize().unwrap_or(output_path);
-    println!("TA signed and saved to: {:?}", absolute_output_path);
-
-    Ok(())
+    let output = target_dir.join(format!("{}.ta", uuid));
+
+    sign::sign_ta(&SignConfig {
+        input: stripped_path.to_path_buf(),
+        uuid: uuid.clone(),
+        signer: Signer::Pem(config.signing_key.clone()),
+        public_key: None,
+        output: output.clone(),
+        ta_dev_kit_dir: config.ta_dev_kit_dir.clone(),
+        encryption: config.encryption.clone(),
+    })?;
+    Ok((uuid, output))
 }
 
 /// Check if the required cross-compile toolchain is available
@@ -298,6 +375,12 @@ fn check_toolchain_exists(cross_compile_prefix: &str) -> Result<()> {
         eprintln!("# For x86_64 host (Intel/AMD machine):");
         eprintln!("apt update && apt -y install gcc-aarch64-linux-gnu gcc-arm-linux-gnueabihf");
         eprintln!();
+        eprintln!("# For riscv64 targets:");
+        eprintln!("apt update && apt -y install gcc-riscv64-linux-gnu");
+        eprintln!();
+        eprintln!("# For riscv32 targets, build a riscv32-unknown-linux-gnu toolchain from");
+        eprintln!("# https://github.com/riscv-collab/riscv-gnu-toolchain");
+        eprintln!();
         eprintln!("Or manually install the cross-compilation tools for your target architecture.");
 
         bail!("Cross-compile toolchain not available");
@@ -306,10 +389,12 @@ fn check_toolchain_exists(cross_compile_prefix: &str) -> Result<()> {
     Ok(())
 }
 
-// Helper function to setup base command with common environment variables
+// Helper function to setup base command with common environment variables,
+// building in `target_dir` instead of the target directory of the project if set
 fn setup_build_command(
     config: &TaBuildConfig,
     command: &str,
+    target_dir: Option<&Path>,
 ) -> Result<(Command, Option<TempDir>)> {
     // Determine target and cross-compile based on arch and std mode
     let build_mode = if config.std {
@@ -331,6 +416,8 @@ fn setup_build_command(
     cmd.arg(command);
     if config.std {
         cmd.arg("-Z").arg("build-std=std,panic_abort");
+    } else if needs_build_std(config.arch) {
+        cmd.arg("-Z").arg("build-std=core,alloc");
     }
     cmd.arg("--target").arg(&target);
 
@@ -365,7 +452,28 @@ fn setup_build_command(
         rustflags.push(' ');
     }
     rustflags.push_str("-C panic=abort");
+    if config.reproducible {
+        let target_dir = match target_dir {
+            Some(target_dir) => target_dir.to_path_buf(),
+            None => get_target_directory_from_metadata()?,
+        };
+        for flag in reproducible_rustflags(config, &target_dir)? {
+            rustflags.push(' ');
+            rustflags.push_str(&flag);
+        }
+        cmd.env("SOURCE_DATE_EPOCH", source_date_epoch(&config.path));
+        cmd.env("CARGO_INCREMENTAL", "0");
+    }
+    // Every function calls `_mcount` of libutee, which records the calls in
+    // the ftrace buffer, sized by the linker of optee-utee-build
+    if let Some(ftrace_buf_size) = config.ftrace_buf_size {
+        rustflags.push_str(" -Z instrument-mcount -C force-frame-pointers=yes");
+        cmd.env("TA_FTRACE_BUF_SIZE", ftrace_buf_size.to_string());
+    }
     cmd.env("RUSTFLAGS", &rustflags);
+    if let Some(target_dir) = target_dir {
+        cmd.env("CARGO_TARGET_DIR", target_dir);
+    }
 
     // Apply custom environment variables
     for (key, value) in &config.env {
@@ -407,6 +515,66 @@ fn setup_build_command(
     Ok((cmd, temp_dir))
 }
 
+/// Flags of a reproducible build: the directories the paths embedded in the
+/// TA (panic locations, debug info) start with are replaced by fixed names,
+/// and the linker orders the input sections by name rather than by the order
+/// of the object files.
+fn reproducible_rustflags(config: &TaBuildConfig, target_dir: &Path) -> Result<Vec<String>> {
+    let mut remaps = vec![(get_workspace_root_from_metadata()?, "/ta")];
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")));
+    if let Some(cargo_home) = cargo_home {
+        remaps.push((cargo_home, "/cargo"));
+    }
+    if config.std
+        && let Some(rust_src) = env::var_os("__CARGO_TESTS_ONLY_SRC_ROOT")
+    {
+        remaps.push((PathBuf::from(rust_src), "/rust/library"));
+    }
+    if let Some(sysroot) = rustc_sysroot() {
+        remaps.push((sysroot, "/rustc"));
+    }
+    // Last, as rustc applies the last matching prefix and the target
+    // directory is usually in the workspace
+    remaps.push((target_dir.to_path_buf(), "/target"));
+
+    let mut flags: Vec<String> = remaps
+        .iter()
+        .map(|(from, to)| format!("--remap-path-prefix={}={}", from.display(), to))
+        .collect();
+    flags.push("-C link-arg=-Wl,--sort-section=name".to_string());
+    Ok(flags)
+}
+
+/// `SOURCE_DATE_EPOCH` if set, or the time of the last commit of the TA, or
+/// the Unix epoch outside of a git repository
+fn source_date_epoch(project_path: &Path) -> String {
+    if let Ok(epoch) = env::var("SOURCE_DATE_EPOCH") {
+        return epoch;
+    }
+    Command::new("git")
+        .args(["log", "-1", "--format=%ct"])
+        .current_dir(project_path)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|epoch| !epoch.is_empty())
+        .unwrap_or_else(|| "0".to_string())
+}
+
+fn rustc_sysroot() -> Option<PathBuf> {
+    let output = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
 // Helper function to setup custom target JSONs for std builds
 // Returns TempDir to keep it alive during the build
 fn setup_custom_targets() -> Result<TempDir> {
@@ -415,9 +583,13 @@ fn setup_custom_targets() -> Result<TempDir> {
     // Write the embedded target JSON files
     let aarch64_path = temp_dir.path().join("aarch64-unknown-optee.json");
     let arm_path = temp_dir.path().join("arm-unknown-optee.json");
+    let riscv64_path = temp_dir.path().join("riscv64gc-unknown-optee.json");
+    let riscv32_path = temp_dir.path().join("riscv32gc-unknown-optee.json");
 
     fs::write(aarch64_path, AARCH64_TARGET_JSON)?;
     fs::write(arm_path, ARM_TARGET_JSON)?;
+    fs::write(riscv64_path, RISCV64_TARGET_JSON)?;
+    fs::write(riscv32_path, RISCV32_TARGET_JSON)?;
 
     Ok(temp_dir)
 }
@@ -0,0 +1,132 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::{Result, bail};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use tempfile::TempDir;
+
+use crate::common::{ChangeDirectoryGuard, print_output_and_bail, read_uuid_from_file};
+use crate::config::TaBuildConfig;
+use crate::qemu_test::Ssh;
+use crate::status;
+use crate::ta_builder::locate_binary;
+
+/// Options of `cargo optee trace pull`
+pub struct TracePullConfig {
+    /// The TA whose trace is pulled, built with `--profile ftrace`
+    pub ta: TaBuildConfig,
+    /// SSH destination and port of the emulator or device
+    pub destination: String,
+    pub port: u16,
+    /// File the trace is written to, `ftrace-<uuid>.txt` by default
+    pub output: Option<PathBuf>,
+    /// Replace the addresses of the trace with the functions of the TA
+    pub symbolize: bool,
+}
+
+/// Copy the function graph of the TA, which tee-supplicant writes to
+/// `/tmp/ftrace-<uuid>.out` when the last session of an instrumented TA is
+/// closed, and symbolize it with the `symbolize.py` of the TA dev kit and the
+/// unstripped TA.
+pub fn pull(config: &TracePullConfig) -> Result<()> {
+    let uuid_path = config
+        .ta
+        .uuid_path
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("UUID path is required but not configured"))?;
+    let uuid = read_uuid_from_file(uuid_path)?;
+    let remote = format!("/tmp/ftrace-{}.out", uuid);
+
+    status!("Pulling {} from {}...", remote, config.destination);
+    let output = Ssh::new(&config.destination, config.port)
+        .command(&format!("cat {}", remote))
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run ssh: {}", e))?;
+    if !output.status.success() {
+        bail!(
+            "Could not read {} on {}: {}\n\
+            Build the TA with `cargo optee build ta --profile ftrace`, run it and close its sessions first",
+            remote,
+            config.destination,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let trace = if config.symbolize {
+        symbolize(&config.ta, &uuid, output.stdout)?
+    } else {
+        output.stdout
+    };
+    let output_path = config
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("ftrace-{}.txt", uuid)));
+    fs::write(&output_path, trace)?;
+    status!("Function graph of TA {} written to {:?}", uuid, output_path);
+    Ok(())
+}
+
+/// Run `trace` through `symbolize.py`, which finds the TA as `<uuid>.elf` in
+/// the directories it is given. Keeps the addresses if the TA dev kit has no
+/// `symbolize.py`.
+fn symbolize(ta: &TaBuildConfig, uuid: &str, trace: Vec<u8>) -> Result<Vec<u8>> {
+    let script = ta.ta_dev_kit_dir.join("scripts").join("symbolize.py");
+    if !script.exists() {
+        eprintln!(
+            "Warning: {:?} not found, writing the trace without symbols",
+            script
+        );
+        return Ok(trace);
+    }
+
+    let binary_path = {
+        let _guard = ChangeDirectoryGuard::new(&ta.path)?;
+        locate_binary(ta)?.1
+    };
+    let elf_dir = TempDir::new()?;
+    fs::copy(&binary_path, elf_dir.path().join(format!("{}.elf", uuid)))?;
+
+    status!("Symbolizing with {:?}...", script);
+    symbolize_with(&script, elf_dir.path(), trace)
+}
+
+fn symbolize_with(script: &Path, elf_dir: &Path, trace: Vec<u8>) -> Result<Vec<u8>> {
+    let mut child = Command::new("python3")
+        .arg(script)
+        .arg("-d")
+        .arg(elf_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run python3: {}", e))?;
+
+    // Written from another thread, so a long trace cannot fill the pipes
+    // both ways
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || stdin.write_all(&trace));
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+    if !output.status.success() {
+        print_output_and_bail("symbolize.py", &output)?;
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,210 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::qemu_test::StagingDirs;
+use crate::run::{Component, Connection, Target};
+
+/// Directory of the build artifacts, never holding sources
+const TARGET_DIR: &str = "target";
+
+/// Options of `cargo optee watch`
+pub struct WatchConfig {
+    pub target: Target,
+    /// The components of the project and their project directories
+    pub components: Vec<(Component, PathBuf)>,
+    /// Command run on the device after each deployment
+    pub run: Option<String>,
+    /// File or serial device the secure world console is read from
+    pub secure_log: Option<PathBuf>,
+    /// Time between two scans of the sources
+    pub interval: Duration,
+}
+
+/// Modification time and size of the watched files
+type Snapshot = BTreeMap<PathBuf, (Option<SystemTime>, u64)>;
+
+/// Build and deploy the project, then rebuild and redeploy the components
+/// whose sources change until interrupted.
+///
+/// `build` builds a component into its staging directory, signing the TA.
+/// Files outside of the component directories, e.g. a proto crate shared by
+/// the TA and the CA, belong to every component.
+pub fn watch(
+    config: &WatchConfig,
+    staging: &StagingDirs,
+    mut build: impl FnMut(Component) -> Result<()>,
+) -> Result<()> {
+    let root = common_ancestor(config.components.iter().map(|(_, dir)| dir.as_path()));
+    // The shared folder holds the staged components, which change on every
+    // build
+    let skipped = staging.root.parent().unwrap_or(&staging.root).to_path_buf();
+
+    // Nothing can be deployed until everything built once
+    let components: Vec<Component> = config.components.iter().map(|(c, _)| *c).collect();
+    for &component in &components {
+        build(component)?;
+    }
+    let connection = Connection::open(&config.target, config.secure_log.as_deref(), staging)?;
+    let _followers = connection.follow_consoles();
+    deploy(&connection, config, staging, &components)?;
+
+    let mut snapshot = scan(&root, &skipped)?;
+    // Components whose last build failed, rebuilt with the next change
+    let mut pending: Vec<Component> = Vec::new();
+    println!("Watching {:?} for changes, press Ctrl-C to stop", root);
+    loop {
+        thread::sleep(config.interval);
+        let mut current = scan(&root, &skipped)?;
+        if current == snapshot {
+            continue;
+        }
+        // Let the editor or the VCS finish writing
+        loop {
+            thread::sleep(config.interval);
+            let next = scan(&root, &skipped)?;
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+
+        for component in changed_components(&config.components, &snapshot, &current) {
+            if !pending.contains(&component) {
+                pending.push(component);
+            }
+        }
+        snapshot = current;
+        pending.sort_by_key(|component| components.iter().position(|c| c == component));
+
+        let mut built = Vec::new();
+        for component in std::mem::take(&mut pending) {
+            println!("Rebuilding the {}...", component.name());
+            match build(component) {
+                Ok(()) => built.push(component),
+                Err(e) => {
+                    eprintln!("Error: building the {} failed: {}", component.name(), e);
+                    pending.push(component);
+                }
+            }
+        }
+        if !built.is_empty()
+            && let Err(e) = deploy(&connection, config, staging, &built)
+        {
+            eprintln!("Error: {}", e);
+        }
+        println!("Watching for changes...");
+    }
+}
+
+/// Install `components` on the target and run the command of the watch
+fn deploy(
+    connection: &Connection,
+    config: &WatchConfig,
+    staging: &StagingDirs,
+    components: &[Component],
+) -> Result<()> {
+    connection.install(staging, components)?;
+    if let Some(command) = &config.run {
+        // A failing command is reported, the next change may fix it
+        let status = connection.run_streaming(command)?;
+        if !status.success() {
+            eprintln!("`{}` failed with exit code: {:?}", command, status.code());
+        }
+    }
+    Ok(())
+}
+
+/// The deepest directory containing all of `dirs`
+fn common_ancestor<'a>(mut dirs: impl Iterator<Item = &'a Path>) -> PathBuf {
+    let mut ancestor = dirs.next().map(Path::to_path_buf).unwrap_or_default();
+    for dir in dirs {
+        while !dir.starts_with(&ancestor) {
+            if !ancestor.pop() {
+                break;
+            }
+        }
+    }
+    ancestor
+}
+
+/// Record the files under `root`, except in `skipped`, build directories and
+/// hidden directories such as `.git`
+fn scan(root: &Path, skipped: &Path) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        // Files may be removed while scanning, the next scan sees it
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if name != TARGET_DIR && !name.starts_with('.') && path != skipped {
+                    dirs.push(path);
+                }
+            } else {
+                snapshot.insert(path, (metadata.modified().ok(), metadata.len()));
+            }
+        }
+    }
+    Ok(snapshot)
+}
+
+/// The components affected by the differences between two snapshots
+fn changed_components(
+    components: &[(Component, PathBuf)],
+    before: &Snapshot,
+    after: &Snapshot,
+) -> Vec<Component> {
+    let changed = before
+        .iter()
+        .filter(|(path, state)| after.get(*path) != Some(state))
+        .chain(after.iter().filter(|(path, _)| !before.contains_key(*path)))
+        .map(|(path, _)| path);
+
+    let mut affected = Vec::new();
+    for path in changed {
+        // The innermost component directory, should they be nested
+        let owner = components
+            .iter()
+            .filter(|(_, dir)| path.starts_with(dir))
+            .max_by_key(|(_, dir)| dir.components().count());
+        let owners: Vec<Component> = match owner {
+            Some((component, _)) => vec![*component],
+            None => components.iter().map(|(c, _)| *c).collect(),
+        };
+        for component in owners {
+            if !affected.contains(&component) {
+                affected.push(component);
+            }
+        }
+    }
+    affected
+}
@@ -0,0 +1,196 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use anyhow::{Context, Result, bail};
+use cargo_metadata::MetadataCommand;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ca_builder;
+use crate::common::Arch;
+use crate::config::{CaBuildConfig, ComponentType, TaBuildConfig};
+use crate::project_uuid;
+use crate::status;
+use crate::ta_builder;
+
+/// Options of `cargo optee build --workspace`
+pub struct WorkspaceBuildConfig {
+    /// Manifest of the workspace
+    pub manifest_path: PathBuf,
+    /// Packages to build, all the OP-TEE packages if empty
+    pub packages: Vec<String>,
+    /// Packages not to build
+    pub exclude: Vec<String>,
+    /// Directory the components are installed to, in `ta`, `ca` and `plugin`
+    /// subdirectories (default: `optee` in the target directory)
+    pub out_dir: Option<PathBuf>,
+    // Overrides of the metadata of every package
+    pub arch: Option<Arch>,
+    pub debug: bool,
+    pub ta_dev_kit_dir: Option<PathBuf>,
+    pub optee_client_export: Option<PathBuf>,
+}
+
+/// A workspace member with `[package.metadata.optee.<component>]`
+struct Member {
+    name: String,
+    path: PathBuf,
+    component: ComponentType,
+}
+
+/// Build every TA, CA and plugin of a workspace into a combined output
+/// directory.
+///
+/// TAs are built first, then the plugins and the CAs, stopping at the first
+/// failure.
+pub fn build_workspace(config: &WorkspaceBuildConfig) -> Result<()> {
+    let metadata = MetadataCommand::new()
+        .manifest_path(&config.manifest_path)
+        .no_deps()
+        .exec()?;
+
+    let mut members = Vec::new();
+    for package in metadata.workspace_packages() {
+        let selected = config.packages.is_empty() || config.packages.contains(&package.name);
+        if !selected || config.exclude.contains(&package.name) {
+            continue;
+        }
+        let path = package
+            .manifest_path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid manifest path of {}", package.name))?;
+        for component in [ComponentType::Ta, ComponentType::Plugin, ComponentType::Ca] {
+            let section = package
+                .metadata
+                .get("optee")
+                .and_then(|optee| optee.get(component.as_str()));
+            if section.is_some() {
+                members.push(Member {
+                    name: package.name.to_string(),
+                    path: path.into(),
+                    component,
+                });
+            }
+        }
+    }
+    for name in &config.packages {
+        if !metadata
+            .workspace_packages()
+            .iter()
+            .any(|p| &p.name == name)
+        {
+            bail!("Package `{}` is not a member of the workspace", name);
+        }
+    }
+    if members.is_empty() {
+        bail!(
+            "No package of the workspace has a [package.metadata.optee.ta], \
+            [package.metadata.optee.ca] or [package.metadata.optee.plugin] section"
+        );
+    }
+    if let Some(workspace_dir) = config.manifest_path.parent() {
+        project_uuid::warn_duplicates(workspace_dir)?;
+    }
+    // TAs first, so the CAs are only built once the TAs they talk to are
+    members.sort_by_key(|m| match m.component {
+        ComponentType::Ta => 0,
+        ComponentType::Plugin => 1,
+        ComponentType::Ca => 2,
+    });
+
+    // Absolute, as the builders change into the package directories
+    let out_dir = config
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| metadata.target_directory.join("optee").into());
+    fs::create_dir_all(&out_dir)?;
+    let out_dir = out_dir.canonicalize()?;
+
+    status!(
+        "Building {} components of the workspace into {:?}",
+        members.len(),
+        out_dir
+    );
+    for member in &members {
+        let install_dir = out_dir.join(member.component.as_str());
+        fs::create_dir_all(&install_dir)?;
+        build_member(config, member, &install_dir).with_context(|| {
+            format!(
+                "Failed to build the {} of package `{}`",
+                member.component.as_str().to_uppercase(),
+                member.name
+            )
+        })?;
+    }
+
+    status!("Built the workspace:");
+    for member in &members {
+        status!(
+            "  {:<6} {} -> {}/",
+            member.component.as_str(),
+            member.name,
+            out_dir.join(member.component.as_str()).display()
+        );
+    }
+    Ok(())
+}
+
+fn build_member(config: &WorkspaceBuildConfig, member: &Member, install_dir: &Path) -> Result<()> {
+    status!("\n=== {} `{}` ===", member.component.as_str(), member.name);
+    match member.component {
+        ComponentType::Ta => {
+            let ta_config = TaBuildConfig::resolve(
+                &member.path,
+                config.arch,
+                Some(config.debug),
+                None,
+                Vec::new(),
+                false,
+                None,
+                false,
+                None,
+                config.ta_dev_kit_dir.clone(),
+                None,
+                None,
+                false,
+                false,
+                None,
+                None,
+                false,
+                None,
+            )?;
+            ta_config.print_config();
+            ta_builder::build_ta(ta_config, Some(install_dir))
+        }
+        ComponentType::Ca | ComponentType::Plugin => {
+            let ca_config = CaBuildConfig::resolve(
+                &member.path,
+                config.arch,
+                Some(config.debug),
+                None,
+                Vec::new(),
+                false,
+                None,
+                false,
+                config.optee_client_export.clone(),
+                member.component == ComponentType::Plugin,
+            )?;
+            ca_config.print_config();
+            ca_builder::build_ca(ca_config, Some(install_dir))
+        }
+    }
+}
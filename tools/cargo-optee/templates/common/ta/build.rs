@@ -0,0 +1,6 @@
+use optee_utee_build::{Error, TaConfig};
+
+fn main() -> Result<(), Error> {
+    let config = TaConfig::new_default_with_cargo_env(proto::UUID)?;
+    optee_utee_build::build(config)
+}
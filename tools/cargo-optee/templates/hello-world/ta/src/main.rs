@@ -0,0 +1,55 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![no_main]
+
+use optee_utee::prelude::*;
+use optee_utee::{ErrorKind, Result};
+use proto::Command;
+
+#[ta_create]
+fn create() -> Result<()> {
+    trace_println!("[+] TA create");
+    Ok(())
+}
+
+#[ta_open_session]
+fn open_session(_params: &mut ParametersNone) -> Result<()> {
+    trace_println!("[+] TA open session");
+    Ok(())
+}
+
+#[ta_close_session]
+fn close_session() {
+    trace_println!("[+] TA close session");
+}
+
+#[ta_destroy]
+fn destroy() {
+    trace_println!("[+] TA destroy");
+}
+
+#[ta_invoke_command]
+fn invoke_command(
+    cmd_id: u32,
+    params: &mut (
+        ParameterValueInout,
+        ParameterNone,
+        ParameterNone,
+        ParameterNone,
+    ),
+) -> Result<()> {
+    trace_println!("[+] TA invoke command");
+    let values = &mut params.0;
+    match Command::from(cmd_id) {
+        Command::IncValue => {
+            values.set_a(values.get_a() + 100);
+            Ok(())
+        }
+        Command::DecValue => {
+            values.set_a(values.get_a() - 100);
+            Ok(())
+        }
+        _ => Err(ErrorKind::BadParameters.into()),
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));
@@ -0,0 +1,23 @@
+use optee_teec::{Context, Operation, ParamNone, ParamTmpRef, Session, Uuid};
+use proto::{Command, UUID};
+
+fn ping_ta(session: &mut Session) -> optee_teec::Result<()> {
+    let data = [0x36u8; 10];
+    let p0 = ParamTmpRef::new_input(&data);
+    let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
+
+    println!("host -> TA -> plugin: sending {:?}", data);
+    session.invoke_command(Command::Ping as u32, &mut operation)?;
+    Ok(())
+}
+
+fn main() -> optee_teec::Result<()> {
+    let mut ctx = Context::new()?;
+    let uuid = Uuid::parse_str(UUID)?;
+    let mut session = ctx.open_session(uuid)?;
+
+    ping_ta(&mut session)?;
+
+    println!("Success");
+    Ok(())
+}
@@ -0,0 +1,9 @@
+use optee_teec_build::{uuid::Uuid, PluginConfig};
+
+fn main() -> anyhow::Result<()> {
+    PluginConfig::new(Uuid::parse_str(proto::PLUGIN_UUID)?)
+        .with_name("{{crate_name}}")
+        .build()?;
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use optee_teec::{
+    macros::{plugin_init, plugin_invoke},
+    ErrorKind, PluginParameters, Result,
+};
+use proto::PluginCommand;
+
+#[plugin_init]
+fn init() -> Result<()> {
+    println!("*plugin*: init");
+    Ok(())
+}
+
+#[plugin_invoke]
+fn invoke(params: &mut PluginParameters) -> Result<()> {
+    match PluginCommand::from(params.cmd) {
+        PluginCommand::Print => {
+            println!("*plugin*: received {:?}", params.get_buffer());
+            params.set_buf_from_slice(b"pong")?;
+            Ok(())
+        }
+        _ => {
+            println!("*plugin*: unsupported command {}", params.cmd);
+            Err(ErrorKind::BadParameters.into())
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/plugin_static.rs"));
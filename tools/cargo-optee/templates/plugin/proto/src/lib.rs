@@ -0,0 +1,28 @@
+#![no_std]
+use num_enum::{FromPrimitive, IntoPrimitive};
+
+/// Commands of the TA.
+#[derive(FromPrimitive, IntoPrimitive)]
+#[repr(u32)]
+pub enum Command {
+    Ping,
+    #[default]
+    Unknown,
+}
+
+/// Commands of the supplicant plugin, invoked by the TA.
+#[derive(FromPrimitive, IntoPrimitive)]
+#[repr(u32)]
+pub enum PluginCommand {
+    Print,
+    #[default]
+    Unknown,
+}
+
+pub const PLUGIN_SUBCMD_NULL: u32 = 0xFFFFFFFF;
+
+// If Uuid::parse_str() returns an InvalidLength error, there may be an extra
+// newline in your uuid.txt or plugin_uuid.txt file. You can remove it by
+// running `truncate -s 36 uuid.txt`.
+pub const UUID: &str = include_str!("../../uuid.txt");
+pub const PLUGIN_UUID: &str = include_str!("../../plugin_uuid.txt");
@@ -0,0 +1,54 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![no_main]
+
+extern crate alloc;
+
+use optee_utee::prelude::*;
+use optee_utee::LoadablePlugin;
+use optee_utee::{ErrorKind, Result, Uuid};
+use proto::{Command, PluginCommand, PLUGIN_SUBCMD_NULL, PLUGIN_UUID};
+
+#[ta_create]
+fn create() -> Result<()> {
+    trace_println!("[+] TA create");
+    Ok(())
+}
+
+#[ta_open_session]
+fn open_session(_params: &mut ParametersNone) -> Result<()> {
+    trace_println!("[+] TA open session");
+    Ok(())
+}
+
+#[ta_close_session]
+fn close_session() {
+    trace_println!("[+] TA close session");
+}
+
+#[ta_destroy]
+fn destroy() {
+    trace_println!("[+] TA destroy");
+}
+
+#[ta_invoke_command]
+fn invoke_command(cmd_id: u32, (p0, _, _, _): &mut ParametersAny<'_>) -> Result<()> {
+    trace_println!("[+] TA invoke command");
+    let p0 = p0.as_memref_input()?;
+    let uuid = Uuid::parse_str(PLUGIN_UUID)?;
+
+    match Command::from(cmd_id) {
+        Command::Ping => {
+            let plugin = LoadablePlugin::new(&uuid);
+            let output = plugin.invoke(
+                PluginCommand::Print as u32,
+                PLUGIN_SUBCMD_NULL,
+                p0.get_buffer(),
+            )?;
+            trace_println!("[+] TA received {:?} from the plugin", output);
+            Ok(())
+        }
+        _ => Err(ErrorKind::BadParameters.into()),
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));
@@ -0,0 +1,36 @@
+use optee_teec::{Context, ErrorKind, Operation, ParamNone, ParamTmpRef, Session, Uuid};
+use proto::{Command, GreetRequest, GreetResponse, UUID};
+
+fn greet(session: &mut Session, name: &str) -> optee_teec::Result<GreetResponse> {
+    let request = GreetRequest {
+        name: name.to_string(),
+    };
+    let input = serde_json::to_vec(&request).map_err(|e| {
+        eprintln!("Failed to serialize request: {}", e);
+        ErrorKind::BadParameters
+    })?;
+    let mut output = vec![0u8; 1024];
+
+    let p0 = ParamTmpRef::new_input(&input);
+    let p1 = ParamTmpRef::new_output(&mut output);
+    let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+    session.invoke_command(Command::Greet as u32, &mut operation)?;
+    let len = operation.parameters().1.updated_size();
+
+    serde_json::from_slice(&output[..len]).map_err(|e| {
+        eprintln!("Failed to deserialize response: {}", e);
+        ErrorKind::BadFormat.into()
+    })
+}
+
+fn main() -> optee_teec::Result<()> {
+    let mut ctx = Context::new()?;
+    let uuid = Uuid::parse_str(UUID)?;
+    let mut session = ctx.open_session(uuid)?;
+
+    let response = greet(&mut session, "World")?;
+    println!("{}", response.message);
+
+    println!("Success");
+    Ok(())
+}
@@ -0,0 +1,29 @@
+#![no_std]
+extern crate alloc;
+
+use alloc::string::String;
+use num_enum::{FromPrimitive, IntoPrimitive};
+use serde::{Deserialize, Serialize};
+
+#[derive(FromPrimitive, IntoPrimitive, Clone, Copy, Debug)]
+#[repr(u32)]
+pub enum Command {
+    Greet,
+    #[default]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GreetRequest {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GreetResponse {
+    pub message: String,
+}
+
+// If Uuid::parse_str() returns an InvalidLength error, there may be an extra
+// newline in your uuid.txt file. You can remove it by running
+// `truncate -s 36 uuid.txt`.
+pub const UUID: &str = include_str!("../../uuid.txt");
@@ -0,0 +1,57 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use optee_utee::dispatch::Json;
+use optee_utee::prelude::*;
+use optee_utee::Result;
+use proto::{GreetRequest, GreetResponse};
+
+// Requests and responses are exchanged as JSON: the host passes the request
+// in parameter 0 and receives the response in parameter 1. The command IDs
+// are shared with the host through `proto::Command`.
+#[derive(TaCommand)]
+#[ta_command(codec = Json)]
+enum Command {
+    #[ta_command(id = proto::Command::Greet, output = GreetResponse)]
+    Greet(GreetRequest),
+}
+
+#[derive(Default)]
+struct Handler;
+
+#[ta_dispatch(stateless)]
+impl CommandHandler for Handler {
+    fn greet(&mut self, request: GreetRequest) -> Result<GreetResponse> {
+        trace_println!("[+] TA greet {}", request.name);
+        Ok(GreetResponse {
+            message: format!("Hello, {}!", request.name),
+        })
+    }
+}
+
+#[ta_create]
+fn create() -> Result<()> {
+    trace_println!("[+] TA create");
+    Ok(())
+}
+
+#[ta_open_session]
+fn open_session(_params: &mut ParametersNone) -> Result<()> {
+    trace_println!("[+] TA open session");
+    Ok(())
+}
+
+#[ta_close_session]
+fn close_session() {
+    trace_println!("[+] TA close session");
+}
+
+#[ta_destroy]
+fn destroy() {
+    trace_println!("[+] TA destroy");
+}
+
+include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));
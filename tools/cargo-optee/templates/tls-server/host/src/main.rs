@@ -0,0 +1,83 @@
+use optee_teec::{Context, ErrorKind, Operation, Session, Uuid};
+use optee_teec::{ParamNone, ParamTmpRef, ParamType, ParamValue};
+use proto::{Command, UUID};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// Largest TLS record: 16 KiB of payload, expansion and header
+const MAX_WIRE_SIZE: usize = 16384 + 2048 + 5;
+
+fn invoke(
+    session: &mut Session,
+    command: Command,
+    session_id: u32,
+    p1: Option<ParamTmpRef>,
+) -> optee_teec::Result<usize> {
+    let p0 = ParamValue::new(session_id, 0, ParamType::ValueInput);
+    match p1 {
+        Some(p1) => {
+            let mut operation = Operation::new(0, p0, p1, ParamNone, ParamNone);
+            session.invoke_command(command as u32, &mut operation)?;
+            Ok(operation.parameters().1.updated_size())
+        }
+        None => {
+            let mut operation = Operation::new(0, p0, ParamNone, ParamNone, ParamNone);
+            session.invoke_command(command as u32, &mut operation)?;
+            Ok(0)
+        }
+    }
+}
+
+// Relay the TLS records between the client and the TA, which terminates TLS.
+fn handle_client(
+    session: &mut Session,
+    session_id: u32,
+    mut stream: TcpStream,
+) -> optee_teec::Result<()> {
+    invoke(session, Command::NewTlsSession, session_id, None)?;
+    let mut buf = vec![0u8; MAX_WIRE_SIZE];
+    loop {
+        let n = match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        invoke(
+            session,
+            Command::DoTlsRead,
+            session_id,
+            Some(ParamTmpRef::new_input(&buf[..n])),
+        )?;
+        let n = invoke(
+            session,
+            Command::DoTlsWrite,
+            session_id,
+            Some(ParamTmpRef::new_output(&mut buf)),
+        )?;
+        if stream.write_all(&buf[..n]).is_err() {
+            break;
+        }
+    }
+    invoke(session, Command::CloseTlsSession, session_id, None)?;
+    Ok(())
+}
+
+fn main() -> optee_teec::Result<()> {
+    let mut ctx = Context::new()?;
+    let uuid = Uuid::parse_str(UUID)?;
+    let mut session = ctx.open_session(uuid)?;
+
+    let listener = TcpListener::bind("0.0.0.0:4433").map_err(|e| {
+        eprintln!("Failed to bind TCP listener: {}", e);
+        ErrorKind::BadParameters
+    })?;
+    println!("listening on 0.0.0.0:4433");
+
+    for (session_id, stream) in (1..).zip(listener.incoming()) {
+        let stream = stream.map_err(|e| {
+            eprintln!("Failed to accept TCP connection: {}", e);
+            ErrorKind::Communication
+        })?;
+        handle_client(&mut session, session_id, stream)?;
+    }
+    Ok(())
+}
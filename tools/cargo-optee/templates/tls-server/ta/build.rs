@@ -0,0 +1,9 @@
+use optee_utee_build::{Error, TaConfig};
+
+fn main() -> Result<(), Error> {
+    // TLS sessions need a larger heap and stack than the defaults
+    let config = TaConfig::new_default_with_cargo_env(proto::UUID)?
+        .ta_data_size(18 * 1024 * 1024)
+        .ta_stack_size(2 * 1024 * 1024);
+    optee_utee_build::build(config)
+}
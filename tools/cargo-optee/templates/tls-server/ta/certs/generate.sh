@@ -0,0 +1,16 @@
+#!/bin/bash
+# Generate the self-signed server certificate embedded into the TA. Replace
+# server.cert and server.key with your own certificate for anything other
+# than testing.
+
+set -e
+cd "$(dirname "$0")"
+
+openssl req -x509 -nodes \
+    -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 \
+    -keyout server.key \
+    -out server.cert \
+    -sha256 \
+    -days 365 \
+    -subj "/CN=localhost" \
+    -addext "subjectAltName=DNS:localhost"
@@ -0,0 +1,87 @@
+#![no_main]
+
+use anyhow::Context;
+use optee_tls::{TlsCommand, TlsContext, TlsSessionManager};
+use optee_utee::prelude::*;
+use optee_utee::{ErrorKind, Result};
+use proto::Command;
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use std::sync::{Arc, OnceLock};
+
+// getrandom 0.2 has no built-in OP-TEE support, register the OP-TEE RNG from
+// `rustls_provider` as its custom implementation.
+getrandom::register_custom_getrandom!(rustls_provider::optee_getrandom);
+
+// The certificate and key are generated by `certs/generate.sh`.
+const SERVER_CERT: &[u8] = include_bytes!("../certs/server.cert");
+const SERVER_KEY: &[u8] = include_bytes!("../certs/server.key");
+
+static TLS_SESSIONS: OnceLock<TlsSessionManager> = OnceLock::new();
+
+#[ta_create]
+fn create() -> Result<()> {
+    trace_println!("[+] TA create");
+    let config = make_config().map_err(|e| {
+        trace_println!("[-] Failed to create TLS config: {:?}", e);
+        ErrorKind::Generic
+    })?;
+    let _ = TLS_SESSIONS.set(TlsSessionManager::new(config));
+    Ok(())
+}
+
+#[ta_open_session]
+fn open_session(_params: &mut ParametersNone) -> Result<()> {
+    trace_println!("[+] TA open session");
+    Ok(())
+}
+
+#[ta_close_session]
+fn close_session() {
+    trace_println!("[+] TA close session");
+}
+
+#[ta_destroy]
+fn destroy() {
+    trace_println!("[+] TA destroy");
+}
+
+#[ta_invoke_command]
+fn invoke_command(cmd_id: u32, params: &mut ParametersAny<'_>) -> Result<()> {
+    let command = match Command::from(cmd_id) {
+        Command::NewTlsSession => TlsCommand::NewSession,
+        Command::CloseTlsSession => TlsCommand::CloseSession,
+        Command::DoTlsRead => TlsCommand::Read,
+        Command::DoTlsWrite => TlsCommand::Write,
+        _ => return Err(ErrorKind::BadParameters.into()),
+    };
+    let sessions = TLS_SESSIONS.get().ok_or(ErrorKind::BadState)?;
+    sessions.invoke_command(command, params, handle_plaintext)
+}
+
+// Called with the application data decrypted by DoTlsRead, queue the response
+// to be encrypted and returned by DoTlsWrite. This template echoes it back.
+fn handle_plaintext(session: &mut TlsContext) -> Result<()> {
+    let request = session.read_plaintext()?;
+    session.write_plaintext(&request)
+}
+
+fn make_config() -> anyhow::Result<Arc<rustls::ServerConfig>> {
+    let crypto_provider = Arc::new(rustls_provider::optee_crypto_provider());
+    let time_provider = Arc::new(rustls_provider::optee_time_provider());
+
+    let certs = CertificateDer::pem_slice_iter(SERVER_CERT)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context("Failed to parse certificate")?;
+    let private_key =
+        PrivateKeyDer::from_pem_slice(SERVER_KEY).context("Failed to parse private key")?;
+
+    let config = rustls::ServerConfig::builder_with_details(crypto_provider, time_provider)
+        .with_safe_default_protocol_versions()
+        .context("Inconsistent cipher-suite/versions selected")?
+        .with_no_client_auth()
+        .with_single_cert(certs, private_key)
+        .context("Failed to create server config")?;
+    Ok(Arc::new(config))
+}
+
+include!(concat!(env!("OUT_DIR"), "/user_ta_header.rs"));